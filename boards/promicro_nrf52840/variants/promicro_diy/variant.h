@@ -42,6 +42,15 @@ NRF52 PRO MICRO PIN ASSIGNMENT
 | P1.01 | Free pin    |     | 0.15     | LED          |       |
 | P1.02 | Free pin    |     | 0.13     | 3V3_EN       |       |
 | P1.07 | Free pin    |     |          |              |       |
+
+The Rust tracker firmware (firmware/src/board.rs) keeps the pins above with
+these differences:
+- P1.13 (LORA_CS) is the SD card chip select. An SX1262 on the same SPI bus
+  (`lora` feature) uses P1.06 as its chip select instead; BUSY, DIO1 and
+  RESET stay on P0.29, P0.10 and P0.09.
+- RXEN is not wired; the SX1262 drives its RF switch from DIO2.
+- P1.01 is the GPS PPS input (`gps-pps` feature), P1.07 the buzzer (`buzzer`
+  feature) and P0.26 the GPS reset (`gps-reset` feature).
 */
 
 // Number of pins defined in PinDescription array
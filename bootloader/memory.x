@@ -0,0 +1,27 @@
+MEMORY
+{
+  /* The Adafruit UF2 bootloader (0xF4000) starts whatever sits at the S140
+     7.3.0 application address, 0x00027000: this stage. */
+  FLASH            : ORIGIN = 0x00027000, LENGTH = 24K
+  BOOTLOADER_STATE : ORIGIN = 0x0002D000, LENGTH = 4K
+  /* Slot records of the firmware (slots.rs); the bootloader leaves them alone. */
+  SLOT_META        : ORIGIN = 0x0002E000, LENGTH = 8K
+  /* Bank A, where the firmware is linked (firmware/memory.x). */
+  ACTIVE           : ORIGIN = 0x00030000, LENGTH = 0x5E000
+  /* Bank B, one page larger for the swap. Ends at the flash track ring
+     (0xED000), in the Adafruit bootloader's application data area. */
+  DFU              : ORIGIN = 0x0008E000, LENGTH = 0x5F000
+
+  /* Above the MBR and SoftDevice RAM; only the firmware's stack area,
+     which is dead until it starts. */
+  RAM              : ORIGIN = 0x20003000, LENGTH = 32K
+}
+
+__bootloader_state_start = ORIGIN(BOOTLOADER_STATE);
+__bootloader_state_end = ORIGIN(BOOTLOADER_STATE) + LENGTH(BOOTLOADER_STATE);
+
+__bootloader_active_start = ORIGIN(ACTIVE);
+__bootloader_active_end = ORIGIN(ACTIVE) + LENGTH(ACTIVE);
+
+__bootloader_dfu_start = ORIGIN(DFU);
+__bootloader_dfu_end = ORIGIN(DFU) + LENGTH(DFU);
@@ -0,0 +1,71 @@
+//! Slot bootloader: picks the firmware image that runs.
+//!
+//! The Adafruit UF2 bootloader starts whatever sits at the S140 application
+//! address. This stage sits there and runs embassy-boot in front of the
+//! tracker firmware, which is linked for bank A (`memory.x`).
+//!
+//! # Design
+//!
+//! - `BootLoader::prepare` swaps bank B in when the firmware has marked an
+//!   update, and swaps it back on the next boot if the new image did not
+//!   mark itself booted first. The firmware keeps its own record of both
+//!   banks (`firmware/src/slot_meta.rs`).
+//! - The watchdog starts before anything else and `WatchdogFlash` feeds it
+//!   during the swap, so a new image that hangs is reset and rolled back
+//!   like one that crashes. The firmware takes it over with the same
+//!   settings.
+//! - The SoftDevice forwards interrupts to the application's vector table,
+//!   which it is told to look for in bank A before the jump; `SCB.VTOR` stays
+//!   on the MBR, which the SoftDevice needs.
+//! - No RTT or defmt, to fit 24 KiB. A panic or HardFault resets; a reset
+//!   loop can still be broken with a double reset into the UF2 drive.
+
+#![no_std]
+#![no_main]
+
+use core::cell::RefCell;
+
+use cortex_m::peripheral::SCB;
+use cortex_m_rt::{entry, exception, ExceptionFrame};
+use embassy_boot_nrf::{BootLoader, BootLoaderConfig, WatchdogFlash};
+use embassy_nrf::nvmc::Nvmc;
+use embassy_nrf::wdt::{self, HaltConfig, SleepConfig};
+use embassy_sync::blocking_mutex::Mutex;
+
+/// Same as `WATCHDOG_TIMEOUT_S` in `firmware/src/slots.rs`.
+const WATCHDOG_TIMEOUT_S: u32 = 8;
+
+#[entry]
+fn main() -> ! {
+    let p = embassy_nrf::init(Default::default());
+
+    let mut wdt_config = wdt::Config::default();
+    wdt_config.timeout_ticks = 32768 * WATCHDOG_TIMEOUT_S;
+    wdt_config.action_during_sleep = SleepConfig::RUN;
+    wdt_config.action_during_debug_halt = HaltConfig::PAUSE;
+
+    let flash = WatchdogFlash::start(Nvmc::new(p.NVMC), p.WDT, wdt_config);
+    let flash = Mutex::new(RefCell::new(flash));
+
+    let config = BootLoaderConfig::from_linkerfile_blocking(&flash, &flash, &flash);
+    let active = config.active.offset();
+    let _bootloader: BootLoader = BootLoader::prepare(config);
+
+    // SAFETY: the Adafruit bootloader initialised the SoftDevice before it
+    // started this stage, so its calls are served with it disabled, and
+    // bank A starts with the firmware's vector table.
+    unsafe {
+        nrf_softdevice_s140::sd_softdevice_vector_table_base_set(active);
+        cortex_m::asm::bootload(active as *const u32)
+    }
+}
+
+#[exception]
+unsafe fn HardFault(_frame: &ExceptionFrame) -> ! {
+    SCB::sys_reset()
+}
+
+#[panic_handler]
+fn panic(_info: &core::panic::PanicInfo) -> ! {
+    SCB::sys_reset()
+}
@@ -0,0 +1,30 @@
+//! Track viewer for `.gpz` logs copied off the SD card.
+//!
+//! Built for `wasm32-unknown-unknown` and loaded by `www/index.html`, which
+//! draws the track on an OpenStreetMap slippy map next to its statistics, so
+//! a recording can be inspected without importing it into third-party tools.
+//!
+//! ```bash
+//! cd examples/track_viewer
+//! cargo build --release --target wasm32-unknown-unknown
+//! cp target/wasm32-unknown-unknown/release/track_viewer.wasm www/
+//! python3 -m http.server -d www
+//! ```
+//!
+//! # Design
+//!
+//! - Decoding uses the firmware's own `gpz` module, so the viewer reads
+//!   every block version the device writes.
+//! - `track` splits the points into segments and applies the filters of the
+//!   firmware's `track_stats`, so distance and moving time agree with
+//!   `GET_TODAY_STATS`.
+//! - No wasm-bindgen: the page copies the file into `viewer_input`, calls
+//!   `viewer_decode` and reads the results as typed arrays over the module
+//!   memory (see `wasm`).
+
+#[allow(dead_code)]
+#[path = "../../../firmware/src/gpz.rs"]
+mod gpz;
+
+pub mod track;
+mod wasm;
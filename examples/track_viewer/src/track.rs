@@ -0,0 +1,244 @@
+//! Points, segments and statistics of one decoded track.
+
+use crate::gpz::{GpzDecoder, TrackPoint};
+
+/// Same filters as the firmware's `track_stats`, so the numbers agree.
+const MIN_STEP_M: f64 = 5.0;
+const MAX_SEGMENT_GAP_S: u32 = 60;
+const MAX_PLAUSIBLE_SPEED_MPS: f64 = 100.0;
+/// Same sphere as the firmware's `geo`.
+const EARTH_RADIUS_M: f64 = 6_371_000.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Point {
+    pub timestamp: u32,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub altitude_m: f64,
+}
+
+impl Point {
+    /// `None` for a point outside the valid range or at 0, 0 (no fix).
+    fn from_track_point(point: &TrackPoint) -> Option<Self> {
+        let latitude = point.latitude_e7 as f64 / 1e7;
+        let longitude = point.longitude_e7 as f64 / 1e7;
+        if !(-90.0..=90.0).contains(&latitude)
+            || !(-180.0..=180.0).contains(&longitude)
+            || (point.latitude_e7 == 0 && point.longitude_e7 == 0)
+        {
+            return None;
+        }
+        Some(Self {
+            timestamp: point.timestamp,
+            latitude,
+            longitude,
+            altitude_m: point.altitude_dm as f64 / 10.0,
+        })
+    }
+
+    fn distance_m(&self, other: &Point) -> f64 {
+        let phi1 = self.latitude.to_radians();
+        let phi2 = other.latitude.to_radians();
+        let half_dphi = (other.latitude - self.latitude).to_radians() / 2.0;
+        let half_dlambda = (other.longitude - self.longitude).to_radians() / 2.0;
+        let a = half_dphi.sin().powi(2) + phi1.cos() * phi2.cos() * half_dlambda.sin().powi(2);
+        2.0 * EARTH_RADIUS_M * a.sqrt().atan2((1.0 - a).sqrt())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Stats {
+    /// UTC time of the first and the last point.
+    pub start: u32,
+    pub end: u32,
+    pub distance_m: f64,
+    pub moving_s: u32,
+    pub max_speed_kmh: f64,
+    pub ascent_m: f64,
+    pub descent_m: f64,
+    pub min_altitude_m: f64,
+    pub max_altitude_m: f64,
+}
+
+impl Stats {
+    /// Average over the moving time, 0 without any.
+    pub fn avg_speed_kmh(&self) -> f64 {
+        if self.moving_s == 0 {
+            0.0
+        } else {
+            self.distance_m / self.moving_s as f64 * 3.6
+        }
+    }
+}
+
+pub struct Track {
+    pub points: Vec<Point>,
+    /// Index into `points` of the first point of each segment.
+    pub segment_starts: Vec<u32>,
+    pub stats: Stats,
+    /// Invalid blocks the decoder skipped.
+    pub errors: u32,
+}
+
+impl Track {
+    pub fn decode(bytes: &[u8]) -> Self {
+        let mut decoder = GpzDecoder::new();
+        let points: Vec<Point> = decoder
+            .points(bytes)
+            .filter_map(|point| Point::from_track_point(&point))
+            .collect();
+        let mut segment_starts = Vec::new();
+        for (i, pair) in points.windows(2).enumerate() {
+            if pair[1].timestamp.saturating_sub(pair[0].timestamp) > MAX_SEGMENT_GAP_S {
+                segment_starts.push(i as u32 + 1);
+            }
+        }
+        if !points.is_empty() {
+            segment_starts.insert(0, 0);
+        }
+        let mut track = Self {
+            points,
+            segment_starts,
+            stats: Stats::default(),
+            errors: decoder.errors(),
+        };
+        track.stats = track.compute_stats();
+        track
+    }
+
+    pub fn segments(&self) -> impl Iterator<Item = &[Point]> {
+        let ends = self
+            .segment_starts
+            .iter()
+            .skip(1)
+            .copied()
+            .chain([self.points.len() as u32]);
+        self.segment_starts
+            .iter()
+            .zip(ends)
+            .map(|(&start, end)| &self.points[start as usize..end as usize])
+    }
+
+    fn compute_stats(&self) -> Stats {
+        let (Some(first), Some(last)) = (self.points.first(), self.points.last()) else {
+            return Stats::default();
+        };
+        let mut stats = Stats {
+            start: first.timestamp,
+            end: last.timestamp,
+            min_altitude_m: f64::MAX,
+            max_altitude_m: f64::MIN,
+            ..Stats::default()
+        };
+        for point in &self.points {
+            stats.min_altitude_m = stats.min_altitude_m.min(point.altitude_m);
+            stats.max_altitude_m = stats.max_altitude_m.max(point.altitude_m);
+        }
+        for segment in self.segments() {
+            // Last point counted towards distance.
+            let mut anchor = segment[0];
+            for point in &segment[1..] {
+                let dt = point.timestamp as i64 - anchor.timestamp as i64;
+                let step = anchor.distance_m(point);
+                if step < MIN_STEP_M || dt <= 0 {
+                    continue;
+                }
+                let speed = step / dt as f64;
+                if speed <= MAX_PLAUSIBLE_SPEED_MPS {
+                    stats.distance_m += step;
+                    stats.moving_s += dt as u32;
+                    stats.max_speed_kmh = stats.max_speed_kmh.max(speed * 3.6);
+                    let climb = point.altitude_m - anchor.altitude_m;
+                    if climb > 0.0 {
+                        stats.ascent_m += climb;
+                    } else {
+                        stats.descent_m -= climb;
+                    }
+                }
+                anchor = *point;
+            }
+        }
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// V2 full block: 1e7 coordinates, altitude in decimetres.
+    fn full(ts: u32, lat_e7: i32, lon_e7: i32, alt_dm: i32) -> Vec<u8> {
+        let mut out = vec![0xFE];
+        for field in [ts as i32, lat_e7, lon_e7, alt_dm] {
+            out.extend_from_slice(&field.to_le_bytes());
+        }
+        out
+    }
+
+    /// Points `step_e7` apart in latitude (about 1.1 m per 100), one a second.
+    fn walk(start_ts: u32, count: u32, step_e7: i32, climb_dm: i32) -> Vec<u8> {
+        (0..count as i32)
+            .flat_map(|i| {
+                full(
+                    start_ts + i as u32,
+                    300_000_000 + i * step_e7,
+                    1_200_000_000,
+                    i * climb_dm,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn splits_segments_at_gaps() {
+        let mut bytes = walk(1_000, 5, 100, 0);
+        bytes.extend(walk(1_000 + 4 + MAX_SEGMENT_GAP_S + 1, 3, 100, 0));
+        let track = Track::decode(&bytes);
+        assert_eq!(track.points.len(), 8);
+        assert_eq!(track.segment_starts, vec![0, 5]);
+        let lens: Vec<usize> = track.segments().map(<[Point]>::len).collect();
+        assert_eq!(lens, vec![5, 3]);
+    }
+
+    #[test]
+    fn counts_distance_and_climb() {
+        // 10 steps of ~11 m and +1 m each.
+        let track = Track::decode(&walk(1_000, 11, 1_000, 10));
+        let stats = track.stats;
+        assert_eq!((stats.start, stats.end), (1_000, 1_010));
+        assert!(
+            (stats.distance_m - 111.2).abs() < 0.5,
+            "{}",
+            stats.distance_m
+        );
+        assert_eq!(stats.moving_s, 10);
+        assert!((stats.ascent_m - 10.0).abs() < 1e-9);
+        assert_eq!(stats.descent_m, 0.0);
+        assert_eq!((stats.min_altitude_m, stats.max_altitude_m), (0.0, 10.0));
+        assert!((stats.avg_speed_kmh() - 40.0).abs() < 0.5);
+    }
+
+    #[test]
+    fn ignores_jitter_and_jumps() {
+        // ~1 m steps accumulate until one reaches MIN_STEP_M from the anchor.
+        let track = Track::decode(&walk(1_000, 11, 100, 0));
+        assert!((track.stats.distance_m - 11.1).abs() < 0.5);
+        assert_eq!(track.stats.moving_s, 10);
+        // ~1.1 km in one second is a glitch, not movement.
+        let mut bytes = walk(1_000, 1, 0, 0);
+        bytes.extend(full(1_001, 300_100_000, 1_200_000_000, 0));
+        assert_eq!(Track::decode(&bytes).stats.distance_m, 0.0);
+    }
+
+    #[test]
+    fn drops_points_without_a_fix() {
+        let mut bytes = full(1_000, 0, 0, 0);
+        bytes.extend(full(1_001, 910_000_000, 0, 0));
+        bytes.push(0xAA);
+        bytes.extend(walk(1_002, 2, 100, 0));
+        let track = Track::decode(&bytes);
+        assert_eq!(track.points.len(), 2);
+        assert_eq!(track.errors, 1);
+        assert_eq!(Track::decode(&[]).stats, Stats::default());
+    }
+}
@@ -0,0 +1,91 @@
+//! Exports for `www/viewer.js`.
+//!
+//! Pointers point into the module memory and stay valid until the next
+//! `viewer_input` or `viewer_decode`; the page copies what it needs first.
+
+use std::sync::Mutex;
+
+use crate::track::Track;
+
+/// `[start][end][points][segments][distance_m][moving_s][avg_speed_kmh]
+/// [max_speed_kmh][ascent_m][descent_m][min_altitude_m][max_altitude_m]
+/// [errors]`, all `f64`.
+const STATS_LEN: usize = 13;
+
+struct State {
+    input: Vec<u8>,
+    /// `[latitude][longitude]` per point.
+    coordinates: Vec<f64>,
+    segment_starts: Vec<u32>,
+    stats: [f64; STATS_LEN],
+}
+
+static STATE: Mutex<State> = Mutex::new(State {
+    input: Vec::new(),
+    coordinates: Vec::new(),
+    segment_starts: Vec::new(),
+    stats: [0.0; STATS_LEN],
+});
+
+fn state() -> std::sync::MutexGuard<'static, State> {
+    STATE
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Buffer of `len` bytes for the file to decode.
+#[no_mangle]
+pub extern "C" fn viewer_input(len: usize) -> *mut u8 {
+    let mut state = state();
+    state.input.clear();
+    state.input.resize(len, 0);
+    state.input.as_mut_ptr()
+}
+
+/// Decode the input buffer; returns the number of points kept.
+#[no_mangle]
+pub extern "C" fn viewer_decode() -> u32 {
+    let mut state = state();
+    let track = Track::decode(&state.input);
+    let stats = track.stats;
+    state.coordinates = track
+        .points
+        .iter()
+        .flat_map(|point| [point.latitude, point.longitude])
+        .collect();
+    state.stats = [
+        stats.start as f64,
+        stats.end as f64,
+        track.points.len() as f64,
+        track.segment_starts.len() as f64,
+        stats.distance_m,
+        stats.moving_s as f64,
+        stats.avg_speed_kmh(),
+        stats.max_speed_kmh,
+        stats.ascent_m,
+        stats.descent_m,
+        stats.min_altitude_m,
+        stats.max_altitude_m,
+        track.errors as f64,
+    ];
+    state.segment_starts = track.segment_starts;
+    track.points.len() as u32
+}
+
+/// Two `f64` per point from the last `viewer_decode`.
+#[no_mangle]
+pub extern "C" fn viewer_coordinates() -> *const f64 {
+    state().coordinates.as_ptr()
+}
+
+/// One `u32` per segment: the index of its first point.
+#[no_mangle]
+pub extern "C" fn viewer_segment_starts() -> *const u32 {
+    state().segment_starts.as_ptr()
+}
+
+/// `STATS_LEN` `f64`s, see there.
+#[no_mangle]
+pub extern "C" fn viewer_stats() -> *const f64 {
+    state().stats.as_ptr()
+}
@@ -0,0 +1,121 @@
+// Opens a .gpz log with the track_viewer WASM module and draws it on the map.
+// The module layouts are documented in src/wasm.rs.
+
+const STATS_FIELDS = [
+  "start", "end", "points", "segments", "distanceM", "movingS", "avgSpeedKmh",
+  "maxSpeedKmh", "ascentM", "descentM", "minAltitudeM", "maxAltitudeM", "errors",
+];
+
+const { instance } = await WebAssembly.instantiateStreaming(
+  fetch("track_viewer.wasm"),
+  {},
+);
+const viewer = instance.exports;
+
+const map = L.map("map").setView([0, 0], 2);
+L.tileLayer("https://tile.openstreetmap.org/{z}/{x}/{y}.png", {
+  maxZoom: 19,
+  attribution: "&copy; OpenStreetMap contributors",
+}).addTo(map);
+const layers = L.layerGroup().addTo(map);
+
+function decode(bytes) {
+  new Uint8Array(viewer.memory.buffer, viewer.viewer_input(bytes.length), bytes.length)
+    .set(bytes);
+  const count = viewer.viewer_decode();
+  const memory = viewer.memory.buffer;
+  const values = new Float64Array(memory, viewer.viewer_stats(), STATS_FIELDS.length);
+  const stats = Object.fromEntries(STATS_FIELDS.map((name, i) => [name, values[i]]));
+  const coordinates = new Float64Array(memory, viewer.viewer_coordinates(), count * 2);
+  const starts = Array.from(
+    new Uint32Array(memory, viewer.viewer_segment_starts(), stats.segments),
+  );
+  const segments = starts.map((start, i) => {
+    const end = i + 1 < starts.length ? starts[i + 1] : count;
+    const segment = [];
+    for (let p = start; p < end; p++) {
+      segment.push([coordinates[2 * p], coordinates[2 * p + 1]]);
+    }
+    return segment;
+  });
+  return { stats, segments };
+}
+
+function duration(seconds) {
+  const h = Math.floor(seconds / 3600);
+  const m = Math.floor((seconds % 3600) / 60);
+  const s = Math.floor(seconds % 60);
+  return `${h}:${String(m).padStart(2, "0")}:${String(s).padStart(2, "0")}`;
+}
+
+function statsRows(stats) {
+  const time = (ts) => new Date(ts * 1000).toLocaleString();
+  const rows = [
+    ["Start", time(stats.start)],
+    ["End", time(stats.end)],
+    ["Points", `${stats.points} in ${stats.segments} segment(s)`],
+    ["Distance", `${(stats.distanceM / 1000).toFixed(2)} km`],
+    ["Duration", duration(stats.end - stats.start)],
+    ["Moving time", duration(stats.movingS)],
+    ["Avg speed", `${stats.avgSpeedKmh.toFixed(1)} km/h (moving)`],
+    ["Max speed", `${stats.maxSpeedKmh.toFixed(1)} km/h`],
+    [
+      "Altitude",
+      `${stats.minAltitudeM.toFixed(0)} .. ${stats.maxAltitudeM.toFixed(0)} m`,
+    ],
+    ["Climb", `+${stats.ascentM.toFixed(0)} m / -${stats.descentM.toFixed(0)} m`],
+  ];
+  if (stats.errors > 0) {
+    rows.push(["Skipped", `${stats.errors} invalid block(s)`]);
+  }
+  return rows;
+}
+
+function show(name, bytes) {
+  const { stats, segments } = decode(bytes);
+  document.getElementById("title").textContent = name;
+  const table = document.getElementById("stats");
+  table.replaceChildren();
+  layers.clearLayers();
+  if (stats.points === 0) {
+    table.insertRow().insertCell().textContent = "No valid points in this file.";
+    return;
+  }
+  for (const [label, value] of statsRows(stats)) {
+    const row = table.insertRow();
+    const th = document.createElement("th");
+    th.textContent = label;
+    row.appendChild(th);
+    row.insertCell().textContent = value;
+  }
+
+  const line = L.polyline(segments, { color: "#e4572e", weight: 4 }).addTo(layers);
+  const last = segments[segments.length - 1];
+  L.circleMarker(segments[0][0], { radius: 6, color: "#2e7d32", fillOpacity: 1 })
+    .bindTooltip("Start").addTo(layers);
+  L.circleMarker(last[last.length - 1], { radius: 6, color: "#c62828", fillOpacity: 1 })
+    .bindTooltip("End").addTo(layers);
+  map.fitBounds(line.getBounds(), { padding: [20, 20] });
+}
+
+async function open(file) {
+  show(file.name, new Uint8Array(await file.arrayBuffer()));
+}
+
+document.getElementById("file").addEventListener("change", (event) => {
+  const [file] = event.target.files;
+  if (file) open(file);
+});
+document.body.addEventListener("dragover", (event) => {
+  event.preventDefault();
+  document.body.classList.add("dragging");
+});
+document.body.addEventListener("dragleave", () => {
+  document.body.classList.remove("dragging");
+});
+document.body.addEventListener("drop", (event) => {
+  event.preventDefault();
+  document.body.classList.remove("dragging");
+  const [file] = event.dataTransfer.files;
+  if (file) open(file);
+});
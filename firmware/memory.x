@@ -1,7 +1,10 @@
 MEMORY
 {
-  /* S140 7.3.0 occupies up to 0x00026498, round up to 0x00027000 */
-  FLASH : ORIGIN = 0x00027000, LENGTH = 1024K - 0x27000
+  /* Bank A of the slot bootloader, which sits at 0x00027000 right after
+     S140 7.3.0; bootloader/memory.x has the whole layout. Bank B ends where
+     the Adafruit bootloader keeps 0xED000-0xF4000 for application data; the
+     first pages hold the flash track ring (flash_track.rs). */
+  FLASH : ORIGIN = 0x00030000, LENGTH = 0x5E000
 
   /* Reserve 0x3000 RAM for SoftDevice by default */
   RAM : ORIGIN = 0x20003000, LENGTH = 256K - 0x3000
@@ -0,0 +1,377 @@
+//! Accelerometer parts the motion pipeline can run on.
+//!
+//! Breakouts for the LIS3DH come and go; DIY builds often end up with a
+//! BMI160 or an LSM6DS3 instead. Every driver delivers the same thing: one
+//! sample in milli-g at a ±2 g full scale, at 50 Hz or, for vibration
+//! capture, 100 Hz. The motion filter, step counter and vibration encoder
+//! never see which part is fitted.
+//!
+//! # Design
+//!
+//! - `probe` reads the identity register at each address a part can strap
+//!   to, LIS3DH first since the reference board carries one. The first
+//!   match is configured and kept; nothing on the bus answers at two of
+//!   these addresses.
+//! - The LIS3DH goes through the `lis3dh` crate as before. The BMI160 and
+//!   LSM6DS3 only need a handful of registers and are driven directly, like
+//!   the current monitors.
+//! - The gyroscopes of the 6-axis parts stay in their power-on suspend mode.
+//! - For SYSTEM OFF, `arm_motion_wake` latches a change of about 0.1 g onto
+//!   INT1, active high, at the lowest rate that still catches a pick-up.
+//!   The LIS3DH high-pass filters its threshold so gravity does not fire
+//!   it; the BMI160 any-motion and the LSM6DS3 wake-up engines compare
+//!   slopes already.
+
+use embassy_embedded_hal::shared_bus::blocking::i2c::I2cDevice;
+use embassy_nrf::twim;
+use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+use embassy_time::Timer;
+use embedded_hal::i2c::I2c;
+use lis3dh::accelerometer::RawAccelerometer;
+use lis3dh::{
+    Configuration, DataRate, Lis3dh, Lis3dhCore, Lis3dhI2C, Mode, Range, Register, SlaveAddr,
+};
+
+pub(super) type SharedI2c = I2cDevice<'static, NoopRawMutex, twim::Twim<'static>>;
+
+const LIS3DH_ADDRS: [u8; 2] = [0x19, 0x18];
+const LIS3DH_REG_WHO_AM_I: u8 = 0x0F;
+const LIS3DH_ID: u8 = 0x33;
+/// 10 Hz low-power mode, all axes.
+const LIS3DH_CTRL1_WAKE: u8 = 0x2F;
+/// High-pass filter on the INT1 path.
+const LIS3DH_CTRL2_HPIS1: u8 = 0x01;
+const LIS3DH_CTRL3_I1_IA1: u8 = 0x40;
+const LIS3DH_CTRL5_LIR_INT1: u8 = 0x08;
+/// X, Y or Z high event.
+const LIS3DH_INT1_CFG_ANY_HIGH: u8 = 0x2A;
+/// 16 mg per count at ±2 g.
+const LIS3DH_INT1_THS_WAKE: u8 = 6;
+
+const BMI160_ADDRS: [u8; 2] = [0x68, 0x69];
+const BMI160_REG_CHIP_ID: u8 = 0x00;
+const BMI160_ID: u8 = 0xD1;
+const BMI160_REG_DATA_ACC: u8 = 0x12;
+const BMI160_REG_ACC_CONF: u8 = 0x40;
+const BMI160_REG_ACC_RANGE: u8 = 0x41;
+const BMI160_REG_CMD: u8 = 0x7E;
+const BMI160_CMD_ACC_NORMAL: u8 = 0x11;
+/// Normal filter mode (`acc_bwp = 2`) with `acc_odr` in the low nibble.
+const BMI160_ACC_CONF_50HZ: u8 = 0x27;
+const BMI160_ACC_CONF_100HZ: u8 = 0x28;
+const BMI160_RANGE_2G: u8 = 0x03;
+/// Accelerometer start-up time after the power mode command.
+const BMI160_STARTUP_MS: u64 = 5;
+const BMI160_REG_INT_EN_0: u8 = 0x50;
+const BMI160_REG_INT_OUT_CTRL: u8 = 0x53;
+const BMI160_REG_INT_LATCH: u8 = 0x54;
+const BMI160_REG_INT_MAP_0: u8 = 0x55;
+const BMI160_REG_INT_MOTION_1: u8 = 0x60;
+const BMI160_CMD_ACC_LOW_POWER: u8 = 0x12;
+/// Undersampling, no averaging, 25 Hz.
+const BMI160_ACC_CONF_WAKE: u8 = 0x86;
+/// Any-motion on X, Y and Z.
+const BMI160_INT_EN_ANYMOTION: u8 = 0x07;
+/// INT1 output enabled, push-pull, active high.
+const BMI160_INT_OUT_INT1_HIGH: u8 = 0x0A;
+const BMI160_INT_LATCH_PERMANENT: u8 = 0x0F;
+const BMI160_INT_MAP_INT1_ANYMOTION: u8 = 0x04;
+/// 3.91 mg per count at ±2 g.
+const BMI160_ANYMOTION_THS_WAKE: u8 = 26;
+
+const LSM6DS3_ADDRS: [u8; 2] = [0x6A, 0x6B];
+const LSM6DS3_REG_WHO_AM_I: u8 = 0x0F;
+/// LSM6DS3, and LSM6DS3TR-C / LSM6DSL with the same register map.
+const LSM6DS3_IDS: [u8; 2] = [0x69, 0x6A];
+const LSM6DS3_REG_CTRL1_XL: u8 = 0x10;
+const LSM6DS3_REG_CTRL3_C: u8 = 0x12;
+const LSM6DS3_REG_OUTX_L_XL: u8 = 0x28;
+/// `ODR_XL` in the high nibble, ±2 g.
+const LSM6DS3_CTRL1_52HZ: u8 = 0x30;
+const LSM6DS3_CTRL1_104HZ: u8 = 0x40;
+/// Block data update and register address auto-increment.
+const LSM6DS3_CTRL3_BDU_INC: u8 = 0x44;
+const LSM6DS3_REG_TAP_CFG: u8 = 0x58;
+const LSM6DS3_REG_WAKE_UP_THS: u8 = 0x5B;
+const LSM6DS3_REG_WAKE_UP_DUR: u8 = 0x5C;
+const LSM6DS3_REG_MD1_CFG: u8 = 0x5E;
+const LSM6DS3_CTRL1_26HZ: u8 = 0x20;
+/// Embedded functions enabled, latched interrupts.
+const LSM6DS3_TAP_CFG_INT_LIR: u8 = 0x81;
+/// 31.25 mg (full scale / 64) per count.
+const LSM6DS3_WAKE_UP_THS_WAKE: u8 = 3;
+const LSM6DS3_MD1_INT1_WU: u8 = 0x20;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(super) enum SampleRate {
+    Hz50,
+    Hz100,
+}
+
+/// One accelerometer part, read at ±2 g.
+pub(super) trait AccelDriver {
+    fn name(&self) -> &'static str;
+
+    fn set_rate(&mut self, rate: SampleRate) -> bool;
+
+    /// One sample in milli-g.
+    fn read_mg(&mut self) -> Option<[i16; 3]>;
+
+    /// Latch motion onto INT1 for waking from SYSTEM OFF.
+    fn arm_motion_wake(&mut self) -> bool;
+}
+
+pub(super) struct Lis3dhDriver {
+    lis: Lis3dh<Lis3dhI2C<SharedI2c>>,
+}
+
+impl Lis3dhDriver {
+    fn new(i2c: SharedI2c, addr: u8) -> Option<Self> {
+        let config = Configuration {
+            mode: Mode::HighResolution,
+            datarate: DataRate::Hz_50,
+            ..Default::default()
+        };
+        let slave = if addr == LIS3DH_ADDRS[0] {
+            SlaveAddr::Alternate
+        } else {
+            SlaveAddr::Default
+        };
+        let mut lis = Lis3dh::new_i2c_with_config(i2c, slave, config).ok()?;
+        if lis.set_range(Range::G2).is_err() {
+            defmt::warn!("LIS3DH range set failed");
+        }
+        Some(Self { lis })
+    }
+}
+
+impl AccelDriver for Lis3dhDriver {
+    fn name(&self) -> &'static str {
+        "LIS3DH"
+    }
+
+    fn set_rate(&mut self, rate: SampleRate) -> bool {
+        let datarate = match rate {
+            SampleRate::Hz50 => DataRate::Hz_50,
+            SampleRate::Hz100 => DataRate::Hz_100,
+        };
+        self.lis.set_datarate(datarate).is_ok()
+    }
+
+    fn read_mg(&mut self) -> Option<[i16; 3]> {
+        let raw = self.lis.accel_raw().ok()?;
+        // 12-bit left-justified data; 1 mg per count in high-resolution mode.
+        Some([raw.x >> 4, raw.y >> 4, raw.z >> 4])
+    }
+
+    fn arm_motion_wake(&mut self) -> bool {
+        [
+            (Register::CTRL1, LIS3DH_CTRL1_WAKE),
+            (Register::CTRL2, LIS3DH_CTRL2_HPIS1),
+            (Register::INT1_THS, LIS3DH_INT1_THS_WAKE),
+            (Register::INT1_DURATION, 0),
+            (Register::CTRL5, LIS3DH_CTRL5_LIR_INT1),
+            (Register::INT1_CFG, LIS3DH_INT1_CFG_ANY_HIGH),
+            (Register::CTRL3, LIS3DH_CTRL3_I1_IA1),
+        ]
+        .into_iter()
+        .all(|(reg, value)| self.lis.write_register(reg, value).is_ok())
+    }
+}
+
+pub(super) struct Bmi160Driver {
+    i2c: SharedI2c,
+    addr: u8,
+}
+
+impl Bmi160Driver {
+    async fn new(mut i2c: SharedI2c, addr: u8) -> Option<Self> {
+        if !write_reg(&mut i2c, addr, BMI160_REG_CMD, BMI160_CMD_ACC_NORMAL) {
+            return None;
+        }
+        Timer::after_millis(BMI160_STARTUP_MS).await;
+        let mut driver = Self { i2c, addr };
+        let ok = write_reg(&mut driver.i2c, addr, BMI160_REG_ACC_RANGE, BMI160_RANGE_2G)
+            && driver.set_rate(SampleRate::Hz50);
+        ok.then_some(driver)
+    }
+}
+
+impl AccelDriver for Bmi160Driver {
+    fn name(&self) -> &'static str {
+        "BMI160"
+    }
+
+    fn set_rate(&mut self, rate: SampleRate) -> bool {
+        let conf = match rate {
+            SampleRate::Hz50 => BMI160_ACC_CONF_50HZ,
+            SampleRate::Hz100 => BMI160_ACC_CONF_100HZ,
+        };
+        write_reg(&mut self.i2c, self.addr, BMI160_REG_ACC_CONF, conf)
+    }
+
+    fn read_mg(&mut self) -> Option<[i16; 3]> {
+        let raw = read_xyz(&mut self.i2c, self.addr, BMI160_REG_DATA_ACC)?;
+        // 16384 counts per g at ±2 g.
+        Some(raw.map(|v| ((v as i32 * 1000) >> 14) as i16))
+    }
+
+    fn arm_motion_wake(&mut self) -> bool {
+        [
+            (BMI160_REG_INT_MOTION_1, BMI160_ANYMOTION_THS_WAKE),
+            (BMI160_REG_INT_OUT_CTRL, BMI160_INT_OUT_INT1_HIGH),
+            (BMI160_REG_INT_LATCH, BMI160_INT_LATCH_PERMANENT),
+            (BMI160_REG_INT_MAP_0, BMI160_INT_MAP_INT1_ANYMOTION),
+            (BMI160_REG_INT_EN_0, BMI160_INT_EN_ANYMOTION),
+            (BMI160_REG_ACC_CONF, BMI160_ACC_CONF_WAKE),
+            (BMI160_REG_CMD, BMI160_CMD_ACC_LOW_POWER),
+        ]
+        .into_iter()
+        .all(|(reg, value)| write_reg(&mut self.i2c, self.addr, reg, value))
+    }
+}
+
+pub(super) struct Lsm6ds3Driver {
+    i2c: SharedI2c,
+    addr: u8,
+}
+
+impl Lsm6ds3Driver {
+    fn new(mut i2c: SharedI2c, addr: u8) -> Option<Self> {
+        if !write_reg(&mut i2c, addr, LSM6DS3_REG_CTRL3_C, LSM6DS3_CTRL3_BDU_INC) {
+            return None;
+        }
+        let mut driver = Self { i2c, addr };
+        driver.set_rate(SampleRate::Hz50).then_some(driver)
+    }
+}
+
+impl AccelDriver for Lsm6ds3Driver {
+    fn name(&self) -> &'static str {
+        "LSM6DS3"
+    }
+
+    /// The part has no 50/100 Hz steps; 52 and 104 Hz are the nearest.
+    fn set_rate(&mut self, rate: SampleRate) -> bool {
+        let ctrl = match rate {
+            SampleRate::Hz50 => LSM6DS3_CTRL1_52HZ,
+            SampleRate::Hz100 => LSM6DS3_CTRL1_104HZ,
+        };
+        write_reg(&mut self.i2c, self.addr, LSM6DS3_REG_CTRL1_XL, ctrl)
+    }
+
+    fn read_mg(&mut self) -> Option<[i16; 3]> {
+        let raw = read_xyz(&mut self.i2c, self.addr, LSM6DS3_REG_OUTX_L_XL)?;
+        // 0.061 mg per count at ±2 g.
+        Some(raw.map(|v| (v as i32 * 61 / 1000) as i16))
+    }
+
+    fn arm_motion_wake(&mut self) -> bool {
+        [
+            (LSM6DS3_REG_CTRL1_XL, LSM6DS3_CTRL1_26HZ),
+            (LSM6DS3_REG_WAKE_UP_THS, LSM6DS3_WAKE_UP_THS_WAKE),
+            (LSM6DS3_REG_WAKE_UP_DUR, 0),
+            (LSM6DS3_REG_TAP_CFG, LSM6DS3_TAP_CFG_INT_LIR),
+            (LSM6DS3_REG_MD1_CFG, LSM6DS3_MD1_INT1_WU),
+        ]
+        .into_iter()
+        .all(|(reg, value)| write_reg(&mut self.i2c, self.addr, reg, value))
+    }
+}
+
+/// Whichever part `probe` found.
+pub(super) enum Accel {
+    Lis3dh(Lis3dhDriver),
+    Bmi160(Bmi160Driver),
+    Lsm6ds3(Lsm6ds3Driver),
+}
+
+impl Accel {
+    fn driver(&mut self) -> &mut dyn AccelDriver {
+        match self {
+            Self::Lis3dh(driver) => driver,
+            Self::Bmi160(driver) => driver,
+            Self::Lsm6ds3(driver) => driver,
+        }
+    }
+
+    pub(super) fn name(&mut self) -> &'static str {
+        self.driver().name()
+    }
+
+    pub(super) fn set_rate(&mut self, rate: SampleRate) {
+        let driver = self.driver();
+        if !driver.set_rate(rate) {
+            defmt::warn!("{} datarate set failed", driver.name());
+        }
+    }
+
+    pub(super) fn read_mg(&mut self) -> Option<[i16; 3]> {
+        let driver = self.driver();
+        let sample = driver.read_mg();
+        if sample.is_none() {
+            defmt::warn!("{} read failed", driver.name());
+        }
+        sample
+    }
+
+    pub(super) fn arm_motion_wake(&mut self) -> bool {
+        let driver = self.driver();
+        let armed = driver.arm_motion_wake();
+        if !armed {
+            defmt::warn!("{} motion wake setup failed", driver.name());
+        }
+        armed
+    }
+}
+
+/// Find and configure the fitted accelerometer.
+pub(super) async fn probe(mut i2c: SharedI2c) -> Option<Accel> {
+    for addr in LIS3DH_ADDRS {
+        if read_id(&mut i2c, addr, LIS3DH_REG_WHO_AM_I) == Some(LIS3DH_ID) {
+            return report_init(Lis3dhDriver::new(i2c, addr).map(Accel::Lis3dh), "LIS3DH");
+        }
+    }
+    for addr in BMI160_ADDRS {
+        if read_id(&mut i2c, addr, BMI160_REG_CHIP_ID) == Some(BMI160_ID) {
+            let driver = Bmi160Driver::new(i2c, addr).await;
+            return report_init(driver.map(Accel::Bmi160), "BMI160");
+        }
+    }
+    for addr in LSM6DS3_ADDRS {
+        let id = read_id(&mut i2c, addr, LSM6DS3_REG_WHO_AM_I);
+        if id.is_some_and(|id| LSM6DS3_IDS.contains(&id)) {
+            return report_init(Lsm6ds3Driver::new(i2c, addr).map(Accel::Lsm6ds3), "LSM6DS3");
+        }
+    }
+    defmt::warn!("No accelerometer found");
+    None
+}
+
+fn report_init(accel: Option<Accel>, name: &str) -> Option<Accel> {
+    if accel.is_none() {
+        defmt::warn!("{} init failed", name);
+    }
+    accel
+}
+
+fn read_id(i2c: &mut SharedI2c, addr: u8, reg: u8) -> Option<u8> {
+    let mut buf = [0u8; 1];
+    i2c.write_read(addr, &[reg], &mut buf).ok()?;
+    Some(buf[0])
+}
+
+/// Three little-endian `i16` axes starting at `reg`.
+fn read_xyz(i2c: &mut SharedI2c, addr: u8, reg: u8) -> Option<[i16; 3]> {
+    let mut buf = [0u8; 6];
+    i2c.write_read(addr, &[reg], &mut buf).ok()?;
+    Some([
+        i16::from_le_bytes([buf[0], buf[1]]),
+        i16::from_le_bytes([buf[2], buf[3]]),
+        i16::from_le_bytes([buf[4], buf[5]]),
+    ])
+}
+
+fn write_reg(i2c: &mut SharedI2c, addr: u8, reg: u8, value: u8) -> bool {
+    i2c.write(addr, &[reg, value]).is_ok()
+}
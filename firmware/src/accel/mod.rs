@@ -0,0 +1,436 @@
+mod driver;
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use embassy_executor::task;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+use embassy_time::{with_timeout, Duration, Instant, Ticker, Timer};
+use libm::sqrtf;
+
+use crate::ble;
+use crate::diag::{self, TaskId};
+use crate::stationary::{GpsSample, MotionFrame, StationaryDetector};
+use crate::steps::{DailySteps, HourlySteps, StepDetector};
+use crate::storage;
+use crate::system_info::{GpsState, SYSTEM_INFO};
+use crate::timezone::{self, TzCache};
+use crate::vibration::{self, BurstEncoder, CaptureRequest};
+use driver::{Accel, SampleRate, SharedI2c};
+
+const ACCEL_UPDATE_INTERVAL_MS: u64 = 50;
+const ALPHA_LP: f32 = 0.05;
+const ALPHA_E: f32 = 0.20;
+const ALPHA_PLANAR: f32 = 0.12;
+const NORM_BASE_ALPHA: f32 = 0.02;
+const FREEFALL_SCALE: f32 = 0.22;
+const FREEFALL_MIN_G: f32 = 0.18;
+const FREEFALL_MAX_G: f32 = 0.30;
+const FREEFALL_FRAMES: u8 = 2;
+const BLE_COOLDOWN_FRAMES: u8 = 40;
+const MIN_GRAVITY_NORM: f32 = 1e-3;
+/// Capture range; every driver reads at ±2 g in milli-g.
+const CAPTURE_RANGE_G: u8 = 2;
+/// Day total saved to SD at most this often while it changes.
+const STEP_CHECKPOINT_MS: u64 = 10 * 60 * 1000;
+/// Minimum spacing of step count notifications.
+const STEP_NOTIFY_MS: u64 = 10_000;
+/// Wire size of `steps_snapshot`.
+pub const STEPS_LEN: usize = 8;
+/// `arm_motion_wake` gives up if a capture keeps the task busy this long.
+const WAKE_ARM_TIMEOUT_MS: u64 = 1_000;
+
+static STEPS_CHANGED: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+static WAKE_REQUEST: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+static WAKE_ARMED: Signal<CriticalSectionRawMutex, bool> = Signal::new();
+/// Latest `dyn_g` as `f32` bits, `u32::MAX` before the first sample.
+static MOTION_G: AtomicU32 = AtomicU32::new(u32::MAX);
+
+#[derive(Clone, Copy)]
+struct MotionOutput {
+    dyn_g: f32,
+    planar_g: f32,
+    trigger_fast_adv: bool,
+}
+
+struct MotionFilter {
+    initialized: bool,
+    lp_x: f32,
+    lp_y: f32,
+    lp_z: f32,
+    e_ema: f32,
+    planar_ema: f32,
+    norm_base: f32,
+    ff_cnt: u8,
+    ble_cooldown_cnt: u8,
+}
+
+impl MotionFilter {
+    const fn new() -> Self {
+        Self {
+            initialized: false,
+            lp_x: 0.0,
+            lp_y: 0.0,
+            lp_z: 0.0,
+            e_ema: 0.0,
+            planar_ema: 0.0,
+            norm_base: 1.0,
+            ff_cnt: 0,
+            ble_cooldown_cnt: 0,
+        }
+    }
+
+    /// `stationary` is the fused state from the previous frame; the gravity
+    /// baseline for free-fall detection only adapts while at rest.
+    fn update(&mut self, x: f32, y: f32, z: f32, stationary: bool) -> MotionOutput {
+        let norm = vec_norm(x, y, z);
+
+        if !self.initialized {
+            self.initialized = true;
+            self.lp_x = x;
+            self.lp_y = y;
+            self.lp_z = z;
+            self.norm_base = norm;
+            self.e_ema = 0.0;
+            self.planar_ema = 0.0;
+        }
+
+        self.lp_x += ALPHA_LP * (x - self.lp_x);
+        self.lp_y += ALPHA_LP * (y - self.lp_y);
+        self.lp_z += ALPHA_LP * (z - self.lp_z);
+
+        let hp_x = x - self.lp_x;
+        let hp_y = y - self.lp_y;
+        let hp_z = z - self.lp_z;
+        let energy = hp_x * hp_x + hp_y * hp_y + hp_z * hp_z;
+        self.e_ema = ALPHA_E * energy + (1.0 - ALPHA_E) * self.e_ema;
+
+        let g_norm = vec_norm(self.lp_x, self.lp_y, self.lp_z);
+        let (g_hat_x, g_hat_y, g_hat_z) = if g_norm > MIN_GRAVITY_NORM {
+            (self.lp_x / g_norm, self.lp_y / g_norm, self.lp_z / g_norm)
+        } else {
+            (0.0, 0.0, 1.0)
+        };
+        let proj = x * g_hat_x + y * g_hat_y + z * g_hat_z;
+        let planar_x = x - proj * g_hat_x;
+        let planar_y = y - proj * g_hat_y;
+        let planar_z = z - proj * g_hat_z;
+        let planar_raw = vec_norm(planar_x, planar_y, planar_z);
+        self.planar_ema = ALPHA_PLANAR * planar_raw + (1.0 - ALPHA_PLANAR) * self.planar_ema;
+
+        let dyn_g = sqrtf(self.e_ema.max(0.0));
+
+        if stationary {
+            self.norm_base += NORM_BASE_ALPHA * (norm - self.norm_base);
+        }
+
+        let ff_thr = clamp_f32(FREEFALL_SCALE * self.norm_base, FREEFALL_MIN_G, FREEFALL_MAX_G);
+        if norm < ff_thr {
+            self.ff_cnt = self.ff_cnt.saturating_add(1);
+        } else {
+            self.ff_cnt = 0;
+        }
+
+        if self.ble_cooldown_cnt > 0 {
+            self.ble_cooldown_cnt -= 1;
+        }
+
+        let mut trigger_fast_adv = false;
+        if self.ff_cnt >= FREEFALL_FRAMES && self.ble_cooldown_cnt == 0 {
+            trigger_fast_adv = true;
+            self.ff_cnt = 0;
+            self.ble_cooldown_cnt = BLE_COOLDOWN_FRAMES;
+        }
+
+        MotionOutput {
+            dyn_g,
+            planar_g: self.planar_ema,
+            trigger_fast_adv,
+        }
+    }
+}
+
+fn vec_norm(x: f32, y: f32, z: f32) -> f32 {
+    sqrtf(x * x + y * y + z * z)
+}
+
+fn clamp_f32(value: f32, min: f32, max: f32) -> f32 {
+    if value < min {
+        min
+    } else if value > max {
+        max
+    } else {
+        value
+    }
+}
+
+/// `[steps: u32 LE][date: u32 LE, YYYYMMDD, 0 = unknown]` of the current day.
+pub async fn steps_snapshot() -> [u8; STEPS_LEN] {
+    let info = SYSTEM_INFO.lock().await;
+    let mut out = [0u8; STEPS_LEN];
+    out[..4].copy_from_slice(&info.steps_today.to_le_bytes());
+    out[4..].copy_from_slice(&info.steps_date.to_le_bytes());
+    out
+}
+
+/// Smoothed dynamic acceleration in g, `None` without an accelerometer.
+pub fn motion_g() -> Option<f32> {
+    let bits = MOTION_G.load(Ordering::Relaxed);
+    (bits != u32::MAX).then(|| f32::from_bits(bits))
+}
+
+/// Wait until the day's step count changed, at most every `STEP_NOTIFY_MS`.
+pub async fn wait_steps_change() {
+    STEPS_CHANGED.wait().await;
+}
+
+/// Switch the accelerometer to wake-on-motion for SYSTEM OFF; sampling
+/// stops for good. False if no part is fitted or it rejected the setup.
+pub async fn arm_motion_wake() -> bool {
+    WAKE_REQUEST.signal(());
+    with_timeout(
+        Duration::from_millis(WAKE_ARM_TIMEOUT_MS),
+        WAKE_ARMED.wait(),
+    )
+    .await
+    .unwrap_or(false)
+}
+
+#[task]
+pub async fn accel_task(i2c: SharedI2c) {
+    let mut accel = driver::probe(i2c).await;
+    if let Some(accel) = accel.as_mut() {
+        defmt::info!("{} initialized", accel.name());
+    }
+    let mut motion = MotionPipeline::new();
+    motion.restore_steps().await;
+
+    loop {
+        if WAKE_REQUEST.try_take().is_some() {
+            let armed = accel.as_mut().is_some_and(|accel| accel.arm_motion_wake());
+            WAKE_ARMED.signal(armed);
+            if armed {
+                // Further reads would only disturb the latched interrupt.
+                core::future::pending::<()>().await;
+            }
+        }
+
+        if let Some(req) = vibration::take_request() {
+            if let Some(accel) = accel.as_mut().filter(|_| req.duration_s > 0) {
+                run_capture(accel, &mut motion, req).await;
+                continue;
+            }
+        }
+
+        if let Some(sample) = accel.as_mut().and_then(|accel| accel.read_mg()) {
+            let _busy = diag::busy(TaskId::Accel);
+            let [x, y, z] = sample.map(|v| v as f32 / 1000.0);
+            motion.process(x, y, z).await;
+        }
+
+        Timer::after_millis(ACCEL_UPDATE_INTERVAL_MS).await;
+    }
+}
+
+/// Motion filter, stationary fusion and step counter, fed once per
+/// `ACCEL_UPDATE_INTERVAL_MS` frame.
+struct MotionPipeline {
+    filter: MotionFilter,
+    stationary: StationaryDetector,
+    steps: StepDetector,
+    hourly: HourlySteps,
+    daily: DailySteps,
+    tz_cache: TzCache,
+    /// Day of `date_minute`, recomputed once per minute.
+    date: Option<u32>,
+    date_minute: Option<u32>,
+    /// `(date, steps)` last written to `/STEPDAY.CFG`, and when.
+    saved: (Option<u32>, u32),
+    saved_ms: u64,
+    /// `(date, steps)` last signalled to BLE, and when.
+    notified: (Option<u32>, u32),
+    notified_ms: u64,
+}
+
+impl MotionPipeline {
+    const fn new() -> Self {
+        Self {
+            filter: MotionFilter::new(),
+            stationary: StationaryDetector::new(),
+            steps: StepDetector::new(),
+            hourly: HourlySteps::new(),
+            daily: DailySteps::new(),
+            tz_cache: TzCache::new(),
+            date: None,
+            date_minute: None,
+            saved: (None, 0),
+            saved_ms: 0,
+            notified: (None, 0),
+            notified_ms: 0,
+        }
+    }
+
+    async fn restore_steps(&mut self) {
+        let Some((date, steps)) = storage::read_step_checkpoint().await else {
+            return;
+        };
+        self.daily.restore(date, steps);
+        self.saved = (Some(date), steps);
+        self.notified = self.saved;
+        self.publish_steps().await;
+        defmt::info!("Steps: resumed {} for {}", steps, date);
+    }
+
+    async fn publish_steps(&self) {
+        let mut info = SYSTEM_INFO.lock().await;
+        info.steps_today = self.daily.steps();
+        info.steps_date = self.daily.date().unwrap_or(0);
+    }
+
+    /// Log day of the current time, when it is known.
+    fn current_date(&mut self, now_ms: u64, latitude: f64, longitude: f64) -> Option<u32> {
+        let unix_ts = self.hourly.unix_now(now_ms)?;
+        let minute = unix_ts / 60;
+        if self.date_minute != Some(minute) {
+            self.date_minute = Some(minute);
+            self.date = storage::log_day(unix_ts, latitude, longitude, &mut self.tz_cache);
+        }
+        self.date
+    }
+
+    /// Add to the day total, finish the day on rollover, and notify and
+    /// checkpoint changes at their own pace.
+    async fn count_daily(&mut self, new_steps: u32, now_ms: u64, latitude: f64, longitude: f64) {
+        let date = self.current_date(now_ms, latitude, longitude);
+        let previous_date = self.daily.date();
+        let finished = self.daily.add(date, new_steps);
+        let rolled = self.daily.date() != previous_date;
+        if new_steps > 0 || rolled {
+            self.publish_steps().await;
+        }
+        if let Some((date, total)) = finished {
+            if !storage::append_step_day(date, total).await {
+                defmt::warn!("Steps: day total append failed");
+            }
+        }
+
+        let current = (self.daily.date(), self.daily.steps());
+        if current != self.notified
+            && (rolled || now_ms.saturating_sub(self.notified_ms) >= STEP_NOTIFY_MS)
+        {
+            self.notified = current;
+            self.notified_ms = now_ms;
+            STEPS_CHANGED.signal(());
+        }
+        if current != self.saved
+            && (rolled || now_ms.saturating_sub(self.saved_ms) >= STEP_CHECKPOINT_MS)
+        {
+            if let Some(date) = current.0 {
+                self.saved_ms = now_ms;
+                if storage::write_step_checkpoint(date, current.1).await {
+                    self.saved = current;
+                }
+            }
+        }
+    }
+
+    async fn process(&mut self, x: f32, y: f32, z: f32) {
+        let output = self
+            .filter
+            .update(x, y, z, self.stationary.is_stationary());
+        MOTION_G.store(output.dyn_g.to_bits(), Ordering::Relaxed);
+        let new_steps = self.steps.update(vec_norm(x, y, z));
+        let frame = MotionFrame {
+            dyn_g: output.dyn_g,
+            planar_g: output.planar_g,
+            walking: self.steps.is_walking(),
+        };
+
+        let now_ms = Instant::now().as_millis();
+        let (gps_off, unix_ts, latitude, longitude) = {
+            let mut info = SYSTEM_INFO.lock().await;
+            // A fix left over from before the GPS powered off is stale.
+            let gps = (info.location_valid && info.gps_state != GpsState::S2IdleGpsOff).then(
+                || GpsSample {
+                    speed_kmph: info.speed,
+                    latitude: info.latitude,
+                    longitude: info.longitude,
+                },
+            );
+            info.is_stationary = self.stationary.update(&frame, gps);
+            let unix_ts = if info.date_time_valid {
+                timezone::date_time_to_unix_timestamp(
+                    info.year,
+                    info.month,
+                    info.day,
+                    info.hour,
+                    info.minute,
+                    info.second,
+                )
+            } else {
+                None
+            };
+            (
+                info.gps_state == GpsState::S2IdleGpsOff,
+                unix_ts,
+                info.latitude,
+                info.longitude,
+            )
+        };
+        if let Some(unix_ts) = unix_ts {
+            self.hourly.note_time(unix_ts, now_ms);
+        }
+
+        let counted = if gps_off { new_steps } else { 0 };
+        if let Some((hour_start, total)) = self.hourly.add(counted, now_ms) {
+            if !storage::append_step_log(hour_start, total).await {
+                defmt::warn!("Steps: SD append failed");
+            }
+        }
+        self.count_daily(new_steps, now_ms, latitude, longitude).await;
+
+        if output.trigger_fast_adv {
+            ble::request_fast_advertising();
+        }
+    }
+}
+
+/// Sample at the capture rate until the duration elapses or a stop request
+/// arrives. The motion pipeline is fed at its usual rate from the same samples.
+async fn run_capture(accel: &mut Accel, motion: &mut MotionPipeline, req: CaptureRequest) {
+    let rate = if req.rate_hz >= 100 {
+        SampleRate::Hz100
+    } else {
+        SampleRate::Hz50
+    };
+    accel.set_rate(rate);
+
+    let period_ms = 1000 / req.rate_hz as u64;
+    let decimation = (ACCEL_UPDATE_INTERVAL_MS / period_ms).max(1) as u32;
+    let duration_ms = req.duration_s as u64 * 1000;
+    let mut ticker = Ticker::every(Duration::from_millis(period_ms));
+    let mut encoder = BurstEncoder::begin(req, CAPTURE_RANGE_G).await;
+    let mut sample_idx = 0u32;
+
+    while encoder.elapsed_ms() < duration_ms {
+        if let Some(next) = vibration::take_request() {
+            // Stop, or restart with the new parameters after this capture closes.
+            if next.duration_s > 0 {
+                vibration::request_capture(next.rate_hz, next.duration_s);
+            }
+            break;
+        }
+        if let Some(sample) = accel.read_mg() {
+            encoder.push(sample);
+            if sample_idx.is_multiple_of(decimation) {
+                let [x, y, z] = sample.map(|v| v as f32 / 1000.0);
+                motion.process(x, y, z).await;
+            }
+            sample_idx = sample_idx.wrapping_add(1);
+        }
+        ticker.next().await;
+    }
+
+    encoder.finish().await;
+    accel.set_rate(SampleRate::Hz50);
+}
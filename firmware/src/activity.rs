@@ -0,0 +1,357 @@
+//! Activity auto-detection: walking, cycling or driving.
+//!
+//! Each logged point feeds the classifier with the GPS speed, the fused
+//! altitude and the accelerometer's motion level. The label goes into the
+//! `.gpz` log as an activity block whenever it changes, so a day's track
+//! splits into walks, rides and drives, and the main page shows it.
+//!
+//! # Design
+//!
+//! - Points are grouped into `WINDOW_S` windows. A window is labelled from
+//!   its average speed, average motion and altitude rate: a walking pace
+//!   with the jolt of steps is a walk; up to `RIDE_MAX_KMH` a bumpy ride is
+//!   cycling and a smooth one driving; faster is driving, except a bumpy
+//!   fast descent, which is a bike going downhill.
+//! - Without accelerometer readings (no sensor found) speed alone decides,
+//!   as if every window were bumpy.
+//! - A window below `STOP_KMH` says nothing: a red light or a rest keeps
+//!   the current label.
+//! - The label only changes after `CONFIRM_WINDOWS` windows in a row agree,
+//!   so a short sprint or stop-and-go traffic does not flip it back and
+//!   forth.
+//! - A gap of more than two windows between points (GPS off, no fix)
+//!   starts a fresh window; the label itself stays until something else
+//!   is confirmed.
+//! - `ActivityTally` counts the labelled points of a recording session, so
+//!   the session is tagged with the activity most of it was. After a reboot
+//!   it is rebuilt from the stored label, share and point count, which keep
+//!   the label and share but not how the rest was split.
+
+const WINDOW_S: u32 = 30;
+const CONFIRM_WINDOWS: u8 = 2;
+const STOP_KMH: f32 = 1.5;
+const WALK_MAX_KMH: f32 = 7.0;
+const RIDE_MAX_KMH: f32 = 30.0;
+/// A bike freewheeling downhill stays below this.
+const DESCENT_MAX_KMH: f32 = 60.0;
+/// Sink rate of a downhill ride, m/s.
+const DESCENT_RATE: f32 = -1.0;
+/// Average accelerometer motion (g) above which a ride is not a car's.
+const BUMPY_G: f32 = 0.06;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Activity {
+    Unknown = 0,
+    Walk = 1,
+    Cycle = 2,
+    Drive = 3,
+}
+
+impl Activity {
+    pub fn from_u8(value: u8) -> Self {
+        match value {
+            1 => Self::Walk,
+            2 => Self::Cycle,
+            3 => Self::Drive,
+            _ => Self::Unknown,
+        }
+    }
+
+    /// Short name for the display, empty when unknown.
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Unknown => "",
+            Self::Walk => "Walk",
+            Self::Cycle => "Ride",
+            Self::Drive => "Drive",
+        }
+    }
+}
+
+/// One window's worth of points.
+#[derive(Clone, Copy)]
+struct Window {
+    start_ts: u32,
+    last_ts: u32,
+    start_altitude_m: f32,
+    last_altitude_m: f32,
+    speed_sum: f32,
+    count: u32,
+    motion_sum: f32,
+    motion_count: u32,
+}
+
+pub struct ActivityClassifier {
+    window: Option<Window>,
+    current: Activity,
+    candidate: Activity,
+    streak: u8,
+}
+
+impl ActivityClassifier {
+    pub const fn new() -> Self {
+        Self {
+            window: None,
+            current: Activity::Unknown,
+            candidate: Activity::Unknown,
+            streak: 0,
+        }
+    }
+
+    pub fn current(&self) -> Activity {
+        self.current
+    }
+
+    /// Add one logged point and return the current label. `speed_kmh` is
+    /// negative when unknown; `motion_g` is the accelerometer's motion
+    /// level, if it has one.
+    pub fn update(
+        &mut self,
+        timestamp: u32,
+        speed_kmh: f32,
+        altitude_m: f32,
+        motion_g: Option<f32>,
+    ) -> Activity {
+        if speed_kmh < 0.0 || timestamp == 0 {
+            return self.current;
+        }
+        let window = match self.window.as_mut() {
+            Some(window)
+                if timestamp > window.last_ts && timestamp - window.last_ts <= 2 * WINDOW_S =>
+            {
+                window
+            }
+            Some(window) if timestamp <= window.last_ts => return self.current,
+            _ => self.window.insert(Window {
+                start_ts: timestamp,
+                last_ts: timestamp,
+                start_altitude_m: altitude_m,
+                last_altitude_m: altitude_m,
+                speed_sum: 0.0,
+                count: 0,
+                motion_sum: 0.0,
+                motion_count: 0,
+            }),
+        };
+        window.last_ts = timestamp;
+        window.last_altitude_m = altitude_m;
+        window.speed_sum += speed_kmh;
+        window.count += 1;
+        if let Some(motion_g) = motion_g {
+            window.motion_sum += motion_g;
+            window.motion_count += 1;
+        }
+        if timestamp - window.start_ts < WINDOW_S {
+            return self.current;
+        }
+
+        let window = *window;
+        self.window = None;
+        if let Some(activity) = classify(&window) {
+            self.confirm(activity);
+        }
+        self.current
+    }
+
+    fn confirm(&mut self, activity: Activity) {
+        if activity == self.current {
+            self.streak = 0;
+            return;
+        }
+        if activity == self.candidate {
+            self.streak += 1;
+        } else {
+            self.candidate = activity;
+            self.streak = 1;
+        }
+        if self.streak >= CONFIRM_WINDOWS {
+            self.current = activity;
+            self.streak = 0;
+        }
+    }
+}
+
+/// Points per label, for the dominant activity of a stretch of track.
+#[derive(Clone, Copy)]
+pub struct ActivityTally {
+    /// Walk, cycle, drive.
+    points: [u32; 3],
+}
+
+impl ActivityTally {
+    pub const fn new() -> Self {
+        Self { points: [0; 3] }
+    }
+
+    /// A tally whose `dominant` is `activity` at `share_pct` of `points`;
+    /// the other labels share the rest evenly.
+    pub fn seeded(activity: Activity, share_pct: u8, points: u32) -> Self {
+        let mut tally = Self::new();
+        if activity == Activity::Unknown {
+            return tally;
+        }
+        let best = activity as usize - 1;
+        let count = (points as u64 * share_pct.min(100) as u64 / 100) as u32;
+        let rest = points - count;
+        for (index, slot) in tally.points.iter_mut().enumerate() {
+            *slot = if index == best { count } else { rest / 2 };
+        }
+        tally
+    }
+
+    /// Count one point; unlabelled points are left out.
+    pub fn note(&mut self, activity: Activity) {
+        if activity != Activity::Unknown {
+            let count = &mut self.points[activity as usize - 1];
+            *count = count.saturating_add(1);
+        }
+    }
+
+    /// The label with the most points and its share of the labelled points
+    /// in percent, `(Unknown, 0)` before any. A tie goes to the slower one.
+    pub fn dominant(&self) -> (Activity, u8) {
+        let total: u64 = self.points.iter().map(|&count| count as u64).sum();
+        if total == 0 {
+            return (Activity::Unknown, 0);
+        }
+        let mut best = 0;
+        for (index, &count) in self.points.iter().enumerate() {
+            if count > self.points[best] {
+                best = index;
+            }
+        }
+        let share_pct = self.points[best] as u64 * 100 / total;
+        (Activity::from_u8(best as u8 + 1), share_pct as u8)
+    }
+}
+
+/// Label of a full window, `None` while stopped.
+fn classify(window: &Window) -> Option<Activity> {
+    let speed_kmh = window.speed_sum / window.count as f32;
+    let bumpy = match window.motion_count {
+        0 => true,
+        count => window.motion_sum / count as f32 >= BUMPY_G,
+    };
+    let span_s = (window.last_ts - window.start_ts) as f32;
+    let climb_rate = (window.last_altitude_m - window.start_altitude_m) / span_s;
+    let activity = match speed_kmh {
+        s if s < STOP_KMH => return None,
+        s if s <= WALK_MAX_KMH && bumpy => Activity::Walk,
+        s if s <= RIDE_MAX_KMH && bumpy => Activity::Cycle,
+        s if s <= DESCENT_MAX_KMH && bumpy && climb_rate <= DESCENT_RATE => Activity::Cycle,
+        _ => Activity::Drive,
+    };
+    Some(activity)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 1 Hz points, each call carrying on where the last one stopped.
+    struct Trip {
+        classifier: ActivityClassifier,
+        timestamp: u32,
+    }
+
+    impl Trip {
+        fn new() -> Self {
+            Self {
+                classifier: ActivityClassifier::new(),
+                timestamp: 1_000,
+            }
+        }
+
+        /// Travel for `seconds` and return the label after the last point; a
+        /// negative `motion_g` means no accelerometer.
+        fn go(&mut self, seconds: u32, speed_kmh: f32, climb_rate: f32, motion_g: f32) -> Activity {
+            let motion_g = (motion_g >= 0.0).then_some(motion_g);
+            let classifier = &mut self.classifier;
+            let mut activity = classifier.current();
+            for i in 0..seconds {
+                let altitude_m = 500.0 + climb_rate * i as f32;
+                activity = classifier.update(self.timestamp, speed_kmh, altitude_m, motion_g);
+                self.timestamp += 1;
+            }
+            activity
+        }
+    }
+
+    #[test]
+    fn labels_need_two_agreeing_windows() {
+        let mut trip = Trip::new();
+        // One window of walking is not enough.
+        assert_eq!(trip.go(31, 5.0, 0.0, 0.25), Activity::Unknown);
+        assert_eq!(trip.go(31, 5.0, 0.0, 0.25), Activity::Walk);
+        // A stop keeps the label.
+        assert_eq!(trip.go(93, 0.2, 0.0, 0.01), Activity::Walk);
+        // Smooth and fast: a car, after two windows.
+        assert_eq!(trip.go(31, 50.0, 0.0, 0.02), Activity::Walk);
+        assert_eq!(trip.go(31, 50.0, 0.0, 0.02), Activity::Drive);
+        // A single bumpy window in between resets the streak.
+        assert_eq!(trip.go(31, 18.0, 0.0, 0.12), Activity::Drive);
+        assert_eq!(trip.go(31, 50.0, 0.0, 0.02), Activity::Drive);
+        assert_eq!(trip.go(31, 18.0, 0.0, 0.12), Activity::Drive);
+        assert_eq!(trip.go(31, 18.0, 0.0, 0.12), Activity::Cycle);
+    }
+
+    #[test]
+    fn fast_bumpy_descent_is_cycling() {
+        assert_eq!(Trip::new().go(62, 45.0, -2.0, 0.15), Activity::Cycle);
+        assert_eq!(Trip::new().go(62, 45.0, 0.0, 0.15), Activity::Drive);
+
+        // Unknown speed and gaps do not complete a window.
+        let mut gaps = ActivityClassifier::new();
+        for i in 0..10 {
+            gaps.update(1_000 + i * 100, 5.0, 0.0, Some(0.3));
+            gaps.update(1_001 + i * 100, -1.0, 0.0, Some(0.3));
+        }
+        assert_eq!(gaps.current(), Activity::Unknown);
+        // No accelerometer: speed alone.
+        assert_eq!(Trip::new().go(62, 18.0, 0.0, -1.0), Activity::Cycle);
+        assert_eq!(Activity::from_u8(Activity::Drive as u8), Activity::Drive);
+        assert_eq!(Activity::from_u8(9), Activity::Unknown);
+    }
+
+    #[test]
+    fn tally_picks_the_dominant_label() {
+        let mut tally = ActivityTally::new();
+        assert_eq!(tally.dominant(), (Activity::Unknown, 0));
+        // Walk to the car park, then a long drive; the first minute of
+        // points is not labelled yet.
+        let mut trip = Trip::new();
+        for (seconds, speed_kmh, motion_g) in [(300, 5.0, 0.25), (900, 60.0, 0.02)] {
+            for _ in 0..seconds {
+                tally.note(trip.go(1, speed_kmh, 0.0, motion_g));
+            }
+        }
+        let (activity, share_pct) = tally.dominant();
+        assert_eq!(activity, Activity::Drive);
+        assert!((70..80).contains(&share_pct), "{}", share_pct);
+
+        let mut tie = ActivityTally::new();
+        tie.note(Activity::Drive);
+        tie.note(Activity::Cycle);
+        tie.note(Activity::Unknown);
+        assert_eq!(tie.dominant(), (Activity::Cycle, 50));
+    }
+
+    #[test]
+    fn seeded_tally_resumes_the_stored_label() {
+        let mut tally = ActivityTally::seeded(Activity::Drive, 74, 1200);
+        assert_eq!(tally.dominant(), (Activity::Drive, 74));
+        // The session carries on by bike; the drive still dominates.
+        for _ in 0..100 {
+            tally.note(Activity::Cycle);
+        }
+        let (activity, share_pct) = tally.dominant();
+        assert_eq!(activity, Activity::Drive);
+        assert!((65..74).contains(&share_pct), "{}", share_pct);
+
+        let barely = ActivityTally::seeded(Activity::Walk, 34, 100);
+        assert_eq!(barely.dominant(), (Activity::Walk, 34));
+        let none = ActivityTally::seeded(Activity::Unknown, 0, 50);
+        assert_eq!(none.dominant(), (Activity::Unknown, 0));
+    }
+}
@@ -2,7 +2,7 @@
 //!
 //! The nRF SoftDevice S140 supports only one advertising set handle.
 //! This module arbitrates access between connectable (main BLE) and
-//! non-connectable (Find My / FMDN) advertising using a cooperative
+//! non-connectable (Find My / FMDN / BTHome) advertising using a cooperative
 //! preemption model with round-robin alternation for background tasks.
 //!
 //! # Design
@@ -11,8 +11,8 @@
 //! - Higher-priority callers preempt lower-priority holders via signal.
 //! - `AdvGuard::wait_preempted().await` lets holders react to preemption.
 //! - `drop(guard)` releases the resource and wakes the next waiter.
-//! - Background tasks (FindMy / FMDN) alternate via round-robin: when one
-//!   releases, the other is granted first if waiting.
+//! - Background tasks (FindMy / FMDN / BTHome) alternate via round-robin:
+//!   when one releases, the next one after it is granted first if waiting.
 
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_sync::blocking_mutex::Mutex;
@@ -20,7 +20,7 @@ use embassy_sync::signal::Signal;
 
 use core::cell::RefCell;
 
-const PRIORITY_COUNT: usize = 3;
+const PRIORITY_COUNT: usize = 4;
 
 /// Time slice for background advertising alternation (seconds).
 /// Each background task (FindMy / FMDN) advertises for this duration
@@ -33,8 +33,16 @@ pub enum AdvPriority {
     MainAdv = 0,
     FindMyAdv = 1,
     FmdnAdv = 2,
+    BthomeAdv = 3,
 }
 
+/// Round-robin order of the background advertisers.
+const BACKGROUND: [AdvPriority; 3] = [
+    AdvPriority::FindMyAdv,
+    AdvPriority::FmdnAdv,
+    AdvPriority::BthomeAdv,
+];
+
 
 struct SchedulerState {
     current_holder: Option<AdvPriority>,
@@ -54,8 +62,8 @@ impl AdvScheduler {
                 current_holder: None,
                 waiting: [false; PRIORITY_COUNT],
             })),
-            grant_signals: [Signal::new(), Signal::new(), Signal::new()],
-            preempt_signals: [Signal::new(), Signal::new(), Signal::new()],
+            grant_signals: [Signal::new(), Signal::new(), Signal::new(), Signal::new()],
+            preempt_signals: [Signal::new(), Signal::new(), Signal::new(), Signal::new()],
         }
     }
 
@@ -77,7 +85,7 @@ impl AdvScheduler {
                     }
                     Some(holder) if priority == AdvPriority::MainAdv => {
                         // Only MainAdv may preempt background advertisers.
-                        // Background tasks (FindMy / FMDN) must not preempt
+                        // Background tasks (FindMy / FMDN / BTHome) must not preempt
                         // each other; they rely on voluntary 5-second yielding.
                         self.preempt_signals[holder as usize].signal(());
                         st.waiting[priority as usize] = true;
@@ -118,14 +126,14 @@ impl AdvScheduler {
                 return;
             }
 
-            // For background tasks, prefer the OTHER background task (round-robin).
-            let (first, second) = match priority {
-                AdvPriority::FindMyAdv => (AdvPriority::FmdnAdv, AdvPriority::FindMyAdv),
-                AdvPriority::FmdnAdv => (AdvPriority::FindMyAdv, AdvPriority::FmdnAdv),
-                _ => (AdvPriority::FindMyAdv, AdvPriority::FmdnAdv),
-            };
+            // For background tasks, start after the releaser (round-robin).
+            let start = BACKGROUND
+                .iter()
+                .position(|&p| p == priority)
+                .map_or(0, |i| i + 1);
 
-            for p in [first, second] {
+            for offset in 0..BACKGROUND.len() {
+                let p = BACKGROUND[(start + offset) % BACKGROUND.len()];
                 if st.waiting[p as usize] {
                     st.waiting[p as usize] = false;
                     st.current_holder = Some(p);
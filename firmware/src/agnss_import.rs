@@ -0,0 +1,418 @@
+//! Turns AGNSS uploads into the CASIC frames the receiver takes.
+//!
+//! The AGNSS service serves its GPS/BDS file as back-to-back CASIC frames.
+//! Companions used to parse it themselves and send one frame per chunk;
+//! with `WRITE_AGNSS_STREAM` they forward the file as downloaded, sliced
+//! however the link likes, and the device does the framing. Companions
+//! without a CASIC source send the broadcast GPS navigation message instead
+//! (`WRITE_AGNSS_LNAV`), and `gps_ephemeris_from_lnav` converts it.
+//!
+//! # Design
+//!
+//! - Bytes go through the same `CasicParser` as receiver output, so a
+//!   frame may be split across any number of pushes and junk between frames
+//!   is skipped.
+//! - Only AID-INI and the `MSG` class (ephemerides, ionosphere, UTC) are
+//!   kept. Anything else, such as a `CFG` frame, would reconfigure the
+//!   receiver and is dropped along with frames that fail their checksum.
+//! - Kept frames are re-encoded from the parsed packet, so what reaches the
+//!   queue is always a well-formed frame.
+//! - LNAV input is subframes 1-3 of one satellite, as ICD-GPS-200 words
+//!   with their parity: Android's `GnssNavigationMessage` layout, each
+//!   30-bit word right-aligned in 4 big-endian bytes. Parity is checked and
+//!   the D30* inversion undone; the first word of each subframe follows a
+//!   word 10, whose last two bits the ICD fixes at zero.
+//! - The three subframes must carry the same issue of data (IODE in 2 and
+//!   3, the low byte of IODC in 1), or they are from different uploads.
+//! - The ICD scale factors are the ones MSG-GPSEPH uses, so conversion only
+//!   moves bit fields. The layout was read off the captured frames in
+//!   `agnss_window`'s tests: word 0 is the sum of the other 17, and bytes
+//!   69-71 hold the same constants in every capture, copied as seen.
+//! - Only GPS ephemerides convert. MSG-BDSEPH's layout is unverified, and
+//!   the receiver has no almanac or ionosphere/UTC message documented here.
+
+use crate::casic::{
+    encode_casic_packet, CasicParser, CASIC_CLASS_AID, CASIC_CLASS_MSG, CASIC_ID_AID_INI,
+    CASIC_ID_MSG_GPSEPH, CASIC_MAX_PAYLOAD_SIZE,
+};
+
+/// Largest frame the splitter hands on.
+pub const MAX_FRAME_LEN: usize = CASIC_MAX_PAYLOAD_SIZE + 10;
+
+/// Words in an LNAV subframe.
+const LNAV_WORDS: usize = 10;
+/// Subframes 1-3, each word in 4 bytes.
+pub const LNAV_EPHEMERIS_LEN: usize = 3 * LNAV_WORDS * 4;
+const LNAV_PREAMBLE: u32 = 0x8B;
+/// Parity equations of ICD-GPS-200 table 20-XIV over a word laid out as
+/// D29* D30* d1..d24 D25..D30 from bit 31 down.
+const LNAV_PARITY: [u32; 6] = [
+    0xBB1F_3480,
+    0x5D8F_9A40,
+    0xAEC7_CD00,
+    0x5763_E680,
+    0x6BB1_F340,
+    0x8B7A_89C0,
+];
+
+const GPS_EPHEMERIS_LEN: usize = 72;
+/// Encoded MSG-GPSEPH frame.
+pub const GPS_EPHEMERIS_FRAME_LEN: usize = GPS_EPHEMERIS_LEN + 10;
+const GPS_EPHEMERIS_VALID: u8 = 0x03;
+const GPS_EPHEMERIS_TRAILER: u16 = 0x41A3;
+
+/// Why an LNAV ephemeris was refused; the value is the wire status.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LnavError {
+    /// A word failed its parity check.
+    Parity = 1,
+    /// Not subframes 1, 2 and 3 in order.
+    Subframe = 2,
+    /// The subframes carry different issues of data.
+    IssueMismatch = 3,
+    /// PRN outside 1-32.
+    Svid = 4,
+}
+
+/// Convert subframes 1-3 of PRN `svid` into a MSG-GPSEPH frame.
+pub fn gps_ephemeris_from_lnav(
+    svid: u8,
+    subframes: &[u8; LNAV_EPHEMERIS_LEN],
+) -> Result<[u8; GPS_EPHEMERIS_FRAME_LEN], LnavError> {
+    if !(1..=32).contains(&svid) {
+        return Err(LnavError::Svid);
+    }
+    let mut data = [[0u32; LNAV_WORDS]; 3];
+    for (index, (words, raw)) in data.iter_mut().zip(subframes.chunks_exact(40)).enumerate() {
+        *words = lnav_subframe_data(raw).ok_or(LnavError::Parity)?;
+        let preamble = Subframe(words).bits(0, 8);
+        if preamble != LNAV_PREAMBLE || Subframe(words).bits(43, 3) != index as u32 + 1 {
+            return Err(LnavError::Subframe);
+        }
+    }
+    let (sf1, sf2, sf3) = (Subframe(&data[0]), Subframe(&data[1]), Subframe(&data[2]));
+    let iodc = sf1.bits(70, 2) << 8 | sf1.bits(168, 8);
+    let iode = sf2.bits(48, 8);
+    if iode != sf3.bits(216, 8) || iode != iodc & 0xFF {
+        return Err(LnavError::IssueMismatch);
+    }
+
+    let mut payload = [0u8; GPS_EPHEMERIS_LEN];
+    let mut put = |at: usize, bytes: &[u8]| payload[at..at + bytes.len()].copy_from_slice(bytes);
+    put(4, &sf2.bits(184, 32).to_le_bytes()); // sqrtA
+    put(8, &sf2.bits(136, 32).to_le_bytes()); // e
+    put(12, &sf3.signed(160, 32).to_le_bytes()); // omega
+    put(16, &sf2.signed(88, 32).to_le_bytes()); // M0
+    put(20, &sf3.signed(112, 32).to_le_bytes()); // i0
+    put(24, &sf3.signed(64, 32).to_le_bytes()); // OMEGA0
+    put(28, &sf3.signed(192, 24).to_le_bytes()); // OMEGADOT
+    put(32, &(sf2.signed(72, 16) as i16).to_le_bytes()); // delta n
+    put(34, &(sf3.signed(224, 14) as i16).to_le_bytes()); // IDOT
+    put(36, &(sf2.signed(120, 16) as i16).to_le_bytes()); // Cuc
+    put(38, &(sf2.signed(168, 16) as i16).to_le_bytes()); // Cus
+    put(40, &(sf3.signed(144, 16) as i16).to_le_bytes()); // Crc
+    put(42, &(sf2.signed(56, 16) as i16).to_le_bytes()); // Crs
+    put(44, &(sf3.signed(48, 16) as i16).to_le_bytes()); // Cic
+    put(46, &(sf3.signed(96, 16) as i16).to_le_bytes()); // Cis
+    put(48, &(sf2.bits(216, 16) as u16).to_le_bytes()); // toe
+    put(50, &(sf1.bits(48, 10) as u16).to_le_bytes()); // week mod 1024
+    put(52, &(sf1.bits(176, 16) as u16).to_le_bytes()); // toc
+    put(56, &sf1.signed(216, 22).to_le_bytes()); // af0
+    put(60, &(sf1.signed(200, 16) as i16).to_le_bytes()); // af1
+    put(62, &[sf1.bits(192, 8) as u8, sf1.bits(160, 8) as u8]); // af2, TGD
+    put(64, &(iodc as u16).to_le_bytes());
+    let (ura, health) = (sf1.bits(60, 4) as u8, sf1.bits(64, 6) as u8);
+    put(66, &[ura, health, svid, GPS_EPHEMERIS_VALID]);
+    put(70, &GPS_EPHEMERIS_TRAILER.to_le_bytes());
+    let sum = payload[4..]
+        .chunks_exact(4)
+        .map(|w| u32::from_le_bytes([w[0], w[1], w[2], w[3]]))
+        .fold(0, u32::wrapping_add);
+    payload[..4].copy_from_slice(&sum.to_le_bytes());
+
+    let mut frame = [0u8; GPS_EPHEMERIS_FRAME_LEN];
+    encode_casic_packet(CASIC_CLASS_MSG, CASIC_ID_MSG_GPSEPH, &payload, &mut frame);
+    Ok(frame)
+}
+
+/// The 24 data bits of each word in a 40-byte subframe, parity checked.
+fn lnav_subframe_data(raw: &[u8]) -> Option<[u32; LNAV_WORDS]> {
+    let mut data = [0u32; LNAV_WORDS];
+    // D29* and D30* of the word before, in bits 31 and 30.
+    let mut previous = 0u32;
+    for (out, bytes) in data.iter_mut().zip(raw.chunks_exact(4)) {
+        let received = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        let mut word = previous | received & 0x3FFF_FFFF;
+        if word & 0x4000_0000 != 0 {
+            word ^= 0x3FFF_FFC0;
+        }
+        if lnav_parity(word) != word & 0x3F {
+            return None;
+        }
+        *out = word >> 6 & 0xFF_FFFF;
+        previous = received << 30;
+    }
+    Some(data)
+}
+
+/// D25..D30 for a word laid out as in `LNAV_PARITY`, data not inverted.
+fn lnav_parity(word: u32) -> u32 {
+    LNAV_PARITY.iter().fold(0, |parity, mask| {
+        parity << 1 | (word & mask).count_ones() & 1
+    })
+}
+
+/// Data bits of one subframe, numbered from 0 with the parity left out.
+struct Subframe<'a>(&'a [u32; LNAV_WORDS]);
+
+impl Subframe<'_> {
+    fn bits(&self, start: usize, len: usize) -> u32 {
+        (start..start + len).fold(0, |value, bit| {
+            value << 1 | self.0[bit / 24] >> (23 - bit % 24) & 1
+        })
+    }
+
+    fn signed(&self, start: usize, len: usize) -> i32 {
+        ((self.bits(start, len) << (32 - len)) as i32) >> (32 - len)
+    }
+}
+
+pub struct FrameSplitter {
+    parser: CasicParser,
+    kept: u16,
+    dropped: u16,
+}
+
+impl FrameSplitter {
+    pub fn new() -> Self {
+        Self {
+            parser: CasicParser::new(),
+            kept: 0,
+            dropped: 0,
+        }
+    }
+
+    /// Feed the next slice of the file. `sink` gets every frame worth
+    /// sending and returns false if it has no room, which counts the frame
+    /// as dropped.
+    pub fn push(&mut self, bytes: &[u8], mut sink: impl FnMut(&[u8]) -> bool) {
+        let mut frame = [0u8; MAX_FRAME_LEN];
+        for &byte in bytes {
+            // Uploads arrive faster than the parser's receiver timeout.
+            if !self.parser.encode(byte, 0) {
+                continue;
+            }
+            if !self.parser.is_new_casic_data() {
+                // Checksum mismatch.
+                self.dropped = self.dropped.saturating_add(1);
+                continue;
+            }
+            self.parser.clear_casic_data();
+            let packet = self.parser.last_casic_packet();
+            let aid_ini = packet.class_id == CASIC_CLASS_AID && packet.msg_id == CASIC_ID_AID_INI;
+            let payload = &packet.payload[..packet.payload_length as usize];
+            let stored = (aid_ini || packet.class_id == CASIC_CLASS_MSG)
+                && encode_casic_packet(packet.class_id, packet.msg_id, payload, &mut frame)
+                    .is_some_and(|len| sink(&frame[..len]));
+            if stored {
+                self.kept = self.kept.saturating_add(1);
+            } else {
+                self.dropped = self.dropped.saturating_add(1);
+            }
+        }
+    }
+
+    /// Frames handed to the sink so far.
+    pub fn kept(&self) -> u16 {
+        self.kept
+    }
+
+    /// Frames refused so far: bad checksum, not AGNSS data, or no room.
+    pub fn dropped(&self) -> u16 {
+        self.dropped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::casic::{casic_checksum, CASIC_CLASS_CFG, CASIC_ID_CFG_MSG};
+
+    fn frame(class_id: u8, msg_id: u8, len: usize) -> Vec<u8> {
+        let payload: Vec<u8> = (0..len as u8).collect();
+        let mut out = [0u8; MAX_FRAME_LEN];
+        let len = encode_casic_packet(class_id, msg_id, &payload, &mut out).unwrap();
+        out[..len].to_vec()
+    }
+
+    #[test]
+    fn reassembles_frames_split_across_pushes() {
+        let eph = frame(CASIC_CLASS_MSG, CASIC_ID_MSG_GPSEPH, 72);
+        let ini = frame(CASIC_CLASS_AID, CASIC_ID_AID_INI, 56);
+        let mut file = vec![0x00, 0x42];
+        file.extend(&ini);
+        file.extend(&eph);
+        file.extend(&eph);
+
+        let mut splitter = FrameSplitter::new();
+        let mut out: Vec<Vec<u8>> = Vec::new();
+        for slice in file.chunks(17) {
+            splitter.push(slice, |frame| {
+                out.push(frame.to_vec());
+                true
+            });
+        }
+        assert_eq!(out, vec![ini, eph.clone(), eph]);
+        assert_eq!((splitter.kept(), splitter.dropped()), (3, 0));
+    }
+
+    #[test]
+    fn drops_corrupt_and_foreign_frames() {
+        let mut corrupt = frame(CASIC_CLASS_MSG, CASIC_ID_MSG_GPSEPH, 72);
+        corrupt[20] ^= 0x01;
+        let mut file = corrupt;
+        file.extend(frame(CASIC_CLASS_CFG, CASIC_ID_CFG_MSG, 4));
+        file.extend(frame(CASIC_CLASS_MSG, CASIC_ID_MSG_GPSEPH, 72));
+
+        let mut splitter = FrameSplitter::new();
+        let mut offered = 0;
+        // The queue is full by the time the one good frame arrives.
+        splitter.push(&file, |_| {
+            offered += 1;
+            false
+        });
+        assert_eq!(offered, 1);
+        assert_eq!((splitter.kept(), splitter.dropped()), (0, 3));
+    }
+
+    /// PRN 1 from the L76K AGNSS guide's example upload, as in `agnss_window`.
+    const CAPTURED_PRN1: [u8; 72] = [
+        0xCD, 0xCD, 0x9A, 0x10, 0xE5, 0x7D, 0x0D, 0xA1, 0xA0, 0x03, 0x59, 0x05, 0x58, 0x30, 0x63,
+        0x21, 0x98, 0x4B, 0x91, 0x03, 0xDA, 0x64, 0x0F, 0x28, 0xEC, 0x77, 0x2E, 0xB1, 0x4C, 0xA8,
+        0xFF, 0xFF, 0xEC, 0x2C, 0x81, 0x05, 0x12, 0x05, 0x85, 0x14, 0xDB, 0x19, 0x9C, 0x05, 0x0C,
+        0x00, 0x40, 0x00, 0xFA, 0x32, 0x63, 0x00, 0xFA, 0x32, 0x00, 0x00, 0x8E, 0x96, 0x18, 0x00,
+        0xB7, 0xFF, 0x00, 0x0A, 0x27, 0x00, 0x00, 0x00, 0x01, 0x03, 0xA3, 0x41,
+    ];
+
+    /// (subframe, first data bit, bits, MSG-GPSEPH offset, bytes) per field.
+    const FIELDS: [(usize, usize, usize, usize, usize); 26] = [
+        (1, 48, 10, 50, 2),
+        (1, 60, 4, 66, 1),
+        (1, 64, 6, 67, 1),
+        (1, 70, 2, 65, 1),
+        (1, 168, 8, 64, 1),
+        (1, 160, 8, 63, 1),
+        (1, 176, 16, 52, 2),
+        (1, 192, 8, 62, 1),
+        (1, 200, 16, 60, 2),
+        (1, 216, 22, 56, 4),
+        (2, 56, 16, 42, 2),
+        (2, 72, 16, 32, 2),
+        (2, 88, 32, 16, 4),
+        (2, 120, 16, 36, 2),
+        (2, 136, 32, 8, 4),
+        (2, 168, 16, 38, 2),
+        (2, 184, 32, 4, 4),
+        (2, 216, 16, 48, 2),
+        (3, 48, 16, 44, 2),
+        (3, 64, 32, 24, 4),
+        (3, 96, 16, 46, 2),
+        (3, 112, 32, 20, 4),
+        (3, 144, 16, 40, 2),
+        (3, 160, 32, 12, 4),
+        (3, 192, 24, 28, 4),
+        (3, 224, 14, 34, 2),
+    ];
+
+    fn set_bits(data: &mut [u32; LNAV_WORDS], start: usize, len: usize, value: u32) {
+        for i in 0..len {
+            let bit = start + i;
+            let mask = 1 << (23 - bit % 24);
+            if value >> (len - 1 - i) & 1 != 0 {
+                data[bit / 24] |= mask;
+            } else {
+                data[bit / 24] &= !mask;
+            }
+        }
+    }
+
+    /// Subframes 1-3 carrying the fields of a MSG-GPSEPH payload, with parity.
+    fn lnav_from(payload: &[u8; 72]) -> [u8; LNAV_EPHEMERIS_LEN] {
+        let mut data = [[0u32; LNAV_WORDS]; 3];
+        for (index, words) in data.iter_mut().enumerate() {
+            set_bits(words, 0, 8, LNAV_PREAMBLE);
+            set_bits(words, 43, 3, index as u32 + 1);
+        }
+        for &(subframe, start, len, at, size) in &FIELDS {
+            let mut raw = [0u8; 4];
+            raw[..size].copy_from_slice(&payload[at..at + size]);
+            let value = u32::from_le_bytes(raw) & (u32::MAX >> (32 - len));
+            set_bits(&mut data[subframe - 1], start, len, value);
+        }
+        let iode = payload[64] as u32;
+        set_bits(&mut data[1], 48, 8, iode);
+        set_bits(&mut data[2], 216, 8, iode);
+
+        let mut out = [0u8; LNAV_EPHEMERIS_LEN];
+        let mut words = out.chunks_exact_mut(4);
+        for subframe in &data {
+            let mut previous = 0u32;
+            for &source in subframe {
+                let parity = lnav_parity(previous | source << 6);
+                let sent = if previous & 0x4000_0000 != 0 {
+                    source ^ 0xFF_FFFF
+                } else {
+                    source
+                };
+                let received = sent << 6 | parity;
+                words
+                    .next()
+                    .unwrap()
+                    .copy_from_slice(&received.to_be_bytes());
+                previous = received << 30;
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn converts_lnav_subframes_to_the_captured_frame() {
+        let frame = gps_ephemeris_from_lnav(1, &lnav_from(&CAPTURED_PRN1)).unwrap();
+        assert_eq!(
+            frame[..6],
+            [0xBA, 0xCE, 72, 0, CASIC_CLASS_MSG, CASIC_ID_MSG_GPSEPH]
+        );
+        assert_eq!(frame[6..78], CAPTURED_PRN1);
+        let checksum = casic_checksum(CASIC_CLASS_MSG, CASIC_ID_MSG_GPSEPH, &CAPTURED_PRN1);
+        assert_eq!(frame[78..], checksum.to_le_bytes());
+    }
+
+    #[test]
+    fn rejects_broken_or_mixed_subframes() {
+        let lnav = lnav_from(&CAPTURED_PRN1);
+        assert_eq!(gps_ephemeris_from_lnav(0, &lnav), Err(LnavError::Svid));
+
+        let mut flipped = lnav;
+        flipped[85] ^= 0x10;
+        assert_eq!(gps_ephemeris_from_lnav(1, &flipped), Err(LnavError::Parity));
+
+        let mut swapped = lnav;
+        swapped[..40].copy_from_slice(&lnav[40..80]);
+        swapped[40..80].copy_from_slice(&lnav[..40]);
+        assert_eq!(
+            gps_ephemeris_from_lnav(1, &swapped),
+            Err(LnavError::Subframe)
+        );
+
+        // Subframe 3 from the next issue of data.
+        let mut next_issue = CAPTURED_PRN1;
+        next_issue[64] += 1;
+        let mut mixed = lnav;
+        mixed[80..].copy_from_slice(&lnav_from(&next_issue)[80..]);
+        assert_eq!(
+            gps_ephemeris_from_lnav(1, &mixed),
+            Err(LnavError::IssueMismatch)
+        );
+    }
+}
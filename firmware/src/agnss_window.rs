@@ -0,0 +1,209 @@
+//! Validity window of an uploaded AGNSS set.
+//!
+//! Broadcast ephemerides are fitted around their reference time `toe` and
+//! are only good for a few hours either side of it. The AGNSS queue reads
+//! `toe` from every GPS ephemeris message in an upload and keeps the
+//! window the whole set covers, so the device can refuse data that has
+//! already run out and tell the companion app when it needs a new download.
+//!
+//! # Design
+//!
+//! - `toe` is seconds into the GNSS week with no week number, so it is
+//!   resolved to the week closest to a reference time: the AID-INI time in
+//!   the same upload if it has one, otherwise the device clock.
+//! - MSG-GPSEPH is decoded at the offsets checked against captured frames
+//!   (see the tests). MSG-BDSEPH is not: its layout is unverified and the
+//!   BDS part of a download comes from the same snapshot as the GPS part.
+//! - A GPS ephemeris that does not decode leaves the whole set's window
+//!   unknown rather than guessing it, so a misread never refuses an upload.
+//! - The window runs from the earliest `toe` minus its half fit interval to
+//!   the latest `toe` plus its half fit interval. Freshness only looks at
+//!   the end: the receiver still has fresh satellites until the last one
+//!   expires.
+
+const GPS_EPOCH_UNIX_S: i64 = 315_964_800;
+const GPS_UTC_LEAP_SECONDS: i64 = 18;
+const SECONDS_PER_WEEK: i64 = 604_800;
+
+/// GPS ephemerides are fitted over 4 hours.
+pub const GPS_HALF_FIT_S: u32 = 2 * 3600;
+
+const AID_INI_MIN_LEN: usize = 56;
+const AID_INI_FLAG_TIME_VALID: u8 = 1 << 1;
+const GPS_EPHEMERIS_LEN: usize = 72;
+/// `toe`: U2 at this offset, in units of 16 s.
+const GPS_EPHEMERIS_TOE: usize = 48;
+const TOE_SCALE_S: u32 = 16;
+
+/// Unix time carried by an AID-INI payload, `None` without the time-valid
+/// flag.
+pub fn aid_ini_unix(payload: &[u8]) -> Option<u64> {
+    if payload.len() < AID_INI_MIN_LEN || payload[55] & AID_INI_FLAG_TIME_VALID == 0 {
+        return None;
+    }
+    let mut tow = [0u8; 8];
+    tow.copy_from_slice(&payload[24..32]);
+    let tow = f64::from_le_bytes(tow);
+    if !(0.0..SECONDS_PER_WEEK as f64).contains(&tow) {
+        return None;
+    }
+    let week = u16::from_le_bytes([payload[52], payload[53]]) as i64;
+    let gps_s = week * SECONDS_PER_WEEK + tow as i64;
+    u64::try_from(GPS_EPOCH_UNIX_S + gps_s - GPS_UTC_LEAP_SECONDS).ok()
+}
+
+/// `toe` of a MSG-GPSEPH payload, seconds of week; `None` when the payload
+/// does not decode as one.
+pub fn gps_ephemeris_toe(payload: &[u8]) -> Option<u32> {
+    if payload.len() != GPS_EPHEMERIS_LEN {
+        return None;
+    }
+    let raw = [payload[GPS_EPHEMERIS_TOE], payload[GPS_EPHEMERIS_TOE + 1]];
+    let toe = u16::from_le_bytes(raw) as u32 * TOE_SCALE_S;
+    ((toe as i64) < SECONDS_PER_WEEK).then_some(toe)
+}
+
+/// Unix time of `toe_sow` in the week closest to `reference_unix`.
+pub fn resolve_toe(toe_sow: u32, reference_unix: u64) -> u64 {
+    let reference = reference_unix as i64 - GPS_EPOCH_UNIX_S + GPS_UTC_LEAP_SECONDS;
+    let week_start = reference - reference.rem_euclid(SECONDS_PER_WEEK);
+    let mut toe = week_start + toe_sow as i64;
+    if toe - reference > SECONDS_PER_WEEK / 2 {
+        toe -= SECONDS_PER_WEEK;
+    } else if reference - toe > SECONDS_PER_WEEK / 2 {
+        toe += SECONDS_PER_WEEK;
+    }
+    (toe + GPS_EPOCH_UNIX_S - GPS_UTC_LEAP_SECONDS).max(0) as u64
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ValidityWindow {
+    /// Unix seconds.
+    pub start: u64,
+    /// Unix seconds.
+    pub end: u64,
+}
+
+impl ValidityWindow {
+    /// Seconds left at `now_unix`; 0 once the window has ended.
+    pub fn remaining_s(&self, now_unix: u64) -> u32 {
+        self.end.saturating_sub(now_unix).min(u32::MAX as u64) as u32
+    }
+}
+
+/// Folds the ephemerides of one upload into a `ValidityWindow`.
+pub struct WindowBuilder {
+    reference_unix: u64,
+    window: Option<ValidityWindow>,
+}
+
+impl WindowBuilder {
+    pub const fn new(reference_unix: u64) -> Self {
+        Self {
+            reference_unix,
+            window: None,
+        }
+    }
+
+    pub fn add(&mut self, toe_sow: u32, half_fit_s: u32) {
+        let toe = resolve_toe(toe_sow, self.reference_unix);
+        let start = toe.saturating_sub(half_fit_s as u64);
+        let end = toe + half_fit_s as u64;
+        self.window = Some(match self.window {
+            Some(window) => ValidityWindow {
+                start: window.start.min(start),
+                end: window.end.max(end),
+            },
+            None => ValidityWindow { start, end },
+        });
+    }
+
+    /// `None` when no ephemeris was added.
+    pub fn finish(self) -> Option<ValidityWindow> {
+        self.window
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2024-06-01 12:00:00 UTC: GPS week 2316, Saturday.
+    const NOW: u64 = 1_717_243_200;
+
+    fn aid_ini(week: u16, tow: f64, flags: u8) -> [u8; 56] {
+        let mut payload = [0u8; 56];
+        payload[24..32].copy_from_slice(&tow.to_le_bytes());
+        payload[52..54].copy_from_slice(&week.to_le_bytes());
+        payload[55] = flags;
+        payload
+    }
+
+    #[test]
+    fn reads_aid_ini_time() {
+        // Saturday 12:00 UTC is 6 days + 12 h + 18 leap seconds into the week.
+        let payload = aid_ini(2316, 561_618.0, AID_INI_FLAG_TIME_VALID);
+        assert_eq!(aid_ini_unix(&payload), Some(NOW));
+        assert_eq!(aid_ini_unix(&aid_ini(2316, 561_618.0, 0)), None);
+    }
+
+    /// PRN 1 and PRN 2 from the L76K AGNSS guide's example upload
+    /// (docs/casic_agnss.md), with the checksum each frame carried.
+    const CAPTURED_GPSEPH: [([u8; 72], u32); 2] = [
+        (
+            [
+                0xCD, 0xCD, 0x9A, 0x10, 0xE5, 0x7D, 0x0D, 0xA1, 0xA0, 0x03, 0x59, 0x05, 0x58, 0x30,
+                0x63, 0x21, 0x98, 0x4B, 0x91, 0x03, 0xDA, 0x64, 0x0F, 0x28, 0xEC, 0x77, 0x2E, 0xB1,
+                0x4C, 0xA8, 0xFF, 0xFF, 0xEC, 0x2C, 0x81, 0x05, 0x12, 0x05, 0x85, 0x14, 0xDB, 0x19,
+                0x9C, 0x05, 0x0C, 0x00, 0x40, 0x00, 0xFA, 0x32, 0x63, 0x00, 0xFA, 0x32, 0x00, 0x00,
+                0x8E, 0x96, 0x18, 0x00, 0xB7, 0xFF, 0x00, 0x0A, 0x27, 0x00, 0x00, 0x00, 0x01, 0x03,
+                0xA3, 0x41,
+            ],
+            0x283D_9BE2,
+        ),
+        (
+            [
+                0xF3, 0xE1, 0x31, 0x8C, 0x1E, 0xFC, 0x0C, 0xA1, 0x8D, 0xA9, 0x6D, 0x0A, 0x3B, 0xF5,
+                0x4C, 0xC1, 0x28, 0xF5, 0xA4, 0x0D, 0x0A, 0x5C, 0x32, 0x27, 0xC1, 0xAD, 0xD6, 0xAD,
+                0xAB, 0xA3, 0xFF, 0xFF, 0xE0, 0x31, 0x8F, 0x04, 0xDA, 0x05, 0x7F, 0x15, 0x26, 0x17,
+                0x08, 0x07, 0x57, 0xFF, 0xB1, 0xFF, 0xFA, 0x32, 0x63, 0x00, 0xFA, 0x32, 0x00, 0x00,
+                0x39, 0xED, 0xEC, 0xFF, 0xDF, 0xFF, 0x00, 0xDA, 0x2A, 0x00, 0x00, 0x00, 0x02, 0x03,
+                0xA3, 0x41,
+            ],
+            0x1F6B_C42E,
+        ),
+    ];
+
+    #[test]
+    fn decodes_toe_from_captured_frames() {
+        for (payload, checksum) in &CAPTURED_GPSEPH {
+            // Transcribed right: the CASIC checksum of class 08, id 07, length 72.
+            let words = payload
+                .chunks(4)
+                .map(|w| u32::from_le_bytes(w.try_into().unwrap()));
+            assert_eq!(words.fold(0x0708_0048, u32::wrapping_add), *checksum);
+            // Tuesday 10:00 GPS time, on the two-hour grid toe sits on.
+            assert_eq!(gps_ephemeris_toe(payload), Some(208_800));
+        }
+        let (payload, _) = CAPTURED_GPSEPH[0];
+        assert_eq!(gps_ephemeris_toe(&payload[..71]), None);
+        let mut past_week = payload;
+        past_week[48..50].copy_from_slice(&0xFFFFu16.to_le_bytes());
+        assert_eq!(gps_ephemeris_toe(&past_week), None);
+    }
+
+    #[test]
+    fn window_spans_toe_across_the_week_boundary() {
+        // Saturday night: a toe early in the week belongs to the next week.
+        let reference = NOW + 11 * 3600;
+        let mut builder = WindowBuilder::new(reference);
+        builder.add(604_800 - 3600, GPS_HALF_FIT_S);
+        builder.add(1800, GPS_HALF_FIT_S);
+        let window = builder.finish().unwrap();
+        let week_end = NOW + 12 * 3600 - 18;
+        assert_eq!(window.start, week_end - 3 * 3600);
+        assert_eq!(window.end, week_end + 1800 + 2 * 3600);
+        assert_eq!(window.remaining_s(week_end), 9000);
+        assert_eq!(window.remaining_s(window.end + 1), 0);
+    }
+}
@@ -0,0 +1,184 @@
+//! Alert routing to the display, LED, buzzer and phone.
+//!
+//! Features report what happened with `raise`; this module decides where it
+//! goes. Each alert kind has an `alert.*` setting holding its output
+//! channels and severity, so the user picks per alert whether it shows a
+//! banner, blinks the LED or reaches the phone, instead of every feature
+//! poking the display itself.
+//!
+//! # Design
+//!
+//! - Setting value: bits 0-3 are the `CH_*` channel mask, bits 4-5 the
+//!   severity. Kind ids and channel bits are part of the protocol: only
+//!   append.
+//! - An info banner only shows while the display is on; warnings and
+//!   critical alerts turn it on.
+//! - The LED blinks once for info, twice for a warning and three times for
+//!   a critical alert. An alert raised while it blinks replaces the pending
+//!   one.
+//! - The buzzer, with the `buzzer` feature, beeps as often as the LED
+//!   blinks; see `sound`. Without it `CH_BUZZER` is accepted and ignored.
+//! - BLE alerts queue up to `EVENT_QUEUE` deep on the alert characteristic
+//!   and are dropped when no phone drains them; the BLE side clears the
+//!   queue on connect, like geofence crossings.
+//! - `geofence.banner` predates the router; turning it off still removes
+//!   the display channel of geofence alerts.
+
+use embassy_executor::task;
+use embassy_nrf::gpio::Output;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::channel::Channel;
+use embassy_sync::signal::Signal;
+use embassy_time::Timer;
+
+use crate::display;
+use crate::phone_location;
+use crate::settings;
+#[cfg(feature = "buzzer")]
+use crate::sound;
+use crate::system_info::SYSTEM_INFO;
+use crate::timezone;
+
+pub const CH_DISPLAY: u8 = 1 << 0;
+pub const CH_LED: u8 = 1 << 1;
+#[cfg_attr(not(feature = "buzzer"), allow(dead_code))]
+pub const CH_BUZZER: u8 = 1 << 2;
+pub const CH_BLE: u8 = 1 << 3;
+const CHANNEL_MASK: i32 = 0x0F;
+const SEVERITY_SHIFT: u32 = 4;
+
+/// Serialized `AlertEvent`: `[kind: u8][severity: u8][timestamp: u32]`.
+pub const ALERT_EVENT_LEN: usize = 6;
+const EVENT_QUEUE: usize = 4;
+
+const BLINK_ON_MS: u64 = 150;
+const BLINK_OFF_MS: u64 = 250;
+
+#[derive(Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum AlertKind {
+    Geofence = 1,
+    LowBattery = 2,
+    KeepAliveExpired = 3,
+    Storm = 4,
+}
+
+impl AlertKind {
+    fn setting(self) -> u16 {
+        match self {
+            AlertKind::Geofence => settings::ALERT_GEOFENCE,
+            AlertKind::LowBattery => settings::ALERT_BATTERY,
+            AlertKind::KeepAliveExpired => settings::ALERT_KEEP_ALIVE,
+            AlertKind::Storm => settings::ALERT_STORM,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, defmt::Format)]
+pub enum Severity {
+    Info = 0,
+    Warning = 1,
+    Critical = 2,
+}
+
+impl Severity {
+    fn from_setting(value: i32) -> Self {
+        match value >> SEVERITY_SHIFT {
+            0 => Severity::Info,
+            1 => Severity::Warning,
+            _ => Severity::Critical,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct AlertEvent {
+    pub kind: AlertKind,
+    pub severity: Severity,
+    /// Unix time of the alert, 0 without GPS or phone time.
+    pub timestamp: u32,
+}
+
+impl AlertEvent {
+    pub fn to_bytes(&self) -> [u8; ALERT_EVENT_LEN] {
+        let mut out = [0u8; ALERT_EVENT_LEN];
+        out[0] = self.kind as u8;
+        out[1] = self.severity as u8;
+        out[2..6].copy_from_slice(&self.timestamp.to_le_bytes());
+        out
+    }
+}
+
+static EVENTS: Channel<CriticalSectionRawMutex, AlertEvent, EVENT_QUEUE> = Channel::new();
+static BLINK: Signal<CriticalSectionRawMutex, Severity> = Signal::new();
+
+/// Route one alert to the channels its setting selects. `text` is the
+/// banner, cut to the display width.
+pub async fn raise(kind: AlertKind, text: &str) {
+    let value = settings::stored(kind.setting());
+    let severity = Severity::from_setting(value);
+    let mut channels = (value & CHANNEL_MASK) as u8;
+    if kind == AlertKind::Geofence && settings::stored(settings::GEOFENCE_BANNER) == 0 {
+        channels &= !CH_DISPLAY;
+    }
+    defmt::info!("Alert {} ({}): channels 0x{:02x}", kind, severity, channels);
+
+    let display_on = display::remote_state()[0] != 0;
+    if channels & CH_DISPLAY != 0 && (severity > Severity::Info || display_on) {
+        display::show_banner(text);
+    }
+    if channels & CH_LED != 0 {
+        BLINK.signal(severity);
+    }
+    #[cfg(feature = "buzzer")]
+    if channels & CH_BUZZER != 0 {
+        sound::alert(severity);
+    }
+    if channels & CH_BLE != 0 {
+        let timestamp = unix_ts().await;
+        let _ = EVENTS.try_send(AlertEvent {
+            kind,
+            severity,
+            timestamp,
+        });
+    }
+}
+
+async fn unix_ts() -> u32 {
+    let info = *SYSTEM_INFO.lock().await;
+    if !info.date_time_valid {
+        return phone_location::unix_now().map_or(0, |unix_ts| unix_ts as u32);
+    }
+    timezone::date_time_to_unix_timestamp(
+        info.year,
+        info.month,
+        info.day,
+        info.hour,
+        info.minute,
+        info.second,
+    )
+    .unwrap_or(0)
+}
+
+/// Wait for the next alert routed to BLE.
+pub async fn next_event() -> AlertEvent {
+    EVENTS.receive().await
+}
+
+/// Drop alerts nobody was connected to receive.
+pub fn clear_events() {
+    EVENTS.clear();
+}
+
+/// Blink the status LED (P0.15, active high) for alerts on `CH_LED`.
+#[task]
+pub async fn led_task(mut led: Output<'static>) {
+    loop {
+        let severity = BLINK.wait().await;
+        for _ in 0..=severity as u8 {
+            led.set_high();
+            Timer::after_millis(BLINK_ON_MS).await;
+            led.set_low();
+            Timer::after_millis(BLINK_OFF_MS).await;
+        }
+    }
+}
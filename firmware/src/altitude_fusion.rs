@@ -0,0 +1,92 @@
+//! Barometric altitude fused with GPS altitude for the track log.
+//!
+//! GPS altitude has the right level but jumps by metres from fix to fix.
+//! The BMP280 altitude is smooth, but it drifts with the weather and rests on
+//! a guessed sea-level pressure. A complementary filter takes the profile's
+//! shape from the barometer and its level from the GPS.
+//!
+//! # Design
+//!
+//! - The output is `baro + offset`. The offset follows `gps - baro` through
+//!   a first-order low-pass with time constant `TAU_S`, so weather drift is
+//!   removed while fix-to-fix noise is averaged out.
+//! - Fixes with an HDOP above `MAX_HDOP` do not move the offset.
+//! - The first fix, or the first after a gap longer than `RESEED_GAP_S`,
+//!   sets the offset directly: while the GPS slept the weather may have
+//!   moved the barometer further than the filter could follow quickly.
+//! - Without a barometer reading the GPS altitude passes through unchanged.
+
+const TAU_S: f32 = 120.0;
+const MAX_HDOP: f32 = 5.0;
+const RESEED_GAP_S: u32 = 600;
+
+pub struct AltitudeFusion {
+    offset_m: f32,
+    /// Timestamp of the last fix that updated the offset.
+    last_timestamp: Option<u32>,
+}
+
+impl AltitudeFusion {
+    pub const fn new() -> Self {
+        Self {
+            offset_m: 0.0,
+            last_timestamp: None,
+        }
+    }
+
+    /// Fused altitude for a fix at `timestamp`.
+    pub fn update(
+        &mut self,
+        timestamp: u32,
+        gps_altitude_m: f32,
+        hdop: f32,
+        baro_altitude_m: Option<f32>,
+    ) -> f32 {
+        let Some(baro) = baro_altitude_m else {
+            return gps_altitude_m;
+        };
+        let error = gps_altitude_m - baro;
+        match self.last_timestamp {
+            Some(last) if timestamp.wrapping_sub(last) <= RESEED_GAP_S => {
+                if hdop <= MAX_HDOP {
+                    let dt = timestamp.saturating_sub(last) as f32;
+                    self.offset_m += (error - self.offset_m) * dt / (TAU_S + dt);
+                    self.last_timestamp = Some(timestamp);
+                }
+            }
+            _ => {
+                self.offset_m = error;
+                self.last_timestamp = Some(timestamp);
+            }
+        }
+        baro + self.offset_m
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn passes_gps_through_without_a_barometer() {
+        let mut fusion = AltitudeFusion::new();
+        assert_eq!(fusion.update(100, 42.0, 1.0, None), 42.0);
+    }
+
+    #[test]
+    fn smooths_gps_noise_and_follows_baro_shape() {
+        let mut fusion = AltitudeFusion::new();
+        assert_eq!(fusion.update(0, 100.0, 1.0, Some(90.0)), 100.0);
+        // GPS jumps 8 m for a second while the barometer climbs 1 m.
+        let fused = fusion.update(1, 108.0, 1.0, Some(91.0));
+        assert!((fused - 101.06).abs() < 0.01, "{fused}");
+    }
+
+    #[test]
+    fn reseeds_after_a_gap() {
+        let mut fusion = AltitudeFusion::new();
+        fusion.update(0, 100.0, 1.0, Some(90.0));
+        // An hour later the weather moved the barometer by 20 m.
+        assert_eq!(fusion.update(3600, 100.0, 1.0, Some(70.0)), 100.0);
+    }
+}
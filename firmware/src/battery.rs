@@ -1,13 +1,33 @@
 use embassy_executor::task;
 use embassy_nrf::saadc::Saadc;
-use embassy_time::Timer;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::mutex::Mutex;
+use embassy_time::{Instant, Timer};
 
-use crate::system_info::SYSTEM_INFO;
+use crate::alerts::{self, AlertKind};
+use crate::battery_history::{BatteryHistory, HistorySlot, HISTORY_SLOTS};
+use crate::bmp280;
+use crate::diag::{self, TaskId};
+use crate::display;
+use crate::fuel_gauge::{self, FuelGauge, GaugeSample};
+use crate::power::{self, CutoffMonitor, ShutdownReason};
+use crate::settings;
+use crate::solar::{self, SolarMode, SolarPolicy};
+use crate::storage;
+use crate::system_info::{GpsState, SYSTEM_INFO};
+use crate::timezone;
+use crate::usb_connected;
 
 const BATTERY_UPDATE_INTERVAL_MS: u64 = 1_000;
 const BATTERY_EMA_ALPHA_FAST: f32 = 0.70;
 const BATTERY_EMA_ALPHA_SLOW: f32 = 0.12;
 const BATTERY_FAST_DELTA_MV: f32 = 80.0;
+/// Charge that raises the low battery alert, once per discharge.
+const LOW_BATTERY_PERCENT: u8 = 10;
+/// Charge the alert re-arms above, so it does not repeat at the threshold.
+const LOW_BATTERY_REARM_PERCENT: u8 = 15;
+/// One `/BATT.CSV` line per interval.
+const BATTERY_LOG_INTERVAL_S: u64 = 60;
 
 // ADC 电压转换常量
 // embassy-nrf SAADC 默认配置:
@@ -32,6 +52,19 @@ const VBAT_DIVIDER_COMP: f32 = 1.0 / VBAT_ADC_RATIO; // 1.666...
 
 const REAL_VBAT_MV_PER_LSB: f32 = VBAT_MV_PER_LSB * VBAT_DIVIDER_COMP;
 
+static HISTORY: Mutex<CriticalSectionRawMutex, BatteryHistory> = Mutex::new(BatteryHistory::new());
+
+/// Five-minute history slots, newest first after skipping `skip`, for
+/// `BATTERY_HISTORY`; returns the number copied into `out`.
+pub async fn read_history(skip: usize, out: &mut [HistorySlot]) -> usize {
+    HISTORY.lock().await.read_newest(skip, out)
+}
+
+/// Last 24 hours of voltage, oldest first, for the battery page.
+pub async fn history_series() -> [Option<f32>; HISTORY_SLOTS] {
+    HISTORY.lock().await.series()
+}
+
 #[task]
 pub async fn battery_task(mut saadc: Saadc<'static, 1>) {
     saadc.calibrate().await;
@@ -39,9 +72,16 @@ pub async fn battery_task(mut saadc: Saadc<'static, 1>) {
     let mut ema_initialized = false;
     let mut last_filtered_mv = 0.0f32;
     let mut sample = [0i16; 1];
+    let mut solar_policy = SolarPolicy::new();
+    let mut cutoff = CutoffMonitor::new();
+    let mut low_alerted = false;
+    let mut gauge = FuelGauge::new();
+    let mut last_sample_s: Option<f32> = None;
+    let mut next_log_s = 0;
 
     loop {
         saadc.sample(&mut sample).await;
+        let busy = diag::busy(TaskId::Battery);
         let raw = sample[0].max(0) as u16;
         let voltage_mv = raw as f32 * REAL_VBAT_MV_PER_LSB;
 
@@ -59,45 +99,86 @@ pub async fn battery_task(mut saadc: Saadc<'static, 1>) {
                 last_filtered_mv = alpha * voltage_mv + (1.0 - alpha) * last_filtered_mv;
             }
 
+            let temperature_c = {
+                let bmp = bmp280::BMP280_DATA.lock().await;
+                bmp.ok.then_some(bmp.temperature_c)
+            };
+            let now_s = Instant::now().as_millis() as f32 / 1000.0;
+            let elapsed_s = last_sample_s.map_or(0.0, |last| now_s - last);
+            last_sample_s = Some(now_s);
             let mut info = SYSTEM_INFO.lock().await;
+            let gps_on = info.gps_state != GpsState::S2IdleGpsOff;
+            let display_on = display::remote_state()[0] != 0;
+            let sample = GaugeSample {
+                voltage_mv: last_filtered_mv,
+                temperature_c,
+                load_ma: fuel_gauge::estimate_load_ma(gps_on, display_on),
+                vbus: usb_connected(),
+                elapsed_s,
+            };
+            let capacity_mah = settings::stored(settings::POWER_BATTERY_MAH) as f32;
+            let reading = gauge.update(&sample, capacity_mah);
+            let percent = reading.percent;
             info.battery_voltage = last_filtered_mv / 1000.0;
+            info.battery_percent = percent;
+            info.charge_state = reading.state;
+            info.time_to_empty_min = reading.time_to_empty_min.unwrap_or(u16::MAX);
+
+            let uptime_s = Instant::now().as_secs();
+            let unix_ts = if info.date_time_valid {
+                timezone::date_time_to_unix_timestamp(
+                    info.year,
+                    info.month,
+                    info.day,
+                    info.hour,
+                    info.minute,
+                    info.second,
+                )
+            } else {
+                None
+            };
+            if let Some(unix_ts) = unix_ts.filter(|_| info.location_valid) {
+                solar_policy.note_fix(uptime_s, unix_ts, info.latitude, info.longitude);
+            }
+            drop(info);
+            HISTORY
+                .lock()
+                .await
+                .note(uptime_s, last_filtered_mv, raw, usb_connected());
+            if uptime_s >= next_log_s {
+                next_log_s = uptime_s + BATTERY_LOG_INTERVAL_S;
+                let unix = unix_ts.unwrap_or(0);
+                let uptime = uptime_s as u32;
+                if !storage::append_battery_log(uptime, unix, raw, &sample, &reading).await {
+                    defmt::warn!("Battery: BATT.CSV append failed");
+                }
+            }
+            solar_policy.note_voltage(uptime_s, last_filtered_mv);
+            solar::publish(if settings::stored(settings::POWER_SOLAR) != 0 {
+                solar_policy.mode(uptime_s)
+            } else {
+                SolarMode::Normal
+            });
+            if usb_connected() || percent > LOW_BATTERY_REARM_PERCENT {
+                low_alerted = false;
+            } else if percent <= LOW_BATTERY_PERCENT && !low_alerted {
+                low_alerted = true;
+                alerts::raise(AlertKind::LowBattery, "Battery low").await;
+            }
+            let cutoff_mv = settings::stored(settings::POWER_CUTOFF_MV);
+            if cutoff.note_voltage(uptime_s, last_filtered_mv, cutoff_mv, usb_connected()) {
+                power::request_shutdown(ShutdownReason::LowBattery);
+            }
         } else {
             ema_initialized = false;
+            gauge.reset();
             let mut info = SYSTEM_INFO.lock().await;
             info.battery_voltage = -1.0;
+            info.battery_percent = 0;
+            info.time_to_empty_min = u16::MAX;
         }
+        drop(busy);
 
         Timer::after_millis(BATTERY_UPDATE_INTERVAL_MS).await;
     }
 }
-
-pub fn estimate_battery_level(voltage_mv: f32) -> f32 {
-    const VOLTAGE_POINTS: [f32; 11] = [
-        3000.0, 3300.0, 3500.0, 3600.0, 3700.0, 3800.0, 3850.0, 3900.0, 3950.0, 4100.0, 4200.0,
-    ];
-    const SOC_POINTS: [f32; 11] = [
-        0.0, 5.0, 10.0, 20.0, 35.0, 50.0, 60.0, 70.0, 80.0, 95.0, 100.0,
-    ];
-
-    if voltage_mv <= VOLTAGE_POINTS[0] {
-        return SOC_POINTS[0];
-    }
-    if voltage_mv >= VOLTAGE_POINTS[VOLTAGE_POINTS.len() - 1] {
-        return SOC_POINTS[SOC_POINTS.len() - 1];
-    }
-
-    for idx in 1..VOLTAGE_POINTS.len() {
-        if voltage_mv <= VOLTAGE_POINTS[idx] {
-            let v1 = VOLTAGE_POINTS[idx - 1];
-            let v2 = VOLTAGE_POINTS[idx];
-            let soc1 = SOC_POINTS[idx - 1];
-            let soc2 = SOC_POINTS[idx];
-            if (v2 - v1).abs() < f32::EPSILON {
-                return soc1;
-            }
-            return soc1 + (voltage_mv - v1) * (soc2 - soc1) / (v2 - v1);
-        }
-    }
-
-    0.0
-}
@@ -0,0 +1,250 @@
+//! Last 24 hours of battery voltage, five minutes per slot.
+//!
+//! `/BATT.CSV` already has a line a minute, but reading it needs the SD
+//! card and a file transfer. This keeps a day of averages in RAM for the
+//! `BATTERY_HISTORY` command and the battery page, so a discharge curve,
+//! or a cell that sags under load, shows at a glance.
+//!
+//! # Design
+//!
+//! - `HISTORY_SLOTS` slots of `SLOT_S`, indexed by uptime: the clock is not
+//!   needed, and a newly set clock does not split a slot. The history starts
+//!   over on reboot.
+//! - A slot holds the average filtered voltage, the average raw SAADC count
+//!   and whether the charger was connected at any point in it; the raw count
+//!   shows a divider or reference problem the filter would hide.
+//! - The current slot is read back with its running average, so the newest
+//!   slot is never more than a second old.
+//! - Slots without samples (no battery reading) read back empty, as
+//!   `voltage_mv == 0`.
+
+pub const HISTORY_SLOTS: usize = 288;
+pub const SLOT_S: u64 = 300;
+/// `[voltage_mv: u16][raw_adc: u16][flags: u8]`, little endian.
+pub const HISTORY_RECORD_LEN: usize = 5;
+/// `flags` bit: the charger was connected during the slot.
+pub const FLAG_CHARGING: u8 = 0x01;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct HistorySlot {
+    /// Average filtered voltage, 0 when the slot has no samples.
+    pub voltage_mv: u16,
+    /// Average SAADC count.
+    pub raw_adc: u16,
+    pub charging: bool,
+}
+
+impl HistorySlot {
+    pub fn is_empty(&self) -> bool {
+        self.voltage_mv == 0
+    }
+
+    pub fn to_bytes(self) -> [u8; HISTORY_RECORD_LEN] {
+        let mut out = [0u8; HISTORY_RECORD_LEN];
+        out[0..2].copy_from_slice(&self.voltage_mv.to_le_bytes());
+        out[2..4].copy_from_slice(&self.raw_adc.to_le_bytes());
+        out[4] = if self.charging { FLAG_CHARGING } else { 0 };
+        out
+    }
+}
+
+/// The slot being filled.
+#[derive(Clone, Copy)]
+struct Accumulator {
+    mv_sum: u32,
+    raw_sum: u32,
+    count: u32,
+    charging: bool,
+}
+
+impl Accumulator {
+    const EMPTY: Self = Self {
+        mv_sum: 0,
+        raw_sum: 0,
+        count: 0,
+        charging: false,
+    };
+
+    fn average(&self) -> HistorySlot {
+        if self.count == 0 {
+            return HistorySlot::default();
+        }
+        HistorySlot {
+            voltage_mv: (self.mv_sum / self.count) as u16,
+            raw_adc: (self.raw_sum / self.count) as u16,
+            charging: self.charging,
+        }
+    }
+}
+
+pub struct BatteryHistory {
+    /// Finished slots, by slot number modulo `HISTORY_SLOTS`.
+    slots: [HistorySlot; HISTORY_SLOTS],
+    /// Number of the current slot, `None` before the first sample.
+    current: Option<u64>,
+    accumulator: Accumulator,
+}
+
+impl BatteryHistory {
+    pub const fn new() -> Self {
+        Self {
+            slots: [HistorySlot {
+                voltage_mv: 0,
+                raw_adc: 0,
+                charging: false,
+            }; HISTORY_SLOTS],
+            current: None,
+            accumulator: Accumulator::EMPTY,
+        }
+    }
+
+    /// Add one battery sample taken at `uptime_s`.
+    pub fn note(&mut self, uptime_s: u64, voltage_mv: f32, raw_adc: u16, charging: bool) {
+        let slot = uptime_s / SLOT_S;
+        match self.current {
+            Some(current) if slot < current => return,
+            Some(current) if slot > current => {
+                self.slots[current as usize % HISTORY_SLOTS] = self.accumulator.average();
+                // Slots skipped over had no samples.
+                let skipped = (slot - current - 1).min(HISTORY_SLOTS as u64);
+                for number in current + 1..current + 1 + skipped {
+                    self.slots[number as usize % HISTORY_SLOTS] = HistorySlot::default();
+                }
+                self.accumulator = Accumulator::EMPTY;
+            }
+            _ => {}
+        }
+        self.current = Some(slot);
+        let accumulator = &mut self.accumulator;
+        accumulator.mv_sum += voltage_mv.clamp(0.0, u16::MAX as f32) as u32;
+        accumulator.raw_sum += raw_adc as u32;
+        accumulator.count += 1;
+        accumulator.charging |= charging;
+    }
+
+    /// Slots covered so far, the current one included.
+    pub fn len(&self) -> usize {
+        self.current.map_or(0, |current| {
+            (current + 1).min(HISTORY_SLOTS as u64) as usize
+        })
+    }
+
+    /// `skip` slots back from the current one, which is 0.
+    pub fn get(&self, skip: usize) -> Option<HistorySlot> {
+        let current = self.current?;
+        if skip >= self.len() {
+            return None;
+        }
+        if skip == 0 {
+            return Some(self.accumulator.average());
+        }
+        Some(self.slots[(current - skip as u64) as usize % HISTORY_SLOTS])
+    }
+
+    /// Copy slots into `out`, newest first after skipping `skip`; returns
+    /// the number copied.
+    pub fn read_newest(&self, skip: usize, out: &mut [HistorySlot]) -> usize {
+        let mut count = 0;
+        for (offset, slot) in out.iter_mut().enumerate() {
+            let Some(value) = self.get(skip + offset) else {
+                break;
+            };
+            *slot = value;
+            count += 1;
+        }
+        count
+    }
+
+    /// Voltage per slot over the whole window, oldest first, ending at the
+    /// current slot; `None` where there is no reading.
+    pub fn series(&self) -> [Option<f32>; HISTORY_SLOTS] {
+        let mut out = [None; HISTORY_SLOTS];
+        for (index, value) in out.iter_mut().enumerate() {
+            let skip = HISTORY_SLOTS - 1 - index;
+            if let Some(slot) = self.get(skip).filter(|slot| !slot.is_empty()) {
+                *value = Some(slot.voltage_mv as f32);
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slots_average_their_samples_and_read_back_newest_first() {
+        let mut history = BatteryHistory::new();
+        assert_eq!(history.len(), 0);
+        assert_eq!(history.get(0), None);
+
+        for second in 0..SLOT_S {
+            let mv = if second % 2 == 0 { 4000.0 } else { 4100.0 };
+            history.note(second, mv, 2730, false);
+        }
+        history.note(SLOT_S, 3990.0, 2724, false);
+        history.note(SLOT_S + 1, 4010.0, 2736, true);
+        assert_eq!(history.len(), 2);
+
+        let mut out = [HistorySlot::default(); 4];
+        assert_eq!(history.read_newest(0, &mut out), 2);
+        assert_eq!(
+            out[..2],
+            [
+                HistorySlot {
+                    voltage_mv: 4000,
+                    raw_adc: 2730,
+                    charging: true,
+                },
+                HistorySlot {
+                    voltage_mv: 4050,
+                    raw_adc: 2730,
+                    charging: false,
+                },
+            ]
+        );
+        assert_eq!(out[0].to_bytes(), [0xA0, 0x0F, 0xAA, 0x0A, FLAG_CHARGING]);
+        assert_eq!(history.read_newest(1, &mut out), 1);
+        assert_eq!(out[0].voltage_mv, 4050);
+        assert_eq!(history.read_newest(2, &mut out), 0);
+
+        // Older samples than the current slot are dropped.
+        history.note(10, 3000.0, 2000, false);
+        assert_eq!(history.get(1).unwrap().voltage_mv, 4050);
+    }
+
+    #[test]
+    fn gaps_read_empty_and_the_window_keeps_a_day() {
+        let mut history = BatteryHistory::new();
+        history.note(0, 4100.0, 2800, false);
+        // No battery reading for three slots.
+        history.note(4 * SLOT_S, 4000.0, 2730, false);
+        assert_eq!(history.len(), 5);
+        assert!(history.get(1).unwrap().is_empty());
+        assert!(history.get(3).unwrap().is_empty());
+        assert_eq!(history.get(4).unwrap().voltage_mv, 4100);
+
+        let series = history.series();
+        assert_eq!(series[HISTORY_SLOTS - 1], Some(4000.0));
+        assert_eq!(series[HISTORY_SLOTS - 2], None);
+        assert_eq!(series[HISTORY_SLOTS - 5], Some(4100.0));
+        assert_eq!(series[0], None);
+
+        // A day later the first slots have rotated out.
+        let day = HISTORY_SLOTS as u64 * SLOT_S;
+        for uptime_s in (day..day + 4 * SLOT_S).step_by(SLOT_S as usize) {
+            history.note(uptime_s, 3700.0, 2525, false);
+        }
+        assert_eq!(history.len(), HISTORY_SLOTS);
+        assert_eq!(history.get(HISTORY_SLOTS - 1).unwrap().voltage_mv, 4000);
+        assert!(!history.series().contains(&Some(4100.0)));
+        assert_eq!(history.get(3).unwrap().voltage_mv, 3700);
+        assert_eq!(history.get(HISTORY_SLOTS), None);
+        // A long gap empties the whole window.
+        history.note(10 * day, 3600.0, 2457, false);
+        assert!(history.series()[..HISTORY_SLOTS - 1]
+            .iter()
+            .all(Option::is_none));
+    }
+}
@@ -1,20 +1,44 @@
+use core::cell::{Cell, RefCell};
 use core::cmp;
-use core::sync::atomic::{AtomicU16, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicU16, AtomicU32, Ordering};
 
 use embassy_executor::task;
-use embassy_futures::select::{select, Either};
+use embassy_futures::select::{select, select3, select4, Either, Either3, Either4};
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex as BlockingMutex;
 use embassy_sync::channel::Channel;
 use embassy_sync::signal::Signal;
+use embassy_time::Instant;
 use heapless::Vec;
 use nrf_softdevice::ble::advertisement_builder::{
     Flag, LegacyAdvertisementBuilder, LegacyAdvertisementPayload, ServiceList,
 };
-use nrf_softdevice::ble::{gatt_server, peripheral, Connection, PhySet};
-use nrf_softdevice::Softdevice;
+use nrf_softdevice::ble::security::{IoCapabilities, SecurityHandler};
+use nrf_softdevice::ble::{
+    gatt_server, peripheral, Address, Connection, EncryptionInfo, IdentityKey,
+    IdentityResolutionKey, MasterId, PhySet, SecurityMode,
+};
+use nrf_softdevice::{raw, Softdevice};
 
+use crate::accel;
 use crate::adv_scheduler::{AdvPriority, ADV_SCHEDULER};
+use crate::alerts;
+use crate::bonds::{BondRecord, BondTable, BONDS_FILE_MAX_LEN};
+use crate::crash;
+use crate::dfu::{self, DfuTarget};
+use crate::display;
+use crate::file_jobs;
+use crate::geofences;
+use crate::gps;
+use crate::guest;
+use crate::live_track;
+use crate::location_history;
+use crate::nmea_passthrough::{self, MAX_LINE_LEN, MAX_WRITE_LEN};
+use crate::phone_location;
 use crate::protocol::FileTransferProtocol;
+use crate::settings;
+use crate::storage::{self, GpsDataEncoder, FULL_BLOCK_INTERVAL};
+use crate::track_stats;
 
 pub const DEVICE_NAME: &str = "MGT GPS Tracker";
 const NUS_SERVICE_UUID: u128 = 0x6e400001_b5a3_f393_e0a9_e50e24dcca9e_u128;
@@ -26,10 +50,44 @@ const CONN_MIN_INTERVAL: u16 = 6; // 7.5ms (units of 1.25ms).
 const CONN_MAX_INTERVAL: u16 = 12; // 15ms (units of 1.25ms).
 const CONN_SLAVE_LATENCY: u16 = 0;
 const CONN_SUP_TIMEOUT: u16 = 400; // 4s (units of 10ms).
+const PAIRING_WINDOW_S: u32 = 60;
+/// Longest FMDN beacon actions write handled and notification sent: data
+/// id, length, one-time key and four bytes of data.
+pub(crate) const BEACON_ACTIONS_LEN: usize = 14;
+/// Longest a finder may hold the FMDN link, which takes the only
+/// connection the owner's app could use.
+#[cfg(feature = "google-fmdn")]
+const FMDN_LINK_MAX_S: u64 = 60;
 
 static RX_CHANNEL: Channel<CriticalSectionRawMutex, Vec<u8, MAX_GATT_PAYLOAD>, 8> = Channel::new();
+/// Config characteristic writes: setting id and the value to set, if any.
+static CONFIG_WRITES: Channel<CriticalSectionRawMutex, (u16, Option<i32>), 4> = Channel::new();
 static ADV_REQUEST_SIGNAL: Signal<CriticalSectionRawMutex, ()> = Signal::new();
 static ADV_REQUEST_TIMEOUT: AtomicU16 = AtomicU16::new(0);
+static CONNECTED: AtomicBool = AtomicBool::new(false);
+/// Set for good on power off.
+static ADV_STOPPED: AtomicBool = AtomicBool::new(false);
+/// Live track CCCD writes: whether notifications are now enabled.
+static LIVE_SUBSCRIBE: Signal<CriticalSectionRawMutex, bool> = Signal::new();
+/// Relay stream CCCD writes, as above.
+static RELAY_SUBSCRIBE: Signal<CriticalSectionRawMutex, bool> = Signal::new();
+/// Bonded peers, loaded from `/BONDS.DB` at boot.
+static BONDS: BlockingMutex<CriticalSectionRawMutex, RefCell<BondTable>> =
+    BlockingMutex::new(RefCell::new(BondTable::new()));
+/// A bond was added; `ble_task` saves the table.
+static BONDS_CHANGED: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+/// Uptime second at which the pairing window closes.
+static PAIRING_END_S: AtomicU32 = AtomicU32::new(0);
+/// Passkey of the pairing in progress, as ASCII digits.
+static PASSKEY: BlockingMutex<CriticalSectionRawMutex, Cell<Option<[u8; 6]>>> =
+    BlockingMutex::new(Cell::new(None));
+/// The current link's key came from a bond.
+static BOND_KEY: AtomicBool = AtomicBool::new(false);
+/// The current link is encrypted with a bonded key.
+static LINK_BONDED: AtomicBool = AtomicBool::new(false);
+/// `LINK_BONDED` may have changed; `ble_task` refreshes what only a trusted
+/// link may read.
+static LINK_TRUST_CHANGED: Signal<CriticalSectionRawMutex, ()> = Signal::new();
 
 static ADV_DATA: LegacyAdvertisementPayload = LegacyAdvertisementBuilder::new()
     .flags(&[Flag::GeneralDiscovery, Flag::LE_Only])
@@ -57,19 +115,449 @@ pub(crate) struct NusService {
     tx: Vec<u8, MAX_GATT_PAYLOAD>,
 }
 
+// Shares the NUS vendor base, so it needs no extra `vs_uuid_count` slot.
+#[nrf_softdevice::gatt_service(uuid = "6e400010-b5a3-f393-e0a9-e50e24dcca9e")]
+pub(crate) struct DisplayService {
+    /// `[power: u8][page: u8]`, see `display::apply_remote_state`.
+    #[characteristic(
+        uuid = "6e400011-b5a3-f393-e0a9-e50e24dcca9e",
+        read,
+        write,
+        notify,
+        value = "[1u8, 0u8]"
+    )]
+    state: [u8; 2],
+}
+
+// Same vendor base as NUS, like `DisplayService`.
+#[nrf_softdevice::gatt_service(uuid = "6e400020-b5a3-f393-e0a9-e50e24dcca9e")]
+pub(crate) struct FileJobService {
+    /// `file_jobs::progress()` record.
+    #[characteristic(
+        uuid = "6e400021-b5a3-f393-e0a9-e50e24dcca9e",
+        read,
+        notify,
+        value = "[0u8; file_jobs::PROGRESS_LEN]"
+    )]
+    progress: [u8; file_jobs::PROGRESS_LEN],
+}
+
+// Same vendor base as NUS. Reading needs an encrypted link, which the
+// phone sets up with Just Works pairing on first access.
+#[nrf_softdevice::gatt_service(uuid = "6e400030-b5a3-f393-e0a9-e50e24dcca9e")]
+pub(crate) struct HistoryService {
+    /// `location_history::snapshot()` record.
+    #[characteristic(
+        uuid = "6e400031-b5a3-f393-e0a9-e50e24dcca9e",
+        read,
+        security = "justworks",
+        value = "[0u8; location_history::HISTORY_LEN]"
+    )]
+    hourly: [u8; location_history::HISTORY_LEN],
+    /// `location_history::latest()` record, notified on every change.
+    #[characteristic(
+        uuid = "6e400032-b5a3-f393-e0a9-e50e24dcca9e",
+        read,
+        notify,
+        security = "justworks",
+        value = "[0u8; location_history::LATEST_LEN]"
+    )]
+    latest: [u8; location_history::LATEST_LEN],
+}
+
+// Same vendor base as NUS.
+#[nrf_softdevice::gatt_service(uuid = "6e400040-b5a3-f393-e0a9-e50e24dcca9e")]
+pub(crate) struct LiveTrackService {
+    /// Whole delta-encoded track points, see `live_track`.
+    #[characteristic(
+        uuid = "6e400041-b5a3-f393-e0a9-e50e24dcca9e",
+        notify,
+        value = "heapless::Vec::<u8, MAX_GATT_PAYLOAD>::new()"
+    )]
+    points: Vec<u8, MAX_GATT_PAYLOAD>,
+    /// Relay batches: `[stream_id: u32][first_seq: u32]` and delta-encoded
+    /// points starting with a full block.
+    #[characteristic(
+        uuid = "6e400042-b5a3-f393-e0a9-e50e24dcca9e",
+        notify,
+        value = "heapless::Vec::<u8, MAX_GATT_PAYLOAD>::new()"
+    )]
+    relay: Vec<u8, MAX_GATT_PAYLOAD>,
+}
+
+// Same vendor base as NUS.
+#[nrf_softdevice::gatt_service(uuid = "6e400050-b5a3-f393-e0a9-e50e24dcca9e")]
+pub(crate) struct GeofenceService {
+    /// Last `geofences::FenceEvent`.
+    #[characteristic(
+        uuid = "6e400051-b5a3-f393-e0a9-e50e24dcca9e",
+        read,
+        notify,
+        value = "[0u8; geofences::FENCE_EVENT_LEN]"
+    )]
+    alert: [u8; geofences::FENCE_EVENT_LEN],
+}
+
+// Same vendor base as NUS.
+#[nrf_softdevice::gatt_service(uuid = "6e400060-b5a3-f393-e0a9-e50e24dcca9e")]
+pub(crate) struct StepsService {
+    /// `accel::steps_snapshot()` record.
+    #[characteristic(
+        uuid = "6e400061-b5a3-f393-e0a9-e50e24dcca9e",
+        read,
+        notify,
+        value = "[0u8; accel::STEPS_LEN]"
+    )]
+    today: [u8; accel::STEPS_LEN],
+}
+
+// Same vendor base as NUS.
+#[nrf_softdevice::gatt_service(uuid = "6e400070-b5a3-f393-e0a9-e50e24dcca9e")]
+pub(crate) struct AgnssService {
+    /// `gps::AgnssFreshness` record; a notify with the stale state asks the
+    /// app for a new download.
+    #[characteristic(
+        uuid = "6e400071-b5a3-f393-e0a9-e50e24dcca9e",
+        read,
+        notify,
+        value = "[0u8; gps::AGNSS_FRESHNESS_LEN]"
+    )]
+    freshness: [u8; gps::AGNSS_FRESHNESS_LEN],
+}
+
+// Same vendor base as NUS.
+#[nrf_softdevice::gatt_service(uuid = "6e400080-b5a3-f393-e0a9-e50e24dcca9e")]
+pub(crate) struct TrackStatsService {
+    /// `track_stats::DayStats` record, as `GET_TODAY_STATS` returns it.
+    #[characteristic(
+        uuid = "6e400081-b5a3-f393-e0a9-e50e24dcca9e",
+        read,
+        notify,
+        value = "[0u8; track_stats::TODAY_STATS_LEN]"
+    )]
+    today: [u8; track_stats::TODAY_STATS_LEN],
+}
+
+// Same vendor base as NUS.
+#[nrf_softdevice::gatt_service(uuid = "6e400090-b5a3-f393-e0a9-e50e24dcca9e")]
+pub(crate) struct TimeSyncService {
+    /// Phone UTC time `[unix_ts: u32 LE]`, see `phone_location::set_time`.
+    #[characteristic(
+        uuid = "6e400091-b5a3-f393-e0a9-e50e24dcca9e",
+        write,
+        value = "[0u8; 4]"
+    )]
+    unix: [u8; 4],
+}
+
+// Same vendor base as NUS.
+#[nrf_softdevice::gatt_service(uuid = "6e4000a0-b5a3-f393-e0a9-e50e24dcca9e")]
+pub(crate) struct AlertService {
+    /// Last `alerts::AlertEvent` routed to BLE.
+    #[characteristic(
+        uuid = "6e4000a1-b5a3-f393-e0a9-e50e24dcca9e",
+        read,
+        notify,
+        value = "[0u8; alerts::ALERT_EVENT_LEN]"
+    )]
+    last: [u8; alerts::ALERT_EVENT_LEN],
+}
+
+// Same vendor base as NUS.
+#[nrf_softdevice::gatt_service(uuid = "6e4000b0-b5a3-f393-e0a9-e50e24dcca9e")]
+pub(crate) struct ConfigService {
+    /// Write `[id: u16]` to read a setting or `[id: u16][value: i32]` to set
+    /// it; the `settings::access` result is then readable and notified.
+    #[characteristic(
+        uuid = "6e4000b1-b5a3-f393-e0a9-e50e24dcca9e",
+        read,
+        write,
+        notify,
+        value = "heapless::Vec::<u8, { settings::ACCESS_RESULT_LEN }>::new()"
+    )]
+    access: Vec<u8, { settings::ACCESS_RESULT_LEN }>,
+}
+
+// Same vendor base as NUS. Writing needs a link encrypted with a
+// passkey-paired key, which only a bond from the pairing window has.
+#[nrf_softdevice::gatt_service(uuid = "6e4000c0-b5a3-f393-e0a9-e50e24dcca9e")]
+pub(crate) struct DfuService {
+    /// Write a `dfu::DfuTarget` to restart into the bootloader.
+    #[characteristic(
+        uuid = "6e4000c1-b5a3-f393-e0a9-e50e24dcca9e",
+        write,
+        security = "mitm",
+        value = "[0u8; 1]"
+    )]
+    control: [u8; 1],
+}
+
+// Same vendor base as NUS. Laid out like NUS so serial-over-BLE tools can
+// use it once pointed at the UUIDs.
+#[nrf_softdevice::gatt_service(uuid = "6e4000d0-b5a3-f393-e0a9-e50e24dcca9e")]
+pub(crate) struct NmeaService {
+    /// Bytes for the GPS UART, see `nmea_passthrough`.
+    #[characteristic(
+        uuid = "6e4000d1-b5a3-f393-e0a9-e50e24dcca9e",
+        write,
+        write_without_response,
+        value = "heapless::Vec::<u8, MAX_WRITE_LEN>::new()"
+    )]
+    rx: Vec<u8, MAX_WRITE_LEN>,
+    /// NMEA lines from the GPS; subscribing turns pass-through on.
+    #[characteristic(
+        uuid = "6e4000d2-b5a3-f393-e0a9-e50e24dcca9e",
+        notify,
+        value = "heapless::Vec::<u8, MAX_LINE_LEN>::new()"
+    )]
+    tx: Vec<u8, MAX_LINE_LEN>,
+}
+
+// Same vendor base as NUS.
+#[nrf_softdevice::gatt_service(uuid = "6e4000e0-b5a3-f393-e0a9-e50e24dcca9e")]
+pub(crate) struct DiagnosticsService {
+    /// Reset reason and last crash, see `crash::BootReport::to_bytes`.
+    #[characteristic(
+        uuid = "6e4000e1-b5a3-f393-e0a9-e50e24dcca9e",
+        read,
+        value = "heapless::Vec::<u8, { crash::REPORT_MAX_LEN }>::new()"
+    )]
+    boot: Vec<u8, { crash::REPORT_MAX_LEN }>,
+}
+
+// Google Fast Pair service, here only for FMDN's beacon actions (ring). Its
+// 128-bit characteristic takes the second `vs_uuid_count` slot. Writes do
+// nothing without `google-fmdn`, and on the main link, see
+// `serve_fmdn_link`.
+#[nrf_softdevice::gatt_service(uuid = "fe2c")]
+pub(crate) struct FastPairService {
+    /// Read `[version][nonce]`, write an authenticated action, see
+    /// `google_fmdn`.
+    #[characteristic(
+        uuid = "fe2c1238-8366-4814-8eb0-01de32100bea",
+        read,
+        write,
+        notify,
+        value = "heapless::Vec::<u8, BEACON_ACTIONS_LEN>::new()"
+    )]
+    beacon_actions: Vec<u8, BEACON_ACTIONS_LEN>,
+}
+
 #[nrf_softdevice::gatt_server]
 pub(crate) struct Server {
     nus: NusService,
+    display: DisplayService,
+    file_jobs: FileJobService,
+    history: HistoryService,
+    live: LiveTrackService,
+    geofence: GeofenceService,
+    steps: StepsService,
+    agnss: AgnssService,
+    track_stats: TrackStatsService,
+    time_sync: TimeSyncService,
+    alerts: AlertService,
+    config: ConfigService,
+    dfu: DfuService,
+    nmea: NmeaService,
+    diagnostics: DiagnosticsService,
+    fast_pair: FastPairService,
+}
+
+/// Outside the pairing window, accepts Just Works pairing so encrypted
+/// characteristics can be read, and bonds nothing: keys last for one
+/// connection. Inside it, shows a passkey on the display and bonds the
+/// peer, which then reconnects with its stored key.
+///
+/// Pairing is legacy passkey entry, which protects against a
+/// man-in-the-middle while the passkey stays secret. LE Secure Connections
+/// would need the pinned nrf-softdevice to set `lesc` in its pairing
+/// parameters and hand the DHKey request to the application; it does
+/// neither.
+struct Bonder;
+
+impl SecurityHandler for Bonder {
+    fn io_capabilities(&self) -> IoCapabilities {
+        if pairing_window_remaining_s() > 0 {
+            IoCapabilities::DisplayOnly
+        } else {
+            IoCapabilities::None
+        }
+    }
+
+    fn can_bond(&self, _conn: &Connection) -> bool {
+        pairing_window_remaining_s() > 0
+    }
+
+    fn display_passkey(&self, passkey: &[u8; 6]) {
+        PASSKEY.lock(|slot| slot.set(Some(*passkey)));
+    }
+
+    fn on_security_update(&self, _conn: &Connection, security_mode: SecurityMode) {
+        let encrypted = !matches!(security_mode, SecurityMode::NoAccess | SecurityMode::Open);
+        let bonded = encrypted && BOND_KEY.load(Ordering::Acquire);
+        LINK_BONDED.store(bonded, Ordering::Release);
+        LINK_TRUST_CHANGED.signal(());
+    }
+
+    fn on_bonded(
+        &self,
+        _conn: &Connection,
+        master_id: MasterId,
+        key: EncryptionInfo,
+        peer_id: IdentityKey,
+    ) {
+        let record = BondRecord {
+            ediv: master_id.ediv,
+            rand: master_id.rand,
+            ltk: key.ltk,
+            ltk_flags: key.flags,
+            irk: peer_id.irk.as_raw().irk,
+            addr_flags: peer_id.addr.flags,
+            addr: peer_id.addr.bytes,
+        };
+        BONDS.lock(|bonds| bonds.borrow_mut().insert(record));
+        BOND_KEY.store(true, Ordering::Release);
+        LINK_BONDED.store(true, Ordering::Release);
+        LINK_TRUST_CHANGED.signal(());
+        // One phone per window.
+        PAIRING_END_S.store(0, Ordering::Release);
+        PASSKEY.lock(|slot| slot.set(None));
+        BONDS_CHANGED.signal(());
+        defmt::info!("BLE peer bonded");
+    }
+
+    fn get_key(&self, _conn: &Connection, master_id: MasterId) -> Option<EncryptionInfo> {
+        let key = BONDS.lock(|bonds| {
+            let bonds = bonds.borrow();
+            let record = bonds.find(master_id.ediv, &master_id.rand)?;
+            Some(EncryptionInfo {
+                ltk: record.ltk,
+                flags: record.ltk_flags,
+            })
+        });
+        BOND_KEY.store(key.is_some(), Ordering::Release);
+        key
+    }
+}
+
+static BONDER: Bonder = Bonder;
+
+fn uptime_s() -> u32 {
+    Instant::now().as_secs() as u32
+}
+
+fn identity_key(record: &BondRecord) -> IdentityKey {
+    IdentityKey {
+        irk: IdentityResolutionKey::from_raw(raw::ble_gap_irk_t { irk: record.irk }),
+        addr: Address {
+            flags: record.addr_flags,
+            bytes: record.addr,
+        },
+    }
+}
+
+/// Whether `addr` is a bonded peer's, resolving private addresses.
+fn is_bonded_peer(addr: Address) -> bool {
+    BONDS.lock(|bonds| {
+        let records = bonds.borrow();
+        let mut records = records.records().iter();
+        records.any(|record| identity_key(record).is_match(addr))
+    })
+}
+
+/// Load the bonded peers from SD. Call once after the SD logger is
+/// initialized, before BLE starts advertising.
+pub async fn load_bonds() {
+    let mut buf = [0u8; BONDS_FILE_MAX_LEN];
+    let Some(len) = storage::read_bond_db(&mut buf).await else {
+        return;
+    };
+    let Some(table) = BondTable::from_bytes(&buf[..len]) else {
+        return;
+    };
+    if !table.is_empty() {
+        defmt::info!("BLE: {} bonded peers", table.len());
+    }
+    BONDS.lock(|bonds| *bonds.borrow_mut() = table);
+}
+
+async fn save_bonds() -> bool {
+    let mut buf = [0u8; BONDS_FILE_MAX_LEN];
+    let len = BONDS.lock(|bonds| bonds.borrow().to_bytes(&mut buf));
+    storage::write_bond_db(&buf[..len]).await
+}
+
+/// Forget every bonded peer. A bonded link stays up until it disconnects.
+pub async fn clear_bonds() -> bool {
+    BONDS.lock(|bonds| bonds.borrow_mut().clear());
+    save_bonds().await
+}
+
+pub fn bond_count() -> usize {
+    BONDS.lock(|bonds| bonds.borrow().len())
+}
+
+/// Bond the next phone that pairs within `PAIRING_WINDOW_S` seconds. A
+/// locked-down tracker also opens its guest window, so the phone can
+/// connect.
+pub fn open_pairing_window() {
+    PAIRING_END_S.store(uptime_s() + PAIRING_WINDOW_S, Ordering::Release);
+    PASSKEY.lock(|slot| slot.set(None));
+    if guest::lockdown() {
+        guest::open_window();
+    }
+    request_fast_advertising();
+    defmt::info!("BLE pairing window open for {}s", PAIRING_WINDOW_S);
+}
+
+pub fn pairing_window_remaining_s() -> u32 {
+    let end_s = PAIRING_END_S.load(Ordering::Acquire);
+    end_s.saturating_sub(uptime_s())
+}
+
+/// Passkey for the phone to enter, once a pairing in the window asks for
+/// one.
+pub fn pairing_passkey() -> Option<[u8; 6]> {
+    if pairing_window_remaining_s() == 0 {
+        return None;
+    }
+    PASSKEY.lock(Cell::get)
+}
+
+/// Whether the current link is encrypted with a bonded key.
+pub fn link_bonded() -> bool {
+    LINK_BONDED.load(Ordering::Acquire)
+}
+
+/// Whether the current link may use file transfer and configuration.
+fn link_trusted() -> bool {
+    settings::stored(settings::BLE_BONDED_ONLY) == 0 || link_bonded()
+}
+
+fn nmea_passthrough_allowed() -> bool {
+    settings::stored(settings::BLE_NMEA_PASSTHROUGH) != 0 && link_trusted()
 }
 
 pub fn init_server(sd: &mut Softdevice) -> Result<Server, gatt_server::RegisterError> {
     Server::new(sd)
 }
 
+/// True while a central is connected.
+#[cfg(feature = "lora")]
+pub fn is_connected() -> bool {
+    CONNECTED.load(Ordering::Acquire)
+}
+
 pub fn request_fast_advertising() {
     request_advertising(ADV_TIMEOUT_FAST_10MS);
 }
 
+/// End connectable advertising until the next reset.
+pub fn stop_advertising() {
+    ADV_STOPPED.store(true, Ordering::Release);
+    ADV_REQUEST_SIGNAL.signal(());
+}
+
 #[task]
 pub async fn ble_task(sd: &'static Softdevice, server: &'static Server) {
     let mut pending_timeout = Some(ADV_TIMEOUT_BOOT_10MS);
@@ -85,6 +573,21 @@ pub async fn ble_task(sd: &'static Softdevice, server: &'static Server) {
                 }
             }
         };
+        if ADV_STOPPED.load(Ordering::Acquire) {
+            defmt::info!("BLE advertising stopped");
+            continue;
+        }
+        // Locked down: advertise for the rest of the guest window, for
+        // bonded peers only, or not at all.
+        let timeout = match (guest::lockdown(), guest::window_remaining_s()) {
+            (false, _) => timeout,
+            (true, 0) if bond_count() > 0 => timeout,
+            (true, 0) => {
+                defmt::info!("BLE locked down, not advertising");
+                continue;
+            }
+            (true, remaining_s) => cmp::min(remaining_s * 100, u16::MAX as u32) as u16,
+        };
 
         // Acquire the advertising resource (preempts FindMy if active).
         let guard = ADV_SCHEDULER.acquire(AdvPriority::MainAdv).await;
@@ -100,7 +603,7 @@ pub async fn ble_task(sd: &'static Softdevice, server: &'static Server) {
         };
 
         let mut conn = match select(
-            peripheral::advertise_connectable(sd, adv, &config),
+            peripheral::advertise_pairable(sd, adv, &config, &BONDER),
             ADV_REQUEST_SIGNAL.wait(),
         )
         .await
@@ -125,6 +628,16 @@ pub async fn ble_task(sd: &'static Softdevice, server: &'static Server) {
 
         // Connection established — adv handle is free, release for FindMy.
         drop(guard);
+        // Boot advertising can start before `/LOCK.CFG` is loaded.
+        if !guest::connection_allowed() && !is_bonded_peer(conn.peer_address()) {
+            defmt::info!("BLE locked down, dropping connection");
+            let _ = conn.disconnect();
+            continue;
+        }
+        CONNECTED.store(true, Ordering::Release);
+        BOND_KEY.store(false, Ordering::Release);
+        LINK_BONDED.store(false, Ordering::Release);
+        LINK_TRUST_CHANGED.reset();
 
         let _ = conn.data_length_update(None);
         let _ = conn.phy_update(PhySet::M2, PhySet::M2);
@@ -138,37 +651,382 @@ pub async fn ble_task(sd: &'static Softdevice, server: &'static Server) {
         }
 
         RX_CHANNEL.clear();
+        CONFIG_WRITES.clear();
+        LIVE_SUBSCRIBE.reset();
+        RELAY_SUBSCRIBE.reset();
+        nmea_passthrough::set_enabled(false);
+        live_track::reset_relay_resume();
+        geofences::clear_events();
+        alerts::clear_events();
         let mut protocol = FileTransferProtocol::new();
+        let _ = server.display.state_set(&display::remote_state());
+        let _ = server.file_jobs.progress_set(&file_jobs::progress());
+        let _ = server.steps.today_set(&accel::steps_snapshot().await);
+        let _ = server.agnss.freshness_set(&gps::agnss_freshness().await.to_bytes());
+        let _ = server.track_stats.today_set(&track_stats::current().await.to_bytes());
 
+        // Config writes go through the settings registry, which may write
+        // the SD card, so they are served here rather than in the GATT
+        // callback; so is saving a new bond.
         let rx_fut = async {
             loop {
-                let data = RX_CHANNEL.receive().await;
-                process_bytes(&mut protocol, &conn, &server, &data).await;
+                let next = select3(
+                    RX_CHANNEL.receive(),
+                    CONFIG_WRITES.receive(),
+                    BONDS_CHANGED.wait(),
+                )
+                .await;
+                match next {
+                    Either3::First(data) => {
+                        process_bytes(&mut protocol, &conn, &server, &data).await;
+                    }
+                    Either3::Second((id, value)) => {
+                        let result = settings::access(id, value).await;
+                        let result = Vec::from_slice(&result).unwrap_or_default();
+                        let _ = server.config.access_set(&result);
+                        let _ = server.config.access_notify(&conn, &result);
+                    }
+                    Either3::Third(()) => {
+                        if !save_bonds().await {
+                            defmt::warn!("BLE bond not saved: SD write failed");
+                        }
+                    }
+                }
             }
         };
 
+        // Every write and subscription needs a trusted link; notifications
+        // to an untrusted one are skipped below.
         let gatt_fut = gatt_server::run(&conn, server, |event| match event {
             ServerEvent::Nus(evt) => match evt {
                 NusServiceEvent::RxWrite(data) => {
-                    let _ = RX_CHANNEL.try_send(data);
+                    if !link_trusted() {
+                        defmt::warn!("BLE file transfer write rejected: link not bonded");
+                    } else {
+                        let _ = RX_CHANNEL.try_send(data);
+                    }
                 }
                 NusServiceEvent::TxCccdWrite { notifications } => {
                     defmt::info!("BLE notifications enabled: {}", notifications);
                 }
             },
+            ServerEvent::Display(DisplayServiceEvent::StateWrite(_)) if !link_trusted() => {
+                defmt::warn!("BLE display state rejected: link not bonded");
+            }
+            ServerEvent::Display(evt) => match evt {
+                DisplayServiceEvent::StateWrite(value) => {
+                    if !display::apply_remote_state(&value) {
+                        defmt::warn!("BLE display state rejected: {:?}", value);
+                    }
+                }
+                DisplayServiceEvent::StateCccdWrite { .. } => {}
+            },
+            ServerEvent::FileJobs(FileJobServiceEvent::ProgressCccdWrite { .. }) => {}
+            ServerEvent::History(HistoryServiceEvent::LatestCccdWrite { .. }) => {}
+            // The streams give away where the tracker is.
+            ServerEvent::Live(_) if !link_trusted() => {
+                defmt::warn!("BLE live track subscription rejected: link not bonded");
+            }
+            ServerEvent::Live(LiveTrackServiceEvent::PointsCccdWrite { notifications }) => {
+                LIVE_SUBSCRIBE.signal(notifications);
+            }
+            ServerEvent::Live(LiveTrackServiceEvent::RelayCccdWrite { notifications }) => {
+                RELAY_SUBSCRIBE.signal(notifications);
+            }
+            ServerEvent::Geofence(GeofenceServiceEvent::AlertCccdWrite { .. }) => {}
+            ServerEvent::Steps(StepsServiceEvent::TodayCccdWrite { .. }) => {}
+            ServerEvent::Agnss(AgnssServiceEvent::FreshnessCccdWrite { .. }) => {}
+            ServerEvent::TrackStats(TrackStatsServiceEvent::TodayCccdWrite { .. }) => {}
+            ServerEvent::Alerts(AlertServiceEvent::LastCccdWrite { .. }) => {}
+            ServerEvent::Config(ConfigServiceEvent::AccessWrite(_)) if !link_trusted() => {
+                defmt::warn!("BLE config write rejected: link not bonded");
+            }
+            ServerEvent::Config(ConfigServiceEvent::AccessWrite(data)) => {
+                let request = match *data {
+                    [a, b] => Some((u16::from_le_bytes([a, b]), None)),
+                    [a, b, c, d, e, f] => Some((
+                        u16::from_le_bytes([a, b]),
+                        Some(i32::from_le_bytes([c, d, e, f])),
+                    )),
+                    _ => None,
+                };
+                match request {
+                    Some(request) => {
+                        let _ = CONFIG_WRITES.try_send(request);
+                    }
+                    None => defmt::warn!("BLE config write rejected: {} bytes", data.len()),
+                }
+            }
+            ServerEvent::Config(ConfigServiceEvent::AccessCccdWrite { .. }) => {}
+            // The bootloader flashes unsigned images: bonded phones only.
+            ServerEvent::Dfu(_) if !(link_trusted() && link_bonded()) => {
+                defmt::warn!("BLE DFU write rejected: link not bonded");
+            }
+            ServerEvent::Dfu(DfuServiceEvent::ControlWrite([value])) => {
+                match DfuTarget::from_u8(value) {
+                    Some(target) => dfu::request(target),
+                    None => defmt::warn!("BLE DFU write rejected: {}", value),
+                }
+            }
+            // Whatever is written goes to the receiver unchecked.
+            ServerEvent::Nmea(_) if !nmea_passthrough_allowed() => {
+                defmt::warn!("BLE NMEA pass-through rejected: off or link not bonded");
+            }
+            ServerEvent::Nmea(NmeaServiceEvent::RxWrite(data)) => {
+                if !nmea_passthrough::write_to_gps(&data) {
+                    defmt::warn!("BLE NMEA write dropped: {} bytes", data.len());
+                }
+            }
+            ServerEvent::Nmea(NmeaServiceEvent::TxCccdWrite { notifications }) => {
+                let dropped = nmea_passthrough::dropped_lines();
+                defmt::info!("NMEA pass-through: {} ({} dropped)", notifications, dropped);
+                nmea_passthrough::set_enabled(notifications);
+            }
+            ServerEvent::TimeSync(_) if !link_trusted() => {
+                defmt::warn!("BLE time sync rejected: link not bonded");
+            }
+            ServerEvent::TimeSync(TimeSyncServiceEvent::UnixWrite(value)) => {
+                if !phone_location::set_time(u32::from_le_bytes(value)) {
+                    defmt::warn!("BLE time sync rejected: {}", u32::from_le_bytes(value));
+                }
+            }
+            ServerEvent::Diagnostics(evt) => match evt {},
+            ServerEvent::FastPair(FastPairServiceEvent::BeaconActionsWrite(_)) => {
+                defmt::warn!("BLE beacon action ignored: served on the FMDN address only");
+            }
+            ServerEvent::FastPair(FastPairServiceEvent::BeaconActionsCccdWrite { .. }) => {}
         });
 
-        match select(gatt_fut, rx_fut).await {
-            Either::First(_) => {
+        // Keep the readable value current and notify subscribers, so the app
+        // also sees button presses and the inactivity timeout.
+        let display_fut = async {
+            loop {
+                display::wait_state_change().await;
+                let state = display::remote_state();
+                let _ = server.display.state_set(&state);
+                if link_trusted() {
+                    let _ = server.display.state_notify(&conn, &state);
+                }
+            }
+        };
+
+        // NMEA lines for the pass-through, split to the link's MTU.
+        // Values only a trusted link may read, filled in once it is.
+        let trust_fut = async {
+            loop {
+                set_trusted_values(server).await;
+                LINK_TRUST_CHANGED.wait().await;
+            }
+        };
+
+        let nmea_fut = async {
+            loop {
+                let line = nmea_passthrough::next_line().await;
+                if !nmea_passthrough_allowed() {
+                    // Turned off since the subscription.
+                    nmea_passthrough::set_enabled(false);
+                    continue;
+                }
+                for part in line.chunks(max_notify_len(&conn).max(1)) {
+                    let chunk: Vec<u8, MAX_LINE_LEN> = Vec::from_slice(part).unwrap_or_default();
+                    if server.nmea.tx_notify(&conn, &chunk).is_err() {
+                        break;
+                    }
+                }
+            }
+        };
+
+        // File job progress, the hourly history, routed alerts, the live and
+        // relay streams, geofence alerts, the step count, AGNSS freshness and
+        // the day's track stats share one future.
+        let job_fut = async {
+            let mut live = LiveStream::new();
+            let mut relay = RelayStream::new();
+            loop {
+                let event = select4(
+                    file_jobs::wait_progress_change(),
+                    select(location_history::wait_change(), alerts::next_event()),
+                    select(
+                        select(LIVE_SUBSCRIBE.wait(), live_track::wait_point()),
+                        select3(
+                            RELAY_SUBSCRIBE.wait(),
+                            live_track::wait_relay_point(),
+                            live_track::wait_relay_resume(),
+                        ),
+                    ),
+                    select4(
+                        geofences::next_event(),
+                        accel::wait_steps_change(),
+                        gps::wait_agnss_freshness_change(),
+                        track_stats::wait_change(),
+                    ),
+                )
+                .await;
+                // Untrusted links may read these but not subscribe to them.
+                let trusted = link_trusted();
+                match event {
+                    Either4::First(()) => {
+                        let progress = file_jobs::progress();
+                        let _ = server.file_jobs.progress_set(&progress);
+                        if trusted {
+                            let _ = server.file_jobs.progress_notify(&conn, &progress);
+                        }
+                    }
+                    Either4::Second(Either::First(())) => {
+                        set_history(server).await;
+                        if trusted {
+                            let latest = location_history::latest().await;
+                            // Fails unless the link is encrypted and subscribed.
+                            let _ = server.history.latest_notify(&conn, &latest);
+                        }
+                    }
+                    Either4::Second(Either::Second(event)) => {
+                        let alert = event.to_bytes();
+                        let _ = server.alerts.last_set(&alert);
+                        if trusted {
+                            let _ = server.alerts.last_notify(&conn, &alert);
+                        }
+                    }
+                    Either4::Third(Either::First(Either::First(subscribed))) => {
+                        live.subscribe(subscribed).await;
+                        live.send_pending(&conn, server).await;
+                    }
+                    Either4::Third(Either::First(Either::Second(()))) => {
+                        live.send_pending(&conn, server).await;
+                    }
+                    Either4::Third(Either::Second(Either3::First(subscribed))) => {
+                        relay.subscribe(subscribed).await;
+                        relay.send_pending(&conn, server).await;
+                    }
+                    Either4::Third(Either::Second(Either3::Second(()))) => {
+                        relay.send_pending(&conn, server).await;
+                    }
+                    Either4::Third(Either::Second(Either3::Third(seq))) => {
+                        relay.resume(seq);
+                        relay.send_pending(&conn, server).await;
+                    }
+                    Either4::Fourth(Either4::First(event)) => {
+                        let alert = event.to_bytes();
+                        let _ = server.geofence.alert_set(&alert);
+                        if trusted {
+                            let _ = server.geofence.alert_notify(&conn, &alert);
+                        }
+                    }
+                    Either4::Fourth(Either4::Second(())) => {
+                        let steps = accel::steps_snapshot().await;
+                        let _ = server.steps.today_set(&steps);
+                        if trusted {
+                            let _ = server.steps.today_notify(&conn, &steps);
+                        }
+                    }
+                    Either4::Fourth(Either4::Third(())) => {
+                        let freshness = gps::agnss_freshness().await.to_bytes();
+                        let _ = server.agnss.freshness_set(&freshness);
+                        if trusted {
+                            let _ = server.agnss.freshness_notify(&conn, &freshness);
+                        }
+                    }
+                    Either4::Fourth(Either4::Fourth(())) => {
+                        let stats = track_stats::current().await.to_bytes();
+                        let _ = server.track_stats.today_set(&stats);
+                        if trusted {
+                            let _ = server.track_stats.today_notify(&conn, &stats);
+                        }
+                    }
+                }
+            }
+        };
+
+        let side_fut = select3(display_fut, nmea_fut, trust_fut);
+        match select4(gatt_fut, rx_fut, side_fut, job_fut).await {
+            Either4::First(_) => {
                 defmt::info!("BLE disconnected");
             }
-            Either::Second(_) => {}
+            Either4::Second(_) | Either4::Third(_) | Either4::Fourth(_) => {}
         }
+        CONNECTED.store(false, Ordering::Release);
+        LINK_BONDED.store(false, Ordering::Release);
+        nmea_passthrough::set_enabled(false);
 
         pending_timeout = take_adv_request().or(Some(timeout));
     }
 }
 
+/// Serve a link a finder made on the FMDN address. Only the beacon actions
+/// characteristic acts on it; the app's services ignore it like an
+/// untrusted link. Each action gets a fresh nonce, and the end of a ring it
+/// started is notified.
+#[cfg(feature = "google-fmdn")]
+pub async fn serve_fmdn_link(sd: &'static Softdevice, server: &'static Server, conn: Connection) {
+    use crate::google_fmdn::{self, NONCE_LEN};
+    use embassy_time::{Duration, Timer};
+
+    defmt::info!("FMDN: finder connected");
+    let new_nonce = || {
+        let mut nonce = [0u8; NONCE_LEN];
+        if nrf_softdevice::random_bytes(sd, &mut nonce).is_err() {
+            defmt::warn!("FMDN: no random nonce");
+        }
+        let value = google_fmdn::beacon_actions_read_value(&nonce);
+        let _ = server.fast_pair.beacon_actions_set(&value);
+        nonce
+    };
+    let nonce = Cell::new(new_nonce());
+
+    let gatt_fut = gatt_server::run(&conn, server, |event| match event {
+        ServerEvent::FastPair(FastPairServiceEvent::BeaconActionsWrite(data)) => {
+            if let Some(reply) = google_fmdn::beacon_action(&nonce.get(), &data) {
+                let _ = server.fast_pair.beacon_actions_notify(&conn, &reply);
+            }
+            nonce.set(new_nonce());
+        }
+        ServerEvent::FastPair(FastPairServiceEvent::BeaconActionsCccdWrite { .. }) => {}
+        _ => defmt::warn!("FMDN: write ignored, not a beacon action"),
+    });
+
+    let ring_end_fut = async {
+        #[cfg(feature = "buzzer")]
+        loop {
+            let end = crate::sound::wait_ring_end().await;
+            let reply = google_fmdn::ring_end_notification(&nonce.get(), end);
+            let _ = server.fast_pair.beacon_actions_notify(&conn, &reply);
+        }
+        #[cfg(not(feature = "buzzer"))]
+        core::future::pending::<()>().await
+    };
+
+    let limit = Timer::after(Duration::from_secs(FMDN_LINK_MAX_S));
+    if let Either3::Third(()) = select3(gatt_fut, ring_end_fut, limit).await {
+        let _ = conn.disconnect();
+    }
+    defmt::info!("FMDN: finder disconnected");
+}
+
+/// Fill in the crash report and the location history for a trusted link,
+/// or empty them: they carry code addresses and where the tracker has been.
+async fn set_trusted_values(server: &Server) {
+    set_history(server).await;
+    let mut boot = [0u8; crash::REPORT_MAX_LEN];
+    let len = if link_trusted() {
+        crash::boot_report().to_bytes(&mut boot)
+    } else {
+        0
+    };
+    let report = Vec::from_slice(&boot[..len]).unwrap_or_default();
+    let _ = server.diagnostics.boot_set(&report);
+}
+
+async fn set_history(server: &Server) {
+    let mut hourly = [0; location_history::HISTORY_LEN];
+    let mut latest = [0; location_history::LATEST_LEN];
+    if link_trusted() {
+        hourly = location_history::snapshot().await;
+        latest = location_history::latest().await;
+    }
+    let _ = server.history.hourly_set(&hourly);
+    let _ = server.history.latest_set(&latest);
+}
+
 async fn process_bytes(
     protocol: &mut FileTransferProtocol,
     conn: &Connection,
@@ -178,13 +1036,21 @@ async fn process_bytes(
     for &byte in data {
         if let Some(len) = protocol.push_byte(byte).await {
             ble_send(conn, server, protocol.response(len)).await;
+            while let Some(len) = protocol.next_frame().await {
+                ble_send(conn, server, protocol.response(len)).await;
+            }
         }
     }
 }
 
-async fn ble_send(conn: &Connection, server: &Server, data: &[u8]) {
+/// Largest notification the link can carry.
+fn max_notify_len(conn: &Connection) -> usize {
     let mtu_payload = conn.att_mtu().saturating_sub(3) as usize;
-    let max_payload = cmp::min(mtu_payload, MAX_GATT_PAYLOAD);
+    cmp::min(mtu_payload, MAX_GATT_PAYLOAD)
+}
+
+async fn ble_send(conn: &Connection, server: &Server, data: &[u8]) {
+    let max_payload = max_notify_len(conn);
     if max_payload == 0 {
         return;
     }
@@ -204,6 +1070,160 @@ async fn ble_send(conn: &Connection, server: &Server, data: &[u8]) {
     }
 }
 
+/// Per-connection state of the live track stream.
+struct LiveStream {
+    subscribed: bool,
+    /// Sequence number of the next point to send.
+    next_seq: u32,
+    encoder: GpsDataEncoder,
+}
+
+impl LiveStream {
+    fn new() -> Self {
+        Self {
+            subscribed: false,
+            next_seq: 0,
+            encoder: GpsDataEncoder::new(FULL_BLOCK_INTERVAL),
+        }
+    }
+
+    async fn subscribe(&mut self, subscribed: bool) {
+        self.subscribed = subscribed;
+        if subscribed {
+            self.next_seq = live_track::catch_up_seq().await;
+            self.encoder.clear();
+        }
+    }
+
+    /// Send every point from `next_seq` on, packed into notifications. On a
+    /// failed notification the unsent points are retried with the next
+    /// point, starting from a full block.
+    async fn send_pending(&mut self, conn: &Connection, server: &Server) {
+        if !self.subscribed {
+            return;
+        }
+        let max_payload = max_notify_len(conn);
+        let mut chunk: Vec<u8, MAX_GATT_PAYLOAD> = Vec::new();
+        let mut chunk_start = self.next_seq;
+        while let Some((seq, point)) = live_track::point_from(self.next_seq).await {
+            if seq != self.next_seq {
+                // Overwritten before we got to them: resync.
+                self.encoder.clear();
+            }
+            self.encoder.encode(point);
+            let data = self.encoder.buffer();
+            if chunk.len() + data.len() > max_payload {
+                if !self.notify(conn, server, &chunk, chunk_start) {
+                    return;
+                }
+                chunk.clear();
+                chunk_start = seq;
+            }
+            if chunk.is_empty() {
+                chunk_start = seq;
+            }
+            let _ = chunk.extend_from_slice(data);
+            self.next_seq = seq.wrapping_add(1);
+        }
+        if !chunk.is_empty() {
+            let _ = self.notify(conn, server, &chunk, chunk_start);
+        }
+    }
+
+    fn notify(&mut self, conn: &Connection, server: &Server, chunk: &[u8], first_seq: u32) -> bool {
+        let mut value: Vec<u8, MAX_GATT_PAYLOAD> = Vec::new();
+        let _ = value.extend_from_slice(chunk);
+        if let Err(err) = server.live.points_notify(conn, &value) {
+            defmt::warn!("BLE live track notify failed: {:?}", err);
+            self.next_seq = first_seq;
+            self.encoder.clear();
+            return false;
+        }
+        true
+    }
+}
+
+/// Per-connection state of the relay stream.
+struct RelayStream {
+    subscribed: bool,
+    /// Set by `RELAY_RESUME`; otherwise a new subscriber starts with the
+    /// oldest point kept.
+    resumed: bool,
+    next_seq: u32,
+    encoder: GpsDataEncoder,
+}
+
+impl RelayStream {
+    const HEADER_LEN: usize = 8;
+    /// Largest encoded point: a V3 full block.
+    const MAX_POINT_LEN: usize = 21;
+
+    fn new() -> Self {
+        Self {
+            subscribed: false,
+            resumed: false,
+            next_seq: 0,
+            encoder: GpsDataEncoder::new(FULL_BLOCK_INTERVAL),
+        }
+    }
+
+    async fn subscribe(&mut self, subscribed: bool) {
+        self.subscribed = subscribed;
+        if subscribed && !self.resumed {
+            self.next_seq = live_track::relay_status().await.oldest_seq;
+        }
+    }
+
+    fn resume(&mut self, seq: u32) {
+        self.resumed = true;
+        self.next_seq = seq;
+    }
+
+    /// Send every point from `next_seq` on. Each notification is a batch of
+    /// consecutive points that decodes on its own; a failed one is resent
+    /// with the next point.
+    async fn send_pending(&mut self, conn: &Connection, server: &Server) {
+        if !self.subscribed {
+            return;
+        }
+        let max_payload = max_notify_len(conn);
+        if max_payload < Self::HEADER_LEN + Self::MAX_POINT_LEN {
+            return;
+        }
+        let stream_id = live_track::relay_status().await.stream_id;
+        loop {
+            let mut batch: Vec<u8, MAX_GATT_PAYLOAD> = Vec::new();
+            let _ = batch.extend_from_slice(&[0; Self::HEADER_LEN]);
+            let mut first_seq = None;
+            let mut cursor = self.next_seq;
+            self.encoder.clear();
+            while let Some((seq, point)) = live_track::relay_point_from(cursor).await {
+                // A point overwritten while we were sending starts a new batch.
+                if (first_seq.is_some() && seq != cursor)
+                    || batch.len() + Self::MAX_POINT_LEN > max_payload
+                {
+                    break;
+                }
+                first_seq.get_or_insert(seq);
+                self.encoder.encode(point);
+                let _ = batch.extend_from_slice(self.encoder.buffer());
+                cursor = seq.wrapping_add(1);
+            }
+            let Some(first_seq) = first_seq else {
+                return;
+            };
+            batch[..4].copy_from_slice(&stream_id.to_le_bytes());
+            batch[4..Self::HEADER_LEN].copy_from_slice(&first_seq.to_le_bytes());
+            if let Err(err) = server.live.relay_notify(conn, &batch) {
+                defmt::warn!("BLE relay notify failed: {:?}", err);
+                return;
+            }
+            self.next_seq = cursor;
+        }
+    }
+
+}
+
 fn request_advertising(timeout_10ms: u16) {
     ADV_REQUEST_TIMEOUT.store(timeout_10ms, Ordering::Release);
     ADV_REQUEST_SIGNAL.signal(());
@@ -4,13 +4,25 @@ use embassy_nrf::twim;
 use embassy_sync::blocking_mutex::raw::NoopRawMutex;
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_sync::mutex::Mutex;
-use embassy_time::Timer;
+use embassy_time::{Instant, Timer};
 use libm::powf;
 
 use bmp280_rs::{BMP280, Config, I2CAddress, ModeNormal, ModeSleep};
 
+use crate::alerts::{self, AlertKind};
+use crate::diag::{self, TaskId};
+use crate::pressure_trend::PressureTrend;
+use crate::settings;
+use crate::storage;
+use crate::system_info::SYSTEM_INFO;
+use crate::timezone;
+
 const BMP280_UPDATE_INTERVAL_MS: u64 = 50;
 const BMP280_SEA_LEVEL_HPA: f32 = 1017.9;
+/// One pressure trend sample per interval.
+const PRESSURE_TREND_SAMPLE_S: u64 = 60;
+/// One `/PRESSURE.CSV` line per interval.
+const PRESSURE_LOG_INTERVAL_S: u64 = 3_600;
 
 type SharedI2c = I2cDevice<'static, NoopRawMutex, twim::Twim<'static>>;
 
@@ -36,6 +48,15 @@ impl Bmp280Data {
 pub static BMP280_DATA: Mutex<CriticalSectionRawMutex, Bmp280Data> =
     Mutex::new(Bmp280Data::new());
 
+static PRESSURE_TREND: Mutex<CriticalSectionRawMutex, PressureTrend> =
+    Mutex::new(PressureTrend::new());
+
+/// Pressure change over the last three hours in hPa, `None` with less than
+/// an hour of readings; see `pressure_trend`.
+pub async fn pressure_change_3h_hpa() -> Option<f32> {
+    PRESSURE_TREND.lock().await.change_3h_hpa()
+}
+
 #[task]
 pub async fn bmp280_task(mut i2c: SharedI2c) {
     let mut ok = false;
@@ -63,8 +84,11 @@ pub async fn bmp280_task(mut i2c: SharedI2c) {
 
     let mut data = Bmp280Data::new();
     data.ok = ok;
+    let mut next_trend_s = 0;
+    let mut next_log_s = 0;
 
     loop {
+        let busy = diag::busy(TaskId::Bmp280);
         if let Some(bmp) = bmp.as_mut() {
             if let (Ok(temp), Ok(press)) = (
                 bmp.read_temperature(&mut i2c),
@@ -83,11 +107,59 @@ pub async fn bmp280_task(mut i2c: SharedI2c) {
             let mut guard = BMP280_DATA.lock().await;
             *guard = data;
         }
+        let uptime_s = Instant::now().as_secs();
+        if data.ok && data.pressure_pa > 0.0 && uptime_s >= next_trend_s {
+            next_trend_s = uptime_s + PRESSURE_TREND_SAMPLE_S;
+            let log = uptime_s >= next_log_s;
+            if log {
+                next_log_s = uptime_s + PRESSURE_LOG_INTERVAL_S;
+            }
+            sample_pressure_trend(uptime_s, &data, log).await;
+        }
+        drop(busy);
 
         Timer::after_millis(BMP280_UPDATE_INTERVAL_MS).await;
     }
 }
 
+/// Feed the weather trend, raise the storm alert on a fast fall and, with
+/// `log`, append the hourly `/PRESSURE.CSV` line.
+async fn sample_pressure_trend(uptime_s: u64, data: &Bmp280Data, log: bool) {
+    let (gps_altitude_m, unix) = {
+        let info = SYSTEM_INFO.lock().await;
+        let unix = if info.date_time_valid {
+            timezone::date_time_to_unix_timestamp(
+                info.year,
+                info.month,
+                info.day,
+                info.hour,
+                info.minute,
+                info.second,
+            )
+        } else {
+            None
+        };
+        let altitude_m = info.location_valid.then_some(info.altitude);
+        (altitude_m, unix.unwrap_or(0))
+    };
+    let threshold_hpa = settings::stored(settings::WEATHER_STORM_DROP) as f32 / 10.0;
+    let mut trend = PRESSURE_TREND.lock().await;
+    trend.note(uptime_s, data.pressure_pa, gps_altitude_m);
+    let change_hpa = trend.change_3h_hpa();
+    let storm = trend.storm_alert(threshold_hpa);
+    drop(trend);
+
+    if storm {
+        defmt::warn!("BMP280: pressure fell {} hPa in 3 h", change_hpa);
+        alerts::raise(AlertKind::Storm, "Pressure falling fast").await;
+    }
+    if log {
+        if !storage::append_pressure_log(uptime_s as u32, unix, data, change_hpa).await {
+            defmt::warn!("BMP280: PRESSURE.CSV append failed");
+        }
+    }
+}
+
 fn pressure_to_altitude(pressure_pa: f32) -> f32 {
     let sea_level_pa = BMP280_SEA_LEVEL_HPA * 100.0;
     if pressure_pa <= 0.0 {
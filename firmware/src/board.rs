@@ -16,6 +16,13 @@ pub struct Board {
     pub v3v3_en: Peri<'static, peripherals::P0_13>,
     pub serial2_rx: Peri<'static, peripherals::P0_06>,
     pub serial2_tx: Peri<'static, peripherals::P0_08>,
+    pub gps_pps: Peri<'static, peripherals::P1_01>,
+    pub gps_reset: Peri<'static, peripherals::P0_26>,
+    pub lora_cs: Peri<'static, peripherals::P1_06>,
+    pub lora_reset: Peri<'static, peripherals::P0_09>,
+    pub lora_busy: Peri<'static, peripherals::P0_29>,
+    pub lora_dio1: Peri<'static, peripherals::P0_10>,
+    pub buzzer: Peri<'static, peripherals::P1_07>,
     pub uarte0: Peri<'static, peripherals::UARTE0>,
     pub twispi0: Peri<'static, peripherals::TWISPI0>,
     pub spi3: Peri<'static, peripherals::SPI3>,
@@ -25,6 +32,9 @@ pub struct Board {
     pub ppi_ch8: Peri<'static, peripherals::PPI_CH8>,
     pub ppi_ch9: Peri<'static, peripherals::PPI_CH9>,
     pub ppi_group1: Peri<'static, peripherals::PPI_GROUP1>,
+    pub gpiote_ch0: Peri<'static, peripherals::GPIOTE_CH0>,
+    pub pwm0: Peri<'static, peripherals::PWM0>,
+    pub wdt: Peri<'static, peripherals::WDT>,
 }
 
 impl Board {
@@ -45,6 +55,13 @@ impl Board {
             v3v3_en: p.P0_13,
             serial2_rx: p.P0_06,
             serial2_tx: p.P0_08,
+            gps_pps: p.P1_01,
+            gps_reset: p.P0_26,
+            lora_cs: p.P1_06,
+            lora_reset: p.P0_09,
+            lora_busy: p.P0_29,
+            lora_dio1: p.P0_10,
+            buzzer: p.P1_07,
             uarte0: p.UARTE0,
             twispi0: p.TWISPI0,
             spi3: p.SPI3,
@@ -54,6 +71,9 @@ impl Board {
             ppi_ch8: p.PPI_CH8,
             ppi_ch9: p.PPI_CH9,
             ppi_group1: p.PPI_GROUP1,
+            gpiote_ch0: p.GPIOTE_CH0,
+            pwm0: p.PWM0,
+            wdt: p.WDT,
         }
     }
 }
@@ -0,0 +1,215 @@
+//! Bonded BLE peers, as stored in `/BONDS.DB`.
+//!
+//! `ble` bonds a phone that pairs during the pairing window and keeps its
+//! keys here, so it can reconnect encrypted and, with `ble.bonded_only`,
+//! reach file transfer and configuration. This module holds the table and
+//! its file format; `ble` converts to and from the SoftDevice types.
+//!
+//! # Design
+//!
+//! - Fixed `BOND_RECORD_LEN`-byte records, newest first: `[ediv: u16]`
+//!   `[rand: 8][ltk: 16][ltk_flags: u8][irk: 16][addr_flags: u8][addr: 6]`.
+//!   The master id finds the key on reconnection; the IRK and identity
+//!   address recognise the peer behind a resolvable private address.
+//! - At most `MAX_BONDS`. Bonding a peer again replaces its record, and a
+//!   new peer on a full table evicts the peer bonded longest ago.
+//! - A file whose length is not a whole number of records, or a record
+//!   past `MAX_BONDS`, is ignored rather than half-read.
+
+pub const MAX_BONDS: usize = 4;
+pub const BOND_RECORD_LEN: usize = 50;
+pub const BONDS_FILE_MAX_LEN: usize = MAX_BONDS * BOND_RECORD_LEN;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BondRecord {
+    pub ediv: u16,
+    pub rand: [u8; 8],
+    pub ltk: [u8; 16],
+    pub ltk_flags: u8,
+    pub irk: [u8; 16],
+    /// Address type flags, as the SoftDevice reports them.
+    pub addr_flags: u8,
+    pub addr: [u8; 6],
+}
+
+impl BondRecord {
+    pub fn to_bytes(self) -> [u8; BOND_RECORD_LEN] {
+        let mut out = [0u8; BOND_RECORD_LEN];
+        out[0..2].copy_from_slice(&self.ediv.to_le_bytes());
+        out[2..10].copy_from_slice(&self.rand);
+        out[10..26].copy_from_slice(&self.ltk);
+        out[26] = self.ltk_flags;
+        out[27..43].copy_from_slice(&self.irk);
+        out[43] = self.addr_flags;
+        out[44..50].copy_from_slice(&self.addr);
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8; BOND_RECORD_LEN]) -> Self {
+        let mut record = Self {
+            ediv: u16::from_le_bytes([bytes[0], bytes[1]]),
+            rand: [0; 8],
+            ltk: [0; 16],
+            ltk_flags: bytes[26],
+            irk: [0; 16],
+            addr_flags: bytes[43],
+            addr: [0; 6],
+        };
+        record.rand.copy_from_slice(&bytes[2..10]);
+        record.ltk.copy_from_slice(&bytes[10..26]);
+        record.irk.copy_from_slice(&bytes[27..43]);
+        record.addr.copy_from_slice(&bytes[44..50]);
+        record
+    }
+
+    fn same_peer(&self, other: &BondRecord) -> bool {
+        self.addr_flags == other.addr_flags && self.addr == other.addr
+    }
+}
+
+const EMPTY_RECORD: BondRecord = BondRecord {
+    ediv: 0,
+    rand: [0; 8],
+    ltk: [0; 16],
+    ltk_flags: 0,
+    irk: [0; 16],
+    addr_flags: 0,
+    addr: [0; 6],
+};
+
+#[derive(Clone)]
+pub struct BondTable {
+    /// Newest first; the first `len` are in use.
+    records: [BondRecord; MAX_BONDS],
+    len: usize,
+}
+
+impl BondTable {
+    pub const fn new() -> Self {
+        Self {
+            records: [EMPTY_RECORD; MAX_BONDS],
+            len: 0,
+        }
+    }
+
+    /// Parse `/BONDS.DB`; `None` if its length is not whole records.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if !bytes.len().is_multiple_of(BOND_RECORD_LEN) {
+            return None;
+        }
+        let mut table = Self::new();
+        for chunk in bytes.chunks_exact(BOND_RECORD_LEN).take(MAX_BONDS) {
+            let mut raw = [0u8; BOND_RECORD_LEN];
+            raw.copy_from_slice(chunk);
+            table.records[table.len] = BondRecord::from_bytes(&raw);
+            table.len += 1;
+        }
+        Some(table)
+    }
+
+    /// Serialise into `out`; returns the length written.
+    pub fn to_bytes(&self, out: &mut [u8; BONDS_FILE_MAX_LEN]) -> usize {
+        let chunks = out.chunks_exact_mut(BOND_RECORD_LEN);
+        for (record, chunk) in self.records().iter().zip(chunks) {
+            chunk.copy_from_slice(&record.to_bytes());
+        }
+        self.len * BOND_RECORD_LEN
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn records(&self) -> &[BondRecord] {
+        &self.records[..self.len]
+    }
+
+    /// Store `record` as the newest bond, replacing the same peer or, on a
+    /// full table, the oldest one.
+    pub fn insert(&mut self, record: BondRecord) {
+        let end = match self.records().iter().position(|old| old.same_peer(&record)) {
+            Some(index) => index,
+            None if self.len == MAX_BONDS => MAX_BONDS - 1,
+            None => {
+                self.len += 1;
+                self.len - 1
+            }
+        };
+        self.records.copy_within(..end, 1);
+        self.records[0] = record;
+    }
+
+    /// Key for a reconnection that presents `ediv` and `rand`.
+    pub fn find(&self, ediv: u16, rand: &[u8; 8]) -> Option<&BondRecord> {
+        self.records()
+            .iter()
+            .find(|record| record.ediv == ediv && record.rand == *rand)
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(tag: u8) -> BondRecord {
+        BondRecord {
+            ediv: 0x1200 | tag as u16,
+            rand: [tag; 8],
+            ltk: [0xA0 | tag; 16],
+            ltk_flags: 0x03,
+            irk: [0x50 | tag; 16],
+            addr_flags: 0x01,
+            addr: [tag, 2, 3, 4, 5, 0xC6],
+        }
+    }
+
+    #[test]
+    fn table_round_trips_through_the_file() {
+        let mut table = BondTable::new();
+        table.insert(record(1));
+        table.insert(record(2));
+        let mut file = [0u8; BONDS_FILE_MAX_LEN];
+        let len = table.to_bytes(&mut file);
+        assert_eq!(len, 2 * BOND_RECORD_LEN);
+
+        let loaded = BondTable::from_bytes(&file[..len]).unwrap();
+        assert_eq!(loaded.records(), &[record(2), record(1)]);
+        assert_eq!(loaded.find(0x1201, &[1; 8]), Some(&record(1)));
+        assert_eq!(loaded.find(0x1201, &[2; 8]), None);
+
+        assert!(BondTable::from_bytes(&file[..len - 1]).is_none());
+        assert_eq!(BondTable::from_bytes(&[]).unwrap().len(), 0);
+    }
+
+    #[test]
+    fn rebonding_replaces_and_a_full_table_evicts_the_oldest() {
+        let mut table = BondTable::new();
+        for tag in 1..=4 {
+            table.insert(record(tag));
+        }
+        // The same peer with new keys moves to the front.
+        let rebonded = BondRecord {
+            ediv: 0x7777,
+            ..record(2)
+        };
+        table.insert(rebonded);
+        assert_eq!(table.len(), 4);
+        assert_eq!(table.records()[0], rebonded);
+        assert_eq!(table.find(0x1202, &[2; 8]), None);
+
+        table.insert(record(5));
+        let tags: Vec<u8> = table.records().iter().map(|r| r.addr[0]).collect();
+        assert_eq!(tags, [5, 2, 4, 3]);
+
+        table.clear();
+        assert!(table.is_empty());
+    }
+}
@@ -0,0 +1,203 @@
+//! BTHome v2 telemetry advertisement for home-automation scanners.
+//!
+//! When the `ble.bthome` setting is on, the tracker periodically broadcasts a
+//! short non-connectable BTHome frame with battery, temperature and fix
+//! state, so Home Assistant (or any BTHome scanner) can passively see that it
+//! is home and healthy without connecting.
+//!
+//! # Design
+//!
+//! - One burst of `BURST_SECS` every `PERIOD_SECS`, taking a background
+//!   slot in the advertising scheduler like Find My and FMDN, so it never
+//!   holds up the main connectable advertising.
+//! - Sent from the device's own address: scanners key devices by address,
+//!   and this frame is meant to be tracked, unlike the rotating finder
+//!   beacons.
+//! - Unencrypted: nothing here is more private than the device name.
+//! - Temperature is omitted without a BMP280 and voltage without a battery
+//!   reading; BTHome objects are optional and must be in ascending id order.
+
+use embassy_executor::task;
+use embassy_futures::select::{select, Either};
+use embassy_time::{Duration, Timer};
+use heapless::Vec;
+use libm::roundf;
+use nrf_softdevice::{raw, RawError};
+
+use crate::adv_scheduler::{AdvPriority, ADV_SCHEDULER};
+use crate::bmp280;
+use crate::settings;
+use crate::system_info::SYSTEM_INFO;
+
+const PERIOD_SECS: u64 = 60;
+const BURST_SECS: u64 = 2;
+/// 100 ms (units of 0.625 ms): a passive scanner sees a few packets per burst.
+const ADV_INTERVAL_UNITS: u32 = 160;
+
+const AD_TYPE_FLAGS: u8 = 0x01;
+const AD_TYPE_SHORT_NAME: u8 = 0x08;
+const AD_TYPE_SERVICE_DATA_16: u8 = 0x16;
+const BTHOME_UUID: u16 = 0xFCD2;
+/// Version 2, unencrypted, regular interval.
+const DEVICE_INFO: u8 = 0x40;
+const OBJ_PACKET_ID: u8 = 0x00;
+const OBJ_BATTERY: u8 = 0x01;
+const OBJ_TEMPERATURE: u8 = 0x02;
+const OBJ_COUNT: u8 = 0x09;
+const OBJ_VOLTAGE: u8 = 0x0C;
+const OBJ_GENERIC_BOOLEAN: u8 = 0x0F;
+const SHORT_NAME: &[u8] = b"MGT";
+
+const ADV_LEN: usize = 31;
+
+static mut BTHOME_ADV_HANDLE: u8 = raw::BLE_GAP_ADV_SET_HANDLE_NOT_SET as u8;
+
+#[derive(Clone, Copy)]
+struct Telemetry {
+    packet_id: u8,
+    battery_percent: u8,
+    temperature_c: Option<f32>,
+    satellites: u8,
+    battery_mv: Option<u16>,
+    has_fix: bool,
+}
+
+fn build_adv_payload(t: &Telemetry) -> Vec<u8, ADV_LEN> {
+    let mut service_data: Vec<u8, ADV_LEN> = Vec::new();
+    let _ = service_data.extend_from_slice(&BTHOME_UUID.to_le_bytes());
+    let _ = service_data.extend_from_slice(&[DEVICE_INFO, OBJ_PACKET_ID, t.packet_id]);
+    let _ = service_data.extend_from_slice(&[OBJ_BATTERY, t.battery_percent]);
+    if let Some(temperature_c) = t.temperature_c {
+        let centi = roundf(temperature_c * 100.0) as i16;
+        let _ = service_data.push(OBJ_TEMPERATURE);
+        let _ = service_data.extend_from_slice(&centi.to_le_bytes());
+    }
+    let _ = service_data.extend_from_slice(&[OBJ_COUNT, t.satellites]);
+    if let Some(mv) = t.battery_mv {
+        let _ = service_data.push(OBJ_VOLTAGE);
+        let _ = service_data.extend_from_slice(&mv.to_le_bytes());
+    }
+    let _ = service_data.extend_from_slice(&[OBJ_GENERIC_BOOLEAN, t.has_fix as u8]);
+
+    let mut out: Vec<u8, ADV_LEN> = Vec::new();
+    let _ = out.extend_from_slice(&[2, AD_TYPE_FLAGS, 0x06]);
+    let _ = out.push(service_data.len() as u8 + 1);
+    let _ = out.push(AD_TYPE_SERVICE_DATA_16);
+    let _ = out.extend_from_slice(&service_data);
+    let _ = out.push(SHORT_NAME.len() as u8 + 1);
+    let _ = out.push(AD_TYPE_SHORT_NAME);
+    let _ = out.extend_from_slice(SHORT_NAME);
+    out
+}
+
+async fn telemetry(packet_id: u8) -> Telemetry {
+    let temperature_c = {
+        let bmp = bmp280::BMP280_DATA.lock().await;
+        bmp.ok.then_some(bmp.temperature_c)
+    };
+    let info = SYSTEM_INFO.lock().await;
+    Telemetry {
+        packet_id,
+        battery_percent: info.battery_percent,
+        temperature_c,
+        satellites: info.satellites.min(u8::MAX as u32) as u8,
+        battery_mv: (info.battery_voltage >= 0.0)
+            .then(|| roundf(info.battery_voltage * 1000.0) as u16),
+        has_fix: info.location_valid,
+    }
+}
+
+fn configure_adv_set(
+    adv_data: &raw::ble_gap_adv_data_t,
+    adv_params: &raw::ble_gap_adv_params_t,
+) -> Result<u8, RawError> {
+    unsafe {
+        let handle_ptr = &raw mut BTHOME_ADV_HANDLE;
+        let first = RawError::convert(raw::sd_ble_gap_adv_set_configure(
+            handle_ptr,
+            adv_data as *const _,
+            adv_params as *const _,
+        ));
+        match first {
+            Ok(()) => Ok(*handle_ptr),
+            Err(RawError::NoMem) => {
+                *handle_ptr = 0;
+                RawError::convert(raw::sd_ble_gap_adv_set_configure(
+                    handle_ptr,
+                    adv_data as *const _,
+                    adv_params as *const _,
+                ))?;
+                Ok(*handle_ptr)
+            }
+            Err(RawError::BleInvalidAdvHandle) => {
+                *handle_ptr = raw::BLE_GAP_ADV_SET_HANDLE_NOT_SET as u8;
+                RawError::convert(raw::sd_ble_gap_adv_set_configure(
+                    handle_ptr,
+                    adv_data as *const _,
+                    adv_params as *const _,
+                ))?;
+                Ok(*handle_ptr)
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Background task: one BTHome burst per period while the setting is on.
+#[task]
+pub async fn bthome_task() {
+    let mut packet_id: u8 = 0;
+    loop {
+        Timer::after(Duration::from_secs(PERIOD_SECS)).await;
+        if settings::stored(settings::BLE_BTHOME) == 0 {
+            continue;
+        }
+
+        let guard = ADV_SCHEDULER.acquire(AdvPriority::BthomeAdv).await;
+        packet_id = packet_id.wrapping_add(1);
+        let adv_payload = build_adv_payload(&telemetry(packet_id).await);
+
+        let mut adv_params: raw::ble_gap_adv_params_t = unsafe { core::mem::zeroed() };
+        adv_params.properties.type_ =
+            raw::BLE_GAP_ADV_TYPE_NONCONNECTABLE_NONSCANNABLE_UNDIRECTED as u8;
+        adv_params.interval = ADV_INTERVAL_UNITS;
+        adv_params.duration = 0;
+        adv_params.filter_policy = raw::BLE_GAP_ADV_FP_ANY as u8;
+        adv_params.primary_phy = raw::BLE_GAP_PHY_1MBPS as u8;
+
+        let adv_data = raw::ble_gap_adv_data_t {
+            adv_data: raw::ble_data_t {
+                p_data: adv_payload.as_ptr() as *mut u8,
+                len: adv_payload.len() as u16,
+            },
+            scan_rsp_data: raw::ble_data_t {
+                p_data: core::ptr::null_mut(),
+                len: 0,
+            },
+        };
+
+        let adv_handle = match configure_adv_set(&adv_data, &adv_params) {
+            Ok(h) => h,
+            Err(e) => {
+                defmt::warn!("BTHome: adv configure failed: {:?}", e);
+                drop(guard);
+                continue;
+            }
+        };
+        if let Err(e) = RawError::convert(unsafe {
+            raw::sd_ble_gap_adv_start(adv_handle, raw::BLE_CONN_CFG_TAG_DEFAULT as u8)
+        }) {
+            defmt::warn!("BTHome: adv start failed: {:?}", e);
+            drop(guard);
+            continue;
+        }
+        defmt::debug!("BTHome: advertising packet {}", packet_id);
+
+        let burst = Timer::after(Duration::from_secs(BURST_SECS));
+        if let Either::First(()) = select(guard.wait_preempted(), burst).await {
+            defmt::info!("BTHome: preempted by main BLE");
+        }
+        let _ = RawError::convert(unsafe { raw::sd_ble_gap_adv_stop(adv_handle) });
+        drop(guard);
+    }
+}
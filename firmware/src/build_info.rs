@@ -0,0 +1,67 @@
+//! Identity of the running image, for bug reports.
+//!
+//! - Firmware version: `git describe` captured by `build.rs`.
+//! - Build features: the `features::CAPABILITIES` bitmask.
+//! - SoftDevice firmware ID, read from the SoftDevice info struct.
+//! - Bootloader version: the Adafruit UF2 bootloader leaves it in
+//!   `TIMER2.CC[0]` as `major << 16 | minor << 8 | patch` before jumping to
+//!   the application, so it must be captured before anything touches TIMER2.
+//! - Debug access protection (UICR.APPROTECT): this part has no secure boot,
+//!   and a locked debug port is the closest equivalent worth reporting.
+
+use core::ptr::read_volatile;
+
+use crate::features;
+
+pub const FIRMWARE_VERSION: &str = env!("GIT_DESCRIBE");
+/// Bytes reserved for the version string in the system info snapshot.
+pub const FIRMWARE_VERSION_FIELD_LEN: usize = 24;
+
+/// `SD_FWID` in the S140 info struct (MBR 0x1000 + info offset 0x2000 + 0x0C).
+const SOFTDEVICE_FWID_ADDR: usize = 0x0000_300C;
+const TIMER2_CC0_ADDR: usize = 0x4000_A540;
+const UICR_APPROTECT_ADDR: usize = 0x1000_1208;
+const APPROTECT_ENABLED: u32 = 0x00;
+
+#[derive(Clone, Copy, Debug)]
+pub struct BuildInfo {
+    pub firmware_version: &'static str,
+    pub capabilities: u32,
+    pub softdevice_fwid: u16,
+    /// `major << 16 | minor << 8 | patch`, 0 when unknown.
+    pub bootloader_version: u32,
+    pub debug_protected: bool,
+}
+
+impl BuildInfo {
+    pub const fn unknown() -> Self {
+        Self {
+            firmware_version: FIRMWARE_VERSION,
+            capabilities: features::CAPABILITIES,
+            softdevice_fwid: 0,
+            bootloader_version: 0,
+            debug_protected: false,
+        }
+    }
+}
+
+/// Read the hardware-dependent fields. Call first thing in `main`.
+pub fn capture() -> BuildInfo {
+    // SAFETY: fixed, always-readable flash, UICR and peripheral addresses on
+    // the nRF52840.
+    let (fwid, timer2_cc0, approtect) = unsafe {
+        (
+            read_volatile(SOFTDEVICE_FWID_ADDR as *const u16),
+            read_volatile(TIMER2_CC0_ADDR as *const u32),
+            read_volatile(UICR_APPROTECT_ADDR as *const u32),
+        )
+    };
+    // Anything above 0x00FF_FFFF is not a packed bootloader version.
+    let bootloader_version = if timer2_cc0 <= 0x00FF_FFFF { timer2_cc0 } else { 0 };
+    BuildInfo {
+        softdevice_fwid: if fwid == 0xFFFF { 0 } else { fwid },
+        bootloader_version,
+        debug_protected: approtect & 0xFF == APPROTECT_ENABLED,
+        ..BuildInfo::unknown()
+    }
+}
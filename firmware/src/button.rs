@@ -5,18 +5,35 @@ use embassy_nrf::gpio::Input;
 use embassy_time::{Instant, Timer};
 
 use crate::ble;
-use crate::display::{send_command, DisplayCommand};
-use crate::storage::{self, ListDirOutcome};
+use crate::guest;
+use crate::markers::{self, WaypointOutcome};
+use crate::power::{self, ShutdownReason};
+use crate::recording;
+use crate::sessions;
+use crate::display::{self, send_command, DisplayCommand};
+use crate::settings;
+use crate::storage::{self, ListDirOutcome, LogFileOutcome};
+use crate::usb_power::{self, UsbSource};
 use crate::{request_usb_mode_transition, usb_connected};
 
 const DEBOUNCE_DELAY_MS: u64 = 50;
 const LONG_PRESS_MS: u64 = 2000;
 const VERY_LONG_PRESS_MS: u64 = 5000;
+const POWER_OFF_PRESS_MS: u64 = 10_000;
+const DOUBLE_PRESS_WINDOW_MS: u64 = 400;
 const LIST_SD_ON_BUTTON: bool = false;
+/// A short press within this long after the USB prompt confirms USB mode.
+const USB_CONFIRM_MS: u64 = display::BANNER_MS;
+/// A second long press within this long deletes the log on the Files page.
+const DELETE_CONFIRM_MS: u64 = display::BANNER_MS;
 
 #[task]
 pub async fn button_task(mut button: Input<'static>) {
     let mut last_valid = Instant::now().as_millis();
+    // Deadline of a pending USB mode prompt.
+    let mut usb_confirm_until: Option<u64> = None;
+    // Deadline of a pending log delete prompt on the Files page.
+    let mut delete_confirm_until: Option<u64> = None;
 
     loop {
         button.wait_for_falling_edge().await;
@@ -29,15 +46,47 @@ pub async fn button_task(mut button: Input<'static>) {
         // Tier 1: wait for short press threshold
         match select(button.wait_for_rising_edge(), Timer::after_millis(LONG_PRESS_MS)).await {
             Either::First(_) => {
-                defmt::info!("Button short press");
-                handle_short_press();
+                // A second press inside the window makes it a double press.
+                match select(
+                    button.wait_for_falling_edge(),
+                    Timer::after_millis(DOUBLE_PRESS_WINDOW_MS),
+                )
+                .await
+                {
+                    Either::First(_) => {
+                        defmt::info!("Button double press");
+                        handle_double_press().await;
+                        button.wait_for_rising_edge().await;
+                        last_valid = Instant::now().as_millis();
+                    }
+                    Either::Second(_) => {
+                        defmt::info!("Button short press");
+                        // The selection moves, so the prompt no longer applies.
+                        delete_confirm_until = None;
+                        let now = Instant::now().as_millis();
+                        if usb_confirm_until.take().is_some_and(|until| now < until) {
+                            defmt::info!("USB mode confirmed");
+                            request_usb_mode_transition();
+                        } else {
+                            handle_short_press();
+                        }
+                    }
+                }
                 Timer::after_millis(1).await;
                 continue;
             }
             Either::Second(_) => {
                 // Held past LONG_PRESS_MS — execute long press action
                 defmt::info!("Button long press");
-                handle_long_press().await;
+                if on_files_page() {
+                    let now = Instant::now().as_millis();
+                    let confirmed = delete_confirm_until.take().is_some_and(|until| now < until);
+                    delete_confirm_until = handle_files_long_press(confirmed)
+                        .await
+                        .then(|| now + DELETE_CONFIRM_MS);
+                } else {
+                    handle_long_press().await;
+                }
             }
         }
 
@@ -50,8 +99,22 @@ pub async fn button_task(mut button: Input<'static>) {
             Either::Second(_) => {
                 // Held past VERY_LONG_PRESS_MS — enter USB MSC mode
                 defmt::info!("Button very long press");
-                handle_very_long_press();
-                button.wait_for_rising_edge().await;
+                usb_confirm_until = handle_very_long_press()
+                    .then(|| Instant::now().as_millis() + USB_CONFIRM_MS);
+
+                // Tier 3: held on to POWER_OFF_PRESS_MS — power off
+                let remaining = POWER_OFF_PRESS_MS - VERY_LONG_PRESS_MS;
+                if let Either::Second(_) = select(
+                    button.wait_for_rising_edge(),
+                    Timer::after_millis(remaining),
+                )
+                .await
+                {
+                    defmt::info!("Button power-off press");
+                    usb_confirm_until = None;
+                    power::request_shutdown(ShutdownReason::Button);
+                    button.wait_for_rising_edge().await;
+                }
             }
         }
 
@@ -85,14 +148,81 @@ pub async fn usb_only_button_task(mut button: Input<'static>) {
     }
 }
 
-/// Short press: toggle display only
+/// Short press: toggle display only. On the Files page it moves the
+/// selection and leaves the page past the last log.
 fn handle_short_press() {
     send_command(DisplayCommand::ResetTimeout);
+    if on_files_page() && display::browser::select_next() {
+        return;
+    }
     send_command(DisplayCommand::Toggle);
 }
 
-/// Long press (~2s): BLE broadcast + flush SD cache
+fn on_files_page() -> bool {
+    display::remote_state() == [1, display::DisplayPage::Files as u8]
+}
+
+/// Long press on the Files page: prompt, then delete the selected log when
+/// `confirmed`. Returns true if a prompt was shown.
+async fn handle_files_long_press(confirmed: bool) -> bool {
+    send_command(DisplayCommand::ResetTimeout);
+    let Some(log) = display::browser::selected() else {
+        return false;
+    };
+    if !confirmed {
+        display::show_banner("Hold again: delete");
+        return true;
+    }
+    defmt::info!("Files page: deleting log {}", log.date);
+    let outcome = display::browser::delete_selected().await;
+    display::show_banner(match outcome {
+        Some(LogFileOutcome::Done) => "Log deleted",
+        Some(LogFileOutcome::Skipped) => "Log in use",
+        Some(LogFileOutcome::Missing) => "Log not found",
+        Some(LogFileOutcome::Failed) | None => "Delete failed",
+    });
+    false
+}
+
+/// Double press: pause/resume the open session, else start/stop recording,
+/// or drop a waypoint with `button.double_press` = 1. On the Find My page
+/// it switches Find My broadcasting instead.
+async fn handle_double_press() {
+    #[cfg(feature = "findmy")]
+    if display::remote_state() == [1, display::DisplayPage::FindMy as u8] {
+        let banner = if crate::findmy::toggle_enabled().await {
+            "Find My: on"
+        } else {
+            "Find My: off"
+        };
+        display::show_banner(banner);
+        return;
+    }
+    if settings::stored(settings::BUTTON_DOUBLE_PRESS) == 1 {
+        display::show_banner(match markers::drop_waypoint().await {
+            WaypointOutcome::Saved => "Waypoint saved",
+            WaypointOutcome::NoFix => "Waypoint: no fix",
+            WaypointOutcome::Failed => "Waypoint failed",
+        });
+        return;
+    }
+    if !sessions::toggle_pause().await {
+        let recording = recording::toggle().await;
+        defmt::info!("Recording toggled by button: {}", recording);
+    }
+    send_command(DisplayCommand::ResetTimeout);
+}
+
+/// Long press (~2s): BLE broadcast (guest window when locked down) + flush SD cache.
+/// On the Device info page it also opens the BLE pairing window.
 async fn handle_long_press() {
+    if display::remote_state() == [1, display::DisplayPage::DeviceInfo as u8] {
+        ble::open_pairing_window();
+        display::show_banner("Pairing: 60s");
+    }
+    if guest::lockdown() {
+        guest::open_window();
+    }
     ble::request_fast_advertising();
 
     if storage::flush_sd_cache().await {
@@ -108,14 +238,27 @@ async fn handle_long_press() {
     send_command(DisplayCommand::ResetTimeout);
 }
 
-/// Very long press (~5s): enter USB MSC mode
-fn handle_very_long_press() {
-    if usb_connected() {
+/// Very long press (~5s): enter USB MSC mode. With `usb.confirm` on, only
+/// prompt on the display; returns true if a short press must confirm.
+fn handle_very_long_press() -> bool {
+    if usb_power::source() == UsbSource::Charger {
+        defmt::warn!("Very long press but USB port is a charger");
+    } else if !usb_connected() {
+        defmt::warn!("Very long press but USB not connected");
+    } else if settings::stored(settings::USB_CONFIRM) != 0 {
+        defmt::info!("Very long press -> USB mode prompt");
+        // USB mode resets into mass storage and ends the recording.
+        display::show_banner(if recording::is_recording() {
+            "Press: USB, stop log"
+        } else {
+            "Press again: USB mode"
+        });
+        return true;
+    } else {
         defmt::info!("Very long press -> request USB mode");
         request_usb_mode_transition();
-    } else {
-        defmt::warn!("Very long press but USB not connected");
     }
+    false
 }
 
 async fn list_sd_root() {
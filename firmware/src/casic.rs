@@ -5,16 +5,18 @@ pub const CASIC_PACKET_TIMEOUT_MS: u64 = 30_000;
 
 // ACK and NACK share the same class ID (0x05) per CASIC protocol spec;
 // they are distinguished by message ID (ACK=0x01, NACK=0x00).
+pub const CASIC_CLASS_NAV: u8 = 0x01;
+pub const CASIC_CLASS_CFG: u8 = 0x06;
 pub const CASIC_CLASS_ACK: u8 = 0x05;
 pub const CASIC_CLASS_NACK: u8 = 0x05;
-#[allow(dead_code)] // protocol completeness
 pub const CASIC_CLASS_AID: u8 = 0x0B;
 pub const CASIC_CLASS_MSG: u8 = 0x08;
 
 pub const CASIC_ID_ACK: u8 = 0x01;
 pub const CASIC_ID_NACK: u8 = 0x00;
-#[allow(dead_code)] // protocol completeness
 pub const CASIC_ID_AID_INI: u8 = 0x01;
+pub const CASIC_ID_NAV_TIMEUTC: u8 = 0x10;
+pub const CASIC_ID_CFG_MSG: u8 = 0x01;
 #[allow(dead_code)] // protocol completeness
 pub const CASIC_ID_MSG_BDSUTC: u8 = 0x00;
 #[allow(dead_code)] // protocol completeness
@@ -251,22 +253,12 @@ impl CasicParser {
     }
 
     fn calculate_checksum(&self) -> u32 {
-        let mut checksum =
-            ((self.current.msg_id as u32) << 24)
-                + ((self.current.class_id as u32) << 16)
-                + (self.current.payload_length as u32);
-
-        // CASIC protocol guarantees payload_length is always a multiple of 4 bytes.
-        let words = (self.current.payload_length as usize) / 4;
-        for i in 0..words {
-            let base = i * 4;
-            let word = (self.current.payload[base] as u32)
-                | ((self.current.payload[base + 1] as u32) << 8)
-                | ((self.current.payload[base + 2] as u32) << 16)
-                | ((self.current.payload[base + 3] as u32) << 24);
-            checksum = checksum.wrapping_add(word);
-        }
-        checksum
+        let len = self.current.payload_length as usize;
+        casic_checksum(
+            self.current.class_id,
+            self.current.msg_id,
+            &self.current.payload[..len],
+        )
     }
 
     fn reset_parser(&mut self, now_ms: u64) {
@@ -284,3 +276,59 @@ impl CasicParser {
         now_ms.saturating_sub(self.state_change_ms) > CASIC_PACKET_TIMEOUT_MS
     }
 }
+
+/// UTC from a `NAV-TIMEUTC` payload as `(year, month, day, hour, minute,
+/// second)`, if the receiver marks it valid.
+///
+/// Layout: runTime U4, tAcc R4, msErr R4, ms U2, year U2, month, day, hour,
+/// min, sec, valid, timeSrc, dateValid (U1 each), 24 bytes in total.
+pub fn nav_timeutc(packet: &CasicPacket) -> Option<(u16, u8, u8, u8, u8, u8)> {
+    if !packet.valid
+        || packet.class_id != CASIC_CLASS_NAV
+        || packet.msg_id != CASIC_ID_NAV_TIMEUTC
+        || packet.payload_length < 24
+    {
+        return None;
+    }
+    let p = &packet.payload;
+    if p[21] == 0 || p[23] == 0 {
+        return None;
+    }
+    let year = u16::from_le_bytes([p[14], p[15]]);
+    Some((year, p[16], p[17], p[18], p[19], p[20]))
+}
+
+/// CASIC checksum: header word plus the payload summed as little-endian words.
+///
+/// The CASIC protocol guarantees the payload length is a multiple of 4 bytes.
+pub fn casic_checksum(class_id: u8, msg_id: u8, payload: &[u8]) -> u32 {
+    let mut checksum =
+        ((msg_id as u32) << 24) + ((class_id as u32) << 16) + (payload.len() as u32);
+    for word in payload.chunks_exact(4) {
+        checksum =
+            checksum.wrapping_add(u32::from_le_bytes([word[0], word[1], word[2], word[3]]));
+    }
+    checksum
+}
+
+/// Frame `payload` as a CASIC packet into `out`; returns the packet length.
+pub fn encode_casic_packet(
+    class_id: u8,
+    msg_id: u8,
+    payload: &[u8],
+    out: &mut [u8],
+) -> Option<usize> {
+    let total = payload.len() + 10;
+    if payload.len() > CASIC_MAX_PAYLOAD_SIZE || out.len() < total {
+        return None;
+    }
+    out[0] = CASIC_HEADER_1;
+    out[1] = CASIC_HEADER_2;
+    out[2..4].copy_from_slice(&(payload.len() as u16).to_le_bytes());
+    out[4] = class_id;
+    out[5] = msg_id;
+    out[6..6 + payload.len()].copy_from_slice(payload);
+    out[6 + payload.len()..total]
+        .copy_from_slice(&casic_checksum(class_id, msg_id, payload).to_le_bytes());
+    Some(total)
+}
@@ -0,0 +1,258 @@
+//! Crash records that survive the reset, and the reason for the last one.
+//!
+//! panic-probe printed a panic over RTT and halted, which in the field meant
+//! a stuck tracker and nothing to go on. The panic and HardFault handlers
+//! here keep what happened in RAM across a reset instead; the next boot
+//! appends it to `/CRASH.LOG` and reports it with `RESETREAS` on the
+//! diagnostics characteristic.
+//!
+//! # Design
+//!
+//! - The record sits in `.uninit`, which cortex-m-rt neither zeroes nor
+//!   loads, behind a magic word and a CRC, so RAM left random by a power-on
+//!   never reads as a crash. `capture` takes it and clears the magic.
+//! - A panic keeps `file:line` and as much of the message as fits in
+//!   `MESSAGE_LEN`; a HardFault keeps PC and LR from the exception frame.
+//!   The first record of a boot wins, so the fault a debugger break raises
+//!   cannot overwrite the panic that caused it.
+//! - Both handlers then reset. With a debugger attached they break first,
+//!   like panic-probe did.
+//! - `RESETREAS` is read and cleared in `capture`, before the SoftDevice
+//!   restricts access to POWER. `0` means power-on or brown-out.
+
+use core::cell::RefCell;
+use core::fmt::{self, Write};
+use core::mem::MaybeUninit;
+use core::panic::PanicInfo;
+use core::ptr::{addr_of_mut, read_volatile, write_volatile};
+
+use cortex_m::peripheral::{DCB, SCB};
+use cortex_m_rt::{exception, ExceptionFrame};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex as BlockingMutex;
+use nrf_pac as pac;
+
+use crate::crc32::Crc32;
+use crate::storage;
+
+const MAGIC: u32 = 0xC2A5_11ED;
+pub const MESSAGE_LEN: usize = 80;
+/// `[reset_reason: u32][kind: u8][pc: u32][lr: u32][message]`, little endian.
+pub const REPORT_HEADER_LEN: usize = 13;
+pub const REPORT_MAX_LEN: usize = REPORT_HEADER_LEN + MESSAGE_LEN;
+
+/// `RESETREAS` bits for resets nobody asked for.
+const RESETREAS_DOG: u32 = 1 << 1;
+const RESETREAS_LOCKUP: u32 = 1 << 3;
+
+#[repr(u8)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum CrashKind {
+    None = 0,
+    Panic = 1,
+    HardFault = 2,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct CrashRecord {
+    magic: u32,
+    kind: u32,
+    pc: u32,
+    lr: u32,
+    message_len: u32,
+    message: [u8; MESSAGE_LEN],
+    crc: u32,
+}
+
+impl CrashRecord {
+    fn checksum(&self) -> u32 {
+        let mut crc = Crc32::new();
+        for word in [self.kind, self.pc, self.lr, self.message_len] {
+            crc.update(&word.to_le_bytes());
+        }
+        crc.update(&self.message);
+        crc.finish()
+    }
+
+    fn is_valid(&self) -> bool {
+        self.magic == MAGIC
+            && self.message_len as usize <= MESSAGE_LEN
+            && self.crc == self.checksum()
+    }
+}
+
+/// What the previous run ended with, as reported over BLE.
+#[derive(Clone, Copy)]
+pub struct BootReport {
+    pub reset_reason: u32,
+    pub kind: CrashKind,
+    pub pc: u32,
+    pub lr: u32,
+    message_len: usize,
+    message: [u8; MESSAGE_LEN],
+}
+
+impl BootReport {
+    const fn empty() -> Self {
+        Self {
+            reset_reason: 0,
+            kind: CrashKind::None,
+            pc: 0,
+            lr: 0,
+            message_len: 0,
+            message: [0; MESSAGE_LEN],
+        }
+    }
+
+    pub fn message(&self) -> &[u8] {
+        &self.message[..self.message_len]
+    }
+
+    /// Bytes for the diagnostics characteristic; returns the length used.
+    pub fn to_bytes(&self, out: &mut [u8; REPORT_MAX_LEN]) -> usize {
+        out[0..4].copy_from_slice(&self.reset_reason.to_le_bytes());
+        out[4] = self.kind as u8;
+        out[5..9].copy_from_slice(&self.pc.to_le_bytes());
+        out[9..13].copy_from_slice(&self.lr.to_le_bytes());
+        let message = self.message();
+        out[REPORT_HEADER_LEN..REPORT_HEADER_LEN + message.len()].copy_from_slice(message);
+        REPORT_HEADER_LEN + message.len()
+    }
+}
+
+#[unsafe(link_section = ".uninit.CRASH")]
+static mut RECORD: MaybeUninit<CrashRecord> = MaybeUninit::uninit();
+
+static BOOT_REPORT: BlockingMutex<CriticalSectionRawMutex, RefCell<BootReport>> =
+    BlockingMutex::new(RefCell::new(BootReport::empty()));
+
+fn read_record() -> CrashRecord {
+    // SAFETY: every bit pattern is a valid `CrashRecord` (integers and a
+    // byte array), and only the handlers and `capture` touch it, never at
+    // the same time.
+    unsafe { read_volatile(addr_of_mut!(RECORD)).assume_init() }
+}
+
+fn write_record(record: &CrashRecord) {
+    // SAFETY: as in `read_record`.
+    unsafe { write_volatile(addr_of_mut!(RECORD), MaybeUninit::new(*record)) };
+}
+
+/// Take the crash record and the reset reason. Call early in `main`,
+/// before the SoftDevice starts.
+pub fn capture() {
+    let reset_reason = pac::POWER.resetreas().read();
+    pac::POWER.resetreas().write_value(reset_reason);
+    let mut report = BootReport {
+        reset_reason: reset_reason.0,
+        ..BootReport::empty()
+    };
+    let record = read_record();
+    if record.is_valid() {
+        report.kind = match record.kind {
+            1 => CrashKind::Panic,
+            _ => CrashKind::HardFault,
+        };
+        report.pc = record.pc;
+        report.lr = record.lr;
+        report.message_len = record.message_len as usize;
+        report.message = record.message;
+    }
+    write_record(&CrashRecord { magic: 0, ..record });
+    BOOT_REPORT.lock(|cell| *cell.borrow_mut() = report);
+    if report.kind != CrashKind::None {
+        defmt::warn!(
+            "Crash: previous run ended in {=u8} at pc 0x{:08x}: {=[u8]:a}",
+            report.kind as u8,
+            report.pc,
+            report.message()
+        );
+    }
+    defmt::info!("Reset reason 0x{:08x}", report.reset_reason);
+}
+
+pub fn boot_report() -> BootReport {
+    BOOT_REPORT.lock(|cell| *cell.borrow())
+}
+
+/// Append the previous crash, or an unexpected watchdog or lockup reset, to
+/// `/CRASH.LOG`. Call once the SD card is up.
+pub async fn log_previous() {
+    let report = boot_report();
+    let unexpected = report.reset_reason & (RESETREAS_DOG | RESETREAS_LOCKUP) != 0;
+    if report.kind == CrashKind::None && !unexpected {
+        return;
+    }
+    if !storage::append_crash_log(&report).await {
+        defmt::warn!("Crash: CRASH.LOG append failed");
+    }
+}
+
+/// Formats into the record's message, dropping what does not fit.
+struct MessageWriter<'a> {
+    buf: &'a mut [u8; MESSAGE_LEN],
+    len: usize,
+}
+
+impl Write for MessageWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let take = s.len().min(MESSAGE_LEN - self.len);
+        self.buf[self.len..self.len + take].copy_from_slice(&s.as_bytes()[..take]);
+        self.len += take;
+        Ok(())
+    }
+}
+
+fn store(kind: CrashKind, pc: u32, lr: u32, describe: impl FnOnce(&mut MessageWriter)) {
+    if read_record().is_valid() {
+        return;
+    }
+    let mut message = [0u8; MESSAGE_LEN];
+    let mut writer = MessageWriter {
+        buf: &mut message,
+        len: 0,
+    };
+    describe(&mut writer);
+    let message_len = writer.len as u32;
+    let mut record = CrashRecord {
+        magic: MAGIC,
+        kind: kind as u32,
+        pc,
+        lr,
+        message_len,
+        message,
+        crc: 0,
+    };
+    record.crc = record.checksum();
+    write_record(&record);
+}
+
+fn reset() -> ! {
+    if DCB::is_debugger_attached() {
+        cortex_m::asm::bkpt();
+    }
+    SCB::sys_reset()
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    cortex_m::interrupt::disable();
+    defmt::error!("{}", defmt::Display2Format(info));
+    store(CrashKind::Panic, 0, 0, |writer| {
+        if let Some(location) = info.location() {
+            let _ = write!(writer, "{}:{}: ", location.file(), location.line());
+        }
+        let _ = write!(writer, "{}", info.message());
+    });
+    reset()
+}
+
+#[exception]
+unsafe fn HardFault(frame: &ExceptionFrame) -> ! {
+    defmt::error!("HardFault at pc 0x{:08x}", frame.pc());
+    store(CrashKind::HardFault, frame.pc(), frame.lr(), |writer| {
+        let _ = writer.write_str("HardFault");
+    });
+    reset()
+}
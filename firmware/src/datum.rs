@@ -0,0 +1,326 @@
+//! WGS-84 to GCJ-02 conversion for the displayed position.
+//!
+//! Maps in mainland China use GCJ-02, which is WGS-84 shifted by a few
+//! hundred metres, so a position read off the screen lands in the wrong
+//! street there. With `display.gcj02` on, the Main page shows the converted
+//! position under a "GLat"/"GLng" label.
+//!
+//! # Design
+//!
+//! - Display only: the log, GPX export, BLE status and navigation all keep
+//!   WGS-84, which is what the receiver reports.
+//! - The published forward transform, which matches the reference to well
+//!   under a metre; there is no exact inverse and none is needed.
+//! - Outside a coarse outline of the mainland the position is left as is,
+//!   so the setting can stay on when travelling. The outline leaves out
+//!   Hong Kong, Macau, Taiwan with Kinmen and Matsu, and the neighbouring
+//!   countries; along a land border it is only good to a few tens of km.
+
+#[cfg(not(feature = "host-test"))]
+use libm::{cos, sin, sqrt};
+
+/// Semi-major axis of the Krasovsky 1940 ellipsoid GCJ-02 is based on.
+const A: f64 = 6_378_245.0;
+/// Its first eccentricity squared.
+const EE: f64 = 0.006_693_421_622_965_943;
+const PI: f64 = core::f64::consts::PI;
+
+/// Mainland China with Hainan as `(latitude, longitude)`, clockwise from
+/// Mohe; the coast runs offshore.
+const MAINLAND: [(f64, f64); 93] = [
+    (53.6, 123.3),
+    (53.3, 125.8),
+    (50.2, 127.7),
+    (48.3, 130.8),
+    (47.7, 132.6),
+    (48.45, 134.2),
+    (48.5, 134.8),
+    (47.2, 134.3),
+    (45.2, 133.1),
+    (44.9, 131.9),
+    (43.9, 131.3),
+    (42.9, 131.0),
+    (42.4, 130.5),
+    (41.9, 128.1),
+    (41.3, 126.4),
+    (40.1, 124.2),
+    (39.2, 123.4),
+    (38.2, 122.8),
+    (37.3, 123.0),
+    (36.0, 122.4),
+    (33.5, 121.4),
+    (31.0, 122.9),
+    (28.8, 122.5),
+    (27.3, 121.2),
+    (26.4, 120.4),
+    (25.4, 119.95),
+    (24.7, 118.9),
+    (23.6, 117.8),
+    (22.9, 116.3),
+    (22.4, 115.0),
+    (22.05, 114.3),
+    (21.4, 112.5),
+    (20.3, 111.4),
+    (18.0, 110.3),
+    (18.0, 108.9),
+    (19.4, 108.4),
+    (21.3, 108.6),
+    (21.55, 107.95),
+    (22.05, 106.7),
+    (22.85, 106.8),
+    (23.3, 105.3),
+    (22.4, 103.95),
+    (22.75, 102.2),
+    (21.15, 101.6),
+    (21.1, 100.9),
+    (21.6, 100.1),
+    (22.2, 99.1),
+    (23.9, 98.7),
+    (24.0, 97.6),
+    (25.3, 97.6),
+    (27.3, 98.6),
+    (28.3, 98.1),
+    (29.2, 96.2),
+    (28.2, 93.8),
+    (27.9, 91.8),
+    (28.1, 89.9),
+    (27.3, 88.9),
+    (28.0, 88.1),
+    (27.9, 86.0),
+    (29.2, 83.3),
+    (30.3, 81.2),
+    (30.2, 79.0),
+    (32.3, 79.4),
+    (33.2, 78.8),
+    (35.3, 78.0),
+    (35.8, 76.7),
+    (37.0, 74.8),
+    (38.6, 73.7),
+    (39.5, 73.6),
+    (40.1, 74.8),
+    (41.0, 76.8),
+    (42.1, 80.2),
+    (44.3, 80.6),
+    (45.2, 82.4),
+    (47.0, 83.0),
+    (47.2, 85.6),
+    (49.0, 87.3),
+    (48.0, 88.6),
+    (46.6, 90.8),
+    (45.1, 93.5),
+    (42.7, 96.4),
+    (42.6, 100.2),
+    (41.6, 105.0),
+    (42.5, 108.7),
+    (43.6, 111.8),
+    (44.9, 113.5),
+    (45.5, 117.0),
+    (46.6, 119.8),
+    (47.7, 119.9),
+    (47.9, 118.2),
+    (49.8, 116.7),
+    (50.2, 119.3),
+    (53.3, 121.3),
+];
+
+/// Inside `MAINLAND` but not in GCJ-02: Hong Kong, Macau, Kinmen, Matsu.
+const EXCLUDED: [&[(f64, f64)]; 4] = [
+    &[
+        (22.4, 113.8),
+        (22.5, 113.95),
+        (22.53, 114.06),
+        (22.56, 114.22),
+        (22.58, 114.45),
+        (22.15, 114.45),
+        (22.15, 113.8),
+    ],
+    &[
+        (22.215, 113.52),
+        (22.215, 113.61),
+        (22.1, 113.61),
+        (22.1, 113.52),
+    ],
+    &[
+        (24.52, 118.22),
+        (24.52, 118.5),
+        (24.38, 118.5),
+        (24.38, 118.22),
+    ],
+    &[
+        (26.4, 119.9),
+        (26.4, 120.52),
+        (25.93, 120.52),
+        (25.93, 119.9),
+    ],
+];
+
+/// Whether GCJ-02 applies at a WGS-84 position.
+pub fn in_mainland_china(latitude: f64, longitude: f64) -> bool {
+    contains(&MAINLAND, latitude, longitude)
+        && !EXCLUDED
+            .iter()
+            .any(|area| contains(area, latitude, longitude))
+}
+
+/// Even-odd test of a position against a polygon.
+fn contains(polygon: &[(f64, f64)], latitude: f64, longitude: f64) -> bool {
+    let mut inside = false;
+    let mut previous = polygon[polygon.len() - 1];
+    for &(lat, lon) in polygon {
+        let (prev_lat, prev_lon) = previous;
+        if (lat > latitude) != (prev_lat > latitude) {
+            let crossing = lon + (latitude - lat) * (prev_lon - lon) / (prev_lat - lat);
+            if longitude < crossing {
+                inside = !inside;
+            }
+        }
+        previous = (lat, lon);
+    }
+    inside
+}
+
+/// GCJ-02 position for a WGS-84 one; unchanged outside China.
+pub fn wgs84_to_gcj02(latitude: f64, longitude: f64) -> (f64, f64) {
+    if !in_mainland_china(latitude, longitude) {
+        return (latitude, longitude);
+    }
+    let x = longitude - 105.0;
+    let y = latitude - 35.0;
+    let rad_lat = latitude / 180.0 * PI;
+    let magic = 1.0 - EE * sin(rad_lat) * sin(rad_lat);
+    let sqrt_magic = sqrt(magic);
+    let d_lat = shift_lat(x, y) * 180.0 / (A * (1.0 - EE) / (magic * sqrt_magic) * PI);
+    let d_lon = shift_lon(x, y) * 180.0 / (A / sqrt_magic * cos(rad_lat) * PI);
+    (latitude + d_lat, longitude + d_lon)
+}
+
+/// Wave terms shared by both shifts.
+fn ripple(x: f64) -> f64 {
+    (20.0 * sin(6.0 * x * PI) + 20.0 * sin(2.0 * x * PI)) * 2.0 / 3.0
+}
+
+fn shift_lat(x: f64, y: f64) -> f64 {
+    let mut shift = -100.0 + 2.0 * x + 3.0 * y + 0.2 * y * y + 0.1 * x * y + 0.2 * sqrt(x.abs());
+    shift += ripple(x);
+    shift += (20.0 * sin(y * PI) + 40.0 * sin(y / 3.0 * PI)) * 2.0 / 3.0;
+    shift += (160.0 * sin(y / 12.0 * PI) + 320.0 * sin(y * PI / 30.0)) * 2.0 / 3.0;
+    shift
+}
+
+fn shift_lon(x: f64, y: f64) -> f64 {
+    let mut shift = 300.0 + x + 2.0 * y + 0.1 * x * x + 0.1 * x * y + 0.1 * sqrt(x.abs());
+    shift += ripple(x);
+    shift += (20.0 * sin(x * PI) + 40.0 * sin(x / 3.0 * PI)) * 2.0 / 3.0;
+    shift += (150.0 * sin(x / 12.0 * PI) + 300.0 * sin(x / 30.0 * PI)) * 2.0 / 3.0;
+    shift
+}
+
+// The host tests have std and no libm.
+#[cfg(feature = "host-test")]
+fn sin(x: f64) -> f64 {
+    x.sin()
+}
+
+#[cfg(feature = "host-test")]
+fn cos(x: f64) -> f64 {
+    x.cos()
+}
+
+#[cfg(feature = "host-test")]
+fn sqrt(x: f64) -> f64 {
+    x.sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_reference_vectors() {
+        // WGS-84 in, GCJ-02 out, from the eviltransform test suite.
+        let vectors = [
+            (31.1774276, 121.5272106, 31.17530398364597, 121.531541859215),
+            (22.543847, 113.912316, 22.540796131694766, 113.9171764808363),
+            (39.911954, 116.377817, 39.91334545536069, 116.38404722455657),
+        ];
+        for (lat, lon, gcj_lat, gcj_lon) in vectors {
+            let (out_lat, out_lon) = wgs84_to_gcj02(lat, lon);
+            assert!((out_lat - gcj_lat).abs() < 1e-9, "{} {}", lat, out_lat);
+            assert!((out_lon - gcj_lon).abs() < 1e-9, "{} {}", lon, out_lon);
+        }
+    }
+
+    #[test]
+    fn leaves_positions_outside_china_alone() {
+        for (lat, lon) in [(51.5, -0.1), (40.7, -74.0), (-33.9, 151.2), (0.5, 110.0)] {
+            assert!(!in_mainland_china(lat, lon));
+            assert_eq!(wgs84_to_gcj02(lat, lon), (lat, lon));
+        }
+        // Hong Kong, which borders Shenzhen, keeps WGS-84.
+        assert_eq!(wgs84_to_gcj02(22.28, 114.16), (22.28, 114.16));
+        // The shift in Beijing is a few hundred metres.
+        let (lat, lon) = wgs84_to_gcj02(39.9, 116.4);
+        assert!((lat - 39.9).abs() < 0.005 && (lon - 116.4).abs() < 0.01);
+        assert!((lat - 39.9).abs() + (lon - 116.4).abs() > 0.002);
+    }
+
+    #[test]
+    fn follows_the_mainland_border() {
+        let mainland = [
+            (22.54, 114.06), // Shenzhen, across the river from Hong Kong
+            (22.49, 113.92), // Shekou
+            (22.27, 113.57), // Zhuhai, next to Macau
+            (22.12, 113.5),  // Hengqin
+            (24.48, 118.09), // Xiamen, facing Kinmen
+            (26.33, 119.88), // Huangqi, facing Matsu
+            (25.5, 119.79),  // Pingtan
+            (20.04, 110.34), // Haikou
+            (18.25, 109.5),  // Sanya
+            (21.55, 107.97), // Dongxing, on the Vietnamese border
+            (22.51, 103.96), // Hekou
+            (29.65, 91.1),   // Lhasa
+            (27.99, 85.98),  // Zhangmu, on the Nepalese border
+            (39.47, 75.99),  // Kashgar
+            (47.84, 88.13),  // Altay
+            (43.65, 111.98), // Erenhot, on the Mongolian border
+            (52.97, 122.53), // Mohe
+            (48.36, 134.29), // Fuyuan
+            (42.87, 130.36), // Hunchun
+            (37.5, 122.1),   // Weihai
+        ];
+        for (lat, lon) in mainland {
+            assert!(in_mainland_china(lat, lon), "{} {}", lat, lon);
+        }
+        let elsewhere = [
+            (22.28, 114.16), // Hong Kong Island
+            (22.39, 113.97), // Tuen Mun
+            (22.44, 114.03), // Yuen Long
+            (22.19, 113.54), // Macau
+            (22.16, 113.56), // Taipa
+            (25.03, 121.56), // Taipei
+            (22.63, 120.3),  // Kaohsiung
+            (23.57, 119.58), // Penghu
+            (24.43, 118.32), // Kinmen
+            (26.15, 119.93), // Matsu
+            (37.57, 126.98), // Seoul
+            (39.03, 125.75), // Pyongyang
+            (37.95, 124.7),  // Baengnyeong
+            (33.59, 130.4),  // Fukuoka
+            (21.03, 105.85), // Hanoi
+            (21.85, 106.76), // Lang Son
+            (47.9, 106.9),   // Ulaanbaatar
+            (44.89, 110.14), // Sainshand
+            (21.97, 96.08),  // Mandalay
+            (27.7, 85.3),    // Kathmandu
+            (27.47, 89.64),  // Thimphu
+            (34.16, 77.58),  // Leh
+            (28.6, 77.2),    // Delhi
+            (43.24, 76.9),   // Almaty
+            (43.1, 131.9),   // Vladivostok
+            (48.48, 135.07), // Khabarovsk
+        ];
+        for (lat, lon) in elsewhere {
+            assert!(!in_mainland_china(lat, lon), "{} {}", lat, lon);
+        }
+    }
+}
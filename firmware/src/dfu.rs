@@ -0,0 +1,98 @@
+//! Firmware update hand-off to the bootloader.
+//!
+//! New firmware used to need a UF2 drag-and-drop after a button press, or
+//! SWD. The DFU GATT service restarts the device into the Adafruit
+//! bootloader's UF2 drive on USB instead.
+//!
+//! # Design
+//!
+//! - Over BLE, images are staged into bank B by `slots` and swapped in by
+//!   the slot bootloader. The Adafruit bootloader's own BLE OTA DFU writes
+//!   its image at the start of the application area, where the slot
+//!   bootloader sits, so it is not offered; see `docs/firmware_ab_slots.md`.
+//! - A UF2 file carries its addresses, so the drive can replace bank A and
+//!   the slot bootloader as built, without a trial boot.
+//! - The bootloader does not check signatures, so only a bonded phone may
+//!   ask for the restart; without a bond the service refuses every write.
+//! - The bootloader reads its mode from GPREGRET, so the register is
+//!   cleared before the magic is written: the application's own boot flags
+//!   live there too.
+//! - The UF2 drive needs USB, without which it would wait for a host
+//!   forever.
+//! - The SD cache is flushed before the reset, as on power off.
+
+use cortex_m::peripheral::SCB;
+use embassy_executor::task;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+use embassy_time::Timer;
+use nrf_softdevice::{raw, RawError};
+
+use crate::display;
+use crate::storage;
+use crate::usb_connected;
+
+/// Adafruit bootloader: start the UF2 drive (`DFU_MAGIC_UF2_RESET`).
+const BOOTLOADER_UF2_MAGIC: u8 = 0x57;
+/// Time for the write response and the banner before the reset.
+const RESET_DELAY_MS: u64 = 500;
+
+#[derive(Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum DfuTarget {
+    /// UF2 drive over USB. `1`, the bootloader's BLE OTA DFU, is not
+    /// offered next to the slot bootloader.
+    Usb = 2,
+}
+
+impl DfuTarget {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            2 => Some(DfuTarget::Usb),
+            _ => None,
+        }
+    }
+}
+
+static REQUEST: Signal<CriticalSectionRawMutex, DfuTarget> = Signal::new();
+
+/// Ask `dfu_task` to restart into the bootloader.
+pub fn request(target: DfuTarget) {
+    REQUEST.signal(target);
+}
+
+#[task]
+pub async fn dfu_task() {
+    loop {
+        let target = REQUEST.wait().await;
+        if !target_ready(target) {
+            continue;
+        }
+        defmt::info!("DFU: restarting into the bootloader ({})", target);
+        display::show_banner("Firmware update");
+        if !storage::flush_sd_cache().await {
+            defmt::warn!("DFU: SD cache flush failed");
+        }
+        Timer::after_millis(RESET_DELAY_MS).await;
+        let magic = match target {
+            DfuTarget::Usb => BOOTLOADER_UF2_MAGIC,
+        };
+        let _ = unsafe { raw::sd_power_gpregret_clr(0, 0xFF) };
+        let result = RawError::convert(unsafe { raw::sd_power_gpregret_set(0, magic as u32) });
+        if let Err(err) = result {
+            defmt::warn!("DFU: set GPREGRET failed: {:?}", err);
+            continue;
+        }
+        SCB::sys_reset();
+    }
+}
+
+fn target_ready(target: DfuTarget) -> bool {
+    match target {
+        DfuTarget::Usb if !usb_connected() => {
+            defmt::warn!("DFU: UF2 mode refused without USB");
+            display::show_banner("Update: plug USB");
+            false
+        }
+        _ => true,
+    }
+}
@@ -0,0 +1,178 @@
+//! RAM headroom diagnostics.
+//!
+//! Embassy tasks are state machines stored in static task pools; they do not
+//! own stacks. Everything (`main`, all task polls, interrupt handlers) runs
+//! on the single MSP stack, so one stack watermark covers every task, and a
+//! task's own footprint shows up in the static RAM figure instead.
+//!
+//! With `flip-link` the stack sits at the bottom of RAM, from the start of
+//! the application RAM region up to `_stack_start`, with `.data`/`.bss` above
+//! it. `paint_stack` fills the unused part with a pattern at boot and
+//! `stack_peak_used` scans for the lowest overwritten word.
+//!
+//! CPU use is estimated per task loop instead of by instrumenting the
+//! executor: the hot loops hold a `busy` guard while they work, and the
+//! guards add up active time and wakeups per `TaskId` over one-minute
+//! windows. Time spent in awaits inside a guarded section (lock waits, short
+//! delays) counts as active, so the figures are an upper bound; resolution
+//! is one `Instant` tick (about 30 us).
+
+use core::cell::RefCell;
+use core::ptr::{addr_of, read_volatile, write_volatile};
+
+use embassy_sync::blocking_mutex::{raw::CriticalSectionRawMutex, Mutex as CsMutex};
+use embassy_time::Instant;
+
+/// Application RAM origin, must match `RAM` in `memory.x` (after the
+/// SoftDevice reservation).
+const RAM_START: usize = 0x2000_3000;
+const STACK_PAINT: u32 = 0xFEED_C0DE;
+/// Bytes below the current SP left unpainted for interrupt frames.
+const PAINT_GUARD_BYTES: usize = 1024;
+
+unsafe extern "C" {
+    static _stack_start: u32;
+    static __sdata: u32;
+    static __sheap: u32;
+}
+
+fn stack_top() -> usize {
+    addr_of!(_stack_start) as usize
+}
+
+/// Fill the unused stack with `STACK_PAINT`. Call once, early in `main`.
+pub fn paint_stack() {
+    let sp = cortex_m::register::msp::read() as usize;
+    let end = sp.saturating_sub(PAINT_GUARD_BYTES) & !0x3;
+    cortex_m::interrupt::free(|_| {
+        let mut addr = RAM_START;
+        while addr < end {
+            // SAFETY: [RAM_START, end) lies below the live stack frames and
+            // interrupts are masked, so nothing else uses this memory.
+            unsafe { write_volatile(addr as *mut u32, STACK_PAINT) };
+            addr += 4;
+        }
+    });
+}
+
+/// Total stack size in bytes.
+pub fn stack_size() -> u32 {
+    (stack_top() - RAM_START) as u32
+}
+
+/// Deepest stack usage observed since boot, in bytes.
+pub fn stack_peak_used() -> u32 {
+    let top = stack_top();
+    let mut addr = RAM_START;
+    while addr < top {
+        // SAFETY: reading a word-aligned address inside the RAM region.
+        if unsafe { read_volatile(addr as *const u32) } != STACK_PAINT {
+            break;
+        }
+        addr += 4;
+    }
+    (top - addr) as u32
+}
+
+/// Bytes of `.data` + `.bss` (includes all task pools and static buffers).
+pub fn static_ram_used() -> u32 {
+    (addr_of!(__sheap) as usize - addr_of!(__sdata) as usize) as u32
+}
+
+/// Instrumented task loops. Ids index the `GET_DIAGNOSTICS` activity table:
+/// never renumber, only append.
+#[repr(u8)]
+#[derive(Clone, Copy)]
+pub enum TaskId {
+    GpsRx = 0,
+    GpsState = 1,
+    Display = 2,
+    LogWriter = 3,
+    Accel = 4,
+    Bmp280 = 5,
+    Battery = 6,
+}
+
+pub const TASK_COUNT: usize = 7;
+const ACTIVITY_WINDOW_MS: u64 = 60_000;
+
+#[derive(Clone, Copy, Default)]
+pub struct TaskActivity {
+    pub busy_us: u32,
+    pub wakeups: u32,
+}
+
+struct ActivityWindows {
+    start_ms: u64,
+    current: [TaskActivity; TASK_COUNT],
+    last: [TaskActivity; TASK_COUNT],
+}
+
+impl ActivityWindows {
+    const fn new() -> Self {
+        const IDLE: TaskActivity = TaskActivity {
+            busy_us: 0,
+            wakeups: 0,
+        };
+        Self {
+            start_ms: 0,
+            current: [IDLE; TASK_COUNT],
+            last: [IDLE; TASK_COUNT],
+        }
+    }
+
+    fn roll(&mut self, now_ms: u64) {
+        let elapsed = now_ms.saturating_sub(self.start_ms);
+        if elapsed < ACTIVITY_WINDOW_MS {
+            return;
+        }
+        // A window skipped entirely (nothing ran for a minute) was idle.
+        self.last = if elapsed < 2 * ACTIVITY_WINDOW_MS {
+            self.current
+        } else {
+            [TaskActivity::default(); TASK_COUNT]
+        };
+        self.current = [TaskActivity::default(); TASK_COUNT];
+        self.start_ms = now_ms - elapsed % ACTIVITY_WINDOW_MS;
+    }
+}
+
+static ACTIVITY: CsMutex<CriticalSectionRawMutex, RefCell<ActivityWindows>> =
+    CsMutex::new(RefCell::new(ActivityWindows::new()));
+
+/// Counts one wakeup of `task` and its active time until dropped.
+pub struct Busy {
+    task: TaskId,
+    start: Instant,
+}
+
+impl Drop for Busy {
+    fn drop(&mut self) {
+        let now = Instant::now();
+        let busy_us = now.duration_since(self.start).as_micros() as u32;
+        ACTIVITY.lock(|windows| {
+            let mut windows = windows.borrow_mut();
+            windows.roll(now.as_millis());
+            let activity = &mut windows.current[self.task as usize];
+            activity.busy_us = activity.busy_us.saturating_add(busy_us);
+            activity.wakeups = activity.wakeups.saturating_add(1);
+        });
+    }
+}
+
+/// Hold while `task` works; drop before it waits for its next wakeup.
+pub fn busy(task: TaskId) -> Busy {
+    Busy {
+        task,
+        start: Instant::now(),
+    }
+}
+
+/// Activity per `TaskId` over the last complete one-minute window.
+pub fn last_minute_activity() -> [TaskActivity; TASK_COUNT] {
+    ACTIVITY.lock(|windows| {
+        let mut windows = windows.borrow_mut();
+        windows.roll(Instant::now().as_millis());
+        windows.last
+    })
+}
@@ -0,0 +1,76 @@
+//! Daily log browser behind the `Files` page.
+//!
+//! # Design
+//!
+//! - The list is read from the card when the page is entered and after a
+//!   delete, not on every frame: a listing walks the year and month
+//!   directories and competes with the logger for the SD arbiter.
+//! - The button task moves the selection and deletes; the display task
+//!   refreshes and renders. Both go through the state here.
+//! - Only the newest `storage::MAX_LISTED_LOGS` logs are listed, newest
+//!   first, so older days have to be removed over BLE or USB.
+//! - A delete goes through `storage::delete_day_log`, which refuses
+//!   today's open log and removes the day stats checkpoint with the log.
+
+use core::cell::RefCell;
+
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex as BlockingMutex;
+use heapless::Vec;
+
+use crate::storage::{self, DayLog, LogFileOutcome, MAX_LISTED_LOGS};
+
+pub(super) struct Listing {
+    pub logs: Vec<DayLog, MAX_LISTED_LOGS>,
+    pub selected: usize,
+}
+
+static LISTING: BlockingMutex<CriticalSectionRawMutex, RefCell<Listing>> =
+    BlockingMutex::new(RefCell::new(Listing {
+        logs: Vec::new(),
+        selected: 0,
+    }));
+
+/// Re-read the list, keeping the selection on the same row if it exists.
+pub async fn refresh() {
+    let logs = storage::list_day_logs().await;
+    LISTING.lock(|listing| {
+        let mut listing = listing.borrow_mut();
+        listing.selected = listing.selected.min(logs.len().saturating_sub(1));
+        listing.logs = logs;
+    });
+}
+
+/// Move the selection down one row. Returns false, and goes back to the
+/// top, when it was already on the last row.
+pub fn select_next() -> bool {
+    LISTING.lock(|listing| {
+        let mut listing = listing.borrow_mut();
+        if listing.selected + 1 < listing.logs.len() {
+            listing.selected += 1;
+            true
+        } else {
+            listing.selected = 0;
+            false
+        }
+    })
+}
+
+pub fn selected() -> Option<DayLog> {
+    LISTING.lock(|listing| {
+        let listing = listing.borrow();
+        listing.logs.get(listing.selected).copied()
+    })
+}
+
+/// Delete the selected log and re-read the list.
+pub async fn delete_selected() -> Option<LogFileOutcome> {
+    let log = selected()?;
+    let outcome = storage::delete_day_log(log).await;
+    refresh().await;
+    Some(outcome)
+}
+
+pub(super) fn with_listing<R>(f: impl FnOnce(&Listing) -> R) -> R {
+    LISTING.lock(|listing| f(&listing.borrow()))
+}
@@ -0,0 +1,2527 @@
+pub mod browser;
+mod panel;
+
+use core::cell::RefCell;
+use core::fmt::Write;
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use chrono::{Datelike, Timelike};
+use embassy_executor::task;
+use embassy_futures::select::{select3, Either3};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex as BlockingMutex;
+use embassy_sync::channel::Channel;
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Instant, Timer};
+use embedded_graphics::image::{Image, ImageRaw};
+use embedded_graphics::mono_font::MonoTextStyle;
+use embedded_graphics::pixelcolor::BinaryColor;
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::{Circle, Line, PrimitiveStyle, Rectangle};
+use embedded_graphics::text::{Alignment, Baseline, Text, TextStyleBuilder};
+use embedded_graphics::text::renderer::TextRenderer;
+use heapless::String;
+
+// Ferris logo bitmap: 64x42 pixels, 1-bit per pixel (MSB first)
+// Each row is 8 bytes (64 bits), 42 rows total = 336 bytes
+const FERRIS_WIDTH: u32 = 64;
+const FERRIS_HEIGHT: u32 = 42;
+const FERRIS_LOGO: [u8; 336] = [
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // Row 0
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // Row 1
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // Row 2
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // Row 3
+    0x00, 0x00, 0x00, 0x00, 0x80, 0x00, 0x00, 0x00, // Row 4
+    0x00, 0x00, 0x00, 0x19, 0xCC, 0x00, 0x00, 0x00, // Row 5
+    0x00, 0x00, 0x03, 0x3F, 0xDC, 0xC0, 0x00, 0x00, // Row 6
+    0x00, 0x00, 0x03, 0xFF, 0xFF, 0xE0, 0x00, 0x00, // Row 7
+    0x00, 0x00, 0x13, 0xFF, 0xFF, 0xE4, 0x00, 0x00, // Row 8
+    0x00, 0x00, 0x3F, 0xFF, 0xFF, 0xFE, 0x00, 0x00, // Row 9
+    0x00, 0x00, 0x3F, 0xFF, 0xFF, 0xFE, 0x00, 0x30, // Row 10
+    0x04, 0x01, 0xBF, 0xFF, 0xFF, 0xFC, 0xC0, 0xE2, // Row 11
+    0x06, 0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xC1, 0xE6, // Row 12
+    0x67, 0x81, 0xFF, 0xFF, 0xFF, 0xFF, 0xC3, 0xE7, // Row 13
+    0x77, 0x81, 0xFF, 0xFF, 0xFF, 0xFF, 0xC3, 0xEF, // Row 14
+    0xF7, 0xCF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFB, 0xEF, // Row 15
+    0xFF, 0xCF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFB, 0xFE, // Row 16
+    0xFF, 0xCF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFB, 0xFE, // Row 17
+    0x7F, 0xC7, 0xFF, 0xFF, 0xFF, 0xFF, 0xF3, 0xFC, // Row 18
+    0x3F, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xF8, // Row 19
+    0x1F, 0xBF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xF0, // Row 20
+    0x0F, 0xBF, 0xFF, 0xEF, 0xFF, 0xFF, 0xFF, 0xE0, // Row 21
+    0x03, 0xDF, 0xFF, 0xE7, 0xFB, 0xFF, 0xFF, 0xC0, // Row 22
+    0x03, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x80, // Row 23
+    0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x00, // Row 24
+    0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x80, // Row 25
+    0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xC0, // Row 26
+    0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xC0, // Row 27
+    0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xDB, 0xC0, // Row 28
+    0x00, 0xF7, 0x7F, 0xFF, 0xFF, 0xFF, 0x3F, 0x80, // Row 29
+    0x00, 0x7B, 0x0F, 0xFF, 0xFF, 0xF8, 0x37, 0x00, // Row 30
+    0x00, 0x3D, 0x80, 0x3F, 0xFF, 0x00, 0x67, 0x00, // Row 31
+    0x00, 0x1D, 0xC0, 0x00, 0x00, 0x00, 0x6E, 0x00, // Row 32
+    0x00, 0x0E, 0xC0, 0x00, 0x00, 0x00, 0x4C, 0x00, // Row 33
+    0x00, 0x07, 0x00, 0x00, 0x00, 0x00, 0x18, 0x00, // Row 34
+    0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x18, 0x00, // Row 35
+    0x00, 0x01, 0x80, 0x00, 0x00, 0x00, 0x10, 0x00, // Row 36
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // Row 37
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // Row 38
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // Row 39
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // Row 40
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // Row 41
+];
+
+const LOGO_DISPLAY_MS: u64 = 2000;
+
+// USB_ICON bitmap: 32x32 pixels, 1-bit per pixel (MSB first)
+// Each row is 4 bytes (32 bits), 32 rows total = 128 bytes
+const USB_ICON_WIDTH: u32 = 32;
+#[allow(dead_code)]
+const USB_ICON_HEIGHT: u32 = 32;
+const USB_ICON: [u8; 128] = [
+    0xFF, 0xFF, 0xFF, 0xFF, // Row 0
+    0xFF, 0xFF, 0xFF, 0xFF, // Row 1
+    0xFF, 0xFF, 0xFF, 0xFF, // Row 2
+    0xFF, 0xFF, 0xFF, 0xFF, // Row 3
+    0xFF, 0xFF, 0xFF, 0xFF, // Row 4
+    0xFF, 0xFF, 0xFF, 0xFF, // Row 5
+    0xFF, 0x80, 0x00, 0x0F, // Row 6
+    0xFE, 0x00, 0x00, 0x0F, // Row 7
+    0xF8, 0x00, 0x00, 0x1F, // Row 8
+    0xF0, 0x00, 0x00, 0x01, // Row 9
+    0xE0, 0x00, 0x00, 0x00, // Row 10
+    0xE0, 0x00, 0x60, 0x00, // Row 11
+    0xE3, 0x09, 0x99, 0xFC, // Row 12
+    0x83, 0x09, 0x81, 0x0C, // Row 13
+    0x03, 0x19, 0x81, 0x18, // Row 14
+    0x03, 0x18, 0x71, 0xF8, // Row 15
+    0x02, 0x18, 0x19, 0x18, // Row 16
+    0x03, 0x33, 0x1B, 0x18, // Row 17
+    0xC3, 0xF1, 0xF3, 0xF8, // Row 18
+    0xC0, 0x00, 0x00, 0x01, // Row 19
+    0xE0, 0x00, 0x00, 0x01, // Row 20
+    0xE0, 0x00, 0x00, 0x07, // Row 21
+    0xF0, 0x00, 0x00, 0x7F, // Row 22
+    0xF8, 0x00, 0x00, 0x7F, // Row 23
+    0xFF, 0x80, 0x00, 0xFF, // Row 24
+    0xFF, 0xFF, 0xFF, 0xFF, // Row 25
+    0xFF, 0xFF, 0xFF, 0xFF, // Row 26
+    0xFF, 0xFF, 0xFF, 0xFF, // Row 27
+    0xFF, 0xFF, 0xFF, 0xFF, // Row 28
+    0xFF, 0xFF, 0xFF, 0xFF, // Row 29
+    0xFF, 0xFF, 0xFF, 0xFF, // Row 30
+    0xFF, 0xFF, 0xFF, 0xFF, // Row 31
+];
+
+use crate::battery;
+use crate::bmp280;
+use crate::datum;
+use crate::diag::{self, TaskId};
+use crate::fuel_gauge::ChargeState;
+use crate::geo;
+use crate::gps;
+use crate::settings;
+use crate::sky_view::Constellation;
+use crate::splash;
+use crate::storage;
+use crate::system_info::{GpsState, SystemInfo, SYSTEM_INFO};
+use crate::timezone::TzCache;
+use crate::track_stats::{self, DayStats};
+use crate::trend::{self, Series, TrendKind};
+use crate::usb_power;
+use panel::{layout, Panel, SharedI2c};
+
+const DISPLAY_UPDATE_INTERVAL_MS: u64 = 100;
+/// Refresh interval once `IDLE_AFTER_FRAMES` frames in a row were unchanged.
+const DISPLAY_IDLE_INTERVAL_MS: u64 = 1000;
+const IDLE_AFTER_FRAMES: u8 = 10;
+
+/// New values in `SYSTEM_INFO`; wakes an idle display at once.
+static INFO_CHANGED: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+/// Tell the display `SYSTEM_INFO` changed, so an idle page redraws now
+/// rather than at its next 1 Hz tick.
+pub fn notify_info_changed() {
+    INFO_CHANGED.signal(());
+}
+const DEGRADED_BLINK_MS: u64 = 500;
+const TREND_MIN_ALT_SPAN_M: f32 = 10.0;
+/// Smallest voltage span the battery graph is drawn over.
+const BATTERY_MIN_SPAN_MV: f32 = 100.0;
+/// Signal that fills a bar on the Satellites page, dB-Hz.
+const SKY_FULL_SNR: u8 = 50;
+/// Banner length: one line of the 128x64 layout, longer text is cut off.
+const BANNER_CHARS: usize = 21;
+pub const BANNER_MS: u64 = 5_000;
+
+type Display = Panel;
+
+/// Page ids are shared with the BLE display-control characteristic.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DisplayPage {
+    Main = 0,
+    FindMy = 1,
+    GoogleFmdn = 2,
+    DeviceInfo = 3,
+    #[cfg(feature = "power-monitor")]
+    Power = 4,
+    Trend = 5,
+    Steps = 6,
+    TrackStats = 7,
+    Files = 8,
+    Compass = 9,
+    Satellites = 10,
+    Battery = 11,
+}
+
+impl DisplayPage {
+    fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(Self::Main),
+            1 => Some(Self::FindMy),
+            2 => Some(Self::GoogleFmdn),
+            3 => Some(Self::DeviceInfo),
+            #[cfg(feature = "power-monitor")]
+            4 => Some(Self::Power),
+            5 => Some(Self::Trend),
+            6 => Some(Self::Steps),
+            7 => Some(Self::TrackStats),
+            8 => Some(Self::Files),
+            9 => Some(Self::Compass),
+            10 => Some(Self::Satellites),
+            11 => Some(Self::Battery),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct DisplayTimeAnchor {
+    unix_ts: u64,
+    monotonic_ms: u64,
+}
+
+#[derive(Clone, Copy)]
+struct FindMyDisplayTime {
+    unix_ts: Option<u64>,
+    estimated: bool,
+}
+
+#[derive(Clone, Copy, Debug)]
+#[allow(dead_code)]
+pub enum DisplayCommand {
+    Toggle,
+    TurnOn,
+    TurnOff,
+    ResetTimeout,
+    /// Turn on (if needed) and jump straight to a page.
+    ShowPage(DisplayPage),
+    UsbMode,
+    /// USB mode could not be entered; back to the normal pages.
+    ExitUsbMode,
+    SetFindMyAddress([u8; 6]),
+    ClearFindMyAddress,
+    SetFmdnAddress([u8; 6]),
+    ClearFmdnAddress,
+}
+
+static DISPLAY_COMMANDS: Channel<CriticalSectionRawMutex, DisplayCommand, 8> = Channel::new();
+
+const STATE_ON: u8 = 0x80;
+/// Last published `[on][page]` state, packed as `STATE_ON | page`.
+static DISPLAY_STATE: AtomicU8 = AtomicU8::new(STATE_ON);
+static DISPLAY_STATE_CHANGED: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+pub fn send_command(cmd: DisplayCommand) {
+    let _ = DISPLAY_COMMANDS.try_send(cmd);
+}
+
+struct Banner {
+    text: String<BANNER_CHARS>,
+    until: Instant,
+}
+
+static BANNER: BlockingMutex<CriticalSectionRawMutex, RefCell<Option<Banner>>> =
+    BlockingMutex::new(RefCell::new(None));
+
+/// Show `text` inverted over the bottom line of whatever page is up, for
+/// `BANNER_MS`. Turns the display on if it is off.
+pub fn show_banner(text: &str) {
+    let mut banner = String::new();
+    for ch in text.chars() {
+        if banner.push(ch).is_err() {
+            break;
+        }
+    }
+    BANNER.lock(|slot| {
+        *slot.borrow_mut() = Some(Banner {
+            text: banner,
+            until: Instant::now() + Duration::from_millis(BANNER_MS),
+        });
+    });
+    if remote_state()[0] == 0 {
+        send_command(DisplayCommand::TurnOn);
+    } else {
+        send_command(DisplayCommand::ResetTimeout);
+    }
+}
+
+/// Current value of the BLE display-control characteristic:
+/// `[power: u8][page: u8]`, power 1 = on.
+pub fn remote_state() -> [u8; 2] {
+    let state = DISPLAY_STATE.load(Ordering::Acquire);
+    [u8::from(state & STATE_ON != 0), state & !STATE_ON]
+}
+
+/// Wait until the display turns on/off or changes page.
+pub async fn wait_state_change() {
+    DISPLAY_STATE_CHANGED.wait().await;
+}
+
+/// Apply a write to the BLE display-control characteristic. Power 0 turns
+/// the display off; power 1 turns it on at the given page. Returns false for
+/// a malformed value or an unknown page.
+pub fn apply_remote_state(raw: &[u8]) -> bool {
+    match *raw {
+        [0, _] => send_command(DisplayCommand::TurnOff),
+        [1, page] => match DisplayPage::from_id(page) {
+            Some(page) => send_command(DisplayCommand::ShowPage(page)),
+            None => return false,
+        },
+        _ => return false,
+    }
+    true
+}
+
+fn publish_state(display_on: bool, page: DisplayPage) {
+    let power = if display_on { STATE_ON } else { 0 };
+    let state = power | page as u8;
+    if DISPLAY_STATE.swap(state, Ordering::AcqRel) != state {
+        DISPLAY_STATE_CHANGED.signal(());
+    }
+}
+
+#[task]
+pub async fn display_task(i2c: SharedI2c) {
+    let mut display = Panel::new(i2c);
+
+    if !display.init() {
+        defmt::warn!("Display init failed");
+        return;
+    }
+
+    // Show startup logo
+    let _ = display.set_display_on(true);
+    let splash = settings::stored(settings::DISPLAY_SPLASH);
+    if splash != 0 {
+        if splash != 2 || !render_user_logo(&mut display).await {
+            render_logo(&mut display);
+        }
+        Timer::after_millis(LOGO_DISPLAY_MS).await;
+    }
+
+    let mut display_on = true;
+    let mut last_activity = Instant::now();
+    let mut usb_mode = false;
+    let mut findmy_addr: Option<[u8; 6]> = None;
+    let mut fmdn_addr: Option<[u8; 6]> = None;
+    let mut current_page = DisplayPage::Main;
+    let mut findmy_time_anchor: Option<DisplayTimeAnchor> = None;
+    let mut tz_cache = TzCache::new();
+
+    let text_style = MonoTextStyle::new(layout().font, BinaryColor::On);
+    let text_settings = TextStyleBuilder::new().baseline(Baseline::Top).build();
+
+    // Render first frame after logo
+    let mut info = { *SYSTEM_INFO.lock().await };
+    info.keep_alive_remaining_s = gps::get_keep_alive_remaining_s().await;
+    render_current_page(
+        &mut display,
+        &text_style,
+        text_settings,
+        &info,
+        &mut tz_cache,
+        current_page,
+        findmy_addr,
+        fmdn_addr,
+        &mut findmy_time_anchor,
+    )
+    .await;
+
+    // Frames in a row that matched the one on the panel. Redraws slow to
+    // `DISPLAY_IDLE_INTERVAL_MS` past `IDLE_AFTER_FRAMES`; the clock and
+    // banners still change within a second.
+    let mut unchanged_frames: u8 = 0;
+
+    loop {
+        publish_state(display_on, current_page);
+        display.set_flipped(settings::stored(settings::DISPLAY_FLIP) != 0);
+        if display_on {
+            let interval_ms = if unchanged_frames >= IDLE_AFTER_FRAMES {
+                DISPLAY_IDLE_INTERVAL_MS
+            } else {
+                DISPLAY_UPDATE_INTERVAL_MS
+            };
+            match select3(
+                DISPLAY_COMMANDS.receive(),
+                Timer::after_millis(interval_ms),
+                INFO_CHANGED.wait(),
+            )
+            .await
+            {
+                Either3::First(cmd) => {
+                    let _busy = diag::busy(TaskId::Display);
+                    unchanged_frames = 0;
+                    handle_command(
+                        cmd,
+                        &mut display,
+                        &mut display_on,
+                        &mut last_activity,
+                        &mut usb_mode,
+                        &mut findmy_addr,
+                        &mut fmdn_addr,
+                        &mut current_page,
+                        &mut findmy_time_anchor,
+                        &mut tz_cache,
+                        &text_style,
+                        text_settings,
+                    )
+                    .await;
+                }
+                Either3::Second(()) | Either3::Third(()) => {
+                    let _busy = diag::busy(TaskId::Display);
+                    let now_ms = Instant::now().as_millis();
+                    let timeout_s =
+                        usb_power::display_timeout_s(settings::stored(settings::DISPLAY_TIMEOUT_S));
+                    let timeout_ms = timeout_s as u64 * 1000;
+                    if now_ms.wrapping_sub(last_activity.as_millis()) > timeout_ms {
+                        handle_command(
+                            DisplayCommand::TurnOff,
+                            &mut display,
+                            &mut display_on,
+                            &mut last_activity,
+                            &mut usb_mode,
+                            &mut findmy_addr,
+                            &mut fmdn_addr,
+                            &mut current_page,
+                            &mut findmy_time_anchor,
+                            &mut tz_cache,
+                            &text_style,
+                            text_settings,
+                        )
+                        .await;
+                        continue;
+                    }
+                    if usb_mode {
+                        render_usb_mode(&mut display, &text_style, text_settings);
+                    } else {
+                        let mut info = { *SYSTEM_INFO.lock().await };
+                        info.keep_alive_remaining_s = gps::get_keep_alive_remaining_s().await;
+                        render_current_page(
+                            &mut display,
+                            &text_style,
+                            text_settings,
+                            &info,
+                            &mut tz_cache,
+                            current_page,
+                            findmy_addr,
+                            fmdn_addr,
+                            &mut findmy_time_anchor,
+                        )
+                        .await;
+                    }
+                    unchanged_frames = if panel::last_flush_changed() {
+                        0
+                    } else {
+                        unchanged_frames.saturating_add(1)
+                    };
+                }
+            }
+        } else {
+            let cmd = DISPLAY_COMMANDS.receive().await;
+            let _busy = diag::busy(TaskId::Display);
+            unchanged_frames = 0;
+            handle_command(
+                cmd,
+                &mut display,
+                &mut display_on,
+                &mut last_activity,
+                &mut usb_mode,
+                &mut findmy_addr,
+                &mut fmdn_addr,
+                &mut current_page,
+                &mut findmy_time_anchor,
+                &mut tz_cache,
+                &text_style,
+                text_settings,
+            )
+            .await;
+        }
+
+        while let Ok(cmd) = DISPLAY_COMMANDS.try_receive() {
+            handle_command(
+                cmd,
+                &mut display,
+                &mut display_on,
+                &mut last_activity,
+                &mut usb_mode,
+                &mut findmy_addr,
+                &mut fmdn_addr,
+                &mut current_page,
+                &mut findmy_time_anchor,
+                &mut tz_cache,
+                &text_style,
+                text_settings,
+            )
+            .await;
+        }
+    }
+}
+
+async fn handle_command(
+    cmd: DisplayCommand,
+    display: &mut Display,
+    display_on: &mut bool,
+    last_activity: &mut Instant,
+    usb_mode: &mut bool,
+    findmy_addr: &mut Option<[u8; 6]>,
+    fmdn_addr: &mut Option<[u8; 6]>,
+    current_page: &mut DisplayPage,
+    findmy_time_anchor: &mut Option<DisplayTimeAnchor>,
+    tz_cache: &mut TzCache,
+    text_style: &MonoTextStyle<'_, BinaryColor>,
+    text_settings: embedded_graphics::text::TextStyle,
+) {
+    match cmd {
+        DisplayCommand::Toggle => {
+            if !*display_on {
+                *current_page = DisplayPage::Main;
+                turn_display_on(display, display_on, last_activity);
+                if *usb_mode {
+                    render_usb_mode(display, text_style, text_settings);
+                } else {
+                    let mut info = *SYSTEM_INFO.lock().await;
+                    info.keep_alive_remaining_s = gps::get_keep_alive_remaining_s().await;
+                    render_current_page(
+                        display,
+                        text_style,
+                        text_settings,
+                        &info,
+                        tz_cache,
+                        *current_page,
+                        *findmy_addr,
+                        *fmdn_addr,
+                        findmy_time_anchor,
+                    )
+                    .await;
+                }
+                return;
+            }
+
+            if *usb_mode {
+                *current_page = DisplayPage::Main;
+                turn_display_off(display, display_on);
+                return;
+            }
+
+            match *current_page {
+                DisplayPage::Main => {
+                    *current_page = DisplayPage::FindMy;
+                    let mut info = *SYSTEM_INFO.lock().await;
+                    info.keep_alive_remaining_s = gps::get_keep_alive_remaining_s().await;
+                    render_current_page(
+                        display,
+                        text_style,
+                        text_settings,
+                        &info,
+                        tz_cache,
+                        *current_page,
+                        *findmy_addr,
+                        *fmdn_addr,
+                        findmy_time_anchor,
+                    )
+                    .await;
+                    *last_activity = Instant::now();
+                }
+                DisplayPage::FindMy => {
+                    *current_page = DisplayPage::GoogleFmdn;
+                    let mut info = *SYSTEM_INFO.lock().await;
+                    info.keep_alive_remaining_s = gps::get_keep_alive_remaining_s().await;
+                    render_current_page(
+                        display,
+                        text_style,
+                        text_settings,
+                        &info,
+                        tz_cache,
+                        *current_page,
+                        *findmy_addr,
+                        *fmdn_addr,
+                        findmy_time_anchor,
+                    )
+                    .await;
+                    *last_activity = Instant::now();
+                }
+                DisplayPage::GoogleFmdn => {
+                    *current_page = DisplayPage::DeviceInfo;
+                    let info = *SYSTEM_INFO.lock().await;
+                    render_current_page(
+                        display,
+                        text_style,
+                        text_settings,
+                        &info,
+                        tz_cache,
+                        *current_page,
+                        *findmy_addr,
+                        *fmdn_addr,
+                        findmy_time_anchor,
+                    )
+                    .await;
+                    *last_activity = Instant::now();
+                }
+                #[cfg(feature = "power-monitor")]
+                DisplayPage::DeviceInfo => {
+                    *current_page = DisplayPage::Power;
+                    let info = *SYSTEM_INFO.lock().await;
+                    render_current_page(
+                        display,
+                        text_style,
+                        text_settings,
+                        &info,
+                        tz_cache,
+                        *current_page,
+                        *findmy_addr,
+                        *fmdn_addr,
+                        findmy_time_anchor,
+                    )
+                    .await;
+                    *last_activity = Instant::now();
+                }
+                #[cfg(not(feature = "power-monitor"))]
+                DisplayPage::DeviceInfo => {
+                    *current_page = DisplayPage::Trend;
+                    let info = *SYSTEM_INFO.lock().await;
+                    render_current_page(
+                        display,
+                        text_style,
+                        text_settings,
+                        &info,
+                        tz_cache,
+                        *current_page,
+                        *findmy_addr,
+                        *fmdn_addr,
+                        findmy_time_anchor,
+                    )
+                    .await;
+                    *last_activity = Instant::now();
+                }
+                #[cfg(feature = "power-monitor")]
+                DisplayPage::Power => {
+                    *current_page = DisplayPage::Trend;
+                    let info = *SYSTEM_INFO.lock().await;
+                    render_current_page(
+                        display,
+                        text_style,
+                        text_settings,
+                        &info,
+                        tz_cache,
+                        *current_page,
+                        *findmy_addr,
+                        *fmdn_addr,
+                        findmy_time_anchor,
+                    )
+                    .await;
+                    *last_activity = Instant::now();
+                }
+                DisplayPage::Trend => {
+                    *current_page = DisplayPage::Steps;
+                    let info = *SYSTEM_INFO.lock().await;
+                    render_current_page(
+                        display,
+                        text_style,
+                        text_settings,
+                        &info,
+                        tz_cache,
+                        *current_page,
+                        *findmy_addr,
+                        *fmdn_addr,
+                        findmy_time_anchor,
+                    )
+                    .await;
+                    *last_activity = Instant::now();
+                }
+                DisplayPage::Steps => {
+                    *current_page = DisplayPage::TrackStats;
+                    let info = *SYSTEM_INFO.lock().await;
+                    render_current_page(
+                        display,
+                        text_style,
+                        text_settings,
+                        &info,
+                        tz_cache,
+                        *current_page,
+                        *findmy_addr,
+                        *fmdn_addr,
+                        findmy_time_anchor,
+                    )
+                    .await;
+                    *last_activity = Instant::now();
+                }
+                DisplayPage::TrackStats => {
+                    *current_page = DisplayPage::Files;
+                    browser::refresh().await;
+                    let info = *SYSTEM_INFO.lock().await;
+                    render_current_page(
+                        display,
+                        text_style,
+                        text_settings,
+                        &info,
+                        tz_cache,
+                        *current_page,
+                        *findmy_addr,
+                        *fmdn_addr,
+                        findmy_time_anchor,
+                    )
+                    .await;
+                    *last_activity = Instant::now();
+                }
+                DisplayPage::Files => {
+                    *current_page = DisplayPage::Compass;
+                    let info = *SYSTEM_INFO.lock().await;
+                    render_current_page(
+                        display,
+                        text_style,
+                        text_settings,
+                        &info,
+                        tz_cache,
+                        *current_page,
+                        *findmy_addr,
+                        *fmdn_addr,
+                        findmy_time_anchor,
+                    )
+                    .await;
+                    *last_activity = Instant::now();
+                }
+                DisplayPage::Compass => {
+                    *current_page = DisplayPage::Satellites;
+                    let info = *SYSTEM_INFO.lock().await;
+                    render_current_page(
+                        display,
+                        text_style,
+                        text_settings,
+                        &info,
+                        tz_cache,
+                        *current_page,
+                        *findmy_addr,
+                        *fmdn_addr,
+                        findmy_time_anchor,
+                    )
+                    .await;
+                    *last_activity = Instant::now();
+                }
+                DisplayPage::Satellites => {
+                    *current_page = DisplayPage::Battery;
+                    let info = *SYSTEM_INFO.lock().await;
+                    render_current_page(
+                        display,
+                        text_style,
+                        text_settings,
+                        &info,
+                        tz_cache,
+                        *current_page,
+                        *findmy_addr,
+                        *fmdn_addr,
+                        findmy_time_anchor,
+                    )
+                    .await;
+                    *last_activity = Instant::now();
+                }
+                DisplayPage::Battery => {
+                    *current_page = DisplayPage::Main;
+                    turn_display_off(display, display_on);
+                }
+            }
+        }
+        DisplayCommand::TurnOn => {
+            *current_page = DisplayPage::Main;
+            turn_display_on(display, display_on, last_activity);
+            if *usb_mode {
+                render_usb_mode(display, text_style, text_settings);
+            } else {
+                let mut info = *SYSTEM_INFO.lock().await;
+                info.keep_alive_remaining_s = gps::get_keep_alive_remaining_s().await;
+                render_current_page(
+                    display,
+                    text_style,
+                    text_settings,
+                    &info,
+                    tz_cache,
+                    *current_page,
+                    *findmy_addr,
+                    *fmdn_addr,
+                    findmy_time_anchor,
+                )
+                .await;
+            }
+        }
+        DisplayCommand::TurnOff => {
+            *current_page = DisplayPage::Main;
+            turn_display_off(display, display_on);
+        }
+        DisplayCommand::ResetTimeout => {
+            *last_activity = Instant::now();
+        }
+        DisplayCommand::ShowPage(page) => {
+            turn_display_on(display, display_on, last_activity);
+            if *usb_mode {
+                render_usb_mode(display, text_style, text_settings);
+            } else {
+                *current_page = page;
+                if page == DisplayPage::Files {
+                    browser::refresh().await;
+                }
+                let mut info = *SYSTEM_INFO.lock().await;
+                info.keep_alive_remaining_s = gps::get_keep_alive_remaining_s().await;
+                render_current_page(
+                    display,
+                    text_style,
+                    text_settings,
+                    &info,
+                    tz_cache,
+                    *current_page,
+                    *findmy_addr,
+                    *fmdn_addr,
+                    findmy_time_anchor,
+                )
+                .await;
+            }
+        }
+        DisplayCommand::UsbMode => {
+            *usb_mode = true;
+            turn_display_on(display, display_on, last_activity);
+            render_usb_mode(display, text_style, text_settings);
+        }
+        DisplayCommand::ExitUsbMode => {
+            *usb_mode = false;
+            *current_page = DisplayPage::Main;
+            turn_display_on(display, display_on, last_activity);
+            let mut info = *SYSTEM_INFO.lock().await;
+            info.keep_alive_remaining_s = gps::get_keep_alive_remaining_s().await;
+            render_current_page(
+                display,
+                text_style,
+                text_settings,
+                &info,
+                tz_cache,
+                *current_page,
+                *findmy_addr,
+                *fmdn_addr,
+                findmy_time_anchor,
+            )
+            .await;
+        }
+        DisplayCommand::SetFindMyAddress(addr) => {
+            *findmy_addr = Some(addr);
+            if *display_on && !*usb_mode && *current_page == DisplayPage::FindMy {
+                let mut info = *SYSTEM_INFO.lock().await;
+                info.keep_alive_remaining_s = gps::get_keep_alive_remaining_s().await;
+                render_current_page(
+                    display,
+                    text_style,
+                    text_settings,
+                    &info,
+                    tz_cache,
+                    *current_page,
+                    *findmy_addr,
+                    *fmdn_addr,
+                    findmy_time_anchor,
+                )
+                .await;
+            }
+        }
+        DisplayCommand::ClearFindMyAddress => {
+            *findmy_addr = None;
+            if *display_on && !*usb_mode && *current_page == DisplayPage::FindMy {
+                let mut info = *SYSTEM_INFO.lock().await;
+                info.keep_alive_remaining_s = gps::get_keep_alive_remaining_s().await;
+                render_current_page(
+                    display,
+                    text_style,
+                    text_settings,
+                    &info,
+                    tz_cache,
+                    *current_page,
+                    *findmy_addr,
+                    *fmdn_addr,
+                    findmy_time_anchor,
+                )
+                .await;
+            }
+        }
+        DisplayCommand::SetFmdnAddress(addr) => {
+            *fmdn_addr = Some(addr);
+            if *display_on && !*usb_mode && *current_page == DisplayPage::GoogleFmdn {
+                let mut info = *SYSTEM_INFO.lock().await;
+                info.keep_alive_remaining_s = gps::get_keep_alive_remaining_s().await;
+                render_current_page(
+                    display,
+                    text_style,
+                    text_settings,
+                    &info,
+                    tz_cache,
+                    *current_page,
+                    *findmy_addr,
+                    *fmdn_addr,
+                    findmy_time_anchor,
+                )
+                .await;
+            }
+        }
+        DisplayCommand::ClearFmdnAddress => {
+            *fmdn_addr = None;
+            if *display_on && !*usb_mode && *current_page == DisplayPage::GoogleFmdn {
+                let mut info = *SYSTEM_INFO.lock().await;
+                info.keep_alive_remaining_s = gps::get_keep_alive_remaining_s().await;
+                render_current_page(
+                    display,
+                    text_style,
+                    text_settings,
+                    &info,
+                    tz_cache,
+                    *current_page,
+                    *findmy_addr,
+                    *fmdn_addr,
+                    findmy_time_anchor,
+                )
+                .await;
+            }
+        }
+    }
+}
+
+fn turn_display_on(
+    display: &mut Display,
+    display_on: &mut bool,
+    last_activity: &mut Instant,
+) {
+    if *display_on {
+        *last_activity = Instant::now();
+        return;
+    }
+    let _ = display.set_display_on(true);
+    *display_on = true;
+    *last_activity = Instant::now();
+}
+
+fn turn_display_off(display: &mut Display, display_on: &mut bool) {
+    if !*display_on {
+        return;
+    }
+    let _ = display.clear(BinaryColor::Off);
+    let _ = display.flush();
+    let _ = display.set_display_on(false);
+    *display_on = false;
+}
+
+fn render_logo(display: &mut Display) {
+    let _ = display.clear(BinaryColor::Off);
+
+    // Create raw image from bitmap data (MSB first format)
+    let raw_image: ImageRaw<BinaryColor> = ImageRaw::new(&FERRIS_LOGO, FERRIS_WIDTH);
+    let layout = layout();
+
+    if layout.compact() {
+        // No room for the captions: the logo alone, centered
+        let y = (layout.height - FERRIS_HEIGHT as i32) / 2;
+        let _ = Image::new(&raw_image, Point::new(0, y)).draw(display);
+        let _ = display.flush();
+        return;
+    }
+
+    let text_style = MonoTextStyle::new(layout.font, BinaryColor::On);
+    let text_settings = TextStyleBuilder::new().baseline(Baseline::Top).build();
+    let char_width = layout.font.character_size.width as i32;
+
+    // Top text: "MGT GPS Tracker"
+    let title = "MGT GPS Tracker";
+    let title_width = title.len() as i32 * char_width;
+    let title_x = (layout.width - title_width) / 2;
+    Text::with_text_style(title, Point::new(title_x, 0), text_style, text_settings)
+        .draw(display)
+        .ok();
+
+    // Center the logo vertically (offset down a bit for title)
+    let x = (layout.width - FERRIS_WIDTH as i32) / 2;
+    let y = 10; // Start below title
+
+    let image = Image::new(&raw_image, Point::new(x, y));
+    let _ = image.draw(display);
+
+    // Bottom text: "Powered by Rust"
+    let bottom = "Powered by Rust";
+    let bottom_width = bottom.len() as i32 * char_width;
+    let bottom_x = (layout.width - bottom_width) / 2;
+    Text::with_text_style(bottom, Point::new(bottom_x, 55), text_style, text_settings)
+        .draw(display)
+        .ok();
+
+    let _ = display.flush();
+}
+
+/// Draw `/SPLASH.PBM` centered. False if it is missing, malformed or
+/// larger than the panel.
+async fn render_user_logo(display: &mut Display) -> bool {
+    let mut file = [0u8; splash::MAX_FILE_LEN];
+    let Some(len) = storage::read_splash(&mut file).await else {
+        return false;
+    };
+    let layout = layout();
+    match splash::parse_pbm(&file[..len]) {
+        Some(bitmap)
+            if bitmap.width as i32 <= layout.width && bitmap.height as i32 <= layout.height =>
+        {
+            let _ = display.clear(BinaryColor::Off);
+            let raw_image: ImageRaw<BinaryColor> = ImageRaw::new(bitmap.data, bitmap.width);
+            let x = (layout.width - bitmap.width as i32) / 2;
+            let y = (layout.height - bitmap.height as i32) / 2;
+            let _ = Image::new(&raw_image, Point::new(x, y)).draw(display);
+            let _ = display.flush();
+            true
+        }
+        _ => {
+            defmt::warn!("Display: SPLASH.PBM unusable, showing the default logo");
+            false
+        }
+    }
+}
+
+async fn render_current_page(
+    display: &mut Display,
+    text_style: &MonoTextStyle<'_, BinaryColor>,
+    text_settings: embedded_graphics::text::TextStyle,
+    info: &SystemInfo,
+    tz_cache: &mut TzCache,
+    page: DisplayPage,
+    findmy_addr: Option<[u8; 6]>,
+    fmdn_addr: Option<[u8; 6]>,
+    findmy_time_anchor: &mut Option<DisplayTimeAnchor>,
+) {
+    match page {
+        DisplayPage::Main => {
+            let info = &with_phone_time(info);
+            let nav_line = nav_target_text(info).await;
+            let points_today = track_stats::current().await.points;
+            render_main_page(
+                display,
+                text_style,
+                text_settings,
+                info,
+                tz_cache,
+                nav_line,
+                points_today,
+            )
+        }
+        DisplayPage::FindMy => {
+            let findmy_time = resolve_findmy_display_time(info, findmy_time_anchor);
+            render_findmy_page(display, text_style, text_settings, info, findmy_addr, findmy_time)
+        }
+        DisplayPage::GoogleFmdn => {
+            let info = &with_phone_time(info);
+            render_fmdn_page(display, text_style, text_settings, info, fmdn_addr)
+        }
+        DisplayPage::DeviceInfo => {
+            let agnss = gps::agnss_freshness().await;
+            render_device_info_page(display, text_style, text_settings, info, agnss)
+        }
+        #[cfg(feature = "power-monitor")]
+        DisplayPage::Power => {
+            let data = *crate::power_monitor::POWER_DATA.lock().await;
+            render_power_page(display, text_style, text_settings, &data)
+        }
+        DisplayPage::Trend => {
+            let speed = trend::series(TrendKind::Speed).await;
+            let altitude = trend::series(TrendKind::Altitude).await;
+            let pressure = bmp280::pressure_change_3h_hpa().await;
+            render_trend_page(
+                display,
+                text_style,
+                text_settings,
+                &speed,
+                &altitude,
+                pressure,
+            )
+        }
+        DisplayPage::Steps => render_steps_page(display, text_style, text_settings, info),
+        DisplayPage::TrackStats => {
+            let stats = track_stats::current().await;
+            render_track_stats_page(display, text_style, text_settings, &stats)
+        }
+        DisplayPage::Files => render_files_page(display, text_style, text_settings),
+        DisplayPage::Compass => {
+            let target = compass_target(info).await;
+            render_compass_page(display, text_style, info, target.as_ref())
+        }
+        DisplayPage::Satellites => render_satellites_page(display, text_style, text_settings, info),
+        DisplayPage::Battery => {
+            let history = battery::history_series().await;
+            render_battery_page(display, text_style, text_settings, info, &history)
+        }
+    }
+    draw_banner(display, text_settings);
+}
+
+/// Overlay the active banner, if any, on the frame just rendered.
+fn draw_banner(display: &mut Display, text_settings: embedded_graphics::text::TextStyle) {
+    let text = BANNER.lock(|slot| {
+        let mut slot = slot.borrow_mut();
+        match slot.as_ref() {
+            Some(banner) if Instant::now() < banner.until => Some(banner.text.clone()),
+            Some(_) => {
+                *slot = None;
+                None
+            }
+            None => None,
+        }
+    });
+    let Some(text) = text else {
+        return;
+    };
+    let layout = layout();
+    let top = layout.height - layout.line_height;
+    let size = Size::new(layout.width as u32, layout.line_height as u32);
+    Rectangle::new(Point::new(0, top), size)
+        .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+        .draw(display)
+        .ok();
+    let text_width = text.len() as i32 * layout.font.character_size.width as i32;
+    let x = ((layout.width - text_width) / 2).max(0);
+    let style = MonoTextStyle::new(layout.font, BinaryColor::Off);
+    Text::with_text_style(&text, Point::new(x, top), style, text_settings)
+        .draw(display)
+        .ok();
+    let _ = display.flush();
+}
+
+/// Lines the main page fills without a navigation target.
+const MAIN_PAGE_LINES: i32 = 7;
+
+/// Favorite or nearest waypoint for the main page.
+#[cfg_attr(not(feature = "nav"), allow(dead_code))]
+struct NavLine {
+    /// `Name 2.3 km NE`, the name cut to fit the panel width.
+    full: String<32>,
+    /// `2.3 km NE`.
+    short: String<16>,
+}
+
+fn render_main_page(
+    display: &mut Display,
+    text_style: &MonoTextStyle<'_, BinaryColor>,
+    text_settings: embedded_graphics::text::TextStyle,
+    info: &SystemInfo,
+    tz_cache: &mut TzCache,
+    nav_line: Option<NavLine>,
+    points_today: u32,
+) {
+    let _ = display.clear(BinaryColor::Off);
+
+    // Line 0: Speed (left) + Battery (right)
+    let mut speed_str = String::<32>::new();
+    speed_str.push_str("Spd: ").ok();
+    if info.speed >= 0.0 {
+        let _ = write!(speed_str, "{:.1}", info.speed);
+    } else {
+        speed_str.push_str("N/A").ok();
+    }
+    if info.is_stationary {
+        speed_str.push_str(" S").ok();
+    }
+    if info.keep_alive_remaining_s > 0 {
+        speed_str.push_str(" K").ok();
+    }
+    if !crate::recording::is_recording() {
+        // Recording stopped (auto-start off or stopped by user).
+        speed_str.push_str(" P").ok();
+    }
+    Text::with_text_style(&speed_str, Point::new(0, 0), *text_style, text_settings)
+        .draw(display)
+        .ok();
+
+    // Battery on right side of line 0
+    let mut battery = String::<16>::new();
+    if info.battery_voltage >= 0.0 {
+        let _ = write!(battery, "{}%", info.battery_percent);
+    } else {
+        battery.push_str("N/A").ok();
+    }
+    let battery_width = text_width(text_style, &battery);
+    let battery_x = layout().width - 1 - battery_width;
+    Text::with_text_style(&battery, Point::new(battery_x, 0), *text_style, text_settings)
+        .draw(display)
+        .ok();
+
+    // Inverted "NoSD" left of the battery while the card is out, or a
+    // blinking "SD!" while log writes fail.
+    let blink_on = (Instant::now().as_millis() / DEGRADED_BLINK_MS) % 2 == 0;
+    let sd_label = if info.sd_card_missing {
+        Some("NoSD")
+    } else if info.logging_degraded && blink_on {
+        Some("SD!")
+    } else {
+        None
+    };
+    if let Some(label) = sd_label {
+        let label_width = text_width(text_style, label);
+        let label_x = battery_x - 3 - label_width;
+        let _ = Rectangle::new(
+            Point::new(label_x - 1, 0),
+            Size::new(label_width as u32 + 2, layout().line_height as u32),
+        )
+        .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+        .draw(display);
+        let inverted = MonoTextStyle::new(layout().font, BinaryColor::Off);
+        Text::with_text_style(label, Point::new(label_x, 0), inverted, text_settings)
+            .draw(display)
+            .ok();
+    }
+
+    // Line 1: the date. With a fix, the favorite/nearest waypoint goes on
+    // the panel's spare line, or as distance and direction right of the
+    // date when it has none and they fit.
+    let spare_line = layout().height / layout().line_height > MAIN_PAGE_LINES;
+    let date = format_date(info);
+    match nav_line {
+        Some(nav) if spare_line => {
+            let nav_row = MAIN_PAGE_LINES;
+            draw_line(display, text_style, text_settings, 1, "Date: ", date);
+            draw_line(display, text_style, text_settings, nav_row, "", nav.full);
+        }
+        Some(nav) => {
+            let date_width = text_width(text_style, &date);
+            let short = &nav.short;
+            draw_line(display, text_style, text_settings, 1, "", date);
+            draw_right_if_fits(display, text_style, text_settings, 1, date_width, short);
+        }
+        None => draw_line(display, text_style, text_settings, 1, "Date: ", date),
+    }
+
+    // Time line with local time and UTC offset
+    let time_str = format_local_time(info, tz_cache);
+    draw_line(
+        display,
+        text_style,
+        text_settings,
+        2,
+        "",
+        time_str,
+    );
+    let gcj02 = shows_gcj02(info);
+    let (lat_label, lng_label) = if gcj02 {
+        ("GLat: ", "GLng: ")
+    } else {
+        ("Lat: ", "Lng: ")
+    };
+    let lat = format_lat(info, gcj02);
+    let lng = format_lng(info, gcj02);
+    // Logging liveness right of the position: points in today's log and
+    // the age of the last SD write, each dropped if the line is too full.
+    let lat_width = text_width(text_style, lat_label) + text_width(text_style, &lat);
+    let lng_width = text_width(text_style, lng_label) + text_width(text_style, &lng);
+    draw_line(display, text_style, text_settings, 3, lat_label, lat);
+    draw_line(display, text_style, text_settings, 4, lng_label, lng);
+    let points = format_points_today(points_today);
+    draw_right_if_fits(display, text_style, text_settings, 3, lat_width, &points);
+    let write_age = format_write_age(crate::storage::last_log_write_age_s());
+    draw_right_if_fits(display, text_style, text_settings, 4, lng_width, &write_age);
+
+    let mut line6 = String::<32>::new();
+    line6.push_str("A:").ok();
+    if info.location_valid {
+        let _ = write!(line6, "{:.1}m", info.altitude);
+    } else {
+        line6.push_str("N/A").ok();
+    }
+    let _ = write!(line6, " S:{}", info.satellites);
+    line6.push_str(" H:").ok();
+    if info.hdop < 99.0 {
+        let _ = write!(line6, "{:.1}", info.hdop);
+    } else {
+        line6.push_str("N/A").ok();
+    }
+    Text::with_text_style(
+        &line6,
+        Point::new(0, layout().line_height * 5),
+        *text_style,
+        text_settings,
+    )
+    .draw(display)
+    .ok();
+
+    let gps_state = gps_state_label(info.gps_state);
+    let mut line7 = String::<32>::new();
+    // Replace the "GPS:" prefix with the fix type once a position is available.
+    match fix_type_label(info) {
+        Some(label) => {
+            line7.push_str(label).ok();
+            line7.push(' ').ok();
+        }
+        None => {
+            line7.push_str("GPS: ").ok();
+        }
+    }
+    line7.push_str(gps_state).ok();
+    Text::with_text_style(
+        &line7,
+        Point::new(0, layout().line_height * 6),
+        *text_style,
+        text_settings,
+    )
+    .draw(display)
+    .ok();
+    // Detected activity right of the GPS state, while recording.
+    let line7_width = text_width(text_style, &line7);
+    let activity = info.activity.label();
+    draw_right_if_fits(display, text_style, text_settings, 6, line7_width, activity);
+
+    let _ = display.flush();
+}
+
+fn render_findmy_page(
+    display: &mut Display,
+    text_style: &MonoTextStyle<'_, BinaryColor>,
+    text_settings: embedded_graphics::text::TextStyle,
+    info: &SystemInfo,
+    findmy_addr: Option<[u8; 6]>,
+    findmy_time: FindMyDisplayTime,
+) {
+    let _ = display.clear(BinaryColor::Off);
+
+    let status = findmy_status_text(info, findmy_addr);
+
+    // Keep line-0 right-side battery style consistent with page 1.
+    let mut battery = String::<16>::new();
+    if info.battery_voltage >= 0.0 {
+        let _ = write!(battery, "{}%", info.battery_percent);
+    } else {
+        battery.push_str("N/A").ok();
+    }
+    let battery_width = text_width(text_style, &battery);
+    let battery_x = layout().width - 1 - battery_width;
+    Text::with_text_style(&battery, Point::new(battery_x, 0), *text_style, text_settings)
+        .draw(display)
+        .ok();
+
+    let mac = format_findmy_mac(findmy_addr);
+    draw_line(display, text_style, text_settings, 1, "FM:", status);
+    draw_line(display, text_style, text_settings, 2, "MAC:", mac);
+
+    let (date_text, time_text, estimated_time) = if info.date_time_valid {
+        (format_date(info), format_time(info), false)
+    } else if let Some(unix_ts) = findmy_time.unix_ts {
+        (
+            format_date_from_unix(unix_ts),
+            format_time_from_unix(unix_ts),
+            findmy_time.estimated,
+        )
+    } else {
+        let mut na_date = String::<32>::new();
+        na_date.push_str("N/A").ok();
+        let mut na_time = String::<32>::new();
+        na_time.push_str("N/A").ok();
+        (na_date, na_time, false)
+    };
+
+    draw_line(display, text_style, text_settings, 3, "Date: ", date_text);
+    draw_line(
+        display,
+        text_style,
+        text_settings,
+        4,
+        if estimated_time { "Time*: " } else { "Time: " },
+        time_text,
+    );
+
+    let _ = display.flush();
+}
+
+fn render_fmdn_page(
+    display: &mut Display,
+    text_style: &MonoTextStyle<'_, BinaryColor>,
+    text_settings: embedded_graphics::text::TextStyle,
+    info: &SystemInfo,
+    fmdn_addr: Option<[u8; 6]>,
+) {
+    let _ = display.clear(BinaryColor::Off);
+
+    // Battery on right side of line 0 (consistent with other pages).
+    let mut battery = String::<16>::new();
+    if info.battery_voltage >= 0.0 {
+        let _ = write!(battery, "{}%", info.battery_percent);
+    } else {
+        battery.push_str("N/A").ok();
+    }
+    let battery_width = text_width(text_style, &battery);
+    let battery_x = layout().width - 1 - battery_width;
+    Text::with_text_style(&battery, Point::new(battery_x, 0), *text_style, text_settings)
+        .draw(display)
+        .ok();
+
+    let status = fmdn_diag_text();
+    draw_line(display, text_style, text_settings, 1, "FMDN:", status);
+
+    let mac = format_findmy_mac(fmdn_addr);
+    draw_line(display, text_style, text_settings, 2, "MAC:", mac);
+
+    let date_text = format_date(info);
+    draw_line(display, text_style, text_settings, 3, "Date: ", date_text);
+
+    let time_text = if info.date_time_valid {
+        format_time(info)
+    } else {
+        let mut na = String::<32>::new();
+        na.push_str("N/A").ok();
+        na
+    };
+    draw_line(display, text_style, text_settings, 4, "Time: ", time_text);
+
+    let rotation = fmdn_rotation_text(info);
+    draw_line(display, text_style, text_settings, 5, "EID: ", rotation);
+
+    let _ = display.flush();
+}
+
+fn render_device_info_page(
+    display: &mut Display,
+    text_style: &MonoTextStyle<'_, BinaryColor>,
+    text_settings: embedded_graphics::text::TextStyle,
+    info: &SystemInfo,
+    agnss: gps::AgnssFreshness,
+) {
+    let _ = display.clear(BinaryColor::Off);
+    let build = &info.build;
+    let mut value = String::<32>::new();
+
+    draw_line(display, text_style, text_settings, 0, "Device info", value.clone());
+
+    // AGNSS freshness on the right of the title, e.g. "AG 2h13m".
+    let mut agnss_str = String::<16>::new();
+    match agnss {
+        gps::AgnssFreshness::None => {}
+        gps::AgnssFreshness::Fresh { remaining_s } => {
+            let _ = write!(agnss_str, "AG {}h{:02}m", remaining_s / 3600, remaining_s / 60 % 60);
+        }
+        gps::AgnssFreshness::Stale => {
+            agnss_str.push_str("AG stale").ok();
+        }
+        gps::AgnssFreshness::Unknown => {
+            agnss_str.push_str("AG ?").ok();
+        }
+    }
+    let agnss_x = layout().width - 1 - text_width(text_style, &agnss_str);
+    Text::with_text_style(&agnss_str, Point::new(agnss_x, 0), *text_style, text_settings)
+        .draw(display)
+        .ok();
+
+    // Leave room for the "FW: " prefix on a 21-character line.
+    let version = build.firmware_version;
+    value.push_str(&version[..version.len().min(17)]).ok();
+    draw_line(display, text_style, text_settings, 1, "FW: ", value.clone());
+
+    value.clear();
+    let bl = build.bootloader_version;
+    if bl == 0 {
+        value.push_str("N/A").ok();
+    } else {
+        let _ = write!(value, "{}.{}.{}", bl >> 16, (bl >> 8) & 0xFF, bl & 0xFF);
+    }
+    draw_line(display, text_style, text_settings, 2, "BL: ", value.clone());
+
+    value.clear();
+    let _ = write!(value, "FWID 0x{:04X}", build.softdevice_fwid);
+    draw_line(display, text_style, text_settings, 3, "SD: ", value.clone());
+
+    value.clear();
+    let _ = write!(value, "0x{:08X}", build.capabilities);
+    draw_line(display, text_style, text_settings, 4, "Caps: ", value.clone());
+
+    // The pairing window takes the last two lines: time left, then the
+    // passkey once a phone asks for one.
+    let pairing_s = crate::ble::pairing_window_remaining_s();
+    if pairing_s > 0 {
+        value.clear();
+        let _ = write!(value, "{}s", pairing_s);
+        draw_line(display, text_style, text_settings, 5, "Pair: ", value.clone());
+
+        value.clear();
+        let passkey = crate::ble::pairing_passkey();
+        let text = match &passkey {
+            Some(key) => core::str::from_utf8(key).unwrap_or("?"),
+            None => "waiting",
+        };
+        value.push_str(text).ok();
+        draw_line(display, text_style, text_settings, 6, "Key: ", value);
+
+        let _ = display.flush();
+        return;
+    }
+
+    value.clear();
+    let _ = write!(
+        value,
+        "{}.{}",
+        crate::features::PROTOCOL_VERSION_MAJOR,
+        crate::features::PROTOCOL_VERSION_MINOR
+    );
+    draw_line(display, text_style, text_settings, 5, "Proto: ", value.clone());
+
+    value.clear();
+    value
+        .push_str(if build.debug_protected { "locked" } else { "open" })
+        .ok();
+    draw_line(display, text_style, text_settings, 6, "Debug: ", value);
+
+    let _ = display.flush();
+}
+
+#[cfg(feature = "power-monitor")]
+fn render_power_page(
+    display: &mut Display,
+    text_style: &MonoTextStyle<'_, BinaryColor>,
+    text_settings: embedded_graphics::text::TextStyle,
+    data: &crate::power_monitor::PowerData,
+) {
+    let _ = display.clear(BinaryColor::Off);
+    let mut value = String::<32>::new();
+
+    draw_line(display, text_style, text_settings, 0, "Power", value.clone());
+
+    push_milliamps(&mut value, data.gps_ua);
+    draw_line(display, text_style, text_settings, 1, "GPS: ", value.clone());
+
+    value.clear();
+    push_milliamps(&mut value, data.sys_ua);
+    draw_line(display, text_style, text_settings, 2, "Sys: ", value.clone());
+
+    value.clear();
+    match data.sys_mv {
+        Some(mv) => {
+            let _ = write!(value, "{}.{:02} V", mv / 1000, (mv % 1000) / 10);
+        }
+        None => {
+            value.push_str("N/A").ok();
+        }
+    }
+    draw_line(display, text_style, text_settings, 3, "Bus: ", value.clone());
+
+    value.clear();
+    let _ = write!(value, "S{}", data.gps_state as u8);
+    draw_line(display, text_style, text_settings, 4, "State: ", value.clone());
+
+    // Averages over all time spent in the current state.
+    value.clear();
+    push_milliamps(&mut value, data.state_gps_ua);
+    draw_line(display, text_style, text_settings, 5, "Avg GPS: ", value.clone());
+
+    value.clear();
+    push_milliamps(&mut value, data.state_sys_ua);
+    draw_line(display, text_style, text_settings, 6, "Avg Sys: ", value);
+
+    let _ = display.flush();
+}
+
+#[cfg(feature = "power-monitor")]
+fn push_milliamps(out: &mut String<32>, ua: Option<i32>) {
+    let Some(ua) = ua else {
+        out.push_str("N/A").ok();
+        return;
+    };
+    let sign = if ua < 0 { "-" } else { "" };
+    let ua = ua.unsigned_abs();
+    let _ = write!(out, "{}{}.{} mA", sign, ua / 1000, (ua % 1000) / 100);
+}
+
+#[cfg(feature = "google-fmdn")]
+fn fmdn_diag_text() -> String<32> {
+    let mut out = String::<32>::new();
+    match crate::google_fmdn::diag_state() {
+        crate::google_fmdn::FmdnDiagState::Disabled => {
+            out.push_str("Disabled").ok();
+        }
+        crate::google_fmdn::FmdnDiagState::WaitingGpsTime => {
+            out.push_str("Wait GPS time").ok();
+        }
+        crate::google_fmdn::FmdnDiagState::WaitingBleIdle => {
+            out.push_str("Wait BLE idle").ok();
+        }
+        crate::google_fmdn::FmdnDiagState::EidReady => {
+            out.push_str("EID ready").ok();
+        }
+        crate::google_fmdn::FmdnDiagState::Advertising => {
+            out.push_str("Broadcasting").ok();
+        }
+        crate::google_fmdn::FmdnDiagState::SetAddrFailed => {
+            out.push_str("Set addr fail").ok();
+        }
+        crate::google_fmdn::FmdnDiagState::AdvConfigureFailed => {
+            out.push_str("Adv cfg fail").ok();
+        }
+        crate::google_fmdn::FmdnDiagState::AdvStartFailed => {
+            out.push_str("Adv start fail").ok();
+        }
+        crate::google_fmdn::FmdnDiagState::Connected => {
+            out.push_str("Finder linked").ok();
+        }
+    }
+    out
+}
+
+#[cfg(not(feature = "google-fmdn"))]
+fn fmdn_diag_text() -> String<32> {
+    let mut out = String::<32>::new();
+    out.push_str("N/A").ok();
+    out
+}
+
+#[cfg(feature = "google-fmdn")]
+fn fmdn_rotation_text(info: &SystemInfo) -> String<32> {
+    let mut out = String::<32>::new();
+    if !crate::google_fmdn::is_enabled() {
+        out.push_str("N/A").ok();
+        return out;
+    }
+    if let Some(unix_ts) = info_unix_ts(info) {
+        // EID rotation every 1024 seconds.
+        let period = 1024u64;
+        let remaining = period - (unix_ts % period);
+        let _ = write!(out, "rot {}s", remaining);
+    } else {
+        out.push_str("no time").ok();
+    }
+    out
+}
+
+#[cfg(not(feature = "google-fmdn"))]
+fn fmdn_rotation_text(_info: &SystemInfo) -> String<32> {
+    let mut out = String::<32>::new();
+    out.push_str("N/A").ok();
+    out
+}
+
+/// Speed (top) and altitude (bottom) over the last hour, one column pair
+/// per minute, with the three-hour pressure change beside the altitude.
+fn render_trend_page(
+    display: &mut Display,
+    text_style: &MonoTextStyle<'_, BinaryColor>,
+    text_settings: embedded_graphics::text::TextStyle,
+    speed: &Series,
+    altitude: &Series,
+    pressure_change_hpa: Option<f32>,
+) {
+    let _ = display.clear(BinaryColor::Off);
+    let mut label = String::<32>::new();
+
+    let speed_range = trend::min_max(speed);
+    match speed_range {
+        Some((_, max)) => {
+            let _ = write!(label, "Spd 1h max {:.1}", max);
+        }
+        None => {
+            label.push_str("Spd 1h: no data").ok();
+        }
+    }
+    Text::with_text_style(&label, Point::new(0, 0), *text_style, text_settings)
+        .draw(display)
+        .ok();
+    let line_height = layout().line_height;
+    let graph_height = layout().trend_graph_height();
+    if let Some((_, max)) = speed_range {
+        // Speed graphs start at standstill.
+        draw_sparkline(display, speed, line_height + 1, graph_height, 0.0, max);
+    }
+
+    label.clear();
+    let altitude_range = trend::min_max(altitude);
+    match altitude_range {
+        Some((min, max)) => {
+            let _ = write!(label, "Alt {:.0}-{:.0} m", min, max);
+        }
+        None => {
+            label.push_str("Alt 1h: no data").ok();
+        }
+    }
+    let alt_label_y = line_height + 2 + graph_height;
+    Text::with_text_style(&label, Point::new(0, alt_label_y), *text_style, text_settings)
+        .draw(display)
+        .ok();
+    if let Some(change_hpa) = pressure_change_hpa {
+        let mut pressure = String::<16>::new();
+        let _ = write!(pressure, "{:+.1}hPa", change_hpa);
+        let x = layout().width - text_width(text_style, &pressure);
+        if x >= text_width(text_style, &label) + text_width(text_style, " ") {
+            let origin = Point::new(x, alt_label_y);
+            let text = Text::with_text_style(&pressure, origin, *text_style, text_settings);
+            let _ = text.draw(display);
+        }
+    }
+    if let Some((min, max)) = altitude_range {
+        // Keep a few metres of noise from filling the whole graph.
+        let pad = ((TREND_MIN_ALT_SPAN_M - (max - min)) / 2.0).max(0.0);
+        draw_sparkline(
+            display,
+            altitude,
+            alt_label_y + line_height + 1,
+            graph_height,
+            min - pad,
+            max + pad,
+        );
+    }
+
+    let _ = display.flush();
+}
+
+fn render_steps_page(
+    display: &mut Display,
+    text_style: &MonoTextStyle<'_, BinaryColor>,
+    text_settings: embedded_graphics::text::TextStyle,
+    info: &SystemInfo,
+) {
+    let _ = display.clear(BinaryColor::Off);
+    let mut value = String::<32>::new();
+
+    draw_line(display, text_style, text_settings, 0, "Pedometer", value.clone());
+
+    let _ = write!(value, "{}", info.steps_today);
+    draw_line(display, text_style, text_settings, 2, "Steps: ", value.clone());
+
+    value.clear();
+    let date = info.steps_date;
+    if date == 0 {
+        value.push_str("no clock yet").ok();
+    } else {
+        let _ = write!(value, "{:04}-{:02}-{:02}", date / 10000, date / 100 % 100, date % 100);
+    }
+    draw_line(display, text_style, text_settings, 3, "Day: ", value);
+
+    let _ = display.flush();
+}
+
+fn render_track_stats_page(
+    display: &mut Display,
+    text_style: &MonoTextStyle<'_, BinaryColor>,
+    text_settings: embedded_graphics::text::TextStyle,
+    stats: &DayStats,
+) {
+    let _ = display.clear(BinaryColor::Off);
+    let mut value = String::<32>::new();
+
+    draw_line(display, text_style, text_settings, 0, "Today's track", value.clone());
+
+    let _ = write!(value, "{:.2} km", stats.distance_m / 1000.0);
+    draw_line(display, text_style, text_settings, 2, "Dist: ", value.clone());
+
+    value.clear();
+    let _ = write!(value, "{}h{:02}m", stats.moving_s / 3600, stats.moving_s / 60 % 60);
+    draw_line(display, text_style, text_settings, 3, "Moving: ", value.clone());
+
+    value.clear();
+    let _ = write!(value, "{:.1} km/h", stats.max_speed_kmh);
+    draw_line(display, text_style, text_settings, 4, "Max: ", value.clone());
+
+    value.clear();
+    let _ = write!(value, "+{:.0} -{:.0} m", stats.ascent_m, stats.descent_m);
+    draw_line(display, text_style, text_settings, 5, "Alt: ", value.clone());
+
+    value.clear();
+    let _ = write!(value, "{}", stats.points);
+    draw_line(display, text_style, text_settings, 6, "Points: ", value);
+
+    let _ = display.flush();
+}
+
+/// Heading-up compass rose on the left, readings on the right. Without a
+/// heading the rose stays north-up.
+fn render_compass_page(
+    display: &mut Display,
+    text_style: &MonoTextStyle<'_, BinaryColor>,
+    info: &SystemInfo,
+    target: Option<&CompassTarget>,
+) {
+    let _ = display.clear(BinaryColor::Off);
+    let layout = layout();
+    let stroke = PrimitiveStyle::with_stroke(BinaryColor::On, 1);
+    let diameter = layout.height.min(layout.width / 2);
+    let radius = diameter / 2;
+    let top = (layout.height - diameter) / 2;
+    let center = Point::new(radius, top + radius);
+    let heading = (info.heading >= 0.0).then_some(info.heading);
+    // Screen direction of a bearing: up is the heading.
+    let at = |bearing: f32, length: i32| {
+        let angle = (bearing - heading.unwrap_or(0.0)).to_radians();
+        let x = libm::sinf(angle) * length as f32;
+        let y = libm::cosf(angle) * length as f32;
+        center + Point::new(x as i32, -(y as i32))
+    };
+
+    let _ = Circle::new(Point::new(0, top), diameter as u32)
+        .into_styled(stroke)
+        .draw(display);
+    // Lubber line: the direction of travel.
+    let _ = Line::new(Point::new(center.x, top), Point::new(center.x, top + 3))
+        .into_styled(stroke)
+        .draw(display);
+    let label_style = TextStyleBuilder::new()
+        .alignment(Alignment::Center)
+        .baseline(Baseline::Middle)
+        .build();
+    let label_radius = radius - layout.line_height / 2 - 2;
+    for (label, bearing) in [("N", 0.0), ("E", 90.0), ("S", 180.0), ("W", 270.0)] {
+        let _ = Text::with_text_style(label, at(bearing, label_radius), *text_style, label_style)
+            .draw(display);
+    }
+    if let Some(target) = target {
+        let _ = Line::new(center, at(target.bearing_deg, radius * 2 / 3))
+            .into_styled(PrimitiveStyle::with_stroke(BinaryColor::On, 2))
+            .draw(display);
+    }
+    let _ = Circle::with_center(center, 3)
+        .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+        .draw(display);
+
+    let text_settings = TextStyleBuilder::new().baseline(Baseline::Top).build();
+    let mut draw_row = |line: i32, text: &str| {
+        let origin = Point::new(diameter + 2, layout.line_height * line);
+        let _ = Text::with_text_style(text, origin, *text_style, text_settings).draw(display);
+    };
+    let mut value = String::<32>::new();
+    let _ = match heading {
+        Some(heading) => {
+            let degrees = libm::roundf(heading) as u16 % 360;
+            let point = geo::compass_point(degrees as f64);
+            write!(value, "{:03} {}", degrees, point)
+        }
+        None if !info.location_valid => write!(value, "No fix"),
+        None => write!(value, "N up"),
+    };
+    draw_row(0, &value);
+    if let Some(target) = target {
+        draw_row(2, &target.name);
+        draw_row(3, &target.distance);
+    }
+
+    let _ = display.flush();
+}
+
+fn render_satellites_page(
+    display: &mut Display,
+    text_style: &MonoTextStyle<'_, BinaryColor>,
+    text_settings: embedded_graphics::text::TextStyle,
+    info: &SystemInfo,
+) {
+    let _ = display.clear(BinaryColor::Off);
+    let layout = layout();
+    let sky = info.sky.by_snr();
+    let (in_view, tracked) = sky.counts(None);
+    let mut value = String::<32>::new();
+    let _ = write!(value, "{}/{}", tracked, in_view);
+    let left_width = text_width(text_style, "Sats: ") + text_width(text_style, &value);
+    draw_line(display, text_style, text_settings, 0, "Sats: ", value);
+    if in_view == 0 {
+        let text = match info.gps_state {
+            GpsState::S2IdleGpsOff => "GPS off",
+            _ => "No satellites",
+        };
+        draw_line(display, text_style, text_settings, 2, text, String::new());
+        let _ = display.flush();
+        return;
+    }
+    if info.hdop < 99.0 {
+        let mut hdop = String::<16>::new();
+        let _ = write!(hdop, "HDOP {:.1}", info.hdop);
+        draw_right_if_fits(display, text_style, text_settings, 0, left_width, &hdop);
+    }
+
+    // One bar per satellite, strongest first; a stub for those in view but
+    // not tracked.
+    let top = layout.line_height + 1;
+    let bottom = layout.height - layout.line_height - 2;
+    let height = bottom - top;
+    let slot = (layout.width / in_view as i32).clamp(2, 12);
+    let fill = PrimitiveStyle::with_fill(BinaryColor::On);
+    for (index, satellite) in sky.satellites().iter().enumerate() {
+        let snr = satellite.snr.min(SKY_FULL_SNR) as i32;
+        let level = (snr * height / SKY_FULL_SNR as i32).max(1);
+        let corner = Point::new(index as i32 * slot, bottom - level);
+        let size = Size::new((slot - 1) as u32, level as u32);
+        let _ = Rectangle::new(corner, size).into_styled(fill).draw(display);
+    }
+
+    // Tracked/in view per constellation, by RINEX letter.
+    let mut counts = String::<32>::new();
+    for constellation in Constellation::ALL {
+        let (in_view, tracked) = sky.counts(Some(constellation));
+        if in_view > 0 {
+            let _ = write!(counts, "{}{}/{} ", constellation.letter(), tracked, in_view);
+        }
+    }
+    let origin = Point::new(0, layout.height - layout.line_height);
+    let line = Text::with_text_style(counts.trim_end(), origin, *text_style, text_settings);
+    let _ = line.draw(display);
+    let _ = display.flush();
+}
+
+/// Battery voltage over the last 24 hours, one point per five minutes, and
+/// the current reading below.
+fn render_battery_page(
+    display: &mut Display,
+    text_style: &MonoTextStyle<'_, BinaryColor>,
+    text_settings: embedded_graphics::text::TextStyle,
+    info: &SystemInfo,
+    history: &[Option<f32>],
+) {
+    let _ = display.clear(BinaryColor::Off);
+    let layout = layout();
+    let mut label = String::<32>::new();
+    let range = trend::min_max(history);
+    match range {
+        Some((min, max)) => {
+            let _ = write!(label, "Bat 24h {:.2}-{:.2}V", min / 1000.0, max / 1000.0);
+        }
+        None => {
+            label.push_str("Bat 24h: no data").ok();
+        }
+    }
+    let title = Text::with_text_style(&label, Point::new(0, 0), *text_style, text_settings);
+    let _ = title.draw(display);
+    if let Some((min, max)) = range {
+        // A healthy cell at rest barely moves; keep that flat.
+        let pad = ((BATTERY_MIN_SPAN_MV - (max - min)) / 2.0).max(0.0);
+        let top = layout.line_height + 1;
+        let height = layout.height - 2 * layout.line_height - 2;
+        draw_sparkline(display, history, top, height, min - pad, max + pad);
+    }
+
+    label.clear();
+    if info.battery_voltage >= 0.0 {
+        let charge = match info.charge_state {
+            ChargeState::Discharging => "",
+            ChargeState::Charging => " chg",
+            ChargeState::Full => " full",
+        };
+        let (volts, percent) = (info.battery_voltage, info.battery_percent);
+        let _ = write!(label, "Now {:.2}V {}%{}", volts, percent, charge);
+    } else {
+        label.push_str("Now: N/A").ok();
+    }
+    let origin = Point::new(0, layout.height - layout.line_height);
+    let line = Text::with_text_style(&label, origin, *text_style, text_settings);
+    let _ = line.draw(display);
+    let _ = display.flush();
+}
+
+/// Rows of the log list below the title line.
+const FILES_ROWS: usize = 6;
+
+fn render_files_page(
+    display: &mut Display,
+    text_style: &MonoTextStyle<'_, BinaryColor>,
+    text_settings: embedded_graphics::text::TextStyle,
+) {
+    let _ = display.clear(BinaryColor::Off);
+    browser::with_listing(|listing| {
+        let mut value = String::<32>::new();
+        let _ = write!(value, "{}", listing.logs.len());
+        draw_line(display, text_style, text_settings, 0, "Logs: ", value);
+        if listing.logs.is_empty() {
+            draw_line(display, text_style, text_settings, 2, "No logs", String::new());
+            return;
+        }
+
+        // Scroll so the selected row stays on screen.
+        let first = (listing.selected + 1).saturating_sub(FILES_ROWS);
+        let rows = listing.logs.iter().enumerate().skip(first).take(FILES_ROWS);
+        for (row, (index, log)) in rows.enumerate() {
+            let mut value = String::<32>::new();
+            let _ = if layout().compact() {
+                write!(value, "{:02}{:02} ", log.month(), log.day())
+            } else {
+                write!(value, "{}-{:02}-{:02} ", log.year(), log.month(), log.day())
+            };
+            push_file_size(&mut value, log.size);
+            let prefix = if index == listing.selected { ">" } else { " " };
+            let line = row as i32 + 1;
+            draw_line(display, text_style, text_settings, line, prefix, value);
+        }
+    });
+    let _ = display.flush();
+}
+
+fn push_file_size(out: &mut String<32>, bytes: u32) {
+    let _ = match bytes {
+        0..=1023 => write!(out, "{}B", bytes),
+        1024..=1_048_575 => write!(out, "{}K", bytes / 1024),
+        _ => write!(out, "{}.{}M", bytes / 1_048_576, bytes % 1_048_576 * 10 / 1_048_576),
+    };
+}
+
+/// Draw `series` across the full width, scaled into `height` rows from
+/// `top`. A `None` sample breaks the line.
+fn draw_sparkline(
+    display: &mut Display,
+    series: &[Option<f32>],
+    top: i32,
+    height: i32,
+    min: f32,
+    max: f32,
+) {
+    let span = (max - min).max(f32::EPSILON);
+    let style = PrimitiveStyle::with_stroke(BinaryColor::On, 1);
+    let mut prev: Option<Point> = None;
+    for (index, value) in series.iter().enumerate() {
+        let Some(value) = value else {
+            prev = None;
+            continue;
+        };
+        let x = index as i32 * (layout().width - 1) / (series.len() as i32 - 1);
+        let level = ((value - min) / span * (height - 1) as f32) as i32;
+        let point = Point::new(x, top + height - 1 - level.clamp(0, height - 1));
+        let _ = Line::new(prev.unwrap_or(point), point)
+            .into_styled(style)
+            .draw(display);
+        prev = Some(point);
+    }
+}
+
+fn render_usb_mode(
+    display: &mut Display,
+    _text_style: &MonoTextStyle<'_, BinaryColor>,
+    _text_settings: embedded_graphics::text::TextStyle,
+) {
+    let _ = display.clear(BinaryColor::Off);
+
+    let layout = layout();
+    let text_style = MonoTextStyle::new(layout.font, BinaryColor::On);
+    let text_settings = TextStyleBuilder::new().baseline(Baseline::Top).build();
+    let char_width = layout.font.character_size.width as i32;
+    let (title_y, icon_y, status_y) = if layout.compact() {
+        (0, 7, 41)
+    } else {
+        (2, 14, 48)
+    };
+
+    // Top text: "USB Mass Storage"
+    let title = "USB Mass Storage";
+    let title_width = title.len() as i32 * char_width;
+    let title_x = (layout.width - title_width) / 2;
+    Text::with_text_style(
+        title,
+        Point::new(title_x, title_y),
+        text_style,
+        text_settings,
+    )
+    .draw(display)
+    .ok();
+
+    // Draw USB icon from bitmap (centered)
+    let raw_image: ImageRaw<BinaryColor> = ImageRaw::new(&USB_ICON, USB_ICON_WIDTH);
+    let icon_x = (layout.width - USB_ICON_WIDTH as i32) / 2;
+    let image = Image::new(&raw_image, Point::new(icon_x, icon_y));
+    let _ = image.draw(display);
+
+    // Status text
+    let status = "Connected";
+    let status_width = status.len() as i32 * char_width;
+    let status_x = (layout.width - status_width) / 2;
+    Text::with_text_style(
+        status,
+        Point::new(status_x, status_y),
+        text_style,
+        text_settings,
+    )
+    .draw(display)
+    .ok();
+
+    // Bottom hint, below the status line on the full-size panel only
+    if !layout.compact() {
+        let hint = "Safe to transfer";
+        let hint_width = hint.len() as i32 * char_width;
+        let hint_x = (layout.width - hint_width) / 2;
+        Text::with_text_style(hint, Point::new(hint_x, 57), text_style, text_settings)
+            .draw(display)
+            .ok();
+    }
+
+    let _ = display.flush();
+}
+
+fn draw_line<D>(
+    display: &mut D,
+    text_style: &MonoTextStyle<'_, BinaryColor>,
+    text_settings: embedded_graphics::text::TextStyle,
+    line_index: i32,
+    prefix: &str,
+    value: String<32>,
+) where
+    D: DrawTarget<Color = BinaryColor>,
+{
+    let mut line = String::<32>::new();
+    line.push_str(prefix).ok();
+    line.push_str(&value).ok();
+    Text::with_text_style(
+        &line,
+        Point::new(0, layout().line_height * line_index),
+        *text_style,
+        text_settings,
+    )
+    .draw(display)
+    .ok();
+}
+
+/// Right-align `text` on `line_index` unless it would come within a
+/// character of the `left_width` pixels already drawn there.
+fn draw_right_if_fits<D>(
+    display: &mut D,
+    text_style: &MonoTextStyle<'_, BinaryColor>,
+    text_settings: embedded_graphics::text::TextStyle,
+    line_index: i32,
+    left_width: i32,
+    text: &str,
+) where
+    D: DrawTarget<Color = BinaryColor>,
+{
+    let width = text_width(text_style, text);
+    let x = layout().width - width;
+    if x < left_width + text_width(text_style, " ") {
+        return;
+    }
+    let position = Point::new(x, layout().line_height * line_index);
+    Text::with_text_style(text, position, *text_style, text_settings)
+        .draw(display)
+        .ok();
+}
+
+fn resolve_findmy_display_time(
+    info: &SystemInfo,
+    anchor: &mut Option<DisplayTimeAnchor>,
+) -> FindMyDisplayTime {
+    if let Some(unix_ts) = info_unix_ts(info) {
+        *anchor = Some(DisplayTimeAnchor {
+            unix_ts,
+            monotonic_ms: Instant::now().as_millis(),
+        });
+        return FindMyDisplayTime {
+            unix_ts: Some(unix_ts),
+            estimated: false,
+        };
+    }
+
+    if let Some(unix_ts) = crate::phone_location::unix_now() {
+        return FindMyDisplayTime {
+            unix_ts: Some(unix_ts),
+            estimated: true,
+        };
+    }
+
+    if let Some(base) = *anchor {
+        let now_ms = Instant::now().as_millis();
+        let elapsed_secs = now_ms.saturating_sub(base.monotonic_ms) / 1000;
+        return FindMyDisplayTime {
+            unix_ts: Some(base.unix_ts.saturating_add(elapsed_secs)),
+            estimated: true,
+        };
+    }
+
+    FindMyDisplayTime {
+        unix_ts: None,
+        estimated: false,
+    }
+}
+
+fn format_date_from_unix(unix_ts: u64) -> String<32> {
+    let mut out = String::<32>::new();
+    if let Some(dt) = chrono::DateTime::from_timestamp(unix_ts as i64, 0) {
+        let _ = write!(out, "{:04}-{:02}-{:02}", dt.year(), dt.month(), dt.day());
+    } else {
+        out.push_str("N/A").ok();
+    }
+    out
+}
+
+fn format_time_from_unix(unix_ts: u64) -> String<32> {
+    let mut out = String::<32>::new();
+    if let Some(dt) = chrono::DateTime::from_timestamp(unix_ts as i64, 0) {
+        let _ = write!(out, "{:02}:{:02}:{:02}", dt.hour(), dt.minute(), dt.second());
+    } else {
+        out.push_str("N/A").ok();
+    }
+    out
+}
+
+fn format_date(info: &SystemInfo) -> String<32> {
+    let mut out = String::<32>::new();
+    if info.date_time_valid {
+        let _ = write!(out, "{:04}-{:02}-{:02}", info.year, info.month, info.day);
+    } else {
+        out.push_str("N/A").ok();
+    }
+    out
+}
+
+#[allow(dead_code)]
+fn format_time(info: &SystemInfo) -> String<32> {
+    let mut out = String::<32>::new();
+    if info.date_time_valid {
+        let _ = write!(out, "{:02}:{:02}:{:02}", info.hour, info.minute, info.second);
+    } else {
+        out.push_str("N/A").ok();
+    }
+    out
+}
+
+/// Format local time with UTC offset, e.g. "Time: 14:30:00 +8"
+fn format_local_time(info: &SystemInfo, tz_cache: &mut TzCache) -> String<32> {
+    let mut out = String::<32>::new();
+    out.push_str("Time: ").ok();
+    
+    if !info.date_time_valid {
+        out.push_str("N/A").ok();
+        return out;
+    }
+    
+    // Get UTC offset if we have valid location or a manual timezone override
+    let has_offset = info.location_valid || crate::timezone::has_override();
+    let offset = if has_offset {
+        tz_cache.get_offset(
+            info.latitude as f32,
+            info.longitude as f32,
+            info.year,
+            info.month,
+            info.day,
+            info.hour,
+            info.minute,
+            info.second,
+        )
+    } else {
+        crate::timezone::UtcOffset::from_minutes(0)
+    };
+    
+    // Convert UTC time to local time
+    let utc_minutes = info.hour as i32 * 60 + info.minute as i32;
+    let local_minutes = utc_minutes + offset.total_minutes as i32;
+    
+    // Handle day wraparound
+    let (local_hour, local_minute) = if local_minutes < 0 {
+        let adjusted = local_minutes + 24 * 60;
+        ((adjusted / 60) as u8, (adjusted % 60) as u8)
+    } else if local_minutes >= 24 * 60 {
+        let adjusted = local_minutes - 24 * 60;
+        ((adjusted / 60) as u8, (adjusted % 60) as u8)
+    } else {
+        ((local_minutes / 60) as u8, (local_minutes % 60) as u8)
+    };
+    
+    // Format time
+    let _ = write!(out, "{:02}:{:02}:{:02}", local_hour, local_minute, info.second);
+    
+    // Add UTC offset
+    if has_offset {
+        let sign = if offset.is_positive() { '+' } else { '-' };
+        let hours = offset.hours().unsigned_abs();
+        let mins = offset.minutes();
+        
+        if mins == 0 {
+            let _ = write!(out, " {}{}", sign, hours);
+        } else {
+            let _ = write!(out, " {}{}:{:02}", sign, hours, mins);
+        }
+    } else {
+        out.push_str(" UTC").ok();
+    }
+    
+    out
+}
+
+/// "123p", or "12k" from 10000 points on.
+fn format_points_today(points: u32) -> String<16> {
+    let mut out = String::<16>::new();
+    if points < 10_000 {
+        let _ = write!(out, "{}p", points);
+    } else {
+        let _ = write!(out, "{}k", points / 1000);
+    }
+    out
+}
+
+/// "42s", "5m" or "3h" since the last SD write, "-" before the first.
+fn format_write_age(age_s: Option<u32>) -> String<16> {
+    let mut out = String::<16>::new();
+    let _ = match age_s {
+        None => write!(out, "-"),
+        Some(age) if age < 60 => write!(out, "{}s", age),
+        Some(age) if age < 3600 => write!(out, "{}m", age / 60),
+        Some(age) => write!(out, "{}h", (age / 3600).min(99)),
+    };
+    out
+}
+
+/// Fix, or else last phone position, as WGS-84 `(latitude, longitude)`.
+fn main_page_position(info: &SystemInfo) -> Option<(f64, f64)> {
+    if info.location_valid {
+        return Some((info.latitude, info.longitude));
+    }
+    crate::phone_location::last_known().map(|last| (last.latitude, last.longitude))
+}
+
+/// `display.gcj02` is on and the position shown is in mainland China.
+fn shows_gcj02(info: &SystemInfo) -> bool {
+    settings::stored(settings::DISPLAY_GCJ02) != 0
+        && main_page_position(info).is_some_and(|(lat, lon)| datum::in_mainland_china(lat, lon))
+}
+
+/// Position as shown on the Main page, converted when `gcj02`.
+fn shown_position(latitude: f64, longitude: f64, gcj02: bool) -> (f64, f64) {
+    if gcj02 {
+        datum::wgs84_to_gcj02(latitude, longitude)
+    } else {
+        (latitude, longitude)
+    }
+}
+
+fn format_lat(info: &SystemInfo, gcj02: bool) -> String<32> {
+    let mut out = String::<32>::new();
+    if info.location_valid {
+        let (lat, _) = shown_position(info.latitude, info.longitude, gcj02);
+        let _ = write!(out, "{:.7}", lat);
+    } else if let Some(last) = crate::phone_location::last_known() {
+        // Coarse phone position: "~" marks it as last known, not a fix.
+        let (lat, _) = shown_position(last.latitude, last.longitude, gcj02);
+        let _ = write!(out, "~{:.4}", lat);
+    } else {
+        out.push_str("N/A").ok();
+    }
+    out
+}
+
+fn format_lng(info: &SystemInfo, gcj02: bool) -> String<32> {
+    let mut out = String::<32>::new();
+    if info.location_valid {
+        let (_, lng) = shown_position(info.latitude, info.longitude, gcj02);
+        let _ = write!(out, "{:.7}", lng);
+    } else if let Some(last) = crate::phone_location::last_known() {
+        // Coarse phone position: "~" marks it as last known, not a fix.
+        let (_, lng) = shown_position(last.latitude, last.longitude, gcj02);
+        let _ = write!(out, "~{:.4}", lng);
+    } else {
+        out.push_str("N/A").ok();
+    }
+    out
+}
+
+/// "Home 2.3 km NE": favorite (or nearest) waypoint, `None` without a fix.
+#[cfg(feature = "nav")]
+async fn nav_target_text(info: &SystemInfo) -> Option<NavLine> {
+    if !info.location_valid {
+        return None;
+    }
+    let target = crate::waypoints::nav_target(info.latitude, info.longitude).await?;
+    let distance = format_distance(target.distance_m);
+    let direction = geo::compass_point(target.bearing_deg);
+    let mut short = String::<16>::new();
+    let _ = write!(short, "{} {}", distance, direction);
+
+    // Truncate the name so distance and direction always fit on the line.
+    let reserved = short.len() + 1;
+    let name = core::str::from_utf8(target.waypoint.name()).unwrap_or("?");
+    let mut full = String::<32>::new();
+    for ch in name.chars() {
+        if full.len() + ch.len_utf8() > layout().chars().saturating_sub(reserved) {
+            break;
+        }
+        full.push(ch).ok();
+    }
+    let _ = write!(full, " {}", short);
+    Some(NavLine { full, short })
+}
+
+#[cfg(not(feature = "nav"))]
+async fn nav_target_text(_info: &SystemInfo) -> Option<NavLine> {
+    None
+}
+
+#[cfg(feature = "nav")]
+fn format_distance(distance_m: f64) -> String<16> {
+    let mut out = String::<16>::new();
+    if distance_m < 1000.0 {
+        let _ = write!(out, "{:.0} m", distance_m);
+    } else if distance_m < 10_000.0 {
+        let _ = write!(out, "{:.1} km", distance_m / 1000.0);
+    } else {
+        let _ = write!(out, "{:.0} km", distance_m / 1000.0);
+    }
+    out
+}
+
+/// Waypoint the compass needle points at.
+#[cfg_attr(not(feature = "nav"), allow(dead_code))]
+struct CompassTarget {
+    bearing_deg: f32,
+    name: String<16>,
+    distance: String<16>,
+}
+
+#[cfg(feature = "nav")]
+async fn compass_target(info: &SystemInfo) -> Option<CompassTarget> {
+    if !info.location_valid {
+        return None;
+    }
+    let target = crate::waypoints::nav_target(info.latitude, info.longitude).await?;
+    let mut name = String::<16>::new();
+    let full_name = core::str::from_utf8(target.waypoint.name()).unwrap_or("?");
+    for ch in full_name.chars() {
+        if name.push(ch).is_err() {
+            break;
+        }
+    }
+    Some(CompassTarget {
+        bearing_deg: target.bearing_deg as f32,
+        name,
+        distance: format_distance(target.distance_m),
+    })
+}
+
+#[cfg(not(feature = "nav"))]
+async fn compass_target(_info: &SystemInfo) -> Option<CompassTarget> {
+    None
+}
+
+fn format_findmy_mac(addr: Option<[u8; 6]>) -> String<32> {
+    let mut out = String::<32>::new();
+    if let Some(a) = addr {
+        let _ = write!(
+            out,
+            "{:02X}:{:02X}:{:02X}:{:02X}:{:02X}:{:02X}",
+            a[5], a[4], a[3], a[2], a[1], a[0]
+        );
+    } else {
+        out.push_str("N/A").ok();
+    }
+    out
+}
+
+#[cfg(feature = "findmy")]
+fn findmy_status_text(info: &SystemInfo, _findmy_addr: Option<[u8; 6]>) -> String<32> {
+    let mut out = String::<32>::new();
+    match crate::findmy::diag_state() {
+        crate::findmy::FindMyDiagState::Disabled if crate::findmy::is_provisioned() => {
+            out.push_str("Switched off").ok();
+        }
+        crate::findmy::FindMyDiagState::Disabled => {
+            out.push_str("Disabled").ok();
+        }
+        crate::findmy::FindMyDiagState::WaitingGpsTime => {
+            if findmy_waiting_epoch(info) {
+                out.push_str("Waiting epoch").ok();
+            } else {
+                out.push_str("Waiting GPS time").ok();
+            }
+        }
+        crate::findmy::FindMyDiagState::WaitingBleIdle => {
+            out.push_str("Waiting BLE idle").ok();
+        }
+        crate::findmy::FindMyDiagState::AddressReady => {
+            out.push_str("Address ready").ok();
+        }
+        crate::findmy::FindMyDiagState::Advertising => {
+            out.push_str("Broadcasting").ok();
+        }
+        crate::findmy::FindMyDiagState::SetAddrFailed => {
+            out.push_str("Set addr failed").ok();
+        }
+        crate::findmy::FindMyDiagState::AdvConfigureFailed => {
+            out.push_str("Adv cfg failed").ok();
+        }
+        crate::findmy::FindMyDiagState::AdvStartFailed => {
+            out.push_str("Adv start failed").ok();
+        }
+    }
+    out
+}
+
+#[cfg(not(feature = "findmy"))]
+fn findmy_status_text(_info: &SystemInfo, _findmy_addr: Option<[u8; 6]>) -> String<32> {
+    let mut out = String::<32>::new();
+    out.push_str("Disabled").ok();
+    out
+}
+
+#[cfg(feature = "findmy")]
+fn findmy_waiting_epoch(info: &SystemInfo) -> bool {
+    let Some(unix_ts) = info_unix_ts(info) else {
+        return false;
+    };
+    unix_ts < crate::findmy::epoch_secs()
+}
+
+#[cfg(not(feature = "findmy"))]
+fn findmy_waiting_epoch(_info: &SystemInfo) -> bool {
+    false
+}
+
+/// `info` with the phone time filled in while GPS time is invalid, so the
+/// pages show a clock indoors before the first fix.
+fn with_phone_time(info: &SystemInfo) -> SystemInfo {
+    let mut info = *info;
+    if info.date_time_valid {
+        return info;
+    }
+    let phone = crate::phone_location::unix_now()
+        .and_then(|unix_ts| chrono::DateTime::from_timestamp(unix_ts as i64, 0));
+    if let Some(dt) = phone {
+        info.year = dt.year() as u16;
+        info.month = dt.month() as u8;
+        info.day = dt.day() as u8;
+        info.hour = dt.hour() as u8;
+        info.minute = dt.minute() as u8;
+        info.second = dt.second() as u8;
+        info.date_time_valid = true;
+    }
+    info
+}
+
+fn info_unix_ts(info: &SystemInfo) -> Option<u64> {
+    if !info.date_time_valid {
+        return None;
+    }
+    let dt = chrono::NaiveDate::from_ymd_opt(info.year as i32, info.month as u32, info.day as u32)?
+        .and_hms_opt(info.hour as u32, info.minute as u32, info.second as u32)?;
+    Some(dt.and_utc().timestamp() as u64)
+}
+
+/// Fix type label, e.g. "3D DGPS". `None` while there is no usable fix.
+fn fix_type_label(info: &SystemInfo) -> Option<&'static str> {
+    if !info.location_valid {
+        return None;
+    }
+    let dgps = info.fix_quality == 2;
+    match (info.fix_mode, dgps) {
+        (2, false) => Some("2D"),
+        (2, true) => Some("2D DGPS"),
+        (3, false) => Some("3D"),
+        (3, true) => Some("3D DGPS"),
+        _ => None,
+    }
+}
+
+fn gps_state_label(state: GpsState) -> &'static str {
+    match state {
+        GpsState::S0Initializing => "Initializing",
+        GpsState::S1GpsSearchingFix => "Searching",
+        GpsState::S2IdleGpsOff => "Idle (GPS Off)",
+        GpsState::S3TrackingFixed => "Fixed",
+        GpsState::S4AnalyzingStillness => "Analyze-Still",
+        GpsState::S5AgnssProcessing => "AGNSS Proc",
+    }
+}
+
+fn text_width(style: &MonoTextStyle<'_, BinaryColor>, text: &str) -> i32 {
+    let metrics = style.measure_string(text, Point::zero(), Baseline::Top);
+    metrics.bounding_box.size.width as i32
+}
@@ -0,0 +1,386 @@
+//! OLED panel variants behind one draw target.
+//!
+//! The reference board carries a 128x64 SSD1306, but cheap modules are
+//! often an SH1106 (132 columns of RAM, the visible 128 starting at column
+//! 2), which the SSD1306 driver renders as garbage, or a 64x48 SSD1306.
+//! `Panel` hides which one is fitted; `layout()` gives the pages the screen
+//! size, font and line height to draw with.
+//!
+//! # Design
+//!
+//! - `display.panel` selects the variant. With auto (the default) the
+//!   status byte decides between the two 128x64 controllers: the SH1106
+//!   reads back `0x08` in its low nibble, the SSD1306 does not. The 64x48
+//!   panel is an SSD1306 as well and has to be selected.
+//! - The SH1106 has no horizontal addressing mode, so the driver keeps its
+//!   own frame buffer and writes it one page at a time.
+//! - `display.flip` turns the picture 180° for enclosures mounted upside
+//!   down, through the controller's segment remap and COM scan direction.
+//!   The frame buffer is not touched, so it applies at the next frame.
+//! - Pages are laid out for 21 characters by 7 lines. The small panel uses
+//!   the 4x6 font, which gives 16 by 8: lines keep their rows and lose a
+//!   few characters at the end rather than whole lines.
+//! - Every frame starts with `clear`, so a hash of the drawing calls since
+//!   then fingerprints the frame. `flush` skips the I2C transfer when it
+//!   matches the frame on the panel; a mismatch only costs one redundant
+//!   transfer, and a 32-bit collision one stale frame until the next change.
+
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU8, Ordering};
+
+use embassy_embedded_hal::shared_bus::blocking::i2c::I2cDevice;
+use embassy_nrf::twim;
+use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+use embedded_graphics::mono_font::ascii::{FONT_4X6, FONT_6X9};
+use embedded_graphics::mono_font::MonoFont;
+use embedded_graphics::pixelcolor::BinaryColor;
+use embedded_graphics::prelude::*;
+use embedded_hal::i2c::I2c;
+use ssd1306::mode::BufferedGraphicsMode;
+use ssd1306::prelude::{
+    DisplayConfig, DisplayRotation, DisplaySize128x64, DisplaySize64x48, I2CInterface,
+};
+use ssd1306::{I2CDisplayInterface, Ssd1306};
+
+use crate::settings;
+
+pub(super) type SharedI2c = I2cDevice<'static, NoopRawMutex, twim::Twim<'static>>;
+type Ssd1306Large =
+    Ssd1306<I2CInterface<SharedI2c>, DisplaySize128x64, BufferedGraphicsMode<DisplaySize128x64>>;
+type Ssd1306Small =
+    Ssd1306<I2CInterface<SharedI2c>, DisplaySize64x48, BufferedGraphicsMode<DisplaySize64x48>>;
+
+const OLED_ADDR: u8 = 0x3C;
+const SH1106_STATUS_ID: u8 = 0x08;
+const SH1106_COLUMN_OFFSET: u8 = 2;
+const SH1106_WIDTH: usize = 128;
+const SH1106_PAGES: usize = 8;
+/// Control bytes: a command stream, or display RAM data.
+const CONTROL_COMMAND: u8 = 0x00;
+const CONTROL_DATA: u8 = 0x40;
+const CMD_DISPLAY_OFF: u8 = 0xAE;
+const CMD_DISPLAY_ON: u8 = 0xAF;
+/// Segment remap and COM scan direction for the upright and the flipped
+/// picture.
+const SH1106_ORIENTATION: [u8; 2] = [0xA1, 0xC8];
+const SH1106_ORIENTATION_FLIPPED: [u8; 2] = [0xA0, 0xC0];
+const SH1106_ORIENTATION_AT: usize = 10;
+/// Display off, clock, multiplex 1/64, no offset, start line 0, DC-DC on,
+/// segment remap and reversed COM scan (the SSD1306 driver's `Rotate0`),
+/// alternative COM pins, contrast, pre-charge, VCOM deselect, resume from
+/// RAM, normal.
+const SH1106_INIT: [u8; 22] = [
+    0xAE, 0xD5, 0x80, 0xA8, 0x3F, 0xD3, 0x00, 0x40, 0xAD, 0x8B, 0xA1, 0xC8, 0xDA, 0x12, 0x81, 0x80,
+    0xD9, 0x22, 0xDB, 0x35, 0xA4, 0xA6,
+];
+
+/// `display.panel` values.
+#[derive(Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub(super) enum PanelKind {
+    Ssd1306 = 1,
+    Sh1106 = 2,
+    Ssd1306Small = 3,
+}
+
+pub(super) struct Layout {
+    pub width: i32,
+    pub height: i32,
+    pub font: &'static MonoFont<'static>,
+    pub line_height: i32,
+}
+
+impl Layout {
+    /// Less than the 128x64 the pages were designed for.
+    pub fn compact(&self) -> bool {
+        self.width < 128
+    }
+
+    pub fn chars(&self) -> usize {
+        (self.width / self.font.character_size.width as i32) as usize
+    }
+
+    /// Rows per sparkline on the trend page: two graphs and two labels.
+    pub fn trend_graph_height(&self) -> i32 {
+        (self.height - 2 * self.line_height - 4) / 2
+    }
+}
+
+const LAYOUT_LARGE: Layout = Layout {
+    width: 128,
+    height: 64,
+    font: &FONT_6X9,
+    line_height: 9,
+};
+const LAYOUT_SMALL: Layout = Layout {
+    width: 64,
+    height: 48,
+    font: &FONT_4X6,
+    line_height: 6,
+};
+
+static KIND: AtomicU8 = AtomicU8::new(PanelKind::Ssd1306 as u8);
+static FLIPPED: AtomicBool = AtomicBool::new(false);
+/// FNV-1a over the pixels drawn since the last `clear`.
+static FRAME_HASH: AtomicU32 = AtomicU32::new(FNV_OFFSET);
+/// Hash of the frame the panel shows, if it is known to show one.
+static SHOWN_HASH: AtomicU32 = AtomicU32::new(0);
+static SHOWN_VALID: AtomicBool = AtomicBool::new(false);
+static LAST_FLUSH_CHANGED: AtomicBool = AtomicBool::new(true);
+const FNV_OFFSET: u32 = 0x811C_9DC5;
+const FNV_PRIME: u32 = 0x0100_0193;
+
+/// Layout of the panel found by `Panel::new`.
+pub(super) fn layout() -> &'static Layout {
+    if KIND.load(Ordering::Relaxed) == PanelKind::Ssd1306Small as u8 {
+        &LAYOUT_SMALL
+    } else {
+        &LAYOUT_LARGE
+    }
+}
+
+/// Whether the last `flush` sent a frame that differed from the one shown.
+pub(super) fn last_flush_changed() -> bool {
+    LAST_FLUSH_CHANGED.load(Ordering::Relaxed)
+}
+
+fn mix(mut hash: u32, pixel: &Pixel<BinaryColor>) -> u32 {
+    let Pixel(point, color) = pixel;
+    let (x, y) = (point.x as u32 & 0xFFF, point.y as u32 & 0xFFF);
+    let word = x | (y << 12) | ((color.is_on() as u32) << 24);
+    for byte in word.to_le_bytes() {
+        hash = (hash ^ byte as u32).wrapping_mul(FNV_PRIME);
+    }
+    hash
+}
+
+/// The panel may no longer show the last flushed frame.
+fn forget_shown() {
+    SHOWN_VALID.store(false, Ordering::Relaxed);
+}
+
+/// 128x64 SH1106 with a local frame buffer, one bit per pixel in pages of
+/// eight rows like the controller's RAM.
+pub(super) struct Sh1106 {
+    i2c: SharedI2c,
+    buffer: [u8; SH1106_WIDTH * SH1106_PAGES],
+}
+
+impl Sh1106 {
+    fn new(i2c: SharedI2c) -> Self {
+        Self {
+            i2c,
+            buffer: [0; SH1106_WIDTH * SH1106_PAGES],
+        }
+    }
+
+    fn command(&mut self, bytes: &[u8]) -> bool {
+        let mut buf = [0u8; SH1106_INIT.len() + 1];
+        buf[0] = CONTROL_COMMAND;
+        buf[1..=bytes.len()].copy_from_slice(bytes);
+        self.i2c.write(OLED_ADDR, &buf[..=bytes.len()]).is_ok()
+    }
+
+    fn init(&mut self) -> bool {
+        let mut init = SH1106_INIT;
+        let at = SH1106_ORIENTATION_AT;
+        init[at..at + 2].copy_from_slice(&sh1106_orientation(FLIPPED.load(Ordering::Relaxed)));
+        self.command(&init)
+    }
+
+    fn flush(&mut self) -> bool {
+        let mut data = [0u8; SH1106_WIDTH + 1];
+        data[0] = CONTROL_DATA;
+        for page in 0..SH1106_PAGES {
+            let position = [
+                0xB0 | page as u8,
+                SH1106_COLUMN_OFFSET & 0x0F,
+                0x10 | (SH1106_COLUMN_OFFSET >> 4),
+            ];
+            let row = &self.buffer[page * SH1106_WIDTH..(page + 1) * SH1106_WIDTH];
+            data[1..].copy_from_slice(row);
+            if !self.command(&position) || self.i2c.write(OLED_ADDR, &data).is_err() {
+                return false;
+            }
+        }
+        true
+    }
+
+    fn set_pixel(&mut self, x: i32, y: i32, on: bool) {
+        if !(0..SH1106_WIDTH as i32).contains(&x) || !(0..SH1106_PAGES as i32 * 8).contains(&y) {
+            return;
+        }
+        let index = x as usize + (y as usize / 8) * SH1106_WIDTH;
+        let bit = 1 << (y % 8);
+        if on {
+            self.buffer[index] |= bit;
+        } else {
+            self.buffer[index] &= !bit;
+        }
+    }
+}
+
+pub(super) enum Panel {
+    Ssd1306(Ssd1306Large),
+    Sh1106(Sh1106),
+    Ssd1306Small(Ssd1306Small),
+}
+
+impl Panel {
+    /// Build the driver for the configured or detected variant.
+    pub(super) fn new(mut i2c: SharedI2c) -> Self {
+        let kind = match settings::stored(settings::DISPLAY_PANEL) {
+            1 => PanelKind::Ssd1306,
+            2 => PanelKind::Sh1106,
+            3 => PanelKind::Ssd1306Small,
+            _ => probe(&mut i2c),
+        };
+        let flipped = settings::stored(settings::DISPLAY_FLIP) != 0;
+        defmt::info!("Display panel: {}, flipped: {}", kind, flipped);
+        KIND.store(kind as u8, Ordering::Relaxed);
+        FLIPPED.store(flipped, Ordering::Relaxed);
+        let rotation = ssd1306_rotation(flipped);
+        match kind {
+            PanelKind::Ssd1306 => Self::Ssd1306(
+                Ssd1306::new(I2CDisplayInterface::new(i2c), DisplaySize128x64, rotation)
+                    .into_buffered_graphics_mode(),
+            ),
+            PanelKind::Sh1106 => Self::Sh1106(Sh1106::new(i2c)),
+            PanelKind::Ssd1306Small => Self::Ssd1306Small(
+                Ssd1306::new(I2CDisplayInterface::new(i2c), DisplaySize64x48, rotation)
+                    .into_buffered_graphics_mode(),
+            ),
+        }
+    }
+
+    /// Turn the picture 180° or back; nothing is sent when it already is.
+    pub(super) fn set_flipped(&mut self, flipped: bool) {
+        if FLIPPED.swap(flipped, Ordering::Relaxed) == flipped {
+            return;
+        }
+        let ok = match self {
+            Self::Ssd1306(display) => display.set_rotation(ssd1306_rotation(flipped)).is_ok(),
+            Self::Sh1106(display) => display.command(&sh1106_orientation(flipped)),
+            Self::Ssd1306Small(display) => display.set_rotation(ssd1306_rotation(flipped)).is_ok(),
+        };
+        if ok {
+            // The panel now maps rows differently; redraw the whole frame.
+            forget_shown();
+        } else {
+            defmt::warn!("Display flip failed");
+        }
+    }
+
+    pub(super) fn init(&mut self) -> bool {
+        forget_shown();
+        match self {
+            Self::Ssd1306(display) => display.init().is_ok(),
+            Self::Sh1106(display) => display.init(),
+            Self::Ssd1306Small(display) => display.init().is_ok(),
+        }
+    }
+
+    pub(super) fn flush(&mut self) -> bool {
+        let hash = FRAME_HASH.load(Ordering::Relaxed);
+        let shown = SHOWN_VALID.load(Ordering::Relaxed);
+        if shown && SHOWN_HASH.load(Ordering::Relaxed) == hash {
+            LAST_FLUSH_CHANGED.store(false, Ordering::Relaxed);
+            return true;
+        }
+        LAST_FLUSH_CHANGED.store(true, Ordering::Relaxed);
+        let ok = match self {
+            Self::Ssd1306(display) => display.flush().is_ok(),
+            Self::Sh1106(display) => display.flush(),
+            Self::Ssd1306Small(display) => display.flush().is_ok(),
+        };
+        SHOWN_HASH.store(hash, Ordering::Relaxed);
+        SHOWN_VALID.store(ok, Ordering::Relaxed);
+        ok
+    }
+
+    pub(super) fn set_display_on(&mut self, on: bool) -> bool {
+        forget_shown();
+        match self {
+            Self::Ssd1306(display) => display.set_display_on(on).is_ok(),
+            Self::Sh1106(display) => {
+                display.command(&[if on { CMD_DISPLAY_ON } else { CMD_DISPLAY_OFF }])
+            }
+            Self::Ssd1306Small(display) => display.set_display_on(on).is_ok(),
+        }
+    }
+}
+
+fn ssd1306_rotation(flipped: bool) -> DisplayRotation {
+    if flipped {
+        DisplayRotation::Rotate180
+    } else {
+        DisplayRotation::Rotate0
+    }
+}
+
+fn sh1106_orientation(flipped: bool) -> [u8; 2] {
+    if flipped {
+        SH1106_ORIENTATION_FLIPPED
+    } else {
+        SH1106_ORIENTATION
+    }
+}
+
+/// Tell the 128x64 controllers apart by their status byte.
+fn probe(i2c: &mut SharedI2c) -> PanelKind {
+    let mut status = [0u8; 1];
+    match i2c.read(OLED_ADDR, &mut status) {
+        Ok(()) if status[0] & 0x0F == SH1106_STATUS_ID => PanelKind::Sh1106,
+        _ => PanelKind::Ssd1306,
+    }
+}
+
+impl OriginDimensions for Panel {
+    fn size(&self) -> Size {
+        let layout = layout();
+        Size::new(layout.width as u32, layout.height as u32)
+    }
+}
+
+impl DrawTarget for Panel {
+    type Color = BinaryColor;
+    type Error = core::convert::Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        let mut hash = FRAME_HASH.load(Ordering::Relaxed);
+        let pixels = pixels.into_iter().inspect(|pixel| hash = mix(hash, pixel));
+        match self {
+            Self::Ssd1306(display) => {
+                let _ = display.draw_iter(pixels);
+            }
+            Self::Sh1106(display) => {
+                for Pixel(point, color) in pixels {
+                    display.set_pixel(point.x, point.y, color.is_on());
+                }
+            }
+            Self::Ssd1306Small(display) => {
+                let _ = display.draw_iter(pixels);
+            }
+        }
+        FRAME_HASH.store(hash, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn clear(&mut self, color: Self::Color) -> Result<(), Self::Error> {
+        let seed = FNV_OFFSET ^ color.is_on() as u32;
+        FRAME_HASH.store(seed.wrapping_mul(FNV_PRIME), Ordering::Relaxed);
+        match self {
+            Self::Ssd1306(display) => {
+                let _ = display.clear(color);
+            }
+            Self::Sh1106(display) => {
+                display.buffer.fill(if color.is_on() { 0xFF } else { 0x00 });
+            }
+            Self::Ssd1306Small(display) => {
+                let _ = display.clear(color);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,602 @@
+//! Long file names for the day files the firmware creates on the SD card.
+//!
+//! embedded-sdmmc reads long names but only writes 8.3 entries, so a day log
+//! shows up on a PC as `20250101.GPZ`. This module writes the long-name
+//! entries itself, straight to the card's blocks, and leaves the file to
+//! embedded-sdmmc from then on.
+//!
+//! # Design
+//!
+//! - `create` writes the whole entry set of an empty file, the long-name
+//!   entries and the 8.3 entry they belong to, into the first run of free
+//!   slots of the directory. embedded-sdmmc then opens the file by its 8.3
+//!   name, grows it and updates that entry in place.
+//! - The directory is found by walking its path from the root of the first
+//!   partition, the volume embedded-sdmmc mounts. FAT16 and FAT32 only.
+//! - Nothing is allocated. A directory without a free run leaves the file
+//!   to embedded-sdmmc, which grows the directory and creates it 8.3 only.
+//! - `forget` marks the long-name entries in front of an 8.3 entry free.
+//!   embedded-sdmmc only marks the 8.3 entry when it deletes a file, which
+//!   would leave the long name orphaned.
+//! - The 8.3 name stays the one the firmware and companions use; the long
+//!   name is for people reading the card on a PC.
+
+pub const BLOCK_LEN: usize = 512;
+pub type Block = [u8; BLOCK_LEN];
+
+/// Raw block access to the card.
+pub trait Blocks {
+    fn read(&mut self, idx: u32, block: &mut Block) -> bool;
+    fn write(&mut self, idx: u32, block: &Block) -> bool;
+}
+
+/// Longest long name written.
+pub const MAX_LONG_NAME: usize = 32;
+
+const ENTRY_LEN: usize = 32;
+const ENTRY_END: u8 = 0x00;
+const ENTRY_FREE: u8 = 0xE5;
+const ATTR_VOLUME_ID: u8 = 0x08;
+const ATTR_DIRECTORY: u8 = 0x10;
+const ATTR_ARCHIVE: u8 = 0x20;
+const ATTR_LONG_NAME: u8 = 0x0F;
+/// Set in the order byte of the first long-name entry, the name's last part.
+const LAST_LONG_ENTRY: u8 = 0x40;
+const CHARS_PER_ENTRY: usize = 13;
+/// Offsets of the 13 UCS-2 characters in a long-name entry.
+const CHAR_OFFSETS: [usize; CHARS_PER_ENTRY] = [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+const MAX_LONG_ENTRIES: usize = MAX_LONG_NAME.div_ceil(CHARS_PER_ENTRY);
+/// Long-name entries `forget` looks back over: a 255-character name.
+const MAX_FORGET_ENTRIES: usize = 20;
+/// Bound on a directory's cluster chain, against a looped FAT.
+const MAX_DIR_CLUSTERS: usize = 4096;
+
+/// Day files that get a long name: extension and what the file holds.
+const DAY_FILE_KINDS: [(&[u8], &[u8]); 3] = [
+    (b"gpz", b"track"),
+    (b"gpx", b"track"),
+    (b"wpt", b"waypoints"),
+];
+
+pub struct LongName {
+    buf: [u8; MAX_LONG_NAME],
+    len: usize,
+}
+
+impl LongName {
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
+
+/// `YYYY-MM-DD_track.gpz` and so on for the day file `YYYYMMDD.ext`;
+/// `None` for any other file.
+pub fn day_long_name(short: &[u8]) -> Option<LongName> {
+    let [date @ .., b'.', e0, e1, e2] = short else {
+        return None;
+    };
+    let ext = [*e0, *e1, *e2].map(|b| b.to_ascii_lowercase());
+    let (_, kind) = DAY_FILE_KINDS.iter().find(|(known, _)| *known == ext)?;
+    if date.len() != 8 || !date.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    let mut name = LongName {
+        buf: [0; MAX_LONG_NAME],
+        len: 0,
+    };
+    let parts: [&[u8]; 9] = [
+        &date[..4],
+        b"-",
+        &date[4..6],
+        b"-",
+        &date[6..],
+        b"_",
+        kind,
+        b".",
+        &ext,
+    ];
+    for part in parts {
+        name.buf[name.len..name.len + part.len()].copy_from_slice(part);
+        name.len += part.len();
+    }
+    Some(name)
+}
+
+/// The 11-byte directory form of an 8.3 name, `None` if it is not one.
+pub fn short_name(name: &[u8]) -> Option<[u8; 11]> {
+    match name.iter().rposition(|&b| b == b'.') {
+        Some(dot) => short_name_parts(&name[..dot], &name[dot + 1..]),
+        None => short_name_parts(name, &[]),
+    }
+}
+
+/// `short_name` of a base name and extension given apart.
+pub fn short_name_parts(base: &[u8], ext: &[u8]) -> Option<[u8; 11]> {
+    let valid = |part: &[u8]| {
+        part.iter()
+            .all(|&b| b.is_ascii_alphanumeric() || matches!(b, b'_' | b'-' | b'~'))
+    };
+    if base.is_empty() || base.len() > 8 || ext.len() > 3 || !valid(base) || !valid(ext) {
+        return None;
+    }
+    let mut out = [b' '; 11];
+    out[..base.len()].copy_from_slice(base);
+    out[8..8 + ext.len()].copy_from_slice(ext);
+    Some(out.map(|b| b.to_ascii_uppercase()))
+}
+
+/// Create the empty file `short` with the long name `long` in the directory
+/// at `path`, stamped with `stamp` (FAT date in the high half, time in the
+/// low). Returns whether it did; false when the file exists, the directory
+/// has no free run or the card failed, and embedded-sdmmc creates it 8.3.
+pub fn create(
+    dev: &mut impl Blocks,
+    path: &[u8],
+    short: &[u8; 11],
+    long: &[u8],
+    stamp: u32,
+) -> bool {
+    if long.is_empty() || long.len() > MAX_LONG_NAME || !long.is_ascii() {
+        return false;
+    }
+    let Some(volume) = Volume::mount(dev) else {
+        return false;
+    };
+    let Some(dir) = volume.open_path(dev, path) else {
+        return false;
+    };
+
+    let long_entries = long.len().div_ceil(CHARS_PER_ENTRY);
+    let need = long_entries + 1;
+    let mut run = [Slot::default(); MAX_LONG_ENTRIES + 2];
+    let mut run_len = 0;
+    // Past the end marker every slot is free, and the run may take some;
+    // then the slot after the run becomes the end marker.
+    let (mut at_end, mut run_in_end) = (false, false);
+    let mut exists = false;
+    let scanned = volume.scan(dev, dir, |slot, entry| {
+        at_end |= entry[0] == ENTRY_END;
+        if run_len < need {
+            if at_end || entry[0] == ENTRY_FREE {
+                run[run_len] = slot;
+                run_len += 1;
+                run_in_end |= at_end;
+            } else {
+                run_len = 0;
+            }
+        } else if run_in_end {
+            run[run_len] = slot;
+            run_len += 1;
+            return false;
+        }
+        if !at_end && is_named(entry, short) {
+            exists = true;
+            return false;
+        }
+        !(at_end && run_len == need && !run_in_end)
+    });
+    if !scanned || exists || run_len < need {
+        return false;
+    }
+
+    let mut entries = [[0u8; ENTRY_LEN]; MAX_LONG_ENTRIES + 2];
+    let checksum = short_name_checksum(short);
+    for (i, entry) in entries[..long_entries].iter_mut().enumerate() {
+        // The first slot holds the last part of the name.
+        let order = long_entries - i;
+        entry[0] = order as u8 | if i == 0 { LAST_LONG_ENTRY } else { 0 };
+        entry[11] = ATTR_LONG_NAME;
+        entry[13] = checksum;
+        for (k, &at) in CHAR_OFFSETS.iter().enumerate() {
+            let pos = (order - 1) * CHARS_PER_ENTRY + k;
+            let c = match long.get(pos) {
+                Some(&b) => b as u16,
+                None if pos == long.len() => 0,
+                None => 0xFFFF,
+            };
+            entry[at..at + 2].copy_from_slice(&c.to_le_bytes());
+        }
+    }
+    let entry = &mut entries[long_entries];
+    entry[..11].copy_from_slice(short);
+    entry[11] = ATTR_ARCHIVE;
+    let (date, time) = ((stamp >> 16) as u16, stamp as u16);
+    for at in [14, 22] {
+        entry[at..at + 2].copy_from_slice(&time.to_le_bytes());
+    }
+    for at in [16, 18, 24] {
+        entry[at..at + 2].copy_from_slice(&date.to_le_bytes());
+    }
+    // Any slot after the run is left all zero: the new end marker.
+    patch(dev, &run[..run_len], |i, slot| {
+        slot.copy_from_slice(&entries[i])
+    })
+}
+
+/// Mark the long-name entries of `short` in the directory at `path` free,
+/// ahead of deleting it. Returns false only when the card failed.
+pub fn forget(dev: &mut impl Blocks, path: &[u8], short: &[u8; 11]) -> bool {
+    let Some(volume) = Volume::mount(dev) else {
+        return false;
+    };
+    let Some(dir) = volume.open_path(dev, path) else {
+        return true;
+    };
+    let checksum = short_name_checksum(short);
+    let mut longs = [Slot::default(); MAX_FORGET_ENTRIES];
+    let mut count = 0;
+    let mut found = false;
+    let scanned = volume.scan(dev, dir, |slot, entry| {
+        if entry[0] == ENTRY_END {
+            return false;
+        }
+        if entry[0] != ENTRY_FREE && entry[11] == ATTR_LONG_NAME && entry[13] == checksum {
+            if entry[0] & LAST_LONG_ENTRY != 0 {
+                count = 0;
+            }
+            if count < MAX_FORGET_ENTRIES {
+                longs[count] = slot;
+                count += 1;
+            }
+            return true;
+        }
+        if is_named(entry, short) {
+            found = true;
+            return false;
+        }
+        count = 0;
+        true
+    });
+    if !scanned {
+        return false;
+    }
+    !found || count == 0 || patch(dev, &longs[..count], |_, slot| slot[0] = ENTRY_FREE)
+}
+
+fn short_name_checksum(name: &[u8; 11]) -> u8 {
+    name.iter()
+        .fold(0u8, |sum, &b| sum.rotate_right(1).wrapping_add(b))
+}
+
+/// Whether `entry` is the live 8.3 entry of `short`.
+fn is_named(entry: &[u8], short: &[u8; 11]) -> bool {
+    entry[0] != ENTRY_FREE
+        && entry[11] != ATTR_LONG_NAME
+        && entry[11] & ATTR_VOLUME_ID == 0
+        && entry[..11] == short[..]
+}
+
+/// Rewrite the entries at `slots`, given in directory order.
+fn patch(dev: &mut impl Blocks, slots: &[Slot], mut f: impl FnMut(usize, &mut [u8])) -> bool {
+    let mut block = [0u8; BLOCK_LEN];
+    let mut i = 0;
+    while i < slots.len() {
+        let idx = slots[i].block;
+        if !dev.read(idx, &mut block) {
+            return false;
+        }
+        while i < slots.len() && slots[i].block == idx {
+            let at = slots[i].index * ENTRY_LEN;
+            f(i, &mut block[at..at + ENTRY_LEN]);
+            i += 1;
+        }
+        if !dev.write(idx, &block) {
+            return false;
+        }
+    }
+    true
+}
+
+fn le16(bytes: &[u8], at: usize) -> u32 {
+    u16::from_le_bytes([bytes[at], bytes[at + 1]]) as u32
+}
+
+fn le32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+}
+
+/// A directory entry: its block and index within it.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+struct Slot {
+    block: u32,
+    index: usize,
+}
+
+#[derive(Clone, Copy)]
+enum Dir {
+    /// The FAT16 root directory, a fixed run of blocks.
+    Fixed { start: u32, blocks: u32 },
+    /// Any other directory, by its first cluster.
+    Chain(u32),
+}
+
+struct Volume {
+    fat_start: u32,
+    data_start: u32,
+    blocks_per_cluster: u32,
+    fat32: bool,
+    root: Dir,
+}
+
+impl Volume {
+    /// The FAT volume in the first partition of the MBR.
+    fn mount(dev: &mut impl Blocks) -> Option<Self> {
+        let mut block = [0u8; BLOCK_LEN];
+        if !dev.read(0, &mut block) || block[510..] != [0x55, 0xAA] {
+            return None;
+        }
+        let start = le32(&block, 0x1C6);
+        if !dev.read(start, &mut block) || block[510..] != [0x55, 0xAA] {
+            return None;
+        }
+        let blocks_per_cluster = block[13] as u32;
+        if le16(&block, 11) != BLOCK_LEN as u32 || blocks_per_cluster == 0 {
+            return None;
+        }
+        let fat_start = start + le16(&block, 14);
+        let fat16_len = le16(&block, 22);
+        let fat32 = fat16_len == 0;
+        let fat_len = if fat32 { le32(&block, 36) } else { fat16_len };
+        let root_start = fat_start + block[16] as u32 * fat_len;
+        let root_blocks = (le16(&block, 17) * ENTRY_LEN as u32).div_ceil(BLOCK_LEN as u32);
+        let root = if fat32 {
+            Dir::Chain(le32(&block, 44))
+        } else {
+            Dir::Fixed {
+                start: root_start,
+                blocks: root_blocks,
+            }
+        };
+        Some(Self {
+            fat_start,
+            data_start: root_start + root_blocks,
+            blocks_per_cluster,
+            fat32,
+            root,
+        })
+    }
+
+    /// The directory at `path`, `/`-separated 8.3 names from the root.
+    fn open_path(&self, dev: &mut impl Blocks, path: &[u8]) -> Option<Dir> {
+        let mut dir = self.root;
+        for component in path.split(|&b| b == b'/').filter(|c| !c.is_empty()) {
+            let name = short_name(component)?;
+            let mut found = None;
+            let scanned = self.scan(dev, dir, |_, entry| {
+                if entry[0] == ENTRY_END {
+                    return false;
+                }
+                if is_named(entry, &name) && entry[11] & ATTR_DIRECTORY != 0 {
+                    found = Some(le16(entry, 20) << 16 | le16(entry, 26));
+                    return false;
+                }
+                true
+            });
+            dir = Dir::Chain(found.filter(|&cluster| scanned && cluster >= 2)?);
+        }
+        Some(dir)
+    }
+
+    /// Visit every entry of `dir` in order until `f` returns false.
+    /// Returns false if a block could not be read.
+    fn scan<D: Blocks>(
+        &self,
+        dev: &mut D,
+        dir: Dir,
+        mut f: impl FnMut(Slot, &[u8]) -> bool,
+    ) -> bool {
+        let mut block = [0u8; BLOCK_LEN];
+        // Some(false) once `f` asked to stop.
+        let mut visit = |dev: &mut D, idx: u32| -> Option<bool> {
+            if !dev.read(idx, &mut block) {
+                return None;
+            }
+            let mut entries = block.chunks_exact(ENTRY_LEN).enumerate();
+            Some(entries.all(|(index, entry)| f(Slot { block: idx, index }, entry)))
+        };
+        match dir {
+            Dir::Fixed { start, blocks } => {
+                for idx in start..start + blocks {
+                    match visit(dev, idx) {
+                        Some(true) => {}
+                        Some(false) => return true,
+                        None => return false,
+                    }
+                }
+                true
+            }
+            Dir::Chain(mut cluster) => {
+                for _ in 0..MAX_DIR_CLUSTERS {
+                    let first = self.data_start + (cluster - 2) * self.blocks_per_cluster;
+                    for idx in first..first + self.blocks_per_cluster {
+                        match visit(dev, idx) {
+                            Some(true) => {}
+                            Some(false) => return true,
+                            None => return false,
+                        }
+                    }
+                    match self.next_cluster(dev, cluster) {
+                        Ok(Some(next)) => cluster = next,
+                        Ok(None) => return true,
+                        Err(()) => return false,
+                    }
+                }
+                false
+            }
+        }
+    }
+
+    /// The cluster after `cluster` in its chain; `None` at the end.
+    fn next_cluster(&self, dev: &mut impl Blocks, cluster: u32) -> Result<Option<u32>, ()> {
+        let offset = cluster * if self.fat32 { 4 } else { 2 };
+        let mut block = [0u8; BLOCK_LEN];
+        if !dev.read(self.fat_start + offset / BLOCK_LEN as u32, &mut block) {
+            return Err(());
+        }
+        let at = (offset % BLOCK_LEN as u32) as usize;
+        let (next, end) = if self.fat32 {
+            (le32(&block, at) & 0x0FFF_FFFF, 0x0FFF_FFF8)
+        } else {
+            (le16(&block, at), 0xFFF8)
+        };
+        Ok((2..end).contains(&next).then_some(next))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// FAT16, one block per cluster: MBR, boot sector at 1, FAT at 2, a
+    /// one-block root at 3, clusters from 4. `/2025` is cluster 2 and
+    /// `/2025/01` clusters 3 and 4, its first block full but for a free
+    /// last slot.
+    struct Card(Vec<Block>);
+
+    impl Blocks for Card {
+        fn read(&mut self, idx: u32, block: &mut Block) -> bool {
+            self.0
+                .get(idx as usize)
+                .map(|b| block.copy_from_slice(b))
+                .is_some()
+        }
+
+        fn write(&mut self, idx: u32, block: &Block) -> bool {
+            self.0
+                .get_mut(idx as usize)
+                .map(|b| b.copy_from_slice(block))
+                .is_some()
+        }
+    }
+
+    fn dir_entry(name: &[u8; 11], attr: u8, cluster: u16) -> [u8; ENTRY_LEN] {
+        let mut entry = [0u8; ENTRY_LEN];
+        entry[..11].copy_from_slice(name);
+        entry[11] = attr;
+        entry[26..28].copy_from_slice(&cluster.to_le_bytes());
+        entry
+    }
+
+    fn card() -> Card {
+        let mut blocks = vec![[0u8; BLOCK_LEN]; 7];
+        blocks[0][0x1C6] = 1;
+        blocks[1][11..13].copy_from_slice(&512u16.to_le_bytes());
+        blocks[1][13] = 1;
+        blocks[1][14] = 1;
+        blocks[1][16] = 1;
+        blocks[1][17] = 16;
+        blocks[1][22] = 1;
+        for block in &mut blocks[..2] {
+            block[510..].copy_from_slice(&[0x55, 0xAA]);
+        }
+        for (cluster, next) in [(2, 0xFFFF), (3, 4), (4, 0xFFFF)] {
+            blocks[2][cluster * 2..cluster * 2 + 2].copy_from_slice(&u16::to_le_bytes(next));
+        }
+        blocks[3][..32].copy_from_slice(&dir_entry(b"2025       ", ATTR_DIRECTORY, 2));
+        blocks[4][..32].copy_from_slice(&dir_entry(b".          ", ATTR_DIRECTORY, 2));
+        blocks[4][32..64].copy_from_slice(&dir_entry(b"..         ", ATTR_DIRECTORY, 0));
+        blocks[4][64..96].copy_from_slice(&dir_entry(b"01         ", ATTR_DIRECTORY, 3));
+        for (i, slot) in blocks[5].chunks_exact_mut(ENTRY_LEN).enumerate() {
+            let name = short_name(format!("NOTE{i}.TXT").as_bytes()).unwrap();
+            slot.copy_from_slice(&dir_entry(&name, ATTR_ARCHIVE, 0));
+        }
+        blocks[5][15 * ENTRY_LEN] = ENTRY_FREE;
+        Card(blocks)
+    }
+
+    fn slot(card: &Card, block: usize, index: usize) -> &[u8] {
+        &card.0[block][index * ENTRY_LEN..][..ENTRY_LEN]
+    }
+
+    /// The long name stored in `entries`, last part first as on the card.
+    fn read_long_name(entries: &[&[u8]], short: &[u8; 11]) -> String {
+        let mut name = Vec::new();
+        for (i, entry) in entries.iter().rev().enumerate() {
+            assert_eq!(entry[0] & !LAST_LONG_ENTRY, i as u8 + 1);
+            assert_eq!(
+                (entry[11], entry[13]),
+                (ATTR_LONG_NAME, short_name_checksum(short))
+            );
+            name.extend(CHAR_OFFSETS.iter().map(|&at| le16(entry, at) as u16));
+        }
+        assert_ne!(entries[0][0] & LAST_LONG_ENTRY, 0);
+        let end = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+        String::from_utf16(&name[..end]).unwrap()
+    }
+
+    #[test]
+    fn names_day_files() {
+        let name = |short: &str| day_long_name(short.as_bytes()).map(|n| n.as_bytes().to_vec());
+        assert_eq!(name("20250101.gpz").unwrap(), b"2025-01-01_track.gpz");
+        assert_eq!(name("20250101.GPX").unwrap(), b"2025-01-01_track.gpx");
+        assert_eq!(name("20251231.wpt").unwrap(), b"2025-12-31_waypoints.wpt");
+        assert!(name("20250101.sts").is_none());
+        assert!(name("BATT.CSV").is_none());
+        assert_eq!(short_name(b"20250101.gpz"), Some(*b"20250101GPZ"));
+        assert_eq!(short_name(b"2025"), Some(*b"2025       "));
+        assert_eq!(short_name(b"toolongname.txt"), None);
+    }
+
+    #[test]
+    fn creates_long_name_across_clusters() {
+        let mut card = card();
+        let short = short_name(b"20250101.gpz").unwrap();
+        let long = day_long_name(b"20250101.gpz").unwrap();
+        let stamp = 0x5A21_6000;
+        assert!(create(
+            &mut card,
+            b"2025/01",
+            &short,
+            long.as_bytes(),
+            stamp
+        ));
+
+        let entries = [slot(&card, 5, 15), slot(&card, 6, 0)];
+        assert_eq!(read_long_name(&entries, &short), "2025-01-01_track.gpz");
+        let entry = slot(&card, 6, 1);
+        assert_eq!(entry[..12], dir_entry(&short, ATTR_ARCHIVE, 0)[..12]);
+        assert_eq!((le16(entry, 22), le16(entry, 24)), (0x6000, 0x5A21));
+        assert_eq!((le16(entry, 26), le32(entry, 28)), (0, 0));
+        assert_eq!(slot(&card, 6, 2), [0u8; ENTRY_LEN]);
+
+        // Already there: left for embedded-sdmmc to open.
+        assert!(!create(
+            &mut card,
+            b"2025/01",
+            &short,
+            long.as_bytes(),
+            stamp
+        ));
+        assert!(!create(
+            &mut card,
+            b"2025/02",
+            &short,
+            long.as_bytes(),
+            stamp
+        ));
+    }
+
+    #[test]
+    fn forget_frees_the_long_entries_for_reuse() {
+        let mut card = card();
+        let short = short_name(b"20250101.gpz").unwrap();
+        let long = day_long_name(b"20250101.gpz").unwrap();
+        assert!(create(&mut card, b"2025/01", &short, long.as_bytes(), 0));
+        assert!(forget(&mut card, b"/2025/01", &short));
+        assert_eq!(
+            (slot(&card, 5, 15)[0], slot(&card, 6, 0)[0]),
+            (ENTRY_FREE, ENTRY_FREE)
+        );
+        assert_eq!(slot(&card, 6, 1)[..11], short);
+
+        // embedded-sdmmc deletes the 8.3 entry; the next day takes the run.
+        card.0[6][ENTRY_LEN] = ENTRY_FREE;
+        let next = short_name(b"20250102.wpt").unwrap();
+        let long = day_long_name(b"20250102.wpt").unwrap();
+        assert!(create(&mut card, b"2025/01", &next, long.as_bytes(), 0));
+        let entries = [slot(&card, 5, 15), slot(&card, 6, 0)];
+        assert_eq!(read_long_name(&entries, &next), "2025-01-02_waypoints.wpt");
+        assert_eq!(slot(&card, 6, 1)[..11], next);
+        assert_eq!(slot(&card, 6, 2)[0], ENTRY_END);
+    }
+}
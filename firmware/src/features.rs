@@ -0,0 +1,103 @@
+//! Compile-time capability registry.
+//!
+//! Optional subsystems are Cargo features; this module turns the enabled set
+//! into a bitmask the host reads with the `HELLO` command, so apps can hide
+//! UI for commands the firmware was built without instead of probing them.
+//!
+//! Bits are part of the protocol: never renumber, only append. The first
+//! word is full; new bits go in `CAPABILITIES_EXT`.
+
+pub const PROTOCOL_VERSION_MAJOR: u8 = 1;
+pub const PROTOCOL_VERSION_MINOR: u8 = 49;
+
+pub const CAP_SD_STORAGE: u32 = 1 << 0;
+pub const CAP_FINDMY: u32 = 1 << 1;
+pub const CAP_GOOGLE_FMDN: u32 = 1 << 2;
+pub const CAP_GPS_PPS: u32 = 1 << 3;
+pub const CAP_NAV: u32 = 1 << 4;
+pub const CAP_GPIO_HOOKS: u32 = 1 << 5;
+pub const CAP_TIMEZONE: u32 = 1 << 6;
+pub const CAP_RECORDING: u32 = 1 << 7;
+pub const CAP_SESSIONS: u32 = 1 << 8;
+pub const CAP_VIBRATION: u32 = 1 << 9;
+pub const CAP_STEPS: u32 = 1 << 10;
+pub const CAP_DIAGNOSTICS: u32 = 1 << 11;
+pub const CAP_LORA: u32 = 1 << 12;
+pub const CAP_POWER_MONITOR: u32 = 1 << 13;
+pub const CAP_SETTINGS: u32 = 1 << 14;
+pub const CAP_MARKERS: u32 = 1 << 15;
+pub const CAP_GEOFENCES: u32 = 1 << 16;
+pub const CAP_SESSION_META: u32 = 1 << 17;
+pub const CAP_LIVE_RELAY: u32 = 1 << 18;
+pub const CAP_TRANSFER_V2: u32 = 1 << 19;
+pub const CAP_SYS_INFO_SCHEMA: u32 = 1 << 20;
+pub const CAP_AGNSS_FRESHNESS: u32 = 1 << 21;
+pub const CAP_TRACK_STATS: u32 = 1 << 22;
+pub const CAP_TIME_SYNC: u32 = 1 << 23;
+pub const CAP_ALERTS: u32 = 1 << 24;
+pub const CAP_CONFIG_GATT: u32 = 1 << 25;
+pub const CAP_AGNSS_STREAM: u32 = 1 << 26;
+pub const CAP_DFU: u32 = 1 << 27;
+pub const CAP_FIX_STATS: u32 = 1 << 28;
+pub const CAP_NMEA_PASSTHROUGH: u32 = 1 << 29;
+pub const CAP_KEEP_ALIVE_QUERY: u32 = 1 << 30;
+pub const CAP_CRASH_REPORT: u32 = 1 << 31;
+
+pub const CAP_EXT_TRANSFER_SEQ: u32 = 1 << 0;
+pub const CAP_EXT_FLASH_TRACK: u32 = 1 << 1;
+pub const CAP_EXT_BUZZER: u32 = 1 << 2;
+pub const CAP_EXT_BONDING: u32 = 1 << 3;
+pub const CAP_EXT_BATTERY_HISTORY: u32 = 1 << 4;
+pub const CAP_EXT_AGNSS_LNAV: u32 = 1 << 5;
+pub const CAP_EXT_FIRMWARE_SLOTS: u32 = 1 << 6;
+
+const ALWAYS_ON: u32 = CAP_GPIO_HOOKS
+    | CAP_TIMEZONE
+    | CAP_RECORDING
+    | CAP_SESSIONS
+    | CAP_DIAGNOSTICS
+    | CAP_SETTINGS
+    | CAP_LIVE_RELAY
+    | CAP_SYS_INFO_SCHEMA
+    | CAP_AGNSS_FRESHNESS
+    | CAP_TRACK_STATS
+    | CAP_TIME_SYNC
+    | CAP_ALERTS
+    | CAP_CONFIG_GATT
+    | CAP_AGNSS_STREAM
+    | CAP_DFU
+    | CAP_FIX_STATS
+    | CAP_NMEA_PASSTHROUGH
+    | CAP_KEEP_ALIVE_QUERY
+    | CAP_CRASH_REPORT;
+/// Everything driven by the shared I2C/SPI bus: SD card and accelerometer.
+const BUS_PERIPHERALS: u32 = CAP_SD_STORAGE
+    | CAP_VIBRATION
+    | CAP_STEPS
+    | CAP_MARKERS
+    | CAP_GEOFENCES
+    | CAP_SESSION_META
+    | CAP_TRANSFER_V2;
+
+const fn flag(enabled: bool, cap: u32) -> u32 {
+    if enabled { cap } else { 0 }
+}
+
+/// Capabilities compiled into this firmware image.
+pub const CAPABILITIES: u32 = ALWAYS_ON
+    | flag(cfg!(feature = "i2c-spi"), BUS_PERIPHERALS)
+    | flag(cfg!(feature = "findmy"), CAP_FINDMY)
+    | flag(cfg!(feature = "google-fmdn"), CAP_GOOGLE_FMDN)
+    | flag(cfg!(feature = "gps-pps"), CAP_GPS_PPS)
+    | flag(cfg!(feature = "nav"), CAP_NAV)
+    | flag(cfg!(feature = "lora"), CAP_LORA)
+    | flag(cfg!(feature = "power-monitor"), CAP_POWER_MONITOR);
+
+/// Second capability word, after `MaxPayload` in the `HELLO` response.
+pub const CAPABILITIES_EXT: u32 = CAP_EXT_FLASH_TRACK
+    | CAP_EXT_BONDING
+    | CAP_EXT_BATTERY_HISTORY
+    | CAP_EXT_AGNSS_LNAV
+    | CAP_EXT_FIRMWARE_SLOTS
+    | flag(cfg!(feature = "i2c-spi"), CAP_EXT_TRANSFER_SEQ)
+    | flag(cfg!(feature = "buzzer"), CAP_EXT_BUZZER);
@@ -0,0 +1,210 @@
+//! Bulk delete / archive of daily logs by date range.
+//!
+//! Removing months of `YYYY/MM/YYYYMMDD.gpz` files with one `DELETE_FILE`
+//! per file is slow from the app. A job instead walks an inclusive date
+//! range on its own task and applies one action to each day's log:
+//! delete it, or move it to `/ARCHIVE/YYYY/MM/`.
+//!
+//! # Design
+//!
+//! - One job at a time. The protocol starts, queries and cancels it; the
+//!   BLE task notifies the progress record whenever it changes.
+//! - Each day takes its own SD locks at transfer priority, and an archive
+//!   copy one per kilobyte, so GPX logging and file transfers interleave
+//!   with a long job.
+//! - Today's open log and days hit while a transfer has a file open are
+//!   skipped, never touched.
+
+use core::sync::atomic::{AtomicBool, AtomicU16, AtomicU8, Ordering};
+
+use embassy_executor::task;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+
+use crate::storage::{self, LogFileAction, LogFileOutcome};
+
+/// `[state][action][days_done: u16][days_total: u16][done: u16][skipped: u16][failed: u16]`
+pub const PROGRESS_LEN: usize = 12;
+/// About ten years; keeps day counts within `u16`.
+pub const MAX_RANGE_DAYS: u32 = 3660;
+
+const STATE_IDLE: u8 = 0;
+const STATE_RUNNING: u8 = 1;
+const STATE_DONE: u8 = 2;
+const STATE_CANCELLED: u8 = 3;
+/// Empty days only report progress this often.
+const EMPTY_DAYS_PER_NOTIFY: u16 = 31;
+
+#[derive(Clone, Copy)]
+struct Job {
+    action: LogFileAction,
+    start: (u16, u8, u8),
+    days: u16,
+}
+
+static JOB_REQUEST: Signal<CriticalSectionRawMutex, Job> = Signal::new();
+static PROGRESS_CHANGED: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+static CANCEL: AtomicBool = AtomicBool::new(false);
+static STATE: AtomicU8 = AtomicU8::new(STATE_IDLE);
+static ACTION: AtomicU8 = AtomicU8::new(0);
+static DAYS_DONE: AtomicU16 = AtomicU16::new(0);
+static DAYS_TOTAL: AtomicU16 = AtomicU16::new(0);
+static FILES_DONE: AtomicU16 = AtomicU16::new(0);
+static FILES_SKIPPED: AtomicU16 = AtomicU16::new(0);
+static FILES_FAILED: AtomicU16 = AtomicU16::new(0);
+
+fn action_id(action: LogFileAction) -> u8 {
+    match action {
+        LogFileAction::Delete => 0,
+        LogFileAction::Archive => 1,
+    }
+}
+
+/// Start a job over `start..=end` (`YYYYMMDD` each). Returns `false` while
+/// another job runs or for an invalid or too long range.
+pub fn start(action: LogFileAction, start: u32, end: u32) -> bool {
+    let (Some(first), Some(last)) = (parse_date(start), parse_date(end)) else {
+        return false;
+    };
+    if start > end {
+        return false;
+    }
+    let mut days = 1u32;
+    let mut day = first;
+    while day != last {
+        if days >= MAX_RANGE_DAYS {
+            return false;
+        }
+        day = next_day(day);
+        days += 1;
+    }
+    if STATE
+        .fetch_update(Ordering::AcqRel, Ordering::Acquire, |state| {
+            (state != STATE_RUNNING).then_some(STATE_RUNNING)
+        })
+        .is_err()
+    {
+        return false;
+    }
+    CANCEL.store(false, Ordering::Release);
+    ACTION.store(action_id(action), Ordering::Release);
+    DAYS_DONE.store(0, Ordering::Release);
+    DAYS_TOTAL.store(days as u16, Ordering::Release);
+    FILES_DONE.store(0, Ordering::Release);
+    FILES_SKIPPED.store(0, Ordering::Release);
+    FILES_FAILED.store(0, Ordering::Release);
+    JOB_REQUEST.signal(Job {
+        action,
+        start: first,
+        days: days as u16,
+    });
+    PROGRESS_CHANGED.signal(());
+    true
+}
+
+/// Stop the running job after the current day.
+pub fn cancel() {
+    if STATE.load(Ordering::Acquire) == STATE_RUNNING {
+        CANCEL.store(true, Ordering::Release);
+    }
+}
+
+pub fn progress() -> [u8; PROGRESS_LEN] {
+    let mut out = [0u8; PROGRESS_LEN];
+    out[0] = STATE.load(Ordering::Acquire);
+    out[1] = ACTION.load(Ordering::Acquire);
+    out[2..4].copy_from_slice(&DAYS_DONE.load(Ordering::Acquire).to_le_bytes());
+    out[4..6].copy_from_slice(&DAYS_TOTAL.load(Ordering::Acquire).to_le_bytes());
+    out[6..8].copy_from_slice(&FILES_DONE.load(Ordering::Acquire).to_le_bytes());
+    out[8..10].copy_from_slice(&FILES_SKIPPED.load(Ordering::Acquire).to_le_bytes());
+    out[10..12].copy_from_slice(&FILES_FAILED.load(Ordering::Acquire).to_le_bytes());
+    out
+}
+
+/// Wait until `progress()` changes.
+pub async fn wait_progress_change() {
+    PROGRESS_CHANGED.wait().await;
+}
+
+#[task]
+pub async fn file_jobs_task() {
+    loop {
+        let job = JOB_REQUEST.wait().await;
+        defmt::info!(
+            "File job: action {} over {} days",
+            action_id(job.action),
+            job.days
+        );
+        let mut date = job.start;
+        let mut since_notify = 0u16;
+        for done in 1..=job.days {
+            if CANCEL.load(Ordering::Acquire) {
+                break;
+            }
+            let (year, month, day) = date;
+            let outcome = storage::apply_log_file_action(job.action, year, month, day).await;
+            let counter = match outcome {
+                LogFileOutcome::Missing => None,
+                LogFileOutcome::Done => Some(&FILES_DONE),
+                LogFileOutcome::Skipped => Some(&FILES_SKIPPED),
+                LogFileOutcome::Failed => Some(&FILES_FAILED),
+            };
+            DAYS_DONE.store(done, Ordering::Release);
+            since_notify += 1;
+            if let Some(counter) = counter {
+                counter.fetch_add(1, Ordering::AcqRel);
+            }
+            if counter.is_some() || since_notify >= EMPTY_DAYS_PER_NOTIFY {
+                since_notify = 0;
+                PROGRESS_CHANGED.signal(());
+            }
+            date = next_day(date);
+            embassy_futures::yield_now().await;
+        }
+        let cancelled = CANCEL.swap(false, Ordering::AcqRel);
+        STATE.store(
+            if cancelled { STATE_CANCELLED } else { STATE_DONE },
+            Ordering::Release,
+        );
+        PROGRESS_CHANGED.signal(());
+        defmt::info!(
+            "File job {}: {} done, {} skipped, {} failed",
+            if cancelled { "cancelled" } else { "finished" },
+            FILES_DONE.load(Ordering::Acquire),
+            FILES_SKIPPED.load(Ordering::Acquire),
+            FILES_FAILED.load(Ordering::Acquire)
+        );
+    }
+}
+
+fn parse_date(value: u32) -> Option<(u16, u8, u8)> {
+    let year = (value / 10000) as u16;
+    let month = ((value / 100) % 100) as u8;
+    let day = (value % 100) as u8;
+    if !(2000..=2099).contains(&year) || !(1..=12).contains(&month) {
+        return None;
+    }
+    if day == 0 || day > days_in_month(year, month) {
+        return None;
+    }
+    Some((year, month, day))
+}
+
+fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        2 if year % 4 == 0 => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+fn next_day((year, month, day): (u16, u8, u8)) -> (u16, u8, u8) {
+    if day < days_in_month(year, month) {
+        (year, month, day + 1)
+    } else if month < 12 {
+        (year, month + 1, 1)
+    } else {
+        (year + 1, 1, 1)
+    }
+}
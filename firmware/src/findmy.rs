@@ -4,6 +4,9 @@
 //! - P-224 elliptic curve key derivation (rolling keys every 15 minutes)
 //! - BLE advertisement payload construction matching Apple's format
 //! - Non-connectable undirected advertising when main BLE is idle
+//! - Broadcasting needs provisioned keys and the `findmy.enabled` setting,
+//!   which the app or a double press on the Find My page switches at
+//!   runtime; `findmy.interval_ms` sets the advertising interval
 //!
 //! # Key Derivation Algorithm
 //!
@@ -31,18 +34,16 @@ use nrf_softdevice::{raw, RawError, Softdevice};
 
 use crate::adv_scheduler::{AdvPriority, ALTERNATION_SECS, ADV_SCHEDULER};
 use crate::display;
+use crate::key_clock::{ClockEvent, KeyClock};
+use crate::settings;
 use crate::storage;
 use crate::system_info::SYSTEM_INFO;
 
 /// Key rotation interval in seconds (15 minutes).
 const KEY_ROTATION_SECS: u64 = 900;
 
-/// BLE advertising interval in units of 0.625ms.
-/// 2000ms balances discoverability and power consumption.
-const FINDMY_ADV_INTERVAL_UNITS: u32 = 3200;
-
-/// Enable/disable Find My advertising at runtime.
-static FINDMY_ENABLED: AtomicBool = AtomicBool::new(false);
+/// Keys are loaded; broadcasting also needs the `findmy.enabled` setting.
+static FINDMY_PROVISIONED: AtomicBool = AtomicBool::new(false);
 static FINDMY_DIAG_STATE: AtomicU8 = AtomicU8::new(FindMyDiagState::Disabled as u8);
 static FINDMY_ADV_HANDLE: AtomicU8 =
     AtomicU8::new(raw::BLE_GAP_ADV_SET_HANDLE_NOT_SET as u8);
@@ -302,12 +303,6 @@ struct DerivedKey {
     public_key_x: [u8; 28],
 }
 
-#[derive(Clone, Copy)]
-struct TimeAnchor {
-    unix_ts: u64,
-    monotonic_ms: u64,
-}
-
 // ---------------------------------------------------------------------------
 // BLE advertisement payload
 // ---------------------------------------------------------------------------
@@ -370,11 +365,17 @@ fn build_ble_address(public_key_x: &[u8; 28]) -> [u8; 6] {
 
 /// Read GPS unix timestamp from SYSTEM_INFO.
 ///
-/// Returns `None` if GPS datetime is not yet valid.
+/// Falls back to the companion app's time while GPS datetime is not yet
+/// valid; returns `None` if neither is available.
 async fn gps_unix_ts() -> Option<u64> {
+    // PPS-disciplined time is exact at the second boundary; NMEA lags it.
+    #[cfg(feature = "gps-pps")]
+    if let Some(unix_ms) = crate::pps::precise_unix_ms() {
+        return Some(unix_ms / 1000);
+    }
     let info = *SYSTEM_INFO.lock().await;
     if !info.date_time_valid {
-        return None;
+        return crate::phone_location::unix_now();
     }
     let dt = chrono::NaiveDate::from_ymd_opt(info.year as i32, info.month as u32, info.day as u32)?
         .and_hms_opt(info.hour as u32, info.minute as u32, info.second as u32)?;
@@ -418,13 +419,7 @@ fn secs_until_next_rotation_from_unix(unix_ts: u64) -> Option<u64> {
 
 /// Read current battery percent from SYSTEM_INFO.
 async fn battery_percent() -> u8 {
-    let info = *SYSTEM_INFO.lock().await;
-    if info.battery_voltage < 0.0 {
-        return 0;
-    }
-    let percent = crate::battery::estimate_battery_level(info.battery_voltage * 1000.0)
-        .clamp(0.0, 100.0);
-    (percent + 0.5) as u8
+    SYSTEM_INFO.lock().await.battery_percent
 }
 
 // ---------------------------------------------------------------------------
@@ -501,36 +496,67 @@ pub async fn invalidate_sk_cache() {
     storage::delete_findmy_sk_cache().await;
 }
 
-/// Enable or disable Find My advertising.
-pub fn set_enabled(enabled: bool) {
-    FINDMY_ENABLED.store(enabled, Ordering::Release);
-    if enabled {
+/// Mark the keys loaded (or withdrawn).
+pub fn set_provisioned(provisioned: bool) {
+    FINDMY_PROVISIONED.store(provisioned, Ordering::Release);
+    if is_enabled() {
         set_diag_state(FindMyDiagState::WaitingGpsTime);
     } else {
         set_diag_state(FindMyDiagState::Disabled);
     }
 }
 
+pub fn is_provisioned() -> bool {
+    FINDMY_PROVISIONED.load(Ordering::Acquire)
+}
+
+/// Keys loaded and the user switch on.
 pub fn is_enabled() -> bool {
-    FINDMY_ENABLED.load(Ordering::Acquire)
+    is_provisioned() && settings::stored(settings::FINDMY_ENABLED) != 0
 }
 
-/// Update anchor from GPS when available; otherwise estimate from monotonic time.
-///
-/// Returns `None` until at least one valid GPS timestamp has been observed.
-async fn unix_ts_with_fallback(anchor: &mut Option<TimeAnchor>) -> Option<u64> {
-    if let Some(unix_ts) = gps_unix_ts().await {
-        *anchor = Some(TimeAnchor {
-            unix_ts,
-            monotonic_ms: Instant::now().as_millis(),
-        });
-        return Some(unix_ts);
+/// Flip and persist `findmy.enabled`, returning the new state. If the SD
+/// write fails the switch still applies for this session.
+pub async fn toggle_enabled() -> bool {
+    let enabled = settings::stored(settings::FINDMY_ENABLED) == 0;
+    if settings::set(settings::FINDMY_ENABLED, enabled as i32).await != settings::SetStatus::Ok {
+        defmt::warn!("FindMy: switch not saved");
     }
+    defmt::info!("FindMy: switched {}", if enabled { "on" } else { "off" });
+    enabled
+}
 
-    let now_ms = Instant::now().as_millis();
-    let base = (*anchor)?;
-    let elapsed_secs = now_ms.saturating_sub(base.monotonic_ms) / 1000;
-    Some(base.unix_ts.saturating_add(elapsed_secs))
+/// `findmy.interval_ms` in units of 0.625 ms.
+fn adv_interval_units() -> u32 {
+    settings::stored(settings::FINDMY_INTERVAL_MS) as u32 * 8 / 5
+}
+
+/// Unix time for the key schedule: GPS (or phone) time through `KeyClock`,
+/// which rides out jumps and keeps running on uptime without a source.
+/// Jumps and re-anchors go to `/FMCLOCK.CSV`.
+///
+/// Returns `None` until at least one timestamp has been observed.
+async fn unix_ts_with_fallback(clock: &mut KeyClock) -> Option<u64> {
+    let uptime_ms = Instant::now().as_millis();
+    let sample = gps_unix_ts().await;
+    let event = clock.note(sample, uptime_ms);
+    let now = clock.now(uptime_ms);
+    let (label, delta_s) = match event {
+        ClockEvent::Steady | ClockEvent::Holding => return now,
+        ClockEvent::Jumped { delta_s } => {
+            defmt::warn!("FindMy: time jumped {}s, keeping key clock", delta_s);
+            ("jump", delta_s)
+        }
+        ClockEvent::Reanchored { delta_s } => {
+            defmt::warn!("FindMy: time jump of {}s held, re-anchored", delta_s);
+            ("reanchor", delta_s)
+        }
+    };
+    let uptime_s = (uptime_ms / 1000) as u32;
+    if !storage::append_key_clock_event(uptime_s, delta_s, label).await {
+        defmt::warn!("FindMy: key clock event not logged");
+    }
+    now
 }
 
 /// Derive advertisement data for the provided unix timestamp.
@@ -563,7 +589,7 @@ async fn adv_data_for_unix(unix_ts: u64) -> Option<([u8; 31], [u8; 6], u32)> {
 pub async fn findmy_task(_sd: &'static Softdevice) {
     defmt::info!("FindMy: task started, waiting for enable + GPS time");
     set_diag_state(FindMyDiagState::Disabled);
-    let mut time_anchor: Option<TimeAnchor> = None;
+    let mut clock = KeyClock::new();
 
     loop {
         // Wait until enabled
@@ -578,7 +604,7 @@ pub async fn findmy_task(_sd: &'static Softdevice) {
                 break 0;
             }
             set_diag_state(FindMyDiagState::WaitingGpsTime);
-            if let Some(unix_ts) = unix_ts_with_fallback(&mut time_anchor).await {
+            if let Some(unix_ts) = unix_ts_with_fallback(&mut clock).await {
                 if let Some(c) = counter_from_unix(unix_ts) {
                     break c;
                 }
@@ -611,7 +637,7 @@ pub async fn findmy_task(_sd: &'static Softdevice) {
                 break;
             }
 
-            let unix_ts = match unix_ts_with_fallback(&mut time_anchor).await {
+            let unix_ts = match unix_ts_with_fallback(&mut clock).await {
                 Some(ts) => ts,
                 None => {
                     set_diag_state(FindMyDiagState::WaitingGpsTime);
@@ -671,7 +697,7 @@ pub async fn findmy_task(_sd: &'static Softdevice) {
                     type_: raw::BLE_GAP_ADV_TYPE_NONCONNECTABLE_NONSCANNABLE_UNDIRECTED as u8,
                     ..unsafe { core::mem::zeroed() }
                 },
-                interval: FINDMY_ADV_INTERVAL_UNITS,
+                interval: adv_interval_units(),
                 duration: 0,
                 filter_policy: raw::BLE_GAP_ADV_FP_ANY as u8,
                 primary_phy: raw::BLE_GAP_PHY_1MBPS as u8,
@@ -0,0 +1,194 @@
+//! Per-day outcome of GPS power cycles.
+//!
+//! Every power-on of the receiver either ends in a fix or is switched off
+//! without one. Counting both per day, with the time to first fix and
+//! whether AGNSS data was loaded, lets the app show whether a profile or
+//! settings change actually helps instead of guessing from the track.
+//!
+//! # Design
+//!
+//! - One attempt runs from GPS power-on to the first fix, or to power-off
+//!   without a fix. Fix timeouts retried under keep-alive stay one attempt,
+//!   and a fix lost and found again while powered is not a new one.
+//! - TTFF counts from power-on, so an AGNSS upload in between is part of it.
+//! - Days are UTC dates of the end of the attempt. Attempts whose end cannot
+//!   be placed in time (no fix and no phone time yet) count towards the
+//!   newest day, or the next dated one when there is none.
+//! - `FIX_STATS_DAYS` days are kept, newest first; the `to_bytes` record is
+//!   the checkpoint and the BLE response alike.
+
+pub const FIX_STATS_DAYS: usize = 7;
+/// `[date: u32][attempts: u16][fixes: u16][ttff_sum_s: u32]
+/// [agnss_attempts: u16][agnss_fixes: u16]`, little endian.
+pub const FIX_DAY_LEN: usize = 16;
+pub const FIX_STATS_LEN: usize = FIX_STATS_DAYS * FIX_DAY_LEN;
+
+#[derive(Clone, Copy)]
+pub struct FixAttempt {
+    pub fixed: bool,
+    /// Power-on to fix, or to power-off without one.
+    pub duration_s: u32,
+    /// Unexpired AGNSS data was loaded in the receiver.
+    pub agnss: bool,
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct FixDay {
+    /// YYYYMMDD (UTC), 0 when unknown.
+    pub date: u32,
+    pub attempts: u16,
+    pub fixes: u16,
+    /// Sum over the fixes; divide by `fixes` for the average TTFF.
+    pub ttff_sum_s: u32,
+    pub agnss_attempts: u16,
+    pub agnss_fixes: u16,
+}
+
+impl FixDay {
+    fn add(&mut self, attempt: &FixAttempt) {
+        self.attempts = self.attempts.saturating_add(1);
+        if attempt.agnss {
+            self.agnss_attempts = self.agnss_attempts.saturating_add(1);
+        }
+        if attempt.fixed {
+            self.fixes = self.fixes.saturating_add(1);
+            self.ttff_sum_s = self.ttff_sum_s.saturating_add(attempt.duration_s);
+            if attempt.agnss {
+                self.agnss_fixes = self.agnss_fixes.saturating_add(1);
+            }
+        }
+    }
+
+    pub fn to_bytes(self) -> [u8; FIX_DAY_LEN] {
+        let mut out = [0u8; FIX_DAY_LEN];
+        out[0..4].copy_from_slice(&self.date.to_le_bytes());
+        out[4..6].copy_from_slice(&self.attempts.to_le_bytes());
+        out[6..8].copy_from_slice(&self.fixes.to_le_bytes());
+        out[8..12].copy_from_slice(&self.ttff_sum_s.to_le_bytes());
+        out[12..14].copy_from_slice(&self.agnss_attempts.to_le_bytes());
+        out[14..16].copy_from_slice(&self.agnss_fixes.to_le_bytes());
+        out
+    }
+
+    fn from_bytes(raw: &[u8]) -> Self {
+        let u16_at = |i: usize| u16::from_le_bytes([raw[i], raw[i + 1]]);
+        let u32_at = |i: usize| u32::from_le_bytes([raw[i], raw[i + 1], raw[i + 2], raw[i + 3]]);
+        Self {
+            date: u32_at(0),
+            attempts: u16_at(4),
+            fixes: u16_at(6),
+            ttff_sum_s: u32_at(8),
+            agnss_attempts: u16_at(12),
+            agnss_fixes: u16_at(14),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct FixStats {
+    /// Newest first; days without attempts are unused.
+    days: [FixDay; FIX_STATS_DAYS],
+}
+
+impl FixStats {
+    pub const fn new() -> Self {
+        Self {
+            days: [FixDay {
+                date: 0,
+                attempts: 0,
+                fixes: 0,
+                ttff_sum_s: 0,
+                agnss_attempts: 0,
+                agnss_fixes: 0,
+            }; FIX_STATS_DAYS],
+        }
+    }
+
+    /// Count one attempt that ended on `date` (0 when unknown).
+    pub fn note(&mut self, date: u32, attempt: &FixAttempt) {
+        let newest = self.days[0];
+        if newest.attempts == 0 || newest.date == 0 {
+            self.days[0].date = date;
+        } else if date != 0 && date != newest.date {
+            self.days.copy_within(..FIX_STATS_DAYS - 1, 1);
+            self.days[0] = FixDay {
+                date,
+                ..FixDay::default()
+            };
+        }
+        self.days[0].add(attempt);
+    }
+
+    /// Days with attempts, newest first.
+    pub fn days(&self) -> impl Iterator<Item = &FixDay> {
+        self.days.iter().take_while(|day| day.attempts != 0)
+    }
+
+    pub fn to_bytes(self) -> [u8; FIX_STATS_LEN] {
+        let mut out = [0u8; FIX_STATS_LEN];
+        for (chunk, day) in out.chunks_exact_mut(FIX_DAY_LEN).zip(&self.days) {
+            chunk.copy_from_slice(&day.to_bytes());
+        }
+        out
+    }
+
+    pub fn from_bytes(raw: &[u8; FIX_STATS_LEN]) -> Self {
+        let mut stats = Self::new();
+        for (day, chunk) in stats.days.iter_mut().zip(raw.chunks_exact(FIX_DAY_LEN)) {
+            *day = FixDay::from_bytes(chunk);
+        }
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attempt(fixed: bool, duration_s: u32, agnss: bool) -> FixAttempt {
+        FixAttempt {
+            fixed,
+            duration_s,
+            agnss,
+        }
+    }
+
+    #[test]
+    fn aggregates_per_day_and_survives_a_checkpoint() {
+        let mut stats = FixStats::new();
+        // Before the first fix of the session nothing can date the attempt.
+        stats.note(0, &attempt(false, 120, false));
+        stats.note(20260301, &attempt(true, 40, true));
+        stats.note(20260302, &attempt(true, 10, true));
+        stats.note(0, &attempt(false, 90, true));
+
+        let stats = FixStats::from_bytes(&stats.to_bytes());
+        let days: Vec<FixDay> = stats.days().copied().collect();
+        assert_eq!(days.len(), 2);
+        assert_eq!(
+            days[0],
+            FixDay {
+                date: 20260302,
+                attempts: 2,
+                fixes: 1,
+                ttff_sum_s: 10,
+                agnss_attempts: 2,
+                agnss_fixes: 1,
+            }
+        );
+        assert_eq!((days[1].date, days[1].attempts), (20260301, 2));
+        assert_eq!((days[1].fixes, days[1].ttff_sum_s), (1, 40));
+    }
+
+    #[test]
+    fn keeps_the_newest_days() {
+        let mut stats = FixStats::new();
+        for day in 1..=FIX_STATS_DAYS as u32 + 2 {
+            stats.note(20260300 + day, &attempt(true, day, false));
+        }
+        let dates: Vec<u32> = stats.days().map(|day| day.date).collect();
+        assert_eq!(dates.len(), FIX_STATS_DAYS);
+        assert_eq!(dates[0], 20260309);
+        assert_eq!(*dates.last().unwrap(), 20260303);
+    }
+}
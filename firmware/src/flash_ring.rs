@@ -0,0 +1,184 @@
+//! Record and page layout of the internal-flash track ring.
+//!
+//! `flash_track` keeps recent points in a few internal flash pages so they
+//! survive a failed or lost SD card. This module holds the parts that do not
+//! touch the flash: the record format and which page and slot come next.
+//!
+//! # Design
+//!
+//! - A page is `PAGE_SIZE` bytes of `RECORD_LEN` slots. Slot 0 is the page
+//!   header, a magic word and a sequence number that grows by one for every
+//!   page started; the rest hold one point each, written in order.
+//! - Records are written once into erased flash, in whole words. Erased
+//!   slots read as all ones; a record cut short by a reset fails its CRC
+//!   and is skipped like one.
+//! - The page with the highest sequence number is the one being written.
+//!   When it is full the oldest page is erased and started, so the ring
+//!   always holds at least `pages - 1` full pages.
+//! - Altitude is kept in whole metres to fit 16 bytes.
+
+use crate::crc32;
+
+pub const PAGE_SIZE: usize = 4096;
+pub const RECORD_LEN: usize = 16;
+pub const SLOTS_PER_PAGE: usize = PAGE_SIZE / RECORD_LEN;
+/// Slot 0 is the header.
+pub const POINTS_PER_PAGE: usize = SLOTS_PER_PAGE - 1;
+
+const PAGE_MAGIC: u32 = 0x4B52_5447;
+const ERASED: [u8; RECORD_LEN] = [0xFF; RECORD_LEN];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RingPoint {
+    pub timestamp: u32,
+    pub latitude_e7: i32,
+    pub longitude_e7: i32,
+    pub altitude_m: i16,
+}
+
+impl RingPoint {
+    /// `[timestamp][latitude_e7][longitude_e7][altitude_m: i16][crc: u16]`,
+    /// little endian; the CRC is the low half of the CRC-32 of the rest.
+    pub fn to_bytes(self) -> [u8; RECORD_LEN] {
+        let mut out = [0u8; RECORD_LEN];
+        out[0..4].copy_from_slice(&self.timestamp.to_le_bytes());
+        out[4..8].copy_from_slice(&self.latitude_e7.to_le_bytes());
+        out[8..12].copy_from_slice(&self.longitude_e7.to_le_bytes());
+        out[12..14].copy_from_slice(&self.altitude_m.to_le_bytes());
+        let crc = crc32::crc32(&out[..14]) as u16;
+        out[14..16].copy_from_slice(&crc.to_le_bytes());
+        out
+    }
+
+    /// `None` for an erased slot or a torn write.
+    pub fn from_bytes(bytes: &[u8; RECORD_LEN]) -> Option<Self> {
+        if *bytes == ERASED {
+            return None;
+        }
+        let crc = u16::from_le_bytes([bytes[14], bytes[15]]);
+        if crc != crc32::crc32(&bytes[..14]) as u16 {
+            return None;
+        }
+        let word = |at: usize| [bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]];
+        Some(Self {
+            timestamp: u32::from_le_bytes(word(0)),
+            latitude_e7: i32::from_le_bytes(word(4)),
+            longitude_e7: i32::from_le_bytes(word(8)),
+            altitude_m: i16::from_le_bytes([bytes[12], bytes[13]]),
+        })
+    }
+}
+
+pub fn is_erased(bytes: &[u8; RECORD_LEN]) -> bool {
+    *bytes == ERASED
+}
+
+pub fn page_header(seq: u32) -> [u8; RECORD_LEN] {
+    let mut out = ERASED;
+    out[0..4].copy_from_slice(&PAGE_MAGIC.to_le_bytes());
+    out[4..8].copy_from_slice(&seq.to_le_bytes());
+    out[8..12].copy_from_slice(&(!seq).to_le_bytes());
+    out
+}
+
+/// Sequence number of a started page.
+pub fn parse_page_header(bytes: &[u8; RECORD_LEN]) -> Option<u32> {
+    let magic = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    let seq = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+    let check = u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]);
+    (magic == PAGE_MAGIC && check == !seq).then_some(seq)
+}
+
+/// Where the next point goes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RingCursor {
+    pub page: usize,
+    pub seq: u32,
+    /// Next slot to write, `1..=POINTS_PER_PAGE`; `SLOTS_PER_PAGE` when the
+    /// page is full.
+    pub slot: usize,
+}
+
+impl RingCursor {
+    /// The newest started page, from each page's header sequence number.
+    /// The slot still has to be found by reading the page.
+    pub fn newest(headers: &[Option<u32>]) -> Option<Self> {
+        let (page, seq) = headers
+            .iter()
+            .enumerate()
+            .filter_map(|(page, seq)| seq.map(|seq| (page, seq)))
+            .max_by_key(|&(_, seq)| seq)?;
+        Some(Self {
+            page,
+            seq,
+            slot: SLOTS_PER_PAGE,
+        })
+    }
+
+    /// Move on to the next page if this one is full. Returns the page to
+    /// erase and start with the new sequence number.
+    pub fn advance(&mut self, pages: usize) -> Option<usize> {
+        if self.slot < SLOTS_PER_PAGE {
+            return None;
+        }
+        self.page = (self.page + 1) % pages;
+        self.seq = self.seq.wrapping_add(1);
+        self.slot = 1;
+        Some(self.page)
+    }
+
+    /// Pages newest first, as `(page, seq)`. A page is only part of the
+    /// ring if its header carries the sequence number expected there.
+    pub fn pages_newest_first(&self, pages: usize) -> impl Iterator<Item = (usize, u32)> {
+        let (newest, seq) = (self.page, self.seq);
+        (0..pages).map(move |back| {
+            let page = (newest + pages - back) % pages;
+            (page, seq.wrapping_sub(back as u32))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_reject_erased_and_torn_slots() {
+        let point = RingPoint {
+            timestamp: 1_717_200_000,
+            latitude_e7: 399_042_000,
+            longitude_e7: -1_163_974_000,
+            altitude_m: -12,
+        };
+        let bytes = point.to_bytes();
+        assert_eq!(RingPoint::from_bytes(&bytes), Some(point));
+        assert!(!is_erased(&bytes));
+        assert_eq!(RingPoint::from_bytes(&[0xFF; RECORD_LEN]), None);
+        // Power lost after the first two words.
+        let mut torn = [0xFF; RECORD_LEN];
+        torn[..8].copy_from_slice(&bytes[..8]);
+        assert_eq!(RingPoint::from_bytes(&torn), None);
+
+        assert_eq!(parse_page_header(&page_header(7)), Some(7));
+        assert_eq!(parse_page_header(&bytes), None);
+        assert_eq!(parse_page_header(&[0xFF; RECORD_LEN]), None);
+    }
+
+    #[test]
+    fn cursor_wraps_around_the_pages() {
+        assert_eq!(RingCursor::newest(&[None, None, None, None]), None);
+        let mut cursor = RingCursor::newest(&[Some(5), Some(6), Some(3), Some(4)]).unwrap();
+        assert_eq!((cursor.page, cursor.seq), (1, 6));
+        let order: Vec<_> = cursor.pages_newest_first(4).collect();
+        assert_eq!(order, [(1, 6), (0, 5), (3, 4), (2, 3)]);
+
+        cursor.slot = 10;
+        assert_eq!(cursor.advance(4), None);
+        cursor.slot = SLOTS_PER_PAGE;
+        assert_eq!(cursor.advance(4), Some(2));
+        assert_eq!((cursor.page, cursor.seq, cursor.slot), (2, 7, 1));
+        cursor.page = 3;
+        cursor.slot = SLOTS_PER_PAGE;
+        assert_eq!(cursor.advance(4), Some(0));
+    }
+}
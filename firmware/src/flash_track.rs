@@ -0,0 +1,191 @@
+//! Recent points mirrored to internal flash.
+//!
+//! The track only lived on the SD card, so a failed or lost card took the
+//! last trip with it. A ring of a few internal flash pages keeps the most
+//! recent points, readable over BLE with `READ_FLASH_TRACK`, so the last
+//! segment and the last known position can still be recovered.
+//!
+//! # Design
+//!
+//! - `RING_PAGES` pages from `RING_START`, in the application data area the
+//!   Adafruit bootloader leaves alone (see `memory.x`); the layout is in
+//!   `flash_ring`. With one page erased, at least 765 points are held.
+//! - One point per `MIRROR_INTERVAL_S`. A page lasts about 10 000 erases;
+//!   at 1 Hz the ring would wear out within a few months of recording, at
+//!   this rate each page is erased every 3 hours of it. The ring then spans
+//!   2 to 3 hours.
+//! - Fed with the points given to the GPX log, before the SD card sees
+//!   them, so a card failure does not stop it.
+//! - Writes go through the SoftDevice flash API, which fits them between
+//!   radio events; the ring is a partition of the `Flash` it shares with
+//!   `slots`. `note_point` only queues; `flash_track_task` writes, and a
+//!   full queue drops the point.
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use embassy_embedded_hal::flash::partition::Partition;
+use embassy_executor::task;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::channel::Channel;
+use embassy_sync::mutex::Mutex;
+use embedded_storage_async::nor_flash::{NorFlash, ReadNorFlash};
+use libm::{round, roundf};
+use nrf_softdevice::Flash;
+
+use crate::flash_ring::{self, RingCursor, RingPoint, PAGE_SIZE, RECORD_LEN, SLOTS_PER_PAGE};
+use crate::slots::SharedFlash;
+
+const RING_START: u32 = 0xED000;
+const RING_PAGES: usize = 4;
+const MIRROR_INTERVAL_S: u32 = 10;
+
+static POINTS: Channel<CriticalSectionRawMutex, RingPoint, 4> = Channel::new();
+static LAST_QUEUED: AtomicU32 = AtomicU32::new(0);
+static RING: Mutex<CriticalSectionRawMutex, Option<Ring>> = Mutex::new(None);
+
+/// The SoftDevice writes whole words from a word-aligned buffer.
+#[repr(align(4))]
+struct Aligned([u8; RECORD_LEN]);
+
+struct Ring {
+    flash: Partition<'static, CriticalSectionRawMutex, Flash>,
+    cursor: RingCursor,
+}
+
+/// Offset in the ring partition.
+fn slot_address(page: usize, slot: usize) -> u32 {
+    (page * PAGE_SIZE + slot * RECORD_LEN) as u32
+}
+
+impl Ring {
+    async fn read_slot(&mut self, page: usize, slot: usize) -> Option<[u8; RECORD_LEN]> {
+        let mut bytes = [0u8; RECORD_LEN];
+        let address = slot_address(page, slot);
+        self.flash.read(address, &mut bytes).await.ok()?;
+        Some(bytes)
+    }
+
+    async fn open(flash: &'static SharedFlash) -> Self {
+        // Until a page is found, the first point starts page 0.
+        let fresh = RingCursor {
+            page: RING_PAGES - 1,
+            seq: 0,
+            slot: SLOTS_PER_PAGE,
+        };
+        let mut ring = Self {
+            flash: Partition::new(flash, RING_START, (RING_PAGES * PAGE_SIZE) as u32),
+            cursor: fresh,
+        };
+        let mut headers = [None; RING_PAGES];
+        for (page, header) in headers.iter_mut().enumerate() {
+            let bytes = ring.read_slot(page, 0).await;
+            *header = bytes.and_then(|bytes| flash_ring::parse_page_header(&bytes));
+        }
+        let Some(mut cursor) = RingCursor::newest(&headers) else {
+            defmt::info!("Flash track: empty");
+            return ring;
+        };
+        cursor.slot = 1;
+        while cursor.slot < SLOTS_PER_PAGE {
+            match ring.read_slot(cursor.page, cursor.slot).await {
+                Some(bytes) if !flash_ring::is_erased(&bytes) => cursor.slot += 1,
+                _ => break,
+            }
+        }
+        defmt::info!("Flash track: page {} slot {}", cursor.page, cursor.slot);
+        ring.cursor = cursor;
+        ring
+    }
+
+    async fn append(&mut self, point: &RingPoint) -> bool {
+        let mut cursor = self.cursor;
+        if let Some(page) = cursor.advance(RING_PAGES) {
+            let (start, end) = (slot_address(page, 0), slot_address(page + 1, 0));
+            let header = Aligned(flash_ring::page_header(cursor.seq));
+            if self.flash.erase(start, end).await.is_err()
+                || self.flash.write(start, &header.0).await.is_err()
+            {
+                return false;
+            }
+        }
+        let record = Aligned(point.to_bytes());
+        let address = slot_address(cursor.page, cursor.slot);
+        let written = self.flash.write(address, &record.0).await;
+        // A failed write may have left part of the record: skip the slot.
+        cursor.slot += 1;
+        self.cursor = cursor;
+        written.is_ok()
+    }
+}
+
+/// Queue a logged point, if `MIRROR_INTERVAL_S` has passed since the last.
+pub fn note_point(timestamp: u32, latitude: f64, longitude: f64, altitude_m: f32) {
+    let last = LAST_QUEUED.load(Ordering::Relaxed);
+    if timestamp == 0 || (last != 0 && timestamp.wrapping_sub(last) < MIRROR_INTERVAL_S) {
+        return;
+    }
+    LAST_QUEUED.store(timestamp, Ordering::Relaxed);
+    let point = RingPoint {
+        timestamp,
+        latitude_e7: round(latitude * 1e7) as i32,
+        longitude_e7: round(longitude * 1e7) as i32,
+        altitude_m: roundf(altitude_m) as i16,
+    };
+    if POINTS.try_send(point).is_err() {
+        defmt::warn!("Flash track: queue full, point dropped");
+    }
+}
+
+/// Fill `out` with stored points, newest first, after skipping the `skip`
+/// newest. Returns how many were filled.
+pub async fn read_newest(skip: usize, out: &mut [RingPoint]) -> usize {
+    let mut ring = RING.lock().await;
+    let Some(ring) = ring.as_mut() else {
+        return 0;
+    };
+    let cursor = ring.cursor;
+    let (mut skipped, mut count) = (0, 0);
+    for (page, seq) in cursor.pages_newest_first(RING_PAGES) {
+        let header = ring.read_slot(page, 0).await;
+        if header.and_then(|bytes| flash_ring::parse_page_header(&bytes)) != Some(seq) {
+            break;
+        }
+        let end = if page == cursor.page {
+            cursor.slot
+        } else {
+            SLOTS_PER_PAGE
+        };
+        for slot in (1..end).rev() {
+            let bytes = ring.read_slot(page, slot).await;
+            let Some(point) = bytes.and_then(|bytes| RingPoint::from_bytes(&bytes)) else {
+                continue;
+            };
+            if skipped < skip {
+                skipped += 1;
+                continue;
+            }
+            out[count] = point;
+            count += 1;
+            if count == out.len() {
+                return count;
+            }
+        }
+    }
+    count
+}
+
+#[task]
+pub async fn flash_track_task(flash: &'static SharedFlash) {
+    let ring = Ring::open(flash).await;
+    *RING.lock().await = Some(ring);
+    loop {
+        let point = POINTS.receive().await;
+        let mut ring = RING.lock().await;
+        let Some(ring) = ring.as_mut() else {
+            continue;
+        };
+        if !ring.append(&point).await {
+            defmt::warn!("Flash track: write failed");
+        }
+    }
+}
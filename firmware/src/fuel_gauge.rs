@@ -0,0 +1,242 @@
+//! Battery state of charge, charge state and time to empty.
+//!
+//! A voltage lookup alone follows the load: the cell sags while the GPS is
+//! on and recovers as soon as it is off, so the percentage saws up and down
+//! with every fix. The gauge counts the charge drawn between samples and
+//! only pulls that count slowly towards the voltage curve.
+//!
+//! # Design
+//!
+//! - There is no current sensor. The load is modelled from what is powered
+//!   (`estimate_load_ma`), and its drop over `INTERNAL_RESISTANCE_MOHM` is
+//!   added back before the voltage lookup, as is the cold offset.
+//! - Each sample subtracts load * time / capacity, then moves
+//!   `VOLTAGE_WEIGHT` of the way to the voltage estimate, so a wrong load
+//!   model or capacity cannot drift far. The first sample starts from the
+//!   voltage estimate.
+//! - VBUS means charging. The charger holds the cell voltage up, so the
+//!   count may only rise then; `Full` from `FULL_MV`. The charge current is
+//!   unknown, so there is no time to full.
+//! - Time to empty is the remaining charge over the load averaged across
+//!   `LOAD_AVERAGE_S`, so GPS duty cycles are averaged in. `None` on VBUS.
+
+/// Cell internal resistance plus protection circuit.
+const INTERNAL_RESISTANCE_MOHM: f32 = 200.0;
+/// MCU, sensors and BLE advertising.
+const LOAD_BASE_MA: f32 = 2.5;
+const LOAD_GPS_MA: f32 = 25.0;
+const LOAD_DISPLAY_MA: f32 = 8.0;
+/// Pull towards the voltage estimate per sample (1 Hz).
+const VOLTAGE_WEIGHT: f32 = 0.002;
+const LOAD_AVERAGE_S: f32 = 900.0;
+/// Voltage on VBUS from which the charger is taken to be done.
+const FULL_MV: f32 = 4150.0;
+
+// 低温补偿：LiPo 在低温下内阻升高，同样的剩余电量端电压更低，直接查表会让
+// 百分比骤降。按温度给电压加上补偿量后再查 SOC 表，20°C 以上不补偿。
+// 温度取自板载 BMP280，近似电芯温度。
+const COMP_TEMP_POINTS_C: [f32; 5] = [-20.0, -10.0, 0.0, 10.0, 20.0];
+const COMP_OFFSET_POINTS_MV: [f32; 5] = [150.0, 100.0, 60.0, 25.0, 0.0];
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ChargeState {
+    Discharging = 0,
+    Charging = 1,
+    Full = 2,
+}
+
+pub struct GaugeSample {
+    /// Filtered battery voltage.
+    pub voltage_mv: f32,
+    pub temperature_c: Option<f32>,
+    pub load_ma: f32,
+    pub vbus: bool,
+    /// Since the previous sample.
+    pub elapsed_s: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GaugeReading {
+    pub percent: u8,
+    pub state: ChargeState,
+    pub time_to_empty_min: Option<u16>,
+}
+
+pub struct FuelGauge {
+    /// Percent, `None` until the first sample.
+    soc: Option<f32>,
+    average_load_ma: f32,
+}
+
+impl FuelGauge {
+    pub const fn new() -> Self {
+        Self {
+            soc: None,
+            average_load_ma: 0.0,
+        }
+    }
+
+    /// Start over from the voltage curve, after the voltage reading was lost.
+    pub fn reset(&mut self) {
+        self.soc = None;
+    }
+
+    pub fn update(&mut self, sample: &GaugeSample, capacity_mah: f32) -> GaugeReading {
+        let rest_mv = sample.voltage_mv + sample.load_ma * INTERNAL_RESISTANCE_MOHM / 1000.0;
+        let from_voltage = estimate_battery_level(rest_mv, sample.temperature_c);
+        let soc = match self.soc {
+            None => {
+                self.average_load_ma = sample.load_ma;
+                from_voltage
+            }
+            Some(soc) => {
+                let weight = (sample.elapsed_s / LOAD_AVERAGE_S).min(1.0);
+                self.average_load_ma += weight * (sample.load_ma - self.average_load_ma);
+                let drawn_mah = sample.load_ma * sample.elapsed_s / 3600.0;
+                let counted = if sample.vbus {
+                    soc
+                } else {
+                    soc - drawn_mah / capacity_mah * 100.0
+                };
+                let blended = counted + VOLTAGE_WEIGHT * (from_voltage - counted);
+                if sample.vbus {
+                    blended.max(soc)
+                } else {
+                    blended
+                }
+            }
+        };
+        let soc = soc.clamp(0.0, 100.0);
+        self.soc = Some(soc);
+
+        let state = if !sample.vbus {
+            ChargeState::Discharging
+        } else if sample.voltage_mv >= FULL_MV {
+            ChargeState::Full
+        } else {
+            ChargeState::Charging
+        };
+        let time_to_empty_min = (!sample.vbus && self.average_load_ma > 0.0).then(|| {
+            let minutes = soc / 100.0 * capacity_mah / self.average_load_ma * 60.0;
+            minutes.min((u16::MAX - 1) as f32) as u16
+        });
+        GaugeReading {
+            percent: (soc + 0.5) as u8,
+            state,
+            time_to_empty_min,
+        }
+    }
+}
+
+/// Modelled battery current for what is powered.
+pub fn estimate_load_ma(gps_on: bool, display_on: bool) -> f32 {
+    let mut load = LOAD_BASE_MA;
+    if gps_on {
+        load += LOAD_GPS_MA;
+    }
+    if display_on {
+        load += LOAD_DISPLAY_MA;
+    }
+    load
+}
+
+/// State of charge (%) for a resting voltage, compensated for cold cells
+/// when the temperature is known.
+pub fn estimate_battery_level(voltage_mv: f32, temperature_c: Option<f32>) -> f32 {
+    let compensated_mv = voltage_mv + temperature_c.map_or(0.0, cold_compensation_mv);
+    soc_from_voltage(compensated_mv)
+}
+
+fn cold_compensation_mv(temperature_c: f32) -> f32 {
+    interpolate(&COMP_TEMP_POINTS_C, &COMP_OFFSET_POINTS_MV, temperature_c)
+}
+
+fn soc_from_voltage(voltage_mv: f32) -> f32 {
+    const VOLTAGE_POINTS: [f32; 11] = [
+        3000.0, 3300.0, 3500.0, 3600.0, 3700.0, 3800.0, 3850.0, 3900.0, 3950.0, 4100.0, 4200.0,
+    ];
+    const SOC_POINTS: [f32; 11] = [
+        0.0, 5.0, 10.0, 20.0, 35.0, 50.0, 60.0, 70.0, 80.0, 95.0, 100.0,
+    ];
+
+    interpolate(&VOLTAGE_POINTS, &SOC_POINTS, voltage_mv)
+}
+
+/// Piecewise-linear lookup; `xs` ascending, clamped at both ends.
+fn interpolate(xs: &[f32], ys: &[f32], x: f32) -> f32 {
+    if x <= xs[0] {
+        return ys[0];
+    }
+    if x >= xs[xs.len() - 1] {
+        return ys[ys.len() - 1];
+    }
+
+    for idx in 1..xs.len() {
+        if x <= xs[idx] {
+            let x1 = xs[idx - 1];
+            let x2 = xs[idx];
+            let y1 = ys[idx - 1];
+            let y2 = ys[idx];
+            if (x2 - x1).abs() < f32::EPSILON {
+                return y1;
+            }
+            return y1 + (x - x1) * (y2 - y1) / (x2 - x1);
+        }
+    }
+
+    0.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(voltage_mv: f32, load_ma: f32, vbus: bool) -> GaugeSample {
+        GaugeSample {
+            voltage_mv,
+            temperature_c: None,
+            load_ma,
+            vbus,
+            elapsed_s: 1.0,
+        }
+    }
+
+    #[test]
+    fn rides_through_load_sag() {
+        let mut gauge = FuelGauge::new();
+        let idle = estimate_load_ma(false, false);
+        let gps = estimate_load_ma(true, false);
+        let start = gauge.update(&sample(3800.0, idle, false), 1000.0);
+        assert_eq!(start.percent, 50);
+        assert_eq!(start.state, ChargeState::Discharging);
+        // The GPS turns on and the cell sags 60 mV: the voltage curve alone
+        // would drop to 38%.
+        let mut reading = start;
+        for _ in 0..60 {
+            reading = gauge.update(&sample(3740.0, gps, false), 1000.0);
+        }
+        assert!(reading.percent >= 48, "{}", reading.percent);
+        // About 490 mAh left over an average load between idle and GPS on.
+        let minutes = reading.time_to_empty_min.unwrap();
+        let (low, high) = (500 / 28 * 60, 500 / 2 * 60);
+        assert!(minutes > low && minutes < high, "{minutes}");
+    }
+
+    #[test]
+    fn charges_without_time_to_empty() {
+        let mut gauge = FuelGauge::new();
+        let load = estimate_load_ma(false, true);
+        gauge.update(&sample(3700.0, load, false), 1000.0);
+        let charging = gauge.update(&sample(4000.0, load, true), 1000.0);
+        assert_eq!(charging.state, ChargeState::Charging);
+        assert_eq!(charging.time_to_empty_min, None);
+        assert!(charging.percent >= 35);
+        // On VBUS a lower voltage never takes charge away.
+        let lower = gauge.update(&sample(3600.0, load, true), 1000.0);
+        assert!(lower.percent >= charging.percent);
+        let full = gauge.update(&sample(4180.0, load, true), 1000.0);
+        assert_eq!(full.state, ChargeState::Full);
+        assert!((estimate_battery_level(3450.0, Some(-20.0)) - 20.0).abs() < 1.0);
+    }
+}
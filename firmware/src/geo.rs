@@ -0,0 +1,38 @@
+//! Great-circle distance and bearing on a spherical Earth.
+//!
+//! Haversine is accurate to ~0.5% against the WGS84 ellipsoid, which is far
+//! below GPS noise at the distances the display shows.
+
+use libm::{atan2, cos, sin, sqrt};
+
+const EARTH_RADIUS_M: f64 = 6_371_000.0;
+const COMPASS_POINTS: [&str; 8] = ["N", "NE", "E", "SE", "S", "SW", "W", "NW"];
+
+/// Distance in metres between two points given in degrees.
+pub fn distance_m(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    let phi1 = lat1.to_radians();
+    let phi2 = lat2.to_radians();
+    let half_dphi = (lat2 - lat1).to_radians() / 2.0;
+    let half_dlambda = (lon2 - lon1).to_radians() / 2.0;
+    let a = sin(half_dphi) * sin(half_dphi)
+        + cos(phi1) * cos(phi2) * sin(half_dlambda) * sin(half_dlambda);
+    2.0 * EARTH_RADIUS_M * atan2(sqrt(a), sqrt(1.0 - a))
+}
+
+/// Initial bearing from the first point to the second, degrees in `[0, 360)`.
+#[cfg(feature = "nav")]
+pub fn bearing_deg(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    let phi1 = lat1.to_radians();
+    let phi2 = lat2.to_radians();
+    let dlambda = (lon2 - lon1).to_radians();
+    let y = sin(dlambda) * cos(phi2);
+    let x = cos(phi1) * sin(phi2) - sin(phi1) * cos(phi2) * cos(dlambda);
+    let deg = atan2(y, x).to_degrees();
+    if deg < 0.0 { deg + 360.0 } else { deg }
+}
+
+/// Eight-point compass label for a bearing in degrees.
+pub fn compass_point(bearing_deg: f64) -> &'static str {
+    let sector = ((bearing_deg + 22.5) / 45.0) as usize % COMPASS_POINTS.len();
+    COMPASS_POINTS[sector]
+}
@@ -0,0 +1,310 @@
+//! Circular geofences with entry/exit alerts.
+//!
+//! Fences are provisioned over the protocol (`SET_GEOFENCE`,
+//! `LIST_GEOFENCES`, `DELETE_GEOFENCE`), kept in RAM and mirrored to
+//! `/FENCES.DB` like the waypoint database. Every fix is checked against
+//! them; a crossing is sent on the geofence GATT characteristic and raised
+//! as a geofence alert (`alerts`), by default a banner on the display.
+//!
+//! # Record layout (`FENCE_RECORD_SIZE` bytes, little-endian)
+//!
+//! | Offset | Size | Field                                           |
+//! | :----- | :--- | :---------------------------------------------- |
+//! | 0      | 1    | flags (bit0 = in use, bit1 = alert on entry,    |
+//! |        |      | bit2 = alert on exit)                           |
+//! | 1      | 1    | name length                                     |
+//! | 2      | 2    | radius, u16, metres                             |
+//! | 4      | 4    | latitude, i32, degrees * 1e7                    |
+//! | 8      | 4    | longitude, i32, degrees * 1e7                   |
+//! | 12     | 20   | name, UTF-8, zero padded                        |
+//!
+//! # Design
+//!
+//! - Inside/outside is only known after the first fix following boot or a
+//!   change to the fence; that fix sets the state without an alert, so a
+//!   reboot inside a fence does not report an entry.
+//! - Leaving needs `exit_margin_m` beyond the radius, so GPS jitter at the
+//!   boundary does not flap between entry and exit.
+//! - Alerts queue up to `EVENT_QUEUE` deep and are dropped when no phone
+//!   drains them; the BLE side clears the queue on connect.
+
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::channel::Channel;
+use embassy_sync::mutex::Mutex;
+use heapless::String;
+
+use crate::alerts::{self, AlertKind};
+use crate::geo;
+use crate::storage;
+use crate::system_info::SYSTEM_INFO;
+use crate::timezone;
+
+pub const MAX_FENCES: usize = 16;
+pub const FENCE_RECORD_SIZE: usize = 32;
+pub const FENCE_NAME_MAX: usize = 20;
+pub const FENCE_DB_SIZE: usize = MAX_FENCES * FENCE_RECORD_SIZE;
+/// Wire size of `FenceEvent::to_bytes`.
+pub const FENCE_EVENT_LEN: usize = 6;
+
+const FLAG_IN_USE: u8 = 0x01;
+pub const FLAG_ALERT_ENTER: u8 = 0x02;
+pub const FLAG_ALERT_EXIT: u8 = 0x04;
+/// Set in `LIST_GEOFENCES` output only: the last fix was inside.
+const FLAG_INSIDE: u8 = 0x80;
+const EVENT_QUEUE: usize = 4;
+const MIN_EXIT_MARGIN_M: f64 = 15.0;
+
+#[derive(Clone, Copy)]
+pub struct Geofence {
+    pub latitude_e7: i32,
+    pub longitude_e7: i32,
+    pub radius_m: u16,
+    pub flags: u8,
+    pub name: [u8; FENCE_NAME_MAX],
+    pub name_len: u8,
+}
+
+impl Geofence {
+    /// Build a fence from wire fields; the name is truncated to fit. Only
+    /// the alert bits of `flags` are kept.
+    pub fn new(
+        latitude_e7: i32,
+        longitude_e7: i32,
+        radius_m: u16,
+        flags: u8,
+        name: &[u8],
+    ) -> Option<Self> {
+        if !(-900_000_000..=900_000_000).contains(&latitude_e7)
+            || !(-1_800_000_000..=1_800_000_000).contains(&longitude_e7)
+            || radius_m == 0
+        {
+            return None;
+        }
+        let len = core::cmp::min(name.len(), FENCE_NAME_MAX);
+        let mut fence = Self {
+            latitude_e7,
+            longitude_e7,
+            radius_m,
+            flags: flags & (FLAG_ALERT_ENTER | FLAG_ALERT_EXIT),
+            name: [0; FENCE_NAME_MAX],
+            name_len: len as u8,
+        };
+        fence.name[..len].copy_from_slice(&name[..len]);
+        Some(fence)
+    }
+
+    pub fn name(&self) -> &[u8] {
+        &self.name[..self.name_len as usize]
+    }
+
+    fn distance_m(&self, latitude: f64, longitude: f64) -> f64 {
+        let lat = self.latitude_e7 as f64 / 1e7;
+        let lon = self.longitude_e7 as f64 / 1e7;
+        geo::distance_m(latitude, longitude, lat, lon)
+    }
+
+    fn exit_margin_m(&self) -> f64 {
+        (self.radius_m as f64 / 10.0).max(MIN_EXIT_MARGIN_M)
+    }
+
+    pub fn write_record(&self, out: &mut [u8], inside: bool) {
+        out[0] = FLAG_IN_USE | self.flags | if inside { FLAG_INSIDE } else { 0 };
+        out[1] = self.name_len;
+        out[2..4].copy_from_slice(&self.radius_m.to_le_bytes());
+        out[4..8].copy_from_slice(&self.latitude_e7.to_le_bytes());
+        out[8..12].copy_from_slice(&self.longitude_e7.to_le_bytes());
+        out[12..12 + FENCE_NAME_MAX].copy_from_slice(&self.name);
+    }
+
+    fn read_record(data: &[u8]) -> Option<Self> {
+        if (data[0] & FLAG_IN_USE) == 0 {
+            return None;
+        }
+        let radius_m = u16::from_le_bytes([data[2], data[3]]);
+        let latitude_e7 = i32::from_le_bytes([data[4], data[5], data[6], data[7]]);
+        let longitude_e7 = i32::from_le_bytes([data[8], data[9], data[10], data[11]]);
+        let name_len = core::cmp::min(data[1] as usize, FENCE_NAME_MAX);
+        Self::new(latitude_e7, longitude_e7, radius_m, data[0], &data[12..12 + name_len])
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct FenceEvent {
+    pub slot: u8,
+    pub entered: bool,
+    pub timestamp: u32,
+}
+
+impl FenceEvent {
+    /// `[slot: u8][kind: u8, 1 = entry, 0 = exit][timestamp: u32 LE]`
+    pub fn to_bytes(&self) -> [u8; FENCE_EVENT_LEN] {
+        let mut out = [0u8; FENCE_EVENT_LEN];
+        out[0] = self.slot;
+        out[1] = self.entered as u8;
+        out[2..6].copy_from_slice(&self.timestamp.to_le_bytes());
+        out
+    }
+}
+
+struct FenceDb {
+    slots: [Option<Geofence>; MAX_FENCES],
+    /// Last known side of each fence, `None` until the next fix.
+    inside: [Option<bool>; MAX_FENCES],
+}
+
+impl FenceDb {
+    const fn new() -> Self {
+        Self {
+            slots: [None; MAX_FENCES],
+            inside: [None; MAX_FENCES],
+        }
+    }
+
+    fn serialize(&self, out: &mut [u8; FENCE_DB_SIZE]) {
+        out.fill(0);
+        for (slot, chunk) in self.slots.iter().zip(out.chunks_exact_mut(FENCE_RECORD_SIZE)) {
+            if let Some(fence) = slot {
+                fence.write_record(chunk, false);
+            }
+        }
+    }
+}
+
+static FENCES: Mutex<CriticalSectionRawMutex, FenceDb> = Mutex::new(FenceDb::new());
+static EVENTS: Channel<CriticalSectionRawMutex, FenceEvent, EVENT_QUEUE> = Channel::new();
+
+/// Load fences from SD card. Call once after the SD logger is initialized.
+pub async fn load() {
+    let mut buf = [0u8; FENCE_DB_SIZE];
+    let Some(len) = storage::read_fence_db(&mut buf).await else {
+        defmt::info!("Geofences: no database on SD");
+        return;
+    };
+    let mut db = FENCES.lock().await;
+    let mut count = 0usize;
+    for (slot, chunk) in db.slots.iter_mut().zip(buf[..len].chunks_exact(FENCE_RECORD_SIZE)) {
+        *slot = Geofence::read_record(chunk);
+        if slot.is_some() {
+            count += 1;
+        }
+    }
+    defmt::info!("Geofences: loaded {} from SD", count);
+}
+
+async fn persist(db: &FenceDb) -> bool {
+    let mut buf = [0u8; FENCE_DB_SIZE];
+    db.serialize(&mut buf);
+    storage::write_fence_db(&buf).await
+}
+
+/// Store `fence` in `slot`, or in the first free slot with `None`.
+/// Returns the slot index.
+pub async fn set(slot: Option<u8>, fence: Geofence) -> Option<u8> {
+    let mut db = FENCES.lock().await;
+    let idx = match slot {
+        Some(slot) if (slot as usize) < MAX_FENCES => slot as usize,
+        Some(_) => return None,
+        None => db.slots.iter().position(|slot| slot.is_none())?,
+    };
+    let previous = db.slots[idx].replace(fence);
+    if !persist(&db).await {
+        db.slots[idx] = previous;
+        return None;
+    }
+    db.inside[idx] = None;
+    Some(idx as u8)
+}
+
+/// Free a slot. Deleting an empty slot is not an error.
+pub async fn delete(slot: u8) -> bool {
+    let mut db = FENCES.lock().await;
+    let Some(entry) = db.slots.get_mut(slot as usize) else {
+        return false;
+    };
+    let Some(previous) = entry.take() else {
+        return true;
+    };
+    if !persist(&db).await {
+        db.slots[slot as usize] = Some(previous);
+        return false;
+    }
+    db.inside[slot as usize] = None;
+    true
+}
+
+/// Return the next occupied slot at or after `start`, with whether the last
+/// fix was inside it.
+pub async fn next_from(start: usize) -> Option<(u8, Geofence, bool)> {
+    let db = FENCES.lock().await;
+    db.slots
+        .iter()
+        .zip(db.inside.iter())
+        .enumerate()
+        .skip(start)
+        .find_map(|(idx, (slot, inside))| {
+            slot.map(|fence| (idx as u8, fence, inside.unwrap_or(false)))
+        })
+}
+
+/// Check the current fix against every fence. Called by the GPS state
+/// machine once per sample while it has a fix.
+pub async fn check_fix() {
+    let (latitude, longitude, timestamp) = {
+        let info = SYSTEM_INFO.lock().await;
+        let timestamp = timezone::date_time_to_unix_timestamp(
+            info.year,
+            info.month,
+            info.day,
+            info.hour,
+            info.minute,
+            info.second,
+        );
+        (info.latitude, info.longitude, timestamp.unwrap_or(0))
+    };
+
+    let mut db = FENCES.lock().await;
+    let db = &mut *db;
+    for (idx, (slot, inside)) in db.slots.iter().zip(db.inside.iter_mut()).enumerate() {
+        let Some(fence) = slot else {
+            continue;
+        };
+        let distance = fence.distance_m(latitude, longitude);
+        let now_inside = match *inside {
+            Some(true) => distance <= fence.radius_m as f64 + fence.exit_margin_m(),
+            _ => distance < fence.radius_m as f64,
+        };
+        let crossed = inside.is_some_and(|was| was != now_inside);
+        *inside = Some(now_inside);
+        if !crossed {
+            continue;
+        }
+        let wanted = if now_inside { FLAG_ALERT_ENTER } else { FLAG_ALERT_EXIT };
+        if fence.flags & wanted == 0 {
+            continue;
+        }
+        defmt::info!("Geofence {}: {}", idx, if now_inside { "entered" } else { "left" });
+        let _ = EVENTS.try_send(FenceEvent {
+            slot: idx as u8,
+            entered: now_inside,
+            timestamp,
+        });
+        raise_alert(fence, now_inside).await;
+    }
+}
+
+async fn raise_alert(fence: &Geofence, entered: bool) {
+    let mut text = String::<32>::new();
+    let _ = text.push_str(if entered { "Enter " } else { "Leave " });
+    let _ = text.push_str(core::str::from_utf8(fence.name()).unwrap_or("fence"));
+    alerts::raise(AlertKind::Geofence, &text).await;
+}
+
+/// Wait for the next fence crossing.
+pub async fn next_event() -> FenceEvent {
+    EVENTS.receive().await
+}
+
+/// Drop crossings nobody was connected to receive.
+pub fn clear_events() {
+    EVENTS.clear();
+}
@@ -5,6 +5,9 @@
 //! - EID rotation every 1024 seconds (~17 minutes)
 //! - BLE advertisement payload construction (Eddystone 0xFEAA format)
 //! - Hashed flags byte (battery level, UTP mode)
+//! - The ring beacon action: the advertisement is connectable while the
+//!   GATT server runs, and a finder rings the buzzer through the beacon
+//!   actions characteristic (see `ble::serve_fmdn_link`)
 //!
 //! # EID Generation Algorithm
 //!
@@ -15,19 +18,34 @@
 //! 4. Compute `R = r * G` (scalar multiplication on SECP160R1 generator)
 //! 5. Extract x-coordinate of R as 20-byte EID (big-endian)
 //! 6. Compute hashed flags: `SHA256(r)[0] XOR flags_raw`
+//!
+//! # Beacon actions
+//!
+//! Reading the characteristic gives `[version 0x01][nonce: 8B]`. A write is
+//! `[data id][length][one-time key: 8B][data]`, where the key is the first
+//! 8 bytes of `HMAC-SHA256(ring key, version || nonce || data id || length
+//! || data)` and the ring key is `SHA256(EIK || 0x02)[..8]`. Ring (`0x05`,
+//! data `[components][timeout_ds: u16 BE][volume]`, components `0` =
+//! stop) and read ring state (`0x06`) are handled; other data ids need the
+//! Fast Pair account key, which this tracker does not have, and are
+//! ignored. The softdevice accepts every write, so a rejected one gets no
+//! ATT error, only no notification. Notifications carry the same header
+//! and a key computed over the same fields followed by `0x01`.
 
 use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
 
 use aes::cipher::{BlockEncrypt, KeyInit};
 use aes::Aes256;
 use embassy_executor::task;
-use embassy_futures::select::{select, Either};
+use embassy_futures::select::{select, select3, Either, Either3};
 use embassy_time::{Duration, Instant, Timer};
 use sha2::{Digest, Sha256};
 
+use nrf_softdevice::ble::peripheral;
 use nrf_softdevice::{raw, RawError, Softdevice};
 
 use crate::adv_scheduler::{AdvPriority, ALTERNATION_SECS, ADV_SCHEDULER};
+use crate::ble;
 use crate::display;
 use crate::secp160r1;
 use crate::system_info::SYSTEM_INFO;
@@ -42,6 +60,25 @@ const K: u8 = 10;
 /// 2000ms matches Find My interval.
 const FMDN_ADV_INTERVAL_UNITS: u32 = 3200;
 
+/// Beacon actions protocol major version, first byte of every read.
+const BEACON_ACTIONS_VERSION: u8 = 0x01;
+const DATA_ID_RING: u8 = 0x05;
+const DATA_ID_READ_RING_STATE: u8 = 0x06;
+/// One-time authentication key: the first bytes of an HMAC-SHA256.
+const AUTH_KEY_LEN: usize = 8;
+pub const NONCE_LEN: usize = 8;
+/// Ring request data: components, timeout (deciseconds), volume.
+const RING_REQUEST_LEN: usize = 4;
+/// The buzzer is the only ring component.
+const RING_COMPONENT: u8 = 0x01;
+/// Longest ring a request may ask for, the 10 minutes `RING` allows.
+const RING_MAX_DS: u16 = 6000;
+
+const _: () = assert!(2 + AUTH_KEY_LEN + RING_REQUEST_LEN == ble::BEACON_ACTIONS_LEN);
+
+/// A beacon actions write or notification.
+pub type BeaconActionsValue = heapless::Vec<u8, { ble::BEACON_ACTIONS_LEN }>;
+
 /// Enable/disable FMDN advertising at runtime.
 static FMDN_ENABLED: AtomicBool = AtomicBool::new(false);
 static FMDN_DIAG_STATE: AtomicU8 = AtomicU8::new(FmdnDiagState::Disabled as u8);
@@ -61,6 +98,8 @@ pub enum FmdnDiagState {
     SetAddrFailed = 5,
     AdvConfigureFailed = 6,
     AdvStartFailed = 7,
+    /// A finder is connected to the FMDN address.
+    Connected = 8,
 }
 
 impl FmdnDiagState {
@@ -74,6 +113,7 @@ impl FmdnDiagState {
             5 => Some(Self::SetAddrFailed),
             6 => Some(Self::AdvConfigureFailed),
             7 => Some(Self::AdvStartFailed),
+            8 => Some(Self::Connected),
             _ => None,
         }
     }
@@ -240,6 +280,209 @@ fn battery_to_flags(battery_percent: u8) -> u8 {
     level << 5
 }
 
+// ---------------------------------------------------------------------------
+// Beacon actions
+// ---------------------------------------------------------------------------
+
+/// Ring state in a ring notification.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum RingState {
+    Started = 0x00,
+    Failed = 0x01,
+    TimedOut = 0x02,
+    StoppedByRequest = 0x04,
+}
+
+/// An authenticated beacon actions write.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum BeaconAction {
+    /// Ring for `timeout_ds` deciseconds, `0` for `sound.ring_s`.
+    Ring {
+        timeout_ds: u16,
+    },
+    Stop,
+    ReadRingState,
+}
+
+/// HMAC-SHA256 of the concatenated `parts`, for keys up to one block.
+fn hmac_sha256(key: &[u8], parts: &[&[u8]]) -> [u8; 32] {
+    let mut inner_pad = [0x36u8; 64];
+    let mut outer_pad = [0x5Cu8; 64];
+    for (i, byte) in key.iter().enumerate() {
+        inner_pad[i] ^= byte;
+        outer_pad[i] ^= byte;
+    }
+    let mut inner = Sha256::new();
+    inner.update(inner_pad);
+    for part in parts {
+        inner.update(part);
+    }
+    let inner = inner.finalize();
+    let mut outer = Sha256::new();
+    outer.update(outer_pad);
+    outer.update(inner);
+    outer.finalize().into()
+}
+
+/// `SHA256(EIK || 0x02)[..8]`, the key of the ring actions.
+fn ring_key(eik: &[u8; 32]) -> [u8; AUTH_KEY_LEN] {
+    let mut hasher = Sha256::new();
+    hasher.update(eik);
+    hasher.update([0x02]);
+    let digest = hasher.finalize();
+    let mut key = [0u8; AUTH_KEY_LEN];
+    key.copy_from_slice(&digest[..AUTH_KEY_LEN]);
+    key
+}
+
+/// One-time key over `header` (data id, length) and `data`, with `suffix`
+/// (`0x01`) appended for notifications.
+fn one_time_key(
+    key: &[u8; AUTH_KEY_LEN],
+    nonce: &[u8; NONCE_LEN],
+    header: &[u8],
+    data: &[u8],
+    suffix: &[u8],
+) -> [u8; AUTH_KEY_LEN] {
+    let version = [BEACON_ACTIONS_VERSION];
+    let mac = hmac_sha256(key, &[&version, nonce, header, data, suffix]);
+    let mut out = [0u8; AUTH_KEY_LEN];
+    out.copy_from_slice(&mac[..AUTH_KEY_LEN]);
+    out
+}
+
+/// Check a write against `nonce` and decode it. `None` for a malformed or
+/// unauthenticated write and for data ids other than the ring ones.
+fn parse_beacon_action(
+    write: &[u8],
+    nonce: &[u8; NONCE_LEN],
+    key: &[u8; AUTH_KEY_LEN],
+) -> Option<BeaconAction> {
+    if write.len() < 2 + AUTH_KEY_LEN || write[1] as usize != write.len() - 2 {
+        return None;
+    }
+    let (header, rest) = write.split_at(2);
+    let (auth, data) = rest.split_at(AUTH_KEY_LEN);
+    let action = match (header[0], data) {
+        (DATA_ID_RING, [0, _, _, _]) => BeaconAction::Stop,
+        (DATA_ID_RING, [_, hi, lo, _]) => BeaconAction::Ring {
+            timeout_ds: u16::from_be_bytes([*hi, *lo]).min(RING_MAX_DS),
+        },
+        (DATA_ID_READ_RING_STATE, []) => BeaconAction::ReadRingState,
+        _ => return None,
+    };
+    // Compare every byte, so the time taken does not tell how many matched.
+    let expected = one_time_key(key, nonce, header, data, &[]);
+    let diff = expected
+        .iter()
+        .zip(auth)
+        .fold(0, |acc, (a, b)| acc | (a ^ b));
+    (diff == 0).then_some(action)
+}
+
+/// Notification for `data_id`: the ring state, if any, the ringing
+/// components and the time left.
+fn ring_notification(
+    key: &[u8; AUTH_KEY_LEN],
+    nonce: &[u8; NONCE_LEN],
+    data_id: u8,
+    state: Option<RingState>,
+    remaining_ds: u16,
+) -> BeaconActionsValue {
+    let components = if remaining_ds > 0 { RING_COMPONENT } else { 0 };
+    let [hi, lo] = remaining_ds.to_be_bytes();
+    let mut data = heapless::Vec::<u8, RING_REQUEST_LEN>::new();
+    if let Some(state) = state {
+        let _ = data.push(state as u8);
+    }
+    let _ = data.extend_from_slice(&[components, hi, lo]);
+    let header = [data_id, (AUTH_KEY_LEN + data.len()) as u8];
+    let auth = one_time_key(key, nonce, &header, &data, &[0x01]);
+    let mut out = BeaconActionsValue::new();
+    let _ = out.extend_from_slice(&header);
+    let _ = out.extend_from_slice(&auth);
+    let _ = out.extend_from_slice(&data);
+    out
+}
+
+fn current_ring_key() -> [u8; AUTH_KEY_LEN] {
+    let eik = unsafe { core::ptr::read_volatile(&raw const EIK) };
+    ring_key(&eik)
+}
+
+/// Deciseconds left of the current ring.
+fn ring_remaining_ds() -> u16 {
+    #[cfg(feature = "buzzer")]
+    {
+        (crate::sound::ring_remaining_s() * 10).min(u16::MAX as u32) as u16
+    }
+    #[cfg(not(feature = "buzzer"))]
+    {
+        0
+    }
+}
+
+/// Start a ring of `timeout_ds` deciseconds; fails without a buzzer.
+fn start_ring(timeout_ds: u16) -> RingState {
+    #[cfg(feature = "buzzer")]
+    {
+        let duration_s = timeout_ds.div_ceil(10);
+        crate::sound::ring((duration_s != 0).then_some(duration_s));
+        RingState::Started
+    }
+    #[cfg(not(feature = "buzzer"))]
+    {
+        let _ = timeout_ds;
+        RingState::Failed
+    }
+}
+
+/// Characteristic value for a read with `nonce`.
+pub fn beacon_actions_read_value(nonce: &[u8; NONCE_LEN]) -> BeaconActionsValue {
+    let mut out = BeaconActionsValue::new();
+    let _ = out.push(BEACON_ACTIONS_VERSION);
+    let _ = out.extend_from_slice(nonce);
+    out
+}
+
+/// Act on a beacon actions write made against `nonce`. Returns the
+/// notification to send now, if any; stopping a ring is reported when it
+/// ends, by `ring_end_notification`.
+pub fn beacon_action(nonce: &[u8; NONCE_LEN], write: &[u8]) -> Option<BeaconActionsValue> {
+    let key = current_ring_key();
+    let Some(action) = parse_beacon_action(write, nonce, &key) else {
+        defmt::warn!("FMDN: beacon action rejected ({} bytes)", write.len());
+        return None;
+    };
+    defmt::info!("FMDN: beacon action, data id {}", write[0]);
+    let (data_id, state) = match action {
+        BeaconAction::Ring { timeout_ds } => (DATA_ID_RING, Some(start_ring(timeout_ds))),
+        BeaconAction::Stop if ring_remaining_ds() > 0 => {
+            #[cfg(feature = "buzzer")]
+            crate::sound::stop();
+            return None;
+        }
+        BeaconAction::Stop => (DATA_ID_RING, Some(RingState::StoppedByRequest)),
+        BeaconAction::ReadRingState => (DATA_ID_READ_RING_STATE, None),
+    };
+    let remaining_ds = ring_remaining_ds();
+    Some(ring_notification(&key, nonce, data_id, state, remaining_ds))
+}
+
+/// Ring state notification for a ring that ended.
+#[cfg(feature = "buzzer")]
+pub fn ring_end_notification(
+    nonce: &[u8; NONCE_LEN],
+    end: crate::sound::RingEnd,
+) -> BeaconActionsValue {
+    let state = match end {
+        crate::sound::RingEnd::Timeout => RingState::TimedOut,
+        crate::sound::RingEnd::Stopped => RingState::StoppedByRequest,
+    };
+    ring_notification(&current_ring_key(), nonce, DATA_ID_RING, Some(state), 0)
+}
+
 // ---------------------------------------------------------------------------
 // SoftDevice advertising helpers
 // ---------------------------------------------------------------------------
@@ -291,9 +534,14 @@ struct TimeAnchor {
 }
 
 async fn gps_unix_ts() -> Option<u64> {
+    // PPS-disciplined time is exact at the second boundary; NMEA lags it.
+    #[cfg(feature = "gps-pps")]
+    if let Some(unix_ms) = crate::pps::precise_unix_ms() {
+        return Some(unix_ms / 1000);
+    }
     let info = *SYSTEM_INFO.lock().await;
     if !info.date_time_valid {
-        return None;
+        return crate::phone_location::unix_now();
     }
     let dt = chrono::NaiveDate::from_ymd_opt(info.year as i32, info.month as u32, info.day as u32)?
         .and_hms_opt(info.hour as u32, info.minute as u32, info.second as u32)?;
@@ -321,13 +569,7 @@ fn secs_until_next_rotation(unix_ts: u64) -> u64 {
 }
 
 async fn battery_percent() -> u8 {
-    let info = *SYSTEM_INFO.lock().await;
-    if info.battery_voltage < 0.0 {
-        return 0;
-    }
-    let percent = crate::battery::estimate_battery_level(info.battery_voltage * 1000.0)
-        .clamp(0.0, 100.0);
-    (percent + 0.5) as u8
+    SYSTEM_INFO.lock().await.battery_percent
 }
 
 // ---------------------------------------------------------------------------
@@ -366,9 +608,10 @@ pub fn is_enabled() -> bool {
 ///
 /// Waits for initial GPS time, then computes EIDs and advertises with rotation
 /// every 1024 seconds. Uses `AdvScheduler` to coordinate with main BLE and
-/// Find My advertising.
+/// Find My advertising. With `server` (not in USB-only boots) the
+/// advertisement is connectable, for the ring beacon action.
 #[task]
-pub async fn fmdn_task(_sd: &'static Softdevice) {
+pub async fn fmdn_task(sd: &'static Softdevice, server: Option<&'static ble::Server>) {
     defmt::info!("FMDN: task started, waiting for enable + GPS time");
     set_diag_state(FmdnDiagState::Disabled);
     let mut time_anchor: Option<TimeAnchor> = None;
@@ -471,7 +714,49 @@ pub async fn fmdn_task(_sd: &'static Softdevice) {
                 continue;
             }
 
-            // Configure non-connectable advertising
+            // Wait until preempted, alternation slice expires, or rotation fires.
+            // Use short slices to allow FindMy alternation.
+            let sleep_secs = secs_until_next_rotation(unix_ts);
+            let adv_secs = core::cmp::min(sleep_secs + 1, ALTERNATION_SECS);
+
+            if let Some(server) = server {
+                let config = peripheral::Config {
+                    interval: FMDN_ADV_INTERVAL_UNITS,
+                    ..Default::default()
+                };
+                let adv = peripheral::ConnectableAdvertisement::ScannableUndirected {
+                    adv_data: &adv_payload,
+                    scan_data: &[],
+                };
+                set_diag_state(FmdnDiagState::Advertising);
+                defmt::info!("FMDN: advertising (masked_ts={})", current_masked_ts);
+                let next = select3(
+                    peripheral::advertise_connectable(sd, adv, &config),
+                    guard.wait_preempted(),
+                    Timer::after(Duration::from_secs(adv_secs)),
+                )
+                .await;
+                // A link keeps the address it was made on, so the next
+                // advertiser can have the handle and the address back.
+                let _ = unsafe { raw::sd_ble_gap_addr_set(&orig_addr) };
+                drop(guard);
+                match next {
+                    Either3::First(Ok(conn)) => {
+                        set_diag_state(FmdnDiagState::Connected);
+                        ble::serve_fmdn_link(sd, server, conn).await;
+                    }
+                    Either3::First(Err(e)) => {
+                        defmt::warn!("FMDN: adv start failed: {:?}", e);
+                        set_diag_state(FmdnDiagState::AdvStartFailed);
+                        Timer::after(Duration::from_secs(5)).await;
+                    }
+                    Either3::Second(()) => defmt::info!("FMDN: preempted by main BLE"),
+                    Either3::Third(()) => {}
+                }
+                continue;
+            }
+
+            // Without the GATT server, advertise non-connectable.
             let mut adv_params: raw::ble_gap_adv_params_t = unsafe { core::mem::zeroed() };
             adv_params.properties.type_ =
                 raw::BLE_GAP_ADV_TYPE_NONCONNECTABLE_NONSCANNABLE_UNDIRECTED as u8;
@@ -517,10 +802,6 @@ pub async fn fmdn_task(_sd: &'static Softdevice) {
             set_diag_state(FmdnDiagState::Advertising);
             defmt::info!("FMDN: advertising (masked_ts={})", current_masked_ts);
 
-            // Wait until preempted, alternation slice expires, or rotation fires.
-            // Use short slices to allow FindMy alternation.
-            let sleep_secs = secs_until_next_rotation(unix_ts);
-            let adv_secs = core::cmp::min(sleep_secs + 1, ALTERNATION_SECS);
             let rotation_timer = Timer::after(Duration::from_secs(adv_secs));
 
             match select(guard.wait_preempted(), rotation_timer).await {
@@ -619,6 +900,95 @@ mod tests {
         assert_eq!(battery_to_flags(0), 0b11 << 5); // critical
     }
 
+    #[test]
+    fn test_hmac_sha256_rfc4231_case_1() {
+        let mac = hmac_sha256(&[0x0b; 20], &[b"Hi ", b"There"]);
+        assert_eq!(
+            mac,
+            [
+                0xb0, 0x34, 0x4c, 0x61, 0xd8, 0xdb, 0x38, 0x53, 0x5c, 0xa8, 0xaf, 0xce, 0xaf, 0x0b,
+                0xf1, 0x2b, 0x88, 0x1d, 0xc2, 0x00, 0xc9, 0x83, 0x3d, 0xa7, 0x26, 0xe9, 0x37, 0x6c,
+                0x2e, 0x32, 0xcf, 0xf7,
+            ]
+        );
+    }
+
+    fn signed_write(
+        key: &[u8; 8],
+        nonce: &[u8; 8],
+        data_id: u8,
+        data: &[u8],
+    ) -> BeaconActionsValue {
+        let header = [data_id, (AUTH_KEY_LEN + data.len()) as u8];
+        let auth = one_time_key(key, nonce, &header, data, &[]);
+        let mut out = BeaconActionsValue::new();
+        let _ = out.extend_from_slice(&header);
+        let _ = out.extend_from_slice(&auth);
+        let _ = out.extend_from_slice(data);
+        out
+    }
+
+    #[test]
+    fn test_parse_beacon_action() {
+        let key = ring_key(&[0x11; 32]);
+        let nonce = [1, 2, 3, 4, 5, 6, 7, 8];
+        let ring = signed_write(&key, &nonce, DATA_ID_RING, &[0xFF, 0x01, 0x2C, 0x00]);
+        assert_eq!(
+            parse_beacon_action(&ring, &nonce, &key),
+            Some(BeaconAction::Ring { timeout_ds: 300 })
+        );
+        let long = signed_write(&key, &nonce, DATA_ID_RING, &[0x01, 0xFF, 0xFF, 0x03]);
+        assert_eq!(
+            parse_beacon_action(&long, &nonce, &key),
+            Some(BeaconAction::Ring {
+                timeout_ds: RING_MAX_DS
+            })
+        );
+        let stop = signed_write(&key, &nonce, DATA_ID_RING, &[0x00, 0x00, 0x00, 0x00]);
+        assert_eq!(
+            parse_beacon_action(&stop, &nonce, &key),
+            Some(BeaconAction::Stop)
+        );
+        let read = signed_write(&key, &nonce, DATA_ID_READ_RING_STATE, &[]);
+        assert_eq!(
+            parse_beacon_action(&read, &nonce, &key),
+            Some(BeaconAction::ReadRingState)
+        );
+
+        // A stale nonce, another key, a flipped bit or a bad length fail.
+        assert_eq!(parse_beacon_action(&ring, &[0; 8], &key), None);
+        assert_eq!(
+            parse_beacon_action(&ring, &nonce, &ring_key(&[0x22; 32])),
+            None
+        );
+        let mut tampered = ring.clone();
+        tampered[11] ^= 0x01;
+        assert_eq!(parse_beacon_action(&tampered, &nonce, &key), None);
+        assert_eq!(
+            parse_beacon_action(&ring[..ring.len() - 1], &nonce, &key),
+            None
+        );
+        // Data ids that need the account key are not handled.
+        let eik = signed_write(&key, &nonce, 0x02, &[0; 4]);
+        assert_eq!(parse_beacon_action(&eik, &nonce, &key), None);
+    }
+
+    #[test]
+    fn test_ring_notification() {
+        let key = ring_key(&[0x11; 32]);
+        let nonce = [8, 7, 6, 5, 4, 3, 2, 1];
+        let started = ring_notification(&key, &nonce, DATA_ID_RING, Some(RingState::Started), 300);
+        assert_eq!(started.len(), ble::BEACON_ACTIONS_LEN);
+        assert_eq!(&started[..2], &[DATA_ID_RING, 12]);
+        assert_eq!(&started[10..], &[0x00, RING_COMPONENT, 0x01, 0x2C]);
+        let auth = one_time_key(&key, &nonce, &started[..2], &started[10..], &[0x01]);
+        assert_eq!(&started[2..10], &auth);
+
+        let state = ring_notification(&key, &nonce, DATA_ID_READ_RING_STATE, None, 0);
+        assert_eq!(&state[..2], &[DATA_ID_READ_RING_STATE, 11]);
+        assert_eq!(&state[10..], &[0x00, 0x00, 0x00]);
+    }
+
     #[test]
     fn test_secs_until_next_rotation() {
         // Exactly on boundary
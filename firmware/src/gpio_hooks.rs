@@ -0,0 +1,210 @@
+//! Rule-driven expansion port GPIOs.
+//!
+//! The two spare header pins (P0.06 / P0.08, formerly reserved for a second
+//! UART) can be configured at runtime over BLE to act as simple integration
+//! hooks, e.g. a camera trigger that fires while moving faster than a
+//! threshold, or an input line from an external logger that wakes the GPS.
+//!
+//! # Design
+//!
+//! - Each pin holds one `HookRule` plus an active-low flag and a `u16` param.
+//! - `gpio_hooks_task` re-evaluates all rules every `HOOK_POLL_INTERVAL_MS`
+//!   or immediately after a configuration change.
+//! - Configuration lives in RAM only and resets to `Disabled` on reboot.
+
+use embassy_executor::task;
+use embassy_futures::select::select;
+use embassy_nrf::gpio::{Flex, OutputDrive, Pull};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::mutex::Mutex;
+use embassy_sync::signal::Signal;
+use embassy_time::Timer;
+
+use crate::gps;
+use crate::system_info::{SystemInfo, SYSTEM_INFO};
+
+pub const HOOK_PIN_COUNT: usize = 2;
+/// Serialized size of one pin entry in `GET_GPIO_HOOKS` responses.
+pub const HOOK_STATUS_ENTRY_LEN: usize = 5;
+
+const HOOK_POLL_INTERVAL_MS: u64 = 200;
+const HOOK_FLAG_ACTIVE_LOW: u8 = 0x01;
+
+/// Rule attached to an expansion pin (wire values are stable).
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum HookRule {
+    /// Pin disconnected (high impedance).
+    Disabled = 0,
+    /// Output driven directly by BLE; `param != 0` asserts it.
+    Manual = 1,
+    /// Output asserted while speed > `param` (0.1 km/h units) with a valid fix.
+    SpeedAbove = 2,
+    /// Output asserted while the GPS has a valid location fix.
+    FixValid = 3,
+    /// Output asserted while the accelerometer reports motion.
+    Moving = 4,
+    /// Input, level reported via `GET_GPIO_HOOKS` only.
+    Input = 5,
+    /// Input, an assert edge triggers a GPS wakeup.
+    InputWakeGps = 6,
+}
+
+impl HookRule {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::Disabled),
+            1 => Some(Self::Manual),
+            2 => Some(Self::SpeedAbove),
+            3 => Some(Self::FixValid),
+            4 => Some(Self::Moving),
+            5 => Some(Self::Input),
+            6 => Some(Self::InputWakeGps),
+            _ => None,
+        }
+    }
+
+    fn is_input(self) -> bool {
+        matches!(self, Self::Input | Self::InputWakeGps)
+    }
+}
+
+#[derive(Clone, Copy)]
+struct HookConfig {
+    rule: HookRule,
+    flags: u8,
+    param: u16,
+}
+
+impl HookConfig {
+    const fn disabled() -> Self {
+        Self {
+            rule: HookRule::Disabled,
+            flags: 0,
+            param: 0,
+        }
+    }
+
+    fn active_low(&self) -> bool {
+        (self.flags & HOOK_FLAG_ACTIVE_LOW) != 0
+    }
+}
+
+struct HookState {
+    configs: [HookConfig; HOOK_PIN_COUNT],
+    /// Logical (polarity-corrected) level last driven or sampled.
+    asserted: [bool; HOOK_PIN_COUNT],
+}
+
+static HOOK_STATE: Mutex<CriticalSectionRawMutex, HookState> = Mutex::new(HookState {
+    configs: [HookConfig::disabled(); HOOK_PIN_COUNT],
+    asserted: [false; HOOK_PIN_COUNT],
+});
+static HOOK_CONFIG_CHANGED: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+/// Configure one expansion pin. Returns `false` on invalid pin index.
+pub async fn set_hook(pin: usize, rule: HookRule, flags: u8, param: u16) -> bool {
+    if pin >= HOOK_PIN_COUNT {
+        return false;
+    }
+    {
+        let mut state = HOOK_STATE.lock().await;
+        state.configs[pin] = HookConfig { rule, flags, param };
+        state.asserted[pin] = false;
+    }
+    defmt::info!(
+        "GPIO hook {}: rule={} flags=0x{:02x} param={}",
+        pin,
+        rule as u8,
+        flags,
+        param
+    );
+    HOOK_CONFIG_CHANGED.signal(());
+    true
+}
+
+/// Serialize all pin entries as `[rule][flags][param:2 LE][asserted]`.
+pub async fn serialize_hooks(out: &mut [u8; HOOK_PIN_COUNT * HOOK_STATUS_ENTRY_LEN]) -> usize {
+    let state = HOOK_STATE.lock().await;
+    let mut offset = 0;
+    for (cfg, asserted) in state.configs.iter().zip(state.asserted.iter()) {
+        out[offset] = cfg.rule as u8;
+        out[offset + 1] = cfg.flags;
+        out[offset + 2..offset + 4].copy_from_slice(&cfg.param.to_le_bytes());
+        out[offset + 4] = u8::from(*asserted);
+        offset += HOOK_STATUS_ENTRY_LEN;
+    }
+    offset
+}
+
+fn evaluate_output(cfg: &HookConfig, info: &SystemInfo) -> bool {
+    match cfg.rule {
+        HookRule::Manual => cfg.param != 0,
+        HookRule::SpeedAbove => {
+            info.location_valid && info.speed * 10.0 > cfg.param as f32
+        }
+        HookRule::FixValid => info.location_valid,
+        HookRule::Moving => !info.is_stationary,
+        HookRule::Disabled | HookRule::Input | HookRule::InputWakeGps => false,
+    }
+}
+
+fn apply_direction(pin: &mut Flex<'static>, rule: HookRule) {
+    match rule {
+        HookRule::Disabled => pin.set_as_disconnected(),
+        HookRule::Input | HookRule::InputWakeGps => pin.set_as_input(Pull::None),
+        _ => pin.set_as_output(OutputDrive::Standard),
+    }
+}
+
+#[task]
+pub async fn gpio_hooks_task(mut pins: [Flex<'static>; HOOK_PIN_COUNT]) {
+    let mut applied = [HookRule::Disabled; HOOK_PIN_COUNT];
+    for pin in pins.iter_mut() {
+        pin.set_as_disconnected();
+    }
+
+    loop {
+        let info = { *SYSTEM_INFO.lock().await };
+        let mut wake_gps = false;
+        {
+            let mut state = HOOK_STATE.lock().await;
+            for (idx, pin) in pins.iter_mut().enumerate() {
+                let cfg = state.configs[idx];
+                if applied[idx] != cfg.rule {
+                    apply_direction(pin, cfg.rule);
+                    applied[idx] = cfg.rule;
+                }
+
+                if cfg.rule == HookRule::Disabled {
+                    state.asserted[idx] = false;
+                } else if cfg.rule.is_input() {
+                    let asserted = pin.is_high() != cfg.active_low();
+                    if asserted && !state.asserted[idx] && cfg.rule == HookRule::InputWakeGps {
+                        defmt::info!("GPIO hook {}: input asserted -> GPS wakeup", idx);
+                        wake_gps = true;
+                    }
+                    state.asserted[idx] = asserted;
+                } else {
+                    let asserted = evaluate_output(&cfg, &info);
+                    if asserted != cfg.active_low() {
+                        pin.set_high();
+                    } else {
+                        pin.set_low();
+                    }
+                    state.asserted[idx] = asserted;
+                }
+            }
+        }
+
+        if wake_gps {
+            gps::trigger_gps_wakeup().await;
+        }
+
+        select(
+            HOOK_CONFIG_CHANGED.wait(),
+            Timer::after_millis(HOOK_POLL_INTERVAL_MS),
+        )
+        .await;
+    }
+}
@@ -0,0 +1,109 @@
+//! Speed- and battery-driven GPS duty cycling.
+//!
+//! The profile's sampling interval suits a car at speed, but logs a walk
+//! far more densely than needed; and with the GPS off the tracker only
+//! woke on motion. This policy stretches the interval at walking pace and
+//! adds a periodic wake while still, stretched again on a low
+//! battery.
+//!
+//! # Design
+//!
+//! - Applied on top of whichever profile is selected, like the solar
+//!   policy, from the `gps.walk_*`, `gps.wake_interval_min` and
+//!   `gps.low_batt_*` settings.
+//! - At or below `walk_kmh` a point is taken every `walk_interval_ms`, never
+//!   more often than the profile asks; an unknown speed keeps the profile
+//!   rate. `walk_interval_ms` = 0 turns this off.
+//! - The periodic wake takes one fix from S2 while recording, then the usual
+//!   stillness check powers the GPS down again. On battery below
+//!   `low_battery_pct`, and not charging, the wake interval becomes
+//!   `low_battery_wake_min` if that is longer; `0` there stops waking then.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AdaptivePolicy {
+    pub walk_kmh: f32,
+    /// `0` = off.
+    pub walk_interval_ms: u32,
+    /// `0` = no periodic wake.
+    pub wake_interval_min: u32,
+    pub low_battery_wake_min: u32,
+    pub low_battery_pct: u8,
+}
+
+impl AdaptivePolicy {
+    /// Sampling interval at `speed_kmh`, negative when unknown.
+    pub fn sampling_interval_ms(&self, profile_ms: u32, speed_kmh: f32) -> u32 {
+        if speed_kmh < 0.0 || speed_kmh > self.walk_kmh {
+            return profile_ms;
+        }
+        self.walk_interval_ms.max(profile_ms)
+    }
+
+    /// Time between periodic wakes from S2, if any. `battery_pct` is `None`
+    /// while charging or before the first battery reading.
+    pub fn wake_interval_ms(&self, battery_pct: Option<u8>) -> Option<u64> {
+        if self.wake_interval_min == 0 {
+            return None;
+        }
+        let low = battery_pct.is_some_and(|pct| pct < self.low_battery_pct);
+        let minutes = match (low, self.low_battery_wake_min) {
+            (false, _) => self.wake_interval_min,
+            (true, 0) => return None,
+            (true, low_min) => low_min.max(self.wake_interval_min),
+        };
+        Some(minutes as u64 * 60_000)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POLICY: AdaptivePolicy = AdaptivePolicy {
+        walk_kmh: 6.0,
+        walk_interval_ms: 5_000,
+        wake_interval_min: 15,
+        low_battery_wake_min: 60,
+        low_battery_pct: 20,
+    };
+
+    #[test]
+    fn walking_pace_samples_less_often() {
+        assert_eq!(POLICY.sampling_interval_ms(1_000, 80.0), 1_000);
+        assert_eq!(POLICY.sampling_interval_ms(1_000, 4.5), 5_000);
+        assert_eq!(POLICY.sampling_interval_ms(1_000, 6.0), 5_000);
+        assert_eq!(POLICY.sampling_interval_ms(1_000, -1.0), 1_000);
+        // Never faster than the profile.
+        assert_eq!(POLICY.sampling_interval_ms(8_000, 3.0), 8_000);
+        let off = AdaptivePolicy {
+            walk_interval_ms: 0,
+            ..POLICY
+        };
+        assert_eq!(off.sampling_interval_ms(1_000, 3.0), 1_000);
+    }
+
+    #[test]
+    fn low_battery_stretches_the_periodic_wake() {
+        assert_eq!(POLICY.wake_interval_ms(Some(80)), Some(15 * 60_000));
+        assert_eq!(POLICY.wake_interval_ms(None), Some(15 * 60_000));
+        assert_eq!(POLICY.wake_interval_ms(Some(19)), Some(60 * 60_000));
+        assert_eq!(POLICY.wake_interval_ms(Some(20)), Some(15 * 60_000));
+
+        let no_low_wake = AdaptivePolicy {
+            low_battery_wake_min: 0,
+            ..POLICY
+        };
+        assert_eq!(no_low_wake.wake_interval_ms(Some(5)), None);
+        let short_low = AdaptivePolicy {
+            low_battery_wake_min: 5,
+            ..POLICY
+        };
+        assert_eq!(short_low.wake_interval_ms(Some(5)), Some(15 * 60_000));
+        let off = AdaptivePolicy {
+            wake_interval_min: 0,
+            ..POLICY
+        };
+        assert_eq!(off.wake_interval_ms(Some(80)), None);
+        assert_eq!(off.wake_interval_ms(Some(5)), None);
+    }
+}
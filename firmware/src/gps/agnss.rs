@@ -1,7 +1,13 @@
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_sync::mutex::Mutex;
+use embassy_sync::signal::Signal;
+use embassy_time::Instant;
 
-use crate::system_info::GpsState;
+use crate::agnss_window::{self, ValidityWindow, WindowBuilder, GPS_HALF_FIT_S};
+use crate::casic::{CASIC_CLASS_AID, CASIC_CLASS_MSG, CASIC_ID_AID_INI, CASIC_ID_MSG_GPSEPH};
+use crate::phone_location;
+use crate::system_info::{GpsState, SYSTEM_INFO};
+use crate::timezone;
 
 const AGNSS_TRIGGER_DELAY_MS: u64 = 10_000;
 const T_AGNSS_MESSAGE_SEND_TIMEOUT_MS: u64 = 1;
@@ -9,6 +15,11 @@ const T_AGNSS_TOTAL_TIMEOUT_MS: u64 = 600_000;
 const MAX_AGNSS_MESSAGE_RETRY: u8 = 3;
 const MAX_AGNSS_MESSAGES: usize = 70;
 pub const MAX_AGNSS_MESSAGE_SIZE: usize = 568;
+/// Wire size of `AgnssFreshness::to_bytes`.
+pub const AGNSS_FRESHNESS_LEN: usize = 5;
+/// CASIC framing around the payload: header, length, class, id, checksum.
+const CASIC_PAYLOAD_OFFSET: usize = 6;
+const CASIC_CHECKSUM_LEN: usize = 4;
 
 #[derive(Clone, Copy)]
 pub struct AgnssMessage {
@@ -37,6 +48,58 @@ impl AgnssMessage {
     pub fn as_slice(&self) -> &[u8] {
         &self.data[..self.len]
     }
+
+    fn is_aid_ini(&self) -> bool {
+        is_message(self.as_slice(), CASIC_CLASS_AID, CASIC_ID_AID_INI)
+    }
+}
+
+fn is_message(packet: &[u8], class_id: u8, msg_id: u8) -> bool {
+    packet.len() >= CASIC_PAYLOAD_OFFSET && packet[4] == class_id && packet[5] == msg_id
+}
+
+fn payload(packet: &[u8]) -> &[u8] {
+    let end = packet.len().saturating_sub(CASIC_CHECKSUM_LEN);
+    packet.get(CASIC_PAYLOAD_OFFSET..end).unwrap_or(&[])
+}
+
+/// How long the ephemerides last sent to the receiver stay usable.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AgnssFreshness {
+    /// No set uploaded since boot.
+    None,
+    Fresh {
+        remaining_s: u32,
+    },
+    /// The set has aged out; the app should download a new one.
+    Stale,
+    /// Accepted before any clock could place its ephemerides in time.
+    Unknown,
+}
+
+impl AgnssFreshness {
+    /// `[state: u8, 0 none / 1 fresh / 2 stale / 3 unknown][remaining_s: u32 LE]`
+    pub fn to_bytes(&self) -> [u8; AGNSS_FRESHNESS_LEN] {
+        let (state, remaining_s) = match *self {
+            Self::None => (0u8, 0u32),
+            Self::Fresh { remaining_s } => (1, remaining_s),
+            Self::Stale => (2, 0),
+            Self::Unknown => (3, 0),
+        };
+        let mut out = [0u8; AGNSS_FRESHNESS_LEN];
+        out[0] = state;
+        out[1..5].copy_from_slice(&remaining_s.to_le_bytes());
+        out
+    }
+}
+
+#[derive(Clone, Copy, Eq, PartialEq)]
+enum Validity {
+    None,
+    Unknown,
+    /// Ephemerides usable until this uptime.
+    Until(u64),
+    Expired,
 }
 
 struct AgnssQueue {
@@ -56,6 +119,11 @@ impl AgnssQueue {
         self.len = 0;
     }
 
+    /// Drop everything except an AID-INI seed at the head.
+    fn keep_seed_only(&mut self) {
+        self.len = (self.len > 0 && self.messages[0].is_aid_ini()) as usize;
+    }
+
     fn is_empty(&self) -> bool {
         self.len == 0
     }
@@ -74,6 +142,22 @@ impl AgnssQueue {
         Ok(())
     }
 
+    /// Put `data` at the head of the queue, replacing an AID-INI already there.
+    fn push_front_aid_ini(&mut self, data: &[u8]) -> Result<(), AgnssQueueError> {
+        let msg = AgnssMessage::from_slice(data).ok_or(AgnssQueueError::MessageTooLarge)?;
+        if self.len > 0 && self.messages[0].is_aid_ini() {
+            self.messages[0] = msg;
+            return Ok(());
+        }
+        if self.len >= MAX_AGNSS_MESSAGES {
+            return Err(AgnssQueueError::TooManyMessages);
+        }
+        self.messages.copy_within(0..self.len, 1);
+        self.messages[0] = msg;
+        self.len += 1;
+        Ok(())
+    }
+
     fn get_copy(&self, index: usize) -> Option<AgnssMessage> {
         if index < self.len {
             Some(self.messages[index])
@@ -87,6 +171,9 @@ impl AgnssQueue {
 pub enum AgnssQueueError {
     TooManyMessages,
     MessageTooLarge,
+    Busy,
+    /// Every ephemeris in the upload had already expired.
+    Stale,
 }
 
 #[derive(Clone, Copy)]
@@ -111,6 +198,7 @@ struct AgnssState {
     message_timer_start: Option<u64>,
     total_timer_start: Option<u64>,
     previous_state: GpsState,
+    validity: Validity,
 }
 
 impl AgnssState {
@@ -124,9 +212,39 @@ impl AgnssState {
             message_timer_start: None,
             total_timer_start: None,
             previous_state: GpsState::S2IdleGpsOff,
+            validity: Validity::None,
+        }
+    }
+
+    fn freshness(&self, now_ms: u64) -> AgnssFreshness {
+        match self.validity {
+            Validity::None => AgnssFreshness::None,
+            Validity::Unknown => AgnssFreshness::Unknown,
+            Validity::Until(end_ms) if now_ms < end_ms => AgnssFreshness::Fresh {
+                remaining_s: ((end_ms - now_ms) / 1000).min(u32::MAX as u64) as u32,
+            },
+            Validity::Until(_) | Validity::Expired => AgnssFreshness::Stale,
         }
     }
 
+    /// Mark the set expired once its window has passed. Ephemerides still
+    /// waiting to be sent are dropped rather than injected stale. Returns
+    /// true on the transition.
+    fn expire(&mut self, now_ms: u64) -> bool {
+        let Validity::Until(end_ms) = self.validity else {
+            return false;
+        };
+        if now_ms < end_ms {
+            return false;
+        }
+        self.validity = Validity::Expired;
+        if self.total_timer_start.is_none() {
+            self.queue.keep_seed_only();
+            self.request_pending = !self.queue.is_empty();
+        }
+        true
+    }
+
     fn clear_processing(&mut self) {
         self.current_index = 0;
         self.current_retry = 0;
@@ -207,10 +325,83 @@ impl AgnssState {
 }
 
 static AGNSS_STATE: Mutex<CriticalSectionRawMutex, AgnssState> = Mutex::new(AgnssState::new());
+static FRESHNESS_CHANGED: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+/// Current UTC time from the GPS, or failing that the phone.
+async fn clock_unix() -> Option<u64> {
+    let gps_ts = {
+        let info = SYSTEM_INFO.lock().await;
+        if info.date_time_valid {
+            timezone::date_time_to_unix_timestamp(
+                info.year,
+                info.month,
+                info.day,
+                info.hour,
+                info.minute,
+                info.second,
+            )
+        } else {
+            None
+        }
+    };
+    gps_ts.map(u64::from).or_else(phone_location::unix_now)
+}
+
+/// Window covered by the GPS ephemerides in `messages`, placed in time by
+/// their AID-INI or by `clock_unix`. Also returns the reference time.
+/// `None`, i.e. unknown, if any of them does not decode.
+fn validity_window(messages: &[&[u8]], clock_unix: Option<u64>) -> Option<(ValidityWindow, u64)> {
+    let reference = messages
+        .iter()
+        .filter(|msg| is_message(msg, CASIC_CLASS_AID, CASIC_ID_AID_INI))
+        .find_map(|msg| agnss_window::aid_ini_unix(payload(msg)))
+        .or(clock_unix)?;
+    let mut builder = WindowBuilder::new(reference);
+    for msg in messages {
+        if !is_message(msg, CASIC_CLASS_MSG, CASIC_ID_MSG_GPSEPH) {
+            continue;
+        }
+        let Some(toe) = agnss_window::gps_ephemeris_toe(payload(msg)) else {
+            defmt::warn!("AGNSS: GPS ephemeris did not decode, validity unknown");
+            return None;
+        };
+        builder.add(toe, GPS_HALF_FIT_S);
+    }
+    builder.finish().map(|window| (window, reference))
+}
 
 pub async fn set_agnss_message_queue(messages: &[&[u8]]) -> Result<(), AgnssQueueError> {
+    let clock = clock_unix().await;
+    let window = validity_window(messages, clock);
+    let now_ms = Instant::now().as_millis();
+    let validity = match window {
+        Some((window, reference)) => {
+            let remaining_s = window.remaining_s(reference);
+            if remaining_s == 0 {
+                defmt::warn!("AGNSS set expired at {}, refused", window.end);
+                return Err(AgnssQueueError::Stale);
+            }
+            defmt::info!(
+                "AGNSS valid {}..{} ({} s left)",
+                window.start,
+                window.end,
+                remaining_s
+            );
+            Validity::Until(now_ms + remaining_s as u64 * 1000)
+        }
+        None => Validity::Unknown,
+    };
+
     let mut agnss = AGNSS_STATE.lock().await;
+    // A position seed queued before this upload still goes out first.
+    let seed = agnss
+        .queue
+        .get_copy(0)
+        .filter(|msg| agnss.request_pending && msg.is_aid_ini());
     agnss.queue.clear();
+    if let Some(seed) = seed {
+        let _ = agnss.queue.push(seed.as_slice());
+    }
     for message in messages {
         if let Err(err) = agnss.queue.push(message) {
             agnss.queue.clear();
@@ -221,9 +412,44 @@ pub async fn set_agnss_message_queue(messages: &[&[u8]]) -> Result<(), AgnssQueu
     }
     agnss.request_pending = !agnss.queue.is_empty();
     agnss.force_trigger = false;
+    agnss.validity = validity;
+    FRESHNESS_CHANGED.signal(());
     Ok(())
 }
 
+pub async fn agnss_freshness() -> AgnssFreshness {
+    let agnss = AGNSS_STATE.lock().await;
+    agnss.freshness(Instant::now().as_millis())
+}
+
+/// Wait until a set is accepted or ages out.
+pub async fn wait_agnss_freshness_change() {
+    FRESHNESS_CHANGED.wait().await;
+}
+
+/// Queue an AID-INI packet ahead of any pending ephemeris upload.
+///
+/// Ignored while a batch is being sent; the receiver would see the seed out
+/// of order relative to the messages already delivered.
+pub async fn queue_aid_ini(packet: &[u8]) -> Result<(), AgnssQueueError> {
+    let mut agnss = AGNSS_STATE.lock().await;
+    if agnss.total_timer_start.is_some() {
+        return Err(AgnssQueueError::Busy);
+    }
+    agnss.queue.push_front_aid_ini(packet)?;
+    agnss.request_pending = true;
+    Ok(())
+}
+
+/// Called by the GPS state machine every step.
+pub(super) async fn agnss_check_expiry(now_ms: u64) {
+    let mut agnss = AGNSS_STATE.lock().await;
+    if agnss.expire(now_ms) {
+        defmt::info!("AGNSS set aged out");
+        FRESHNESS_CHANGED.signal(());
+    }
+}
+
 pub(super) async fn agnss_should_trigger(now_ms: u64, state: GpsState) -> bool {
     let agnss = AGNSS_STATE.lock().await;
     agnss.should_trigger(now_ms, state)
@@ -0,0 +1,184 @@
+//! When a pending AGNSS upload may go to the receiver.
+//!
+//! Injecting a set takes the state machine through S5, which pauses
+//! logging and keeps the GPS powered. That buys nothing while the receiver
+//! already has a good fix, and costs the most on a nearly flat battery.
+//! This policy holds the upload back in both cases, from the
+//! `gps.agnss_skip_hdop` and `gps.agnss_defer_pct` settings.
+//!
+//! # Design
+//!
+//! - A hold never drops the set: it stays pending and goes out at the first
+//!   step the policy allows, so it still speeds up the next cold start.
+//! - Skipped while tracking (S3) with HDOP at or below `skip_hdop`; the
+//!   receiver is decoding current ephemerides from the sky. Losing the fix
+//!   or a worse HDOP lets the upload through, where it helps most.
+//! - Deferred below `defer_battery_pct` while discharging. A charger, or no
+//!   battery reading yet, never defers.
+//! - `0` turns either check off.
+//! - [`AgnssPolicy::step`] is the whole per-step decision, so the state
+//!   machine only gathers its inputs and acts on the result.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AgnssPolicy {
+    /// `0` = never skip.
+    pub skip_hdop: f32,
+    /// `0` = never defer.
+    pub defer_battery_pct: u8,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AgnssGate {
+    Allow,
+    /// Tracking with a good fix.
+    SkipGoodFix,
+    /// Low battery, not charging.
+    DeferLowBattery,
+}
+
+/// What one state machine step does about the upload.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AgnssStep {
+    /// Nothing pending.
+    Idle,
+    /// Pending but held back, for the reason given.
+    Hold(AgnssGate),
+    /// Send it and enter S5.
+    Send,
+}
+
+impl AgnssPolicy {
+    /// `tracking_hdop` is the HDOP while in S3, `None` in any other state;
+    /// `battery_pct` is `None` while charging or before the first reading.
+    pub fn gate(&self, tracking_hdop: Option<f32>, battery_pct: Option<u8>) -> AgnssGate {
+        if battery_pct.is_some_and(|pct| pct < self.defer_battery_pct) {
+            return AgnssGate::DeferLowBattery;
+        }
+        let good_fix = tracking_hdop.is_some_and(|hdop| hdop <= self.skip_hdop);
+        if self.skip_hdop > 0.0 && good_fix {
+            return AgnssGate::SkipGoodFix;
+        }
+        AgnssGate::Allow
+    }
+
+    /// `pending` is whether a set waits to go out from the current state.
+    pub fn step(
+        &self,
+        pending: bool,
+        tracking_hdop: Option<f32>,
+        battery_pct: Option<u8>,
+    ) -> AgnssStep {
+        if !pending {
+            return AgnssStep::Idle;
+        }
+        match self.gate(tracking_hdop, battery_pct) {
+            AgnssGate::Allow => AgnssStep::Send,
+            held => AgnssStep::Hold(held),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POLICY: AgnssPolicy = AgnssPolicy {
+        skip_hdop: 1.5,
+        defer_battery_pct: 10,
+    };
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    enum State {
+        S1Searching,
+        S3Tracking,
+        S5Agnss,
+    }
+
+    /// The state machine's side of an upload: it stays pending until a
+    /// step sends it, which enters S5.
+    struct Machine {
+        policy: AgnssPolicy,
+        state: State,
+        pending: bool,
+    }
+
+    impl Machine {
+        fn new(policy: AgnssPolicy, state: State) -> Self {
+            Self {
+                policy,
+                state,
+                pending: true,
+            }
+        }
+
+        fn run(&mut self, state: State, hdop: f32, battery_pct: Option<u8>) -> AgnssStep {
+            self.state = state;
+            let tracking_hdop = (state == State::S3Tracking).then_some(hdop);
+            let step = self.policy.step(self.pending, tracking_hdop, battery_pct);
+            if step == AgnssStep::Send {
+                self.pending = false;
+                self.state = State::S5Agnss;
+            }
+            step
+        }
+    }
+
+    const HELD_FIX: AgnssStep = AgnssStep::Hold(AgnssGate::SkipGoodFix);
+    const HELD_BATTERY: AgnssStep = AgnssStep::Hold(AgnssGate::DeferLowBattery);
+
+    #[test]
+    fn a_good_fix_holds_the_upload_until_it_degrades() {
+        use State::*;
+        let mut machine = Machine::new(POLICY, S3Tracking);
+        assert_eq!(machine.run(S3Tracking, 0.8, Some(70)), HELD_FIX);
+        assert_eq!(machine.run(S3Tracking, 1.5, Some(70)), HELD_FIX);
+        assert!(machine.pending);
+        assert_eq!(machine.state, S3Tracking);
+        // Into a city canyon: the fix is poor enough for the upload.
+        assert_eq!(machine.run(S3Tracking, 3.2, Some(70)), AgnssStep::Send);
+        assert!(!machine.pending);
+        assert_eq!(machine.state, S5Agnss);
+        // Back in S3 afterwards there is nothing left to send.
+        assert_eq!(machine.run(S3Tracking, 0.8, Some(70)), AgnssStep::Idle);
+
+        // Losing the fix lets it through, whatever HDOP the last fix had.
+        let mut machine = Machine::new(POLICY, S3Tracking);
+        assert_eq!(machine.run(S3Tracking, 0.8, Some(70)), HELD_FIX);
+        assert_eq!(machine.run(S1Searching, 0.8, Some(70)), AgnssStep::Send);
+        assert_eq!(machine.state, S5Agnss);
+
+        let off = AgnssPolicy {
+            skip_hdop: 0.0,
+            ..POLICY
+        };
+        let mut machine = Machine::new(off, S3Tracking);
+        assert_eq!(machine.run(S3Tracking, 0.8, Some(70)), AgnssStep::Send);
+    }
+
+    #[test]
+    fn low_battery_defers_until_charging() {
+        use State::*;
+        let mut machine = Machine::new(POLICY, S3Tracking);
+        // Low battery wins over a good fix in the reason reported.
+        assert_eq!(machine.run(S3Tracking, 0.8, Some(9)), HELD_BATTERY);
+        assert_eq!(machine.run(S3Tracking, 4.0, Some(8)), HELD_BATTERY);
+        assert_eq!(machine.run(S1Searching, 99.9, Some(8)), HELD_BATTERY);
+        assert!(machine.pending);
+        assert_eq!(machine.state, S1Searching);
+        // Plugged in, poor fix.
+        assert_eq!(machine.run(S3Tracking, 4.0, None), AgnssStep::Send);
+        assert_eq!(machine.state, S5Agnss);
+
+        // Plugged in with a good fix: the fix check still applies.
+        let mut machine = Machine::new(POLICY, S3Tracking);
+        assert_eq!(machine.run(S3Tracking, 0.8, None), HELD_FIX);
+        assert_eq!(machine.run(S3Tracking, 0.8, Some(10)), HELD_FIX);
+
+        let off = AgnssPolicy {
+            defer_battery_pct: 0,
+            ..POLICY
+        };
+        let mut machine = Machine::new(off, S1Searching);
+        assert_eq!(machine.run(S1Searching, 99.9, Some(2)), AgnssStep::Send);
+    }
+}
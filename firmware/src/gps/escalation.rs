@@ -0,0 +1,139 @@
+//! What to do after another run of failed fix attempts.
+//!
+//! Every `gps.max_fix_failures` timed-out searches in a row the state
+//! machine climbs one rung: a warm restart, then a cold restart, then a
+//! pulse of the reset line, then backing off. Each step taken goes to
+//! `/GPSESC.CSV`, so a receiver that keeps failing shows up in the logs.
+//!
+//! # Design
+//!
+//! - A fix drops back to the bottom rung.
+//! - A receiver that also produced garbage or NACKs since the last step goes
+//!   straight to the hardware reset, as before the ladder; without a reset
+//!   line (`gps-reset` feature) that rung is skipped.
+//! - Backing off keeps the GPS off for `gps.fix_backoff_s`, doubled on each
+//!   further back-off up to 8x, before motion or the periodic wake power
+//!   it again. Keep-alive still wins, and a wake command ends it. With `0`
+//!   the ladder starts over at the warm restart instead.
+
+/// Largest back-off, as a power of two of `gps.fix_backoff_s`.
+const MAX_BACKOFF_SHIFT: u8 = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Escalation {
+    WarmRestart,
+    ColdRestart,
+    HardwareReset,
+    BackOff { seconds: u32 },
+}
+
+impl Escalation {
+    /// Name in `/GPSESC.CSV`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::WarmRestart => "warm",
+            Self::ColdRestart => "cold",
+            Self::HardwareReset => "reset",
+            Self::BackOff { .. } => "backoff",
+        }
+    }
+}
+
+pub struct EscalationLadder {
+    /// 0 warm, 1 cold, 2 reset, 3 back-off.
+    rung: u8,
+    backoffs: u8,
+}
+
+impl EscalationLadder {
+    pub const fn new() -> Self {
+        Self {
+            rung: 0,
+            backoffs: 0,
+        }
+    }
+
+    /// The receiver got a fix.
+    pub fn reset(&mut self) {
+        self.rung = 0;
+        self.backoffs = 0;
+    }
+
+    /// Next step. `can_reset` says the board has a reset line, `noisy` that
+    /// the receiver reported enough errors to warrant it now.
+    pub fn escalate(&mut self, can_reset: bool, noisy: bool, backoff_s: u32) -> Escalation {
+        if can_reset && (noisy || self.rung == 2) {
+            self.rung = 3;
+            return Escalation::HardwareReset;
+        }
+        match self.rung {
+            0 => {
+                self.rung = 1;
+                Escalation::WarmRestart
+            }
+            1 => {
+                self.rung = 2;
+                Escalation::ColdRestart
+            }
+            _ if backoff_s == 0 => {
+                self.rung = 1;
+                Escalation::WarmRestart
+            }
+            _ => {
+                self.rung = 3;
+                let seconds = backoff_s.saturating_mul(1 << self.backoffs);
+                self.backoffs = (self.backoffs + 1).min(MAX_BACKOFF_SHIFT);
+                Escalation::BackOff { seconds }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn climbs_one_rung_per_failure_run() {
+        let mut ladder = EscalationLadder::new();
+        let steps: Vec<Escalation> = (0..7).map(|_| ladder.escalate(true, false, 300)).collect();
+        let backoff = |seconds| Escalation::BackOff { seconds };
+        assert_eq!(
+            steps,
+            [
+                Escalation::WarmRestart,
+                Escalation::ColdRestart,
+                Escalation::HardwareReset,
+                backoff(300),
+                backoff(600),
+                backoff(1200),
+                backoff(2400),
+            ]
+        );
+        assert_eq!(ladder.escalate(true, false, 300), backoff(2400));
+
+        // A fix starts over.
+        ladder.reset();
+        assert_eq!(ladder.escalate(true, false, 300), Escalation::WarmRestart);
+        assert_eq!(ladder.escalate(true, false, 300), Escalation::ColdRestart);
+        assert_eq!(ladder.escalate(true, false, 300), Escalation::HardwareReset);
+        assert_eq!(ladder.escalate(true, false, 300), backoff(300));
+    }
+
+    #[test]
+    fn noise_resets_at_once_and_missing_rungs_are_skipped() {
+        let mut ladder = EscalationLadder::new();
+        assert_eq!(ladder.escalate(true, true, 300), Escalation::HardwareReset);
+        assert_eq!(
+            ladder.escalate(true, false, 300),
+            Escalation::BackOff { seconds: 300 }
+        );
+
+        // No reset line, no back-off: warm and cold restarts in turn.
+        let mut ladder = EscalationLadder::new();
+        let names: Vec<&str> = (0..5)
+            .map(|_| ladder.escalate(false, true, 0).name())
+            .collect();
+        assert_eq!(names, ["warm", "cold", "warm", "cold", "warm"]);
+    }
+}
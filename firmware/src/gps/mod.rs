@@ -1,31 +1,72 @@
+mod adaptive;
 mod agnss;
+mod agnss_policy;
+mod escalation;
 mod nmea_parser;
+mod profile;
 mod state_machine;
 
+use core::sync::atomic::{AtomicU32, Ordering};
+
 use embassy_nrf::buffered_uarte::{Baudrate, BufferedUarteRx, BufferedUarteTx};
 use embassy_nrf::gpio::Output;
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_sync::mutex::Mutex;
+use embassy_sync::signal::Signal;
 use embassy_time::{Instant, Timer};
 use nmea::Nmea;
 
-use crate::casic::{CasicPacket, CasicParser, CasicParserState, CASIC_MAX_PAYLOAD_SIZE};
-use crate::system_info::{GpsState, SYSTEM_INFO};
-
-pub use agnss::{set_agnss_message_queue, AgnssMessage, AgnssQueueError, MAX_AGNSS_MESSAGE_SIZE};
+use crate::alerts::{self, AlertKind};
+use crate::casic::{
+    self, CasicPacket, CasicParser, CasicParserState, CASIC_CLASS_CFG, CASIC_CLASS_NAV,
+    CASIC_ID_CFG_MSG, CASIC_ID_NAV_TIMEUTC, CASIC_MAX_PAYLOAD_SIZE,
+};
+use crate::diag::{self, TaskId};
+use crate::display;
+use crate::fix_stats::{FixAttempt, FixStats};
+use crate::gpx_export;
+use crate::heading::HeadingFilter;
+use crate::nmea_command::{self, NmeaSentence};
+use crate::nmea_passthrough;
+use crate::sky_view;
+use crate::storage;
+use crate::system_info::{GpsState, SystemInfo, SYSTEM_INFO};
+use crate::time_check::{TimeCheck, TimeSource, Verdict};
+use crate::timezone;
+
+pub use agnss::{
+    agnss_freshness, queue_aid_ini, set_agnss_message_queue, wait_agnss_freshness_change,
+    AgnssFreshness, AgnssMessage, AgnssQueueError, AGNSS_FRESHNESS_LEN, MAX_AGNSS_MESSAGE_SIZE,
+};
 use agnss::AgnssAck;
-use nmea_parser::{update_system_info_from_nmea, NmeaBuffer, SpeedAverage};
+pub use profile::{active as active_profile, PROFILE_LEN};
+use nmea_parser::{gsa_fix_mode, update_system_info_from_nmea, NmeaBuffer, SpeedAverage};
+use profile::GpsProfile;
 use state_machine::GpsStateMachine;
 
-const GPS_SPEED_VEHICLE_THRESHOLD_KMPH: f32 = 5.0;
+/// Shared with the stationary detector so both agree on what "moving" is.
+const GPS_SPEED_VEHICLE_THRESHOLD_KMPH: f32 = crate::stationary::GPS_MOVING_SPEED_KMPH;
 
-const T_ACTIVE_SAMPLING_INTERVAL_MS: u64 = 1_000;
-const T_STILLNESS_CONFIRM_DURATION_MS: u64 = 60_000;
-const T_GPS_QUERY_TIMEOUT_FOR_STILLNESS_MS: u64 = 5_000;
-const T_GPS_COLD_START_FIX_TIMEOUT_MS: u64 = 90_000;
-const T_GPS_REACQUIRE_FIX_TIMEOUT_MS: u64 = 30_000;
-const MAX_CONSECUTIVE_FIX_FAILURES: u8 = 16;
+const GPS_RESET_PULSE_MS: u64 = 10;
+const GPS_RESET_BOOT_MS: u64 = 500;
 const STATE_TICK_INTERVAL_MS: u64 = 200;
+const GPS_CONSTELLATIONS: u32 = nmea_command::CONSTELLATION_GPS
+    | nmea_command::CONSTELLATION_BDS
+    | nmea_command::CONSTELLATION_GLONASS;
+/// `PCAS02` receiver fix interval (2 Hz).
+const GPS_FIX_INTERVAL_MS: u32 = 500;
+/// After configuring the UART, how long to wait for the first sentence.
+const T_NMEA_DETECT_TIMEOUT_MS: u64 = 3_000;
+/// Rates the receiver may be stuck at, most likely first: its power-on
+/// default, the rate we configure, then the rest of the `PCAS01` table.
+const GPS_BAUD_SCAN: [Baudrate; 6] = [
+    Baudrate::BAUD9600,
+    Baudrate::BAUD115200,
+    Baudrate::BAUD38400,
+    Baudrate::BAUD57600,
+    Baudrate::BAUD19200,
+    Baudrate::BAUD4800,
+];
 
 const EMPTY_CASIC_PACKET: CasicPacket = CasicPacket {
     class_id: 0,
@@ -62,8 +103,24 @@ impl GpsEvents {
 }
 
 static GPS_EVENTS: Mutex<CriticalSectionRawMutex, GpsEvents> = Mutex::new(GpsEvents::new());
+/// Parsed NMEA sentences, to tell a silent receiver from a slow fix.
+static NMEA_SENTENCES: AtomicU32 = AtomicU32::new(0);
+/// Corrupt NMEA lines plus CASIC NACKs, to tell a confused receiver from
+/// bad sky.
+static GPS_ERRORS: AtomicU32 = AtomicU32::new(0);
+/// First sample of a time disagreement, logged to SD by the state task.
+static TIME_EVENT: Signal<CriticalSectionRawMutex, TimeEvent> = Signal::new();
 static GPS_WAKEUP: Mutex<CriticalSectionRawMutex, bool> = Mutex::new(false);
 static GPS_KEEP_ALIVE_DEADLINE: Mutex<CriticalSectionRawMutex, Option<u64>> = Mutex::new(None);
+/// Read from `/FIXSTATS.CFG` at first use.
+static FIX_STATS: Mutex<CriticalSectionRawMutex, Option<FixStats>> = Mutex::new(None);
+
+#[derive(Clone, Copy)]
+struct TimeEvent {
+    nmea_unix: u32,
+    casic_unix: u32,
+    verdict: Verdict,
+}
 
 #[embassy_executor::task]
 pub async fn gps_rx_task(mut rx: BufferedUarteRx<'static>) {
@@ -71,6 +128,8 @@ pub async fn gps_rx_task(mut rx: BufferedUarteRx<'static>) {
     let mut nmea = Nmea::default();
     let mut nmea_buf = NmeaBuffer::new();
     let mut speed_avg = SpeedAverage::new();
+    let mut heading = HeadingFilter::new();
+    let mut time_check = TimeCheck::new();
     let mut buf = [0u8; 128];
 
     loop {
@@ -90,6 +149,8 @@ pub async fn gps_rx_task(mut rx: BufferedUarteRx<'static>) {
             nmea = Nmea::default();
             nmea_buf.reset();
             speed_avg.reset();
+            heading.reset();
+            time_check.reset();
         }
 
         match rx.read(&mut buf).await {
@@ -97,21 +158,50 @@ pub async fn gps_rx_task(mut rx: BufferedUarteRx<'static>) {
                 if n == 0 {
                     continue;
                 }
+                let _busy = diag::busy(TaskId::GpsRx);
                 let now_ms = Instant::now().as_millis();
                 for &byte in &buf[..n] {
                     parser.encode(byte, now_ms);
 
                     if parser.parser_state() == CasicParserState::Idle {
                         if let Some(line_len) = nmea_buf.push(byte) {
-                            if let Some(sentence) = nmea_buf.as_str(line_len) {
-                                if nmea.parse(sentence).is_ok() {
+                            let Some(sentence) = nmea_buf.as_str(line_len) else {
+                                GPS_ERRORS.fetch_add(1, Ordering::Relaxed);
+                                continue;
+                            };
+                            nmea_passthrough::forward_sentence(sentence);
+                            if let Some(gsv) = sky_view::parse_gsv(sentence) {
+                                NMEA_SENTENCES.fetch_add(1, Ordering::Relaxed);
+                                SYSTEM_INFO.lock().await.sky.note(&gsv);
+                                continue;
+                            }
+                            match nmea.parse(sentence) {
+                                Ok(kind) => {
+                                    NMEA_SENTENCES.fetch_add(1, Ordering::Relaxed);
                                     let mut info = SYSTEM_INFO.lock().await;
                                     update_system_info_from_nmea(
                                         &mut *info,
                                         &nmea,
                                         &mut speed_avg,
                                     );
+                                    if kind == nmea::SentenceType::RMC {
+                                        let still = info.is_stationary;
+                                        info.heading = heading
+                                            .update(nmea.true_course, info.speed, still)
+                                            .unwrap_or(-1.0);
+                                        // Once per fix epoch.
+                                        display::notify_info_changed();
+                                    }
+                                    check_time(&mut *info, &mut time_check, now_ms);
+                                    if let Some(mode) = gsa_fix_mode(sentence) {
+                                        info.fix_mode = mode;
+                                    }
                                 }
+                                Err(nmea::Error::ChecksumMismatch { .. }) => {
+                                    GPS_ERRORS.fetch_add(1, Ordering::Relaxed);
+                                }
+                                // Unsupported sentence types are not garbage.
+                                Err(_) => {}
                             }
                         }
                     }
@@ -119,6 +209,17 @@ pub async fn gps_rx_task(mut rx: BufferedUarteRx<'static>) {
 
                 if parser.is_new_casic_data() {
                     let pkt = parser.last_casic_packet();
+                    if let Some(utc) = casic::nav_timeutc(&pkt) {
+                        let (year, month, day, hour, minute, second) = utc;
+                        let unix = timezone::date_time_to_unix_timestamp(
+                            year, month, day, hour, minute, second,
+                        );
+                        if let Some(unix) = unix {
+                            time_check.note_casic(unix, now_ms);
+                        }
+                        parser.clear_casic_data();
+                        continue;
+                    }
                     defmt::debug!(
                         "CASIC class={} id={} len={} valid={}",
                         pkt.class_id,
@@ -134,6 +235,7 @@ pub async fn gps_rx_task(mut rx: BufferedUarteRx<'static>) {
                     }
                     if parser.has_new_nack() {
                         events.nack = true;
+                        GPS_ERRORS.fetch_add(1, Ordering::Relaxed);
                     }
                     if parser.has_new_ephemeris() {
                         events.ephemeris = true;
@@ -153,19 +255,104 @@ pub async fn gps_rx_task(mut rx: BufferedUarteRx<'static>) {
 pub async fn gps_state_task(
     mut tx: BufferedUarteTx<'static>,
     mut gps_en: Output<'static>,
+    gps_reset: Option<Output<'static>>,
 ) {
     set_gps_state(GpsState::S0Initializing).await;
     configure_gps_uart(&mut tx, &mut gps_en).await;
-    let mut sm = GpsStateMachine::new();
+    let mut sm = GpsStateMachine::new(gps_reset);
     sm.initialize(&mut gps_en).await;
 
     loop {
-        let now_ms = Instant::now().as_millis();
-        sm.step(now_ms, &mut tx, &mut gps_en).await;
+        {
+            let _busy = diag::busy(TaskId::GpsState);
+            let now_ms = Instant::now().as_millis();
+            sm.step(now_ms, &mut tx, &mut gps_en).await;
+        }
         Timer::after_millis(STATE_TICK_INTERVAL_MS).await;
     }
 }
 
+/// Check the NMEA time just parsed into `info` against CASIC and replace or
+/// drop it when NMEA is the inconsistent one.
+fn check_time(info: &mut SystemInfo, check: &mut TimeCheck, now_ms: u64) {
+    if !info.date_time_valid {
+        return;
+    }
+    let Some(nmea_unix) = timezone::date_time_to_unix_timestamp(
+        info.year,
+        info.month,
+        info.day,
+        info.hour,
+        info.minute,
+        info.second,
+    ) else {
+        return;
+    };
+    let verdict = check.check_nmea(nmea_unix, now_ms);
+    let inconsistent = verdict != Verdict::Consistent;
+    let casic_unix = check.casic_time(now_ms).unwrap_or(0);
+    if inconsistent && !info.time_inconsistent {
+        defmt::warn!(
+            "GPS time: NMEA {} vs CASIC {}, {}",
+            nmea_unix,
+            casic_unix,
+            time_choice(verdict)
+        );
+        TIME_EVENT.signal(TimeEvent {
+            nmea_unix,
+            casic_unix,
+            verdict,
+        });
+    } else if !inconsistent && info.time_inconsistent {
+        defmt::info!("GPS time: NMEA and CASIC agree again");
+    }
+    info.time_inconsistent = inconsistent;
+
+    match verdict {
+        Verdict::Consistent | Verdict::Prefer(TimeSource::Nmea) => {}
+        Verdict::Prefer(TimeSource::Casic) => {
+            let (year, month, day, hour, minute, second) =
+                gpx_export::civil_from_unix(casic_unix);
+            info.year = year as u16;
+            info.month = month as u8;
+            info.day = day as u8;
+            info.hour = hour as u8;
+            info.minute = minute as u8;
+            info.second = second as u8;
+            storage::set_gps_time(
+                info.year,
+                info.month,
+                info.day,
+                info.hour,
+                info.minute,
+                info.second,
+            );
+        }
+        Verdict::Unreliable => {
+            // Same as a sentence without time: no timestamp, no fix.
+            info.date_time_valid = false;
+            info.location_valid = false;
+        }
+    }
+}
+
+fn time_choice(verdict: Verdict) -> &'static str {
+    match verdict {
+        Verdict::Consistent | Verdict::Prefer(TimeSource::Nmea) => "nmea",
+        Verdict::Prefer(TimeSource::Casic) => "casic",
+        Verdict::Unreliable => "none",
+    }
+}
+
+/// Log the last time disagreement, if any. Called from the state task so
+/// the UART reader never waits for the SD card.
+async fn log_time_event() {
+    if let Some(event) = TIME_EVENT.try_take() {
+        let chosen = time_choice(event.verdict);
+        let _ = storage::append_time_event(event.nmea_unix, event.casic_unix, chosen).await;
+    }
+}
+
 pub async fn trigger_gps_wakeup() {
     let mut wake = GPS_WAKEUP.lock().await;
     *wake = true;
@@ -190,7 +377,13 @@ pub async fn set_gps_keep_alive(duration_minutes: u16) {
     }
 }
 
+/// Keep-alive left for the system info snapshot, saturated at `u16::MAX`.
 pub async fn get_keep_alive_remaining_s() -> u16 {
+    keep_alive_remaining_s().await.min(u16::MAX as u32) as u16
+}
+
+/// Seconds of keep-alive left, 0 when none is set.
+pub async fn keep_alive_remaining_s() -> u32 {
     let ka = GPS_KEEP_ALIVE_DEADLINE.lock().await;
     match *ka {
         Some(deadline) => {
@@ -198,27 +391,68 @@ pub async fn get_keep_alive_remaining_s() -> u16 {
             if now_ms >= deadline {
                 0
             } else {
-                ((deadline - now_ms) / 1000) as u16
+                ((deadline - now_ms) / 1000) as u32
             }
         }
         None => 0,
     }
 }
 
+/// Whether keep-alive holds the GPS on. Raises the expiry alert the first
+/// time it is found run out; a cancel does not.
 async fn is_keep_alive_active(now_ms: u64) -> bool {
     let mut ka = GPS_KEEP_ALIVE_DEADLINE.lock().await;
-    match *ka {
-        Some(deadline) => {
-            if now_ms >= deadline {
-                *ka = None;
-                defmt::info!("GPS keep-alive expired");
-                false
-            } else {
-                true
-            }
-        }
-        None => false,
+    let Some(deadline) = *ka else {
+        return false;
+    };
+    if now_ms < deadline {
+        return true;
+    }
+    *ka = None;
+    drop(ka);
+    defmt::info!("GPS keep-alive expired");
+    alerts::raise(AlertKind::KeepAliveExpired, "Keep-alive ended").await;
+    false
+}
+
+/// Count one GPS power cycle ending at `unix` (0 when unknown): a
+/// `/FIXLOG.CSV` line and the per-day statistics with their checkpoint.
+async fn note_fix_attempt(attempt: FixAttempt, unix: u32) {
+    defmt::info!(
+        "GPS power cycle: fix={} after {} s, agnss={}",
+        attempt.fixed,
+        attempt.duration_s,
+        attempt.agnss
+    );
+    if !storage::append_fix_log(unix, &attempt).await {
+        defmt::warn!("GPS: FIXLOG.CSV append failed");
+    }
+    let date = if unix == 0 {
+        0
+    } else {
+        let (year, month, day, _, _, _) = gpx_export::civil_from_unix(unix);
+        year * 10_000 + month * 100 + day
+    };
+    let mut stats = FIX_STATS.lock().await;
+    let stats = loaded_fix_stats(&mut stats).await;
+    stats.note(date, &attempt);
+    if !storage::write_fix_stats(&stats.to_bytes()).await {
+        defmt::warn!("GPS: FIXSTATS.CFG write failed");
+    }
+}
+
+/// Per-day outcome of the GPS power cycles, for `GET_FIX_STATS`.
+pub async fn fix_stats() -> FixStats {
+    let mut stats = FIX_STATS.lock().await;
+    *loaded_fix_stats(&mut stats).await
+}
+
+async fn loaded_fix_stats(slot: &mut Option<FixStats>) -> &mut FixStats {
+    if slot.is_none() {
+        let saved = storage::read_fix_stats().await;
+        *slot = Some(saved.map_or(FixStats::new(), |raw| FixStats::from_bytes(&raw)));
     }
+    slot.get_or_insert(FixStats::new())
 }
 
 async fn set_gps_state(state: GpsState) {
@@ -277,21 +511,90 @@ async fn configure_gps_uart(tx: &mut BufferedUarteTx<'static>, gps_en: &mut Outp
     gps_en.set_high();
     Timer::after_millis(100).await;
 
-    tx.set_baudrate(Baudrate::BAUD9600);
-    write_all(tx, b"$PCAS04,7*1E\r\n").await;
-    write_all(tx, b"$PCAS03,1,0,0,0,1,0,0,0,0,0,,,0,0*02\r\n").await;
+    send_uart_config(tx, Baudrate::BAUD9600).await;
+    if wait_for_nmea(T_NMEA_DETECT_TIMEOUT_MS).await {
+        defmt::info!("GPS UART configured");
+    } else {
+        defmt::warn!("GPS: no NMEA after configure");
+        recover_gps_baud(tx).await;
+    }
+}
+
+/// Send the output and rate configuration at `from`, the rate the receiver
+/// is believed to listen at, and switch the UART to 115200.
+async fn send_uart_config(tx: &mut BufferedUarteTx<'static>, from: Baudrate) {
+    tx.set_baudrate(from);
+    write_command(tx, nmea_command::set_constellations(GPS_CONSTELLATIONS)).await;
+    let rates = nmea_command::SentenceRates::TRACKING;
+    write_command(tx, nmea_command::set_sentence_rates(&rates)).await;
     Timer::after_millis(1500).await;
-    write_all(tx, b"$PCAS01,5*19\r\n").await;
+    write_command(tx, nmea_command::set_baud_rate(nmea_command::BAUD_115200)).await;
     Timer::after_millis(1500).await;
 
     tx.set_baudrate(Baudrate::BAUD115200);
     for _ in 0..4 {
-        write_all(tx, b"$PCAS02,500*1A\r\n").await;
+        write_command(tx, nmea_command::set_fix_interval_ms(GPS_FIX_INTERVAL_MS)).await;
         Timer::after_millis(100).await;
     }
+    // CFG-MSG: NAV-TIMEUTC once per fix, for `time_check`.
+    let mut packet = [0u8; 14];
+    let cfg = [CASIC_CLASS_NAV, CASIC_ID_NAV_TIMEUTC, 1, 0];
+    if let Some(len) =
+        casic::encode_casic_packet(CASIC_CLASS_CFG, CASIC_ID_CFG_MSG, &cfg, &mut packet)
+    {
+        write_all(tx, &packet[..len]).await;
+    }
 
     request_gps_parser_reset().await;
-    defmt::info!("GPS UART configured");
+}
+
+/// Re-run the configuration from each rate in `GPS_BAUD_SCAN` until NMEA
+/// comes back at 115200. Returns whether it did.
+async fn recover_gps_baud(tx: &mut BufferedUarteTx<'static>) -> bool {
+    for (attempt, &rate) in GPS_BAUD_SCAN.iter().enumerate() {
+        defmt::info!("GPS baud scan: attempt {} of {}", attempt + 1, GPS_BAUD_SCAN.len());
+        send_uart_config(tx, rate).await;
+        if wait_for_nmea(T_NMEA_DETECT_TIMEOUT_MS).await {
+            defmt::info!("GPS baud scan: NMEA back after attempt {}", attempt + 1);
+            return true;
+        }
+    }
+    defmt::warn!("GPS baud scan: no NMEA at any rate");
+    false
+}
+
+fn nmea_sentence_count() -> u32 {
+    NMEA_SENTENCES.load(Ordering::Relaxed)
+}
+
+fn gps_error_count() -> u32 {
+    GPS_ERRORS.load(Ordering::Relaxed)
+}
+
+/// Pulse the receiver's reset line and configure it again from its
+/// power-on rate.
+async fn hard_reset_gps(tx: &mut BufferedUarteTx<'static>, reset: &mut Output<'static>) {
+    defmt::warn!("GPS hardware reset");
+    reset.set_low();
+    Timer::after_millis(GPS_RESET_PULSE_MS).await;
+    reset.set_high();
+    Timer::after_millis(GPS_RESET_BOOT_MS).await;
+    send_uart_config(tx, Baudrate::BAUD9600).await;
+    if !wait_for_nmea(T_NMEA_DETECT_TIMEOUT_MS).await {
+        recover_gps_baud(tx).await;
+    }
+}
+
+async fn wait_for_nmea(timeout_ms: u64) -> bool {
+    let start = nmea_sentence_count();
+    let deadline = Instant::now().as_millis() + timeout_ms;
+    while Instant::now().as_millis() < deadline {
+        Timer::after_millis(STATE_TICK_INTERVAL_MS).await;
+        if nmea_sentence_count() != start {
+            return true;
+        }
+    }
+    false
 }
 
 async fn request_gps_parser_reset() {
@@ -310,6 +613,15 @@ fn has_elapsed(start: Option<u64>, now_ms: u64, timeout_ms: u64) -> bool {
     }
 }
 
+/// Send a command built by `nmea_command`; one that did not fit is logged
+/// and skipped.
+async fn write_command(tx: &mut BufferedUarteTx<'static>, command: Option<NmeaSentence>) {
+    match command {
+        Some(sentence) => write_all(tx, sentence.as_bytes()).await,
+        None => defmt::warn!("GPS: NMEA command too long, not sent"),
+    }
+}
+
 async fn write_all(tx: &mut BufferedUarteTx<'static>, data: &[u8]) {
     let mut offset = 0;
     while offset < data.len() {
@@ -1,5 +1,5 @@
 use chrono::{Datelike, Timelike};
-use nmea::Nmea;
+use nmea::{FixType, Nmea};
 
 use crate::storage;
 use crate::system_info::SystemInfo;
@@ -116,6 +116,37 @@ impl SpeedAverage {
     }
 }
 
+/// Extract the fix mode (field 2: 1 = none, 2 = 2D, 3 = 3D) from a GSA sentence.
+/// The nmea crate parses GSA but does not keep the mode in `Nmea`.
+pub(super) fn gsa_fix_mode(sentence: &str) -> Option<u8> {
+    let mut fields = sentence.split(',');
+    let talker = fields.next()?;
+    if talker.len() < 6 || !talker.ends_with("GSA") {
+        return None;
+    }
+    let _selection = fields.next()?;
+    match fields.next()? {
+        "1" => Some(1),
+        "2" => Some(2),
+        "3" => Some(3),
+        _ => None,
+    }
+}
+
+fn fix_quality_code(fix_type: FixType) -> u8 {
+    match fix_type {
+        FixType::Invalid => 0,
+        FixType::Gps => 1,
+        FixType::DGps => 2,
+        FixType::Pps => 3,
+        FixType::Rtk => 4,
+        FixType::FloatRtk => 5,
+        FixType::Estimated => 6,
+        FixType::Manual => 7,
+        FixType::Simulation => 8,
+    }
+}
+
 pub(super) fn update_system_info_from_nmea(
     info: &mut SystemInfo,
     nmea: &Nmea,
@@ -182,6 +213,7 @@ pub(super) fn update_system_info_from_nmea(
     }
 
     info.hdop = nmea.hdop.unwrap_or(99.9);
+    info.fix_quality = nmea.fix_type.map(fix_quality_code).unwrap_or(0);
 
     if let Some(knots) = nmea.speed_over_ground {
         let kmh = knots * KMPH_PER_KNOT;
@@ -0,0 +1,174 @@
+//! GPS tuning profiles.
+//!
+//! The state machine's timeouts, thresholds and retry counts live in one
+//! `GpsProfile`, picked by the `gps.profile` setting: three built-in presets,
+//! or a custom one from the `gps.*` settings. Field workarounds for a slow or
+//! quirky receiver then need no rebuild.
+//!
+//! # Design
+//!
+//! - The profile is re-read on every state machine step, so a change applies
+//!   at once; settings are atomics, so this costs a few loads.
+//! - Custom values go through the settings registry, which range-checks
+//!   them and persists them in `/SETTINGS.CFG`.
+//! - Timing the UART bring-up itself (baud detection, reset pulse) stays
+//!   constant: it runs before the settings are loaded.
+//! - The solar policy (`power.solar`) scales whichever profile is selected,
+//!   so it combines with custom values too. So does the adaptive policy,
+//!   which the state machine applies with the current speed and battery.
+//!   The AGNSS policy (`gps.agnss_*`) is read alongside them.
+
+use super::adaptive::AdaptivePolicy;
+use super::agnss_policy::AgnssPolicy;
+use crate::settings;
+use crate::solar::{self, SolarMode};
+
+/// Wire size of `GpsProfile::to_bytes`.
+pub const PROFILE_LEN: usize = 32;
+/// Sampling interval bounds for the solar adjustments.
+const SOLAR_MIN_SAMPLING_MS: u32 = 500;
+const SOLAR_MAX_SAMPLING_MS: u32 = 10_000;
+
+#[repr(u8)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ProfileId {
+    Default = 0,
+    /// Long fix timeouts for poor sky or a receiver with a slow first fix.
+    LongSearch = 1,
+    /// Slower sampling and shorter searches to save battery.
+    PowerSaver = 2,
+    /// Values from the `gps.*` settings.
+    Custom = 3,
+}
+
+#[derive(Clone, Copy)]
+pub struct GpsProfile {
+    pub sampling_interval_ms: u32,
+    pub stillness_confirm_ms: u32,
+    pub stillness_query_timeout_ms: u32,
+    pub cold_start_fix_timeout_ms: u32,
+    pub reacquire_fix_timeout_ms: u32,
+    /// Fix timeouts in a row before a warm restart or hardware reset.
+    pub max_fix_failures: u32,
+    pub nmea_silence_timeout_ms: u32,
+    /// Corrupt lines or NACKs since the last escalation that make it pulse
+    /// the reset line instead of sending a warm restart.
+    pub errors_for_hard_reset: u32,
+}
+
+impl GpsProfile {
+    pub const DEFAULT: Self = Self {
+        sampling_interval_ms: 1_000,
+        stillness_confirm_ms: 60_000,
+        stillness_query_timeout_ms: 5_000,
+        cold_start_fix_timeout_ms: 90_000,
+        reacquire_fix_timeout_ms: 30_000,
+        max_fix_failures: 16,
+        nmea_silence_timeout_ms: 10_000,
+        errors_for_hard_reset: 32,
+    };
+
+    const LONG_SEARCH: Self = Self {
+        cold_start_fix_timeout_ms: 180_000,
+        reacquire_fix_timeout_ms: 60_000,
+        max_fix_failures: 8,
+        nmea_silence_timeout_ms: 15_000,
+        ..Self::DEFAULT
+    };
+
+    const POWER_SAVER: Self = Self {
+        sampling_interval_ms: 2_000,
+        stillness_confirm_ms: 30_000,
+        cold_start_fix_timeout_ms: 60_000,
+        reacquire_fix_timeout_ms: 20_000,
+        ..Self::DEFAULT
+    };
+
+    fn custom() -> Self {
+        let s = |id| settings::stored(id) as u32;
+        Self {
+            sampling_interval_ms: s(settings::GPS_SAMPLE_INTERVAL_MS),
+            stillness_confirm_ms: s(settings::GPS_STILL_CONFIRM_S) * 1000,
+            stillness_query_timeout_ms: s(settings::GPS_STILL_QUERY_S) * 1000,
+            cold_start_fix_timeout_ms: s(settings::GPS_COLD_FIX_S) * 1000,
+            reacquire_fix_timeout_ms: s(settings::GPS_REACQUIRE_FIX_S) * 1000,
+            max_fix_failures: s(settings::GPS_MAX_FIX_FAILURES),
+            nmea_silence_timeout_ms: s(settings::GPS_NMEA_SILENCE_S) * 1000,
+            errors_for_hard_reset: s(settings::GPS_HARD_RESET_ERRORS),
+        }
+    }
+
+    /// While charging: sample twice as often and stay on twice as long
+    /// before a stop powers the GPS down. At night: sample a quarter as often
+    /// and give up on stops and fix searches in half the time.
+    fn for_solar(self, mode: SolarMode) -> Self {
+        match mode {
+            SolarMode::Normal => self,
+            SolarMode::Boost => Self {
+                sampling_interval_ms: (self.sampling_interval_ms / 2).max(SOLAR_MIN_SAMPLING_MS),
+                stillness_confirm_ms: self.stillness_confirm_ms.saturating_mul(2),
+                ..self
+            },
+            SolarMode::Night => Self {
+                sampling_interval_ms: self
+                    .sampling_interval_ms
+                    .saturating_mul(4)
+                    .min(SOLAR_MAX_SAMPLING_MS),
+                stillness_confirm_ms: self.stillness_confirm_ms / 2,
+                cold_start_fix_timeout_ms: self.cold_start_fix_timeout_ms / 2,
+                reacquire_fix_timeout_ms: self.reacquire_fix_timeout_ms / 2,
+                ..self
+            },
+        }
+    }
+
+    /// 8 x `u32` LE, in field order.
+    pub fn to_bytes(&self) -> [u8; PROFILE_LEN] {
+        let fields = [
+            self.sampling_interval_ms,
+            self.stillness_confirm_ms,
+            self.stillness_query_timeout_ms,
+            self.cold_start_fix_timeout_ms,
+            self.reacquire_fix_timeout_ms,
+            self.max_fix_failures,
+            self.nmea_silence_timeout_ms,
+            self.errors_for_hard_reset,
+        ];
+        let mut out = [0u8; PROFILE_LEN];
+        for (value, chunk) in fields.iter().zip(out.chunks_exact_mut(4)) {
+            chunk.copy_from_slice(&value.to_le_bytes());
+        }
+        out
+    }
+}
+
+/// The profile selected by `gps.profile`, adjusted for the solar mode.
+pub fn active() -> (ProfileId, GpsProfile) {
+    let (id, profile) = match settings::stored(settings::GPS_PROFILE) {
+        1 => (ProfileId::LongSearch, GpsProfile::LONG_SEARCH),
+        2 => (ProfileId::PowerSaver, GpsProfile::POWER_SAVER),
+        3 => (ProfileId::Custom, GpsProfile::custom()),
+        _ => (ProfileId::Default, GpsProfile::DEFAULT),
+    };
+    (id, profile.for_solar(solar::mode()))
+}
+
+/// The speed and battery adjustments from the adaptive `gps.*` settings.
+pub fn adaptive() -> AdaptivePolicy {
+    let s = |id| settings::stored(id) as u32;
+    AdaptivePolicy {
+        walk_kmh: s(settings::GPS_WALK_KMH) as f32,
+        walk_interval_ms: s(settings::GPS_WALK_INTERVAL_MS),
+        wake_interval_min: s(settings::GPS_WAKE_INTERVAL_MIN),
+        low_battery_wake_min: s(settings::GPS_LOW_BATT_WAKE_MIN),
+        low_battery_pct: s(settings::GPS_LOW_BATT_PCT) as u8,
+    }
+}
+
+/// When a pending AGNSS upload is held back, from `gps.agnss_*`.
+pub fn agnss_policy() -> AgnssPolicy {
+    AgnssPolicy {
+        skip_hdop: settings::stored(settings::GPS_AGNSS_SKIP_HDOP) as f32 / 10.0,
+        defer_battery_pct: settings::stored(settings::GPS_AGNSS_DEFER_PCT) as u8,
+    }
+}
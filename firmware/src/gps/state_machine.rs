@@ -1,30 +1,55 @@
 use embassy_nrf::buffered_uarte::BufferedUarteTx;
 use embassy_nrf::gpio::Output;
-use embassy_time::Timer;
+use embassy_time::{Instant, Timer};
 
 use super::agnss::{
-    agnss_ack_next, agnss_finish_processing, agnss_mark_message_sent, agnss_message_timeout,
-    agnss_note_motion, agnss_retry_or_fail, agnss_should_trigger, agnss_start_processing,
-    agnss_total_timeout, AgnssAck, AgnssOutcome,
+    agnss_ack_next, agnss_check_expiry, agnss_finish_processing, agnss_freshness,
+    agnss_mark_message_sent, agnss_message_timeout, agnss_note_motion, agnss_retry_or_fail,
+    agnss_should_trigger, agnss_start_processing, agnss_total_timeout, AgnssAck, AgnssFreshness,
+    AgnssOutcome,
 };
+use super::agnss_policy::{AgnssGate, AgnssStep};
+use super::escalation::{Escalation, EscalationLadder};
 use super::{
-    drain_non_agnss_events, has_elapsed, set_gps_state, snapshot_system_info, take_agnss_ack,
-    take_gps_wakeup, write_all, GPS_EVENTS, GPS_SPEED_VEHICLE_THRESHOLD_KMPH,
-    MAX_CONSECUTIVE_FIX_FAILURES, T_ACTIVE_SAMPLING_INTERVAL_MS, T_GPS_COLD_START_FIX_TIMEOUT_MS,
-    T_GPS_QUERY_TIMEOUT_FOR_STILLNESS_MS, T_GPS_REACQUIRE_FIX_TIMEOUT_MS,
-    T_STILLNESS_CONFIRM_DURATION_MS,
+    drain_non_agnss_events, gps_error_count, hard_reset_gps, has_elapsed, log_time_event,
+    nmea_sentence_count, note_fix_attempt, recover_gps_baud, set_gps_state, snapshot_system_info,
+    take_agnss_ack, take_gps_wakeup, write_all, write_command, GpsProfile, GPS_EVENTS,
+    GPS_SPEED_VEHICLE_THRESHOLD_KMPH,
 };
+use crate::accel;
+use crate::activity::{Activity, ActivityClassifier};
+use crate::altitude_fusion::AltitudeFusion;
+use crate::bmp280;
+use crate::fix_stats::FixAttempt;
+use crate::flash_track;
+use crate::fuel_gauge::ChargeState;
+use crate::geofences;
+use crate::live_track;
+use crate::location_history;
+use crate::nmea_command;
+use crate::nmea_passthrough;
+use crate::phone_location;
+use crate::recording;
+use crate::settings;
 use crate::storage;
 use crate::system_info::{GpsState, SYSTEM_INFO};
 use crate::timezone;
+use crate::trend;
 
 #[derive(Clone, Copy)]
 struct PositionResult {
     timestamp: u32,
     latitude: f64,
     longitude: f64,
+    /// GPS altitude fused with the barometer when it has a reading.
     altitude_m: f32,
+    pressure_pa: Option<f32>,
     hdop: f32,
+    /// km/h, negative when unknown.
+    speed_kmh: f32,
+    /// As `SystemInfo::fix_mode` and `fix_quality`.
+    fix_mode: u8,
+    fix_quality: u8,
 }
 
 impl Default for PositionResult {
@@ -34,7 +59,11 @@ impl Default for PositionResult {
             latitude: 0.0,
             longitude: 0.0,
             altitude_m: 0.0,
+            pressure_pa: None,
             hdop: 1.0e9_f32,
+            speed_kmh: -1.0,
+            fix_mode: 0,
+            fix_quality: 0,
         }
     }
 }
@@ -48,10 +77,33 @@ pub(super) struct GpsStateMachine {
     is_gps_powered_on: bool,
     is_first_fix_attempt_cycle: bool,
     last_successful_position: PositionResult,
+    /// `nmea_sentence_count()` at the last check, and since when it has not
+    /// moved while the GPS is powered.
+    nmea_count: u32,
+    nmea_silent_since: Option<u64>,
+    /// Receiver reset line, if the board has one (`gps-reset` feature).
+    reset_line: Option<Output<'static>>,
+    /// `gps_error_count()` at the last fix-failure escalation.
+    errors_at_escalation: u32,
+    escalation: EscalationLadder,
+    /// End of a fix-failure back-off, during which S2 keeps the GPS off.
+    backoff_until: Option<u64>,
+    /// Refreshed from the settings on every step.
+    profile: GpsProfile,
+    altitude_fusion: AltitudeFusion,
+    activity: ActivityClassifier,
+    /// Power-on of the cycle that has not reached a fix yet.
+    power_on_ms: Option<u64>,
+    /// `(unix, uptime_ms)` of the last first fix, to date cycles without one.
+    clock_anchor: Option<(u32, u64)>,
+    /// Since when S2 has kept the GPS off, for the periodic wake.
+    idle_since: Option<u64>,
+    /// Last verdict on a pending AGNSS upload, to log only its changes.
+    agnss_gate: AgnssGate,
 }
 
 impl GpsStateMachine {
-    pub(super) fn new() -> Self {
+    pub(super) fn new(reset_line: Option<Output<'static>>) -> Self {
         Self {
             stillness_confirm_start: None,
             active_sampling_start: None,
@@ -61,9 +113,37 @@ impl GpsStateMachine {
             is_gps_powered_on: false,
             is_first_fix_attempt_cycle: true,
             last_successful_position: PositionResult::default(),
+            nmea_count: 0,
+            nmea_silent_since: None,
+            reset_line,
+            errors_at_escalation: 0,
+            escalation: EscalationLadder::new(),
+            backoff_until: None,
+            profile: GpsProfile::DEFAULT,
+            altitude_fusion: AltitudeFusion::new(),
+            activity: ActivityClassifier::new(),
+            power_on_ms: None,
+            clock_anchor: None,
+            idle_since: None,
+            agnss_gate: AgnssGate::Allow,
         }
     }
 
+    /// Feed the point just logged to the activity classifier and publish
+    /// its label for the main page.
+    async fn classify_activity(&mut self) -> Activity {
+        let position = &self.last_successful_position;
+        let motion_g = accel::motion_g();
+        let activity = self.activity.update(
+            position.timestamp,
+            position.speed_kmh,
+            position.altitude_m,
+            motion_g,
+        );
+        SYSTEM_INFO.lock().await.activity = activity;
+        activity
+    }
+
     fn reset_state_timers(&mut self) {
         self.stillness_confirm_start = None;
         self.active_sampling_start = None;
@@ -85,25 +165,33 @@ impl GpsStateMachine {
         }
         gps_en.set_high();
         self.is_gps_powered_on = true;
+        self.power_on_ms = Some(Instant::now().as_millis());
         defmt::info!("GPS power on");
         Timer::after_millis(100).await;
     }
 
     async fn power_off_gps(&mut self, gps_en: &mut Output<'static>) {
         gps_en.set_low();
+        let now_ms = Instant::now().as_millis();
+        self.end_fix_attempt(false, now_ms).await;
         if self.is_gps_powered_on {
             defmt::info!("GPS power off");
         }
         self.is_gps_powered_on = false;
+        self.nmea_silent_since = None;
 
         let mut info = SYSTEM_INFO.lock().await;
         info.location_valid = false;
         info.date_time_valid = false;
+        info.time_inconsistent = false;
         info.latitude = 0.0;
         info.longitude = 0.0;
         info.altitude = 0.0;
         info.satellites = 0;
+        info.sky.clear();
         info.hdop = 99.9;
+        info.fix_mode = 0;
+        info.fix_quality = 0;
         info.speed = -1.0;
         info.course = -1.0;
         info.year = 0;
@@ -121,6 +209,95 @@ impl GpsStateMachine {
         events.ephemeris = false;
     }
 
+    /// Count the running power cycle if it has not reached a fix yet.
+    async fn end_fix_attempt(&mut self, fixed: bool, now_ms: u64) {
+        let Some(start_ms) = self.power_on_ms.take() else {
+            return;
+        };
+        if fixed {
+            self.clock_anchor = Some((self.last_successful_position.timestamp, now_ms));
+        }
+        let unix = self.unix_at(now_ms);
+        let agnss = matches!(
+            agnss_freshness().await,
+            AgnssFreshness::Fresh { .. } | AgnssFreshness::Unknown
+        );
+        let attempt = FixAttempt {
+            fixed,
+            duration_s: (now_ms.saturating_sub(start_ms) / 1000) as u32,
+            agnss,
+        };
+        note_fix_attempt(attempt, unix).await;
+    }
+
+    /// Unix time at uptime `now_ms`, from the last first fix or the phone; 0
+    /// when neither is known.
+    fn unix_at(&self, now_ms: u64) -> u32 {
+        match self.clock_anchor {
+            Some((unix, at_ms)) => unix + (now_ms.saturating_sub(at_ms) / 1000) as u32,
+            None => phone_location::unix_now().map_or(0, |unix| unix as u32),
+        }
+    }
+
+    /// Rescan the baud if the powered receiver has gone quiet, e.g. after a
+    /// brown-out left it at another rate.
+    async fn check_nmea_silence(&mut self, now_ms: u64, tx: &mut BufferedUarteTx<'static>) {
+        let count = nmea_sentence_count();
+        if count != self.nmea_count || self.nmea_silent_since.is_none() {
+            self.nmea_count = count;
+            self.nmea_silent_since = Some(now_ms);
+            return;
+        }
+        let silence_ms = self.profile.nmea_silence_timeout_ms as u64;
+        if has_elapsed(self.nmea_silent_since, now_ms, silence_ms) {
+            defmt::warn!("GPS: no NMEA for {} ms, rescanning baud", silence_ms);
+            if !recover_gps_baud(tx).await {
+                if let Some(reset) = self.reset_line.as_mut() {
+                    hard_reset_gps(tx, reset).await;
+                }
+            }
+            self.nmea_count = nmea_sentence_count();
+            self.nmea_silent_since = Some(Instant::now().as_millis());
+        }
+    }
+
+    /// Repeated fix failures: take the next step of the escalation ladder
+    /// and log it to `/GPSESC.CSV`.
+    async fn escalate_fix_failures(&mut self, now_ms: u64, tx: &mut BufferedUarteTx<'static>) {
+        let recent_errors = gps_error_count().wrapping_sub(self.errors_at_escalation);
+        let noisy = recent_errors >= self.profile.errors_for_hard_reset;
+        let backoff_s = settings::stored(settings::GPS_FIX_BACKOFF_S) as u32;
+        let can_reset = self.reset_line.is_some();
+        let step = self.escalation.escalate(can_reset, noisy, backoff_s);
+        let mut logged_backoff_s = 0;
+        match step {
+            Escalation::WarmRestart => {
+                defmt::info!("GPS warm restart after fix failures");
+                write_command(tx, nmea_command::restart(nmea_command::RESTART_WARM)).await;
+            }
+            Escalation::ColdRestart => {
+                defmt::info!("GPS cold restart after fix failures");
+                write_command(tx, nmea_command::restart(nmea_command::RESTART_COLD)).await;
+            }
+            Escalation::HardwareReset => {
+                defmt::info!("GPS hardware reset after fix failures ({} errors)", recent_errors);
+                if let Some(reset) = self.reset_line.as_mut() {
+                    hard_reset_gps(tx, reset).await;
+                }
+            }
+            Escalation::BackOff { seconds } => {
+                defmt::info!("GPS backing off for {} s after fix failures", seconds);
+                self.backoff_until = Some(now_ms + seconds as u64 * 1000);
+                logged_backoff_s = seconds;
+            }
+        }
+        self.errors_at_escalation = gps_error_count();
+        let unix = self.unix_at(now_ms);
+        if !storage::append_escalation_log(unix, step.name(), logged_backoff_s).await {
+            defmt::warn!("GPS: GPSESC.CSV append failed");
+        }
+    }
+
     async fn maybe_trigger_agnss(
         &mut self,
         state: GpsState,
@@ -128,7 +305,7 @@ impl GpsStateMachine {
         tx: &mut BufferedUarteTx<'static>,
         gps_en: &mut Output<'static>,
     ) -> bool {
-        if !agnss_should_trigger(now_ms, state).await {
+        if !self.agnss_allowed(state, now_ms).await {
             return false;
         }
         let message = agnss_start_processing(state, now_ms).await;
@@ -146,6 +323,31 @@ impl GpsStateMachine {
         true
     }
 
+    /// Whether an upload is pending and the `gps.agnss_*` policy lets it go now.
+    async fn agnss_allowed(&mut self, state: GpsState, now_ms: u64) -> bool {
+        let pending = agnss_should_trigger(now_ms, state).await;
+        let tracking_hdop = match state {
+            GpsState::S3TrackingFixed => Some(SYSTEM_INFO.lock().await.hdop),
+            _ => None,
+        };
+        let battery_pct = discharging_battery_pct().await;
+        let policy = super::profile::agnss_policy();
+        let gate = match policy.step(pending, tracking_hdop, battery_pct) {
+            AgnssStep::Idle => return false,
+            AgnssStep::Hold(gate) => gate,
+            AgnssStep::Send => AgnssGate::Allow,
+        };
+        if gate != self.agnss_gate {
+            match gate {
+                AgnssGate::Allow => defmt::info!("AGNSS upload allowed"),
+                AgnssGate::SkipGoodFix => defmt::info!("AGNSS upload held: good fix"),
+                AgnssGate::DeferLowBattery => defmt::info!("AGNSS upload deferred: low battery"),
+            }
+            self.agnss_gate = gate;
+        }
+        gate == AgnssGate::Allow
+    }
+
     async fn transition_back_from_agnss(&mut self, now_ms: u64, gps_en: &mut Output<'static>) {
         let previous_state = agnss_finish_processing().await;
         self.reset_state_timers();
@@ -187,14 +389,35 @@ impl GpsStateMachine {
         tx: &mut BufferedUarteTx<'static>,
         gps_en: &mut Output<'static>,
     ) {
+        self.profile = super::active_profile().1;
         let (state, location_valid, mut is_stationary, speed) = snapshot_system_info().await;
+        let adaptive = super::profile::adaptive();
+        self.profile.sampling_interval_ms =
+            adaptive.sampling_interval_ms(self.profile.sampling_interval_ms, speed);
         if take_gps_wakeup().await {
             is_stationary = false;
+            self.backoff_until = None;
+        }
+        let recording = recording::is_recording();
+        if !recording {
+            // Idle until an explicit start; keep-alive still powers the GPS.
+            is_stationary = true;
         }
         let keep_alive = super::is_keep_alive_active(now_ms).await;
+        log_time_event().await;
+        agnss_check_expiry(now_ms).await;
 
         if state != GpsState::S5AgnssProcessing {
             drain_non_agnss_events().await;
+            if self.is_gps_powered_on {
+                self.check_nmea_silence(now_ms, tx).await;
+                while let Some(data) = nmea_passthrough::take_for_gps() {
+                    write_all(tx, &data).await;
+                }
+            }
+        }
+        if !self.is_gps_powered_on {
+            nmea_passthrough::discard_for_gps();
         }
 
         match state {
@@ -217,23 +440,28 @@ impl GpsStateMachine {
                     self.reset_state_timers();
                     self.active_sampling_start = Some(now_ms);
                     self.consecutive_fix_failures = 0;
+                    self.escalation.reset();
                     self.is_first_fix_attempt_cycle = false;
-                    update_last_position(&mut self.last_successful_position).await;
+                    update_last_position(
+                        &mut self.last_successful_position,
+                        &mut self.altitude_fusion,
+                    )
+                    .await;
+                    self.end_fix_attempt(true, now_ms).await;
                     set_gps_state(GpsState::S3TrackingFixed).await;
                     defmt::info!("GPS State: S1 -> S3_TRACKING_FIXED (fix)");
                     return;
                 }
 
-                let fix_timeout = if self.is_first_fix_attempt_cycle {
-                    T_GPS_COLD_START_FIX_TIMEOUT_MS
+                let fix_timeout_ms = if self.is_first_fix_attempt_cycle {
+                    self.profile.cold_start_fix_timeout_ms
                 } else {
-                    T_GPS_REACQUIRE_FIX_TIMEOUT_MS
+                    self.profile.reacquire_fix_timeout_ms
                 };
-                if has_elapsed(self.fix_attempt_start, now_ms, fix_timeout) {
+                if has_elapsed(self.fix_attempt_start, now_ms, fix_timeout_ms as u64) {
                     self.consecutive_fix_failures = self.consecutive_fix_failures.saturating_add(1);
-                    if self.consecutive_fix_failures >= MAX_CONSECUTIVE_FIX_FAILURES {
-                        defmt::info!("GPS warm restart after fix failures");
-                        write_all(tx, b"$PCAS10,1*1D\r\n").await;
+                    if self.consecutive_fix_failures as u32 >= self.profile.max_fix_failures {
+                        self.escalate_fix_failures(now_ms, tx).await;
                         self.consecutive_fix_failures = 0;
                     }
                     if keep_alive {
@@ -261,13 +489,22 @@ impl GpsStateMachine {
                     self.power_off_gps(gps_en).await;
                 }
 
-                if !is_stationary || keep_alive {
+                let idle_since = *self.idle_since.get_or_insert(now_ms);
+                let wake_ms = adaptive.wake_interval_ms(discharging_battery_pct().await);
+                let periodic_wake = recording
+                    && wake_ms.is_some_and(|ms| has_elapsed(Some(idle_since), now_ms, ms));
+                let backing_off = self.backoff_until.is_some_and(|until| now_ms < until);
+                if keep_alive || (!backing_off && (!is_stationary || periodic_wake)) {
+                    self.backoff_until = None;
                     self.power_on_gps(gps_en).await;
                     self.reset_state_timers();
+                    self.idle_since = None;
                     self.fix_attempt_start = Some(now_ms);
                     set_gps_state(GpsState::S1GpsSearchingFix).await;
                     if keep_alive {
                         defmt::info!("GPS State: S2 -> S1_GPS_SEARCHING_FIX (keep-alive)");
+                    } else if is_stationary {
+                        defmt::info!("GPS State: S2 -> S1_GPS_SEARCHING_FIX (periodic wake)");
                     } else {
                         defmt::info!("GPS State: S2 -> S1_GPS_SEARCHING_FIX (motion)");
                     }
@@ -300,17 +537,55 @@ impl GpsStateMachine {
                 if has_elapsed(
                     self.active_sampling_start,
                     now_ms,
-                    T_ACTIVE_SAMPLING_INTERVAL_MS,
+                    self.profile.sampling_interval_ms as u64,
                 ) {
                     if location_valid {
-                        update_last_position(&mut self.last_successful_position).await;
-                        let _ = storage::append_gpx_point(
+                        geofences::check_fix().await;
+                    }
+                    if location_valid && recording {
+                        update_last_position(
+                            &mut self.last_successful_position,
+                            &mut self.altitude_fusion,
+                        )
+                        .await;
+                        location_history::note_fix(
+                            self.last_successful_position.timestamp,
+                            self.last_successful_position.latitude,
+                            self.last_successful_position.longitude,
+                        )
+                        .await;
+                        trend::note_point(
+                            self.last_successful_position.timestamp,
+                            self.last_successful_position.speed_kmh,
+                            self.last_successful_position.altitude_m,
+                        )
+                        .await;
+                        live_track::note_point(
                             self.last_successful_position.timestamp,
                             self.last_successful_position.latitude,
                             self.last_successful_position.longitude,
                             self.last_successful_position.altitude_m,
+                            self.last_successful_position.pressure_pa,
                         )
                         .await;
+                        flash_track::note_point(
+                            self.last_successful_position.timestamp,
+                            self.last_successful_position.latitude,
+                            self.last_successful_position.longitude,
+                            self.last_successful_position.altitude_m,
+                        );
+                        let activity = self.classify_activity().await;
+                        storage::queue_gpx_point(storage::QueuedPoint {
+                            timestamp: self.last_successful_position.timestamp,
+                            latitude: self.last_successful_position.latitude,
+                            longitude: self.last_successful_position.longitude,
+                            altitude_m: self.last_successful_position.altitude_m,
+                            pressure_pa: self.last_successful_position.pressure_pa,
+                            speed_kmh: self.last_successful_position.speed_kmh,
+                            activity,
+                            fix_mode: self.last_successful_position.fix_mode,
+                            fix_quality: self.last_successful_position.fix_quality,
+                        });
                     }
                     self.active_sampling_start = Some(now_ms);
                 }
@@ -328,7 +603,7 @@ impl GpsStateMachine {
                     && has_elapsed(
                         self.stillness_confirm_start,
                         now_ms,
-                        T_STILLNESS_CONFIRM_DURATION_MS,
+                        self.profile.stillness_confirm_ms as u64,
                     )
                 {
                     self.reset_state_timers();
@@ -372,7 +647,7 @@ impl GpsStateMachine {
                 let s4_timeout = has_elapsed(
                     self.gps_query_timeout_start,
                     now_ms,
-                    T_GPS_QUERY_TIMEOUT_FOR_STILLNESS_MS,
+                    self.profile.stillness_query_timeout_ms as u64,
                 );
                 if s4_timeout || location_valid {
                     if !s4_timeout && location_valid && speed > GPS_SPEED_VEHICLE_THRESHOLD_KMPH {
@@ -462,20 +737,44 @@ impl GpsStateMachine {
     }
 }
 
-async fn update_last_position(last: &mut PositionResult) {
+/// Battery percent for the low-battery wake, `None` while charging or before
+/// the first reading.
+async fn discharging_battery_pct() -> Option<u8> {
     let info = SYSTEM_INFO.lock().await;
-    last.timestamp = date_time_to_unix_timestamp(
-        info.year,
-        info.month,
-        info.day,
-        info.hour,
-        info.minute,
-        info.second,
+    let discharging = info.charge_state == ChargeState::Discharging;
+    (discharging && info.battery_voltage > 0.0).then_some(info.battery_percent)
+}
+
+async fn update_last_position(last: &mut PositionResult, fusion: &mut AltitudeFusion) {
+    {
+        let info = SYSTEM_INFO.lock().await;
+        last.timestamp = date_time_to_unix_timestamp(
+            info.year,
+            info.month,
+            info.day,
+            info.hour,
+            info.minute,
+            info.second,
+        );
+        last.latitude = info.latitude;
+        last.longitude = info.longitude;
+        last.altitude_m = info.altitude;
+        last.hdop = info.hdop;
+        last.speed_kmh = info.speed;
+        last.fix_mode = info.fix_mode;
+        last.fix_quality = info.fix_quality;
+    }
+    let baro = {
+        let bmp = bmp280::BMP280_DATA.lock().await;
+        (bmp.ok && bmp.pressure_pa > 0.0).then_some((bmp.pressure_pa, bmp.altitude_m))
+    };
+    last.pressure_pa = baro.map(|(pressure_pa, _)| pressure_pa);
+    last.altitude_m = fusion.update(
+        last.timestamp,
+        last.altitude_m,
+        last.hdop,
+        baro.map(|(_, altitude_m)| altitude_m),
     );
-    last.latitude = info.latitude;
-    last.longitude = info.longitude;
-    last.altitude_m = info.altitude;
-    last.hdop = info.hdop;
 }
 
 fn date_time_to_unix_timestamp(
@@ -0,0 +1,261 @@
+//! GPX 1.1 rendering of decoded `.gpz` points.
+//!
+//! With the `usb.gpx_export` setting on, entering USB mode writes a standard
+//! `YYYYMMDD.gpx` next to every `YYYYMMDD.gpz` log, so the track can be
+//! dropped straight into Strava or GpsPrune from the mass storage drive.
+//! This module only formats bytes; `storage` walks the card and streams
+//! each log through `GpzDecoder` into it.
+//!
+//! # Design
+//!
+//! - One `<trkpt>` per decoded point, rendered into a fixed buffer of
+//!   `MAX_POINT_LEN`, so a file of any length converts in constant memory.
+//! - Coordinates keep the full 1e7 decoder precision and altitude its
+//!   decimetres; both are printed from integers, without float formatting.
+//! - Times are UTC with a `Z` suffix, as GPX requires.
+//! - `<fix>` comes from the log's fix blocks: `dgps` for a DGPS fix,
+//!   otherwise `2d` or `3d`; left out before the first fix block.
+//! - Each run of points with one activity label is its own `<trk>`, with a
+//!   `<type>` once the activity is known, so a day's log opens as separate
+//!   walks, rides and drives.
+//! - A comment after the header records the length of the log the file was
+//!   rendered from. A log that grew since, or was modified after its GPX,
+//!   is exported again.
+
+use core::fmt::Write;
+
+use crate::activity::Activity;
+use crate::gpz::TrackPoint;
+
+pub const HEADER: &[u8] = b"<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+<gpx version=\"1.1\" creator=\"gps_tracker\" xmlns=\"http://www.topografix.com/GPX/1/1\">\n";
+pub const TRACK_END: &[u8] = b"</trkseg></trk>\n";
+pub const FOOTER: &[u8] = b"</gpx>\n";
+/// Upper bound of one rendered point.
+pub const MAX_POINT_LEN: usize = 128;
+/// Length of `source_mark`, which follows `HEADER`.
+pub const SOURCE_MARK_LEN: usize = 24;
+const SOURCE_MARK_PREFIX: &[u8] = b"<!-- gpz ";
+const SOURCE_MARK_SUFFIX: &[u8] = b" -->\n";
+
+struct Cursor<'a> {
+    out: &'a mut [u8],
+    len: usize,
+}
+
+impl Write for Cursor<'_> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        let end = self.len + s.len();
+        if end > self.out.len() {
+            return Err(core::fmt::Error);
+        }
+        self.out[self.len..end].copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+/// `<!-- gpz NNNNNNNNNN -->`, recording the `.gpz` length `len`.
+pub fn source_mark(len: u32) -> [u8; SOURCE_MARK_LEN] {
+    let mut out = [0u8; SOURCE_MARK_LEN];
+    let digits_end = SOURCE_MARK_LEN - SOURCE_MARK_SUFFIX.len();
+    out[..SOURCE_MARK_PREFIX.len()].copy_from_slice(SOURCE_MARK_PREFIX);
+    let mut value = len;
+    for digit in out[SOURCE_MARK_PREFIX.len()..digits_end].iter_mut().rev() {
+        *digit = b'0' + (value % 10) as u8;
+        value /= 10;
+    }
+    out[digits_end..].copy_from_slice(SOURCE_MARK_SUFFIX);
+    out
+}
+
+/// The length recorded by `source_mark`; `None` for anything else, such as
+/// a file exported before the mark existed.
+pub fn parse_source_mark(bytes: &[u8]) -> Option<u32> {
+    let digits = bytes
+        .get(..SOURCE_MARK_LEN)?
+        .strip_prefix(SOURCE_MARK_PREFIX)?
+        .strip_suffix(SOURCE_MARK_SUFFIX)?;
+    digits.iter().try_fold(0u32, |value, &digit| {
+        let digit = (digit as char).to_digit(10)?;
+        value.checked_mul(10)?.checked_add(digit)
+    })
+}
+
+/// Opening of the track for points labelled `activity`.
+pub fn track_start(activity: u8) -> &'static [u8] {
+    match Activity::from_u8(activity) {
+        Activity::Unknown => b"<trk><trkseg>\n",
+        Activity::Walk => b"<trk><type>walking</type><trkseg>\n",
+        Activity::Cycle => b"<trk><type>cycling</type><trkseg>\n",
+        Activity::Drive => b"<trk><type>driving</type><trkseg>\n",
+    }
+}
+
+/// Render one `<trkpt>` line; returns its length.
+pub fn format_point(point: &TrackPoint, out: &mut [u8; MAX_POINT_LEN]) -> usize {
+    let mut cursor = Cursor { out, len: 0 };
+    let (year, month, day, hour, minute, second) = civil_from_unix(point.timestamp);
+    let _ = write!(cursor, "<trkpt lat=\"");
+    let _ = write_fixed(&mut cursor, point.latitude_e7 as i64, 7);
+    let _ = write!(cursor, "\" lon=\"");
+    let _ = write_fixed(&mut cursor, point.longitude_e7 as i64, 7);
+    let _ = write!(cursor, "\"><ele>");
+    let _ = write_fixed(&mut cursor, point.altitude_dm as i64, 1);
+    let _ = write!(
+        cursor,
+        "</ele><time>{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z</time>",
+        year, month, day, hour, minute, second
+    );
+    if let Some(fix) = gpx_fix(point.fix_mode, point.fix_quality) {
+        let _ = write!(cursor, "<fix>{}</fix>", fix);
+    }
+    let _ = writeln!(cursor, "</trkpt>");
+    cursor.len
+}
+
+/// GPX `<fix>` value of a GSA mode and GGA quality, `None` when unknown.
+fn gpx_fix(mode: u8, quality: u8) -> Option<&'static str> {
+    match (mode, quality) {
+        (2 | 3, 2) => Some("dgps"),
+        (2, _) => Some("2d"),
+        (3, _) => Some("3d"),
+        _ => None,
+    }
+}
+
+/// `value / 10^decimals` in plain decimal notation.
+fn write_fixed(w: &mut impl Write, value: i64, decimals: u32) -> core::fmt::Result {
+    let scale = 10u64.pow(decimals);
+    let sign = if value < 0 { "-" } else { "" };
+    let abs = value.unsigned_abs();
+    write!(
+        w,
+        "{}{}.{:0width$}",
+        sign,
+        abs / scale,
+        abs % scale,
+        width = decimals as usize
+    )
+}
+
+/// UTC calendar fields of a Unix timestamp.
+pub fn civil_from_unix(timestamp: u32) -> (u32, u32, u32, u32, u32, u32) {
+    let days = (timestamp / 86_400) as i64;
+    let secs = timestamp % 86_400;
+    // Days-to-civil over 400-year eras, counted from 0000-03-01.
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (
+        year as u32,
+        month as u32,
+        day as u32,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(point: TrackPoint) -> String {
+        let mut buf = [0u8; MAX_POINT_LEN];
+        let len = format_point(&point, &mut buf);
+        String::from_utf8(buf[..len].to_vec()).unwrap()
+    }
+
+    #[test]
+    fn renders_a_point() {
+        let line = render(TrackPoint {
+            timestamp: 1_709_210_096,
+            latitude_e7: 312_345_678,
+            longitude_e7: 1_214_567_890,
+            altitude_dm: 123,
+            pressure_pa: 0,
+            activity: 0,
+            fix_mode: 0,
+            fix_quality: 0,
+        });
+        assert_eq!(
+            line,
+            "<trkpt lat=\"31.2345678\" lon=\"121.4567890\"><ele>12.3</ele>\
+             <time>2024-02-29T12:34:56Z</time></trkpt>\n"
+        );
+    }
+
+    #[test]
+    fn keeps_the_sign_of_small_negative_values() {
+        let line = render(TrackPoint {
+            timestamp: 0,
+            latitude_e7: -5,
+            longitude_e7: -1_800_000_000,
+            altitude_dm: -7,
+            pressure_pa: 0,
+            activity: 0,
+            fix_mode: 0,
+            fix_quality: 0,
+        });
+        assert_eq!(
+            line,
+            "<trkpt lat=\"-0.0000005\" lon=\"-180.0000000\"><ele>-0.7</ele>\
+             <time>1970-01-01T00:00:00Z</time></trkpt>\n"
+        );
+    }
+
+    #[test]
+    fn worst_case_point_fits() {
+        let line = render(TrackPoint {
+            timestamp: u32::MAX,
+            latitude_e7: i32::MIN,
+            longitude_e7: i32::MIN,
+            altitude_dm: i32::MIN,
+            pressure_pa: 0,
+            activity: 0,
+            fix_mode: 3,
+            fix_quality: 2,
+        });
+        assert!(line.ends_with("<time>2106-02-07T06:28:15Z</time><fix>dgps</fix></trkpt>\n"));
+    }
+
+    #[test]
+    fn fix_follows_mode_and_quality() {
+        let point = |fix_mode, fix_quality| TrackPoint {
+            fix_mode,
+            fix_quality,
+            ..TrackPoint::default()
+        };
+        assert!(render(point(3, 1)).ends_with("Z</time><fix>3d</fix></trkpt>\n"));
+        assert!(render(point(2, 1)).ends_with("Z</time><fix>2d</fix></trkpt>\n"));
+        assert!(render(point(2, 2)).ends_with("Z</time><fix>dgps</fix></trkpt>\n"));
+        // No fix block yet, or no fix.
+        assert!(render(point(0, 0)).ends_with("Z</time></trkpt>\n"));
+        assert!(render(point(1, 0)).ends_with("Z</time></trkpt>\n"));
+    }
+
+    #[test]
+    fn source_mark_round_trips() {
+        for len in [0, 1234, u32::MAX] {
+            let mark = source_mark(len);
+            assert_eq!(parse_source_mark(&mark), Some(len));
+        }
+        assert_eq!(&source_mark(42), b"<!-- gpz 0000000042 -->\n");
+        assert_eq!(parse_source_mark(b"<trk><trkseg>\n<trkpt lat=\"1\""), None);
+        assert_eq!(parse_source_mark(&source_mark(7)[..SOURCE_MARK_LEN - 1]), None);
+    }
+
+    #[test]
+    fn tracks_are_typed_by_activity() {
+        assert_eq!(track_start(0), b"<trk><trkseg>\n");
+        assert_eq!(track_start(2), b"<trk><type>cycling</type><trkseg>\n");
+        assert_eq!(track_start(200), track_start(0));
+    }
+}
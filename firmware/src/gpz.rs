@@ -0,0 +1,643 @@
+//! Streaming decoder for the `.gpz` track format.
+//!
+//! See `docs/delta_compress_gpx.md`. Bytes are pushed one at a time, so a
+//! file can be decoded straight from fixed-size SD reads with only the
+//! current block held in memory.
+//!
+//! `GpzDecoder::points` pulls the points out of one such read. A block cut
+//! off at the end of a read stays in the decoder and completes from the
+//! next one, so a file of any size decodes in chunks of any size, SD
+//! sectors on the device or large reads on a host.
+//!
+//! Points come out with 1e7 coordinates whatever the block version; only
+//! V3 blocks carry a pressure, otherwise it reads as 0. Each point also
+//! carries the label of the last activity block before it, and the fix
+//! mode and quality of the last fix block. An
+//! unknown header byte, or a delta block without a preceding full block of
+//! its version, counts as an error and is skipped; decoding resumes at the
+//! next full block.
+//!
+//! `ValidPrefix` reuses the decoder to find where a log stops decoding
+//! cleanly, so the garbage an unclean shutdown leaves at the end of a file
+//! can be cut off before more points are appended behind it, and where the
+//! log left off, so points logged again after a reboot can be dropped.
+
+const HEADER_FULL_V1: u8 = 0xFF;
+const HEADER_FULL_V2: u8 = 0xFE;
+const HEADER_FULL_V3: u8 = 0xFD;
+pub const HEADER_ACTIVITY: u8 = 0xFC;
+pub const HEADER_FIX: u8 = 0xFB;
+const DELTA_V2_FLAG: u8 = 0x10;
+const DELTA_MASK: u8 = 0x0F;
+const DELTA_V3_MASK: u8 = 0x1F;
+/// Fields of a V3 point; V1 and V2 have the first four.
+const MAX_FIELDS: usize = 5;
+const MAX_PAYLOAD_LEN: usize = MAX_FIELDS * 4;
+const V1_TO_E7: i32 = 100;
+/// A daily log never spans more than this from its first point, whatever
+/// the rotation time zone.
+const MAX_FILE_SPAN_S: u32 = 2 * 86_400;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TrackPoint {
+    pub timestamp: u32,
+    pub latitude_e7: i32,
+    pub longitude_e7: i32,
+    /// Altitude in decimetres.
+    pub altitude_dm: i32,
+    /// Barometric pressure in pascals, 0 when not recorded.
+    pub pressure_pa: u32,
+    /// `activity::Activity` in effect, 0 (unknown) before any activity block.
+    pub activity: u8,
+    /// GSA fix mode in effect (2 = 2D, 3 = 3D), 0 before any fix block.
+    pub fix_mode: u8,
+    /// GGA fix quality in effect (1 = GPS, 2 = DGPS), 0 before any fix block.
+    pub fix_quality: u8,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Version {
+    V1,
+    V2,
+    V3,
+}
+
+impl Version {
+    fn fields(self) -> usize {
+        if self == Version::V3 {
+            MAX_FIELDS
+        } else {
+            4
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum State {
+    Header,
+    Full(Version),
+    /// Remaining fields as a `H_TS H_LAT H_LON H_ALT [H_PRES]` mask.
+    Delta(u8),
+    /// The label byte of an activity block.
+    Activity,
+    /// The mode byte of a fix block, then its quality byte.
+    FixMode,
+    FixQuality,
+}
+
+pub struct GpzDecoder {
+    state: State,
+    /// Version of the last full block; deltas must match it.
+    version: Option<Version>,
+    /// Previous point in the units of `version`.
+    prev: [i32; MAX_FIELDS],
+    fields: [i32; MAX_FIELDS],
+    block: [u8; MAX_PAYLOAD_LEN],
+    block_len: usize,
+    varint: u32,
+    shift: u32,
+    errors: u32,
+    activity: u8,
+    fix_mode: u8,
+    fix_quality: u8,
+}
+
+impl GpzDecoder {
+    pub const fn new() -> Self {
+        Self {
+            state: State::Header,
+            version: None,
+            prev: [0; MAX_FIELDS],
+            fields: [0; MAX_FIELDS],
+            block: [0; MAX_PAYLOAD_LEN],
+            block_len: 0,
+            varint: 0,
+            shift: 0,
+            errors: 0,
+            activity: 0,
+            fix_mode: 0,
+            fix_quality: 0,
+        }
+    }
+
+    /// Invalid blocks skipped so far.
+    pub fn errors(&self) -> u32 {
+        self.errors
+    }
+
+    /// Points completed by `chunk`, the next bytes of the file.
+    pub fn points<'a>(&'a mut self, chunk: &'a [u8]) -> Points<'a> {
+        Points {
+            decoder: self,
+            chunk,
+            used: 0,
+        }
+    }
+
+    /// Feed one byte; returns a point when it completes a block.
+    pub fn push(&mut self, byte: u8) -> Option<TrackPoint> {
+        match self.state {
+            State::Header => self.start_block(byte),
+            State::Full(version) => {
+                self.block[self.block_len] = byte;
+                self.block_len += 1;
+                if self.block_len < version.fields() * 4 {
+                    return None;
+                }
+                self.prev = [0; MAX_FIELDS];
+                for (i, field) in self.prev.iter_mut().take(version.fields()).enumerate() {
+                    let raw = &self.block[i * 4..i * 4 + 4];
+                    *field = i32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]);
+                }
+                self.version = Some(version);
+                self.state = State::Header;
+                Some(self.current_point())
+            }
+            State::Delta(pending) => self.push_delta_byte(pending, byte),
+            State::Activity => {
+                self.activity = byte;
+                self.state = State::Header;
+                None
+            }
+            State::FixMode => {
+                self.fix_mode = byte;
+                self.state = State::FixQuality;
+                None
+            }
+            State::FixQuality => {
+                self.fix_quality = byte;
+                self.state = State::Header;
+                None
+            }
+        }
+    }
+
+    fn start_block(&mut self, header: u8) -> Option<TrackPoint> {
+        match header {
+            HEADER_FULL_V1 | HEADER_FULL_V2 | HEADER_FULL_V3 => {
+                let version = match header {
+                    HEADER_FULL_V1 => Version::V1,
+                    HEADER_FULL_V2 => Version::V2,
+                    _ => Version::V3,
+                };
+                self.block_len = 0;
+                self.state = State::Full(version);
+                None
+            }
+            HEADER_ACTIVITY => {
+                self.state = State::Activity;
+                None
+            }
+            HEADER_FIX => {
+                self.state = State::FixMode;
+                None
+            }
+            0x00..=0x3F => {
+                let (version, mask) = if header >= 0x20 {
+                    (Version::V3, header & DELTA_V3_MASK)
+                } else if header & DELTA_V2_FLAG != 0 {
+                    (Version::V2, header & DELTA_MASK)
+                } else {
+                    (Version::V1, header & DELTA_MASK)
+                };
+                if self.version != Some(version) {
+                    self.errors = self.errors.saturating_add(1);
+                    return None;
+                }
+                self.fields = [0; MAX_FIELDS];
+                if mask == 0 {
+                    return Some(self.current_point());
+                }
+                self.varint = 0;
+                self.shift = 0;
+                self.state = State::Delta(mask);
+                None
+            }
+            _ => {
+                self.errors = self.errors.saturating_add(1);
+                None
+            }
+        }
+    }
+
+    fn push_delta_byte(&mut self, pending: u8, byte: u8) -> Option<TrackPoint> {
+        if self.shift > 28 {
+            // Longer than any varint_s32: corrupt, resynchronise.
+            self.errors = self.errors.saturating_add(1);
+            self.state = State::Header;
+            return None;
+        }
+        self.varint |= ((byte & 0x7F) as u32) << self.shift;
+        self.shift += 7;
+        if byte & 0x80 != 0 {
+            return None;
+        }
+
+        // Fields appear in TS, LAT, LON, ALT, PRES order, highest mask bit
+        // first.
+        let fields = self.version.map_or(4, Version::fields);
+        let bit = 7 - pending.leading_zeros() as usize;
+        let value = ((self.varint >> 1) as i32) ^ -((self.varint & 1) as i32);
+        self.fields[fields - 1 - bit] = value;
+        self.varint = 0;
+        self.shift = 0;
+
+        let pending = pending & !(1 << bit);
+        if pending != 0 {
+            self.state = State::Delta(pending);
+            return None;
+        }
+        for (prev, delta) in self.prev.iter_mut().zip(self.fields) {
+            *prev = prev.wrapping_add(delta);
+        }
+        self.state = State::Header;
+        Some(self.current_point())
+    }
+
+    fn current_point(&self) -> TrackPoint {
+        let scale = if self.version == Some(Version::V1) {
+            V1_TO_E7
+        } else {
+            1
+        };
+        TrackPoint {
+            timestamp: self.prev[0] as u32,
+            latitude_e7: self.prev[1].saturating_mul(scale),
+            longitude_e7: self.prev[2].saturating_mul(scale),
+            altitude_dm: self.prev[3],
+            pressure_pa: self.prev[4] as u32,
+            activity: self.activity,
+            fix_mode: self.fix_mode,
+            fix_quality: self.fix_quality,
+        }
+    }
+}
+
+/// Iterator over the points of one chunk; see `GpzDecoder::points`.
+pub struct Points<'a> {
+    decoder: &'a mut GpzDecoder,
+    chunk: &'a [u8],
+    used: usize,
+}
+
+impl Points<'_> {
+    /// Bytes of the chunk decoded so far. Dropping the iterator early and
+    /// resuming at this offset loses nothing.
+    pub fn used(&self) -> usize {
+        self.used
+    }
+}
+
+impl Iterator for Points<'_> {
+    type Item = TrackPoint;
+
+    fn next(&mut self) -> Option<TrackPoint> {
+        while let Some(&byte) = self.chunk.get(self.used) {
+            self.used += 1;
+            if let Some(point) = self.decoder.push(byte) {
+                return Some(point);
+            }
+        }
+        None
+    }
+}
+
+/// Length of the cleanly decoding start of a log.
+///
+/// Scanning stops at the first invalid block or implausible timestamp.
+/// The valid length only advances past blocks that change the point, so a
+/// zero-filled tail (which decodes as repeats of the last point) is cut off
+/// too; the writer never repeats a point exactly.
+pub struct ValidPrefix {
+    decoder: GpzDecoder,
+    offset: u32,
+    valid_len: u32,
+    first_timestamp: u32,
+    last: Option<TrackPoint>,
+    stopped: bool,
+}
+
+impl ValidPrefix {
+    pub const fn new() -> Self {
+        Self {
+            decoder: GpzDecoder::new(),
+            offset: 0,
+            valid_len: 0,
+            first_timestamp: 0,
+            last: None,
+            stopped: false,
+        }
+    }
+
+    /// Feed the next bytes of the file. Returns `false` once the rest of the
+    /// file no longer matters.
+    pub fn push(&mut self, bytes: &[u8]) -> bool {
+        for &byte in bytes {
+            if self.stopped {
+                break;
+            }
+            self.offset += 1;
+            let point = self.decoder.push(byte);
+            if self.decoder.errors() > 0 {
+                self.stopped = true;
+                break;
+            }
+            let Some(point) = point else {
+                continue;
+            };
+            if !self.plausible(&point) {
+                self.stopped = true;
+                break;
+            }
+            if self.last != Some(point) {
+                self.valid_len = self.offset;
+            }
+            self.last = Some(point);
+        }
+        !self.stopped
+    }
+
+    /// Bytes up to the end of the last valid block seen so far.
+    pub fn valid_len(&self) -> u32 {
+        self.valid_len
+    }
+
+    /// Timestamp of the last valid point seen so far.
+    pub fn last_timestamp(&self) -> Option<u32> {
+        self.last.map(|point| point.timestamp)
+    }
+
+    fn plausible(&mut self, point: &TrackPoint) -> bool {
+        if point.timestamp == 0 {
+            return false;
+        }
+        if self.last.is_none() {
+            self.first_timestamp = point.timestamp;
+            return true;
+        }
+        point.timestamp.abs_diff(self.first_timestamp) <= MAX_FILE_SPAN_S
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(bytes: &[u8]) -> (Vec<TrackPoint>, u32) {
+        let mut decoder = GpzDecoder::new();
+        let points = bytes.iter().filter_map(|&b| decoder.push(b)).collect();
+        (points, decoder.errors())
+    }
+
+    fn full(header: u8, ts: u32, lat: i32, lon: i32, alt: i32) -> Vec<u8> {
+        let mut out = vec![header];
+        out.extend_from_slice(&ts.to_le_bytes());
+        out.extend_from_slice(&lat.to_le_bytes());
+        out.extend_from_slice(&lon.to_le_bytes());
+        out.extend_from_slice(&alt.to_le_bytes());
+        out
+    }
+
+    fn varint_s32(value: i32, out: &mut Vec<u8>) {
+        let mut zz = ((value as u32) << 1) ^ ((value >> 31) as u32);
+        while zz >= 0x80 {
+            out.push((zz as u8) | 0x80);
+            zz >>= 7;
+        }
+        out.push(zz as u8);
+    }
+
+    #[test]
+    fn v1_full_and_delta() {
+        let mut bytes = full(0xFF, 1_700_000_000, 3_112_345, 12_145_678, 123);
+        // ts +1, lon -70 (two-byte varint), alt unchanged.
+        bytes.push(0x0A);
+        varint_s32(1, &mut bytes);
+        varint_s32(-70, &mut bytes);
+        // All fields unchanged.
+        bytes.push(0x00);
+
+        let (points, errors) = decode(&bytes);
+        assert_eq!(errors, 0);
+        assert_eq!(points.len(), 3);
+        assert_eq!(
+            points[0],
+            TrackPoint {
+                timestamp: 1_700_000_000,
+                latitude_e7: 311_234_500,
+                longitude_e7: 1_214_567_800,
+                altitude_dm: 123,
+                pressure_pa: 0,
+                activity: 0,
+                fix_mode: 0,
+                fix_quality: 0,
+            }
+        );
+        assert_eq!(points[1].timestamp, 1_700_000_001);
+        assert_eq!(points[1].latitude_e7, 311_234_500);
+        assert_eq!(points[1].longitude_e7, 1_214_560_800);
+        assert_eq!(points[2], points[1]);
+    }
+
+    #[test]
+    fn v2_keeps_full_precision() {
+        let mut bytes = full(0xFE, 10, 311_234_567, -1_181_234_567, -50);
+        bytes.push(0x1F);
+        for delta in [5, -1_000_000, 300_000, 20] {
+            varint_s32(delta, &mut bytes);
+        }
+        let (points, errors) = decode(&bytes);
+        assert_eq!(errors, 0);
+        assert_eq!(points[1].timestamp, 15);
+        assert_eq!(points[1].latitude_e7, 310_234_567);
+        assert_eq!(points[1].longitude_e7, -1_180_934_567);
+        assert_eq!(points[1].altitude_dm, -30);
+    }
+
+    #[test]
+    fn v3_carries_pressure() {
+        let mut bytes = full(0xFD, 10, 311_234_567, 1_214_567_890, 523);
+        bytes.extend_from_slice(&101_325i32.to_le_bytes());
+        // ts +1, alt -2, pressure +24.
+        bytes.push(0x33);
+        for delta in [1, -2, 24] {
+            varint_s32(delta, &mut bytes);
+        }
+        // A V2 delta cannot follow a V3 block.
+        bytes.push(0x10);
+        let (points, errors) = decode(&bytes);
+        assert_eq!(errors, 1);
+        assert_eq!(points.len(), 2);
+        assert_eq!(points[0].pressure_pa, 101_325);
+        assert_eq!(points[1].timestamp, 11);
+        assert_eq!(points[1].latitude_e7, 311_234_567);
+        assert_eq!(points[1].altitude_dm, 521);
+        assert_eq!(points[1].pressure_pa, 101_349);
+    }
+
+    #[test]
+    fn delta_without_full_block_is_skipped() {
+        let mut bytes = vec![0x00, 0x1A];
+        bytes.extend(full(0xFF, 1, 2, 3, 4));
+        let (points, errors) = decode(&bytes);
+        assert_eq!(errors, 2);
+        assert_eq!(points.len(), 1);
+        assert_eq!(points[0].timestamp, 1);
+    }
+
+    #[test]
+    fn version_mismatch_is_an_error() {
+        let mut bytes = full(0xFF, 1, 2, 3, 4);
+        bytes.push(0x10);
+        let (points, errors) = decode(&bytes);
+        assert_eq!(points.len(), 1);
+        assert_eq!(errors, 1);
+    }
+
+    #[test]
+    fn garbage_header_resyncs_on_next_full_block() {
+        let mut bytes = full(0xFF, 1, 2, 3, 4);
+        bytes.extend_from_slice(&[0x80, 0x42]);
+        bytes.extend(full(0xFF, 9, 2, 3, 4));
+        let (points, errors) = decode(&bytes);
+        assert_eq!(errors, 2);
+        assert_eq!(points.len(), 2);
+        assert_eq!(points[1].timestamp, 9);
+    }
+
+    #[test]
+    fn chunked_decode_matches_whole_file() {
+        let mut bytes = full(0xFD, 10, 311_234_567, 1_214_567_890, 523);
+        bytes.extend_from_slice(&101_325i32.to_le_bytes());
+        for step in 0..40 {
+            bytes.push(0x3F);
+            for delta in [1, -300_000, 70 * step, -2, 24] {
+                varint_s32(delta, &mut bytes);
+            }
+        }
+        let (whole, _) = decode(&bytes);
+        for chunk_len in [1, 2, 3, 7, 64] {
+            let mut decoder = GpzDecoder::new();
+            let points: Vec<_> = bytes
+                .chunks(chunk_len)
+                .flat_map(|chunk| decoder.points(chunk).collect::<Vec<_>>())
+                .collect();
+            assert_eq!(points, whole);
+        }
+
+        // Stopping early and resuming at `used` loses no point.
+        let mut decoder = GpzDecoder::new();
+        let mut points = decoder.points(&bytes);
+        let first = points.nth(2).unwrap();
+        let used = points.used();
+        let rest: Vec<_> = decoder.points(&bytes[used..]).collect();
+        assert_eq!(first, whole[2]);
+        assert_eq!(rest, whole[3..]);
+    }
+
+    #[test]
+    fn activity_blocks_label_the_points_after_them() {
+        // A label may come before the first full block.
+        let mut bytes = vec![HEADER_ACTIVITY, 1];
+        bytes.extend(full(0xFF, 1, 2, 3, 4));
+        bytes.push(0x08);
+        varint_s32(1, &mut bytes);
+        // The label byte is not a header, even when it looks like one.
+        bytes.extend_from_slice(&[HEADER_ACTIVITY, 0x03, 0x08]);
+        varint_s32(1, &mut bytes);
+        let (points, errors) = decode(&bytes);
+        assert_eq!(errors, 0);
+        let labels: Vec<_> = points.iter().map(|p| (p.timestamp, p.activity)).collect();
+        assert_eq!(labels, [(1, 1), (2, 1), (3, 3)]);
+    }
+
+    #[test]
+    fn fix_blocks_label_the_points_after_them() {
+        let mut bytes = full(0xFF, 1, 2, 3, 4);
+        // 3D DGPS, between a full block and its delta; both bytes look
+        // like headers.
+        bytes.extend_from_slice(&[HEADER_FIX, 3, 2, 0x08]);
+        varint_s32(1, &mut bytes);
+        bytes.extend_from_slice(&[HEADER_ACTIVITY, 2, HEADER_FIX, 0xFF, 0x1F, 0x08]);
+        varint_s32(1, &mut bytes);
+        let (points, errors) = decode(&bytes);
+        assert_eq!(errors, 0);
+        let fixes: Vec<_> = points
+            .iter()
+            .map(|p| (p.timestamp, p.fix_mode, p.fix_quality, p.activity))
+            .collect();
+        assert_eq!(fixes, [(1, 0, 0, 0), (2, 3, 2, 0), (3, 0xFF, 0x1F, 2)]);
+    }
+
+    fn valid_len(bytes: &[u8]) -> u32 {
+        let mut prefix = ValidPrefix::new();
+        prefix.push(bytes);
+        prefix.valid_len()
+    }
+
+    fn sample_track() -> Vec<u8> {
+        let mut bytes = full(0xFF, 1_700_000_000, 3_112_345, 12_145_678, 123);
+        for _ in 0..3 {
+            bytes.push(0x0C);
+            varint_s32(1, &mut bytes);
+            varint_s32(-3, &mut bytes);
+        }
+        bytes
+    }
+
+    #[test]
+    fn clean_file_is_fully_valid() {
+        let bytes = sample_track();
+        assert_eq!(valid_len(&bytes), bytes.len() as u32);
+    }
+
+    #[test]
+    fn partial_last_block_is_cut() {
+        let bytes = sample_track();
+        let mut torn = bytes.clone();
+        torn.extend_from_slice(&full(0xFF, 1_700_000_010, 1, 2, 3)[..9]);
+        assert_eq!(valid_len(&torn), bytes.len() as u32);
+    }
+
+    #[test]
+    fn zero_fill_is_cut() {
+        let bytes = sample_track();
+        let mut padded = bytes.clone();
+        padded.extend_from_slice(&[0u8; 512]);
+        assert_eq!(valid_len(&padded), bytes.len() as u32);
+    }
+
+    #[test]
+    fn erased_fill_is_cut() {
+        let bytes = sample_track();
+        let mut padded = bytes.clone();
+        padded.extend_from_slice(&[0xFFu8; 64]);
+        assert_eq!(valid_len(&padded), bytes.len() as u32);
+    }
+
+    #[test]
+    fn implausible_timestamp_stops_scan() {
+        let bytes = sample_track();
+        let mut stale = bytes.clone();
+        stale.extend(full(0xFF, 1_600_000_000, 1, 2, 3));
+        stale.extend(full(0xFF, 1_700_000_100, 1, 2, 3));
+        assert_eq!(valid_len(&stale), bytes.len() as u32);
+    }
+
+    #[test]
+    fn last_timestamp_ignores_the_cut_tail() {
+        let mut torn = sample_track();
+        torn.extend(full(0xFF, 1_600_000_000, 1, 2, 3));
+        let mut prefix = ValidPrefix::new();
+        prefix.push(&torn);
+        assert_eq!(prefix.last_timestamp(), Some(1_700_000_003));
+        assert_eq!(ValidPrefix::new().last_timestamp(), None);
+    }
+
+    #[test]
+    fn appending_after_valid_data_continues() {
+        let mut bytes = sample_track();
+        bytes.extend(full(0xFF, 1_700_003_600, 3_112_000, 12_145_000, 100));
+        assert_eq!(valid_len(&bytes), bytes.len() as u32);
+    }
+}
@@ -0,0 +1,77 @@
+//! Connection lockdown with a time-limited guest window.
+//!
+//! By default any central may connect, as before. With lockdown enabled the
+//! tracker stops advertising and refuses new connections, except during a
+//! guest window opened by a long button press (or by an already connected
+//! host), so a shared or borrowed device can be handed over without staying
+//! open afterwards.
+//!
+//! # Design
+//!
+//! - `/LOCK.CFG` holds one flag byte; a missing file keeps lockdown off.
+//! - The window only gates new connections: a central that connected inside
+//!   it keeps its link until it disconnects.
+//! - Bonded peers (`ble`) may still connect outside the window; without any
+//!   bond, "locked" means no connections at all.
+
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+use embassy_time::Instant;
+
+use crate::storage;
+
+pub const LOCKDOWN_CONFIG_LEN: usize = 1;
+pub const GUEST_WINDOW_S: u32 = 120;
+
+const FLAG_LOCKDOWN: u8 = 0x01;
+
+static LOCKDOWN: AtomicBool = AtomicBool::new(false);
+/// Uptime second at which the guest window closes.
+static WINDOW_END_S: AtomicU32 = AtomicU32::new(0);
+
+fn uptime_s() -> u32 {
+    Instant::now().as_secs() as u32
+}
+
+/// Load the lockdown flag from SD. Call once after the SD logger is
+/// initialized, before BLE starts advertising.
+pub async fn load() {
+    if let Some(flags) = storage::read_lockdown_config().await {
+        LOCKDOWN.store((flags & FLAG_LOCKDOWN) != 0, Ordering::Release);
+    }
+    if lockdown() {
+        defmt::info!("Guest: lockdown enabled, long press to open a guest window");
+    }
+}
+
+pub fn lockdown() -> bool {
+    LOCKDOWN.load(Ordering::Acquire)
+}
+
+/// Persist the lockdown flag and apply it immediately.
+pub async fn set_lockdown(enabled: bool) -> bool {
+    let flags = if enabled { FLAG_LOCKDOWN } else { 0 };
+    if !storage::write_lockdown_config(&[flags]).await {
+        return false;
+    }
+    LOCKDOWN.store(enabled, Ordering::Release);
+    if !enabled {
+        WINDOW_END_S.store(0, Ordering::Release);
+    }
+    true
+}
+
+/// Accept unbonded connections for the next `GUEST_WINDOW_S` seconds.
+pub fn open_window() {
+    WINDOW_END_S.store(uptime_s() + GUEST_WINDOW_S, Ordering::Release);
+    defmt::info!("Guest: window open for {}s", GUEST_WINDOW_S);
+}
+
+pub fn window_remaining_s() -> u32 {
+    WINDOW_END_S.load(Ordering::Acquire).saturating_sub(uptime_s())
+}
+
+/// Whether a new central may connect right now.
+pub fn connection_allowed() -> bool {
+    !lockdown() || window_remaining_s() > 0
+}
@@ -0,0 +1,96 @@
+//! Smoothed direction of travel for the compass page.
+//!
+//! The RMC course is the direction of the last second's movement: it swings
+//! wildly at walking speed and is noise once the tracker stops. The filter
+//! turns it into a heading the compass page can point with.
+//!
+//! # Design
+//!
+//! - The board has no magnetometer, so the accelerometer cannot give a
+//!   heading of its own. It decides when the course is trusted: while the
+//!   stationary detector (accelerometer plus GPS speed) says the tracker is
+//!   still, or below `MIN_SPEED_KMH`, the last heading is held.
+//! - Smoothing is an exponential average on the circle: the step is the
+//!   shortest turn from the current heading, so 350° and 10° average to 0°,
+//!   not 180°. Faster travel gives a steadier course and a larger weight.
+//! - Fed once per RMC sentence. Reset with the NMEA parser, when the GPS
+//!   restarts.
+
+const MIN_SPEED_KMH: f32 = 3.0;
+/// Speed at which a new course gets `ALPHA_MAX`.
+const FULL_SPEED_KMH: f32 = 20.0;
+const ALPHA_MIN: f32 = 0.2;
+const ALPHA_MAX: f32 = 0.8;
+
+pub struct HeadingFilter {
+    heading: Option<f32>,
+}
+
+impl HeadingFilter {
+    pub const fn new() -> Self {
+        Self { heading: None }
+    }
+
+    pub fn reset(&mut self) {
+        self.heading = None;
+    }
+
+    /// Add one course over ground (degrees, `None` without a fix) and return
+    /// the smoothed heading in `[0, 360)`, `None` until the first trusted
+    /// course.
+    pub fn update(&mut self, course: Option<f32>, speed_kmh: f32, stationary: bool) -> Option<f32> {
+        let Some(course) = course.filter(|c| c.is_finite()) else {
+            return self.heading;
+        };
+        if stationary || speed_kmh < MIN_SPEED_KMH {
+            return self.heading;
+        }
+        let course = wrap_360(course);
+        let heading = match self.heading {
+            None => course,
+            Some(heading) => {
+                let ratio = ((speed_kmh - MIN_SPEED_KMH) / (FULL_SPEED_KMH - MIN_SPEED_KMH))
+                    .clamp(0.0, 1.0);
+                let alpha = ALPHA_MIN + (ALPHA_MAX - ALPHA_MIN) * ratio;
+                wrap_360(heading + alpha * turn(heading, course))
+            }
+        };
+        self.heading = Some(heading);
+        self.heading
+    }
+}
+
+/// Shortest signed turn from `from` to `to`, in `[-180, 180)`.
+fn turn(from: f32, to: f32) -> f32 {
+    wrap_360(to - from + 180.0) - 180.0
+}
+
+fn wrap_360(deg: f32) -> f32 {
+    let wrapped = deg % 360.0;
+    if wrapped < 0.0 { wrapped + 360.0 } else { wrapped }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn averages_across_north() {
+        let mut filter = HeadingFilter::new();
+        assert_eq!(filter.update(Some(350.0), 30.0, false), Some(350.0));
+        let heading = filter.update(Some(10.0), 30.0, false).unwrap();
+        assert!(heading < 10.0 && heading > 0.0, "{heading}");
+        assert_eq!(turn(350.0, 10.0), 20.0);
+        assert_eq!(turn(10.0, 350.0), -20.0);
+    }
+
+    #[test]
+    fn holds_while_still_or_slow() {
+        let mut filter = HeadingFilter::new();
+        assert_eq!(filter.update(Some(90.0), 1.0, false), None);
+        filter.update(Some(90.0), 10.0, false);
+        assert_eq!(filter.update(Some(270.0), 10.0, true), Some(90.0));
+        assert_eq!(filter.update(Some(270.0), 2.0, false), Some(90.0));
+        assert_eq!(filter.update(None, 10.0, false), Some(90.0));
+    }
+}
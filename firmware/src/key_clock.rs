@@ -0,0 +1,150 @@
+//! Jump-resistant clock for the Find My key schedule.
+//!
+//! The advertiser derives its key from the current time. A receiver glitch
+//! that sends the clock backwards puts the counter behind the SK cache,
+//! which forces a re-derivation from SK₀ at every rotation or, before the
+//! epoch, stops advertising without a word; a glitch forwards emits future
+//! keys and drags the cache past the real counter. Either way the tag is
+//! not found until the glitch is gone.
+//!
+//! # Design
+//!
+//! - The clock is an anchor (unix, uptime) projected on the uptime clock.
+//!   A sample within `MAX_DRIFT_S` of the projection re-anchors, so the
+//!   time follows the source while it is sane.
+//! - A sample further off is a jump. It is not trusted: the projection is
+//!   kept, so the key schedule never steps backwards or skips ahead on one
+//!   bad sample, and the sample becomes a candidate anchor.
+//! - A candidate that later samples keep agreeing with for `CONFIRM_MS` is
+//!   the real time (the previous anchor was the wrong one, say a bad phone
+//!   time before the first fix) and replaces the anchor. Stale or future
+//!   keys therefore last at most `CONFIRM_MS` plus the advertising slot.
+//! - A sample that agrees with neither starts a new candidate.
+
+/// Allowed disagreement between a sample and the projected clock.
+const MAX_DRIFT_S: u64 = 5;
+/// How long a jumped-to time must hold before the clock follows it.
+const CONFIRM_MS: u64 = 60_000;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ClockEvent {
+    /// The sample agreed with the clock (or there was none).
+    Steady,
+    /// The sample jumped by `delta_s` from the clock and was not used.
+    Jumped { delta_s: i64 },
+    /// The sample agreed with the last jump, which is not confirmed yet.
+    Holding,
+    /// A jump of `delta_s` held for `CONFIRM_MS`; the clock now follows it.
+    Reanchored { delta_s: i64 },
+}
+
+#[derive(Clone, Copy)]
+struct Anchor {
+    unix: u64,
+    uptime_ms: u64,
+}
+
+impl Anchor {
+    fn project(&self, uptime_ms: u64) -> u64 {
+        self.unix + uptime_ms.saturating_sub(self.uptime_ms) / 1000
+    }
+
+    fn agrees(&self, unix: u64, uptime_ms: u64) -> bool {
+        self.project(uptime_ms).abs_diff(unix) <= MAX_DRIFT_S
+    }
+}
+
+pub struct KeyClock {
+    anchor: Option<Anchor>,
+    candidate: Option<Anchor>,
+}
+
+impl KeyClock {
+    pub const fn new() -> Self {
+        Self {
+            anchor: None,
+            candidate: None,
+        }
+    }
+
+    /// Feed the source's unix time, `None` while it has none.
+    pub fn note(&mut self, unix: Option<u64>, uptime_ms: u64) -> ClockEvent {
+        let Some(unix) = unix else {
+            return ClockEvent::Steady;
+        };
+        let sample = Anchor { unix, uptime_ms };
+        let Some(anchor) = self.anchor else {
+            self.anchor = Some(sample);
+            return ClockEvent::Steady;
+        };
+        if anchor.agrees(unix, uptime_ms) {
+            self.anchor = Some(sample);
+            self.candidate = None;
+            return ClockEvent::Steady;
+        }
+        let delta_s = unix as i64 - anchor.project(uptime_ms) as i64;
+        match self.candidate {
+            Some(candidate) if candidate.agrees(unix, uptime_ms) => {
+                if uptime_ms.saturating_sub(candidate.uptime_ms) < CONFIRM_MS {
+                    return ClockEvent::Holding;
+                }
+                self.anchor = Some(sample);
+                self.candidate = None;
+                ClockEvent::Reanchored { delta_s }
+            }
+            _ => {
+                self.candidate = Some(sample);
+                ClockEvent::Jumped { delta_s }
+            }
+        }
+    }
+
+    /// Current unix time, `None` before the first sample.
+    pub fn now(&self, uptime_ms: u64) -> Option<u64> {
+        self.anchor.map(|anchor| anchor.project(uptime_ms))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const T0: u64 = 1_700_000_000;
+
+    #[test]
+    fn a_backward_glitch_does_not_move_the_clock() {
+        let mut clock = KeyClock::new();
+        assert_eq!(clock.note(Some(T0), 0), ClockEvent::Steady);
+        assert_eq!(clock.note(Some(T0 + 10), 10_000), ClockEvent::Steady);
+        // One sentence a day in the past, then the receiver recovers.
+        assert_eq!(
+            clock.note(Some(T0 + 11 - 86_400), 11_000),
+            ClockEvent::Jumped { delta_s: -86_400 }
+        );
+        assert_eq!(clock.now(11_000), Some(T0 + 11));
+        assert_eq!(clock.note(Some(T0 + 12), 12_000), ClockEvent::Steady);
+        // No source: the clock keeps running on uptime.
+        assert_eq!(clock.note(None, 20_000), ClockEvent::Steady);
+        assert_eq!(clock.now(20_000), Some(T0 + 20));
+    }
+
+    #[test]
+    fn a_jump_that_holds_is_followed() {
+        let mut clock = KeyClock::new();
+        clock.note(Some(T0), 0);
+        let jumped = T0 - 3_600;
+        assert_eq!(
+            clock.note(Some(jumped + 1), 1_000),
+            ClockEvent::Jumped { delta_s: -3_600 }
+        );
+        for s in 2..60 {
+            assert_eq!(clock.note(Some(jumped + s), s * 1000), ClockEvent::Holding);
+            assert_eq!(clock.now(s * 1000), Some(T0 + s));
+        }
+        assert_eq!(
+            clock.note(Some(jumped + 61), 61_000),
+            ClockEvent::Reanchored { delta_s: -3_600 }
+        );
+        assert_eq!(clock.now(62_000), Some(jumped + 62));
+    }
+}
@@ -0,0 +1,184 @@
+//! Live track streaming over BLE.
+//!
+//! Logged points are kept in a small ring and streamed to a connected app as
+//! GATT notifications in the same delta format as the `.gpz` logs, so the
+//! phone can mirror the track as it is recorded instead of waiting for a file
+//! transfer.
+//!
+//! # Design
+//!
+//! - Every point gets a sequence number. A subscriber keeps the next number
+//!   it wants; on subscribe that is the oldest point in the ring, so a fresh
+//!   client first catches up on the last `RING_POINTS` points (a few
+//!   minutes at 1 Hz), then follows live with the same code path.
+//! - Each stream has its own encoder and starts with a full block. A
+//!   notification carries whole encoded points only, never a split one.
+//! - A failed notification resets the encoder: the next point is sent as a
+//!   full block, so the phone can always resync after a gap.
+//! - Fed from the same points as the GPX log. RAM only.
+//!
+//! A second, sparser stream is meant for apps that relay the position to a
+//! web service ("share my live location"):
+//!
+//! - One point per `RELAY_INTERVAL_S` goes into a ring of `RELAY_POINTS`,
+//!   enough to bridge about 40 minutes out of range.
+//! - Points are numbered within a stream whose id is the timestamp of its
+//!   first point, so an app can tell a reboot (new id, numbering restarted)
+//!   from a gap. It resumes with `RELAY_RESUME` and the next number it
+//!   wants; missed points that are still in the ring are sent in batches.
+//! - Every notification starts with a full block, so each batch decodes on
+//!   its own and can be forwarded as is.
+
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::mutex::Mutex;
+use embassy_sync::signal::Signal;
+
+use crate::storage::GpxPointInternal;
+
+pub const RING_POINTS: usize = 180;
+pub const RELAY_POINTS: usize = 256;
+const RELAY_INTERVAL_S: u32 = 10;
+
+struct Ring<const N: usize> {
+    points: [GpxPointInternal; N],
+    /// Sequence number of the next point; the ring holds the
+    /// `min(next_seq, N)` points before it.
+    next_seq: u32,
+}
+
+impl<const N: usize> Ring<N> {
+    const fn new() -> Self {
+        Self {
+            points: [GpxPointInternal::ZERO; N],
+            next_seq: 0,
+        }
+    }
+
+    fn push(&mut self, point: GpxPointInternal) {
+        self.points[self.next_seq as usize % N] = point;
+        self.next_seq = self.next_seq.wrapping_add(1);
+    }
+
+    fn oldest_seq(&self) -> u32 {
+        self.next_seq.saturating_sub(N as u32)
+    }
+
+    /// The point at `seq`, or the oldest one kept if `seq` was overwritten.
+    fn get(&self, seq: u32) -> Option<(u32, GpxPointInternal)> {
+        if seq >= self.next_seq {
+            return None;
+        }
+        let seq = seq.max(self.oldest_seq());
+        Some((seq, self.points[seq as usize % N]))
+    }
+}
+
+struct Relay {
+    ring: Ring<RELAY_POINTS>,
+    /// Timestamp of the first point, 0 before it.
+    stream_id: u32,
+    last_timestamp: u32,
+}
+
+/// Where a relay stream stands.
+#[derive(Clone, Copy)]
+pub struct RelayStatus {
+    pub stream_id: u32,
+    pub oldest_seq: u32,
+    pub next_seq: u32,
+}
+
+static RING: Mutex<CriticalSectionRawMutex, Ring<RING_POINTS>> = Mutex::new(Ring::new());
+static RELAY: Mutex<CriticalSectionRawMutex, Relay> = Mutex::new(Relay {
+    ring: Ring::new(),
+    stream_id: 0,
+    last_timestamp: 0,
+});
+static CHANGED: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+static RELAY_CHANGED: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+/// Next relay point an app asked for with `RELAY_RESUME`.
+static RELAY_RESUME: Signal<CriticalSectionRawMutex, u32> = Signal::new();
+
+/// Add a logged point to the stream.
+pub async fn note_point(
+    timestamp: u32,
+    latitude: f64,
+    longitude: f64,
+    altitude_m: f32,
+    pressure_pa: Option<f32>,
+) {
+    if timestamp == 0 {
+        return;
+    }
+    let point = GpxPointInternal::new(timestamp, latitude, longitude, altitude_m, pressure_pa);
+    RING.lock().await.push(point);
+    CHANGED.signal(());
+
+    let mut relay = RELAY.lock().await;
+    if relay.stream_id != 0 && timestamp.wrapping_sub(relay.last_timestamp) < RELAY_INTERVAL_S {
+        return;
+    }
+    if relay.stream_id == 0 {
+        relay.stream_id = timestamp;
+    }
+    relay.last_timestamp = timestamp;
+    relay.ring.push(point);
+    RELAY_CHANGED.signal(());
+}
+
+/// Sequence number a new subscriber starts from.
+pub async fn catch_up_seq() -> u32 {
+    RING.lock().await.oldest_seq()
+}
+
+/// The first point at or after `seq` (see `Ring::get`).
+pub async fn point_from(seq: u32) -> Option<(u32, GpxPointInternal)> {
+    RING.lock().await.get(seq)
+}
+
+/// Wait until a point is added.
+pub async fn wait_point() {
+    CHANGED.wait().await;
+}
+
+pub async fn relay_status() -> RelayStatus {
+    let relay = RELAY.lock().await;
+    RelayStatus {
+        stream_id: relay.stream_id,
+        oldest_seq: relay.ring.oldest_seq(),
+        next_seq: relay.ring.next_seq,
+    }
+}
+
+/// The first relay point at or after `seq` (see `Ring::get`).
+pub async fn relay_point_from(seq: u32) -> Option<(u32, GpxPointInternal)> {
+    RELAY.lock().await.ring.get(seq)
+}
+
+/// Continue the relay stream from `seq`. Numbers beyond the stream (from an
+/// older stream id) restart it from the oldest point kept.
+pub async fn resume_relay(stream_id: u32, seq: u32) -> RelayStatus {
+    let status = relay_status().await;
+    let seq = if stream_id == status.stream_id && seq <= status.next_seq {
+        seq
+    } else {
+        status.oldest_seq
+    };
+    RELAY_RESUME.signal(seq);
+    status
+}
+
+/// Wait until a relay point is added.
+pub async fn wait_relay_point() {
+    RELAY_CHANGED.wait().await;
+}
+
+/// Wait for a `RELAY_RESUME` request; returns the next number to send.
+pub async fn wait_relay_resume() -> u32 {
+    RELAY_RESUME.wait().await
+}
+
+/// Forget a resume request left over from the previous connection.
+pub fn reset_relay_resume() {
+    RELAY_RESUME.reset();
+}
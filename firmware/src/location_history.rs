@@ -0,0 +1,144 @@
+//! Coarse hourly location history for finder apps.
+//!
+//! Keeps one position for each of the last `HISTORY_HOURS` UTC hours and
+//! exposes them on a BLE characteristic, so the owner's phone can backfill
+//! where the tracker has been while it was out of range, without syncing the
+//! daily log files.
+//!
+//! # Design
+//!
+//! - Each hour keeps its latest fix; hours without a fix have no entry, so
+//!   the history can reach further back than `HISTORY_HOURS`.
+//! - Fed from the same points as the GPX log: nothing is kept while
+//!   recording is paused.
+//! - Coordinates are rounded to 1e-4 degrees (about 11 m).
+//! - RAM only: the history restarts empty after a reboot.
+//! - The characteristics need an encrypted link, so they are not readable
+//!   by anyone in range the way the NUS service is.
+//! - The whole history is longer than a notification can be, so only the
+//!   newest hour is notified; the app reads the rest, which takes a read
+//!   with offset on most links.
+
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::mutex::Mutex;
+use embassy_sync::signal::Signal;
+use libm::round;
+
+pub const HISTORY_HOURS: usize = 24;
+const ENTRY_LEN: usize = 12;
+/// `[count: u8]` + `count` x `[hour_start: u32][lat_e4: i32][lon_e4: i32]`,
+/// oldest first.
+pub const HISTORY_LEN: usize = 1 + HISTORY_HOURS * ENTRY_LEN;
+/// `[count: u8]` + the newest entry, zero before any.
+pub const LATEST_LEN: usize = 1 + ENTRY_LEN;
+const SECS_PER_HOUR: u32 = 3600;
+
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+struct Entry {
+    hour_start: u32,
+    lat_e4: i32,
+    lon_e4: i32,
+}
+
+struct History {
+    /// Oldest first.
+    entries: [Entry; HISTORY_HOURS],
+    len: usize,
+}
+
+impl History {
+    const fn new() -> Self {
+        Self {
+            entries: [Entry {
+                hour_start: 0,
+                lat_e4: 0,
+                lon_e4: 0,
+            }; HISTORY_HOURS],
+            len: 0,
+        }
+    }
+
+    /// Returns whether the serialized history changed.
+    fn note(&mut self, entry: Entry) -> bool {
+        if self.len > 0 {
+            let last = &mut self.entries[self.len - 1];
+            if last.hour_start == entry.hour_start {
+                let changed = *last != entry;
+                *last = entry;
+                return changed;
+            }
+            if last.hour_start > entry.hour_start {
+                // Clock went backwards; keep the history monotonic.
+                return false;
+            }
+        }
+        if self.len == HISTORY_HOURS {
+            self.entries.copy_within(1.., 0);
+            self.len -= 1;
+        }
+        self.entries[self.len] = entry;
+        self.len += 1;
+        true
+    }
+
+    fn to_bytes(&self) -> [u8; HISTORY_LEN] {
+        let mut out = [0u8; HISTORY_LEN];
+        out[0] = self.len as u8;
+        for (entry, chunk) in self.entries[..self.len]
+            .iter()
+            .zip(out[1..].chunks_exact_mut(ENTRY_LEN))
+        {
+            entry.write(chunk);
+        }
+        out
+    }
+
+    fn latest_bytes(&self) -> [u8; LATEST_LEN] {
+        let mut out = [0u8; LATEST_LEN];
+        out[0] = self.len as u8;
+        if let Some(entry) = self.entries[..self.len].last() {
+            entry.write(&mut out[1..]);
+        }
+        out
+    }
+}
+
+impl Entry {
+    fn write(&self, out: &mut [u8]) {
+        out[0..4].copy_from_slice(&self.hour_start.to_le_bytes());
+        out[4..8].copy_from_slice(&self.lat_e4.to_le_bytes());
+        out[8..12].copy_from_slice(&self.lon_e4.to_le_bytes());
+    }
+}
+
+static HISTORY: Mutex<CriticalSectionRawMutex, History> = Mutex::new(History::new());
+static CHANGED: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+/// Fold a logged fix into the history.
+pub async fn note_fix(timestamp: u32, latitude: f64, longitude: f64) {
+    if timestamp == 0 {
+        return;
+    }
+    let entry = Entry {
+        hour_start: timestamp - timestamp % SECS_PER_HOUR,
+        lat_e4: round(latitude * 1e4) as i32,
+        lon_e4: round(longitude * 1e4) as i32,
+    };
+    if HISTORY.lock().await.note(entry) {
+        CHANGED.signal(());
+    }
+}
+
+pub async fn snapshot() -> [u8; HISTORY_LEN] {
+    HISTORY.lock().await.to_bytes()
+}
+
+/// The entry count and the newest hour, as notified.
+pub async fn latest() -> [u8; LATEST_LEN] {
+    HISTORY.lock().await.latest_bytes()
+}
+
+/// Wait until `snapshot()` changes.
+pub async fn wait_change() {
+    CHANGED.wait().await;
+}
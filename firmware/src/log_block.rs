@@ -0,0 +1,115 @@
+//! The log block handed from `LOG_WRITER` to `log_writer_task`.
+//!
+//! `storage` keeps one block between its two halves: `append_gpx_point`
+//! fills it from the write cache and `log_writer_task` writes it to the
+//! card. This module holds the block and its transitions; the locks and
+//! signals around it stay in `storage`.
+//!
+//! # Design
+//!
+//! - A block with `len == 0` is free. A hand-off waits while it is not, so
+//!   every transition that frees a block must tell the waiter:
+//!   `finish` after a write and `discard` when today's log was deleted
+//!   underneath it both report that.
+//! - A failed write keeps the block with a failure count and the uptime of
+//!   the next attempt; freeing it resets both.
+
+pub struct LogBlock<const N: usize> {
+    pub date: u32,
+    /// Timestamp of the last point in `data`.
+    pub last_timestamp: u32,
+    pub len: usize,
+    pub data: [u8; N],
+    /// Failed writes of `data` so far.
+    pub failures: u8,
+    /// Uptime (ms) of the next attempt after a failure.
+    pub retry_at_ms: u64,
+}
+
+impl<const N: usize> LogBlock<N> {
+    pub const fn new() -> Self {
+        Self {
+            date: 0,
+            last_timestamp: 0,
+            len: 0,
+            data: [0; N],
+            failures: 0,
+            retry_at_ms: 0,
+        }
+    }
+
+    /// Take `data` for `date` if the block is free; false if it is not.
+    pub fn fill(&mut self, date: u32, last_timestamp: u32, data: &[u8]) -> bool {
+        if self.len != 0 {
+            return false;
+        }
+        self.data[..data.len()].copy_from_slice(data);
+        self.len = data.len();
+        self.date = date;
+        self.last_timestamp = last_timestamp;
+        true
+    }
+
+    /// Free the block after its write succeeded or was given up.
+    pub fn finish(&mut self) {
+        self.len = 0;
+        self.failures = 0;
+    }
+
+    /// Drop the pending block of `date`, whose log was deleted. True if it
+    /// was freed, so a waiting hand-off must be woken.
+    pub fn discard(&mut self, date: u32) -> bool {
+        if self.len == 0 || self.date != date {
+            return false;
+        }
+        self.finish();
+        true
+    }
+
+    /// Uptime (ms) of the next attempt of a block that failed to write.
+    pub fn retry_at_ms(&self) -> Option<u64> {
+        (self.len > 0 && self.failures > 0).then_some(self.retry_at_ms)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: u32 = 20250101;
+
+    #[test]
+    fn delete_during_a_hand_off_frees_the_block() {
+        let mut block = LogBlock::<8>::new();
+        assert!(block.fill(DAY, 100, &[1, 2, 3]));
+        // The next cache waits while the first one is pending.
+        assert!(!block.fill(DAY, 110, &[4, 5]));
+        // Deleting today's log frees the block and asks for a wake-up, so
+        // the waiting hand-off goes through.
+        assert!(block.discard(DAY));
+        assert!(block.fill(DAY, 110, &[4, 5]));
+        assert_eq!((block.len, block.last_timestamp), (2, 110));
+        assert_eq!(&block.data[..2], &[4, 5]);
+    }
+
+    #[test]
+    fn discard_leaves_other_blocks() {
+        let mut block = LogBlock::<8>::new();
+        assert!(!block.discard(DAY));
+        assert!(block.fill(DAY - 1, 100, &[1]));
+        assert!(!block.discard(DAY));
+        assert_eq!(block.len, 1);
+    }
+
+    #[test]
+    fn freeing_resets_the_retry() {
+        let mut block = LogBlock::<8>::new();
+        assert!(block.fill(DAY, 100, &[1]));
+        assert_eq!(block.retry_at_ms(), None);
+        block.failures = 1;
+        block.retry_at_ms = 500;
+        assert_eq!(block.retry_at_ms(), Some(500));
+        assert!(block.discard(DAY));
+        assert_eq!((block.failures, block.retry_at_ms()), (0, None));
+    }
+}
@@ -0,0 +1,271 @@
+//! SX1262 LoRa radio on the shared SPI bus (optional, `lora` feature).
+//!
+//! Groundwork for long-range telemetry: the radio shares SPIM3 with the SD
+//! card through `spi_bus::SharedSpiDevice` and has its own CS, reset, BUSY
+//! and DIO1 (TX done) lines. This module covers bring-up (reset, standby,
+//! regulator and RF switch setup), LoRa modulation setup and a blocking-free
+//! transmit; between transmissions the radio stays in warm-start sleep.
+//! Packet framing and scheduling live in `lorawan`.
+//!
+//! # Design
+//!
+//! - Every command waits for BUSY low first, as the SX126x requires; a stuck
+//!   BUSY line marks the radio absent instead of hanging the caller.
+//! - SPI runs at 8 MHz, mode 0 (the SX126x limit is 16 MHz).
+
+use embassy_executor::task;
+use embassy_futures::select::{select, Either};
+use embassy_nrf::gpio::{Input, Output};
+use embassy_nrf::spim;
+use embassy_time::{Duration, Instant, Timer};
+use embedded_hal::spi::{Operation, SpiDevice};
+
+use crate::lorawan;
+use crate::spi_bus::SharedSpiDevice;
+
+pub const LORA_SPI_FREQ: spim::Frequency = spim::Frequency::M8;
+
+const OP_SET_SLEEP: u8 = 0x84;
+const OP_SET_STANDBY: u8 = 0x80;
+const OP_SET_REGULATOR_MODE: u8 = 0x96;
+const OP_SET_DIO2_AS_RF_SWITCH: u8 = 0x9D;
+const OP_GET_STATUS: u8 = 0xC0;
+const OP_CALIBRATE_IMAGE: u8 = 0x98;
+const OP_SET_PACKET_TYPE: u8 = 0x8A;
+const OP_SET_RF_FREQUENCY: u8 = 0x86;
+const OP_SET_PA_CONFIG: u8 = 0x95;
+const OP_SET_TX_PARAMS: u8 = 0x8E;
+const OP_SET_MODULATION_PARAMS: u8 = 0x8B;
+const OP_SET_PACKET_PARAMS: u8 = 0x8C;
+const OP_SET_BUFFER_BASE: u8 = 0x8F;
+const OP_WRITE_BUFFER: u8 = 0x0E;
+const OP_WRITE_REGISTER: u8 = 0x0D;
+const OP_SET_DIO_IRQ_PARAMS: u8 = 0x08;
+const OP_CLEAR_IRQ_STATUS: u8 = 0x02;
+const OP_GET_IRQ_STATUS: u8 = 0x12;
+const OP_SET_TX: u8 = 0x83;
+
+const REG_LORA_SYNC_WORD: u16 = 0x0740;
+/// LoRaWAN public network sync word.
+const LORA_SYNC_WORD_PUBLIC: [u8; 2] = [0x34, 0x44];
+const PACKET_TYPE_LORA: u8 = 0x01;
+const LORA_BW_125: u8 = 0x04;
+const LORA_CR_4_5: u8 = 0x01;
+const LORA_PREAMBLE_SYMBOLS: u16 = 8;
+const PA_RAMP_200US: u8 = 0x04;
+const IRQ_TX_DONE: u16 = 0x0001;
+const IRQ_TIMEOUT: u16 = 0x0200;
+const TX_TIMEOUT_MS: u64 = 3000;
+/// SetTx timeout units are 15.625 us.
+const TX_TIMEOUT_TICKS: u32 = (TX_TIMEOUT_MS * 64) as u32;
+const XTAL_HZ: u64 = 32_000_000;
+
+const STANDBY_RC: u8 = 0x00;
+const REGULATOR_DCDC: u8 = 0x01;
+const SLEEP_WARM_START: u8 = 0x04;
+const CHIP_MODE_STBY_RC: u8 = 0x2;
+
+const BUSY_TIMEOUT_MS: u64 = 20;
+const RESET_PULSE_MS: u64 = 2;
+
+pub struct LoraPins {
+    pub reset: Output<'static>,
+    pub busy: Input<'static>,
+    pub dio1: Input<'static>,
+}
+
+#[derive(Clone, Copy)]
+pub struct LoraModulation {
+    pub frequency_hz: u32,
+    pub spreading_factor: u8,
+    pub tx_power_dbm: i8,
+}
+
+pub struct Sx1262 {
+    spi: SharedSpiDevice,
+    pins: LoraPins,
+}
+
+impl Sx1262 {
+    pub fn new(spi: SharedSpiDevice, pins: LoraPins) -> Self {
+        Self { spi, pins }
+    }
+
+    async fn wait_busy(&mut self) -> bool {
+        let deadline = Instant::now() + Duration::from_millis(BUSY_TIMEOUT_MS);
+        while self.pins.busy.is_high() {
+            if Instant::now() >= deadline {
+                return false;
+            }
+            Timer::after_micros(100).await;
+        }
+        true
+    }
+
+    pub async fn reset(&mut self) -> bool {
+        self.pins.reset.set_low();
+        Timer::after_millis(RESET_PULSE_MS).await;
+        self.pins.reset.set_high();
+        self.wait_busy().await
+    }
+
+    /// Send `opcode` followed by `params`.
+    pub async fn command(&mut self, opcode: u8, params: &[u8]) -> bool {
+        if !self.wait_busy().await {
+            return false;
+        }
+        self.spi
+            .transaction(&mut [Operation::Write(&[opcode]), Operation::Write(params)])
+            .is_ok()
+    }
+
+    /// Send `opcode` and read the response bytes that follow the status byte.
+    pub async fn read_command(&mut self, opcode: u8, out: &mut [u8]) -> Option<u8> {
+        if !self.wait_busy().await {
+            return None;
+        }
+        let mut head = [opcode, 0x00];
+        self.spi
+            .transaction(&mut [
+                Operation::TransferInPlace(&mut head),
+                Operation::Read(out),
+            ])
+            .ok()?;
+        Some(head[1])
+    }
+
+    pub async fn status(&mut self) -> Option<u8> {
+        self.read_command(OP_GET_STATUS, &mut []).await
+    }
+
+    /// Reset and configure the radio; returns `false` if it does not respond.
+    pub async fn init(&mut self) -> bool {
+        if !self.reset().await {
+            return false;
+        }
+        if !self.command(OP_SET_STANDBY, &[STANDBY_RC]).await
+            || !self.command(OP_SET_REGULATOR_MODE, &[REGULATOR_DCDC]).await
+            || !self.command(OP_SET_DIO2_AS_RF_SWITCH, &[0x01]).await
+        {
+            return false;
+        }
+        let Some(status) = self.status().await else {
+            return false;
+        };
+        (status >> 4) & 0x07 == CHIP_MODE_STBY_RC
+    }
+
+    /// Warm-start sleep; configuration is retained for the next wakeup.
+    pub async fn sleep(&mut self) -> bool {
+        self.command(OP_SET_SLEEP, &[SLEEP_WARM_START]).await
+    }
+
+    async fn write_register(&mut self, addr: u16, data: &[u8]) -> bool {
+        if !self.wait_busy().await {
+            return false;
+        }
+        let [hi, lo] = addr.to_be_bytes();
+        self.spi
+            .transaction(&mut [
+                Operation::Write(&[OP_WRITE_REGISTER, hi, lo]),
+                Operation::Write(data),
+            ])
+            .is_ok()
+    }
+
+    async fn write_buffer(&mut self, data: &[u8]) -> bool {
+        if !self.wait_busy().await {
+            return false;
+        }
+        self.spi
+            .transaction(&mut [
+                Operation::Write(&[OP_WRITE_BUFFER, 0x00]),
+                Operation::Write(data),
+            ])
+            .is_ok()
+    }
+
+    /// Wake from sleep and apply LoRa modulation settings (BW 125 kHz, CR 4/5).
+    pub async fn configure(&mut self, modulation: &LoraModulation) -> bool {
+        let freq = modulation.frequency_hz as u64;
+        let freq_reg = ((freq << 25) / XTAL_HZ) as u32;
+        // Image calibration band: 863-870 MHz or 902-928 MHz.
+        let image = if freq < 900_000_000 {
+            [0xD7, 0xDB]
+        } else {
+            [0xE1, 0xE9]
+        };
+        let sf = modulation.spreading_factor;
+        let low_data_rate = u8::from(sf >= 11);
+        let power = modulation.tx_power_dbm.clamp(-9, 22);
+
+        self.command(OP_SET_STANDBY, &[STANDBY_RC]).await
+            && self.command(OP_SET_PACKET_TYPE, &[PACKET_TYPE_LORA]).await
+            && self.command(OP_CALIBRATE_IMAGE, &image).await
+            && self
+                .command(OP_SET_RF_FREQUENCY, &freq_reg.to_be_bytes())
+                .await
+            // SX1262 high-power PA: duty 4, hpMax 7, device sel 0, paLut 1.
+            && self.command(OP_SET_PA_CONFIG, &[0x04, 0x07, 0x00, 0x01]).await
+            && self
+                .command(OP_SET_TX_PARAMS, &[power as u8, PA_RAMP_200US])
+                .await
+            && self
+                .command(
+                    OP_SET_MODULATION_PARAMS,
+                    &[sf, LORA_BW_125, LORA_CR_4_5, low_data_rate],
+                )
+                .await
+            && self
+                .write_register(REG_LORA_SYNC_WORD, &LORA_SYNC_WORD_PUBLIC)
+                .await
+            && self.command(OP_SET_BUFFER_BASE, &[0x00, 0x00]).await
+    }
+
+    /// Transmit one packet and wait for TX done. Call after `configure`.
+    pub async fn transmit(&mut self, data: &[u8]) -> bool {
+        let [pre_hi, pre_lo] = LORA_PREAMBLE_SYMBOLS.to_be_bytes();
+        let irq_mask = (IRQ_TX_DONE | IRQ_TIMEOUT).to_be_bytes();
+        let timeout = TX_TIMEOUT_TICKS.to_be_bytes();
+        // Explicit header, CRC on, standard IQ.
+        let packet_params = [pre_hi, pre_lo, 0x00, data.len() as u8, 0x01, 0x00];
+        let dio_params = [irq_mask[0], irq_mask[1], irq_mask[0], irq_mask[1], 0, 0, 0, 0];
+        if !(self.command(OP_SET_PACKET_PARAMS, &packet_params).await
+            && self.write_buffer(data).await
+            && self.command(OP_SET_DIO_IRQ_PARAMS, &dio_params).await
+            && self.command(OP_CLEAR_IRQ_STATUS, &[0xFF, 0xFF]).await
+            && self
+                .command(OP_SET_TX, &[timeout[1], timeout[2], timeout[3]])
+                .await)
+        {
+            return false;
+        }
+
+        let done = match select(
+            self.pins.dio1.wait_for_high(),
+            Timer::after_millis(TX_TIMEOUT_MS + 100),
+        )
+        .await
+        {
+            Either::First(()) => true,
+            Either::Second(()) => false,
+        };
+        let mut irq = [0u8; 2];
+        let status_ok = self.read_command(OP_GET_IRQ_STATUS, &mut irq).await.is_some();
+        let _ = self.command(OP_CLEAR_IRQ_STATUS, &[0xFF, 0xFF]).await;
+        done && status_ok && (u16::from_be_bytes(irq) & IRQ_TX_DONE) != 0
+    }
+}
+
+#[task]
+pub async fn lora_task(mut radio: Sx1262) {
+    if !radio.init().await {
+        defmt::warn!("LoRa: SX1262 not responding");
+        return;
+    }
+    defmt::info!("LoRa: SX1262 ready");
+    if !radio.sleep().await {
+        defmt::warn!("LoRa: sleep command failed");
+    }
+    lorawan::uplink_loop(&mut radio).await;
+}
@@ -0,0 +1,382 @@
+//! LoRaWAN position uplink (optional, `lora` feature).
+//!
+//! When no phone is connected over BLE, the last fix and battery level go out
+//! every `interval_min` minutes as an unconfirmed LoRaWAN uplink, so the
+//! tracker stays reachable off-grid. The device uses ABP (no join): session
+//! keys and DevAddr are provisioned over BLE into `/LORA.CFG`.
+//!
+//! # Design
+//!
+//! - Uplink only (class A receive windows are not opened), FPort 1.
+//! - FCntUp is persisted to `/LORA.CNT` *before* each transmit, so a reset
+//!   never reuses a counter the network server has already seen.
+//! - EU868 enforces the 1% duty cycle from the computed time on air; US915
+//!   caps SF at 10 to honour the 400 ms dwell time instead.
+//! - Channels rotate per uplink over the region's default set.
+//!
+//! # `/LORA.CFG` layout (little-endian, `LORA_CONFIG_LEN` bytes)
+//!
+//! `[version=1][flags: bit0 enabled][interval_min: u16][region][sf][tx_power_dbm: i8]`
+//! `[dev_addr: u32][nwk_skey: 16B][app_skey: 16B]`
+//!
+//! # Uplink payload (12 bytes before encryption)
+//!
+//! `[lat_e5: i32][lon_e5: i32][alt_m: i16][battery_percent][flags]`,
+//! flags bit0 = fix valid, bit1 = stationary.
+
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+use aes::cipher::{BlockEncrypt, KeyInit};
+use aes::Aes128;
+use embassy_futures::select::{select, Either};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::mutex::Mutex;
+use embassy_sync::signal::Signal;
+use embassy_time::{Instant, Timer};
+use libm::ceilf;
+
+use crate::ble;
+use crate::lora::{LoraModulation, Sx1262};
+use crate::storage;
+use crate::system_info::SYSTEM_INFO;
+
+pub const LORA_CONFIG_LEN: usize = 43;
+
+const CONFIG_VERSION: u8 = 1;
+const FLAG_ENABLED: u8 = 0x01;
+const REGION_EU868: u8 = 0;
+const REGION_US915: u8 = 1;
+const EU868_CHANNELS_HZ: [u32; 3] = [868_100_000, 868_300_000, 868_500_000];
+/// US915 sub-band 2 (channels 8-15), used by most public networks.
+const US915_BASE_HZ: u32 = 903_900_000;
+const US915_STEP_HZ: u32 = 200_000;
+const US915_CHANNEL_COUNT: u32 = 8;
+const US915_MAX_SF: u8 = 10;
+const EU868_MAX_POWER_DBM: i8 = 14;
+const US915_MAX_POWER_DBM: i8 = 20;
+const EU868_DUTY_CYCLE_FACTOR: u64 = 99;
+
+const MHDR_UNCONFIRMED_UP: u8 = 0x40;
+const FPORT_POSITION: u8 = 1;
+const PAYLOAD_LEN: usize = 12;
+/// MHDR + FHDR(7) + FPort + payload + MIC.
+const FRAME_LEN: usize = 1 + 7 + 1 + PAYLOAD_LEN + 4;
+const PREAMBLE_SYMBOLS: f32 = 8.0;
+
+const FLAG_FIX_VALID: u8 = 0x01;
+const FLAG_STATIONARY: u8 = 0x02;
+
+#[derive(Clone, Copy)]
+pub struct LoraConfig {
+    enabled: bool,
+    interval_min: u16,
+    region: u8,
+    spreading_factor: u8,
+    tx_power_dbm: i8,
+    dev_addr: u32,
+    nwk_skey: [u8; 16],
+    app_skey: [u8; 16],
+}
+
+impl LoraConfig {
+    pub fn from_bytes(data: &[u8; LORA_CONFIG_LEN]) -> Option<Self> {
+        if data[0] != CONFIG_VERSION {
+            return None;
+        }
+        let interval_min = u16::from_le_bytes([data[2], data[3]]);
+        let region = data[4];
+        let spreading_factor = data[5];
+        let max_sf = match region {
+            REGION_EU868 => 12,
+            REGION_US915 => US915_MAX_SF,
+            _ => return None,
+        };
+        if interval_min == 0 || !(7..=max_sf).contains(&spreading_factor) {
+            return None;
+        }
+        let mut nwk_skey = [0u8; 16];
+        let mut app_skey = [0u8; 16];
+        nwk_skey.copy_from_slice(&data[11..27]);
+        app_skey.copy_from_slice(&data[27..43]);
+        Some(Self {
+            enabled: (data[1] & FLAG_ENABLED) != 0,
+            interval_min,
+            region,
+            spreading_factor,
+            tx_power_dbm: data[6] as i8,
+            dev_addr: u32::from_le_bytes([data[7], data[8], data[9], data[10]]),
+            nwk_skey,
+            app_skey,
+        })
+    }
+
+    fn modulation(&self, channel: u32) -> LoraModulation {
+        let (frequency_hz, max_power) = match self.region {
+            REGION_US915 => (
+                US915_BASE_HZ + (channel % US915_CHANNEL_COUNT) * US915_STEP_HZ,
+                US915_MAX_POWER_DBM,
+            ),
+            _ => (
+                EU868_CHANNELS_HZ[channel as usize % EU868_CHANNELS_HZ.len()],
+                EU868_MAX_POWER_DBM,
+            ),
+        };
+        LoraModulation {
+            frequency_hz,
+            spreading_factor: self.spreading_factor,
+            tx_power_dbm: self.tx_power_dbm.min(max_power),
+        }
+    }
+}
+
+static CONFIG: Mutex<CriticalSectionRawMutex, Option<LoraConfig>> = Mutex::new(None);
+static CONFIG_CHANGED: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+static RADIO_OK: AtomicBool = AtomicBool::new(false);
+static FCNT_UP: AtomicU32 = AtomicU32::new(0);
+/// Uptime seconds of the last successful uplink (0 = none yet).
+static LAST_UPLINK_S: AtomicU32 = AtomicU32::new(0);
+
+/// Validate, persist and apply a new `/LORA.CFG`.
+pub async fn set_config(data: &[u8; LORA_CONFIG_LEN]) -> bool {
+    let Some(config) = LoraConfig::from_bytes(data) else {
+        return false;
+    };
+    if !storage::write_lora_config(data).await {
+        return false;
+    }
+    let mut current = CONFIG.lock().await;
+    // New session keys restart the frame counter.
+    let keys_changed = current.is_none_or(|c| {
+        c.dev_addr != config.dev_addr || c.nwk_skey != config.nwk_skey
+    });
+    if keys_changed {
+        FCNT_UP.store(0, Ordering::Release);
+        let _ = storage::write_lora_fcnt(0).await;
+    }
+    *current = Some(config);
+    drop(current);
+    CONFIG_CHANGED.signal(());
+    true
+}
+
+/// `(enabled, radio_ok, interval_min, fcnt_up, last_uplink_age_s)`;
+/// `last_uplink_age_s` is `u32::MAX` before the first uplink.
+pub async fn status() -> (bool, bool, u16, u32, u32) {
+    let config = *CONFIG.lock().await;
+    let last = LAST_UPLINK_S.load(Ordering::Acquire);
+    let age = if last == 0 {
+        u32::MAX
+    } else {
+        (Instant::now().as_secs() as u32).saturating_sub(last)
+    };
+    (
+        config.is_some_and(|c| c.enabled),
+        RADIO_OK.load(Ordering::Acquire),
+        config.map_or(0, |c| c.interval_min),
+        FCNT_UP.load(Ordering::Acquire),
+        age,
+    )
+}
+
+async fn load() {
+    if let Some(data) = storage::read_lora_config().await {
+        *CONFIG.lock().await = LoraConfig::from_bytes(&data);
+    }
+    if let Some(fcnt) = storage::read_lora_fcnt().await {
+        FCNT_UP.store(fcnt, Ordering::Release);
+    }
+}
+
+/// Semtech SX127x/SX126x time-on-air formula, BW 125 kHz, CR 4/5, CRC on.
+fn time_on_air_ms(spreading_factor: u8, payload_len: usize) -> u32 {
+    let sf = spreading_factor as f32;
+    let symbol_ms = (1u32 << spreading_factor) as f32 / 125.0;
+    let de = if spreading_factor >= 11 { 1.0 } else { 0.0 };
+    let numerator = 8.0 * payload_len as f32 - 4.0 * sf + 28.0 + 16.0;
+    let payload_symbols = 8.0 + (ceilf(numerator / (4.0 * (sf - 2.0 * de))) * 5.0).max(0.0);
+    ((PREAMBLE_SYMBOLS + 4.25 + payload_symbols) * symbol_ms) as u32 + 1
+}
+
+async fn build_payload() -> [u8; PAYLOAD_LEN] {
+    let info = *SYSTEM_INFO.lock().await;
+    let mut out = [0u8; PAYLOAD_LEN];
+    let lat = (info.latitude * 1e5) as i32;
+    let lon = (info.longitude * 1e5) as i32;
+    let alt = info.altitude.clamp(i16::MIN as f32, i16::MAX as f32) as i16;
+    out[0..4].copy_from_slice(&lat.to_le_bytes());
+    out[4..8].copy_from_slice(&lon.to_le_bytes());
+    out[8..10].copy_from_slice(&alt.to_le_bytes());
+    out[10] = info.battery_percent;
+    let mut flags = 0u8;
+    if info.location_valid {
+        flags |= FLAG_FIX_VALID;
+    }
+    if info.is_stationary {
+        flags |= FLAG_STATIONARY;
+    }
+    out[11] = flags;
+    out
+}
+
+/// `A_i` / `B_0` block shared by payload encryption and MIC (uplink, dir = 0).
+fn lorawan_block(prefix: u8, dev_addr: u32, fcnt: u32, last: u8) -> [u8; 16] {
+    let mut block = [0u8; 16];
+    block[0] = prefix;
+    block[6..10].copy_from_slice(&dev_addr.to_le_bytes());
+    block[10..14].copy_from_slice(&fcnt.to_le_bytes());
+    block[15] = last;
+    block
+}
+
+fn aes_encrypt(cipher: &Aes128, block: [u8; 16]) -> [u8; 16] {
+    let mut block = aes::Block::from(block);
+    cipher.encrypt_block(&mut block);
+    block.into()
+}
+
+fn cmac_subkey(input: [u8; 16]) -> [u8; 16] {
+    let mut out = [0u8; 16];
+    for i in 0..16 {
+        let next = input.get(i + 1).copied().unwrap_or(0);
+        out[i] = (input[i] << 1) | (next >> 7);
+    }
+    if input[0] & 0x80 != 0 {
+        out[15] ^= 0x87;
+    }
+    out
+}
+
+/// AES-CMAC (RFC 4493) over `parts` concatenated.
+fn aes_cmac(key: &[u8; 16], parts: &[&[u8]]) -> [u8; 16] {
+    let cipher = Aes128::new(key.into());
+    let k1 = cmac_subkey(aes_encrypt(&cipher, [0u8; 16]));
+    let k2 = cmac_subkey(k1);
+
+    let mut state = [0u8; 16];
+    let mut block = [0u8; 16];
+    let mut fill = 0usize;
+    for &byte in parts.iter().flat_map(|p| p.iter()) {
+        if fill == 16 {
+            for (s, b) in state.iter_mut().zip(block.iter()) {
+                *s ^= *b;
+            }
+            state = aes_encrypt(&cipher, state);
+            fill = 0;
+        }
+        block[fill] = byte;
+        fill += 1;
+    }
+
+    let subkey = if fill == 16 {
+        k1
+    } else {
+        block[fill] = 0x80;
+        block[fill + 1..].fill(0);
+        k2
+    };
+    for i in 0..16 {
+        state[i] ^= block[i] ^ subkey[i];
+    }
+    aes_encrypt(&cipher, state)
+}
+
+fn build_frame(config: &LoraConfig, fcnt: u32, payload: &[u8; PAYLOAD_LEN]) -> [u8; FRAME_LEN] {
+    let mut frame = [0u8; FRAME_LEN];
+    frame[0] = MHDR_UNCONFIRMED_UP;
+    frame[1..5].copy_from_slice(&config.dev_addr.to_le_bytes());
+    frame[5] = 0x00; // FCtrl: no ADR, no options
+    frame[6..8].copy_from_slice(&(fcnt as u16).to_le_bytes());
+    frame[8] = FPORT_POSITION;
+
+    let cipher = Aes128::new((&config.app_skey).into());
+    let data = &mut frame[9..9 + PAYLOAD_LEN];
+    data.copy_from_slice(payload);
+    for (i, chunk) in data.chunks_mut(16).enumerate() {
+        let key_stream = aes_encrypt(
+            &cipher,
+            lorawan_block(0x01, config.dev_addr, fcnt, (i + 1) as u8),
+        );
+        for (byte, k) in chunk.iter_mut().zip(key_stream.iter()) {
+            *byte ^= *k;
+        }
+    }
+
+    let msg_len = FRAME_LEN - 4;
+    let b0 = lorawan_block(0x49, config.dev_addr, fcnt, msg_len as u8);
+    let mic = aes_cmac(&config.nwk_skey, &[&b0, &frame[..msg_len]]);
+    frame[msg_len..].copy_from_slice(&mic[..4]);
+    frame
+}
+
+async fn wait_config_or(ms: u64) {
+    if let Either::Second(()) = select(Timer::after_millis(ms), CONFIG_CHANGED.wait()).await {
+        defmt::info!("LoRa: config updated");
+    }
+}
+
+/// Uplink scheduler, run by `lora_task` after radio bring-up.
+pub async fn uplink_loop(radio: &mut Sx1262) -> ! {
+    RADIO_OK.store(true, Ordering::Release);
+    load().await;
+    let mut channel = 0u32;
+    let mut next_allowed_ms = 0u64;
+
+    loop {
+        let Some(config) = *CONFIG.lock().await else {
+            CONFIG_CHANGED.wait().await;
+            continue;
+        };
+        if !config.enabled {
+            CONFIG_CHANGED.wait().await;
+            continue;
+        }
+        let interval_ms = config.interval_min as u64 * 60_000;
+
+        let now_ms = Instant::now().as_millis();
+        if now_ms < next_allowed_ms {
+            wait_config_or(next_allowed_ms - now_ms).await;
+            continue;
+        }
+        if ble::is_connected() {
+            // The phone is in range and syncs over BLE; retry next interval.
+            wait_config_or(interval_ms).await;
+            continue;
+        }
+
+        let fcnt = FCNT_UP.load(Ordering::Acquire);
+        if !storage::write_lora_fcnt(fcnt.wrapping_add(1)).await {
+            defmt::warn!("LoRa: FCnt persist failed, skipping uplink");
+            wait_config_or(interval_ms).await;
+            continue;
+        }
+        FCNT_UP.store(fcnt.wrapping_add(1), Ordering::Release);
+
+        let payload = build_payload().await;
+        let frame = build_frame(&config, fcnt, &payload);
+        let modulation = config.modulation(channel);
+        channel = channel.wrapping_add(1);
+
+        let airtime_ms = time_on_air_ms(config.spreading_factor, frame.len()) as u64;
+        let sent = radio.configure(&modulation).await && radio.transmit(&frame).await;
+        let _ = radio.sleep().await;
+        if sent {
+            LAST_UPLINK_S.store(Instant::now().as_secs() as u32, Ordering::Release);
+            defmt::info!(
+                "LoRa: uplink fcnt={} {}Hz SF{} {}ms",
+                fcnt,
+                modulation.frequency_hz,
+                config.spreading_factor,
+                airtime_ms
+            );
+        } else {
+            defmt::warn!("LoRa: uplink fcnt={} failed", fcnt);
+        }
+
+        let duty_off_ms = if config.region == REGION_EU868 {
+            airtime_ms * EU868_DUTY_CYCLE_FACTOR
+        } else {
+            0
+        };
+        next_allowed_ms = Instant::now().as_millis() + interval_ms.max(duty_off_ms);
+    }
+}
@@ -2,27 +2,100 @@
 #![no_main]
 
 mod accel;
+mod activity;
 mod adv_scheduler;
+mod agnss_import;
+mod agnss_window;
+mod alerts;
+mod altitude_fusion;
 mod battery;
+mod battery_history;
 mod ble;
 mod bmp280;
 mod board;
+mod bonds;
+mod bthome;
+mod build_info;
 mod button;
 mod casic;
+mod crash;
+mod crc32;
+mod datum;
+mod dfu;
+mod diag;
 mod display;
+mod fat_lfn;
+mod features;
+mod file_jobs;
 #[cfg(feature = "findmy")]
 mod findmy;
+mod fix_stats;
+mod flash_ring;
+mod flash_track;
+mod fuel_gauge;
+mod geo;
+mod geofences;
 #[cfg(feature = "google-fmdn")]
 mod google_fmdn;
+mod gpio_hooks;
 mod gps;
+mod gpx_export;
+mod gpz;
+mod guest;
+mod heading;
+#[cfg(feature = "findmy")]
+mod key_clock;
+mod live_track;
+mod location_history;
+mod log_block;
+#[cfg(feature = "lora")]
+mod lora;
+#[cfg(feature = "lora")]
+mod lorawan;
+mod markers;
+mod nmea_command;
+mod nmea_passthrough;
+mod phone_location;
+mod power;
+#[cfg(feature = "power-monitor")]
+mod power_monitor;
+#[cfg(feature = "gps-pps")]
+mod pps;
+mod pressure_trend;
 #[cfg(feature = "google-fmdn")]
 #[allow(dead_code)]
 mod secp160r1;
 mod protocol;
+mod recording;
+mod sd_arbiter;
+mod sessions;
+mod settings;
+mod sky_view;
+mod slot_meta;
+mod slots;
+mod solar;
+#[cfg(feature = "buzzer")]
+mod sound;
+mod spi_bus;
+mod splash;
+mod stationary;
+mod status_schema;
+mod steps;
 mod storage;
 mod system_info;
+mod time_check;
 mod timezone;
+#[cfg(feature = "buzzer")]
+mod tones;
+mod track_decimate;
+mod track_stats;
+mod transfer_seq;
+mod trend;
 mod usb_msc;
+mod usb_power;
+mod vibration;
+#[cfg(feature = "nav")]
+mod waypoints;
 
 use core::cell::RefCell;
 use core::sync::atomic::{AtomicBool, Ordering};
@@ -30,18 +103,19 @@ use core::sync::atomic::{AtomicBool, Ordering};
 use cortex_m::peripheral::SCB;
 use embassy_embedded_hal::shared_bus::blocking::i2c::I2cDevice;
 use embassy_executor::Spawner;
-use embassy_nrf::gpio::{Input, Level, Output, OutputDrive, Pull};
+use embassy_nrf::gpio::{Flex, Input, Level, Output, OutputDrive, Pull};
 use embassy_nrf::interrupt::Priority;
 use embassy_nrf::usb::vbus_detect::SoftwareVbusDetect;
 use embassy_nrf::{bind_interrupts, buffered_uarte, peripherals, saadc, spim, twim, uarte};
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_sync::blocking_mutex::raw::NoopRawMutex;
 use embassy_sync::blocking_mutex::Mutex as BlockingMutex;
+use embassy_sync::mutex::Mutex;
 use embassy_sync::signal::Signal;
 use embassy_time::Timer;
 use static_cell::StaticCell;
 
-use {defmt_rtt as _, panic_probe as _};
+use defmt_rtt as _;
 use nrf_softdevice::ble::SecurityMode;
 use nrf_softdevice::{raw, RawError, SocEvent, Softdevice};
 
@@ -59,12 +133,18 @@ static I2C_TX_BUF: StaticCell<[u8; 32]> = StaticCell::new();
 static BLE_SERVER: StaticCell<ble::Server> = StaticCell::new();
 static I2C_BUS: StaticCell<BlockingMutex<NoopRawMutex, RefCell<twim::Twim<'static>>>> =
     StaticCell::new();
+static SPI_BUS: StaticCell<spi_bus::SharedSpiBus> = StaticCell::new();
+static FLASH: StaticCell<slots::SharedFlash> = StaticCell::new();
 static USB_MODE_SIGNAL: Signal<CriticalSectionRawMutex, ()> = Signal::new();
 static USB_MODE_REQUESTED: AtomicBool = AtomicBool::new(false);
 static USB_CONNECTED: AtomicBool = AtomicBool::new(false);
 const SD_SPI_INIT_FREQ: spim::Frequency = spim::Frequency::K250;
 const SD_SPI_RUN_FREQ: spim::Frequency = spim::Frequency::M16;
 const USB_BOOT_FLAG: u8 = 0x01;
+const USB_CHARGER_FLAG: u8 = 0x02;
+/// GPREGRET2 carries `usb.host_timeout_s` across the reset into USB mode,
+/// which runs without the settings.
+const USB_HOST_TIMEOUT_REG: u32 = 1;
 
 pub(crate) fn request_usb_mode_transition() {
     if !USB_MODE_REQUESTED.swap(true, Ordering::AcqRel) {
@@ -79,38 +159,71 @@ pub(crate) fn usb_connected() -> bool {
     USB_CONNECTED.load(Ordering::Acquire)
 }
 
-fn set_usb_boot_flag() {
-    let result = RawError::convert(unsafe { raw::sd_power_gpregret_set(0, USB_BOOT_FLAG as u32) });
+fn set_boot_flag(flag: u8) {
+    let result = RawError::convert(unsafe { raw::sd_power_gpregret_set(0, flag as u32) });
     match result {
-        Ok(()) => defmt::info!("Set USB boot flag (sd_power_gpregret_set)"),
-        Err(err) => defmt::warn!("Set USB boot flag failed: {:?}", err),
+        Ok(()) => defmt::info!("Set boot flag 0x{:02x} (sd_power_gpregret_set)", flag),
+        Err(err) => defmt::warn!("Set boot flag 0x{:02x} failed: {:?}", flag, err),
     }
 }
 
-fn take_usb_boot_flag() -> bool {
+/// Read and clear a flag left in GPREGRET by the previous boot.
+fn take_boot_flag(flag: u8) -> bool {
     let mut current = 0u32;
     let read = RawError::convert(unsafe { raw::sd_power_gpregret_get(0, &mut current as *mut _) });
     let current = match read {
         Ok(()) => current as u8,
         Err(err) => {
-            defmt::warn!("Read USB boot flag failed: {:?}", err);
+            defmt::warn!("Read boot flags failed: {:?}", err);
             return false;
         }
     };
     defmt::info!("Boot gpregret=0x{:02x}", current);
-    if (current & USB_BOOT_FLAG) == 0 {
-        defmt::info!("USB boot flag not set");
+    if (current & flag) == 0 {
+        defmt::info!("Boot flag 0x{:02x} not set", flag);
         return false;
     }
-    let clear =
-        RawError::convert(unsafe { raw::sd_power_gpregret_clr(0, USB_BOOT_FLAG as u32) });
+    let clear = RawError::convert(unsafe { raw::sd_power_gpregret_clr(0, flag as u32) });
     match clear {
-        Ok(()) => defmt::info!("Cleared USB boot flag"),
-        Err(err) => defmt::warn!("Clear USB boot flag failed: {:?}", err),
+        Ok(()) => defmt::info!("Cleared boot flag 0x{:02x}", flag),
+        Err(err) => defmt::warn!("Clear boot flag 0x{:02x} failed: {:?}", flag, err),
     }
     true
 }
 
+fn set_usb_host_timeout(seconds: u8) {
+    let _ = unsafe { raw::sd_power_gpregret_clr(USB_HOST_TIMEOUT_REG, 0xFF) };
+    let result = RawError::convert(unsafe {
+        raw::sd_power_gpregret_set(USB_HOST_TIMEOUT_REG, seconds as u32)
+    });
+    if let Err(err) = result {
+        defmt::warn!("Set USB host timeout failed: {:?}", err);
+    }
+}
+
+/// Read and clear the timeout left by `set_usb_host_timeout`; 0 if unset.
+fn take_usb_host_timeout() -> u8 {
+    let mut value = 0u32;
+    let read = RawError::convert(unsafe {
+        raw::sd_power_gpregret_get(USB_HOST_TIMEOUT_REG, &mut value as *mut _)
+    });
+    let _ = unsafe { raw::sd_power_gpregret_clr(USB_HOST_TIMEOUT_REG, 0xFF) };
+    match read {
+        Ok(()) => value as u8,
+        Err(err) => {
+            defmt::warn!("Read USB host timeout failed: {:?}", err);
+            0
+        }
+    }
+}
+
+/// USB mode saw VBUS but no host: remember that the port is a charger and
+/// boot back into normal mode.
+pub(crate) fn leave_usb_mode_for_charger() -> ! {
+    set_boot_flag(USB_CHARGER_FLAG);
+    SCB::sys_reset()
+}
+
 #[embassy_executor::task]
 async fn softdevice_task(
     sd: &'static Softdevice,
@@ -127,6 +240,7 @@ async fn softdevice_task(
     sd.run_with_callback(move |event| match event {
         SocEvent::PowerUsbDetected => {
             USB_CONNECTED.store(true, Ordering::Release);
+            usb_power::note_vbus(true);
             defmt::info!("USB detected");
             vbus.detected(true);
             if !hfclk_requested {
@@ -136,6 +250,7 @@ async fn softdevice_task(
         }
         SocEvent::PowerUsbRemoved => {
             USB_CONNECTED.store(false, Ordering::Release);
+            usb_power::note_vbus(false);
             defmt::info!("USB removed");
             vbus.detected(false);
             if hfclk_requested {
@@ -162,9 +277,19 @@ async fn usb_mode_task() {
             continue;
         }
 
+        #[cfg(feature = "i2c-spi")]
+        if !storage::card_mounted() {
+            // Nothing to export; the USB page would stay up until a reboot.
+            defmt::warn!("USB mode requested without an SD card");
+            display::show_banner("USB: no SD card");
+            USB_MODE_REQUESTED.store(false, Ordering::Release);
+            continue;
+        }
+
         display::send_command(display::DisplayCommand::UsbMode);
         #[cfg(feature = "i2c-spi")]
-        let prep_ok = storage::enter_usb_mode().await;
+        let prep_ok =
+            storage::enter_usb_mode(settings::stored(settings::USB_GPX_EXPORT) != 0).await;
         #[cfg(not(feature = "i2c-spi"))]
         let prep_ok = {
             defmt::warn!("USB mode prep skipped (feature i2c-spi off)");
@@ -173,17 +298,96 @@ async fn usb_mode_task() {
 
         if prep_ok {
             defmt::info!("USB mode prep OK, setting boot flag");
-            set_usb_boot_flag();
+            set_usb_host_timeout(settings::stored(settings::USB_HOST_TIMEOUT_S) as u8);
+            set_boot_flag(USB_BOOT_FLAG);
             Timer::after_millis(100).await;
             defmt::info!("USB mode reset now");
             SCB::sys_reset();
         } else {
             defmt::warn!("USB mode prep failed");
+            display::send_command(display::DisplayCommand::ExitUsbMode);
             USB_MODE_REQUESTED.store(false, Ordering::Release);
         }
     }
 }
 
+#[cfg(feature = "findmy")]
+async fn load_findmy_keys() -> bool {
+    let Some(keys) = storage::read_findmy_keys().await else {
+        return false;
+    };
+    let mut pk = [0u8; 28];
+    let mut sk = [0u8; 32];
+    pk.copy_from_slice(&keys[..28]);
+    sk.copy_from_slice(&keys[28..60]);
+    let epoch = u64::from_le_bytes({
+        let mut b = [0u8; 8];
+        b.copy_from_slice(&keys[60..68]);
+        b
+    });
+    findmy::init(&pk, &sk, epoch);
+    findmy::load_sk_cache().await;
+    findmy::set_provisioned(true);
+    defmt::info!("FindMy: loaded keys from SD, epoch={}", epoch);
+    true
+}
+
+#[cfg(feature = "google-fmdn")]
+async fn load_fmdn_eik() -> bool {
+    // 32-byte EIK.
+    let Some(eik_data) = storage::read_fmdn_eik().await else {
+        return false;
+    };
+    let mut eik = [0u8; 32];
+    eik.copy_from_slice(&eik_data[..32]);
+    google_fmdn::init(&eik);
+    google_fmdn::set_enabled(true);
+    defmt::info!("FMDN: loaded EIK from SD");
+    true
+}
+
+/// Read the configuration kept on the card, then let it be written. Runs
+/// at boot, or on the first mount of a card inserted after boot, replacing
+/// whatever was set without it.
+async fn load_card_config() {
+    #[cfg(feature = "nav")]
+    waypoints::load().await;
+    settings::load().await;
+    geofences::load().await;
+    guest::load().await;
+    ble::load_bonds().await;
+    sessions::load().await;
+    crash::log_previous().await;
+    if let Some(tz_settings) = storage::read_tz_settings().await {
+        timezone::set_settings(tz_settings);
+        defmt::info!("Timezone: loaded override settings from SD");
+    }
+    storage::note_config_read();
+}
+
+/// Read a card inserted after boot: its configuration on the first mount,
+/// and tracker keys unless BLE provisioned them in the meantime. The
+/// recording state and `rec.auto_start` stay as booted.
+#[cfg(feature = "i2c-spi")]
+#[embassy_executor::task]
+async fn late_card_task() {
+    loop {
+        storage::wait_card_mounted().await;
+        if !storage::config_read() {
+            defmt::info!("SD: card inserted after boot, loading its configuration");
+            load_card_config().await;
+        }
+        #[cfg(feature = "findmy")]
+        if !findmy::is_provisioned() {
+            load_findmy_keys().await;
+        }
+        #[cfg(feature = "google-fmdn")]
+        if !google_fmdn::is_enabled() {
+            load_fmdn_eik().await;
+        }
+    }
+}
+
 fn init_usb_power_events(vbus: &SoftwareVbusDetect) -> bool {
     unsafe {
         let _ = raw::sd_power_usbdetected_enable(1);
@@ -209,6 +413,11 @@ fn init_usb_power_events(vbus: &SoftwareVbusDetect) -> bool {
 
 #[embassy_executor::main]
 async fn main(spawner: Spawner) {
+    diag::paint_stack();
+    // Before anything can reuse TIMER2, which holds the bootloader version.
+    system_info::SYSTEM_INFO.lock().await.build = build_info::capture();
+    crash::capture();
+
     let mut config = embassy_nrf::config::Config::default();
     config.lfclk_source = embassy_nrf::config::LfclkSource::InternalRC;
 
@@ -232,6 +441,13 @@ async fn main(spawner: Spawner) {
         v3v3_en,
         serial2_rx,
         serial2_tx,
+        gps_pps,
+        gps_reset: gps_reset_pin,
+        lora_cs,
+        lora_reset,
+        lora_busy,
+        lora_dio1,
+        buzzer,
         uarte0,
         twispi0,
         spi3,
@@ -241,7 +457,14 @@ async fn main(spawner: Spawner) {
         ppi_ch8,
         ppi_ch9,
         ppi_group1,
+        gpiote_ch0,
+        pwm0,
+        wdt,
     } = board::Board::new(p);
+    // Started by the slot bootloader, which resets after WATCHDOG_TIMEOUT_S.
+    if let Some(watchdog) = slots::start_watchdog(wdt) {
+        spawner.spawn(slots::watchdog_task(watchdog)).unwrap();
+    }
 
     let device_name = ble::DEVICE_NAME.as_bytes();
     let sd_config = nrf_softdevice::Config {
@@ -259,7 +482,8 @@ async fn main(spawner: Spawner) {
         conn_gatts: Some(raw::ble_gatts_conn_cfg_t {
             hvn_tx_queue_size: 8,
         }),
-        common_vs_uuid: Some(raw::ble_common_cfg_vs_uuid_t { vs_uuid_count: 1 }),
+        // The NUS vendor base and the Fast Pair beacon actions base.
+        common_vs_uuid: Some(raw::ble_common_cfg_vs_uuid_t { vs_uuid_count: 2 }),
         gap_role_count: Some(raw::ble_gap_cfg_role_count_t {
             // S140 7.3.0 supports only one advertising set handle.
             // Find My and connectable BLE must time-share this single handle.
@@ -288,7 +512,12 @@ async fn main(spawner: Spawner) {
     let vbus = usb_msc::init_vbus();
     let usb_present = init_usb_power_events(vbus);
     USB_CONNECTED.store(usb_present, Ordering::Release);
-    let usb_boot_requested = take_usb_boot_flag();
+    let usb_boot_requested = take_boot_flag(USB_BOOT_FLAG);
+    if take_boot_flag(USB_CHARGER_FLAG) && usb_present {
+        usb_power::set_source(usb_power::UsbSource::Charger);
+    } else {
+        usb_power::note_vbus(usb_present);
+    }
     let usb_only = usb_boot_requested;
     defmt::info!(
         "Boot USB: present={} boot_flag={} usb_only={}",
@@ -306,9 +535,14 @@ async fn main(spawner: Spawner) {
         .spawn(softdevice_task(sd, vbus, usb_present, usb_only))
         .unwrap();
     spawner.spawn(usb_mode_task()).unwrap();
+    let flash = FLASH.init(Mutex::new(nrf_softdevice::Flash::take(sd)));
+    spawner.spawn(flash_track::flash_track_task(flash)).unwrap();
+    slots::init(flash).await;
     if usb_only {
         #[cfg(feature = "i2c-spi")]
-        spawner.spawn(usb_msc::usb_msc_task(usbd, vbus)).unwrap();
+        spawner
+            .spawn(usb_msc::usb_msc_task(usbd, vbus, take_usb_host_timeout()))
+            .unwrap();
         #[cfg(not(feature = "i2c-spi"))]
         defmt::warn!("USB MSC disabled (feature i2c-spi off)");
     }
@@ -317,7 +551,7 @@ async fn main(spawner: Spawner) {
     }
 
     // LED is on P0.15 per promicro_diy variant.
-    let _led = Output::new(led, Level::Low, OutputDrive::Standard);
+    let led = Output::new(led, Level::Low, OutputDrive::Standard);
     let _v3v3_en = Output::new(v3v3_en, Level::High, OutputDrive::Standard);
 
     // Phase 2 bring-up: create core drivers.
@@ -334,35 +568,53 @@ async fn main(spawner: Spawner) {
             spi_mosi,
             sd_spi_config.clone(),
         );
+        let spi_bus = SPI_BUS.init(BlockingMutex::new(RefCell::new(sd_spi)));
 
+        // Deassert every CS on the bus before the first SD transaction.
         let sd_cs = Output::new(spi_cs, Level::High, OutputDrive::Standard);
-        if !storage::init_sd_logger(sd_spi, sd_cs, sd_spi_config, SD_SPI_RUN_FREQ) {
+        #[cfg(feature = "lora")]
+        {
+            let lora_cs = Output::new(lora_cs, Level::High, OutputDrive::Standard);
+            let mut lora_spi_config = spim::Config::default();
+            lora_spi_config.frequency = lora::LORA_SPI_FREQ;
+            let pins = lora::LoraPins {
+                reset: Output::new(lora_reset, Level::High, OutputDrive::Standard),
+                busy: Input::new(lora_busy, Pull::None),
+                dio1: Input::new(lora_dio1, Pull::Down),
+            };
+            let radio_spi = spi_bus::SharedSpiDevice::new(spi_bus, lora_cs, lora_spi_config);
+            spawner
+                .spawn(lora::lora_task(lora::Sx1262::new(radio_spi, pins)))
+                .unwrap();
+        }
+        #[cfg(not(feature = "lora"))]
+        drop((lora_cs, lora_reset, lora_busy, lora_dio1));
+
+        if !storage::init_sd_logger(spi_bus, sd_cs, sd_spi_config, SD_SPI_RUN_FREQ) {
             defmt::warn!("SD logger init failed");
         }
+        spawner.spawn(storage::log_writer_task()).unwrap();
     }
     #[cfg(not(feature = "i2c-spi"))]
     {
         defmt::warn!("i2c-spi feature disabled: skipping SD init and sensors/display");
     }
+    // The GPS task queues points with or without a card; without one they
+    // are only counted as failed writes.
+    spawner.spawn(storage::point_writer_task()).unwrap();
+
+    recording::load().await;
+    if storage::card_mounted() {
+        load_card_config().await;
+    } else {
+        #[cfg(feature = "i2c-spi")]
+        defmt::warn!("No SD card: settings at defaults, track kept in RAM");
+    }
 
     #[cfg(feature = "findmy")]
     {
         // Load keys from SD card only after SD logger is initialized.
-        if let Some(keys) = storage::read_findmy_keys().await {
-            let mut pk = [0u8; 28];
-            let mut sk = [0u8; 32];
-            pk.copy_from_slice(&keys[..28]);
-            sk.copy_from_slice(&keys[28..60]);
-            let epoch = u64::from_le_bytes({
-                let mut b = [0u8; 8];
-                b.copy_from_slice(&keys[60..68]);
-                b
-            });
-            findmy::init(&pk, &sk, epoch);
-            findmy::load_sk_cache().await;
-            findmy::set_enabled(true);
-            defmt::info!("FindMy: loaded keys from SD, epoch={}", epoch);
-        } else {
+        if !load_findmy_keys().await {
             defmt::info!("FindMy: no keys on SD, waiting for provisioning via BLE");
         }
         spawner.spawn(findmy::findmy_task(sd)).unwrap();
@@ -370,19 +622,15 @@ async fn main(spawner: Spawner) {
 
     #[cfg(feature = "google-fmdn")]
     {
-        // Load EIK from SD card (32 bytes).
-        if let Some(eik_data) = storage::read_fmdn_eik().await {
-            let mut eik = [0u8; 32];
-            eik.copy_from_slice(&eik_data[..32]);
-            google_fmdn::init(&eik);
-            google_fmdn::set_enabled(true);
-            defmt::info!("FMDN: loaded EIK from SD");
-        } else {
+        if !load_fmdn_eik().await {
             defmt::info!("FMDN: no EIK on SD, waiting for provisioning");
         }
-        spawner.spawn(google_fmdn::fmdn_task(sd)).unwrap();
+        spawner.spawn(google_fmdn::fmdn_task(sd, server)).unwrap();
     }
 
+    #[cfg(feature = "i2c-spi")]
+    spawner.spawn(late_card_task()).unwrap();
+
     let gps_en = Output::new(gps_en_pin, Level::Low, OutputDrive::Standard);
     if !usb_only {
         let gps_uart = {
@@ -405,9 +653,31 @@ async fn main(spawner: Spawner) {
             )
         };
 
+        #[cfg(feature = "gps-reset")]
+        let gps_reset = Some(Output::new(gps_reset_pin, Level::High, OutputDrive::Standard));
+        #[cfg(not(feature = "gps-reset"))]
+        let gps_reset = {
+            drop(gps_reset_pin);
+            None
+        };
+
         let (gps_rx, gps_tx) = gps_uart.split();
         spawner.spawn(gps::gps_rx_task(gps_rx)).unwrap();
-        spawner.spawn(gps::gps_state_task(gps_tx, gps_en)).unwrap();
+        spawner.spawn(gps::gps_state_task(gps_tx, gps_en, gps_reset)).unwrap();
+
+        #[cfg(feature = "gps-pps")]
+        {
+            use embassy_nrf::gpiote;
+            let pps_input = gpiote::InputChannel::new(
+                gpiote_ch0,
+                gps_pps,
+                Pull::Down,
+                gpiote::InputChannelPolarity::LoToHi,
+            );
+            spawner.spawn(pps::pps_task(pps_input)).unwrap();
+        }
+        #[cfg(not(feature = "gps-pps"))]
+        drop((gpiote_ch0, gps_pps));
 
         let button = Input::new(button_pin, Pull::Up);
         let mut saadc_config = saadc::Config::default();
@@ -418,6 +688,24 @@ async fn main(spawner: Spawner) {
 
         spawner.spawn(battery::battery_task(saadc)).unwrap();
         spawner.spawn(button::button_task(button)).unwrap();
+        spawner.spawn(power::power_task()).unwrap();
+        spawner.spawn(alerts::led_task(led)).unwrap();
+        #[cfg(feature = "buzzer")]
+        {
+            use embassy_nrf::pwm;
+            let buzzer = pwm::SimplePwm::new_1ch(pwm0, buzzer, &pwm::SimpleConfig::default());
+            spawner.spawn(sound::sound_task(buzzer)).unwrap();
+        }
+        #[cfg(not(feature = "buzzer"))]
+        drop((pwm0, buzzer));
+        spawner.spawn(dfu::dfu_task()).unwrap();
+
+        // Expansion header pins are rule-driven hooks, idle until configured over BLE.
+        let hook_pins = [Flex::new(serial2_rx), Flex::new(serial2_tx)];
+        spawner.spawn(gpio_hooks::gpio_hooks_task(hook_pins)).unwrap();
+        spawner.spawn(file_jobs::file_jobs_task()).unwrap();
+        spawner.spawn(usb_power::charge_log_task()).unwrap();
+        spawner.spawn(bthome::bthome_task()).unwrap();
 
         #[cfg(feature = "i2c-spi")]
         {
@@ -432,17 +720,30 @@ async fn main(spawner: Spawner) {
             let i2c_display = I2cDevice::new(i2c_bus);
 
             spawner.spawn(accel::accel_task(i2c_accel)).unwrap();
+            spawner.spawn(vibration::vibration_writer_task()).unwrap();
             spawner.spawn(bmp280::bmp280_task(i2c_bmp)).unwrap();
             spawner.spawn(display::display_task(i2c_display)).unwrap();
+            #[cfg(feature = "power-monitor")]
+            {
+                let i2c_power = I2cDevice::new(i2c_bus);
+                spawner
+                    .spawn(power_monitor::power_monitor_task(i2c_power))
+                    .unwrap();
+            }
         }
     } else {
         let button = Input::new(button_pin, Pull::Up);
         spawner.spawn(button::usb_only_button_task(button)).unwrap();
+        drop((serial2_rx, serial2_tx, gpiote_ch0, gps_pps, gps_reset_pin));
+        drop((pwm0, buzzer));
     }
 
-    drop((serial2_rx, serial2_tx));
     #[cfg(not(feature = "i2c-spi"))]
     drop((spi3, spi_sck, spi_miso, spi_mosi, spi_cs, twispi0, i2c_sda, i2c_scl));
+    #[cfg(not(feature = "i2c-spi"))]
+    drop((lora_cs, lora_reset, lora_busy, lora_dio1));
 
+    // Last, so a trial image that fails to come up is never confirmed.
+    spawner.spawn(slots::slots_task()).unwrap();
     core::future::pending::<()>().await;
 }
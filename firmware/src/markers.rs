@@ -0,0 +1,184 @@
+//! Point-in-time markers for photo correlation.
+//!
+//! The companion app sends a marker when the user takes a photo (or taps a
+//! button), carrying its own ID and the phone's clock. The device stamps it
+//! with its own GPS time and position and appends it to `/MARKERS.BIN`, so
+//! the app can later measure the phone-to-GPS clock offset from the pair of
+//! timestamps and place every photo on the track by its EXIF time, not only
+//! the marked ones.
+//!
+//! # Design
+//!
+//! - Markers only append; the file is read back with the normal file
+//!   transfer commands.
+//! - Device time prefers the PPS-disciplined clock (millisecond precision)
+//!   and falls back to NMEA time (whole seconds). Without GPS time the
+//!   device time is 0 and the marker only carries its position; the phone's
+//!   clock is never used as a fallback, since that would hide the offset.
+//! - The position is the latest one in `SYSTEM_INFO`; the flags tell whether
+//!   it was a live fix.
+//! - A double press with `button.double_press` set drops a waypoint
+//!   instead: one line in `YYYY/MM/YYYYMMDD.wpt` next to the day log, and
+//!   with `nav` an entry in the waypoint list named after its UTC time, so
+//!   the compass page can lead back to it. Unlike a photo marker it needs a
+//!   live fix and GPS time.
+//!
+//! # Record layout (`MARKER_RECORD_SIZE` bytes, little-endian)
+//!
+//! | Offset | Size | Field                                          |
+//! | :----- | :--- | :--------------------------------------------- |
+//! | 0      | 4    | marker ID, chosen by the app                   |
+//! | 4      | 8    | phone time, unix milliseconds                  |
+//! | 12     | 8    | device time, unix milliseconds (0 = unknown)   |
+//! | 20     | 1    | time source: 0 none, 1 NMEA, 2 PPS             |
+//! | 21     | 1    | flags (bit0 live fix)                          |
+//! | 22     | 2    | reserved (0)                                   |
+//! | 24     | 4    | latitude, 1e-7 degrees                         |
+//! | 28     | 4    | longitude, 1e-7 degrees                        |
+//! | 32     | 4    | altitude, decimeters                           |
+
+use libm::{round, roundf};
+
+use crate::storage;
+use crate::system_info::{SystemInfo, SYSTEM_INFO};
+use crate::timezone;
+
+pub const MARKER_RECORD_SIZE: usize = 36;
+
+const FLAG_LIVE_FIX: u8 = 0x01;
+
+#[repr(u8)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum TimeSource {
+    None = 0,
+    Nmea = 1,
+    Pps = 2,
+}
+
+#[derive(Clone, Copy)]
+pub struct Marker {
+    pub id: u32,
+    pub phone_unix_ms: u64,
+    pub device_unix_ms: u64,
+    pub time_source: TimeSource,
+    pub live_fix: bool,
+    pub lat_e7: i32,
+    pub lon_e7: i32,
+    pub alt_dm: i32,
+}
+
+impl Marker {
+    /// Phone clock minus device clock, if the device had GPS time.
+    pub fn clock_offset_ms(&self) -> Option<i64> {
+        (self.time_source != TimeSource::None)
+            .then(|| self.phone_unix_ms as i64 - self.device_unix_ms as i64)
+    }
+
+    pub fn write_record(&self, out: &mut [u8]) {
+        out[0..4].copy_from_slice(&self.id.to_le_bytes());
+        out[4..12].copy_from_slice(&self.phone_unix_ms.to_le_bytes());
+        out[12..20].copy_from_slice(&self.device_unix_ms.to_le_bytes());
+        out[20] = self.time_source as u8;
+        out[21] = if self.live_fix { FLAG_LIVE_FIX } else { 0 };
+        out[22..24].fill(0);
+        out[24..28].copy_from_slice(&self.lat_e7.to_le_bytes());
+        out[28..32].copy_from_slice(&self.lon_e7.to_le_bytes());
+        out[32..36].copy_from_slice(&self.alt_dm.to_le_bytes());
+    }
+}
+
+/// Stamp a marker with device time and position and append it to SD.
+/// Returns `None` if the SD write failed.
+pub async fn add(id: u32, phone_unix_ms: u64) -> Option<Marker> {
+    let info = *SYSTEM_INFO.lock().await;
+    let (device_unix_ms, time_source) = device_time_ms(&info);
+    let marker = Marker {
+        id,
+        phone_unix_ms,
+        device_unix_ms,
+        time_source,
+        live_fix: info.location_valid,
+        lat_e7: round(info.latitude * 1e7) as i32,
+        lon_e7: round(info.longitude * 1e7) as i32,
+        alt_dm: roundf(info.altitude * 10.0) as i32,
+    };
+    let mut record = [0u8; MARKER_RECORD_SIZE];
+    marker.write_record(&mut record);
+    if !storage::append_marker(&record).await {
+        defmt::warn!("Markers: SD append failed");
+        return None;
+    }
+    defmt::info!("Markers: id={} source={}", id, time_source as u8);
+    Some(marker)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum WaypointOutcome {
+    Saved,
+    NoFix,
+    Failed,
+}
+
+/// Save the current fix as a waypoint.
+pub async fn drop_waypoint() -> WaypointOutcome {
+    let info = *SYSTEM_INFO.lock().await;
+    if !info.location_valid {
+        return WaypointOutcome::NoFix;
+    }
+    let (unix_ms, time_source) = device_time_ms(&info);
+    let unix = (unix_ms / 1000) as u32;
+    let (lat, lon) = (info.latitude, info.longitude);
+    // The file sits next to the day log, so without GPS time there is no
+    // day to put the point in.
+    let date = match time_source {
+        TimeSource::None => None,
+        _ => storage::log_day(unix, lat, lon, &mut timezone::TzCache::new()),
+    };
+    let Some(date) = date else {
+        return WaypointOutcome::NoFix;
+    };
+    if !storage::append_waypoint_log(date, unix, lat, lon, info.altitude).await {
+        defmt::warn!("Waypoint: SD append failed");
+        return WaypointOutcome::Failed;
+    }
+    #[cfg(feature = "nav")]
+    {
+        let mut name = heapless::String::<16>::new();
+        let _ = core::fmt::write(
+            &mut name,
+            format_args!("Mark {:02}{:02} UTC", info.hour, info.minute),
+        );
+        let lat_e7 = round(lat * 1e7) as i32;
+        let lon_e7 = round(lon * 1e7) as i32;
+        let slot = match crate::waypoints::Waypoint::new(lat_e7, lon_e7, name.as_bytes()) {
+            Some(wp) => crate::waypoints::add(wp).await,
+            None => None,
+        };
+        if slot.is_none() {
+            defmt::warn!("Waypoint: list full, saved to CSV only");
+        }
+    }
+    defmt::info!("Waypoint: saved at {}", unix);
+    WaypointOutcome::Saved
+}
+
+fn device_time_ms(info: &SystemInfo) -> (u64, TimeSource) {
+    #[cfg(feature = "gps-pps")]
+    if let Some(unix_ms) = crate::pps::precise_unix_ms() {
+        return (unix_ms, TimeSource::Pps);
+    }
+    if !info.date_time_valid {
+        return (0, TimeSource::None);
+    }
+    match timezone::date_time_to_unix_timestamp(
+        info.year,
+        info.month,
+        info.day,
+        info.hour,
+        info.minute,
+        info.second,
+    ) {
+        Some(unix_ts) => (unix_ts as u64 * 1000, TimeSource::Nmea),
+        None => (0, TimeSource::None),
+    }
+}
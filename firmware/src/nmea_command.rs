@@ -0,0 +1,232 @@
+//! NMEA command sentences for configuring the receiver.
+//!
+//! The `PCAS` commands used to be byte strings with the checksum worked
+//! out by hand, so changing one field meant recomputing it. `NmeaCommand`
+//! formats the fields and appends the checksum and line ending; the
+//! functions below name the commands the tracker sends.
+//!
+//! # Design
+//!
+//! - Fields are unsigned integers or empty, which covers every `PCAS`
+//!   command.
+//! - A sentence longer than `MAX_SENTENCE_LEN` (NMEA 0183, with `$` and
+//!   CRLF) makes `finish` return `None` rather than a truncated command.
+//! - The checksum is the XOR of the bytes between `$` and `*`, written as
+//!   two upper-case hex digits.
+
+pub const MAX_SENTENCE_LEN: usize = 82;
+/// `*`, two checksum digits and CRLF.
+const TRAILER_LEN: usize = 5;
+
+/// `PCAS01` baud rate code for 115200.
+pub const BAUD_115200: u32 = 5;
+/// `PCAS04` constellation mask bits.
+pub const CONSTELLATION_GPS: u32 = 0x01;
+pub const CONSTELLATION_BDS: u32 = 0x02;
+pub const CONSTELLATION_GLONASS: u32 = 0x04;
+/// `PCAS10` restart mode (0 hot, 3 factory).
+pub const RESTART_WARM: u32 = 1;
+pub const RESTART_COLD: u32 = 2;
+
+pub struct NmeaCommand {
+    buf: [u8; MAX_SENTENCE_LEN],
+    len: usize,
+    overflow: bool,
+}
+
+impl NmeaCommand {
+    /// Start a sentence with its address field, such as `PCAS03`.
+    pub fn new(address: &str) -> Self {
+        let mut command = Self {
+            buf: [0; MAX_SENTENCE_LEN],
+            len: 0,
+            overflow: false,
+        };
+        command.push(b'$');
+        for &byte in address.as_bytes() {
+            command.push(byte);
+        }
+        command
+    }
+
+    pub fn field(mut self, value: u32) -> Self {
+        self.push(b',');
+        let mut digits = [0u8; 10];
+        let mut count = 0;
+        let mut rest = value;
+        loop {
+            digits[count] = b'0' + (rest % 10) as u8;
+            count += 1;
+            rest /= 10;
+            if rest == 0 {
+                break;
+            }
+        }
+        for &digit in digits[..count].iter().rev() {
+            self.push(digit);
+        }
+        self
+    }
+
+    pub fn empty_field(mut self) -> Self {
+        self.push(b',');
+        self
+    }
+
+    /// Append the checksum and CRLF. `None` if the sentence is too long.
+    pub fn finish(mut self) -> Option<NmeaSentence> {
+        if self.overflow || self.len + TRAILER_LEN > MAX_SENTENCE_LEN {
+            return None;
+        }
+        let sum = checksum(&self.buf[1..self.len]);
+        let (high, low) = (hex_digit(sum >> 4), hex_digit(sum & 0x0F));
+        for byte in [b'*', high, low, b'\r', b'\n'] {
+            self.push(byte);
+        }
+        Some(NmeaSentence {
+            buf: self.buf,
+            len: self.len,
+        })
+    }
+
+    fn push(&mut self, byte: u8) {
+        if self.len < MAX_SENTENCE_LEN {
+            self.buf[self.len] = byte;
+            self.len += 1;
+        } else {
+            self.overflow = true;
+        }
+    }
+}
+
+/// A finished sentence, ready for the UART.
+pub struct NmeaSentence {
+    buf: [u8; MAX_SENTENCE_LEN],
+    len: usize,
+}
+
+impl NmeaSentence {
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
+
+/// XOR of the bytes between `$` and `*`.
+fn checksum(body: &[u8]) -> u8 {
+    body.iter().fold(0, |sum, &byte| sum ^ byte)
+}
+
+fn hex_digit(nibble: u8) -> u8 {
+    match nibble {
+        0..=9 => b'0' + nibble,
+        _ => b'A' + nibble - 10,
+    }
+}
+
+/// Output rate of each `PCAS03` sentence, in fixes per sentence (0 = off).
+#[derive(Clone, Copy)]
+pub struct SentenceRates {
+    pub gga: u32,
+    pub gll: u32,
+    pub gsa: u32,
+    pub gsv: u32,
+    pub rmc: u32,
+    pub vtg: u32,
+    pub zda: u32,
+}
+
+impl SentenceRates {
+    /// What the tracker parses: GGA and RMC every fix, GSV every fourth
+    /// for the Satellites page.
+    pub const TRACKING: Self = Self {
+        gga: 1,
+        gll: 0,
+        gsa: 0,
+        gsv: 4,
+        rmc: 1,
+        vtg: 0,
+        zda: 0,
+    };
+}
+
+/// `PCAS01`: UART baud rate, by code (see `BAUD_115200`).
+pub fn set_baud_rate(code: u32) -> Option<NmeaSentence> {
+    NmeaCommand::new("PCAS01").field(code).finish()
+}
+
+/// `PCAS02`: fix interval.
+pub fn set_fix_interval_ms(interval_ms: u32) -> Option<NmeaSentence> {
+    NmeaCommand::new("PCAS02").field(interval_ms).finish()
+}
+
+/// `PCAS03`: sentence output rates. ANT, DHV, LPS, UTC and GST stay off;
+/// the two reserved fields are empty.
+pub fn set_sentence_rates(rates: &SentenceRates) -> Option<NmeaSentence> {
+    NmeaCommand::new("PCAS03")
+        .field(rates.gga)
+        .field(rates.gll)
+        .field(rates.gsa)
+        .field(rates.gsv)
+        .field(rates.rmc)
+        .field(rates.vtg)
+        .field(rates.zda)
+        .field(0)
+        .field(0)
+        .field(0)
+        .empty_field()
+        .empty_field()
+        .field(0)
+        .field(0)
+        .finish()
+}
+
+/// `PCAS04`: constellations to track, a mask of `CONSTELLATION_*`.
+pub fn set_constellations(mask: u32) -> Option<NmeaSentence> {
+    NmeaCommand::new("PCAS04").field(mask).finish()
+}
+
+/// `PCAS10`: restart the receiver (`RESTART_*`).
+pub fn restart(mode: u32) -> Option<NmeaSentence> {
+    NmeaCommand::new("PCAS10").field(mode).finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_the_hand_computed_commands() {
+        let all = CONSTELLATION_GPS | CONSTELLATION_BDS | CONSTELLATION_GLONASS;
+        let cases = [
+            (set_constellations(all), &b"$PCAS04,7*1E\r\n"[..]),
+            (
+                set_sentence_rates(&SentenceRates::TRACKING),
+                b"$PCAS03,1,0,0,4,1,0,0,0,0,0,,,0,0*06\r\n",
+            ),
+            (set_baud_rate(BAUD_115200), b"$PCAS01,5*19\r\n"),
+            (set_fix_interval_ms(500), b"$PCAS02,500*1A\r\n"),
+            (restart(RESTART_WARM), b"$PCAS10,1*1D\r\n"),
+            (restart(RESTART_COLD), b"$PCAS10,2*1E\r\n"),
+        ];
+        for (built, expected) in cases {
+            assert_eq!(built.unwrap().as_bytes(), expected);
+        }
+        let big = NmeaCommand::new("PCAS02").field(u32::MAX).finish().unwrap();
+        assert_eq!(&big.as_bytes()[..18], b"$PCAS02,4294967295");
+    }
+
+    #[test]
+    fn refuses_sentences_that_do_not_fit() {
+        let mut command = NmeaCommand::new("PCAS03");
+        for _ in 0..36 {
+            command = command.field(1);
+        }
+        // 7 + 36 * 2 = 79 bytes leaves no room for the 5-byte trailer.
+        assert!(command.finish().is_none());
+        let mut command = NmeaCommand::new("PCAS03");
+        for _ in 0..100 {
+            command = command.field(0);
+        }
+        assert!(command.finish().is_none());
+    }
+}
@@ -0,0 +1,101 @@
+//! Raw NMEA pass-through between the GPS UART and a BLE central.
+//!
+//! Diagnosing the receiver used to need a cable to its UART. The NMEA
+//! GATT service carries the receiver's sentences out and the central's
+//! bytes in, so a desktop NMEA monitor or the vendor's GNSS tool can watch
+//! and configure it over BLE.
+//!
+//! # Design
+//!
+//! - Off unless `ble.nmea_passthrough` allows it, for a trusted link only:
+//!   the central can send the receiver anything.
+//! - On while the central subscribes to the TX characteristic; nothing is
+//!   copied otherwise, and it turns off again on disconnect.
+//! - `gps_rx_task` copies every complete NMEA line, with its CRLF. A full
+//!   queue drops the line rather than make the UART reader wait for BLE.
+//!   CASIC binary output is not forwarded.
+//! - Written bytes go to the UART unchanged, from the GPS state task
+//!   between steps, so they never interleave with a command it sends. They
+//!   wait while AGNSS data is being loaded and are dropped while the GPS is
+//!   off: a receiver that is not powered would not hear them.
+//! - Nothing checks what is written. A command that changes the baud rate
+//!   or the enabled sentences affects the tracker's own parsing too, until
+//!   the next GPS restart reapplies its configuration.
+
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::channel::Channel;
+use heapless::Vec;
+
+/// Longest line forwarded: 82 characters per NMEA 0183 plus CRLF, with room
+/// for vendor sentences that run over.
+pub const MAX_LINE_LEN: usize = 96;
+/// Largest write forwarded to the receiver in one piece: any CASIC frame
+/// that carries AGNSS data. Both sizes are kept short since the values
+/// come out of the SoftDevice attribute table.
+pub const MAX_WRITE_LEN: usize = 128;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static DROPPED_LINES: AtomicU32 = AtomicU32::new(0);
+static TO_CENTRAL: Channel<CriticalSectionRawMutex, Vec<u8, MAX_LINE_LEN>, 16> = Channel::new();
+static TO_GPS: Channel<CriticalSectionRawMutex, Vec<u8, MAX_WRITE_LEN>, 4> = Channel::new();
+
+/// Start or stop forwarding, on a CCCD write or disconnect.
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Release);
+    if !enabled {
+        TO_CENTRAL.clear();
+        TO_GPS.clear();
+    }
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Acquire)
+}
+
+/// Queue one sentence received from the GPS, without its line ending.
+pub fn forward_sentence(sentence: &str) {
+    if !is_enabled() {
+        return;
+    }
+    let mut line: Vec<u8, MAX_LINE_LEN> = Vec::new();
+    let queued = line.extend_from_slice(sentence.as_bytes()).is_ok()
+        && line.extend_from_slice(b"\r\n").is_ok()
+        && TO_CENTRAL.try_send(line).is_ok();
+    if !queued {
+        DROPPED_LINES.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Next line for the central.
+pub async fn next_line() -> Vec<u8, MAX_LINE_LEN> {
+    TO_CENTRAL.receive().await
+}
+
+/// Lines dropped since boot: too long, or the link fell behind.
+pub fn dropped_lines() -> u32 {
+    DROPPED_LINES.load(Ordering::Relaxed)
+}
+
+/// Queue bytes written by the central for the GPS UART. Returns false if
+/// pass-through is off or the queue is full.
+pub fn write_to_gps(data: &[u8]) -> bool {
+    if !is_enabled() {
+        return false;
+    }
+    let Ok(data) = Vec::from_slice(data) else {
+        return false;
+    };
+    TO_GPS.try_send(data).is_ok()
+}
+
+/// Next write waiting for the GPS UART, if any.
+pub fn take_for_gps() -> Option<Vec<u8, MAX_WRITE_LEN>> {
+    TO_GPS.try_receive().ok()
+}
+
+/// Drop writes queued while the GPS is off.
+pub fn discard_for_gps() {
+    TO_GPS.clear();
+}
@@ -0,0 +1,196 @@
+//! Coarse location and time pushed by the companion app.
+//!
+//! Indoors the GPS may never get a fix, but a connected phone usually knows
+//! roughly where it is (Wi-Fi/cell positioning) and what time it is. The app
+//! sends that with `SET_PHONE_LOCATION`; the firmware then:
+//!
+//! - seeds the receiver with a CASIC AID-INI packet (position + GPS time) so
+//!   the next search starts warm, queued ahead of any AGNSS ephemeris;
+//! - shows the position as "last known" on the display while there is no fix;
+//! - uses the phone time for FindMy/FMDN key rotation until GPS time is valid.
+//!
+//! The time alone also comes with `SET_TIME` or a write to the time-sync
+//! characteristic, which the app can send on every connect without knowing
+//! a position. Whichever arrived last is the phone time; it is anchored to
+//! the uptime clock and stands in for GPS time on the display, in session
+//! and vibration records and for the key schedules. Markers keep GPS time
+//! only, since they measure the phone clock.
+
+use core::cell::Cell;
+
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex as CsMutex;
+use embassy_time::Instant;
+
+use crate::casic::{encode_casic_packet, CASIC_CLASS_AID, CASIC_ID_AID_INI};
+use crate::gps;
+
+pub const PHONE_LOCATION_PAYLOAD_LEN: usize = 16;
+/// Altitude value meaning "unknown".
+pub const ALTITUDE_UNKNOWN: i16 = i16::MIN;
+
+/// Positions older than this are not shown on the display.
+const LAST_KNOWN_MAX_AGE_MS: u64 = 6 * 60 * 60 * 1000;
+/// Assumed accuracy of the phone clock (NTP/NITZ), seconds.
+const PHONE_TIME_ACCURACY_S: f32 = 2.0;
+
+const GPS_EPOCH_UNIX_S: u64 = 315_964_800;
+const GPS_UTC_LEAP_SECONDS: u64 = 18;
+const SECONDS_PER_WEEK: u64 = 604_800;
+
+const AID_INI_PAYLOAD_LEN: usize = 56;
+const AID_INI_PACKET_LEN: usize = AID_INI_PAYLOAD_LEN + 10;
+const AID_INI_FLAG_POS_VALID: u8 = 1 << 0;
+const AID_INI_FLAG_TIME_VALID: u8 = 1 << 1;
+const AID_INI_FLAG_LLA: u8 = 1 << 5;
+const AID_INI_FLAG_ALT_INVALID: u8 = 1 << 6;
+
+#[derive(Clone, Copy)]
+pub struct PhoneLocation {
+    pub latitude: f64,
+    pub longitude: f64,
+    /// Metres above MSL, or `ALTITUDE_UNKNOWN`.
+    pub altitude_m: i16,
+    pub accuracy_m: u16,
+    /// Phone UTC time when the fix was sent, 0 if not provided.
+    pub unix_ts: u32,
+    /// Uptime when the fix was received.
+    pub received_ms: u64,
+}
+
+#[derive(Clone, Copy)]
+struct PhoneTime {
+    unix_ts: u32,
+    received_ms: u64,
+}
+
+static PHONE_LOCATION: CsMutex<CriticalSectionRawMutex, Cell<Option<PhoneLocation>>> =
+    CsMutex::new(Cell::new(None));
+static PHONE_TIME: CsMutex<CriticalSectionRawMutex, Cell<Option<PhoneTime>>> =
+    CsMutex::new(Cell::new(None));
+
+impl PhoneLocation {
+    /// Parse the `SET_PHONE_LOCATION` payload:
+    /// `[lat_e7: i32][lon_e7: i32][alt_m: i16][accuracy_m: u16][unix_ts: u32]`.
+    pub fn from_bytes(raw: &[u8; PHONE_LOCATION_PAYLOAD_LEN], received_ms: u64) -> Option<Self> {
+        let lat_e7 = i32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]);
+        let lon_e7 = i32::from_le_bytes([raw[4], raw[5], raw[6], raw[7]]);
+        let altitude_m = i16::from_le_bytes([raw[8], raw[9]]);
+        let accuracy_m = u16::from_le_bytes([raw[10], raw[11]]);
+        let unix_ts = u32::from_le_bytes([raw[12], raw[13], raw[14], raw[15]]);
+        if !(-900_000_000..=900_000_000).contains(&lat_e7)
+            || !(-1_800_000_000..=1_800_000_000).contains(&lon_e7)
+            || accuracy_m == 0
+        {
+            return None;
+        }
+        if unix_ts != 0 && (unix_ts as u64) < GPS_EPOCH_UNIX_S {
+            return None;
+        }
+        Some(Self {
+            latitude: lat_e7 as f64 / 1e7,
+            longitude: lon_e7 as f64 / 1e7,
+            altitude_m,
+            accuracy_m,
+            unix_ts,
+            received_ms,
+        })
+    }
+
+    fn unix_at(&self, now_ms: u64) -> Option<u64> {
+        if self.unix_ts == 0 {
+            return None;
+        }
+        let elapsed_s = now_ms.saturating_sub(self.received_ms) / 1000;
+        Some(self.unix_ts as u64 + elapsed_s)
+    }
+
+    /// Build an AID-INI packet (LLA position, GPS week/TOW) for `now_ms`.
+    fn encode_aid_ini(&self, now_ms: u64, out: &mut [u8; AID_INI_PACKET_LEN]) -> Option<usize> {
+        let mut payload = [0u8; AID_INI_PAYLOAD_LEN];
+        let mut flags = AID_INI_FLAG_POS_VALID | AID_INI_FLAG_LLA;
+        let altitude = if self.altitude_m == ALTITUDE_UNKNOWN {
+            flags |= AID_INI_FLAG_ALT_INVALID;
+            0.0
+        } else {
+            self.altitude_m as f64
+        };
+        payload[0..8].copy_from_slice(&self.latitude.to_le_bytes());
+        payload[8..16].copy_from_slice(&self.longitude.to_le_bytes());
+        payload[16..24].copy_from_slice(&altitude.to_le_bytes());
+        if let Some(unix) = self.unix_at(now_ms) {
+            let gps_s = unix - GPS_EPOCH_UNIX_S + GPS_UTC_LEAP_SECONDS;
+            let tow = (gps_s % SECONDS_PER_WEEK) as f64;
+            let week = (gps_s / SECONDS_PER_WEEK) as u16;
+            payload[24..32].copy_from_slice(&tow.to_le_bytes());
+            payload[40..44].copy_from_slice(&PHONE_TIME_ACCURACY_S.to_le_bytes());
+            payload[52..54].copy_from_slice(&week.to_le_bytes());
+            flags |= AID_INI_FLAG_TIME_VALID;
+        }
+        // freqBias, fAcc and the timer source stay zero: no clock aiding.
+        payload[36..40].copy_from_slice(&(self.accuracy_m as f32).to_le_bytes());
+        payload[55] = flags;
+        encode_casic_packet(CASIC_CLASS_AID, CASIC_ID_AID_INI, &payload, out)
+    }
+}
+
+/// Store `location`; when `seed_gps` is set, also queue an AID-INI for the
+/// receiver. Returns whether the seed was queued.
+pub async fn set(location: PhoneLocation, seed_gps: bool) -> bool {
+    PHONE_LOCATION.lock(|cell| cell.set(Some(location)));
+    if location.unix_ts != 0 {
+        let time = PhoneTime {
+            unix_ts: location.unix_ts,
+            received_ms: location.received_ms,
+        };
+        PHONE_TIME.lock(|cell| cell.set(Some(time)));
+    }
+    defmt::info!(
+        "Phone location: acc {}m, time {}",
+        location.accuracy_m,
+        location.unix_ts
+    );
+    if !seed_gps {
+        return false;
+    }
+    let mut packet = [0u8; AID_INI_PACKET_LEN];
+    let Some(len) = location.encode_aid_ini(Instant::now().as_millis(), &mut packet) else {
+        return false;
+    };
+    match gps::queue_aid_ini(&packet[..len]).await {
+        Ok(()) => true,
+        Err(_) => {
+            defmt::warn!("Phone location: AID-INI not queued");
+            false
+        }
+    }
+}
+
+/// Recent phone position for display while the GPS has no fix.
+pub fn last_known() -> Option<PhoneLocation> {
+    let location = PHONE_LOCATION.lock(|cell| cell.get())?;
+    let age_ms = Instant::now().as_millis().saturating_sub(location.received_ms);
+    (age_ms <= LAST_KNOWN_MAX_AGE_MS).then_some(location)
+}
+
+/// Store the phone UTC time sent without a position. Returns false for a
+/// time before the GPS epoch.
+pub fn set_time(unix_ts: u32) -> bool {
+    if (unix_ts as u64) < GPS_EPOCH_UNIX_S {
+        return false;
+    }
+    let time = PhoneTime {
+        unix_ts,
+        received_ms: Instant::now().as_millis(),
+    };
+    PHONE_TIME.lock(|cell| cell.set(Some(time)));
+    defmt::info!("Phone time: {}", unix_ts);
+    true
+}
+
+/// Current UTC time extrapolated from the last phone timestamp.
+pub fn unix_now() -> Option<u64> {
+    let time = PHONE_TIME.lock(|cell| cell.get())?;
+    let elapsed_s = Instant::now().as_millis().saturating_sub(time.received_ms) / 1000;
+    Some(time.unix_ts as u64 + elapsed_s)
+}
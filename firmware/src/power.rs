@@ -0,0 +1,173 @@
+//! Power off into nRF SYSTEM OFF.
+//!
+//! Until now the tracker ran until the cell protection cut it out, which
+//! can lose the tail of the day in the SD cache. A critically low battery
+//! or a 10 s button hold now ends the session cleanly and parks the chip
+//! in SYSTEM OFF, where it draws a few microamps until the button (or, with
+//! the `accel-wake` feature, the accelerometer) wakes it. Waking from
+//! SYSTEM OFF is a reset, so the next boot is an ordinary cold start.
+//!
+//! # Design
+//!
+//! - `battery_task` feeds `CutoffMonitor`, which requests a shutdown once
+//!   the filtered voltage stayed below `power.cutoff_mv` for
+//!   `CUTOFF_HOLD_S`. Load dips while the GPS acquires are shorter than
+//!   that. Nothing happens on USB power, where the cell is charging.
+//! - `shut_down` first stops queuing track points, closes the open session
+//!   and flushes the SD cache, so nothing logged during the waits after it
+//!   is lost. It then stops connectable advertising, takes the advertising
+//!   slot from the background beacons, and turns the display off and the
+//!   GPS and 3V3 rail with it.
+//! - The rails are driven through the GPIO registers: their `Output`
+//!   drivers live in other tasks, and pin levels survive SYSTEM OFF.
+//! - The button pin wakes on a low level. The chip would wake at once if
+//!   the button were still down, so the hold is waited out first.
+//! - With `accel-wake`, the accelerometer latches motion onto INT1 (P0.04,
+//!   active high). The 3V3 rail stays on then, since the accelerometer
+//!   hangs off it; the GPS still goes off through its enable pin. If the
+//!   part is missing or does not take the setup, the rail goes off as
+//!   without the feature.
+
+use embassy_executor::task;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+use embassy_time::{with_timeout, Duration, Timer};
+use nrf_pac as pac;
+use nrf_softdevice::{raw, RawError};
+use pac::gpio::vals;
+
+use crate::accel;
+use crate::adv_scheduler::{AdvPriority, ADV_SCHEDULER};
+use crate::ble;
+use crate::display::{self, send_command, DisplayCommand};
+use crate::sessions;
+use crate::storage;
+
+/// Filtered voltage below `power.cutoff_mv` for this long powers off.
+const CUTOFF_HOLD_S: u64 = 60;
+/// Time for the power-off banner to be read.
+const BANNER_HOLD_MS: u64 = 1_500;
+/// Time for the background beacons to stop advertising.
+const ADV_RELEASE_TIMEOUT_MS: u64 = 1_000;
+const BUTTON_POLL_MS: u64 = 20;
+const ACCEL_WAKE: bool = cfg!(feature = "accel-wake");
+
+// Pin numbers of the board wiring (`board.rs`).
+const BUTTON_PIN: usize = 0; // P1.00
+const GPS_EN_PIN: usize = 24; // P0.24
+const V3V3_EN_PIN: usize = 13; // P0.13
+const ACCEL_INT_PIN: usize = 4; // P0.04
+
+static SHUTDOWN_REQUEST: Signal<CriticalSectionRawMutex, ShutdownReason> = Signal::new();
+
+#[derive(Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum ShutdownReason {
+    LowBattery,
+    Button,
+}
+
+pub fn request_shutdown(reason: ShutdownReason) {
+    SHUTDOWN_REQUEST.signal(reason);
+}
+
+/// Tracks how long the battery has been below the cutoff voltage.
+pub struct CutoffMonitor {
+    low_since_s: Option<u64>,
+}
+
+impl CutoffMonitor {
+    pub const fn new() -> Self {
+        Self { low_since_s: None }
+    }
+
+    /// True once the voltage stayed below `cutoff_mv` for `CUTOFF_HOLD_S`.
+    /// A `cutoff_mv` of 0 disables the cutoff.
+    pub fn note_voltage(
+        &mut self,
+        uptime_s: u64,
+        voltage_mv: f32,
+        cutoff_mv: i32,
+        on_usb: bool,
+    ) -> bool {
+        if cutoff_mv <= 0 || on_usb || voltage_mv >= cutoff_mv as f32 {
+            self.low_since_s = None;
+            return false;
+        }
+        let since = *self.low_since_s.get_or_insert(uptime_s);
+        uptime_s.saturating_sub(since) >= CUTOFF_HOLD_S
+    }
+}
+
+#[task]
+pub async fn power_task() {
+    let reason = SHUTDOWN_REQUEST.wait().await;
+    defmt::info!("Power off: {}", reason);
+    shut_down(reason).await;
+}
+
+async fn shut_down(reason: ShutdownReason) -> ! {
+    display::show_banner(match reason {
+        ShutdownReason::LowBattery => "Battery low: off",
+        ShutdownReason::Button => "Powering off",
+    });
+
+    storage::close_point_queue();
+    if !sessions::close_for_power_off().await {
+        defmt::warn!("Power off: closing the session failed");
+    }
+    if !storage::flush_sd_cache().await {
+        defmt::warn!("Power off: SD cache flush failed");
+    }
+
+    ble::stop_advertising();
+    // Let `ble_task` drop its advertising guard before claiming the slot.
+    Timer::after_millis(50).await;
+    let adv_guard = with_timeout(
+        Duration::from_millis(ADV_RELEASE_TIMEOUT_MS),
+        ADV_SCHEDULER.acquire(AdvPriority::MainAdv),
+    )
+    .await;
+    if adv_guard.is_err() {
+        defmt::warn!("Power off: advertising slot not released");
+    }
+
+    Timer::after_millis(BANNER_HOLD_MS).await;
+    send_command(DisplayCommand::TurnOff);
+
+    let accel_armed = ACCEL_WAKE && accel::arm_motion_wake().await;
+    // Time for the display task to send the panel to sleep.
+    Timer::after_millis(100).await;
+    pac::P0.outclr().write(|w| w.set_pin(GPS_EN_PIN, true));
+    if accel_armed {
+        sense_wake(
+            pac::P0,
+            ACCEL_INT_PIN,
+            vals::Pull::PULLDOWN,
+            vals::Sense::HIGH,
+        );
+    } else {
+        pac::P0.outclr().write(|w| w.set_pin(V3V3_EN_PIN, true));
+    }
+
+    while !pac::P1.in_().read().pin(BUTTON_PIN) {
+        Timer::after_millis(BUTTON_POLL_MS).await;
+    }
+    sense_wake(pac::P1, BUTTON_PIN, vals::Pull::PULLUP, vals::Sense::LOW);
+
+    defmt::info!("Entering SYSTEM OFF (accel wake: {})", accel_armed);
+    // Only returns on failure; under a debugger SYSTEM OFF is emulated.
+    let result = RawError::convert(unsafe { raw::sd_power_system_off() });
+    defmt::error!("SYSTEM OFF failed: {:?}", result);
+    loop {
+        cortex_m::asm::wfe();
+    }
+}
+
+fn sense_wake(port: pac::gpio::Gpio, pin: usize, pull: vals::Pull, sense: vals::Sense) {
+    port.pin_cnf(pin).write(|w| {
+        w.set_dir(vals::Dir::INPUT);
+        w.set_input(vals::Input::CONNECT);
+        w.set_pull(pull);
+        w.set_sense(sense);
+    });
+}
@@ -0,0 +1,255 @@
+//! INA219 / INA226 current monitors for power profiling builds.
+//!
+//! With the `power-monitor` feature, up to two current-sense amplifiers on
+//! the shared I2C bus measure the GPS rail and the whole system. Readings
+//! are shown on a display page and summarized per GPS state in
+//! `/POWER.CSV`, so the state machine timeouts can be tuned against measured
+//! current rather than datasheet figures.
+//!
+//! # Design
+//!
+//! - Both channels are probed at boot. An INA226 is recognized by its
+//!   manufacturer ID; any other device that answers is driven as an INA219.
+//!   A channel that does not answer is reported as missing.
+//! - Current is computed from the shunt voltage register and the known
+//!   shunt, so the calibration register is never programmed.
+//! - One sample per second. A log line closes every `LOG_INTERVAL_S` and on
+//!   every GPS state change, so each line covers a single state:
+//!   `uptime_s,gps_state,duration_s,gps_ua,sys_ua,sys_mv` (window averages,
+//!   `-1` for a missing channel).
+
+use embassy_embedded_hal::shared_bus::blocking::i2c::I2cDevice;
+use embassy_executor::task;
+use embassy_nrf::twim;
+use embassy_sync::blocking_mutex::raw::{CriticalSectionRawMutex, NoopRawMutex};
+use embassy_sync::mutex::Mutex;
+use embassy_time::{Instant, Timer};
+use embedded_hal::i2c::I2c;
+
+use crate::storage;
+use crate::system_info::{GpsState, SYSTEM_INFO};
+
+type SharedI2c = I2cDevice<'static, NoopRawMutex, twim::Twim<'static>>;
+
+const SAMPLE_INTERVAL_MS: u64 = 1000;
+const LOG_INTERVAL_S: u32 = 60;
+/// GPS supply rail (A0 = A1 = GND).
+const GPS_ADDR: u8 = 0x40;
+/// Battery-side system rail (A0 = VS).
+const SYS_ADDR: u8 = 0x41;
+/// Shunt resistance fitted on both channels.
+const SHUNT_MILLIOHM: i32 = 100;
+const GPS_STATE_COUNT: usize = 6;
+
+const REG_CONFIG: u8 = 0x00;
+const REG_SHUNT_VOLTAGE: u8 = 0x01;
+const REG_BUS_VOLTAGE: u8 = 0x02;
+const REG_MANUFACTURER_ID: u8 = 0xFE;
+const TI_MANUFACTURER_ID: u16 = 0x5449;
+/// 16 V range, gain /1 (+-40 mV), 12-bit bus, 128-sample shunt average,
+/// continuous.
+const INA219_CONFIG: u16 = 0x01FF;
+/// 128-sample average, 1.1 ms conversions, continuous shunt and bus.
+const INA226_CONFIG: u16 = 0x4927;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Chip {
+    Ina219,
+    Ina226,
+}
+
+struct Monitor {
+    addr: u8,
+    chip: Option<Chip>,
+}
+
+impl Monitor {
+    fn probe(i2c: &mut SharedI2c, addr: u8) -> Self {
+        let chip = match read_reg(i2c, addr, REG_MANUFACTURER_ID) {
+            Some(TI_MANUFACTURER_ID) => Some(Chip::Ina226),
+            Some(_) => Some(Chip::Ina219),
+            None => None,
+        };
+        let config = match chip {
+            Some(Chip::Ina219) => INA219_CONFIG,
+            Some(Chip::Ina226) => INA226_CONFIG,
+            None => return Self { addr, chip: None },
+        };
+        if !write_reg(i2c, addr, REG_CONFIG, config) {
+            defmt::warn!("Power: monitor 0x{:02x} config failed", addr);
+            return Self { addr, chip: None };
+        }
+        match chip {
+            Some(Chip::Ina219) => defmt::info!("Power: INA219 at 0x{:02x}", addr),
+            _ => defmt::info!("Power: INA226 at 0x{:02x}", addr),
+        }
+        Self { addr, chip }
+    }
+
+    fn current_ua(&self, i2c: &mut SharedI2c) -> Option<i32> {
+        // Shunt voltage LSB in nV; nV / mOhm = uA.
+        let lsb_nv = match self.chip? {
+            Chip::Ina219 => 10_000,
+            Chip::Ina226 => 2_500,
+        };
+        let raw = read_reg(i2c, self.addr, REG_SHUNT_VOLTAGE)? as i16 as i32;
+        Some(raw * lsb_nv / SHUNT_MILLIOHM)
+    }
+
+    fn bus_mv(&self, i2c: &mut SharedI2c) -> Option<u32> {
+        let chip = self.chip?;
+        let raw = read_reg(i2c, self.addr, REG_BUS_VOLTAGE)? as u32;
+        Some(match chip {
+            Chip::Ina219 => (raw >> 3) * 4,
+            Chip::Ina226 => raw * 5 / 4,
+        })
+    }
+}
+
+fn read_reg(i2c: &mut SharedI2c, addr: u8, reg: u8) -> Option<u16> {
+    let mut buf = [0u8; 2];
+    i2c.write_read(addr, &[reg], &mut buf).ok()?;
+    Some(u16::from_be_bytes(buf))
+}
+
+fn write_reg(i2c: &mut SharedI2c, addr: u8, reg: u8, value: u16) -> bool {
+    let [hi, lo] = value.to_be_bytes();
+    i2c.write(addr, &[reg, hi, lo]).is_ok()
+}
+
+#[derive(Clone, Copy)]
+pub struct PowerData {
+    pub gps_ua: Option<i32>,
+    pub sys_ua: Option<i32>,
+    pub sys_mv: Option<u32>,
+    pub gps_state: GpsState,
+    /// Mean currents over all time spent in `gps_state` since boot.
+    pub state_gps_ua: Option<i32>,
+    pub state_sys_ua: Option<i32>,
+}
+
+impl PowerData {
+    const fn new() -> Self {
+        Self {
+            gps_ua: None,
+            sys_ua: None,
+            sys_mv: None,
+            gps_state: GpsState::S0Initializing,
+            state_gps_ua: None,
+            state_sys_ua: None,
+        }
+    }
+}
+
+pub static POWER_DATA: Mutex<CriticalSectionRawMutex, PowerData> = Mutex::new(PowerData::new());
+
+/// Running sums of one quantity.
+#[derive(Clone, Copy, Default)]
+struct Mean {
+    sum: i64,
+    count: u32,
+}
+
+impl Mean {
+    fn add(&mut self, value: Option<i32>) {
+        if let Some(value) = value {
+            self.sum += value as i64;
+            self.count += 1;
+        }
+    }
+
+    fn get(&self) -> Option<i32> {
+        (self.count > 0).then(|| (self.sum / self.count as i64) as i32)
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct Totals {
+    gps: Mean,
+    sys: Mean,
+}
+
+struct Window {
+    state: GpsState,
+    start_s: u32,
+    gps: Mean,
+    sys: Mean,
+    mv: Mean,
+}
+
+impl Window {
+    fn new(state: GpsState, start_s: u32) -> Self {
+        Self {
+            state,
+            start_s,
+            gps: Mean::default(),
+            sys: Mean::default(),
+            mv: Mean::default(),
+        }
+    }
+
+    async fn log(&self, now_s: u32) {
+        if self.gps.count == 0 && self.sys.count == 0 {
+            return;
+        }
+        let ok = storage::append_power_log(
+            now_s,
+            self.state as u8,
+            now_s.saturating_sub(self.start_s),
+            self.gps.get().unwrap_or(-1),
+            self.sys.get().unwrap_or(-1),
+            self.mv.get().unwrap_or(-1),
+        )
+        .await;
+        if !ok {
+            defmt::warn!("Power: log append failed");
+        }
+    }
+}
+
+#[task]
+pub async fn power_monitor_task(mut i2c: SharedI2c) {
+    let gps = Monitor::probe(&mut i2c, GPS_ADDR);
+    let sys = Monitor::probe(&mut i2c, SYS_ADDR);
+    if gps.chip.is_none() && sys.chip.is_none() {
+        defmt::warn!("Power: no current monitor found");
+        return;
+    }
+
+    let mut totals = [Totals::default(); GPS_STATE_COUNT];
+    let mut window = Window::new(GpsState::S0Initializing, 0);
+
+    loop {
+        let now_s = Instant::now().as_secs() as u32;
+        let state = SYSTEM_INFO.lock().await.gps_state;
+        if state != window.state || now_s.saturating_sub(window.start_s) >= LOG_INTERVAL_S {
+            window.log(now_s).await;
+            window = Window::new(state, now_s);
+        }
+
+        let gps_ua = gps.current_ua(&mut i2c);
+        let sys_ua = sys.current_ua(&mut i2c);
+        let sys_mv = sys.bus_mv(&mut i2c);
+        window.gps.add(gps_ua);
+        window.sys.add(sys_ua);
+        window.mv.add(sys_mv.map(|mv| mv as i32));
+
+        let state_totals = &mut totals[state as usize];
+        state_totals.gps.add(gps_ua);
+        state_totals.sys.add(sys_ua);
+
+        {
+            let mut data = POWER_DATA.lock().await;
+            *data = PowerData {
+                gps_ua,
+                sys_ua,
+                sys_mv,
+                gps_state: state,
+                state_gps_ua: state_totals.gps.get(),
+                state_sys_ua: state_totals.sys.get(),
+            };
+        }
+
+        Timer::after_millis(SAMPLE_INTERVAL_MS).await;
+    }
+}
@@ -0,0 +1,141 @@
+//! GPS time-pulse (PPS) discipline of the monotonic clock.
+//!
+//! The receiver's PPS output marks the start of each UTC second. A GPIOTE
+//! input channel timestamps every rising edge against `Instant`, and the
+//! UTC second is taken from the NMEA time already in `SYSTEM_INFO`. The
+//! resulting anchor, together with a filtered estimate of the local clock
+//! drift (the LFCLK runs from the internal RC oscillator, +/-500 ppm), lets
+//! other tasks read UTC with sub-millisecond precision between pulses.
+//!
+//! # Design
+//!
+//! - NMEA for second `S` arrives after the pulse for `S`, so a pulse is
+//!   labelled `last_nmea_second + 1`.
+//! - Consecutive pulses must be ~1 s apart; outliers reset the lock.
+//! - `precise_unix_ms()` returns `None` unless the anchor is fresh, so
+//!   callers always fall back to plain NMEA time without PPS hardware.
+
+use core::cell::Cell;
+
+use embassy_executor::task;
+use embassy_nrf::gpiote::InputChannel;
+use embassy_sync::blocking_mutex::{raw::CriticalSectionRawMutex, Mutex as CsMutex};
+use embassy_time::Instant;
+
+use crate::system_info::SYSTEM_INFO;
+use crate::timezone::date_time_to_unix_timestamp;
+
+const MICROS_PER_SEC: i64 = 1_000_000;
+/// Accept pulse intervals within this window of 1 s (covers RC drift + IRQ latency).
+const PULSE_TOLERANCE_US: i64 = 5_000;
+/// Stop trusting the anchor when pulses have not been seen for this long.
+const ANCHOR_STALE_US: u64 = 10 * MICROS_PER_SEC as u64;
+/// Pulses required before the drift estimate is used.
+const LOCK_PULSES: u8 = 4;
+/// EMA weight of a new drift sample (1 / 2^DRIFT_SHIFT).
+const DRIFT_SHIFT: u32 = 3;
+
+#[derive(Clone, Copy)]
+struct PpsAnchor {
+    unix_ts: u32,
+    local_us: u64,
+    /// Local clock drift in parts per million, scaled by 2^DRIFT_SHIFT.
+    drift_ppm_scaled: i32,
+    locked_pulses: u8,
+}
+
+static PPS_ANCHOR: CsMutex<CriticalSectionRawMutex, Cell<Option<PpsAnchor>>> =
+    CsMutex::new(Cell::new(None));
+
+/// Current UTC time in milliseconds from the PPS-disciplined clock,
+/// or `None` while PPS is absent, unlocked or stale.
+pub fn precise_unix_ms() -> Option<u64> {
+    let anchor = PPS_ANCHOR.lock(|cell| cell.get())?;
+    if anchor.locked_pulses < LOCK_PULSES {
+        return None;
+    }
+    let elapsed_local = Instant::now().as_micros().checked_sub(anchor.local_us)?;
+    if elapsed_local > ANCHOR_STALE_US {
+        return None;
+    }
+    let drift_ppm = (anchor.drift_ppm_scaled >> DRIFT_SHIFT) as i64;
+    let correction = elapsed_local as i64 * drift_ppm / MICROS_PER_SEC;
+    let elapsed_utc_us = (elapsed_local as i64 - correction).max(0) as u64;
+    Some(anchor.unix_ts as u64 * 1000 + elapsed_utc_us / 1000)
+}
+
+async fn last_nmea_unix_ts() -> Option<u32> {
+    let info = *SYSTEM_INFO.lock().await;
+    if !info.date_time_valid {
+        return None;
+    }
+    date_time_to_unix_timestamp(
+        info.year,
+        info.month,
+        info.day,
+        info.hour,
+        info.minute,
+        info.second,
+    )
+}
+
+fn next_anchor(prev: Option<PpsAnchor>, unix_ts: u32, local_us: u64) -> PpsAnchor {
+    let fresh = PpsAnchor {
+        unix_ts,
+        local_us,
+        drift_ppm_scaled: prev.map(|p| p.drift_ppm_scaled).unwrap_or(0),
+        locked_pulses: 1,
+    };
+    let Some(prev) = prev else {
+        return fresh;
+    };
+
+    let secs = unix_ts.wrapping_sub(prev.unix_ts) as i64;
+    let interval_us = local_us.wrapping_sub(prev.local_us) as i64;
+    if !(1..=8).contains(&secs)
+        || (interval_us - secs * MICROS_PER_SEC).abs() > PULSE_TOLERANCE_US * secs
+    {
+        defmt::warn!("PPS: pulse interval {}us for {}s, relocking", interval_us, secs);
+        return fresh;
+    }
+
+    // Positive drift: local clock runs fast.
+    let sample_ppm = ((interval_us - secs * MICROS_PER_SEC) / secs) as i32;
+    let drift_ppm_scaled = if prev.locked_pulses == 1 {
+        sample_ppm << DRIFT_SHIFT
+    } else {
+        prev.drift_ppm_scaled + sample_ppm - (prev.drift_ppm_scaled >> DRIFT_SHIFT)
+    };
+    PpsAnchor {
+        unix_ts,
+        local_us,
+        drift_ppm_scaled,
+        locked_pulses: prev.locked_pulses.saturating_add(1),
+    }
+}
+
+#[task]
+pub async fn pps_task(mut pulse: InputChannel<'static>) {
+    defmt::info!("PPS: task started");
+    loop {
+        pulse.wait().await;
+        let local_us = Instant::now().as_micros();
+
+        let Some(nmea_ts) = last_nmea_unix_ts().await else {
+            PPS_ANCHOR.lock(|cell| cell.set(None));
+            continue;
+        };
+        let unix_ts = nmea_ts.wrapping_add(1);
+
+        let prev = PPS_ANCHOR.lock(|cell| cell.get());
+        let prev = prev.filter(|p| local_us.wrapping_sub(p.local_us) <= ANCHOR_STALE_US);
+        let anchor = next_anchor(prev, unix_ts, local_us);
+        if anchor.locked_pulses == LOCK_PULSES {
+            defmt::info!(
+                "PPS: locked, drift={}ppm",
+                anchor.drift_ppm_scaled >> DRIFT_SHIFT
+            );
+        }
+        PPS_ANCHOR.lock(|cell| cell.set(Some(anchor)));
+    }
+}
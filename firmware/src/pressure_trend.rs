@@ -0,0 +1,264 @@
+//! Barometric pressure tendency and storm warning.
+//!
+//! A falling barometer is the one weather forecast a hiker off-grid gets.
+//! The BMP280 pressure is averaged into `BUCKET_S` buckets over the last
+//! `TREND_BUCKETS`, and the change over three hours says whether the
+//! pressure is rising, steady or falling; a fast enough fall raises the
+//! storm alert.
+//!
+//! # Design
+//!
+//! - Buckets are indexed by uptime, like the battery history, and the
+//!   history starts over on reboot. `/PRESSURE.CSV` keeps an hourly line for
+//!   longer records.
+//! - Climbing 100 m lowers the pressure by about 12 hPa, far more than the
+//!   weather does. Each bucket also averages the GPS altitude of its fixes,
+//!   and the older pressure is moved to the newer altitude with the
+//!   barometric formula before comparing. A bucket without fixes is taken to
+//!   be at the same altitude.
+//! - The change is the newest bucket against the one three hours before,
+//!   or the oldest one at least an hour back, scaled to three hours.
+//!   Within `STEADY_HPA` it is steady.
+//! - The storm alert fires once when the fall reaches the threshold and
+//!   re-arms when it has eased to half of it.
+
+pub const TREND_BUCKETS: usize = 36;
+pub const BUCKET_S: u64 = 600;
+/// Three hours, the standard barometric tendency.
+const SPAN_BUCKETS: u64 = 18;
+/// Shortest history a change is reported for.
+const MIN_SPAN_BUCKETS: u64 = 6;
+/// Change over three hours below which the pressure is steady.
+const STEADY_HPA: f32 = 1.0;
+/// Scale height of the standard atmosphere, metres.
+const SCALE_HEIGHT_M: f32 = 8_434.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tendency {
+    Rising,
+    Steady,
+    Falling,
+}
+
+impl Tendency {
+    pub fn from_change(change_hpa: f32) -> Self {
+        if change_hpa >= STEADY_HPA {
+            Self::Rising
+        } else if change_hpa <= -STEADY_HPA {
+            Self::Falling
+        } else {
+            Self::Steady
+        }
+    }
+
+    /// Name in `/PRESSURE.CSV`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Rising => "rising",
+            Self::Steady => "steady",
+            Self::Falling => "falling",
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Bucket {
+    number: u64,
+    pressure_sum: f32,
+    count: u32,
+    altitude_sum: f32,
+    altitude_count: u32,
+}
+
+impl Bucket {
+    const EMPTY: Self = Self {
+        number: 0,
+        pressure_sum: 0.0,
+        count: 0,
+        altitude_sum: 0.0,
+        altitude_count: 0,
+    };
+
+    fn pressure_pa(&self) -> f32 {
+        self.pressure_sum / self.count as f32
+    }
+
+    fn altitude_m(&self) -> Option<f32> {
+        (self.altitude_count > 0).then(|| self.altitude_sum / self.altitude_count as f32)
+    }
+}
+
+pub struct PressureTrend {
+    buckets: [Bucket; TREND_BUCKETS],
+    /// Number of the newest bucket, `None` before the first sample.
+    newest: Option<u64>,
+    storm_alerted: bool,
+}
+
+impl PressureTrend {
+    pub const fn new() -> Self {
+        Self {
+            buckets: [Bucket::EMPTY; TREND_BUCKETS],
+            newest: None,
+            storm_alerted: false,
+        }
+    }
+
+    /// Add a pressure sample taken at `uptime_s`, with the GPS altitude if
+    /// there is a fix.
+    pub fn note(&mut self, uptime_s: u64, pressure_pa: f32, gps_altitude_m: Option<f32>) {
+        let number = uptime_s / BUCKET_S;
+        if pressure_pa <= 0.0 || self.newest.is_some_and(|newest| number < newest) {
+            return;
+        }
+        self.newest = Some(number);
+        let bucket = &mut self.buckets[number as usize % TREND_BUCKETS];
+        if bucket.number != number {
+            *bucket = Bucket {
+                number,
+                ..Bucket::EMPTY
+            };
+        }
+        bucket.pressure_sum += pressure_pa;
+        bucket.count += 1;
+        if let Some(altitude_m) = gps_altitude_m {
+            bucket.altitude_sum += altitude_m;
+            bucket.altitude_count += 1;
+        }
+    }
+
+    fn bucket(&self, number: u64) -> Option<&Bucket> {
+        let bucket = &self.buckets[number as usize % TREND_BUCKETS];
+        (bucket.count > 0 && bucket.number == number).then_some(bucket)
+    }
+
+    /// Pressure change over three hours in hPa, at the newest bucket's
+    /// altitude; `None` with less than an hour of history.
+    pub fn change_3h_hpa(&self) -> Option<f32> {
+        let newest_number = self.newest?;
+        let newest = self.bucket(newest_number)?;
+        let (span, oldest) = (MIN_SPAN_BUCKETS..=SPAN_BUCKETS)
+            .rev()
+            .filter(|&span| span <= newest_number)
+            .find_map(|span| Some((span, self.bucket(newest_number - span)?)))?;
+        let mut old_pa = oldest.pressure_pa();
+        if let (Some(old_m), Some(new_m)) = (oldest.altitude_m(), newest.altitude_m()) {
+            // exp(-dh / H) as its Pade approximant; the error is far below
+            // that of the GPS altitude for any climb on foot.
+            let climb_m = new_m - old_m;
+            old_pa *= (2.0 * SCALE_HEIGHT_M - climb_m) / (2.0 * SCALE_HEIGHT_M + climb_m);
+        }
+        let change_hpa = (newest.pressure_pa() - old_pa) / 100.0;
+        Some(change_hpa * SPAN_BUCKETS as f32 / span as f32)
+    }
+
+    /// Whether to raise the storm alert now, for a fall of `threshold_hpa`
+    /// over three hours; `0` never alerts.
+    pub fn storm_alert(&mut self, threshold_hpa: f32) -> bool {
+        let Some(change_hpa) = self.change_3h_hpa() else {
+            return false;
+        };
+        if threshold_hpa <= 0.0 || change_hpa > -threshold_hpa / 2.0 {
+            self.storm_alerted = false;
+            return false;
+        }
+        if change_hpa > -threshold_hpa || self.storm_alerted {
+            return false;
+        }
+        self.storm_alerted = true;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    impl PressureTrend {
+        fn tendency(&self) -> Option<Tendency> {
+            self.change_3h_hpa().map(Tendency::from_change)
+        }
+    }
+
+    /// A minute of samples every minute from `from_min` to `to_min`, with the
+    /// pressure from `pressure(minute)` and an optional altitude.
+    fn run(
+        trend: &mut PressureTrend,
+        from_min: u64,
+        to_min: u64,
+        pressure: impl Fn(u64) -> f32,
+        altitude_m: Option<f32>,
+    ) {
+        for minute in from_min..to_min {
+            trend.note(minute * 60, pressure(minute), altitude_m);
+        }
+    }
+
+    #[test]
+    fn falling_pressure_reports_a_tendency_and_one_storm_alert() {
+        let mut trend = PressureTrend::new();
+        run(&mut trend, 0, 50, |_| 101_300.0, None);
+        // Under an hour of history.
+        assert_eq!(trend.change_3h_hpa(), None);
+        run(&mut trend, 50, 70, |_| 101_300.0, None);
+        assert_eq!(trend.tendency(), Some(Tendency::Steady));
+        assert!(!trend.storm_alert(4.0));
+
+        // 2 hPa an hour from here on.
+        let falling = |minute: u64| 101_300.0 - (minute - 70) as f32 * 200.0 / 60.0;
+        run(&mut trend, 70, 130, falling, None);
+        assert_eq!(trend.tendency(), Some(Tendency::Falling));
+        // Falling, but not fast enough for a storm yet.
+        assert!(!trend.storm_alert(4.0));
+        run(&mut trend, 130, 250, falling, None);
+        let change = trend.change_3h_hpa().unwrap();
+        assert!((change + 6.0).abs() < 0.5, "{}", change);
+        assert!(trend.storm_alert(4.0));
+        // Once per fall.
+        assert!(!trend.storm_alert(4.0));
+        assert!(!trend.storm_alert(0.0));
+
+        // Steady again: re-armed, and a new fall alerts again.
+        let low = falling(250);
+        run(&mut trend, 250, 450, |_| low, None);
+        assert_eq!(trend.tendency(), Some(Tendency::Steady));
+        assert!(!trend.storm_alert(4.0));
+        run(
+            &mut trend,
+            450,
+            650,
+            |minute| low - (minute - 450) as f32 * 4.0,
+            None,
+        );
+        assert!(trend.storm_alert(4.0));
+        assert_eq!(Tendency::Falling.name(), "falling");
+    }
+
+    #[test]
+    fn a_climb_is_not_mistaken_for_weather() {
+        let mut trend = PressureTrend::new();
+        // Walking up 300 m in two hours under steady weather: the barometer
+        // drops by ~35 hPa.
+        let station_pa = |altitude_m: f32| 101_300.0 * (1.0 - altitude_m / 44_330.0).powf(5.255);
+        for minute in 0..200 {
+            let altitude_m = (minute.min(120) as f32) * 2.5;
+            trend.note(minute * 60, station_pa(altitude_m), Some(altitude_m));
+        }
+        let change = trend.change_3h_hpa().unwrap();
+        assert!(change.abs() < 1.0, "{}", change);
+        assert_eq!(trend.tendency(), Some(Tendency::Steady));
+
+        // The same climb without fixes looks like a storm.
+        let mut blind = PressureTrend::new();
+        for minute in 0..200 {
+            let altitude_m = (minute.min(120) as f32) * 2.5;
+            blind.note(minute * 60, station_pa(altitude_m), None);
+        }
+        assert!(blind.change_3h_hpa().unwrap() < -20.0);
+
+        // Old samples and bad readings are ignored.
+        trend.note(0, 50_000.0, None);
+        trend.note(200 * 60, 0.0, None);
+        assert!(trend.change_3h_hpa().unwrap().abs() < 1.0);
+    }
+}
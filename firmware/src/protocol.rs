@@ -1,13 +1,52 @@
+use heapless::Deque;
+
+use crate::agnss_import::{gps_ephemeris_from_lnav, FrameSplitter, LNAV_EPHEMERIS_LEN};
 use crate::battery;
+use crate::battery_history::{HistorySlot, HISTORY_RECORD_LEN, SLOT_S};
+use crate::ble;
 use crate::bmp280;
+use crate::bonds::MAX_BONDS;
+use crate::crc32::{self, Crc32};
+use crate::diag;
+use crate::features;
+use crate::file_jobs;
 #[cfg(feature = "findmy")]
 use crate::findmy;
+use crate::fix_stats::FIX_DAY_LEN;
+use crate::flash_ring::RingPoint;
+use crate::flash_track;
+use crate::geofences;
 #[cfg(feature = "google-fmdn")]
 use crate::google_fmdn;
+use crate::gpio_hooks;
 use crate::gps;
 use crate::gps::AgnssMessage;
-use crate::storage;
-use crate::system_info::{serialize_system_info, SYSTEM_INFO, SYSTEM_INFO_SERIALIZED_LEN};
+use crate::gpz::{GpzDecoder, TrackPoint};
+use crate::guest;
+use crate::live_track;
+#[cfg(feature = "lora")]
+use crate::lorawan;
+use crate::phone_location::{self, PhoneLocation};
+use crate::markers::{self, MARKER_RECORD_SIZE};
+use crate::recording;
+use crate::sessions;
+use crate::settings;
+use crate::slots::{self, UpdateStatus};
+use crate::solar;
+#[cfg(feature = "buzzer")]
+use crate::sound;
+use crate::status_schema;
+use crate::storage::{self, LogFileAction};
+use crate::system_info::{
+    serialize_system_info, SYSTEM_INFO, SYSTEM_INFO_SERIALIZED_LEN, SYSTEM_INFO_VERSION,
+};
+use crate::timezone::{self, TzSettings};
+use crate::track_decimate::{DecimateMode, Decimator};
+use crate::track_stats;
+use crate::transfer_seq::{SentChunk, TransferSequence};
+use crate::vibration;
+#[cfg(feature = "nav")]
+use crate::waypoints;
 
 const CMD_LIST_DIR: u8 = 0x01;
 const CMD_OPEN_FILE: u8 = 0x02;
@@ -32,13 +71,98 @@ const CMD_WRITE_FMDN_EIK: u8 = 0x0F;
 const CMD_READ_FMDN_EIK: u8 = 0x10;
 #[cfg(feature = "google-fmdn")]
 const CMD_GET_FMDN_STATUS: u8 = 0x11;
+const CMD_SET_GPIO_HOOK: u8 = 0x12;
+const CMD_GET_GPIO_HOOKS: u8 = 0x13;
+#[cfg(feature = "nav")]
+const CMD_ADD_WAYPOINT: u8 = 0x14;
+#[cfg(feature = "nav")]
+const CMD_LIST_WAYPOINTS: u8 = 0x15;
+#[cfg(feature = "nav")]
+const CMD_UPDATE_WAYPOINT: u8 = 0x16;
+#[cfg(feature = "nav")]
+const CMD_DELETE_WAYPOINT: u8 = 0x17;
+const CMD_SET_TIMEZONE: u8 = 0x18;
+const CMD_GET_TIMEZONE: u8 = 0x19;
+const CMD_RECORDING_CONTROL: u8 = 0x1A;
+const CMD_SET_RECORDING_CONFIG: u8 = 0x1B;
+const CMD_SESSION_START: u8 = 0x1C;
+const CMD_SESSION_CONTROL: u8 = 0x1D;
+const CMD_LIST_SESSIONS: u8 = 0x1E;
+const CMD_VIBRATION_CAPTURE: u8 = 0x1F;
+const CMD_GET_DIAGNOSTICS: u8 = 0x20;
+const CMD_HELLO: u8 = 0x21;
+#[cfg(feature = "lora")]
+const CMD_SET_LORA_CONFIG: u8 = 0x22;
+#[cfg(feature = "lora")]
+const CMD_GET_LORA_STATUS: u8 = 0x23;
+const CMD_SET_PHONE_LOCATION: u8 = 0x24;
+#[cfg(feature = "nav")]
+const CMD_SET_FAVORITE_WAYPOINT: u8 = 0x25;
+const CMD_GET_TODAY_STATS: u8 = 0x26;
+const CMD_GUEST_MODE: u8 = 0x27;
+const CMD_BULK_LOG_FILES: u8 = 0x28;
+const CMD_READ_DECIMATED: u8 = 0x29;
+const CMD_SETTINGS: u8 = 0x2A;
+const CMD_ADD_MARKER: u8 = 0x2B;
+const CMD_SET_GEOFENCE: u8 = 0x2C;
+const CMD_LIST_GEOFENCES: u8 = 0x2D;
+const CMD_DELETE_GEOFENCE: u8 = 0x2E;
+const CMD_SESSION_META: u8 = 0x2F;
+const CMD_RELAY_RESUME: u8 = 0x30;
+const CMD_READ_WINDOW: u8 = 0x31;
+const CMD_RESUME_FILE: u8 = 0x32;
+const CMD_GET_SYS_INFO_SCHEMA: u8 = 0x33;
+const CMD_SET_TIME: u8 = 0x34;
+const CMD_WRITE_AGNSS_STREAM: u8 = 0x35;
+const CMD_GET_FIX_STATS: u8 = 0x36;
+const CMD_GET_KEEP_ALIVE: u8 = 0x37;
+const CMD_RETRANSMIT: u8 = 0x38;
+const CMD_READ_FLASH_TRACK: u8 = 0x39;
+#[cfg(feature = "buzzer")]
+const CMD_RING: u8 = 0x3A;
+const CMD_BONDS: u8 = 0x3B;
+const CMD_BATTERY_HISTORY: u8 = 0x3C;
+const CMD_WRITE_AGNSS_LNAV: u8 = 0x3D;
+const CMD_FIRMWARE: u8 = 0x3E;
 
 const MAX_CMD_PAYLOAD: usize = 570;
 const MAX_RESPONSE_PAYLOAD: usize = 256;
 const MAX_RESPONSE_LEN: usize = 2 + MAX_RESPONSE_PAYLOAD;
 const READ_CHUNK_MAX_DATA: usize = 254;
+// [offset: 4B][len: 2B][crc32: 4B] + data fits the 256-byte response payload.
+const WINDOW_FRAME_HEADER: usize = 10;
+const WINDOW_CHUNK_MAX_DATA: usize = MAX_RESPONSE_PAYLOAD - WINDOW_FRAME_HEADER;
+const WINDOW_MAX_CHUNKS: u8 = 16;
+// Sequenced frames put [seq: 2B] in front of the same header.
+const SEQ_FRAME_HEADER: usize = 2 + WINDOW_FRAME_HEADER;
+const SEQ_CHUNK_MAX_DATA: usize = MAX_RESPONSE_PAYLOAD - SEQ_FRAME_HEADER;
+const WINDOW_FLAG_SEQUENCED: u8 = 0x01;
+// `len` of a frame whose chunk could not be read; it carries no data.
+const READ_ERROR_LEN: u16 = 0xFFFF;
+// Bound the SD time one RESUME_FILE spends checking the received tail.
+const RESUME_TAIL_MAX: u32 = 4096;
 const LIST_DIR_RESPONSE_MAX: usize = 128;
 const MAX_AGNSS_MESSAGES: usize = 70;
+#[cfg(feature = "buzzer")]
+const RING_MAX_S: u16 = 600;
+#[cfg(feature = "nav")]
+const WAYPOINT_LIST_MAX_ENTRIES: usize = 7;
+#[cfg(feature = "nav")]
+const WAYPOINT_LIST_END: u8 = 0xFF;
+// 5 * (slot + 48B record) fits the 256-byte response payload.
+const SESSION_LIST_MAX_ENTRIES: usize = 5;
+const SESSION_LIST_END: u8 = 0xFF;
+// 7 * (slot + 32B record) fits the 256-byte response payload.
+const GEOFENCE_LIST_MAX_ENTRIES: usize = 7;
+const GEOFENCE_LIST_END: u8 = 0xFF;
+const DECIMATE_POINT_LEN: usize = 16;
+// [flags][count] + 15 * 16B points fits the 256-byte response payload.
+const DECIMATE_MAX_POINTS: usize = 15;
+const DECIMATE_READ_CHUNK: usize = 128;
+// Bound the SD time one READ_DECIMATED spends on heavily decimated tracks.
+const DECIMATE_SCAN_BUDGET: usize = 4096;
+// [age_s][count] + 48 * 5B slots fits the 256-byte response payload.
+const BATTERY_HISTORY_MAX_SLOTS: usize = 48;
 
 #[derive(Clone, Copy)]
 enum CommandState {
@@ -59,6 +183,42 @@ pub struct FileTransferProtocol {
     agnss_messages: [AgnssMessage; MAX_AGNSS_MESSAGES],
     agnss_len: usize,
     agnss_write_in_progress: bool,
+    /// Frame splitter for `WRITE_AGNSS_STREAM`, set while a write is open.
+    agnss_stream: Option<FrameSplitter>,
+    decimate: Option<DecimateStream>,
+    window: Option<ReadWindow>,
+    sequence: TransferSequence,
+    /// Frames asked for by `RETRANSMIT`, sent before any window.
+    resend: Deque<SentChunk, { WINDOW_MAX_CHUNKS as usize }>,
+}
+
+/// Position of a `READ_DECIMATED` stream in the open transfer file.
+struct DecimateStream {
+    decoder: GpzDecoder,
+    decimator: Decimator,
+    offset: u32,
+    done: bool,
+}
+
+impl DecimateStream {
+    const fn new(mode: DecimateMode) -> Self {
+        Self {
+            decoder: GpzDecoder::new(),
+            decimator: Decimator::new(mode),
+            offset: 0,
+            done: false,
+        }
+    }
+}
+
+/// Chunks of a `READ_WINDOW` still to be sent.
+struct ReadWindow {
+    offset: u32,
+    chunk_len: usize,
+    remaining: u8,
+    /// Frames carry a sequence number, and the end-of-file frame the CRC of
+    /// the whole file.
+    sequenced: bool,
 }
 
 impl FileTransferProtocol {
@@ -73,6 +233,11 @@ impl FileTransferProtocol {
             agnss_messages: [AgnssMessage::empty(); MAX_AGNSS_MESSAGES],
             agnss_len: 0,
             agnss_write_in_progress: false,
+            agnss_stream: None,
+            decimate: None,
+            window: None,
+            sequence: TransferSequence::new(),
+            resend: Deque::new(),
         }
     }
 
@@ -80,6 +245,111 @@ impl FileTransferProtocol {
         &self.response[..len]
     }
 
+    /// The next frame of a `READ_WINDOW`, after the command's own response.
+    /// Returns the response length like `push_byte` until the window is done.
+    pub async fn next_frame(&mut self) -> Option<usize> {
+        if let Some(chunk) = self.resend.pop_front() {
+            return Some(self.resend_frame(chunk).await);
+        }
+        let window = self.window.as_mut()?;
+        let offset = window.offset;
+        let chunk_len = window.chunk_len;
+        let sequenced = window.sequenced;
+        window.remaining -= 1;
+        let mut data = [0u8; WINDOW_CHUNK_MAX_DATA];
+        let read = storage::read_file(offset, &mut data[..chunk_len]).await;
+        let actual = read.unwrap_or(0);
+        if actual == 0 || window.remaining == 0 {
+            // End of file (sent as an empty frame), read error, or window full.
+            self.window = None;
+        } else {
+            window.offset = offset + actual as u32;
+        }
+        if read.is_err() {
+            return Some(self.read_error_frame(sequenced, offset, chunk_len as u16));
+        }
+        if !sequenced {
+            self.response[2..6].copy_from_slice(&offset.to_le_bytes());
+            self.response[6..8].copy_from_slice(&(actual as u16).to_le_bytes());
+            self.response[8..12].copy_from_slice(&crc32::crc32(&data[..actual]).to_le_bytes());
+            self.response[12..12 + actual].copy_from_slice(&data[..actual]);
+            return Some(self.encode_response(WINDOW_FRAME_HEADER + actual));
+        }
+        if actual == 0 {
+            return Some(self.end_of_file_frame().await);
+        }
+        let data = &data[..actual];
+        let seq = self.sequence.record(offset, data);
+        Some(self.encode_seq_frame(seq, offset, data, crc32::crc32(data)))
+    }
+
+    /// A frame again under a new sequence number, read back from the file.
+    async fn resend_frame(&mut self, chunk: SentChunk) -> usize {
+        let mut data = [0u8; SEQ_CHUNK_MAX_DATA];
+        let len = chunk.len as usize;
+        let read = match len {
+            0 => Ok(0),
+            _ => storage::read_file(chunk.offset, &mut data[..len]).await,
+        };
+        let Ok(read) = read else {
+            return self.read_error_frame(true, chunk.offset, chunk.len);
+        };
+        let data = &data[..read];
+        if data.is_empty() {
+            return self.end_of_file_frame().await;
+        }
+        let seq = self.sequence.record(chunk.offset, data);
+        self.encode_seq_frame(seq, chunk.offset, data, crc32::crc32(data))
+    }
+
+    /// The empty frame that ends a sequenced transfer: its offset is the file
+    /// size and its CRC that of the whole file, reading back whatever the
+    /// running CRC missed. A read error sends an error frame instead.
+    async fn end_of_file_frame(&mut self) -> usize {
+        let mut data = [0u8; READ_CHUNK_MAX_DATA];
+        loop {
+            let through = self.sequence.crc_through();
+            let Ok(n) = storage::read_file(through, &mut data).await else {
+                return self.read_error_frame(true, through, 0);
+            };
+            if n == 0 {
+                break;
+            }
+            self.sequence.absorb(through, &data[..n]);
+        }
+        let size = self.sequence.crc_through();
+        let seq = self.sequence.record(size, &[]);
+        let crc = self.sequence.file_crc();
+        self.encode_seq_frame(seq, size, &[], crc)
+    }
+
+    /// A frame saying the `len` bytes at `offset` could not be read. A
+    /// sequenced one is remembered as that chunk, so `RETRANSMIT` reads it
+    /// again.
+    fn read_error_frame(&mut self, sequenced: bool, offset: u32, len: u16) -> usize {
+        defmt::warn!("READ_WINDOW: read failed at offset {}", offset);
+        let (start, payload_len) = if sequenced {
+            let seq = self.sequence.record_unread(offset, len);
+            self.response[2..4].copy_from_slice(&seq.to_le_bytes());
+            (4, SEQ_FRAME_HEADER)
+        } else {
+            (2, WINDOW_FRAME_HEADER)
+        };
+        self.response[start..start + 4].copy_from_slice(&offset.to_le_bytes());
+        self.response[start + 4..start + 6].copy_from_slice(&READ_ERROR_LEN.to_le_bytes());
+        self.response[start + 6..start + 10].fill(0);
+        self.encode_response(payload_len)
+    }
+
+    fn encode_seq_frame(&mut self, seq: u16, offset: u32, data: &[u8], crc: u32) -> usize {
+        self.response[2..4].copy_from_slice(&seq.to_le_bytes());
+        self.response[4..8].copy_from_slice(&offset.to_le_bytes());
+        self.response[8..10].copy_from_slice(&(data.len() as u16).to_le_bytes());
+        self.response[10..14].copy_from_slice(&crc.to_le_bytes());
+        self.response[14..14 + data.len()].copy_from_slice(data);
+        self.encode_response(SEQ_FRAME_HEADER + data.len())
+    }
+
     pub async fn push_byte(&mut self, byte: u8) -> Option<usize> {
         match self.cmd_state {
             CommandState::WaitCmdId => {
@@ -147,6 +417,8 @@ impl FileTransferProtocol {
             CMD_START_AGNSS_WRITE => self.handle_start_agnss_write(),
             CMD_WRITE_AGNSS_CHUNK => self.handle_write_agnss_chunk(payload),
             CMD_END_AGNSS_WRITE => self.handle_end_agnss_write().await,
+            CMD_WRITE_AGNSS_STREAM => self.handle_write_agnss_stream(payload),
+            CMD_WRITE_AGNSS_LNAV => self.handle_write_agnss_lnav(payload),
             CMD_GPS_WAKEUP => self.handle_gps_wakeup().await,
             CMD_GPS_KEEP_ALIVE => self.handle_gps_keep_alive(payload).await,
             #[cfg(feature = "findmy")]
@@ -161,6 +433,57 @@ impl FileTransferProtocol {
             CMD_READ_FMDN_EIK => self.handle_read_fmdn_eik().await,
             #[cfg(feature = "google-fmdn")]
             CMD_GET_FMDN_STATUS => self.handle_get_fmdn_status().await,
+            CMD_SET_GPIO_HOOK => self.handle_set_gpio_hook(payload).await,
+            CMD_GET_GPIO_HOOKS => self.handle_get_gpio_hooks().await,
+            #[cfg(feature = "nav")]
+            CMD_ADD_WAYPOINT => self.handle_add_waypoint(payload).await,
+            #[cfg(feature = "nav")]
+            CMD_LIST_WAYPOINTS => self.handle_list_waypoints(payload).await,
+            #[cfg(feature = "nav")]
+            CMD_UPDATE_WAYPOINT => self.handle_update_waypoint(payload).await,
+            #[cfg(feature = "nav")]
+            CMD_DELETE_WAYPOINT => self.handle_delete_waypoint(payload).await,
+            CMD_SET_TIMEZONE => self.handle_set_timezone(payload).await,
+            CMD_GET_TIMEZONE => self.handle_get_timezone(),
+            CMD_RECORDING_CONTROL => self.handle_recording_control(payload).await,
+            CMD_SET_RECORDING_CONFIG => self.handle_set_recording_config(payload).await,
+            CMD_SESSION_START => self.handle_session_start(payload).await,
+            CMD_SESSION_CONTROL => self.handle_session_control(payload).await,
+            CMD_LIST_SESSIONS => self.handle_list_sessions(payload).await,
+            CMD_VIBRATION_CAPTURE => self.handle_vibration_capture(payload),
+            CMD_GET_DIAGNOSTICS => self.handle_get_diagnostics(),
+            CMD_HELLO => self.handle_hello(),
+            #[cfg(feature = "lora")]
+            CMD_SET_LORA_CONFIG => self.handle_set_lora_config(payload).await,
+            #[cfg(feature = "lora")]
+            CMD_GET_LORA_STATUS => self.handle_get_lora_status().await,
+            CMD_SET_PHONE_LOCATION => self.handle_set_phone_location(payload).await,
+            #[cfg(feature = "nav")]
+            CMD_SET_FAVORITE_WAYPOINT => self.handle_set_favorite_waypoint(payload).await,
+            CMD_GET_TODAY_STATS => self.handle_get_today_stats().await,
+            CMD_GUEST_MODE => self.handle_guest_mode(payload).await,
+            CMD_BULK_LOG_FILES => self.handle_bulk_log_files(payload),
+            CMD_READ_DECIMATED => self.handle_read_decimated(payload).await,
+            CMD_SETTINGS => self.handle_settings(payload).await,
+            CMD_ADD_MARKER => self.handle_add_marker(payload).await,
+            CMD_SET_GEOFENCE => self.handle_set_geofence(payload).await,
+            CMD_LIST_GEOFENCES => self.handle_list_geofences(payload).await,
+            CMD_DELETE_GEOFENCE => self.handle_delete_geofence(payload).await,
+            CMD_SESSION_META => self.handle_session_meta(payload).await,
+            CMD_RELAY_RESUME => self.handle_relay_resume(payload).await,
+            CMD_READ_WINDOW => self.handle_read_window(payload).await,
+            CMD_RESUME_FILE => self.handle_resume_file(payload).await,
+            CMD_GET_SYS_INFO_SCHEMA => self.handle_get_sys_info_schema(payload),
+            CMD_SET_TIME => self.handle_set_time(payload).await,
+            CMD_GET_FIX_STATS => self.handle_get_fix_stats().await,
+            CMD_GET_KEEP_ALIVE => self.handle_get_keep_alive().await,
+            CMD_RETRANSMIT => self.handle_retransmit(payload).await,
+            CMD_READ_FLASH_TRACK => self.handle_read_flash_track(payload).await,
+            #[cfg(feature = "buzzer")]
+            CMD_RING => self.handle_ring(payload),
+            CMD_BONDS => self.handle_bonds(payload).await,
+            CMD_BATTERY_HISTORY => self.handle_battery_history(payload).await,
+            CMD_FIRMWARE => self.handle_firmware(payload).await,
             _ => Some(self.encode_empty_response()),
         };
 
@@ -226,6 +549,10 @@ impl FileTransferProtocol {
         let path_len = core::cmp::min(path_len, payload_len.saturating_sub(1));
         let path = &payload[1..1 + path_len];
 
+        self.decimate = None;
+        self.window = None;
+        self.sequence = TransferSequence::new();
+        self.resend.clear();
         let Some(size) = storage::open_file(path).await else {
             return Some(self.encode_empty_response());
         };
@@ -233,6 +560,176 @@ impl FileTransferProtocol {
         Some(self.encode_response(4))
     }
 
+    async fn handle_read_window(&mut self, payload: &[u8]) -> Option<usize> {
+        // Payload: [offset: 4B LE] [chunk_len: 2B LE] [chunks: 1B] [flags: 1B, optional]
+        // Response: one frame per chunk, each
+        //          [seq: 2B LE, if sequenced] [offset: 4B LE] [len: 2B LE] [crc32: 4B LE]
+        //          [data]
+        if payload.len() < 7 {
+            defmt::warn!("READ_WINDOW: payload too short ({} bytes)", payload.len());
+            self.window = None;
+            return Some(self.encode_empty_response());
+        }
+        let offset = u32::from_le_bytes([payload[0], payload[1], payload[2], payload[3]]);
+        let chunk_len = u16::from_le_bytes([payload[4], payload[5]]) as usize;
+        let flags = payload.get(7).copied().unwrap_or(0);
+        let sequenced = flags & WINDOW_FLAG_SEQUENCED != 0;
+        let max_chunk_len = if sequenced {
+            SEQ_CHUNK_MAX_DATA
+        } else {
+            WINDOW_CHUNK_MAX_DATA
+        };
+        self.resend.clear();
+        self.window = Some(ReadWindow {
+            offset,
+            chunk_len: chunk_len.clamp(1, max_chunk_len),
+            remaining: payload[6].clamp(1, WINDOW_MAX_CHUNKS),
+            sequenced,
+        });
+        self.next_frame().await
+    }
+
+    async fn handle_retransmit(&mut self, payload: &[u8]) -> Option<usize> {
+        // Payload: [seq: 2B LE] x 1-16
+        // Response: the sequenced frames again, with new sequence numbers.
+        // Empty if none of them is remembered.
+        self.window = None;
+        self.resend.clear();
+        for pair in payload.chunks_exact(2) {
+            let seq = u16::from_le_bytes([pair[0], pair[1]]);
+            match self.sequence.lookup(seq) {
+                Some(chunk) => {
+                    if self.resend.push_back(chunk).is_err() {
+                        break;
+                    }
+                }
+                None => defmt::warn!("RETRANSMIT: seq {} no longer held", seq),
+            }
+        }
+        match self.next_frame().await {
+            Some(len) => Some(len),
+            None => Some(self.encode_empty_response()),
+        }
+    }
+
+    async fn handle_resume_file(&mut self, payload: &[u8]) -> Option<usize> {
+        // Payload: [offset: 4B LE] [tail_len: 2B LE] [tail_crc32: 4B LE]
+        //          [path_len: 1B] [path]
+        // Response: [resumed: 1B] [size: 4B LE]
+        if payload.len() < 11 {
+            return Some(self.encode_empty_response());
+        }
+        let offset = u32::from_le_bytes([payload[0], payload[1], payload[2], payload[3]]);
+        let tail_len = u16::from_le_bytes([payload[4], payload[5]]) as u32;
+        let tail_crc = u32::from_le_bytes([payload[6], payload[7], payload[8], payload[9]]);
+        let path_len = core::cmp::min(payload[10] as usize, payload.len() - 11);
+        let path = &payload[11..11 + path_len];
+
+        self.decimate = None;
+        self.window = None;
+        self.sequence = TransferSequence::new();
+        self.resend.clear();
+        let Some(size) = storage::open_file(path).await else {
+            return Some(self.encode_empty_response());
+        };
+        let resumed = offset <= size
+            && tail_len <= offset.min(RESUME_TAIL_MAX)
+            && Self::file_crc(offset - tail_len, offset).await == Some(tail_crc);
+        if !resumed {
+            defmt::warn!("RESUME_FILE: tail before {} does not match, restart", offset);
+        }
+        self.response[2] = u8::from(resumed);
+        self.response[3..7].copy_from_slice(&size.to_le_bytes());
+        Some(self.encode_response(5))
+    }
+
+    /// CRC-32 of `[start, end)` of the open transfer file.
+    async fn file_crc(start: u32, end: u32) -> Option<u32> {
+        let mut crc = Crc32::new();
+        let mut data = [0u8; READ_CHUNK_MAX_DATA];
+        let mut offset = start;
+        while offset < end {
+            let len = core::cmp::min((end - offset) as usize, data.len());
+            let n = storage::read_file(offset, &mut data[..len]).await.ok()?;
+            if n == 0 {
+                return None;
+            }
+            crc.update(&data[..n]);
+            offset += n as u32;
+        }
+        Some(crc.finish())
+    }
+
+    async fn handle_read_decimated(&mut self, payload: &[u8]) -> Option<usize> {
+        // Payload: [action: 1B] (0 = restart keeping every Nth point,
+        //          1 = restart with a tolerance in metres, both followed by
+        //          [param: u16 LE]; 2 = next batch)
+        // Response: [flags: 1B] (bit0 = end of track) [count: 1B]
+        //          count * [timestamp: u32][lat_e7: i32][lon_e7: i32][alt_dm: i32]
+        match payload.first().copied() {
+            Some(action @ (0 | 1)) if payload.len() >= 3 => {
+                let param = u16::from_le_bytes([payload[1], payload[2]]);
+                let mode = if action == 0 {
+                    DecimateMode::EveryNth(param)
+                } else {
+                    DecimateMode::Tolerance(param)
+                };
+                self.decimate = Some(DecimateStream::new(mode));
+            }
+            Some(2) => {}
+            _ => {
+                defmt::warn!("READ_DECIMATED: invalid payload ({} bytes)", payload.len());
+                return Some(self.encode_empty_response());
+            }
+        }
+        let Some(stream) = self.decimate.as_mut() else {
+            defmt::warn!("READ_DECIMATED: no stream started");
+            return Some(self.encode_empty_response());
+        };
+
+        let mut chunk = [0u8; DECIMATE_READ_CHUNK];
+        let mut count = 0usize;
+        let mut scanned = 0usize;
+        let mut read_failed = false;
+        while !stream.done && count < DECIMATE_MAX_POINTS && scanned < DECIMATE_SCAN_BUDGET {
+            let Ok(n) = storage::read_file(stream.offset, &mut chunk).await else {
+                read_failed = true;
+                break;
+            };
+            if n == 0 {
+                stream.done = true;
+                if let Some(point) = stream.decimator.finish() {
+                    write_track_point(&mut self.response[4 + count * DECIMATE_POINT_LEN..], &point);
+                    count += 1;
+                }
+                break;
+            }
+            let mut points = stream.decoder.points(&chunk[..n]);
+            for point in points.by_ref() {
+                if let Some(point) = stream.decimator.push(point) {
+                    write_track_point(&mut self.response[4 + count * DECIMATE_POINT_LEN..], &point);
+                    count += 1;
+                    if count == DECIMATE_MAX_POINTS {
+                        break;
+                    }
+                }
+            }
+            let used = points.used();
+            stream.offset += used as u32;
+            scanned += used;
+        }
+        let done = stream.done;
+        if read_failed {
+            // No transfer file open, or the card went away.
+            self.decimate = None;
+            return Some(self.encode_empty_response());
+        }
+
+        self.response[2] = u8::from(done);
+        self.response[3] = count as u8;
+        Some(self.encode_response(2 + count * DECIMATE_POINT_LEN))
+    }
+
     async fn handle_read_chunk(&mut self, payload: &[u8]) -> Option<usize> {
         if payload.len() < 6 {
             self.response[2] = 0;
@@ -266,7 +763,6 @@ impl FileTransferProtocol {
     async fn handle_get_sys_info(&mut self) -> Option<usize> {
         let mut info = { *SYSTEM_INFO.lock().await };
         info.keep_alive_remaining_s = gps::get_keep_alive_remaining_s().await;
-        info.battery_percent = battery::estimate_battery_level(info.battery_voltage * 1000.0) as u8;
         let bmp = bmp280::BMP280_DATA.lock().await;
         if bmp.ok {
             info.temperature_c = bmp.temperature_c;
@@ -282,9 +778,27 @@ impl FileTransferProtocol {
         Some(self.encode_response(payload_len))
     }
 
+    fn handle_get_sys_info_schema(&mut self, payload: &[u8]) -> Option<usize> {
+        // Payload: [first: 1B] (optional, default 0)
+        // Response: [version: 1B] [snapshot_len: 2B LE] [total: 1B] [first: 1B]
+        //          [count: 1B] count * [id: 1B][type: 1B][offset: 2B LE][size: 1B]
+        let first = payload.first().copied().unwrap_or(0);
+        let count = status_schema::write_entries(
+            first as usize,
+            &mut self.response[8..2 + MAX_RESPONSE_PAYLOAD],
+        );
+        self.response[2] = SYSTEM_INFO_VERSION;
+        self.response[3..5].copy_from_slice(&(SYSTEM_INFO_SERIALIZED_LEN as u16).to_le_bytes());
+        self.response[5] = status_schema::SCHEMA.len() as u8;
+        self.response[6] = first;
+        self.response[7] = count as u8;
+        Some(self.encode_response(6 + count * status_schema::ENTRY_LEN))
+    }
+
     fn handle_start_agnss_write(&mut self) -> Option<usize> {
         self.agnss_len = 0;
         self.agnss_write_in_progress = true;
+        self.agnss_stream = Some(FrameSplitter::new());
         defmt::info!("AGNSS write start");
         Some(self.encode_empty_response())
     }
@@ -332,12 +846,75 @@ impl FileTransferProtocol {
         Some(self.encode_empty_response())
     }
 
+    fn handle_write_agnss_stream(&mut self, payload: &[u8]) -> Option<usize> {
+        // Payload: raw bytes of the AGNSS download, split anywhere.
+        // Response: [kept: 2B LE] [dropped: 2B LE], totals for this write.
+        let Some(splitter) = self.agnss_stream.as_mut() else {
+            defmt::warn!("AGNSS stream ignored: not in progress");
+            return Some(self.encode_empty_response());
+        };
+        splitter.push(payload, |frame| {
+            let slot = self.agnss_messages.get_mut(self.agnss_len);
+            let (Some(slot), Some(msg)) = (slot, AgnssMessage::from_slice(frame)) else {
+                return false;
+            };
+            *slot = msg;
+            self.agnss_len += 1;
+            true
+        });
+        let (kept, dropped) = (splitter.kept(), splitter.dropped());
+        defmt::debug!("AGNSS stream: {} frames kept, {} dropped", kept, dropped);
+        self.response[2..4].copy_from_slice(&kept.to_le_bytes());
+        self.response[4..6].copy_from_slice(&dropped.to_le_bytes());
+        Some(self.encode_response(4))
+    }
+
+    fn handle_write_agnss_lnav(&mut self, payload: &[u8]) -> Option<usize> {
+        // Payload: [svid: 1B] + subframes 1-3, 10 words of 4 bytes each
+        // Response: [status: 1B], 0 = queued, 1-4 = refused (LnavError),
+        //           5 = queue full
+        if !self.agnss_write_in_progress {
+            defmt::warn!("AGNSS LNAV ignored: not in progress");
+            return Some(self.encode_empty_response());
+        }
+        let Some((&svid, subframes)) = payload.split_first() else {
+            return Some(self.encode_empty_response());
+        };
+        let Ok(subframes) = <&[u8; LNAV_EPHEMERIS_LEN]>::try_from(subframes) else {
+            defmt::warn!("AGNSS LNAV ignored: payload {}", payload.len());
+            return Some(self.encode_empty_response());
+        };
+        self.response[2] = match gps_ephemeris_from_lnav(svid, subframes) {
+            Ok(frame) => {
+                let slot = self.agnss_messages.get_mut(self.agnss_len);
+                match (slot, AgnssMessage::from_slice(&frame)) {
+                    (Some(slot), Some(msg)) => {
+                        *slot = msg;
+                        self.agnss_len += 1;
+                        0
+                    }
+                    _ => 5,
+                }
+            }
+            Err(err) => {
+                defmt::warn!("AGNSS LNAV for PRN {} refused: {}", svid, err as u8);
+                err as u8
+            }
+        };
+        Some(self.encode_response(1))
+    }
+
     async fn handle_end_agnss_write(&mut self) -> Option<usize> {
         if !self.agnss_write_in_progress {
             defmt::warn!("AGNSS write end ignored: not in progress");
             return Some(self.encode_empty_response());
         }
         self.agnss_write_in_progress = false;
+        if let Some(splitter) = self.agnss_stream.take() {
+            if splitter.dropped() > 0 {
+                defmt::warn!("AGNSS stream: {} frames dropped", splitter.dropped());
+            }
+        }
 
         let mut slices: [Option<&[u8]>; MAX_AGNSS_MESSAGES] = [None; MAX_AGNSS_MESSAGES];
         for (idx, msg) in self.agnss_messages.iter().take(self.agnss_len).enumerate() {
@@ -354,16 +931,22 @@ impl FileTransferProtocol {
         match gps::set_agnss_message_queue(&ready[..count]).await {
             Ok(()) => {
                 defmt::info!("AGNSS queue set");
+                Some(self.encode_empty_response())
             }
             Err(err) => {
-                let err_tag = match err {
-                    gps::AgnssQueueError::TooManyMessages => "TooManyMessages",
-                    gps::AgnssQueueError::MessageTooLarge => "MessageTooLarge",
+                let (err_tag, code) = match err {
+                    gps::AgnssQueueError::TooManyMessages => ("TooManyMessages", 1u8),
+                    gps::AgnssQueueError::MessageTooLarge => ("MessageTooLarge", 2),
+                    gps::AgnssQueueError::Busy => ("Busy", 3),
+                    gps::AgnssQueueError::Stale => ("Stale", 4),
                 };
                 defmt::warn!("AGNSS queue set failed: {}", err_tag);
+                // Older apps only accept an empty payload, so any byte reads
+                // as a failed upload.
+                self.response[2] = code;
+                Some(self.encode_response(1))
             }
         }
-        Some(self.encode_empty_response())
     }
 
     async fn handle_gps_wakeup(&mut self) -> Option<usize> {
@@ -381,6 +964,70 @@ impl FileTransferProtocol {
         Some(self.encode_empty_response())
     }
 
+    async fn handle_get_keep_alive(&mut self) -> Option<usize> {
+        // Response: [remaining_s: u32 LE], 0 = no keep-alive
+        let remaining_s = gps::keep_alive_remaining_s().await;
+        self.response[2..6].copy_from_slice(&remaining_s.to_le_bytes());
+        Some(self.encode_response(4))
+    }
+
+    async fn handle_read_flash_track(&mut self, payload: &[u8]) -> Option<usize> {
+        // Payload: [skip: u16 LE], optional
+        // Response: [count: 1B] + count x 16B points, newest first
+        let skip = match payload {
+            [a, b, ..] => u16::from_le_bytes([*a, *b]) as usize,
+            _ => 0,
+        };
+        let empty = RingPoint {
+            timestamp: 0,
+            latitude_e7: 0,
+            longitude_e7: 0,
+            altitude_m: 0,
+        };
+        let mut points = [empty; DECIMATE_MAX_POINTS];
+        let count = flash_track::read_newest(skip, &mut points).await;
+        self.response[2] = count as u8;
+        for (i, point) in points[..count].iter().enumerate() {
+            let track_point = TrackPoint {
+                timestamp: point.timestamp,
+                latitude_e7: point.latitude_e7,
+                longitude_e7: point.longitude_e7,
+                altitude_dm: point.altitude_m as i32 * 10,
+                pressure_pa: 0,
+                activity: 0,
+                fix_mode: 0,
+                fix_quality: 0,
+            };
+            let at = 3 + i * DECIMATE_POINT_LEN;
+            let out = &mut self.response[at..at + DECIMATE_POINT_LEN];
+            write_track_point(out, &track_point);
+        }
+        Some(self.encode_response(1 + count * DECIMATE_POINT_LEN))
+    }
+
+    #[cfg(feature = "buzzer")]
+    fn handle_ring(&mut self, payload: &[u8]) -> Option<usize> {
+        // Payload: [action: 1B][duration_s: u16 LE], duration optional
+        // Response: [remaining_s: u32 LE]
+        let remaining_s = match payload {
+            [0, ..] => {
+                sound::stop();
+                0
+            }
+            [1, rest @ ..] => {
+                let duration_s = match rest {
+                    [a, b, ..] => u16::from_le_bytes([*a, *b]).min(RING_MAX_S),
+                    _ => 0,
+                };
+                sound::ring((duration_s != 0).then_some(duration_s)) as u32
+            }
+            [2, ..] => sound::ring_remaining_s(),
+            _ => return Some(self.encode_empty_response()),
+        };
+        self.response[2..6].copy_from_slice(&remaining_s.to_le_bytes());
+        Some(self.encode_response(4))
+    }
+
     #[cfg(feature = "findmy")]
     async fn handle_write_findmy_keys(&mut self, payload: &[u8]) -> Option<usize> {
         if payload.len() != storage::FINDMY_KEY_SIZE {
@@ -393,7 +1040,8 @@ impl FileTransferProtocol {
         }
         let mut keys = [0u8; storage::FINDMY_KEY_SIZE];
         keys.copy_from_slice(payload);
-        if !storage::write_findmy_keys(&keys).await {
+        let saved = storage::write_findmy_keys(&keys).await;
+        if !saved && storage::card_mounted() {
             defmt::warn!("WRITE_FINDMY_KEYS: SD write failed");
             return Some(self.encode_empty_response());
         }
@@ -409,9 +1057,10 @@ impl FileTransferProtocol {
         });
         findmy::init(&pk, &sk, epoch);
         findmy::invalidate_sk_cache().await;
-        findmy::set_enabled(true);
-        defmt::info!("WRITE_FINDMY_KEYS: OK, epoch={}", epoch);
-        self.response[2] = 0x01; // success flag
+        findmy::set_provisioned(true);
+        defmt::info!("WRITE_FINDMY_KEYS: OK, epoch={} saved={}", epoch, saved);
+        // 0x02: no card, active until reboot.
+        self.response[2] = if saved { 0x01 } else { 0x02 };
         Some(self.encode_response(1))
     }
 
@@ -428,9 +1077,13 @@ impl FileTransferProtocol {
 
     #[cfg(feature = "findmy")]
     async fn handle_get_findmy_status(&mut self) -> Option<usize> {
-        // Response: [enabled: 1B]
-        self.response[2] = if findmy::is_enabled() { 0x01 } else { 0x00 };
-        Some(self.encode_response(1))
+        // Response: [enabled: 1B][switch: 1B][provisioned: 1B][interval_ms: 2B]
+        let interval_ms = settings::stored(settings::FINDMY_INTERVAL_MS) as u16;
+        self.response[2] = findmy::is_enabled() as u8;
+        self.response[3] = (settings::stored(settings::FINDMY_ENABLED) != 0) as u8;
+        self.response[4] = findmy::is_provisioned() as u8;
+        self.response[5..7].copy_from_slice(&interval_ms.to_le_bytes());
+        Some(self.encode_response(5))
     }
 
     #[cfg(feature = "google-fmdn")]
@@ -445,15 +1098,17 @@ impl FileTransferProtocol {
         }
         let mut eik = [0u8; storage::FMDN_EIK_SIZE];
         eik.copy_from_slice(payload);
-        if !storage::write_fmdn_eik(&eik).await {
+        let saved = storage::write_fmdn_eik(&eik).await;
+        if !saved && storage::card_mounted() {
             defmt::warn!("WRITE_FMDN_EIK: SD write failed");
             return Some(self.encode_empty_response());
         }
         // Activate immediately.
         google_fmdn::init(&eik);
         google_fmdn::set_enabled(true);
-        defmt::info!("WRITE_FMDN_EIK: OK");
-        self.response[2] = 0x01; // success flag
+        defmt::info!("WRITE_FMDN_EIK: OK, saved={}", saved);
+        // 0x02: no card, active until reboot.
+        self.response[2] = if saved { 0x01 } else { 0x02 };
         Some(self.encode_response(1))
     }
 
@@ -475,6 +1130,739 @@ impl FileTransferProtocol {
         Some(self.encode_response(2))
     }
 
+    async fn handle_set_gpio_hook(&mut self, payload: &[u8]) -> Option<usize> {
+        // Payload: [pin: 1B] [rule: 1B] [flags: 1B] [param: 2B LE]
+        if payload.len() < 5 {
+            defmt::warn!("SET_GPIO_HOOK: payload too short {}", payload.len());
+            return Some(self.encode_empty_response());
+        }
+        let Some(rule) = gpio_hooks::HookRule::from_u8(payload[1]) else {
+            defmt::warn!("SET_GPIO_HOOK: unknown rule {}", payload[1]);
+            return Some(self.encode_empty_response());
+        };
+        let param = u16::from_le_bytes([payload[3], payload[4]]);
+        if !gpio_hooks::set_hook(payload[0] as usize, rule, payload[2], param).await {
+            defmt::warn!("SET_GPIO_HOOK: bad pin {}", payload[0]);
+            return Some(self.encode_empty_response());
+        }
+        self.response[2] = 0x01; // success flag
+        Some(self.encode_response(1))
+    }
+
+    async fn handle_get_gpio_hooks(&mut self) -> Option<usize> {
+        let mut entries = [0u8; gpio_hooks::HOOK_PIN_COUNT * gpio_hooks::HOOK_STATUS_ENTRY_LEN];
+        let len = gpio_hooks::serialize_hooks(&mut entries).await;
+        self.response[2] = gpio_hooks::HOOK_PIN_COUNT as u8;
+        self.response[3..3 + len].copy_from_slice(&entries[..len]);
+        Some(self.encode_response(1 + len))
+    }
+
+    #[cfg(feature = "nav")]
+    async fn handle_add_waypoint(&mut self, payload: &[u8]) -> Option<usize> {
+        let Some(wp) = parse_waypoint(payload) else {
+            defmt::warn!("ADD_WAYPOINT: invalid payload");
+            return Some(self.encode_empty_response());
+        };
+        let Some(slot) = waypoints::add(wp).await else {
+            defmt::warn!("ADD_WAYPOINT: database full or SD write failed");
+            return Some(self.encode_empty_response());
+        };
+        self.response[2] = slot;
+        Some(self.encode_response(1))
+    }
+
+    #[cfg(feature = "nav")]
+    async fn handle_list_waypoints(&mut self, payload: &[u8]) -> Option<usize> {
+        // Response: [next_slot: 1B] [count: 1B] [slot: 1B + record: 32B] * count
+        let mut next = payload.first().copied().unwrap_or(0) as usize;
+        let mut count = 0usize;
+        let mut cursor = 2usize;
+        while count < WAYPOINT_LIST_MAX_ENTRIES {
+            let Some((slot, wp)) = waypoints::next_from(next).await else {
+                next = WAYPOINT_LIST_END as usize;
+                break;
+            };
+            self.response[2 + cursor] = slot;
+            wp.write_record(
+                &mut self.response[3 + cursor..3 + cursor + waypoints::WAYPOINT_RECORD_SIZE],
+            );
+            cursor += 1 + waypoints::WAYPOINT_RECORD_SIZE;
+            count += 1;
+            next = slot as usize + 1;
+        }
+        if next >= waypoints::MAX_WAYPOINTS {
+            next = WAYPOINT_LIST_END as usize;
+        }
+        self.response[2] = next as u8;
+        self.response[3] = count as u8;
+        Some(self.encode_response(cursor))
+    }
+
+    #[cfg(feature = "nav")]
+    async fn handle_update_waypoint(&mut self, payload: &[u8]) -> Option<usize> {
+        let Some((&slot, rest)) = payload.split_first() else {
+            return Some(self.encode_empty_response());
+        };
+        let Some(wp) = parse_waypoint(rest) else {
+            defmt::warn!("UPDATE_WAYPOINT: invalid payload");
+            return Some(self.encode_empty_response());
+        };
+        if !waypoints::update(slot, wp).await {
+            defmt::warn!("UPDATE_WAYPOINT: slot {} not updated", slot);
+            return Some(self.encode_empty_response());
+        }
+        self.response[2] = 0x01; // success flag
+        Some(self.encode_response(1))
+    }
+
+    #[cfg(feature = "nav")]
+    async fn handle_delete_waypoint(&mut self, payload: &[u8]) -> Option<usize> {
+        let Some(&slot) = payload.first() else {
+            return Some(self.encode_empty_response());
+        };
+        if !waypoints::delete(slot).await {
+            defmt::warn!("DELETE_WAYPOINT: slot {} not deleted", slot);
+            return Some(self.encode_empty_response());
+        }
+        self.response[2] = 0x01; // success flag
+        Some(self.encode_response(1))
+    }
+
+    #[cfg(feature = "nav")]
+    async fn handle_set_favorite_waypoint(&mut self, payload: &[u8]) -> Option<usize> {
+        // Payload: [slot: 1B] (0xFF = none, show the nearest waypoint)
+        let Some(&slot) = payload.first() else {
+            return Some(self.encode_empty_response());
+        };
+        let slot = (slot != WAYPOINT_LIST_END).then_some(slot);
+        if !waypoints::set_favorite(slot).await {
+            defmt::warn!("SET_FAVORITE_WAYPOINT: slot unused or SD write failed");
+            return Some(self.encode_empty_response());
+        }
+        self.response[2] = 0x01; // success flag
+        Some(self.encode_response(1))
+    }
+
+    async fn handle_set_timezone(&mut self, payload: &[u8]) -> Option<usize> {
+        // Payload: [mode: 1B] [flags: 1B] [offset_min: i16 LE] [lat: i32 LE] [lon: i32 LE]
+        if payload.len() < 4 {
+            defmt::warn!("SET_TIMEZONE: payload too short {}", payload.len());
+            return Some(self.encode_empty_response());
+        }
+        let mut raw = [payload[0], payload[1], payload[2], payload[3], 0, 0];
+        if payload[0] == 2 {
+            // Pin the zone found at the given reference coordinates.
+            if payload.len() < 12 {
+                defmt::warn!("SET_TIMEZONE: zone mode needs coordinates");
+                return Some(self.encode_empty_response());
+            }
+            let lat_e7 = i32::from_le_bytes([payload[4], payload[5], payload[6], payload[7]]);
+            let lon_e7 = i32::from_le_bytes([payload[8], payload[9], payload[10], payload[11]]);
+            let tz_id =
+                timezone::zone_id_for_location(lat_e7 as f32 / 1e7, lon_e7 as f32 / 1e7);
+            raw[4..6].copy_from_slice(&tz_id.to_le_bytes());
+        }
+        let Some(settings) = TzSettings::from_bytes(&raw) else {
+            defmt::warn!("SET_TIMEZONE: invalid settings");
+            return Some(self.encode_empty_response());
+        };
+        if !storage::write_tz_settings(&settings).await {
+            defmt::warn!("SET_TIMEZONE: SD write failed, applying for this session only");
+        }
+        timezone::set_settings(settings);
+        self.response[2] = 0x01; // success flag
+        Some(self.encode_response(1))
+    }
+
+    fn handle_get_timezone(&mut self) -> Option<usize> {
+        // Response: [mode: 1B] [flags: 1B] [offset_min: i16 LE] [tz_id: u16 LE]
+        let settings = timezone::settings();
+        self.response[2..2 + timezone::TZ_SETTINGS_LEN].copy_from_slice(&settings.to_bytes());
+        Some(self.encode_response(timezone::TZ_SETTINGS_LEN))
+    }
+
+    async fn handle_recording_control(&mut self, payload: &[u8]) -> Option<usize> {
+        // Payload: [action: 1B] (0 = stop, 1 = start, 2 / empty = query)
+        // Response: [recording: 1B] [auto_start: 1B]
+        match payload.first().copied().unwrap_or(2) {
+            0 => recording::stop().await,
+            1 => recording::start().await,
+            2 => {}
+            action => {
+                defmt::warn!("RECORDING_CONTROL: unknown action {}", action);
+                return Some(self.encode_empty_response());
+            }
+        }
+        self.response[2] = u8::from(recording::is_recording());
+        self.response[3] = u8::from(recording::auto_start());
+        Some(self.encode_response(2))
+    }
+
+    async fn handle_set_recording_config(&mut self, payload: &[u8]) -> Option<usize> {
+        // Payload: [flags: 1B] (bit0 = auto-start at boot)
+        let Some(&flags) = payload.first() else {
+            return Some(self.encode_empty_response());
+        };
+        if !recording::set_auto_start((flags & 0x01) != 0).await {
+            defmt::warn!("SET_RECORDING_CONFIG: SD write failed");
+            return Some(self.encode_empty_response());
+        }
+        self.response[2] = 0x01; // success flag
+        Some(self.encode_response(1))
+    }
+
+    async fn handle_session_start(&mut self, payload: &[u8]) -> Option<usize> {
+        // Payload: [activity: 1B] [name_len: 1B] [name]
+        if payload.len() < 2 {
+            defmt::warn!("SESSION_START: payload too short {}", payload.len());
+            return Some(self.encode_empty_response());
+        }
+        let name_len = core::cmp::min(payload[1] as usize, payload.len() - 2);
+        let Some(slot) = sessions::start(payload[0], &payload[2..2 + name_len]).await else {
+            defmt::warn!("SESSION_START: session open or SD write failed");
+            return Some(self.encode_empty_response());
+        };
+        self.response[2] = slot;
+        Some(self.encode_response(1))
+    }
+
+    async fn handle_session_control(&mut self, payload: &[u8]) -> Option<usize> {
+        // Payload: [action: 1B] (0 = stop, 1 = pause, 2 = resume, 3 / empty = query)
+        // Response: [state: 1B] [slot: 1B]
+        let action = match payload.first().copied().unwrap_or(3) {
+            0 => Some(sessions::SessionAction::Stop),
+            1 => Some(sessions::SessionAction::Pause),
+            2 => Some(sessions::SessionAction::Resume),
+            3 => None,
+            action => {
+                defmt::warn!("SESSION_CONTROL: unknown action {}", action);
+                return Some(self.encode_empty_response());
+            }
+        };
+        if let Some(action) = action {
+            if !sessions::control(action).await {
+                defmt::warn!("SESSION_CONTROL: no open session or SD write failed");
+                return Some(self.encode_empty_response());
+            }
+        }
+        let (state, slot) = sessions::status().await;
+        self.response[2] = state as u8;
+        self.response[3] = slot;
+        Some(self.encode_response(2))
+    }
+
+    async fn handle_list_sessions(&mut self, payload: &[u8]) -> Option<usize> {
+        // Response: [next_slot: 1B] [count: 1B] [slot: 1B + record: 48B] * count
+        let mut next = payload.first().copied().unwrap_or(0) as usize;
+        let mut count = 0usize;
+        let mut cursor = 2usize;
+        while count < SESSION_LIST_MAX_ENTRIES {
+            let Some((slot, session)) = sessions::next_from(next).await else {
+                next = SESSION_LIST_END as usize;
+                break;
+            };
+            self.response[2 + cursor] = slot;
+            session.write_record(
+                &mut self.response[3 + cursor..3 + cursor + sessions::SESSION_RECORD_SIZE],
+            );
+            cursor += 1 + sessions::SESSION_RECORD_SIZE;
+            count += 1;
+            next = slot as usize + 1;
+        }
+        if next >= sessions::MAX_SESSIONS {
+            next = SESSION_LIST_END as usize;
+        }
+        self.response[2] = next as u8;
+        self.response[3] = count as u8;
+        Some(self.encode_response(cursor))
+    }
+
+    async fn handle_session_meta(&mut self, payload: &[u8]) -> Option<usize> {
+        // Payload: [slot: 1B] (query) or
+        //          [slot: 1B] [activity: 1B] [r g b: 3B] [notes_len: 1B] [notes] (set)
+        // Response: [slot: 1B] [record: 48B], all zero when the session has none
+        let Some(&slot) = payload.first() else {
+            return Some(self.encode_empty_response());
+        };
+        if payload.len() >= 6 {
+            let notes_len = core::cmp::min(payload[5] as usize, payload.len() - 6);
+            let meta = sessions::SessionMeta::new(
+                payload[1],
+                [payload[2], payload[3], payload[4]],
+                &payload[6..6 + notes_len],
+            );
+            if !sessions::set_meta(slot, meta).await {
+                defmt::warn!("SESSION_META: slot {} empty or SD write failed", slot);
+                return Some(self.encode_empty_response());
+            }
+        }
+        let Some((seq, meta)) = sessions::meta(slot).await else {
+            defmt::warn!("SESSION_META: slot {} empty", slot);
+            return Some(self.encode_empty_response());
+        };
+        let record = &mut self.response[3..3 + sessions::META_RECORD_SIZE];
+        match meta {
+            Some(meta) => meta.write_record(seq, record),
+            None => record.fill(0),
+        }
+        self.response[2] = slot;
+        Some(self.encode_response(1 + sessions::META_RECORD_SIZE))
+    }
+
+    async fn handle_relay_resume(&mut self, payload: &[u8]) -> Option<usize> {
+        // Payload: [stream_id: 4B LE] [next_seq: 4B LE] (resume) or empty (query)
+        // Response: [stream_id: 4B LE] [oldest_seq: 4B LE] [next_seq: 4B LE]
+        let status = if payload.len() >= 8 {
+            let stream_id = u32::from_le_bytes([payload[0], payload[1], payload[2], payload[3]]);
+            let seq = u32::from_le_bytes([payload[4], payload[5], payload[6], payload[7]]);
+            live_track::resume_relay(stream_id, seq).await
+        } else if payload.is_empty() {
+            live_track::relay_status().await
+        } else {
+            return Some(self.encode_empty_response());
+        };
+        self.response[2..6].copy_from_slice(&status.stream_id.to_le_bytes());
+        self.response[6..10].copy_from_slice(&status.oldest_seq.to_le_bytes());
+        self.response[10..14].copy_from_slice(&status.next_seq.to_le_bytes());
+        Some(self.encode_response(12))
+    }
+
+    fn handle_vibration_capture(&mut self, payload: &[u8]) -> Option<usize> {
+        // Payload: [rate_hz: 1B] [duration_s: 2B LE] (duration 0 = stop, empty = query)
+        // Response: [active: 1B] [rate_hz: 1B] [remaining_s: 2B LE]
+        if payload.len() >= 3 {
+            let duration_s = u16::from_le_bytes([payload[1], payload[2]]);
+            if !vibration::request_capture(payload[0], duration_s) {
+                defmt::warn!(
+                    "VIBRATION_CAPTURE: unsupported {}Hz / {}s",
+                    payload[0],
+                    duration_s
+                );
+                return Some(self.encode_empty_response());
+            }
+        } else if !payload.is_empty() {
+            return Some(self.encode_empty_response());
+        }
+        let (active, rate_hz, remaining_s) = vibration::status();
+        self.response[2] = u8::from(active);
+        self.response[3] = rate_hz;
+        self.response[4..6].copy_from_slice(&remaining_s.to_le_bytes());
+        Some(self.encode_response(4))
+    }
+
+    fn handle_get_diagnostics(&mut self) -> Option<usize> {
+        // Response: [stack_size: u32] [stack_peak: u32] [static_ram: u32]
+        //           [sd_cache_peak: u16] [sd_cache_size: u16]
+        //           [task_count: 1B] + task_count x [busy_us: u32][wakeups: u32]
+        //           [gps_profile: 1B] [profile: gps::PROFILE_LEN B] [solar_mode: 1B]
+        //           [point_queue_peak: 1B] [point_queue_size: 1B] [points_dropped: u32]
+        //           [blocks_dropped: u32]
+        self.response[2..6].copy_from_slice(&diag::stack_size().to_le_bytes());
+        self.response[6..10].copy_from_slice(&diag::stack_peak_used().to_le_bytes());
+        self.response[10..14].copy_from_slice(&diag::static_ram_used().to_le_bytes());
+        self.response[14..16].copy_from_slice(&(storage::cache_peak_len() as u16).to_le_bytes());
+        self.response[16..18].copy_from_slice(&(storage::CACHE_SIZE as u16).to_le_bytes());
+        let activity = diag::last_minute_activity();
+        self.response[18] = activity.len() as u8;
+        for (task, out) in activity.iter().zip(self.response[19..].chunks_exact_mut(8)) {
+            out[0..4].copy_from_slice(&task.busy_us.to_le_bytes());
+            out[4..8].copy_from_slice(&task.wakeups.to_le_bytes());
+        }
+        let mut len = 17 + activity.len() * 8;
+        let (profile_id, profile) = gps::active_profile();
+        self.response[2 + len] = profile_id as u8;
+        self.response[3 + len..3 + len + gps::PROFILE_LEN].copy_from_slice(&profile.to_bytes());
+        len += 1 + gps::PROFILE_LEN;
+        self.response[2 + len] = solar::mode() as u8;
+        len += 1;
+        self.response[2 + len] = storage::point_queue_peak() as u8;
+        self.response[3 + len] = storage::POINT_QUEUE_LEN as u8;
+        self.response[4 + len..8 + len].copy_from_slice(&storage::points_dropped().to_le_bytes());
+        len += 6;
+        let blocks_dropped = storage::blocks_dropped().to_le_bytes();
+        self.response[2 + len..6 + len].copy_from_slice(&blocks_dropped);
+        len += 4;
+        Some(self.encode_response(len))
+    }
+
+    fn handle_hello(&mut self) -> Option<usize> {
+        // Response: [proto_major: 1B] [proto_minor: 1B] [capabilities: u32]
+        //           [max_response_payload: u16] [capabilities_ext: u32]
+        self.response[2] = features::PROTOCOL_VERSION_MAJOR;
+        self.response[3] = features::PROTOCOL_VERSION_MINOR;
+        self.response[4..8].copy_from_slice(&features::CAPABILITIES.to_le_bytes());
+        self.response[8..10].copy_from_slice(&(MAX_RESPONSE_PAYLOAD as u16).to_le_bytes());
+        self.response[10..14].copy_from_slice(&features::CAPABILITIES_EXT.to_le_bytes());
+        Some(self.encode_response(12))
+    }
+
+    async fn handle_get_today_stats(&mut self) -> Option<usize> {
+        // Response: [start_ts: u32][last_ts: u32][distance_m: u32][moving_s: u32][points: u32]
+        //           [max_speed: u16][ascent_m: u16][descent_m: u16]
+        let stats = track_stats::current().await.to_bytes();
+        self.response[2..2 + stats.len()].copy_from_slice(&stats);
+        Some(self.encode_response(stats.len()))
+    }
+
+    async fn handle_get_fix_stats(&mut self) -> Option<usize> {
+        // Response: [count: u8] + count x [date: u32][attempts: u16][fixes: u16]
+        //           [ttff_sum_s: u32][agnss_attempts: u16][agnss_fixes: u16], newest first
+        let stats = gps::fix_stats().await;
+        let mut len = 1;
+        for day in stats.days() {
+            self.response[2 + len..2 + len + FIX_DAY_LEN].copy_from_slice(&day.to_bytes());
+            len += FIX_DAY_LEN;
+        }
+        self.response[2] = ((len - 1) / FIX_DAY_LEN) as u8;
+        Some(self.encode_response(len))
+    }
+
+    async fn handle_guest_mode(&mut self, payload: &[u8]) -> Option<usize> {
+        // Payload: [action: 1B] (0 = lockdown off, 1 = lockdown on,
+        //          2 / empty = query, 3 = open guest window)
+        // Response: [lockdown: 1B] [window_remaining_s: u16 LE]
+        match payload.first().copied().unwrap_or(2) {
+            action @ (0 | 1) => {
+                if !guest::set_lockdown(action == 1).await {
+                    defmt::warn!("GUEST_MODE: SD write failed");
+                    return Some(self.encode_empty_response());
+                }
+            }
+            2 => {}
+            3 => guest::open_window(),
+            action => {
+                defmt::warn!("GUEST_MODE: unknown action {}", action);
+                return Some(self.encode_empty_response());
+            }
+        }
+        let remaining = core::cmp::min(guest::window_remaining_s(), u16::MAX as u32) as u16;
+        self.response[2] = u8::from(guest::lockdown());
+        self.response[3..5].copy_from_slice(&remaining.to_le_bytes());
+        Some(self.encode_response(3))
+    }
+
+    async fn handle_bonds(&mut self, payload: &[u8]) -> Option<usize> {
+        // Payload: [action: 1B] (0 / empty = query, 1 = open pairing window,
+        //          2 = forget all bonds)
+        // Response: [bonds: 1B] [max_bonds: 1B] [pairing_remaining_s: u16 LE]
+        //           [link_bonded: 1B]
+        match payload.first().copied().unwrap_or(0) {
+            0 => {}
+            1 => ble::open_pairing_window(),
+            2 => {
+                if !ble::clear_bonds().await {
+                    defmt::warn!("BONDS: SD write failed");
+                    return Some(self.encode_empty_response());
+                }
+            }
+            action => {
+                defmt::warn!("BONDS: unknown action {}", action);
+                return Some(self.encode_empty_response());
+            }
+        }
+        let remaining = core::cmp::min(ble::pairing_window_remaining_s(), u16::MAX as u32) as u16;
+        self.response[2] = ble::bond_count() as u8;
+        self.response[3] = MAX_BONDS as u8;
+        self.response[4..6].copy_from_slice(&remaining.to_le_bytes());
+        self.response[6] = u8::from(ble::link_bonded());
+        Some(self.encode_response(5))
+    }
+
+    async fn handle_battery_history(&mut self, payload: &[u8]) -> Option<usize> {
+        // Payload: [skip: u16 LE], optional
+        // Response: [age_s: u16 LE][count: 1B] + count x [voltage_mv: u16 LE]
+        //           [raw_adc: u16 LE][flags: 1B], newest first
+        let skip = match payload {
+            [a, b, ..] => u16::from_le_bytes([*a, *b]) as usize,
+            _ => 0,
+        };
+        let mut slots = [HistorySlot::default(); BATTERY_HISTORY_MAX_SLOTS];
+        let count = battery::read_history(skip, &mut slots).await;
+        let age_s = (embassy_time::Instant::now().as_secs() % SLOT_S) as u16;
+        self.response[2..4].copy_from_slice(&age_s.to_le_bytes());
+        self.response[4] = count as u8;
+        for (i, slot) in slots[..count].iter().enumerate() {
+            let at = 5 + i * HISTORY_RECORD_LEN;
+            self.response[at..at + HISTORY_RECORD_LEN].copy_from_slice(&slot.to_bytes());
+        }
+        Some(self.encode_response(3 + count * HISTORY_RECORD_LEN))
+    }
+
+    async fn handle_firmware(&mut self, payload: &[u8]) -> Option<usize> {
+        // Payload: [action: 1B] (0 / empty = query;
+        //          1 = begin, followed by [len: u32 LE][crc32: u32 LE]
+        //              [version_len: 1B][version];
+        //          2 = write, followed by [offset: u32 LE][data];
+        //          3 = finish)
+        // Query response: [boot_state: 1B][rollbacks: u16 LE][received: u32 LE]
+        //          [bank A: 34B][bank B: 34B], see `slot_meta::SlotImage`
+        // Other responses: [status: 1B][received: u32 LE]
+        let word = |at: usize| {
+            payload
+                .get(at..at + 4)
+                .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        };
+        let action = payload.first().copied().unwrap_or(0);
+        if action == 0 {
+            let mut report = [0u8; slots::REPORT_LEN];
+            if slots::report(&mut report).await.is_none() {
+                return Some(self.encode_empty_response());
+            }
+            self.response[2..2 + slots::REPORT_LEN].copy_from_slice(&report);
+            return Some(self.encode_response(slots::REPORT_LEN));
+        }
+        // The slot bootloader swaps in unsigned images: bonded phones only.
+        let status = if !ble::link_bonded() {
+            defmt::warn!("FIRMWARE: link not bonded");
+            UpdateStatus::Refused
+        } else {
+            match (action, word(1), word(5)) {
+                (1, Some(len), Some(crc)) => {
+                    let version_len = payload.get(9).copied().unwrap_or(0) as usize;
+                    let version = payload.get(10..10 + version_len).unwrap_or(&[]);
+                    slots::begin(len, crc, version).await
+                }
+                (2, Some(offset), _) => slots::write(offset, &payload[5..]).await,
+                (3, _, _) => slots::finish().await,
+                _ => {
+                    defmt::warn!("FIRMWARE: bad action {} ({} bytes)", action, payload.len());
+                    return Some(self.encode_empty_response());
+                }
+            }
+        };
+        if status != UpdateStatus::Ok {
+            defmt::warn!("FIRMWARE: action {} failed: {}", action, status);
+        }
+        self.response[2] = status as u8;
+        self.response[3..7].copy_from_slice(&slots::received().await.to_le_bytes());
+        Some(self.encode_response(5))
+    }
+
+    async fn handle_settings(&mut self, payload: &[u8]) -> Option<usize> {
+        // Payload: [action: 1B] (0 = describe, followed by [index: 1B];
+        //          1 = get, followed by [id: u16 LE];
+        //          2 = set, followed by [id: u16 LE][value: i32 LE])
+        // Describe response: [count: 1B][index: 1B][id: u16][kind: 1B]
+        //          [min: i32][max: i32][default: i32][value: i32]
+        //          [key_len: 1B][key]
+        // Get/set response: [status: 1B][id: u16 LE][value: i32 LE]
+        match payload.first().copied() {
+            Some(0) => {
+                let index = payload.get(1).copied().unwrap_or(0) as usize;
+                let Some(entry) = settings::ENTRIES.get(index) else {
+                    defmt::warn!("SETTINGS: index {} out of range", index);
+                    return Some(self.encode_empty_response());
+                };
+                let (min, max) = entry.kind.range();
+                let value = settings::get(entry.id).unwrap_or(entry.default);
+                let key = entry.key.as_bytes();
+                let out = &mut self.response[2..];
+                out[0] = settings::ENTRIES.len() as u8;
+                out[1] = index as u8;
+                out[2..4].copy_from_slice(&entry.id.to_le_bytes());
+                out[4] = entry.kind.id();
+                out[5..9].copy_from_slice(&min.to_le_bytes());
+                out[9..13].copy_from_slice(&max.to_le_bytes());
+                out[13..17].copy_from_slice(&entry.default.to_le_bytes());
+                out[17..21].copy_from_slice(&value.to_le_bytes());
+                out[21] = key.len() as u8;
+                out[22..22 + key.len()].copy_from_slice(key);
+                Some(self.encode_response(22 + key.len()))
+            }
+            Some(action @ (1 | 2)) => {
+                let needed = if action == 1 { 3 } else { 7 };
+                if payload.len() < needed {
+                    defmt::warn!("SETTINGS: short payload ({} bytes)", payload.len());
+                    return Some(self.encode_empty_response());
+                }
+                let id = u16::from_le_bytes([payload[1], payload[2]]);
+                let value = (action == 2)
+                    .then(|| i32::from_le_bytes([payload[3], payload[4], payload[5], payload[6]]));
+                let result = settings::access(id, value).await;
+                self.response[2..2 + result.len()].copy_from_slice(&result);
+                Some(self.encode_response(result.len()))
+            }
+            _ => {
+                defmt::warn!("SETTINGS: unknown action");
+                Some(self.encode_empty_response())
+            }
+        }
+    }
+
+    async fn handle_add_marker(&mut self, payload: &[u8]) -> Option<usize> {
+        // Payload: [marker_id: u32 LE][phone_unix_ms: u64 LE]
+        // Response: [record: 36B][clock_offset_ms: i64 LE]
+        if payload.len() < 12 {
+            defmt::warn!("ADD_MARKER: short payload ({} bytes)", payload.len());
+            return Some(self.encode_empty_response());
+        }
+        let id = u32::from_le_bytes([payload[0], payload[1], payload[2], payload[3]]);
+        let mut phone_ms = [0u8; 8];
+        phone_ms.copy_from_slice(&payload[4..12]);
+        let Some(marker) = markers::add(id, u64::from_le_bytes(phone_ms)).await else {
+            return Some(self.encode_empty_response());
+        };
+        let offset = marker.clock_offset_ms().unwrap_or(0);
+        marker.write_record(&mut self.response[2..2 + MARKER_RECORD_SIZE]);
+        self.response[2 + MARKER_RECORD_SIZE..2 + MARKER_RECORD_SIZE + 8]
+            .copy_from_slice(&offset.to_le_bytes());
+        Some(self.encode_response(MARKER_RECORD_SIZE + 8))
+    }
+
+    async fn handle_set_geofence(&mut self, payload: &[u8]) -> Option<usize> {
+        // Payload: [slot: 1B, 0xFF = first free][flags: 1B][radius_m: u16 LE]
+        //          [lat: i32 LE][lon: i32 LE][name_len: 1B][name]
+        let Some(fence) = parse_geofence(payload) else {
+            defmt::warn!("SET_GEOFENCE: invalid payload");
+            return Some(self.encode_empty_response());
+        };
+        let slot = (payload[0] != GEOFENCE_LIST_END).then_some(payload[0]);
+        let Some(slot) = geofences::set(slot, fence).await else {
+            defmt::warn!("SET_GEOFENCE: bad slot, database full or SD write failed");
+            return Some(self.encode_empty_response());
+        };
+        self.response[2] = slot;
+        Some(self.encode_response(1))
+    }
+
+    async fn handle_list_geofences(&mut self, payload: &[u8]) -> Option<usize> {
+        // Response: [next_slot: 1B] [count: 1B] [slot: 1B + record: 32B] * count
+        let mut next = payload.first().copied().unwrap_or(0) as usize;
+        let mut count = 0usize;
+        let mut cursor = 2usize;
+        while count < GEOFENCE_LIST_MAX_ENTRIES {
+            let Some((slot, fence, inside)) = geofences::next_from(next).await else {
+                next = GEOFENCE_LIST_END as usize;
+                break;
+            };
+            self.response[2 + cursor] = slot;
+            fence.write_record(
+                &mut self.response[3 + cursor..3 + cursor + geofences::FENCE_RECORD_SIZE],
+                inside,
+            );
+            cursor += 1 + geofences::FENCE_RECORD_SIZE;
+            count += 1;
+            next = slot as usize + 1;
+        }
+        if next >= geofences::MAX_FENCES {
+            next = GEOFENCE_LIST_END as usize;
+        }
+        self.response[2] = next as u8;
+        self.response[3] = count as u8;
+        Some(self.encode_response(cursor))
+    }
+
+    async fn handle_delete_geofence(&mut self, payload: &[u8]) -> Option<usize> {
+        let Some(&slot) = payload.first() else {
+            return Some(self.encode_empty_response());
+        };
+        if !geofences::delete(slot).await {
+            defmt::warn!("DELETE_GEOFENCE: slot {} not deleted", slot);
+            return Some(self.encode_empty_response());
+        }
+        self.response[2] = 0x01; // success flag
+        Some(self.encode_response(1))
+    }
+
+    fn handle_bulk_log_files(&mut self, payload: &[u8]) -> Option<usize> {
+        // Payload: [action: 1B] (0 = delete, 1 = archive, followed by
+        //          [start: u32 LE][end: u32 LE] as YYYYMMDD;
+        //          2 / empty = query, 3 = cancel)
+        // Response: file_jobs::progress() record
+        match payload.first().copied().unwrap_or(2) {
+            action @ (0 | 1) => {
+                if payload.len() < 9 {
+                    defmt::warn!("BULK_LOG_FILES: short payload ({} bytes)", payload.len());
+                    return Some(self.encode_empty_response());
+                }
+                let start = u32::from_le_bytes([payload[1], payload[2], payload[3], payload[4]]);
+                let end = u32::from_le_bytes([payload[5], payload[6], payload[7], payload[8]]);
+                let action = if action == 0 {
+                    LogFileAction::Delete
+                } else {
+                    LogFileAction::Archive
+                };
+                if !file_jobs::start(action, start, end) {
+                    defmt::warn!("BULK_LOG_FILES: rejected {}..{}", start, end);
+                    return Some(self.encode_empty_response());
+                }
+            }
+            2 => {}
+            3 => file_jobs::cancel(),
+            action => {
+                defmt::warn!("BULK_LOG_FILES: unknown action {}", action);
+                return Some(self.encode_empty_response());
+            }
+        }
+        let progress = file_jobs::progress();
+        self.response[2..2 + progress.len()].copy_from_slice(&progress);
+        Some(self.encode_response(progress.len()))
+    }
+
+    async fn handle_set_phone_location(&mut self, payload: &[u8]) -> Option<usize> {
+        // Payload: [lat_e7: i32][lon_e7: i32][alt_m: i16][accuracy_m: u16][unix_ts: u32]
+        // Response: [flags: 1B] (bit0 = stored, bit1 = GPS seed queued)
+        let now_ms = embassy_time::Instant::now().as_millis();
+        let Some(location) = payload
+            .try_into()
+            .ok()
+            .and_then(|raw| PhoneLocation::from_bytes(raw, now_ms))
+        else {
+            defmt::warn!("SET_PHONE_LOCATION: invalid payload ({} bytes)", payload.len());
+            return Some(self.encode_empty_response());
+        };
+        // A receiver that already has a fix gains nothing from a coarse seed.
+        let has_fix = SYSTEM_INFO.lock().await.location_valid;
+        let seeded = phone_location::set(location, !has_fix).await;
+        self.response[2] = 0x01 | (u8::from(seeded) << 1);
+        Some(self.encode_response(1))
+    }
+
+    async fn handle_set_time(&mut self, payload: &[u8]) -> Option<usize> {
+        // Payload: [unix_ts: u32]
+        // Response: [flags: 1B] (bit0 = stored, bit1 = GPS time valid)
+        let Ok(raw) = <[u8; 4]>::try_from(payload) else {
+            defmt::warn!("SET_TIME: bad size {}", payload.len());
+            return Some(self.encode_empty_response());
+        };
+        if !phone_location::set_time(u32::from_le_bytes(raw)) {
+            defmt::warn!("SET_TIME: time before the GPS epoch");
+            return Some(self.encode_empty_response());
+        }
+        let gps_time = SYSTEM_INFO.lock().await.date_time_valid;
+        self.response[2] = 0x01 | (u8::from(gps_time) << 1);
+        Some(self.encode_response(1))
+    }
+
+    #[cfg(feature = "lora")]
+    async fn handle_set_lora_config(&mut self, payload: &[u8]) -> Option<usize> {
+        let Ok(config) = <&[u8; lorawan::LORA_CONFIG_LEN]>::try_from(payload) else {
+            defmt::warn!(
+                "SET_LORA_CONFIG: bad size {} (expected {})",
+                payload.len(),
+                lorawan::LORA_CONFIG_LEN
+            );
+            return Some(self.encode_empty_response());
+        };
+        if !lorawan::set_config(config).await {
+            defmt::warn!("SET_LORA_CONFIG: invalid config or SD write failed");
+            return Some(self.encode_empty_response());
+        }
+        self.response[2] = 0x01; // success flag
+        Some(self.encode_response(1))
+    }
+
+    #[cfg(feature = "lora")]
+    async fn handle_get_lora_status(&mut self) -> Option<usize> {
+        // Response: [flags: 1B] [interval_min: u16] [fcnt_up: u32] [last_uplink_age_s: u32]
+        let (enabled, radio_ok, interval_min, fcnt_up, age_s) = lorawan::status().await;
+        self.response[2] = u8::from(enabled) | (u8::from(radio_ok) << 1);
+        self.response[3..5].copy_from_slice(&interval_min.to_le_bytes());
+        self.response[5..9].copy_from_slice(&fcnt_up.to_le_bytes());
+        self.response[9..13].copy_from_slice(&age_s.to_le_bytes());
+        Some(self.encode_response(11))
+    }
+
     fn encode_response(&mut self, payload_len: usize) -> usize {
         let payload_len = core::cmp::min(payload_len, MAX_RESPONSE_PAYLOAD);
         let len_bytes = (payload_len as u16).to_le_bytes();
@@ -489,3 +1877,36 @@ impl FileTransferProtocol {
         2
     }
 }
+
+fn write_track_point(out: &mut [u8], point: &TrackPoint) {
+    out[0..4].copy_from_slice(&point.timestamp.to_le_bytes());
+    out[4..8].copy_from_slice(&point.latitude_e7.to_le_bytes());
+    out[8..12].copy_from_slice(&point.longitude_e7.to_le_bytes());
+    out[12..16].copy_from_slice(&point.altitude_dm.to_le_bytes());
+}
+
+/// Parse a `SET_GEOFENCE` payload; the leading slot byte is not checked here.
+fn parse_geofence(payload: &[u8]) -> Option<geofences::Geofence> {
+    if payload.len() < 13 {
+        return None;
+    }
+    let flags = payload[1];
+    let radius_m = u16::from_le_bytes([payload[2], payload[3]]);
+    let latitude_e7 = i32::from_le_bytes([payload[4], payload[5], payload[6], payload[7]]);
+    let longitude_e7 = i32::from_le_bytes([payload[8], payload[9], payload[10], payload[11]]);
+    let name_len = core::cmp::min(payload[12] as usize, payload.len() - 13);
+    let name = &payload[13..13 + name_len];
+    geofences::Geofence::new(latitude_e7, longitude_e7, radius_m, flags, name)
+}
+
+/// Parse `[lat: i32 LE][lon: i32 LE][name_len: 1B][name]` (degrees * 1e7).
+#[cfg(feature = "nav")]
+fn parse_waypoint(payload: &[u8]) -> Option<waypoints::Waypoint> {
+    if payload.len() < 9 {
+        return None;
+    }
+    let latitude_e7 = i32::from_le_bytes([payload[0], payload[1], payload[2], payload[3]]);
+    let longitude_e7 = i32::from_le_bytes([payload[4], payload[5], payload[6], payload[7]]);
+    let name_len = core::cmp::min(payload[8] as usize, payload.len() - 9);
+    waypoints::Waypoint::new(latitude_e7, longitude_e7, &payload[9..9 + name_len])
+}
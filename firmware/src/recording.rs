@@ -0,0 +1,86 @@
+//! Track recording control.
+//!
+//! By default the tracker records whenever it has power: GPS follows the
+//! motion-driven state machine and every fix in S3 is appended to the day
+//! file. With auto-start disabled the device boots idle and only records after
+//! an explicit start from the button (double press) or BLE.
+//!
+//! # Design
+//!
+//! - `/REC.CFG` holds `RECORDING_CONFIG_LEN` flag bytes; a missing file keeps
+//!   the legacy always-on behavior.
+//! - While not recording the GPS state machine treats the device as
+//!   stationary, so it settles into S2 unless a BLE keep-alive is active, and
+//!   no points are written.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::gps;
+use crate::storage;
+
+pub const RECORDING_CONFIG_LEN: usize = 1;
+
+const FLAG_AUTO_START: u8 = 0x01;
+
+static AUTO_START: AtomicBool = AtomicBool::new(true);
+static RECORDING: AtomicBool = AtomicBool::new(true);
+
+/// Load the auto-start setting from SD and apply it to the boot state.
+/// Call once after the SD logger is initialized, before the GPS task starts.
+pub async fn load() {
+    if let Some(flags) = storage::read_recording_config().await {
+        AUTO_START.store((flags & FLAG_AUTO_START) != 0, Ordering::Release);
+    }
+    let auto_start = auto_start();
+    RECORDING.store(auto_start, Ordering::Release);
+    if !auto_start {
+        defmt::info!("Recording: auto-start disabled, waiting for explicit start");
+    }
+}
+
+pub fn auto_start() -> bool {
+    AUTO_START.load(Ordering::Acquire)
+}
+
+pub fn is_recording() -> bool {
+    RECORDING.load(Ordering::Acquire)
+}
+
+/// Persist the auto-start flag. Applies on the next boot only.
+pub async fn set_auto_start(enabled: bool) -> bool {
+    let flags = if enabled { FLAG_AUTO_START } else { 0 };
+    if !storage::write_recording_config(&[flags]).await {
+        return false;
+    }
+    AUTO_START.store(enabled, Ordering::Release);
+    true
+}
+
+pub async fn start() {
+    if RECORDING.swap(true, Ordering::AcqRel) {
+        return;
+    }
+    defmt::info!("Recording: started");
+    gps::trigger_gps_wakeup().await;
+}
+
+pub async fn stop() {
+    if !RECORDING.swap(false, Ordering::AcqRel) {
+        return;
+    }
+    defmt::info!("Recording: stopped");
+    if !storage::flush_sd_cache().await {
+        defmt::warn!("Recording: SD flush after stop failed");
+    }
+}
+
+/// Toggle recording; returns the new state.
+pub async fn toggle() -> bool {
+    if is_recording() {
+        stop().await;
+        false
+    } else {
+        start().await;
+        true
+    }
+}
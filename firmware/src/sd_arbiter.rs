@@ -0,0 +1,147 @@
+//! Priority arbitration for the shared SD card.
+//!
+//! The GPX logger, config/key files, BLE file transfer and USB mode switches
+//! all go through one `Mutex` around the SD logger. Each access is short and
+//! blocking, but the plain mutex is not fair: a BLE client streaming
+//! `READ_CHUNK` commands re-locks it back to back and can keep the logger's
+//! cache flush waiting for the whole download.
+//!
+//! # Design
+//!
+//! - Every acquisition declares an `SdPriority`. A client backs off while a
+//!   more important client is waiting, so the logger always gets the next
+//!   slot.
+//! - Each priority has a maximum hold time. A guard held longer than that
+//!   puts its class on cooldown for the overrun, which bounds the card share
+//!   a slow operation (e.g. a FAT scan for a large directory) can take.
+
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicU8, AtomicU32, Ordering};
+
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_sync::mutex::{Mutex, MutexGuard};
+use embassy_time::{Instant, Timer};
+
+const PRIORITY_COUNT: usize = 3;
+const BACKOFF_POLL_MS: u64 = 2;
+const MAX_COOLDOWN_MS: u32 = 500;
+
+#[derive(Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum SdPriority {
+    /// GPX points, cache flush and sensor logs.
+    Logger = 0,
+    /// Config/key files and USB mode switches.
+    Config = 1,
+    /// BLE file transfer (list/open/read/delete).
+    Transfer = 2,
+}
+
+impl SdPriority {
+    const fn max_hold_ms(self) -> u32 {
+        match self {
+            SdPriority::Logger => 200,
+            SdPriority::Config => 100,
+            SdPriority::Transfer => 30,
+        }
+    }
+}
+
+static WAITING: [AtomicU8; PRIORITY_COUNT] = [const { AtomicU8::new(0) }; PRIORITY_COUNT];
+/// Uptime (ms) before which a class may not acquire again.
+static COOLDOWN_UNTIL_MS: [AtomicU32; PRIORITY_COUNT] =
+    [const { AtomicU32::new(0) }; PRIORITY_COUNT];
+
+fn now_ms() -> u32 {
+    Instant::now().as_millis() as u32
+}
+
+fn higher_waiting(priority: SdPriority) -> bool {
+    WAITING[..priority as usize]
+        .iter()
+        .any(|count| count.load(Ordering::Acquire) > 0)
+}
+
+fn cooling_down(priority: SdPriority) -> bool {
+    let until = COOLDOWN_UNTIL_MS[priority as usize].load(Ordering::Acquire);
+    (until.wrapping_sub(now_ms()) as i32) > 0
+}
+
+pub struct SdGuard<'a, M: RawMutex, T> {
+    guard: MutexGuard<'a, M, T>,
+    priority: SdPriority,
+    acquired_ms: u32,
+}
+
+impl<M: RawMutex, T> Deref for SdGuard<'_, M, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<M: RawMutex, T> DerefMut for SdGuard<'_, M, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+impl<M: RawMutex, T> Drop for SdGuard<'_, M, T> {
+    fn drop(&mut self) {
+        let held = now_ms().wrapping_sub(self.acquired_ms);
+        let max = self.priority.max_hold_ms();
+        if held > max {
+            let overrun = (held - max).min(MAX_COOLDOWN_MS);
+            COOLDOWN_UNTIL_MS[self.priority as usize]
+                .store(now_ms().wrapping_add(overrun), Ordering::Release);
+            defmt::warn!(
+                "SD: {} held card {}ms (max {}ms)",
+                self.priority,
+                held,
+                max
+            );
+        }
+    }
+}
+
+/// Waiter registration; dropping it also covers a cancelled `lock` future.
+struct Waiting(&'static AtomicU8);
+
+impl Waiting {
+    fn register(priority: SdPriority) -> Self {
+        let count = &WAITING[priority as usize];
+        count.fetch_add(1, Ordering::AcqRel);
+        Self(count)
+    }
+}
+
+impl Drop for Waiting {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// Lock `mutex` on behalf of a `priority` client.
+pub async fn lock<M: RawMutex, T>(
+    mutex: &Mutex<M, T>,
+    priority: SdPriority,
+) -> SdGuard<'_, M, T> {
+    let waiting = Waiting::register(priority);
+    let guard = loop {
+        while higher_waiting(priority) || cooling_down(priority) {
+            Timer::after_millis(BACKOFF_POLL_MS).await;
+        }
+        let guard = mutex.lock().await;
+        // A more important client may have queued while this one waited.
+        if !higher_waiting(priority) {
+            break guard;
+        }
+        drop(guard);
+    };
+    drop(waiting);
+    SdGuard {
+        guard,
+        priority,
+        acquired_ms: now_ms(),
+    }
+}
@@ -0,0 +1,581 @@
+//! Named recording sessions.
+//!
+//! A session marks an intentional recording (a run, a ride, a drive) on top
+//! of the always-appending day files: it stores a name, an activity type and
+//! the UTC time range, so host tools can cut the matching points out of the
+//! day files and list sessions without scanning tracks.
+//!
+//! Each session is also tagged with the activity the classifier saw for most
+//! of its points, so the app can tell rides from walks without asking. A
+//! session resumed after a reboot carries on from its stored label and share.
+//!
+//! Sessions live in RAM and are mirrored to `/SESSIONS.DB` on the SD card as
+//! `MAX_SESSIONS` fixed-size records. When the table is full the oldest
+//! finished session is overwritten.
+//!
+//! # Record layout (`SESSION_RECORD_SIZE` bytes, little-endian)
+//!
+//! | Offset | Size | Field                                        |
+//! | :----- | :--- | :------------------------------------------- |
+//! | 0      | 1    | flags (bit0 in use, bit1 open, bit2 paused)  |
+//! | 1      | 1    | activity type                                |
+//! | 2      | 2    | sequence number                              |
+//! | 4      | 4    | start, unix seconds (0 = no GPS time yet)    |
+//! | 8      | 4    | end, unix seconds (0 while open)             |
+//! | 12     | 4    | logged point count                           |
+//! | 16     | 4    | total paused seconds                         |
+//! | 20     | 1    | name length                                  |
+//! | 21     | 1    | detected activity type (0 = none yet)        |
+//! | 22     | 1    | detected activity's share of points, percent |
+//! | 23     | 1    | reserved (0)                                 |
+//! | 24     | 24   | name, UTF-8, zero padded                     |
+//!
+//! Activity types: 0 other, 1 walk, 2 run, 3 cycle, 4 drive, 5 hike. The
+//! firmware stores the byte as-is, so hosts may define more. The detected
+//! type is only ever 1, 3 or 4.
+//!
+//! # Metadata sidecar (`/SESSMETA.DB`)
+//!
+//! The app can attach a color, notes and a corrected activity to a session
+//! after the fact. They go to a second table of `MAX_SESSIONS` records of
+//! `META_RECORD_SIZE` bytes, one per session slot, so the session table
+//! keeps its layout. The table is only read on demand, not kept in RAM.
+//!
+//! | Offset | Size | Field                                           |
+//! | :----- | :--- | :---------------------------------------------- |
+//! | 0      | 1    | flags (bit0 in use)                             |
+//! | 1      | 1    | activity override (0xFF = the session's own)    |
+//! | 2      | 2    | sequence number of the session                  |
+//! | 4      | 3    | color, R G B                                    |
+//! | 7      | 1    | notes length                                    |
+//! | 8      | 40   | notes, UTF-8, zero padded                       |
+//!
+//! A record whose sequence number differs from the session now in its slot
+//! belongs to an overwritten session and is ignored.
+
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::mutex::Mutex;
+use embassy_time::Instant;
+
+use crate::activity::{Activity, ActivityTally};
+use crate::recording;
+use crate::storage;
+use crate::system_info::SYSTEM_INFO;
+use crate::timezone;
+
+pub const MAX_SESSIONS: usize = 32;
+pub const SESSION_RECORD_SIZE: usize = 48;
+pub const SESSION_NAME_MAX: usize = 24;
+pub const SESSION_DB_SIZE: usize = MAX_SESSIONS * SESSION_RECORD_SIZE;
+pub const META_RECORD_SIZE: usize = 48;
+pub const META_NOTES_MAX: usize = 40;
+pub const META_DB_SIZE: usize = MAX_SESSIONS * META_RECORD_SIZE;
+
+const FLAG_IN_USE: u8 = 0x01;
+const FLAG_OPEN: u8 = 0x02;
+const FLAG_PAUSED: u8 = 0x04;
+
+/// State reported by `SESSION_CONTROL`.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SessionState {
+    None = 0,
+    Active = 1,
+    Paused = 2,
+}
+
+#[derive(Clone, Copy)]
+pub enum SessionAction {
+    Stop,
+    Pause,
+    Resume,
+}
+
+#[derive(Clone, Copy)]
+pub struct Session {
+    flags: u8,
+    pub activity: u8,
+    pub seq: u16,
+    pub start_ts: u32,
+    pub end_ts: u32,
+    pub points: u32,
+    pub paused_secs: u32,
+    /// Activity type of most labelled points, 0 before any.
+    pub detected: u8,
+    pub detected_pct: u8,
+    name: [u8; SESSION_NAME_MAX],
+    name_len: u8,
+}
+
+impl Session {
+    fn new(seq: u16, activity: u8, name: &[u8], start_ts: u32) -> Self {
+        let mut session = Self {
+            flags: FLAG_IN_USE | FLAG_OPEN,
+            activity,
+            seq,
+            start_ts,
+            end_ts: 0,
+            points: 0,
+            paused_secs: 0,
+            detected: 0,
+            detected_pct: 0,
+            name: [0; SESSION_NAME_MAX],
+            name_len: 0,
+        };
+        let len = core::cmp::min(name.len(), SESSION_NAME_MAX);
+        session.name[..len].copy_from_slice(&name[..len]);
+        session.name_len = len as u8;
+        session
+    }
+
+    pub fn is_open(&self) -> bool {
+        (self.flags & FLAG_OPEN) != 0
+    }
+
+    pub fn is_paused(&self) -> bool {
+        (self.flags & FLAG_PAUSED) != 0
+    }
+
+    pub fn write_record(&self, out: &mut [u8]) {
+        out[0] = self.flags;
+        out[1] = self.activity;
+        out[2..4].copy_from_slice(&self.seq.to_le_bytes());
+        out[4..8].copy_from_slice(&self.start_ts.to_le_bytes());
+        out[8..12].copy_from_slice(&self.end_ts.to_le_bytes());
+        out[12..16].copy_from_slice(&self.points.to_le_bytes());
+        out[16..20].copy_from_slice(&self.paused_secs.to_le_bytes());
+        out[20] = self.name_len;
+        out[21] = self.detected;
+        out[22] = self.detected_pct;
+        out[23] = 0;
+        out[24..24 + SESSION_NAME_MAX].copy_from_slice(&self.name);
+    }
+
+    fn read_record(data: &[u8]) -> Option<Self> {
+        if (data[0] & FLAG_IN_USE) == 0 {
+            return None;
+        }
+        let read_u32 =
+            |at: usize| u32::from_le_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]]);
+        let name_len = core::cmp::min(data[20] as usize, SESSION_NAME_MAX);
+        let mut session = Self::new(
+            u16::from_le_bytes([data[2], data[3]]),
+            data[1],
+            &data[24..24 + name_len],
+            read_u32(4),
+        );
+        session.flags = data[0];
+        session.end_ts = read_u32(8);
+        session.points = read_u32(12);
+        session.paused_secs = read_u32(16);
+        session.detected = data[21];
+        session.detected_pct = data[22];
+        Some(session)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct SessionMeta {
+    pub activity: u8,
+    pub color: [u8; 3],
+    notes: [u8; META_NOTES_MAX],
+    notes_len: u8,
+}
+
+impl SessionMeta {
+    /// The notes are truncated to `META_NOTES_MAX` bytes.
+    pub fn new(activity: u8, color: [u8; 3], notes: &[u8]) -> Self {
+        let len = core::cmp::min(notes.len(), META_NOTES_MAX);
+        let mut meta = Self {
+            activity,
+            color,
+            notes: [0; META_NOTES_MAX],
+            notes_len: len as u8,
+        };
+        meta.notes[..len].copy_from_slice(&notes[..len]);
+        meta
+    }
+
+    pub fn write_record(&self, seq: u16, out: &mut [u8]) {
+        out[0] = FLAG_IN_USE;
+        out[1] = self.activity;
+        out[2..4].copy_from_slice(&seq.to_le_bytes());
+        out[4..7].copy_from_slice(&self.color);
+        out[7] = self.notes_len;
+        out[8..8 + META_NOTES_MAX].copy_from_slice(&self.notes);
+    }
+
+    /// Decode a record if it is in use and belongs to session `seq`.
+    fn read_record(data: &[u8], seq: u16) -> Option<Self> {
+        if (data[0] & FLAG_IN_USE) == 0 || u16::from_le_bytes([data[2], data[3]]) != seq {
+            return None;
+        }
+        let notes_len = core::cmp::min(data[7] as usize, META_NOTES_MAX);
+        Some(Self::new(data[1], [data[4], data[5], data[6]], &data[8..8 + notes_len]))
+    }
+}
+
+struct SessionDb {
+    slots: [Option<Session>; MAX_SESSIONS],
+    /// Slot of the open session, if any.
+    current: Option<usize>,
+    /// Uptime when the open session was paused (not persisted).
+    paused_since_ms: Option<u64>,
+    /// Timestamp of the last point logged in the open session (not persisted).
+    last_point_ts: u32,
+    /// Labels of the open session's points (rebuilt from the record on
+    /// resume).
+    tally: ActivityTally,
+}
+
+impl SessionDb {
+    const fn new() -> Self {
+        Self {
+            slots: [None; MAX_SESSIONS],
+            current: None,
+            paused_since_ms: None,
+            last_point_ts: 0,
+            tally: ActivityTally::new(),
+        }
+    }
+
+    fn serialize(&self, out: &mut [u8; SESSION_DB_SIZE]) {
+        out.fill(0);
+        for (slot, chunk) in self.slots.iter().zip(out.chunks_exact_mut(SESSION_RECORD_SIZE)) {
+            if let Some(session) = slot {
+                session.write_record(chunk);
+            }
+        }
+    }
+
+    fn next_seq(&self) -> u16 {
+        self.slots
+            .iter()
+            .flatten()
+            .map(|s| s.seq)
+            .max()
+            .map(|seq| seq.wrapping_add(1))
+            .unwrap_or(0)
+    }
+
+    /// First free slot, otherwise the oldest finished session.
+    fn free_slot(&self) -> Option<usize> {
+        if let Some(idx) = self.slots.iter().position(|slot| slot.is_none()) {
+            return Some(idx);
+        }
+        self.slots
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, slot)| slot.map(|s| (idx, s)))
+            .filter(|(_, s)| !s.is_open())
+            .min_by_key(|(_, s)| s.seq)
+            .map(|(idx, _)| idx)
+    }
+
+    /// End the open session now, or at its last logged point without GPS
+    /// time.
+    fn close(&mut self, now_ts: u32, now_ms: u64) {
+        self.fold_pause(now_ms);
+        let end_ts = match now_ts {
+            0 => self.last_point_ts,
+            now_ts => now_ts,
+        };
+        if let Some(session) = self.current.and_then(|idx| self.slots[idx].as_mut()) {
+            session.flags &= !(FLAG_OPEN | FLAG_PAUSED);
+            session.end_ts = end_ts;
+        }
+    }
+
+    fn fold_pause(&mut self, now_ms: u64) {
+        let (Some(idx), Some(since)) = (self.current, self.paused_since_ms.take()) else {
+            return;
+        };
+        if let Some(session) = self.slots[idx].as_mut() {
+            let paused = (now_ms.saturating_sub(since) / 1000) as u32;
+            session.paused_secs = session.paused_secs.saturating_add(paused);
+        }
+    }
+}
+
+static SESSIONS: Mutex<CriticalSectionRawMutex, SessionDb> = Mutex::new(SessionDb::new());
+
+async fn gps_unix_ts() -> u32 {
+    let info = *SYSTEM_INFO.lock().await;
+    if !info.date_time_valid {
+        // Indoors before the first fix the phone time is better than none.
+        return crate::phone_location::unix_now().map_or(0, |unix_ts| unix_ts as u32);
+    }
+    timezone::date_time_to_unix_timestamp(
+        info.year,
+        info.month,
+        info.day,
+        info.hour,
+        info.minute,
+        info.second,
+    )
+    .unwrap_or(0)
+}
+
+async fn persist(db: &SessionDb) -> bool {
+    let mut buf = [0u8; SESSION_DB_SIZE];
+    db.serialize(&mut buf);
+    storage::write_session_db(&buf).await
+}
+
+/// Load sessions from SD card and resume an open session interrupted by a
+/// reboot. Call after `recording::load()`.
+pub async fn load() {
+    let mut buf = [0u8; SESSION_DB_SIZE];
+    let Some(len) = storage::read_session_db(&mut buf).await else {
+        defmt::info!("Sessions: no database on SD");
+        return;
+    };
+    let mut db = SESSIONS.lock().await;
+    let mut count = 0usize;
+    let mut current = None;
+    for (idx, (slot, chunk)) in db
+        .slots
+        .iter_mut()
+        .zip(buf[..len].chunks_exact(SESSION_RECORD_SIZE))
+        .enumerate()
+    {
+        *slot = Session::read_record(chunk);
+        if let Some(session) = slot {
+            count += 1;
+            if session.is_open() {
+                current = Some(idx);
+            }
+        }
+    }
+    db.current = current;
+    defmt::info!("Sessions: loaded {} from SD", count);
+
+    if let Some(session) = db.current.and_then(|idx| db.slots[idx]) {
+        let label = classifier_label(session.detected);
+        db.tally = ActivityTally::seeded(label, session.detected_pct, session.points);
+        if session.is_paused() {
+            db.paused_since_ms = Some(Instant::now().as_millis());
+            recording::stop().await;
+        } else {
+            recording::start().await;
+        }
+        defmt::info!("Sessions: resumed open session seq={}", session.seq);
+    }
+}
+
+/// Open a new session and start recording. Returns the slot index.
+/// Fails while another session is open.
+pub async fn start(activity: u8, name: &[u8]) -> Option<u8> {
+    let start_ts = gps_unix_ts().await;
+    let mut db = SESSIONS.lock().await;
+    if db.current.is_some() {
+        defmt::warn!("Sessions: start rejected, session already open");
+        return None;
+    }
+    let idx = db.free_slot()?;
+    let previous = db.slots[idx];
+    let seq = db.next_seq();
+    db.slots[idx] = Some(Session::new(seq, activity, name, start_ts));
+    if !persist(&db).await {
+        db.slots[idx] = previous;
+        return None;
+    }
+    db.current = Some(idx);
+    db.paused_since_ms = None;
+    db.last_point_ts = start_ts;
+    db.tally = ActivityTally::new();
+    drop(db);
+
+    defmt::info!("Sessions: started seq={} activity={}", seq, activity);
+    recording::start().await;
+    Some(idx as u8)
+}
+
+/// Stop, pause or resume the open session.
+pub async fn control(action: SessionAction) -> bool {
+    let now_ts = gps_unix_ts().await;
+    let now_ms = Instant::now().as_millis();
+    let mut db = SESSIONS.lock().await;
+    let Some(idx) = db.current else {
+        return false;
+    };
+    let Some(previous) = db.slots[idx] else {
+        return false;
+    };
+    let paused_since = db.paused_since_ms;
+
+    match action {
+        SessionAction::Stop => db.close(now_ts, now_ms),
+        SessionAction::Pause => {
+            if previous.is_paused() {
+                return true;
+            }
+            db.paused_since_ms = Some(now_ms);
+            if let Some(session) = db.slots[idx].as_mut() {
+                session.flags |= FLAG_PAUSED;
+            }
+        }
+        SessionAction::Resume => {
+            if !previous.is_paused() {
+                return true;
+            }
+            db.fold_pause(now_ms);
+            if let Some(session) = db.slots[idx].as_mut() {
+                session.flags &= !FLAG_PAUSED;
+            }
+        }
+    }
+
+    if !persist(&db).await {
+        db.slots[idx] = Some(previous);
+        db.paused_since_ms = paused_since;
+        return false;
+    }
+    if matches!(action, SessionAction::Stop) {
+        db.current = None;
+    }
+    drop(db);
+
+    match action {
+        SessionAction::Stop => {
+            defmt::info!("Sessions: stopped seq={}", previous.seq);
+            // Fall back to the boot-time recording policy.
+            if recording::auto_start() {
+                recording::start().await;
+            } else {
+                recording::stop().await;
+            }
+        }
+        SessionAction::Pause => {
+            defmt::info!("Sessions: paused seq={}", previous.seq);
+            recording::stop().await;
+        }
+        SessionAction::Resume => {
+            defmt::info!("Sessions: resumed seq={}", previous.seq);
+            recording::start().await;
+        }
+    }
+    true
+}
+
+/// Close the open session before power off. Unlike a stop this leaves the
+/// recording state alone; nothing is logged after it anyway.
+pub async fn close_for_power_off() -> bool {
+    let now_ts = gps_unix_ts().await;
+    let mut db = SESSIONS.lock().await;
+    let open = db.current.and_then(|idx| db.slots[idx]);
+    let Some(seq) = open.map(|session| session.seq) else {
+        return true;
+    };
+    db.close(now_ts, Instant::now().as_millis());
+    if !persist(&db).await {
+        return false;
+    }
+    db.current = None;
+    defmt::info!("Sessions: closed seq={} for power off", seq);
+    true
+}
+
+/// State and slot of the open session (`0xFF` when none).
+pub async fn status() -> (SessionState, u8) {
+    let db = SESSIONS.lock().await;
+    match db.current.and_then(|idx| db.slots[idx].map(|s| (idx, s))) {
+        Some((idx, session)) if session.is_paused() => (SessionState::Paused, idx as u8),
+        Some((idx, _)) => (SessionState::Active, idx as u8),
+        None => (SessionState::None, 0xFF),
+    }
+}
+
+/// Toggle pause on the open session. Returns `false` if no session is open.
+pub async fn toggle_pause() -> bool {
+    match status().await.0 {
+        SessionState::None => false,
+        SessionState::Active => control(SessionAction::Pause).await,
+        SessionState::Paused => control(SessionAction::Resume).await,
+    }
+}
+
+/// Account a logged track point and its activity label to the open session
+/// (RAM only; the count is persisted on the next pause/stop).
+pub async fn note_point(timestamp: u32, activity: Activity) {
+    let mut db = SESSIONS.lock().await;
+    let Some(idx) = db.current else {
+        return;
+    };
+    db.last_point_ts = timestamp;
+    db.tally.note(activity);
+    let (dominant, share_pct) = db.tally.dominant();
+    if let Some(session) = db.slots[idx].as_mut() {
+        if session.start_ts == 0 {
+            session.start_ts = timestamp;
+        }
+        session.points = session.points.saturating_add(1);
+        if dominant != Activity::Unknown {
+            session.detected = session_activity(dominant);
+            session.detected_pct = share_pct;
+        }
+    }
+}
+
+/// Session activity type of a classifier label.
+fn session_activity(activity: Activity) -> u8 {
+    match activity {
+        Activity::Unknown => 0,
+        Activity::Walk => 1,
+        Activity::Cycle => 3,
+        Activity::Drive => 4,
+    }
+}
+
+/// Classifier label of a detected session activity type.
+fn classifier_label(detected: u8) -> Activity {
+    match detected {
+        1 => Activity::Walk,
+        3 => Activity::Cycle,
+        4 => Activity::Drive,
+        _ => Activity::Unknown,
+    }
+}
+
+/// Return the next used slot at or after `start`.
+pub async fn next_from(start: usize) -> Option<(u8, Session)> {
+    let db = SESSIONS.lock().await;
+    db.slots
+        .iter()
+        .enumerate()
+        .skip(start)
+        .find_map(|(idx, slot)| slot.map(|s| (idx as u8, s)))
+}
+
+/// Attach metadata to the session in `slot`, replacing any earlier record.
+/// Fails if the slot is empty or the SD card cannot be written.
+pub async fn set_meta(slot: u8, meta: SessionMeta) -> bool {
+    // Held across the read-modify-write, like `persist`.
+    let db = SESSIONS.lock().await;
+    let Some(session) = db.slots.get(slot as usize).copied().flatten() else {
+        return false;
+    };
+    let mut buf = [0u8; META_DB_SIZE];
+    if storage::read_session_meta_db(&mut buf).await.is_none() {
+        buf.fill(0);
+    }
+    let at = slot as usize * META_RECORD_SIZE;
+    meta.write_record(session.seq, &mut buf[at..at + META_RECORD_SIZE]);
+    storage::write_session_meta_db(&buf).await
+}
+
+/// Sequence number and metadata of the session in `slot`; `None` if the
+/// slot is empty, an inner `None` if the session has no metadata.
+pub async fn meta(slot: u8) -> Option<(u16, Option<SessionMeta>)> {
+    let db = SESSIONS.lock().await;
+    let session = db.slots.get(slot as usize).copied().flatten()?;
+    let mut buf = [0u8; META_DB_SIZE];
+    let meta = match storage::read_session_meta_db(&mut buf).await {
+        Some(_) => {
+            let at = slot as usize * META_RECORD_SIZE;
+            SessionMeta::read_record(&buf[at..at + META_RECORD_SIZE], session.seq)
+        }
+        None => None,
+    };
+    Some((session.seq, meta))
+}
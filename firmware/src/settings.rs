@@ -0,0 +1,750 @@
+//! Key-value settings registry.
+//!
+//! Every user-facing setting is listed in `ENTRIES` with a numeric id, a key,
+//! a type with its range and a default, so the companion app can render a
+//! settings UI from the generic `SETTINGS` command without knowing each
+//! feature.
+//!
+//! # Design
+//!
+//! - Ids are part of the protocol: never renumber, only append. The high
+//!   byte groups ids by feature.
+//! - Values are `i32` on the wire; bools are 0/1.
+//! - Settings that predate the registry keep their own file and module
+//!   (`/REC.CFG`, `/TZ.CFG`, `/LOCK.CFG`); the registry only validates and
+//!   dispatches, so the feature commands and the registry see one state.
+//! - Newer settings are owned by the registry and persisted together in
+//!   `/SETTINGS.CFG` as `[id: u16][value: i32]` records. An unknown id or
+//!   an out-of-range value there falls back to the default.
+//! - The first record, id `VERSION_ID`, holds `SCHEMA_VERSION`; files from
+//!   before it have none and read as version 0. Older firmware skips the
+//!   record as an unknown id, and since ids are never reused a file from
+//!   newer firmware loads too. Bump the version when a stored value
+//!   changes meaning and convert it in `migrate`.
+//! - The app reaches the registry with the `SETTINGS` command or the
+//!   config GATT characteristic; both go through `access`.
+//! - Blobs and lists (LoRa keys, GPIO hook rules, waypoints) are not
+//!   settings and keep their dedicated commands.
+
+use core::sync::atomic::{AtomicI32, Ordering};
+
+use crate::guest;
+use crate::recording;
+use crate::storage;
+use crate::timezone::{self, TzOverride, TzSettings};
+
+pub const REC_AUTO_START: u16 = 0x0101;
+pub const TZ_MODE: u16 = 0x0201;
+pub const TZ_FIXED_OFFSET_MIN: u16 = 0x0202;
+pub const TZ_ZONE_ID: u16 = 0x0203;
+pub const TZ_LOCAL_MIDNIGHT: u16 = 0x0204;
+pub const BLE_LOCKDOWN: u16 = 0x0301;
+pub const BLE_BTHOME: u16 = 0x0302;
+pub const BLE_BONDED_ONLY: u16 = 0x0303;
+pub const BLE_NMEA_PASSTHROUGH: u16 = 0x0304;
+pub const DISPLAY_TIMEOUT_S: u16 = 0x0401;
+pub const DISPLAY_PANEL: u16 = 0x0402;
+pub const DISPLAY_FLIP: u16 = 0x0403;
+pub const DISPLAY_SPLASH: u16 = 0x0404;
+pub const DISPLAY_GCJ02: u16 = 0x0405;
+pub const GPS_PROFILE: u16 = 0x0501;
+pub const GPS_SAMPLE_INTERVAL_MS: u16 = 0x0502;
+pub const GPS_STILL_CONFIRM_S: u16 = 0x0503;
+pub const GPS_STILL_QUERY_S: u16 = 0x0504;
+pub const GPS_COLD_FIX_S: u16 = 0x0505;
+pub const GPS_REACQUIRE_FIX_S: u16 = 0x0506;
+pub const GPS_MAX_FIX_FAILURES: u16 = 0x0507;
+pub const GPS_NMEA_SILENCE_S: u16 = 0x0508;
+pub const GPS_HARD_RESET_ERRORS: u16 = 0x0509;
+pub const GPS_WALK_KMH: u16 = 0x050A;
+pub const GPS_WALK_INTERVAL_MS: u16 = 0x050B;
+pub const GPS_WAKE_INTERVAL_MIN: u16 = 0x050C;
+pub const GPS_LOW_BATT_WAKE_MIN: u16 = 0x050D;
+pub const GPS_LOW_BATT_PCT: u16 = 0x050E;
+pub const GPS_AGNSS_SKIP_HDOP: u16 = 0x050F;
+pub const GPS_AGNSS_DEFER_PCT: u16 = 0x0510;
+pub const GPS_FIX_BACKOFF_S: u16 = 0x0511;
+pub const USB_GPX_EXPORT: u16 = 0x0601;
+pub const USB_CONFIRM: u16 = 0x0602;
+pub const USB_HOST_TIMEOUT_S: u16 = 0x0603;
+pub const GEOFENCE_BANNER: u16 = 0x0701;
+pub const POWER_SOLAR: u16 = 0x0801;
+pub const POWER_CUTOFF_MV: u16 = 0x0802;
+pub const POWER_BATTERY_MAH: u16 = 0x0803;
+pub const FINDMY_ENABLED: u16 = 0x0901;
+pub const FINDMY_INTERVAL_MS: u16 = 0x0902;
+pub const ALERT_GEOFENCE: u16 = 0x0A01;
+pub const ALERT_BATTERY: u16 = 0x0A02;
+pub const ALERT_KEEP_ALIVE: u16 = 0x0A03;
+pub const ALERT_STORM: u16 = 0x0A04;
+pub const BUTTON_DOUBLE_PRESS: u16 = 0x0B01;
+pub const SOUND_VOLUME: u16 = 0x0C01;
+pub const SOUND_RING_S: u16 = 0x0C02;
+pub const SOUND_RING_PATTERN: u16 = 0x0C03;
+pub const WEATHER_STORM_DROP: u16 = 0x0D01;
+
+/// Record size in `/SETTINGS.CFG`.
+const RECORD_LEN: usize = 6;
+/// Version record plus one record per stored setting.
+pub const SETTINGS_FILE_MAX_LEN: usize = (STORED_COUNT + 1) * RECORD_LEN;
+/// Reserved id of the schema version record.
+const VERSION_ID: u16 = 0x0000;
+const SCHEMA_VERSION: i32 = 2;
+/// `access` result: `[status: u8][id: u16][value: i32]`.
+pub const ACCESS_RESULT_LEN: usize = 7;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Bool,
+    Int { min: i32, max: i32 },
+}
+
+impl Kind {
+    pub fn id(self) -> u8 {
+        match self {
+            Kind::Bool => 0,
+            Kind::Int { .. } => 1,
+        }
+    }
+
+    pub fn range(self) -> (i32, i32) {
+        match self {
+            Kind::Bool => (0, 1),
+            Kind::Int { min, max } => (min, max),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Backing {
+    /// Slot in `STORED`, persisted in `/SETTINGS.CFG`.
+    Stored(usize),
+    AutoStart,
+    TzMode,
+    TzFixedOffset,
+    TzZone,
+    TzLocalMidnight,
+    Lockdown,
+}
+
+pub struct Entry {
+    pub id: u16,
+    pub key: &'static str,
+    pub kind: Kind,
+    pub default: i32,
+    backing: Backing,
+}
+
+const STORED_COUNT: usize = 43;
+
+pub static ENTRIES: [Entry; ENTRY_COUNT] = ENTRY_TABLE;
+
+const ENTRY_COUNT: usize = 49;
+
+const ENTRY_TABLE: [Entry; ENTRY_COUNT] = [
+    Entry {
+        id: REC_AUTO_START,
+        key: "rec.auto_start",
+        kind: Kind::Bool,
+        default: 1,
+        backing: Backing::AutoStart,
+    },
+    Entry {
+        id: TZ_MODE,
+        key: "tz.mode",
+        // 0 = auto, 1 = fixed offset, 2 = pinned zone.
+        kind: Kind::Int { min: 0, max: 2 },
+        default: 0,
+        backing: Backing::TzMode,
+    },
+    Entry {
+        id: TZ_FIXED_OFFSET_MIN,
+        key: "tz.fixed_offset_min",
+        kind: Kind::Int {
+            min: -14 * 60,
+            max: 14 * 60,
+        },
+        default: 0,
+        backing: Backing::TzFixedOffset,
+    },
+    Entry {
+        id: TZ_ZONE_ID,
+        key: "tz.zone_id",
+        kind: Kind::Int {
+            min: 0,
+            max: u16::MAX as i32,
+        },
+        default: 0,
+        backing: Backing::TzZone,
+    },
+    Entry {
+        id: TZ_LOCAL_MIDNIGHT,
+        key: "tz.local_midnight",
+        kind: Kind::Bool,
+        default: 0,
+        backing: Backing::TzLocalMidnight,
+    },
+    Entry {
+        id: BLE_LOCKDOWN,
+        key: "ble.lockdown",
+        kind: Kind::Bool,
+        default: 0,
+        backing: Backing::Lockdown,
+    },
+    Entry {
+        id: BLE_BTHOME,
+        key: "ble.bthome",
+        kind: Kind::Bool,
+        default: 0,
+        backing: Backing::Stored(1),
+    },
+    Entry {
+        id: BLE_BONDED_ONLY,
+        key: "ble.bonded_only",
+        // File transfer and config writes only from a bonded, encrypted link;
+        // on until turned off, so a fresh device needs a bonded phone.
+        kind: Kind::Bool,
+        default: 1,
+        backing: Backing::Stored(34),
+    },
+    Entry {
+        id: BLE_NMEA_PASSTHROUGH,
+        key: "ble.nmea_passthrough",
+        // The NMEA GATT service reaches the GPS UART unchecked.
+        kind: Kind::Bool,
+        default: 0,
+        backing: Backing::Stored(41),
+    },
+    Entry {
+        id: DISPLAY_TIMEOUT_S,
+        key: "display.timeout_s",
+        kind: Kind::Int { min: 5, max: 600 },
+        default: 30,
+        backing: Backing::Stored(0),
+    },
+    Entry {
+        id: DISPLAY_PANEL,
+        key: "display.panel",
+        // 0 = auto, 1 = SSD1306 128x64, 2 = SH1106 128x64, 3 = SSD1306 64x48.
+        // Read once when the display starts, so it takes effect on reboot.
+        kind: Kind::Int { min: 0, max: 3 },
+        default: 0,
+        backing: Backing::Stored(16),
+    },
+    Entry {
+        id: DISPLAY_FLIP,
+        key: "display.flip",
+        // Applies at the next frame.
+        kind: Kind::Bool,
+        default: 0,
+        backing: Backing::Stored(17),
+    },
+    Entry {
+        id: DISPLAY_SPLASH,
+        key: "display.splash",
+        // 0 = none, straight to the first page, 1 = Ferris, 2 = `/SPLASH.PBM`.
+        kind: Kind::Int { min: 0, max: 2 },
+        default: 1,
+        backing: Backing::Stored(28),
+    },
+    Entry {
+        id: DISPLAY_GCJ02,
+        key: "display.gcj02",
+        // Main page position in GCJ-02 inside mainland China; logs stay WGS-84.
+        kind: Kind::Bool,
+        default: 0,
+        backing: Backing::Stored(40),
+    },
+    Entry {
+        id: GPS_PROFILE,
+        key: "gps.profile",
+        // 0 = default, 1 = long search, 2 = power saver, 3 = custom (below).
+        kind: Kind::Int { min: 0, max: 3 },
+        default: 0,
+        backing: Backing::Stored(2),
+    },
+    Entry {
+        id: GPS_SAMPLE_INTERVAL_MS,
+        key: "gps.sample_interval_ms",
+        // The `gps.*` values below only apply with `gps.profile` = 3.
+        kind: Kind::Int {
+            min: 200,
+            max: 10_000,
+        },
+        default: 1000,
+        backing: Backing::Stored(3),
+    },
+    Entry {
+        id: GPS_STILL_CONFIRM_S,
+        key: "gps.still_confirm_s",
+        kind: Kind::Int { min: 5, max: 600 },
+        default: 60,
+        backing: Backing::Stored(4),
+    },
+    Entry {
+        id: GPS_STILL_QUERY_S,
+        key: "gps.still_query_s",
+        kind: Kind::Int { min: 1, max: 60 },
+        default: 5,
+        backing: Backing::Stored(5),
+    },
+    Entry {
+        id: GPS_COLD_FIX_S,
+        key: "gps.cold_fix_s",
+        kind: Kind::Int { min: 10, max: 900 },
+        default: 90,
+        backing: Backing::Stored(6),
+    },
+    Entry {
+        id: GPS_REACQUIRE_FIX_S,
+        key: "gps.reacquire_fix_s",
+        kind: Kind::Int { min: 5, max: 900 },
+        default: 30,
+        backing: Backing::Stored(7),
+    },
+    Entry {
+        id: GPS_MAX_FIX_FAILURES,
+        key: "gps.max_fix_failures",
+        kind: Kind::Int { min: 1, max: 255 },
+        default: 16,
+        backing: Backing::Stored(8),
+    },
+    Entry {
+        id: GPS_NMEA_SILENCE_S,
+        key: "gps.nmea_silence_s",
+        kind: Kind::Int { min: 3, max: 120 },
+        default: 10,
+        backing: Backing::Stored(9),
+    },
+    Entry {
+        id: GPS_HARD_RESET_ERRORS,
+        key: "gps.hard_reset_errors",
+        kind: Kind::Int {
+            min: 1,
+            max: 10_000,
+        },
+        default: 32,
+        backing: Backing::Stored(10),
+    },
+    Entry {
+        id: GPS_WALK_KMH,
+        key: "gps.walk_kmh",
+        // The adaptive values below apply with every profile.
+        kind: Kind::Int { min: 1, max: 30 },
+        default: 6,
+        backing: Backing::Stored(29),
+    },
+    Entry {
+        id: GPS_WALK_INTERVAL_MS,
+        key: "gps.walk_interval_ms",
+        // 0 = sample at the profile rate at any speed.
+        kind: Kind::Int {
+            min: 0,
+            max: 30_000,
+        },
+        default: 5000,
+        backing: Backing::Stored(30),
+    },
+    Entry {
+        id: GPS_WAKE_INTERVAL_MIN,
+        key: "gps.wake_interval_min",
+        // 0 = only motion wakes the GPS.
+        kind: Kind::Int { min: 0, max: 1440 },
+        default: 15,
+        backing: Backing::Stored(31),
+    },
+    Entry {
+        id: GPS_LOW_BATT_WAKE_MIN,
+        key: "gps.low_batt_wake_min",
+        kind: Kind::Int { min: 0, max: 1440 },
+        default: 60,
+        backing: Backing::Stored(32),
+    },
+    Entry {
+        id: GPS_LOW_BATT_PCT,
+        key: "gps.low_batt_pct",
+        kind: Kind::Int { min: 0, max: 100 },
+        default: 20,
+        backing: Backing::Stored(33),
+    },
+    Entry {
+        id: GPS_AGNSS_SKIP_HDOP,
+        key: "gps.agnss_skip_hdop",
+        // Tenths of HDOP; 0 = upload even with a good fix.
+        kind: Kind::Int { min: 0, max: 200 },
+        default: 15,
+        backing: Backing::Stored(35),
+    },
+    Entry {
+        id: GPS_AGNSS_DEFER_PCT,
+        key: "gps.agnss_defer_pct",
+        // 0 = upload at any battery level.
+        kind: Kind::Int { min: 0, max: 100 },
+        default: 10,
+        backing: Backing::Stored(36),
+    },
+    Entry {
+        id: GPS_FIX_BACKOFF_S,
+        key: "gps.fix_backoff_s",
+        // Last rung of the fix-failure ladder; 0 = start over instead.
+        kind: Kind::Int { min: 0, max: 3600 },
+        default: 300,
+        backing: Backing::Stored(37),
+    },
+    Entry {
+        id: USB_GPX_EXPORT,
+        key: "usb.gpx_export",
+        kind: Kind::Bool,
+        default: 0,
+        backing: Backing::Stored(11),
+    },
+    Entry {
+        id: USB_CONFIRM,
+        key: "usb.confirm",
+        kind: Kind::Bool,
+        default: 1,
+        backing: Backing::Stored(13),
+    },
+    Entry {
+        id: USB_HOST_TIMEOUT_S,
+        key: "usb.host_timeout_s",
+        kind: Kind::Int { min: 2, max: 120 },
+        default: 5,
+        backing: Backing::Stored(14),
+    },
+    Entry {
+        id: GEOFENCE_BANNER,
+        key: "geofence.banner",
+        kind: Kind::Bool,
+        default: 1,
+        backing: Backing::Stored(12),
+    },
+    Entry {
+        id: POWER_SOLAR,
+        key: "power.solar",
+        kind: Kind::Bool,
+        default: 0,
+        backing: Backing::Stored(15),
+    },
+    Entry {
+        id: POWER_CUTOFF_MV,
+        key: "power.cutoff_mv",
+        // 0 = never power off on a low battery.
+        kind: Kind::Int { min: 0, max: 3700 },
+        default: 3300,
+        backing: Backing::Stored(18),
+    },
+    Entry {
+        id: POWER_BATTERY_MAH,
+        key: "power.battery_mah",
+        // Cell capacity for the fuel gauge and time to empty.
+        kind: Kind::Int {
+            min: 50,
+            max: 10_000,
+        },
+        default: 1000,
+        backing: Backing::Stored(24),
+    },
+    Entry {
+        id: FINDMY_ENABLED,
+        key: "findmy.enabled",
+        // Only broadcasts with keys provisioned.
+        kind: Kind::Bool,
+        default: 1,
+        backing: Backing::Stored(19),
+    },
+    Entry {
+        id: FINDMY_INTERVAL_MS,
+        key: "findmy.interval_ms",
+        // Applies from the next key rotation or advertising slot.
+        kind: Kind::Int {
+            min: 500,
+            max: 10_000,
+        },
+        default: 2000,
+        backing: Backing::Stored(20),
+    },
+    Entry {
+        id: ALERT_GEOFENCE,
+        key: "alert.geofence",
+        // Channel mask | severity << 4, see `alerts`. Banner and BLE, warning.
+        kind: Kind::Int { min: 0, max: 0x2F },
+        default: 0x19,
+        backing: Backing::Stored(21),
+    },
+    Entry {
+        id: ALERT_BATTERY,
+        key: "alert.battery",
+        // Banner, LED and BLE, warning.
+        kind: Kind::Int { min: 0, max: 0x2F },
+        default: 0x1B,
+        backing: Backing::Stored(22),
+    },
+    Entry {
+        id: ALERT_KEEP_ALIVE,
+        key: "alert.keep_alive",
+        // BLE only, info: tells the app its keep-alive ran out.
+        kind: Kind::Int { min: 0, max: 0x2F },
+        default: 0x08,
+        backing: Backing::Stored(25),
+    },
+    Entry {
+        id: ALERT_STORM,
+        key: "alert.storm",
+        // Banner and BLE, warning; add the buzzer for the trail.
+        kind: Kind::Int { min: 0, max: 0x2F },
+        default: 0x19,
+        backing: Backing::Stored(38),
+    },
+    Entry {
+        id: BUTTON_DOUBLE_PRESS,
+        key: "button.double_press",
+        // 0 = pause/resume or start/stop recording, 1 = drop a waypoint.
+        kind: Kind::Int { min: 0, max: 1 },
+        default: 0,
+        backing: Backing::Stored(23),
+    },
+    Entry {
+        id: SOUND_VOLUME,
+        key: "sound.volume",
+        // Percent; 0 silences the buzzer (`buzzer` feature).
+        kind: Kind::Int { min: 0, max: 100 },
+        default: 80,
+        backing: Backing::Stored(26),
+    },
+    Entry {
+        id: SOUND_RING_S,
+        key: "sound.ring_s",
+        // How long `RING` plays without a duration of its own.
+        kind: Kind::Int { min: 1, max: 600 },
+        default: 30,
+        backing: Backing::Stored(27),
+    },
+    Entry {
+        id: SOUND_RING_PATTERN,
+        key: "sound.ring_pattern",
+        // 0 = warble, 1 = beeps, 2 = rising sweep; see `tones::ring`.
+        kind: Kind::Int { min: 0, max: 2 },
+        default: 0,
+        backing: Backing::Stored(42),
+    },
+    Entry {
+        id: WEATHER_STORM_DROP,
+        key: "weather.storm_drop",
+        // Tenths of hPa over three hours; 0 = no storm alert.
+        kind: Kind::Int { min: 0, max: 200 },
+        default: 40,
+        backing: Backing::Stored(39),
+    },
+];
+
+/// Values of the `Stored` entries by slot, starting at their defaults.
+static STORED: [AtomicI32; STORED_COUNT] = stored_defaults();
+
+/// `STORED` as its entries' defaults. Fails to compile unless every slot
+/// belongs to exactly one entry.
+const fn stored_defaults() -> [AtomicI32; STORED_COUNT] {
+    let mut values = [const { AtomicI32::new(0) }; STORED_COUNT];
+    let mut taken = [false; STORED_COUNT];
+    let mut index = 0;
+    while index < ENTRY_COUNT {
+        let entry = &ENTRY_TABLE[index];
+        if let Backing::Stored(slot) = entry.backing {
+            assert!(!taken[slot], "settings slot used twice");
+            taken[slot] = true;
+            values[slot] = AtomicI32::new(entry.default);
+        }
+        index += 1;
+    }
+    let mut slot = 0;
+    while slot < STORED_COUNT {
+        assert!(taken[slot], "settings slot without an entry");
+        slot += 1;
+    }
+    values
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SetStatus {
+    Ok = 0,
+    UnknownId = 1,
+    OutOfRange = 2,
+    /// SD write failed. Registry-owned and timezone settings still apply
+    /// for this session; the others are left unchanged, as with their
+    /// feature commands.
+    StorageFailed = 3,
+}
+
+pub fn entry(id: u16) -> Option<&'static Entry> {
+    ENTRIES.iter().find(|entry| entry.id == id)
+}
+
+/// Value of a registry-owned setting, without touching the SD card.
+pub fn stored(id: u16) -> i32 {
+    match entry(id) {
+        Some(Entry {
+            backing: Backing::Stored(slot),
+            ..
+        }) => STORED[*slot].load(Ordering::Acquire),
+        Some(entry) => entry.default,
+        None => 0,
+    }
+}
+
+/// Load registry-owned settings from SD. Call once after the SD logger is
+/// initialized.
+pub async fn load() {
+    let mut buf = [0u8; SETTINGS_FILE_MAX_LEN];
+    let Some(len) = storage::read_settings_file(&mut buf).await else {
+        return;
+    };
+    let mut version = 0;
+    for record in buf[..len].chunks_exact(RECORD_LEN) {
+        let id = u16::from_le_bytes([record[0], record[1]]);
+        let value = i32::from_le_bytes([record[2], record[3], record[4], record[5]]);
+        if id == VERSION_ID {
+            version = value;
+            continue;
+        }
+        let Some(entry) = entry(id) else {
+            continue;
+        };
+        let Some(value) = migrate(version, id, value) else {
+            continue;
+        };
+        if let Backing::Stored(slot) = entry.backing {
+            if in_range(entry, value) {
+                STORED[slot].store(value, Ordering::Release);
+            }
+        }
+    }
+    defmt::info!("Settings: loaded /SETTINGS.CFG (schema {})", version);
+}
+
+/// Convert a value stored under schema `version` to the current meaning,
+/// `None` to drop it for the default.
+fn migrate(version: i32, id: u16, value: i32) -> Option<i32> {
+    // Before version 2 `ble.bonded_only` defaulted to off, and every save
+    // wrote that default along with the setting that changed.
+    if version < 2 && id == BLE_BONDED_ONLY {
+        return None;
+    }
+    Some(value)
+}
+
+pub fn get(id: u16) -> Option<i32> {
+    let entry = entry(id)?;
+    let tz = timezone::settings();
+    Some(match entry.backing {
+        Backing::Stored(slot) => STORED[slot].load(Ordering::Acquire),
+        Backing::AutoStart => recording::auto_start() as i32,
+        Backing::TzMode => match tz.tz_override {
+            TzOverride::Auto => 0,
+            TzOverride::FixedOffset(_) => 1,
+            TzOverride::Zone(_) => 2,
+        },
+        Backing::TzFixedOffset => match tz.tz_override {
+            TzOverride::FixedOffset(minutes) => minutes as i32,
+            _ => 0,
+        },
+        Backing::TzZone => match tz.tz_override {
+            TzOverride::Zone(zone) => zone as i32,
+            _ => 0,
+        },
+        Backing::TzLocalMidnight => tz.local_midnight_rotation as i32,
+        Backing::Lockdown => guest::lockdown() as i32,
+    })
+}
+
+/// Read (`value` None) or write one setting for the app, as
+/// `[status: u8][id: u16][value: i32]` with the value now in effect.
+pub async fn access(id: u16, value: Option<i32>) -> [u8; ACCESS_RESULT_LEN] {
+    let status = match value {
+        Some(value) => set(id, value).await,
+        None if entry(id).is_some() => SetStatus::Ok,
+        None => SetStatus::UnknownId,
+    };
+    let mut out = [0u8; ACCESS_RESULT_LEN];
+    out[0] = status as u8;
+    out[1..3].copy_from_slice(&id.to_le_bytes());
+    out[3..7].copy_from_slice(&get(id).unwrap_or(0).to_le_bytes());
+    out
+}
+
+/// Validate, apply and persist one setting.
+pub async fn set(id: u16, value: i32) -> SetStatus {
+    let Some(entry) = entry(id) else {
+        return SetStatus::UnknownId;
+    };
+    if !in_range(entry, value) {
+        return SetStatus::OutOfRange;
+    }
+    let persisted = match entry.backing {
+        Backing::Stored(slot) => {
+            STORED[slot].store(value, Ordering::Release);
+            write_stored().await
+        }
+        Backing::AutoStart => recording::set_auto_start(value != 0).await,
+        Backing::Lockdown => guest::set_lockdown(value != 0).await,
+        Backing::TzMode | Backing::TzFixedOffset | Backing::TzZone | Backing::TzLocalMidnight => {
+            let Some(tz) = updated_tz(entry.backing, value) else {
+                return SetStatus::OutOfRange;
+            };
+            let persisted = storage::write_tz_settings(&tz).await;
+            // Same as SET_TIMEZONE: apply even if the SD write failed.
+            timezone::set_settings(tz);
+            persisted
+        }
+    };
+    if persisted {
+        SetStatus::Ok
+    } else {
+        SetStatus::StorageFailed
+    }
+}
+
+fn in_range(entry: &Entry, value: i32) -> bool {
+    let (min, max) = entry.kind.range();
+    (min..=max).contains(&value)
+}
+
+/// Current timezone settings with one field replaced. Setting the offset or
+/// the zone also selects that mode.
+fn updated_tz(backing: Backing, value: i32) -> Option<TzSettings> {
+    let mut tz = timezone::settings();
+    tz.tz_override = match (backing, tz.tz_override) {
+        (Backing::TzMode, current) => match (value, current) {
+            (0, _) => TzOverride::Auto,
+            (1, TzOverride::FixedOffset(minutes)) => TzOverride::FixedOffset(minutes),
+            (1, _) => TzOverride::FixedOffset(0),
+            (2, TzOverride::Zone(zone)) => TzOverride::Zone(zone),
+            (2, _) => TzOverride::Zone(0),
+            _ => return None,
+        },
+        (Backing::TzFixedOffset, _) => TzOverride::FixedOffset(value as i16),
+        (Backing::TzZone, _) => TzOverride::Zone(value as u16),
+        (_, current) => current,
+    };
+    if let Backing::TzLocalMidnight = backing {
+        tz.local_midnight_rotation = value != 0;
+    }
+    // Round-trip to reuse the zone id check.
+    TzSettings::from_bytes(&tz.to_bytes())
+}
+
+async fn write_stored() -> bool {
+    let mut buf = [0u8; SETTINGS_FILE_MAX_LEN];
+    buf[..2].copy_from_slice(&VERSION_ID.to_le_bytes());
+    buf[2..RECORD_LEN].copy_from_slice(&SCHEMA_VERSION.to_le_bytes());
+    let mut len = RECORD_LEN;
+    for entry in ENTRIES.iter() {
+        let Backing::Stored(slot) = entry.backing else {
+            continue;
+        };
+        let value = STORED[slot].load(Ordering::Acquire);
+        buf[len..len + 2].copy_from_slice(&entry.id.to_le_bytes());
+        buf[len + 2..len + RECORD_LEN].copy_from_slice(&value.to_le_bytes());
+        len += RECORD_LEN;
+    }
+    storage::write_settings_file(&buf[..len]).await
+}
@@ -0,0 +1,273 @@
+//! Satellites in view and their signal strength, from GSV sentences.
+//!
+//! The fix data says how many satellites were used, not why a fix is poor.
+//! This keeps the receiver's last report of every satellite it sees, per
+//! constellation, for the Satellites page.
+//!
+//! # Design
+//!
+//! - Parsed here rather than by the `nmea` crate, which keeps GSV only
+//!   for some talkers. A sentence with a bad checksum is left to the
+//!   crate, which counts it as a receiver error.
+//! - The first sentence of a constellation's group replaces everything
+//!   known about that constellation, so satellites that set drop out one
+//!   group later and the other constellations are untouched.
+//! - At most `MAX_SATELLITES`; further satellites are left out. An SNR of
+//!   0 means in view but not tracked.
+
+pub const MAX_SATELLITES: usize = 24;
+/// Satellites per GSV sentence.
+const GSV_SATELLITES: usize = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Constellation {
+    Gps,
+    Beidou,
+    Glonass,
+    Galileo,
+    Qzss,
+    Other,
+}
+
+impl Constellation {
+    pub const ALL: [Self; 6] = [
+        Self::Gps,
+        Self::Beidou,
+        Self::Glonass,
+        Self::Galileo,
+        Self::Qzss,
+        Self::Other,
+    ];
+
+    fn from_talker(talker: &str) -> Self {
+        match talker {
+            "GP" => Self::Gps,
+            "BD" | "GB" => Self::Beidou,
+            "GL" => Self::Glonass,
+            "GA" => Self::Galileo,
+            "GQ" => Self::Qzss,
+            _ => Self::Other,
+        }
+    }
+
+    /// RINEX system letter.
+    pub fn letter(self) -> char {
+        match self {
+            Self::Gps => 'G',
+            Self::Beidou => 'C',
+            Self::Glonass => 'R',
+            Self::Galileo => 'E',
+            Self::Qzss => 'J',
+            Self::Other => '?',
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Satellite {
+    pub constellation: Constellation,
+    pub prn: u16,
+    /// dB-Hz, 0 when not tracked.
+    pub snr: u8,
+}
+
+const NO_SATELLITE: Satellite = Satellite {
+    constellation: Constellation::Other,
+    prn: 0,
+    snr: 0,
+};
+
+/// One GSV sentence.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Gsv {
+    pub constellation: Constellation,
+    /// First sentence of its group.
+    pub first: bool,
+    satellites: [Satellite; GSV_SATELLITES],
+    len: usize,
+}
+
+impl Gsv {
+    pub fn satellites(&self) -> &[Satellite] {
+        &self.satellites[..self.len]
+    }
+}
+
+/// Parse `$xxGSV,total,number,in_view{,prn,elevation,azimuth,snr}[,signal]*cs`.
+pub fn parse_gsv(sentence: &str) -> Option<Gsv> {
+    let body = sentence.strip_prefix('$')?;
+    let (body, checksum) = body.split_once('*')?;
+    let checksum = u8::from_str_radix(checksum.get(..2)?, 16).ok()?;
+    if body.bytes().fold(0, |acc, byte| acc ^ byte) != checksum {
+        return None;
+    }
+    let mut fields = body.split(',');
+    let talker = fields.next()?;
+    if talker.len() != 5 || !talker.ends_with("GSV") {
+        return None;
+    }
+    let _total = fields.next()?;
+    let number: u8 = fields.next()?.parse().ok()?;
+    let _in_view = fields.next()?;
+
+    let mut gsv = Gsv {
+        constellation: Constellation::from_talker(&talker[..2]),
+        first: number == 1,
+        satellites: [NO_SATELLITE; GSV_SATELLITES],
+        len: 0,
+    };
+    // NMEA 4.1 adds a signal id after the satellites.
+    while let (Some(prn), Some(_), Some(_), Some(snr)) =
+        (fields.next(), fields.next(), fields.next(), fields.next())
+    {
+        let Ok(prn) = prn.parse() else {
+            continue;
+        };
+        if gsv.len == GSV_SATELLITES {
+            break;
+        }
+        gsv.satellites[gsv.len] = Satellite {
+            constellation: gsv.constellation,
+            prn,
+            snr: snr.parse().unwrap_or(0),
+        };
+        gsv.len += 1;
+    }
+    Some(gsv)
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct SkyView {
+    satellites: [Satellite; MAX_SATELLITES],
+    len: usize,
+}
+
+impl SkyView {
+    pub const fn new() -> Self {
+        Self {
+            satellites: [NO_SATELLITE; MAX_SATELLITES],
+            len: 0,
+        }
+    }
+
+    pub fn note(&mut self, gsv: &Gsv) {
+        if gsv.first {
+            self.remove(gsv.constellation);
+        }
+        for satellite in gsv.satellites() {
+            if self.len == MAX_SATELLITES {
+                return;
+            }
+            self.satellites[self.len] = *satellite;
+            self.len += 1;
+        }
+    }
+
+    fn remove(&mut self, constellation: Constellation) {
+        let mut kept = 0;
+        for i in 0..self.len {
+            if self.satellites[i].constellation != constellation {
+                self.satellites[kept] = self.satellites[i];
+                kept += 1;
+            }
+        }
+        self.len = kept;
+    }
+
+    /// The receiver was powered off.
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    pub fn satellites(&self) -> &[Satellite] {
+        &self.satellites[..self.len]
+    }
+
+    /// In view and tracked, over all constellations or one.
+    pub fn counts(&self, constellation: Option<Constellation>) -> (usize, usize) {
+        let mut counts = (0, 0);
+        for satellite in self.satellites() {
+            if constellation.is_none_or(|wanted| satellite.constellation == wanted) {
+                counts.0 += 1;
+                counts.1 += (satellite.snr > 0) as usize;
+            }
+        }
+        counts
+    }
+
+    /// The satellites, strongest signal first.
+    pub fn by_snr(&self) -> SkyView {
+        let mut sorted = *self;
+        sorted.satellites[..self.len].sort_unstable_by_key(|s| core::cmp::Reverse(s.snr));
+        sorted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_gsv_with_and_without_a_signal_id() {
+        let gsv = parse_gsv("$GPGSV,3,1,10,01,45,120,38,03,10,300,,17,70,045,44,22,05,200,12*7B");
+        // A bad checksum is not ours to accept.
+        assert_eq!(gsv, None);
+
+        let sentence = "$GPGSV,3,1,10,01,45,120,38,03,10,300,,17,70,045,44,22,05,200,12*77";
+        let gsv = parse_gsv(sentence).unwrap();
+        assert_eq!(gsv.constellation, Constellation::Gps);
+        assert!(gsv.first);
+        let snrs: Vec<(u16, u8)> = gsv.satellites().iter().map(|s| (s.prn, s.snr)).collect();
+        assert_eq!(snrs, [(1, 38), (3, 0), (17, 44), (22, 12)]);
+
+        let gsv = parse_gsv("$BDGSV,2,2,05,33,40,100,31,1*47").unwrap();
+        assert_eq!(gsv.constellation, Constellation::Beidou);
+        assert!(!gsv.first);
+        assert_eq!(gsv.satellites().len(), 1);
+        assert_eq!(gsv.satellites()[0].snr, 31);
+
+        assert_eq!(parse_gsv("$GPGGA,1,2*55"), None);
+        assert_eq!(parse_gsv("GPGSV,1,1,00"), None);
+    }
+
+    fn sentence(talker: &str, number: u8, satellites: &[(u16, u8)]) -> Gsv {
+        let mut gsv = Gsv {
+            constellation: Constellation::from_talker(talker),
+            first: number == 1,
+            satellites: [NO_SATELLITE; GSV_SATELLITES],
+            len: satellites.len(),
+        };
+        for (slot, &(prn, snr)) in gsv.satellites.iter_mut().zip(satellites) {
+            *slot = Satellite {
+                constellation: gsv.constellation,
+                prn,
+                snr,
+            };
+        }
+        gsv
+    }
+
+    #[test]
+    fn a_new_group_replaces_only_its_constellation() {
+        let mut sky = SkyView::new();
+        sky.note(&sentence("GP", 1, &[(1, 40), (3, 0), (17, 44), (22, 12)]));
+        sky.note(&sentence("GP", 2, &[(30, 25)]));
+        sky.note(&sentence("BD", 1, &[(33, 31), (34, 0)]));
+        assert_eq!(sky.counts(None), (7, 5));
+        assert_eq!(sky.counts(Some(Constellation::Beidou)), (2, 1));
+
+        // Satellite 22 has set.
+        sky.note(&sentence("GP", 1, &[(1, 41), (3, 20), (17, 43), (30, 26)]));
+        assert_eq!(sky.counts(Some(Constellation::Gps)), (4, 4));
+        assert_eq!(sky.counts(None), (6, 5));
+        let strongest: Vec<u16> = sky.by_snr().satellites().iter().map(|s| s.prn).collect();
+        assert_eq!(&strongest[..3], [17, 1, 33]);
+
+        for _ in 0..10 {
+            sky.note(&sentence("GL", 2, &[(65, 30), (66, 30), (67, 30)]));
+        }
+        assert_eq!(sky.satellites().len(), MAX_SATELLITES);
+        sky.clear();
+        assert_eq!(sky.counts(None), (0, 0));
+    }
+}
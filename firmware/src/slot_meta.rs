@@ -0,0 +1,334 @@
+//! Records of the two firmware banks, kept next to the bootloader state.
+//!
+//! `slots` stages a new image into bank B; the bootloader swaps it into bank
+//! A for a trial boot and swaps it back unless the image confirms itself
+//! before the watchdog or a crash resets it. This module holds the parts that
+//! do not touch the flash: the record format, and how the record follows
+//! what the bootloader did.
+//!
+//! # Design
+//!
+//! - One record describes both banks: state, length, CRC-32 and version
+//!   string of each image, and how many trial images were rolled back.
+//! - The bootloader moves the images without knowing about the record. The
+//!   state it leaves for the application (`BootState`) says which way they
+//!   moved, and `after_boot` moves the two entries the same way. Running it
+//!   again after a reset that came before the record was saved changes
+//!   nothing.
+//! - The running image knows its own version; one installed over UF2 or
+//!   SWD replaces the bank A entry with an unchecked one (no length or
+//!   CRC).
+//! - Pages are written in turn, one record per page behind a magic word, a
+//!   sequence number and a CRC. The highest valid sequence number wins, so a
+//!   reset halfway through a write leaves the previous record.
+
+use crate::crc32;
+
+pub const VERSION_LEN: usize = 24;
+/// `[state][version_len][len: u32][crc: u32][version: 24B]`, little endian.
+pub const IMAGE_LEN: usize = 10 + VERSION_LEN;
+/// `[magic][seq][rollbacks: u16][reserved: u16][bank A][bank B][crc]`.
+pub const META_LEN: usize = 12 + 2 * IMAGE_LEN + 4;
+
+const META_MAGIC: u32 = 0x534C_4F54;
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageState {
+    /// Nothing known, or bank B is being written.
+    Empty = 0,
+    /// Bank B holds a checked image the bootloader is asked to swap in.
+    Staged = 1,
+    /// Swapped in, not confirmed yet.
+    Trial = 2,
+    Confirmed = 3,
+    /// The image that ran before the one in bank A.
+    Previous = 4,
+    /// Failed its trial and was swapped back out.
+    Bad = 5,
+}
+
+impl ImageState {
+    fn from_u8(value: u8) -> Option<Self> {
+        Some(match value {
+            0 => ImageState::Empty,
+            1 => ImageState::Staged,
+            2 => ImageState::Trial,
+            3 => ImageState::Confirmed,
+            4 => ImageState::Previous,
+            5 => ImageState::Bad,
+            _ => return None,
+        })
+    }
+}
+
+/// What the bootloader did before starting this image.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BootState {
+    /// Started bank A as it was.
+    Boot = 0,
+    /// Swapped the staged image in; it runs on trial.
+    Trial = 1,
+    /// The trial image did not confirm; the previous one is back.
+    Reverted = 2,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SlotImage {
+    pub state: ImageState,
+    /// Image length in bytes, 0 when the image was not checked.
+    pub len: u32,
+    pub crc: u32,
+    version: [u8; VERSION_LEN],
+    version_len: u8,
+}
+
+impl SlotImage {
+    pub const EMPTY: Self = Self {
+        state: ImageState::Empty,
+        len: 0,
+        crc: 0,
+        version: [0; VERSION_LEN],
+        version_len: 0,
+    };
+
+    /// A longer version string is cut to `VERSION_LEN` bytes.
+    pub fn new(state: ImageState, len: u32, crc: u32, version: &[u8]) -> Self {
+        let mut image = Self {
+            state,
+            len,
+            crc,
+            ..Self::EMPTY
+        };
+        image.set_version(version);
+        image
+    }
+
+    pub fn version(&self) -> &[u8] {
+        &self.version[..self.version_len as usize]
+    }
+
+    fn set_version(&mut self, version: &[u8]) {
+        let len = version.len().min(VERSION_LEN);
+        self.version = [0; VERSION_LEN];
+        self.version[..len].copy_from_slice(&version[..len]);
+        self.version_len = len as u8;
+    }
+
+    pub fn to_bytes(self) -> [u8; IMAGE_LEN] {
+        let mut out = [0u8; IMAGE_LEN];
+        out[0] = self.state as u8;
+        out[1] = self.version_len;
+        out[2..6].copy_from_slice(&self.len.to_le_bytes());
+        out[6..10].copy_from_slice(&self.crc.to_le_bytes());
+        out[10..].copy_from_slice(&self.version);
+        out
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let state = ImageState::from_u8(bytes[0])?;
+        let version_len = bytes[1];
+        if version_len as usize > VERSION_LEN {
+            return None;
+        }
+        let mut version = [0u8; VERSION_LEN];
+        version.copy_from_slice(&bytes[10..IMAGE_LEN]);
+        Some(Self {
+            state,
+            len: u32::from_le_bytes([bytes[2], bytes[3], bytes[4], bytes[5]]),
+            crc: u32::from_le_bytes([bytes[6], bytes[7], bytes[8], bytes[9]]),
+            version,
+            version_len,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SlotMeta {
+    pub seq: u32,
+    pub rollbacks: u16,
+    /// Bank A, the running image.
+    pub active: SlotImage,
+    /// Bank B: the image staged for the next boot, or the one before.
+    pub standby: SlotImage,
+}
+
+impl SlotMeta {
+    pub const EMPTY: Self = Self {
+        seq: 0,
+        rollbacks: 0,
+        active: SlotImage::EMPTY,
+        standby: SlotImage::EMPTY,
+    };
+
+    pub fn to_bytes(self) -> [u8; META_LEN] {
+        let mut out = [0u8; META_LEN];
+        out[0..4].copy_from_slice(&META_MAGIC.to_le_bytes());
+        out[4..8].copy_from_slice(&self.seq.to_le_bytes());
+        out[8..10].copy_from_slice(&self.rollbacks.to_le_bytes());
+        out[12..12 + IMAGE_LEN].copy_from_slice(&self.active.to_bytes());
+        out[12 + IMAGE_LEN..12 + 2 * IMAGE_LEN].copy_from_slice(&self.standby.to_bytes());
+        let crc = crc32::crc32(&out[..META_LEN - 4]);
+        out[META_LEN - 4..].copy_from_slice(&crc.to_le_bytes());
+        out
+    }
+
+    /// `None` for an erased page or a torn write.
+    pub fn from_bytes(bytes: &[u8; META_LEN]) -> Option<Self> {
+        let word = |at: usize| {
+            u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+        };
+        if word(0) != META_MAGIC || word(META_LEN - 4) != crc32::crc32(&bytes[..META_LEN - 4]) {
+            return None;
+        }
+        Some(Self {
+            seq: word(4),
+            rollbacks: u16::from_le_bytes([bytes[8], bytes[9]]),
+            active: SlotImage::from_bytes(&bytes[12..12 + IMAGE_LEN])?,
+            standby: SlotImage::from_bytes(&bytes[12 + IMAGE_LEN..12 + 2 * IMAGE_LEN])?,
+        })
+    }
+
+    /// The newest record and the page it was read from.
+    pub fn newest(pages: &[Option<SlotMeta>]) -> Option<(usize, SlotMeta)> {
+        pages
+            .iter()
+            .enumerate()
+            .filter_map(|(page, meta)| meta.map(|meta| (page, meta)))
+            .max_by_key(|&(_, meta)| meta.seq)
+    }
+
+    /// Bank B is about to be overwritten.
+    pub fn begin_staging(&mut self) {
+        self.standby = SlotImage::EMPTY;
+    }
+
+    /// Bank B now holds a checked image for the bootloader to swap in.
+    pub fn stage(&mut self, len: u32, crc: u32, version: &[u8]) {
+        self.standby = SlotImage::new(ImageState::Staged, len, crc, version);
+    }
+
+    /// Follow the bootloader's move of the images; `running` is the version
+    /// of this image. Returns whether the record changed.
+    pub fn after_boot(&mut self, boot: BootState, running: &[u8]) -> bool {
+        let before = *self;
+        match boot {
+            BootState::Trial if self.standby.state == ImageState::Staged => {
+                core::mem::swap(&mut self.active, &mut self.standby);
+                self.active.state = ImageState::Trial;
+                self.standby.state = ImageState::Previous;
+            }
+            BootState::Reverted if self.active.state == ImageState::Trial => {
+                core::mem::swap(&mut self.active, &mut self.standby);
+                self.active.state = ImageState::Confirmed;
+                self.standby.state = ImageState::Bad;
+                self.rollbacks = self.rollbacks.saturating_add(1);
+            }
+            // Confirmed before the record was saved.
+            BootState::Boot if self.active.state == ImageState::Trial => {
+                self.active.state = ImageState::Confirmed;
+            }
+            _ => {}
+        }
+        if self.active.state == ImageState::Empty {
+            self.active.state = ImageState::Confirmed;
+        }
+        if self.active.version() != running {
+            if self.active.state != ImageState::Trial {
+                // Not the image the record describes: installed some other way.
+                self.active = SlotImage::new(ImageState::Confirmed, 0, 0, running);
+            }
+            self.active.set_version(running);
+        }
+        *self != before
+    }
+
+    /// The trial image passed. Returns whether the record changed.
+    pub fn confirm(&mut self) -> bool {
+        if self.active.state != ImageState::Trial {
+            return false;
+        }
+        self.active.state = ImageState::Confirmed;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn staged() -> SlotMeta {
+        let mut meta = SlotMeta::EMPTY;
+        assert!(meta.after_boot(BootState::Boot, b"v1.0"));
+        meta.begin_staging();
+        meta.stage(300_000, 0x1234_5678, b"v1.1");
+        meta
+    }
+
+    #[test]
+    fn records_round_trip_and_reject_torn_pages() {
+        let mut meta = staged();
+        meta.seq = 7;
+        meta.rollbacks = 2;
+        let bytes = meta.to_bytes();
+        assert_eq!(SlotMeta::from_bytes(&bytes), Some(meta));
+        assert_eq!(SlotMeta::from_bytes(&[0xFF; META_LEN]), None);
+        let mut torn = bytes;
+        torn[20] ^= 1;
+        assert_eq!(SlotMeta::from_bytes(&torn), None);
+
+        let older = SlotMeta { seq: 6, ..meta };
+        let newest = SlotMeta::newest(&[Some(older), None, Some(meta)]);
+        assert_eq!(newest, Some((2, meta)));
+        assert_eq!(SlotMeta::newest(&[None, None]), None);
+    }
+
+    #[test]
+    fn trial_image_is_confirmed_or_rolled_back() {
+        let mut meta = staged();
+        assert!(meta.after_boot(BootState::Trial, b"v1.1"));
+        assert_eq!(meta.active.state, ImageState::Trial);
+        assert_eq!(
+            (meta.active.len, meta.active.version()),
+            (300_000, &b"v1.1"[..])
+        );
+        assert_eq!(meta.standby.state, ImageState::Previous);
+        assert_eq!(meta.standby.version(), b"v1.0");
+        // A second trial boot before the record was saved changes nothing.
+        assert!(!meta.after_boot(BootState::Trial, b"v1.1"));
+
+        let mut confirmed = meta;
+        assert!(confirmed.confirm());
+        assert!(!confirmed.confirm());
+        assert_eq!(confirmed.active.state, ImageState::Confirmed);
+
+        let mut lost = meta;
+        assert!(lost.after_boot(BootState::Boot, b"v1.1"));
+        assert_eq!(lost, confirmed);
+
+        assert!(meta.after_boot(BootState::Reverted, b"v1.0"));
+        assert_eq!(meta.active.state, ImageState::Confirmed);
+        assert_eq!(meta.active.version(), b"v1.0");
+        assert_eq!(meta.standby.state, ImageState::Bad);
+        assert_eq!(meta.standby.crc, 0x1234_5678);
+        assert_eq!(meta.rollbacks, 1);
+        assert!(!meta.after_boot(BootState::Reverted, b"v1.0"));
+    }
+
+    #[test]
+    fn image_installed_elsewhere_replaces_bank_a() {
+        let mut meta = staged();
+        assert!(meta.after_boot(BootState::Boot, b"v2.0-uf2"));
+        assert_eq!(
+            meta.active,
+            SlotImage::new(ImageState::Confirmed, 0, 0, b"v2.0-uf2")
+        );
+        assert_eq!(meta.standby.state, ImageState::Staged);
+        assert!(!meta.after_boot(BootState::Boot, b"v2.0-uf2"));
+
+        let long = [b'x'; VERSION_LEN + 8];
+        let image = SlotImage::new(ImageState::Staged, 1, 2, &long);
+        assert_eq!(image.version(), &long[..VERSION_LEN]);
+    }
+}
@@ -0,0 +1,420 @@
+//! A/B firmware banks: staging, trial boots and rollback.
+//!
+//! An update replaced the only image in place, so one that crashed or hung
+//! at boot left the tracker dead until it was reflashed over a cable. The
+//! slot bootloader (`bootloader/`) keeps two banks instead; this module is
+//! the application's side of it.
+//!
+//! # Design
+//!
+//! - Layout in `bootloader/memory.x`: bank A (`ACTIVE`) runs, bank B
+//!   (`DFU`) receives the next image, and the bootloader state page and the
+//!   slot records (`slot_meta`) sit in front of bank A.
+//! - `FIRMWARE` writes the image into bank B in order over BLE, erasing each
+//!   page as it is reached. `finish` checks the CRC-32 of bank B against the
+//!   one given at the start before it asks the bootloader to swap, so a torn
+//!   or corrupted transfer never boots.
+//! - The swapped-in image runs on trial. `slots_task` confirms it
+//!   (`mark_booted`) `CONFIRM_AFTER_S` after boot; it is spawned last in
+//!   `main`, so an image that fails during bring-up never gets there. Until
+//!   then a panic, a HardFault or the watchdog resets the device and the
+//!   bootloader swaps the previous image back. Bank B holds that image
+//!   during the trial, so no new one is staged until the trial ends.
+//! - The bootloader starts the watchdog on every boot and `watchdog_task`
+//!   feeds it from the executor, so a task that never yields resets the
+//!   device. A running watchdog keeps its settings; a boot without one
+//!   starts it with the bootloader's timeout.
+//! - One SoftDevice `Flash` serves this module and `flash_track`, each
+//!   through partitions of the shared mutex.
+
+use cortex_m::peripheral::SCB;
+use embassy_boot::{FirmwareUpdater, FirmwareUpdaterConfig, State};
+use embassy_embedded_hal::flash::partition::Partition;
+use embassy_executor::task;
+use embassy_nrf::wdt::{self, HaltConfig, SleepConfig, Watchdog, WatchdogHandle};
+use embassy_nrf::{peripherals, Peri};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::mutex::Mutex;
+use embassy_sync::signal::Signal;
+use embassy_time::Timer;
+use embedded_storage_async::nor_flash::{NorFlash, ReadNorFlash};
+use heapless::Vec;
+use nrf_softdevice::Flash;
+
+use crate::build_info::FIRMWARE_VERSION;
+use crate::crc32::Crc32;
+use crate::display;
+use crate::slot_meta::{BootState, SlotMeta, IMAGE_LEN, META_LEN, VERSION_LEN};
+use crate::storage;
+use crate::system_info::SYSTEM_INFO;
+use crate::usb_connected;
+
+pub type SharedFlash = Mutex<CriticalSectionRawMutex, Flash>;
+type FlashPartition = Partition<'static, CriticalSectionRawMutex, Flash>;
+
+/// Mirrors `bootloader/memory.x`.
+const STATE_START: u32 = 0x2D000;
+const STATE_LEN: u32 = 0x1000;
+const META_START: u32 = 0x2E000;
+const META_PAGES: usize = 2;
+const BANK_A_LEN: u32 = 0x5E000;
+const BANK_B_START: u32 = 0x8E000;
+const BANK_B_LEN: u32 = 0x5F000;
+const PAGE_SIZE: u32 = 4096;
+/// Same as `WATCHDOG_TIMEOUT_S` in the bootloader.
+const WATCHDOG_TIMEOUT_S: u32 = 8;
+const WATCHDOG_PET_MS: u64 = 1000;
+const CONFIRM_AFTER_S: u64 = 60;
+/// On battery; a device that dies halfway keeps running the old image.
+const MIN_BATTERY_PERCENT: u8 = 30;
+/// Largest `write` chunk: a `FIRMWARE` payload less its header.
+pub const WRITE_MAX: usize = 512;
+/// `[boot_state][rollbacks: u16][received: u32][bank A][bank B]`.
+pub const REPORT_LEN: usize = 7 + 2 * IMAGE_LEN;
+/// Time for the finish response and the banner before the reset.
+const RESET_DELAY_MS: u64 = 500;
+
+#[repr(u8)]
+#[derive(Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum UpdateStatus {
+    Ok = 0,
+    /// No slot bootloader, a trial still running, or a low battery.
+    Refused = 1,
+    TooLarge = 2,
+    FlashError = 3,
+    NotStarted = 4,
+    /// Not the next offset, or a chunk that is not whole words before the end.
+    BadOffset = 5,
+    Incomplete = 6,
+    CrcMismatch = 7,
+}
+
+/// The SoftDevice writes whole words from a word-aligned buffer.
+#[repr(align(4))]
+struct Aligned<const N: usize>([u8; N]);
+
+struct Staging {
+    len: u32,
+    crc: u32,
+    version: Vec<u8, VERSION_LEN>,
+    /// Bytes received so far.
+    next: u32,
+    /// Bank B is erased up to here.
+    erased: u32,
+}
+
+struct Slots {
+    flash: &'static SharedFlash,
+    boot: BootState,
+    meta: SlotMeta,
+    meta_page: usize,
+    staging: Option<Staging>,
+}
+
+static SLOTS: Mutex<CriticalSectionRawMutex, Option<Slots>> = Mutex::new(None);
+static RESTART: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+fn partition(flash: &'static SharedFlash, start: u32, len: u32) -> FlashPartition {
+    Partition::new(flash, start, len)
+}
+
+fn updater_config(
+    flash: &'static SharedFlash,
+) -> FirmwareUpdaterConfig<FlashPartition, FlashPartition> {
+    FirmwareUpdaterConfig {
+        dfu: partition(flash, BANK_B_START, BANK_B_LEN),
+        state: partition(flash, STATE_START, STATE_LEN),
+    }
+}
+
+async fn read_state(flash: &'static SharedFlash) -> Option<State> {
+    let mut aligned = Aligned([0u8; 4]);
+    let mut updater = FirmwareUpdater::new(updater_config(flash), &mut aligned.0);
+    updater.get_state().await.ok()
+}
+
+async fn mark_booted(flash: &'static SharedFlash) -> bool {
+    let mut aligned = Aligned([0u8; 4]);
+    let mut updater = FirmwareUpdater::new(updater_config(flash), &mut aligned.0);
+    updater.mark_booted().await.is_ok()
+}
+
+async fn mark_updated(flash: &'static SharedFlash) -> bool {
+    let mut aligned = Aligned([0u8; 4]);
+    let mut updater = FirmwareUpdater::new(updater_config(flash), &mut aligned.0);
+    updater.mark_updated().await.is_ok()
+}
+
+impl Slots {
+    async fn load(flash: &'static SharedFlash, boot: BootState) -> Self {
+        let mut meta = partition(flash, META_START, META_PAGES as u32 * PAGE_SIZE);
+        let mut pages = [None; META_PAGES];
+        for (page, record) in pages.iter_mut().enumerate() {
+            let mut bytes = [0u8; META_LEN];
+            if meta.read(page as u32 * PAGE_SIZE, &mut bytes).await.is_ok() {
+                *record = SlotMeta::from_bytes(&bytes);
+            }
+        }
+        // With no record yet, the first save goes to page 0.
+        let (meta_page, meta) =
+            SlotMeta::newest(&pages).unwrap_or((META_PAGES - 1, SlotMeta::EMPTY));
+        Self {
+            flash,
+            boot,
+            meta,
+            meta_page,
+            staging: None,
+        }
+    }
+
+    async fn save(&mut self) -> bool {
+        let page = (self.meta_page + 1) % META_PAGES;
+        let record = SlotMeta {
+            seq: self.meta.seq.wrapping_add(1),
+            ..self.meta
+        };
+        let bytes = Aligned(record.to_bytes());
+        let mut meta = partition(self.flash, META_START, META_PAGES as u32 * PAGE_SIZE);
+        let start = page as u32 * PAGE_SIZE;
+        if meta.erase(start, start + PAGE_SIZE).await.is_err()
+            || meta.write(start, &bytes.0).await.is_err()
+        {
+            return false;
+        }
+        self.meta = record;
+        self.meta_page = page;
+        true
+    }
+
+    fn received(&self) -> u32 {
+        self.staging.as_ref().map_or(0, |staging| staging.next)
+    }
+}
+
+async fn battery_low() -> bool {
+    let info = *SYSTEM_INFO.lock().await;
+    // A failed reading leaves the voltage negative; do not block on it.
+    !usb_connected() && info.battery_voltage > 0.0 && info.battery_percent < MIN_BATTERY_PERCENT
+}
+
+/// Take over the watchdog the bootloader started, or start it.
+pub fn start_watchdog(wdt: Peri<'static, peripherals::WDT>) -> Option<WatchdogHandle> {
+    let config = wdt::Config::try_new(&wdt).unwrap_or_else(|| {
+        let mut config = wdt::Config::default();
+        config.timeout_ticks = 32768 * WATCHDOG_TIMEOUT_S;
+        config.action_during_sleep = SleepConfig::RUN;
+        config.action_during_debug_halt = HaltConfig::PAUSE;
+        config
+    });
+    match Watchdog::try_new(wdt, config) {
+        Ok((_watchdog, [handle])) => Some(handle),
+        Err(_) => {
+            defmt::warn!("Slots: watchdog running with other settings");
+            None
+        }
+    }
+}
+
+#[task]
+pub async fn watchdog_task(mut handle: WatchdogHandle) {
+    loop {
+        handle.pet();
+        Timer::after_millis(WATCHDOG_PET_MS).await;
+    }
+}
+
+/// Read what the bootloader did and bring the slot records up to date.
+/// Call once the SoftDevice runs.
+pub async fn init(flash: &'static SharedFlash) {
+    let boot = match read_state(flash).await {
+        Some(State::Swap) => BootState::Trial,
+        Some(State::Revert) => BootState::Reverted,
+        Some(_) => BootState::Boot,
+        None => {
+            defmt::warn!("Slots: bootloader state unreadable");
+            return;
+        }
+    };
+    let mut slots = Slots::load(flash, boot).await;
+    if slots.meta.after_boot(boot, FIRMWARE_VERSION.as_bytes()) && !slots.save().await {
+        defmt::warn!("Slots: record write failed");
+    }
+    if boot == BootState::Reverted {
+        defmt::warn!(
+            "Slots: trial image rolled back ({} so far)",
+            slots.meta.rollbacks
+        );
+        // The bootloader only swaps again for a new image.
+        if !mark_booted(flash).await {
+            defmt::warn!("Slots: bootloader state write failed");
+        }
+    }
+    defmt::info!(
+        "Slots: boot {=u8} bank A {=u8} bank B {=u8}",
+        boot as u8,
+        slots.meta.active.state as u8,
+        slots.meta.standby.state as u8
+    );
+    *SLOTS.lock().await = Some(slots);
+}
+
+/// Start writing an image of `len` bytes with CRC-32 `crc` into bank B.
+pub async fn begin(len: u32, crc: u32, version: &[u8]) -> UpdateStatus {
+    let low = battery_low().await;
+    let mut slots = SLOTS.lock().await;
+    let Some(slots) = slots.as_mut() else {
+        return UpdateStatus::Refused;
+    };
+    if slots.boot == BootState::Trial || low {
+        return UpdateStatus::Refused;
+    }
+    if len == 0 || len > BANK_A_LEN {
+        return UpdateStatus::TooLarge;
+    }
+    // Bank B no longer holds what the record says once the first page goes.
+    slots.meta.begin_staging();
+    if !slots.save().await {
+        return UpdateStatus::FlashError;
+    }
+    let mut stored = Vec::new();
+    let _ = stored.extend_from_slice(&version[..version.len().min(VERSION_LEN)]);
+    slots.staging = Some(Staging {
+        len,
+        crc,
+        version: stored,
+        next: 0,
+        erased: 0,
+    });
+    defmt::info!("Slots: staging {} bytes", len);
+    UpdateStatus::Ok
+}
+
+/// Write the chunk at `offset` into bank B.
+pub async fn write(offset: u32, data: &[u8]) -> UpdateStatus {
+    let mut slots = SLOTS.lock().await;
+    let Some(slots) = slots.as_mut() else {
+        return UpdateStatus::Refused;
+    };
+    let flash = slots.flash;
+    let Some(staging) = slots.staging.as_mut() else {
+        return UpdateStatus::NotStarted;
+    };
+    let end = offset.saturating_add(data.len() as u32);
+    let last = end == staging.len;
+    if offset != staging.next
+        || data.is_empty()
+        || data.len() > WRITE_MAX
+        || end > staging.len
+        || (!last && data.len() % 4 != 0)
+    {
+        return UpdateStatus::BadOffset;
+    }
+    // The end of the image is padded to a whole word.
+    let mut chunk = Aligned([0xFFu8; WRITE_MAX]);
+    chunk.0[..data.len()].copy_from_slice(data);
+    let padded = data.len().next_multiple_of(4);
+    let mut bank = partition(flash, BANK_B_START, BANK_B_LEN);
+    while staging.erased < offset + padded as u32 {
+        let page = staging.erased;
+        if bank.erase(page, page + PAGE_SIZE).await.is_err() {
+            return UpdateStatus::FlashError;
+        }
+        staging.erased += PAGE_SIZE;
+    }
+    if bank.write(offset, &chunk.0[..padded]).await.is_err() {
+        return UpdateStatus::FlashError;
+    }
+    staging.next = end;
+    UpdateStatus::Ok
+}
+
+/// Check bank B and ask the bootloader to swap it in; the device restarts
+/// shortly after `Ok`.
+pub async fn finish() -> UpdateStatus {
+    let mut slots = SLOTS.lock().await;
+    let Some(slots) = slots.as_mut() else {
+        return UpdateStatus::Refused;
+    };
+    let flash = slots.flash;
+    let Some(staging) = slots.staging.take() else {
+        return UpdateStatus::NotStarted;
+    };
+    if staging.next != staging.len {
+        slots.staging = Some(staging);
+        return UpdateStatus::Incomplete;
+    }
+    let mut bank = partition(flash, BANK_B_START, BANK_B_LEN);
+    let mut crc = Crc32::new();
+    let mut buf = [0u8; 256];
+    let mut offset = 0;
+    while offset < staging.len {
+        let take = (staging.len - offset).min(buf.len() as u32) as usize;
+        if bank.read(offset, &mut buf[..take]).await.is_err() {
+            return UpdateStatus::FlashError;
+        }
+        crc.update(&buf[..take]);
+        offset += take as u32;
+    }
+    if crc.finish() != staging.crc {
+        defmt::warn!("Slots: bank B CRC mismatch");
+        return UpdateStatus::CrcMismatch;
+    }
+    slots.meta.stage(staging.len, staging.crc, &staging.version);
+    if !slots.save().await || !mark_updated(flash).await {
+        return UpdateStatus::FlashError;
+    }
+    defmt::info!("Slots: image staged, restarting");
+    RESTART.signal(());
+    UpdateStatus::Ok
+}
+
+/// `REPORT_LEN` bytes into `out`; `None` without the slot bootloader.
+pub async fn report(out: &mut [u8; REPORT_LEN]) -> Option<()> {
+    let slots = SLOTS.lock().await;
+    let slots = slots.as_ref()?;
+    out[0] = slots.boot as u8;
+    out[1..3].copy_from_slice(&slots.meta.rollbacks.to_le_bytes());
+    out[3..7].copy_from_slice(&slots.received().to_le_bytes());
+    out[7..7 + IMAGE_LEN].copy_from_slice(&slots.meta.active.to_bytes());
+    out[7 + IMAGE_LEN..].copy_from_slice(&slots.meta.standby.to_bytes());
+    Some(())
+}
+
+/// Bytes of the image being staged received so far, 0 when none is.
+pub async fn received() -> u32 {
+    SLOTS.lock().await.as_ref().map_or(0, Slots::received)
+}
+
+/// Confirm a trial image, then restart whenever `finish` asks.
+#[task]
+pub async fn slots_task() {
+    let trial = SLOTS
+        .lock()
+        .await
+        .as_ref()
+        .is_some_and(|slots| slots.boot == BootState::Trial);
+    if trial {
+        Timer::after_secs(CONFIRM_AFTER_S).await;
+        let mut slots = SLOTS.lock().await;
+        if let Some(slots) = slots.as_mut() {
+            if mark_booted(slots.flash).await {
+                slots.boot = BootState::Boot;
+                if slots.meta.confirm() && !slots.save().await {
+                    defmt::warn!("Slots: record write failed");
+                }
+                defmt::info!("Slots: trial image confirmed");
+            } else {
+                defmt::warn!("Slots: confirm failed");
+            }
+        }
+    }
+    loop {
+        RESTART.wait().await;
+        display::show_banner("Installing update");
+        if !storage::flush_sd_cache().await {
+            defmt::warn!("Slots: SD cache flush failed");
+        }
+        Timer::after_millis(RESET_DELAY_MS).await;
+        SCB::sys_reset();
+    }
+}
@@ -0,0 +1,164 @@
+//! Duty policy for trackers running from a small solar panel.
+//!
+//! With `power.solar` on, the battery task watches the cell voltage and the
+//! sun: while the panel is charging the GPS may work harder, and at night,
+//! when every milliamp-hour has to last until morning, it is throttled. The
+//! GPS profile applies the resulting `SolarMode` on top of the selected
+//! preset.
+//!
+//! # Design
+//!
+//! - Charging is read from the voltage trend: the filtered voltage is kept
+//!   once per minute, and a rise of `CHARGE_RISE_MV` over `TREND_MINUTES`
+//!   means charging until the trend turns negative. A full cell on a sunny
+//!   day no longer rises, so `FULL_MV` in daylight counts as charging too.
+//! - Night is the sun below `NIGHT_ELEVATION_DEG` (civil twilight) at the
+//!   last fix, with the time extrapolated from it on the uptime clock. The
+//!   GPS is off most of the night, so the fix can be hours old; that is
+//!   accurate enough for sunrise. Before the first fix there is no night.
+//! - USB charging looks like sun and gets the boost as well.
+
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use libm::{asin, atan2, cos, sin};
+
+const TREND_MINUTES: usize = 15;
+const CHARGE_RISE_MV: f32 = 10.0;
+const FULL_MV: f32 = 4150.0;
+const NIGHT_ELEVATION_DEG: f64 = -6.0;
+const SECS_PER_DAY: f64 = 86_400.0;
+/// Unix day of J2000.0 (2000-01-01 12:00 UTC).
+const J2000_UNIX_DAY: f64 = 10_957.5;
+
+#[repr(u8)]
+#[derive(Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum SolarMode {
+    /// Policy off, or neither charging nor night.
+    Normal = 0,
+    Boost = 1,
+    Night = 2,
+}
+
+static MODE: AtomicU8 = AtomicU8::new(SolarMode::Normal as u8);
+
+/// Mode published by the battery task.
+pub fn mode() -> SolarMode {
+    match MODE.load(Ordering::Relaxed) {
+        1 => SolarMode::Boost,
+        2 => SolarMode::Night,
+        _ => SolarMode::Normal,
+    }
+}
+
+pub fn publish(mode: SolarMode) {
+    let previous = MODE.swap(mode as u8, Ordering::Relaxed);
+    if previous != mode as u8 {
+        defmt::info!("Solar mode: {}", mode);
+    }
+}
+
+#[derive(Clone, Copy)]
+struct FixAnchor {
+    unix_ts: u32,
+    uptime_s: u64,
+    latitude: f64,
+    longitude: f64,
+}
+
+pub struct SolarPolicy {
+    /// Voltage at the start of each of the last minutes, oldest first once
+    /// the ring has wrapped.
+    minute_mv: [f32; TREND_MINUTES],
+    len: usize,
+    next: usize,
+    last_minute: Option<u64>,
+    latest_mv: f32,
+    charging: bool,
+    anchor: Option<FixAnchor>,
+}
+
+impl SolarPolicy {
+    pub const fn new() -> Self {
+        Self {
+            minute_mv: [0.0; TREND_MINUTES],
+            len: 0,
+            next: 0,
+            last_minute: None,
+            latest_mv: 0.0,
+            charging: false,
+            anchor: None,
+        }
+    }
+
+    /// Feed the filtered battery voltage; called every battery sample.
+    pub fn note_voltage(&mut self, uptime_s: u64, voltage_mv: f32) {
+        self.latest_mv = voltage_mv;
+        let minute = uptime_s / 60;
+        if self.last_minute == Some(minute) {
+            return;
+        }
+        self.last_minute = Some(minute);
+        self.minute_mv[self.next] = voltage_mv;
+        self.next = (self.next + 1) % TREND_MINUTES;
+        self.len = (self.len + 1).min(TREND_MINUTES);
+        if self.len < TREND_MINUTES {
+            return;
+        }
+        // After a full ring, `next` is the oldest entry.
+        let rise = voltage_mv - self.minute_mv[self.next];
+        if rise >= CHARGE_RISE_MV {
+            self.charging = true;
+        } else if rise < 0.0 {
+            self.charging = false;
+        }
+    }
+
+    /// Remember where and when the sun was last known.
+    pub fn note_fix(&mut self, uptime_s: u64, unix_ts: u32, latitude: f64, longitude: f64) {
+        self.anchor = Some(FixAnchor {
+            unix_ts,
+            uptime_s,
+            latitude,
+            longitude,
+        });
+    }
+
+    pub fn mode(&self, uptime_s: u64) -> SolarMode {
+        let elevation = self.anchor.map(|anchor| {
+            let elapsed = uptime_s.saturating_sub(anchor.uptime_s);
+            let unix_ts = anchor.unix_ts as u64 + elapsed;
+            sun_elevation_deg(unix_ts, anchor.latitude, anchor.longitude)
+        });
+        let night = elevation.is_some_and(|deg| deg < NIGHT_ELEVATION_DEG);
+        let full_in_sun = elevation.is_some_and(|deg| deg > 0.0) && self.latest_mv >= FULL_MV;
+        if self.charging || full_in_sun {
+            SolarMode::Boost
+        } else if night {
+            SolarMode::Night
+        } else {
+            SolarMode::Normal
+        }
+    }
+}
+
+/// Sun elevation above the horizon in degrees, from the low-precision
+/// almanac formulas (about 0.01 degree until 2050).
+pub fn sun_elevation_deg(unix_ts: u64, latitude: f64, longitude: f64) -> f64 {
+    let d = unix_ts as f64 / SECS_PER_DAY - J2000_UNIX_DAY;
+    let mean_anomaly = (357.529 + 0.985_600_28 * d).to_radians();
+    let mean_longitude = 280.459 + 0.985_647_36 * d;
+    let ecliptic_longitude = (mean_longitude
+        + 1.915 * sin(mean_anomaly)
+        + 0.020 * sin(2.0 * mean_anomaly))
+    .to_radians();
+    let obliquity = (23.439 - 0.000_000_36 * d).to_radians();
+    let right_ascension = atan2(
+        cos(obliquity) * sin(ecliptic_longitude),
+        cos(ecliptic_longitude),
+    );
+    let declination = asin(sin(obliquity) * sin(ecliptic_longitude));
+    let sidereal_deg = 280.460_618_37 + 360.985_647_366_29 * d;
+    let hour_angle = (sidereal_deg + longitude).to_radians() - right_ascension;
+    let lat = latitude.to_radians();
+    asin(sin(lat) * sin(declination) + cos(lat) * cos(declination) * cos(hour_angle)).to_degrees()
+}
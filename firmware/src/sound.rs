@@ -0,0 +1,156 @@
+//! Piezo buzzer on P1.07, driven by PWM0.
+//!
+//! Plays the "ring my device" pattern that the app starts with `RING` or a
+//! Find Hub phone with the FMDN ring action, and the buzzer channel of
+//! `alerts`. Patterns live in `tones`.
+//!
+//! # Design
+//!
+//! - One task owns the PWM. `ring`, `stop` and `alert` only signal it; a
+//!   new request replaces the one playing.
+//! - A ring repeats the `sound.ring_pattern` pattern for `sound.ring_s`
+//!   seconds unless the request names a duration. Alerts do not interrupt a ring: the ring is
+//!   the louder way to find the tracker, and the alert also goes to its
+//!   other channels.
+//! - `sound.volume` is read at the start of each request, so a change
+//!   applies to the next sound. `0` keeps the buzzer silent.
+//! - The PWM is disabled between tones and when idle, so the buzzer draws
+//!   nothing while quiet.
+//! - FMDN rings through the beacon actions characteristic, authenticated
+//!   with the ring key (see `google_fmdn`), and learns how the ring ended
+//!   from `wait_ring_end`. Find My has no such path: the play-sound request
+//!   belongs to the MFi accessory protocol, which a tracker broadcasting
+//!   OpenHaystack keys does not implement, so the owner rings it with
+//!   `RING` instead.
+
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+use embassy_executor::task;
+use embassy_futures::select::{select, Either};
+use embassy_nrf::pwm::{DutyCycle, SimplePwm};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Instant, Timer};
+
+use crate::alerts::Severity;
+use crate::settings;
+use crate::tones::{self, Tone};
+
+#[derive(Clone, Copy)]
+enum Request {
+    Ring { duration_s: u16 },
+    Alert(Severity),
+    Stop,
+}
+
+/// Why a ring ended.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum RingEnd {
+    Timeout,
+    Stopped,
+}
+
+static REQUEST: Signal<CriticalSectionRawMutex, Request> = Signal::new();
+static RING_END: Signal<CriticalSectionRawMutex, RingEnd> = Signal::new();
+static RINGING: AtomicBool = AtomicBool::new(false);
+/// `Instant` seconds at which the current ring ends.
+static RING_UNTIL_S: AtomicU32 = AtomicU32::new(0);
+
+/// Start ringing for `duration_s`, or `sound.ring_s` with `None`. Returns
+/// the duration used.
+pub fn ring(duration_s: Option<u16>) -> u16 {
+    let default_s = settings::stored(settings::SOUND_RING_S) as u16;
+    let duration_s = duration_s.unwrap_or(default_s);
+    defmt::info!("Sound: ring for {} s", duration_s);
+    REQUEST.signal(Request::Ring { duration_s });
+    duration_s
+}
+
+pub fn stop() {
+    REQUEST.signal(Request::Stop);
+}
+
+/// Beep for an alert on `CH_BUZZER`, unless a ring is playing.
+pub fn alert(severity: Severity) {
+    if !RINGING.load(Ordering::Acquire) {
+        REQUEST.signal(Request::Alert(severity));
+    }
+}
+
+/// Wait for the current ring to time out or be stopped. A ring replaced by
+/// a new one has not ended.
+#[cfg_attr(not(feature = "google-fmdn"), allow(dead_code))]
+pub async fn wait_ring_end() -> RingEnd {
+    RING_END.wait().await
+}
+
+/// Seconds left of the current ring, `0` when not ringing.
+pub fn ring_remaining_s() -> u32 {
+    if !RINGING.load(Ordering::Acquire) {
+        return 0;
+    }
+    let until = RING_UNTIL_S.load(Ordering::Acquire);
+    until.saturating_sub(Instant::now().as_secs() as u32)
+}
+
+async fn play(pwm: &mut SimplePwm<'static>, pattern: &[Tone], until: Option<Instant>) {
+    let volume = settings::stored(settings::SOUND_VOLUME).clamp(0, 100) as u8;
+    loop {
+        for tone in pattern {
+            if tone.freq_hz == 0 || volume == 0 {
+                pwm.disable();
+            } else {
+                pwm.set_period(tone.freq_hz as u32);
+                let duty = tones::duty(pwm.max_duty(), volume);
+                pwm.set_duty(0, DutyCycle::normal(duty));
+                pwm.enable();
+            }
+            Timer::after_millis(tone.ms as u64).await;
+        }
+        match until {
+            Some(until) if Instant::now() < until => {}
+            _ => break,
+        }
+    }
+    pwm.disable();
+}
+
+#[task]
+pub async fn sound_task(mut pwm: SimplePwm<'static>) {
+    pwm.disable();
+    let mut request = REQUEST.wait().await;
+    loop {
+        let (pattern, until) = match request {
+            Request::Ring { duration_s } => {
+                let until = Instant::now() + Duration::from_secs(duration_s as u64);
+                RING_UNTIL_S.store(until.as_secs() as u32, Ordering::Release);
+                RINGING.store(true, Ordering::Release);
+                RING_END.reset();
+                let pattern = settings::stored(settings::SOUND_RING_PATTERN) as u8;
+                (tones::ring(pattern), Some(until))
+            }
+            Request::Alert(severity) => (tones::alert(severity as u8), None),
+            Request::Stop => {
+                request = REQUEST.wait().await;
+                continue;
+            }
+        };
+        let next = select(play(&mut pwm, pattern, until), REQUEST.wait()).await;
+        pwm.disable();
+        let was_ringing = RINGING.swap(false, Ordering::AcqRel);
+        request = match next {
+            Either::First(()) => {
+                if was_ringing {
+                    RING_END.signal(RingEnd::Timeout);
+                }
+                REQUEST.wait().await
+            }
+            Either::Second(next) => {
+                if was_ringing && matches!(next, Request::Stop) {
+                    RING_END.signal(RingEnd::Stopped);
+                }
+                next
+            }
+        };
+    }
+}
@@ -0,0 +1,104 @@
+//! Shared SPIM3 bus for the SD card and an optional LoRa radio.
+//!
+//! Each device owns its chip select and `spim::Config`; a transaction locks
+//! the bus, applies the device's config (the SD card runs at 250 kHz during
+//! init and 16 MHz after, the radio at its own rate and mode), and drives CS
+//! around the operations. Transactions are blocking and short, so one device
+//! never sees another's CS asserted mid-transfer.
+//!
+//! Card-level arbitration (who may use the SD card and for how long) lives in
+//! `sd_arbiter`; this module only serializes raw bus transactions.
+
+use core::cell::RefCell;
+
+use embassy_embedded_hal::SetConfig;
+use embassy_nrf::gpio::Output;
+use embassy_nrf::spim::{self, Spim};
+use embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
+use embassy_sync::blocking_mutex::Mutex as BlockingMutex;
+use embedded_hal::spi::{Operation, SpiBus, SpiDevice};
+
+// ThreadModeRawMutex: SPI is only driven from the thread-mode executor, and
+// unlike NoopRawMutex it keeps devices `Send` so they can sit in static mutexes.
+pub type SharedSpiBus = BlockingMutex<ThreadModeRawMutex, RefCell<Spim<'static>>>;
+
+pub struct SharedSpiDevice {
+    bus: &'static SharedSpiBus,
+    cs: Output<'static>,
+    config: spim::Config,
+}
+
+impl SharedSpiDevice {
+    pub fn new(bus: &'static SharedSpiBus, mut cs: Output<'static>, config: spim::Config) -> Self {
+        cs.set_high();
+        Self { bus, cs, config }
+    }
+
+    pub fn set_frequency(&mut self, frequency: spim::Frequency) {
+        self.config.frequency = frequency;
+    }
+
+    /// Clock out idle bytes with CS deasserted (SD card power-up preamble).
+    pub fn send_idle_clocks(&mut self) -> Result<(), spim::Error> {
+        self.cs.set_high();
+        let idle = [0xFFu8; 10];
+        self.bus.lock(|bus| {
+            let mut spi = bus.borrow_mut();
+            let _ = spi.set_config(&self.config);
+            SpiBus::write(&mut *spi, &idle)?;
+            let _ = SpiBus::flush(&mut *spi);
+            Ok(())
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SharedSpiError {
+    Spi(spim::Error),
+}
+
+impl embedded_hal::spi::Error for SharedSpiError {
+    fn kind(&self) -> embedded_hal::spi::ErrorKind {
+        embedded_hal::spi::ErrorKind::Other
+    }
+}
+
+impl embedded_hal::spi::ErrorType for SharedSpiDevice {
+    type Error = SharedSpiError;
+}
+
+impl SpiDevice<u8> for SharedSpiDevice {
+    fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Self::Error> {
+        self.bus.lock(|bus| {
+            let mut spi = bus.borrow_mut();
+            let spi = &mut *spi;
+            let _ = spi.set_config(&self.config);
+            self.cs.set_low();
+
+            for op in operations {
+                let result = match op {
+                    Operation::Read(buf) => SpiBus::read(spi, buf),
+                    Operation::Write(buf) => SpiBus::write(spi, buf),
+                    Operation::Transfer(read, write) => SpiBus::transfer(spi, read, write),
+                    Operation::TransferInPlace(buf) => SpiBus::transfer_in_place(spi, buf),
+                    Operation::DelayNs(ns) => {
+                        embassy_time::block_for(embassy_time::Duration::from_nanos(*ns as u64));
+                        Ok(())
+                    }
+                };
+                if let Err(err) = result {
+                    self.cs.set_high();
+                    return Err(SharedSpiError::Spi(err));
+                }
+            }
+
+            if let Err(err) = SpiBus::flush(spi) {
+                self.cs.set_high();
+                return Err(SharedSpiError::Spi(err));
+            }
+
+            self.cs.set_high();
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,118 @@
+//! User boot logo: a binary PBM image from the SD card.
+//!
+//! `display.splash` = 2 shows `/SPLASH.PBM` instead of the Ferris logo.
+//! This module checks the file; the display draws it.
+//!
+//! # Design
+//!
+//! - Raw PBM (`P4`), which most image editors export: a text header with
+//!   the width and height, then rows packed MSB first and padded to a
+//!   byte. That is the layout `ImageRaw` draws, so the raster is used in
+//!   place. Set bits (black in an editor) light up.
+//! - At most `MAX_WIDTH` x `MAX_HEIGHT`, the largest panel. A file that
+//!   does not parse, is too large or ends early is rejected as a whole and
+//!   the Ferris logo shows instead.
+
+pub const MAX_WIDTH: u32 = 128;
+pub const MAX_HEIGHT: u32 = 64;
+/// Generous room for the header and its comments.
+const MAX_HEADER_LEN: usize = 64;
+pub const MAX_FILE_LEN: usize = MAX_HEADER_LEN + (MAX_WIDTH / 8 * MAX_HEIGHT) as usize;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Bitmap<'a> {
+    pub width: u32,
+    pub height: u32,
+    /// `height` rows of `(width + 7) / 8` bytes.
+    pub data: &'a [u8],
+}
+
+struct Header<'a> {
+    bytes: &'a [u8],
+    at: usize,
+}
+
+impl Header<'_> {
+    /// Skip whitespace and `#` comments up to the next token.
+    fn skip_space(&mut self) {
+        while let Some(&byte) = self.bytes.get(self.at) {
+            match byte {
+                b'#' => {
+                    while self.bytes.get(self.at).is_some_and(|&byte| byte != b'\n') {
+                        self.at += 1;
+                    }
+                }
+                b' ' | b'\t' | b'\r' | b'\n' => self.at += 1,
+                _ => return,
+            }
+        }
+    }
+
+    fn number(&mut self, max: u32) -> Option<u32> {
+        self.skip_space();
+        let start = self.at;
+        let mut value = 0u32;
+        while let Some(digit) = self.bytes.get(self.at).filter(|byte| byte.is_ascii_digit()) {
+            value = value * 10 + (digit - b'0') as u32;
+            if value > max {
+                return None;
+            }
+            self.at += 1;
+        }
+        (self.at > start && value > 0).then_some(value)
+    }
+}
+
+pub fn parse_pbm(bytes: &[u8]) -> Option<Bitmap<'_>> {
+    let magic = bytes.get(..2)?;
+    if magic != b"P4" {
+        return None;
+    }
+    let mut header = Header { bytes, at: 2 };
+    let width = header.number(MAX_WIDTH)?;
+    let height = header.number(MAX_HEIGHT)?;
+    // Exactly one whitespace byte ends the header.
+    if !bytes.get(header.at)?.is_ascii_whitespace() {
+        return None;
+    }
+    let start = header.at + 1;
+    let len = (width as usize).div_ceil(8) * height as usize;
+    let data = bytes.get(start..start + len)?;
+    Some(Bitmap {
+        width,
+        height,
+        data,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_raw_pbm_with_comments() {
+        let mut file = b"P4\n# made with GIMP\n12 2\n".to_vec();
+        file.extend_from_slice(&[0xFF, 0xF0, 0x80, 0x10]);
+        let bitmap = parse_pbm(&file).unwrap();
+        assert_eq!((bitmap.width, bitmap.height), (12, 2));
+        assert_eq!(bitmap.data, &[0xFF, 0xF0, 0x80, 0x10]);
+
+        // A raster byte that looks like whitespace is still data.
+        let file = b"P4 8 1\n\n";
+        assert_eq!(parse_pbm(file).unwrap().data, b"\n");
+    }
+
+    #[test]
+    fn rejects_malformed_files() {
+        // Plain-text PBM, not raw.
+        assert_eq!(parse_pbm(b"P1\n1 1\n1"), None);
+        assert_eq!(parse_pbm(b"P4\n8 2\n\xFF"), None);
+        assert_eq!(parse_pbm(b"P4\n129 8\n"), None);
+        assert_eq!(parse_pbm(b"P4\n8 65\n"), None);
+        assert_eq!(parse_pbm(b"P4\n0 8\n"), None);
+        assert_eq!(parse_pbm(b"P4\n99999999999 8\n"), None);
+        assert_eq!(parse_pbm(b"P4\n8"), None);
+        assert_eq!(parse_pbm(b"P4\n8 1x\xFF"), None);
+        assert_eq!(parse_pbm(b""), None);
+    }
+}
@@ -0,0 +1,397 @@
+//! Fused stationary detection from accelerometer and GPS.
+//!
+//! The accelerometer alone misreads two common cases: a smooth ride (car,
+//! train) looks as quiet as a desk, and a vibrating mount (motorcycle at a
+//! light) looks like motion. This detector combines three signals, each
+//! with hysteresis, into the single `is_stationary` flag the GPS state
+//! machine, display and GPIO hooks consume:
+//!
+//! - accel dynamic energy (`dyn_g`) and planar drift from `accel`;
+//! - GPS speed, while the receiver has a fix;
+//! - GPS position scatter over the last few fixes, so a speed spike from a
+//!   still receiver (multipath) does not count as motion.
+//!
+//! # Design
+//!
+//! - Entering stationary needs `STILL_ENTER_FRAMES` quiet accel frames and no
+//!   GPS motion; a GPS fix that confirms stillness halves the wait.
+//! - Leaving needs `STILL_EXIT_FRAMES` loud frames, sustained planar drift,
+//!   walking cadence, or GPS speed backed by displacement.
+//! - Self-calibration: while GPS confirms the device is still, the accel
+//!   noise floor is learned and the enter/exit thresholds follow it, so a
+//!   vibrating mount can still settle into stationary.
+//!
+//! Pure logic (no HAL access) so the host test crate can exercise it.
+
+/// GPS speed above which the device is treated as moving.
+pub const GPS_MOVING_SPEED_KMPH: f32 = 5.0;
+const GPS_STILL_SPEED_KMPH: f32 = 1.5;
+
+const DEFAULT_ENTER_DYN_G: f32 = 0.04;
+const MIN_ENTER_DYN_G: f32 = 0.02;
+const MAX_ENTER_DYN_G: f32 = 0.10;
+const EXIT_RATIO: f32 = 2.0;
+/// Enter threshold as a multiple of the learned noise floor.
+const NOISE_MARGIN: f32 = 3.0;
+const NOISE_ALPHA: f32 = 0.01;
+
+const STILL_ENTER_FRAMES: u16 = 120;
+const STILL_EXIT_FRAMES: u16 = 8;
+const PLANAR_MOVE_G: f32 = 0.025;
+const PLANAR_MOVE_FRAMES: u16 = 20;
+
+/// Accel frames between GPS samples (one per second at 50 ms frames).
+const GPS_SAMPLE_FRAMES: u16 = 20;
+const GPS_MOVING_SAMPLES: u8 = 2;
+const SCATTER_SAMPLES: usize = 8;
+const SCATTER_MIN_SAMPLES: usize = 3;
+const SCATTER_STILL_RADIUS_M: f64 = 15.0;
+const METERS_PER_DEG_LAT: f64 = 110_574.0;
+const METERS_PER_DEG_LON_EQUATOR: f64 = 111_320.0;
+
+/// Per-frame accelerometer features.
+#[derive(Clone, Copy)]
+pub struct MotionFrame {
+    /// RMS of the high-passed acceleration, g.
+    pub dyn_g: f32,
+    /// Smoothed acceleration perpendicular to gravity, g.
+    pub planar_g: f32,
+    /// Step detector sees a walking cadence.
+    pub walking: bool,
+}
+
+#[derive(Clone, Copy)]
+pub struct GpsSample {
+    pub speed_kmph: f32,
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+struct Scatter {
+    points: [(f64, f64); SCATTER_SAMPLES],
+    len: usize,
+    next: usize,
+}
+
+impl Scatter {
+    const fn new() -> Self {
+        Self {
+            points: [(0.0, 0.0); SCATTER_SAMPLES],
+            len: 0,
+            next: 0,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.len = 0;
+        self.next = 0;
+    }
+
+    fn push(&mut self, latitude: f64, longitude: f64) {
+        self.points[self.next] = (latitude, longitude);
+        self.next = (self.next + 1) % SCATTER_SAMPLES;
+        self.len = (self.len + 1).min(SCATTER_SAMPLES);
+    }
+
+    /// Whether every recent fix lies within `radius_m` of their centroid;
+    /// `None` until there are enough fixes to judge.
+    fn within(&self, radius_m: f64) -> Option<bool> {
+        if self.len < SCATTER_MIN_SAMPLES {
+            return None;
+        }
+        let points = &self.points[..self.len];
+        let n = self.len as f64;
+        let lat0 = points.iter().map(|p| p.0).sum::<f64>() / n;
+        let lon0 = points.iter().map(|p| p.1).sum::<f64>() / n;
+        let lon_scale = METERS_PER_DEG_LON_EQUATOR * cos_approx(lat0.to_radians());
+        let limit = radius_m * radius_m;
+        Some(points.iter().all(|&(lat, lon)| {
+            let dy = (lat - lat0) * METERS_PER_DEG_LAT;
+            let dx = (lon - lon0) * lon_scale;
+            dx * dx + dy * dy <= limit
+        }))
+    }
+}
+
+/// Cosine for |x| <= pi/2 (latitudes); Taylor series to x^8, error < 1e-5.
+pub(crate) fn cos_approx(x: f64) -> f64 {
+    let x2 = x * x;
+    1.0 - x2 / 2.0 * (1.0 - x2 / 12.0 * (1.0 - x2 / 30.0 * (1.0 - x2 / 56.0)))
+}
+
+pub struct StationaryDetector {
+    stationary: bool,
+    noise_floor_g: f32,
+    still_cnt: u16,
+    move_cnt: u16,
+    planar_move_cnt: u16,
+    frames_since_gps: u16,
+    gps_moving_cnt: u8,
+    gps_still: bool,
+    scatter: Scatter,
+}
+
+impl StationaryDetector {
+    pub const fn new() -> Self {
+        Self {
+            stationary: false,
+            noise_floor_g: DEFAULT_ENTER_DYN_G / NOISE_MARGIN,
+            still_cnt: 0,
+            move_cnt: 0,
+            planar_move_cnt: 0,
+            frames_since_gps: GPS_SAMPLE_FRAMES,
+            gps_moving_cnt: 0,
+            gps_still: false,
+            scatter: Scatter::new(),
+        }
+    }
+
+    pub fn is_stationary(&self) -> bool {
+        self.stationary
+    }
+
+    fn enter_threshold(&self) -> f32 {
+        (self.noise_floor_g * NOISE_MARGIN).clamp(MIN_ENTER_DYN_G, MAX_ENTER_DYN_G)
+    }
+
+    fn note_gps(&mut self, gps: Option<GpsSample>) {
+        let Some(sample) = gps else {
+            // No fix (or GPS off): GPS abstains.
+            self.scatter.clear();
+            self.gps_moving_cnt = 0;
+            self.gps_still = false;
+            self.frames_since_gps = GPS_SAMPLE_FRAMES;
+            return;
+        };
+        self.frames_since_gps = self.frames_since_gps.saturating_add(1);
+        if self.frames_since_gps < GPS_SAMPLE_FRAMES {
+            return;
+        }
+        self.frames_since_gps = 0;
+        self.scatter.push(sample.latitude, sample.longitude);
+        if sample.speed_kmph >= GPS_MOVING_SPEED_KMPH {
+            self.gps_moving_cnt = self.gps_moving_cnt.saturating_add(1);
+        } else {
+            self.gps_moving_cnt = 0;
+        }
+        self.gps_still = sample.speed_kmph < GPS_STILL_SPEED_KMPH
+            && self.scatter.within(SCATTER_STILL_RADIUS_M) == Some(true);
+    }
+
+    fn gps_moving(&self) -> bool {
+        self.gps_moving_cnt >= GPS_MOVING_SAMPLES
+            && self.scatter.within(SCATTER_STILL_RADIUS_M) != Some(true)
+    }
+
+    /// Feed one accel frame plus the current fix (if any); returns the fused state.
+    pub fn update(&mut self, frame: &MotionFrame, gps: Option<GpsSample>) -> bool {
+        self.note_gps(gps);
+        let gps_moving = self.gps_moving();
+        let enter = self.enter_threshold();
+
+        if self.stationary {
+            if frame.dyn_g > enter * EXIT_RATIO {
+                self.move_cnt = self.move_cnt.saturating_add(1);
+            } else {
+                self.move_cnt = 0;
+            }
+            if frame.planar_g > PLANAR_MOVE_G * enter / DEFAULT_ENTER_DYN_G {
+                self.planar_move_cnt = self.planar_move_cnt.saturating_add(1);
+            } else {
+                self.planar_move_cnt = 0;
+            }
+
+            if frame.walking
+                || gps_moving
+                || self.move_cnt >= STILL_EXIT_FRAMES
+                || self.planar_move_cnt >= PLANAR_MOVE_FRAMES
+            {
+                self.stationary = false;
+                self.still_cnt = 0;
+                self.move_cnt = 0;
+                self.planar_move_cnt = 0;
+            } else if self.gps_still {
+                self.noise_floor_g += NOISE_ALPHA * (frame.dyn_g - self.noise_floor_g);
+            }
+        } else {
+            if frame.walking || gps_moving || frame.dyn_g >= enter {
+                self.still_cnt = 0;
+            } else {
+                let credit = if self.gps_still { 2 } else { 1 };
+                self.still_cnt = self.still_cnt.saturating_add(credit);
+            }
+
+            if self.still_cnt >= STILL_ENTER_FRAMES {
+                self.stationary = true;
+                self.still_cnt = 0;
+                self.move_cnt = 0;
+                self.planar_move_cnt = 0;
+            }
+        }
+
+        self.stationary
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QUIET: MotionFrame = MotionFrame {
+        dyn_g: 0.01,
+        planar_g: 0.0,
+        walking: false,
+    };
+    const LOUD: MotionFrame = MotionFrame {
+        dyn_g: 0.20,
+        planar_g: 0.0,
+        walking: false,
+    };
+
+    fn still_fix() -> Option<GpsSample> {
+        Some(GpsSample {
+            speed_kmph: 0.3,
+            latitude: 31.2304,
+            longitude: 121.4737,
+        })
+    }
+
+    fn run(det: &mut StationaryDetector, frame: MotionFrame, frames: usize) -> bool {
+        for _ in 0..frames {
+            det.update(&frame, None);
+        }
+        det.is_stationary()
+    }
+
+    /// Drive north at `speed_kmph` for `seconds`, accel quiet.
+    fn drive(det: &mut StationaryDetector, speed_kmph: f32, seconds: usize) -> bool {
+        let step_deg = speed_kmph as f64 / 3.6 / METERS_PER_DEG_LAT;
+        let mut lat = 31.2304;
+        for _ in 0..seconds {
+            lat += step_deg;
+            let fix = Some(GpsSample {
+                speed_kmph,
+                latitude: lat,
+                longitude: 121.4737,
+            });
+            for _ in 0..GPS_SAMPLE_FRAMES {
+                det.update(&QUIET, fix);
+            }
+        }
+        det.is_stationary()
+    }
+
+    #[test]
+    fn quiet_accel_enters_after_confirm_frames() {
+        let mut det = StationaryDetector::new();
+        assert!(!run(&mut det, QUIET, STILL_ENTER_FRAMES as usize - 1));
+        assert!(run(&mut det, QUIET, 1));
+    }
+
+    #[test]
+    fn loud_frames_exit_with_hysteresis() {
+        let mut det = StationaryDetector::new();
+        run(&mut det, QUIET, STILL_ENTER_FRAMES as usize);
+        // Between the enter and exit thresholds: no change either way.
+        let middle = MotionFrame {
+            dyn_g: 0.06,
+            ..QUIET
+        };
+        assert!(run(&mut det, middle, 200));
+        assert!(run(&mut det, LOUD, STILL_EXIT_FRAMES as usize - 1));
+        assert!(!run(&mut det, LOUD, 1));
+        assert!(!run(&mut det, middle, 200));
+    }
+
+    #[test]
+    fn walking_exits_immediately() {
+        let mut det = StationaryDetector::new();
+        run(&mut det, QUIET, STILL_ENTER_FRAMES as usize);
+        let walking = MotionFrame {
+            walking: true,
+            ..QUIET
+        };
+        assert!(!run(&mut det, walking, 1));
+    }
+
+    #[test]
+    fn smooth_ride_is_not_stationary() {
+        let mut det = StationaryDetector::new();
+        assert!(!drive(&mut det, 40.0, 30));
+    }
+
+    #[test]
+    fn gps_motion_exits_stationary() {
+        let mut det = StationaryDetector::new();
+        run(&mut det, QUIET, STILL_ENTER_FRAMES as usize);
+        assert!(!drive(&mut det, 30.0, 10));
+    }
+
+    #[test]
+    fn speed_spike_without_displacement_is_ignored() {
+        let mut det = StationaryDetector::new();
+        for _ in 0..(GPS_SAMPLE_FRAMES as usize * 10) {
+            det.update(&QUIET, still_fix());
+        }
+        assert!(det.is_stationary());
+        let spike = Some(GpsSample {
+            speed_kmph: 9.0,
+            ..still_fix().unwrap()
+        });
+        for _ in 0..(GPS_SAMPLE_FRAMES as usize * 3) {
+            det.update(&QUIET, spike);
+        }
+        assert!(det.is_stationary());
+    }
+
+    #[test]
+    fn gps_stillness_speeds_up_entry() {
+        let mut det = StationaryDetector::new();
+        // Scatter is judged from the third fix on; after that credit doubles.
+        for _ in 0..(STILL_ENTER_FRAMES - 20) {
+            det.update(&QUIET, still_fix());
+        }
+        assert!(det.is_stationary());
+    }
+
+    #[test]
+    fn noise_floor_calibrates_under_gps_stillness() {
+        let mut det = StationaryDetector::new();
+        run(&mut det, QUIET, STILL_ENTER_FRAMES as usize);
+        // Engine idling: 0.05 g would exceed the default enter threshold.
+        let idle = MotionFrame {
+            dyn_g: 0.05,
+            ..QUIET
+        };
+        for _ in 0..2000 {
+            det.update(&idle, still_fix());
+        }
+        assert!(det.is_stationary());
+        assert!(det.enter_threshold() > 0.05);
+
+        // Ride off, then stop again with the engine still running.
+        assert!(!drive(&mut det, 30.0, 10));
+        let mut entered = false;
+        for _ in 0..(STILL_ENTER_FRAMES as usize * 2) {
+            entered |= det.update(&idle, still_fix());
+        }
+        assert!(entered);
+    }
+
+    #[test]
+    fn threshold_is_clamped() {
+        let mut det = StationaryDetector::new();
+        det.noise_floor_g = 1.0;
+        assert_eq!(det.enter_threshold(), MAX_ENTER_DYN_G);
+        det.noise_floor_g = 0.0;
+        assert_eq!(det.enter_threshold(), MIN_ENTER_DYN_G);
+    }
+
+    #[test]
+    fn cos_approx_matches_std() {
+        for deg in [-90.0f64, -60.0, -30.0, 0.0, 15.0, 45.0, 75.0, 90.0] {
+            let x = deg.to_radians();
+            assert!((cos_approx(x) - x.cos()).abs() < 1e-4, "{deg}");
+        }
+    }
+}
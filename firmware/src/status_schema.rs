@@ -0,0 +1,139 @@
+//! Self-describing layout of the `GET_SYS_INFO` snapshot.
+//!
+//! The snapshot only ever grows at the end, but an app still has to know
+//! each version's layout to read it. `GET_SYS_INFO_SCHEMA` returns this
+//! table instead, so an app built against an older firmware can find the
+//! fields it knows by id in any newer snapshot and skip the rest.
+//!
+//! # Design
+//!
+//! - Field ids are part of the protocol: never renumber or reuse one, only
+//!   append. A field that is dropped from the snapshot keeps its id retired.
+//! - Each entry is `[id: u8][type: u8][offset: u16 LE][size: u8]`; `size`
+//!   also covers fixed-length text such as the firmware version.
+//! - The table is paged so it can outgrow one response.
+
+#[repr(u8)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FieldType {
+    U8 = 1,
+    U16 = 2,
+    U32 = 3,
+    F32 = 4,
+    F64 = 5,
+    /// `u8`, 0 or 1.
+    Bool = 6,
+    /// ASCII padded with `0x00`.
+    Text = 7,
+}
+
+#[derive(Clone, Copy)]
+pub struct SchemaField {
+    pub id: u8,
+    pub kind: FieldType,
+    pub offset: u16,
+    pub size: u8,
+}
+
+pub const ENTRY_LEN: usize = 5;
+/// Bytes described by `SCHEMA`, the V6 snapshot.
+pub const SNAPSHOT_LEN: usize = 104;
+
+const fn field(id: u8, kind: FieldType, offset: u16, size: u8) -> SchemaField {
+    SchemaField {
+        id,
+        kind,
+        offset,
+        size,
+    }
+}
+
+pub const SCHEMA: [SchemaField; 33] = [
+    field(0, FieldType::U8, 0, 1),      // version
+    field(1, FieldType::F64, 1, 8),     // latitude
+    field(2, FieldType::F64, 9, 8),     // longitude
+    field(3, FieldType::F32, 17, 4),    // altitude
+    field(4, FieldType::U32, 21, 4),    // satellites
+    field(5, FieldType::F32, 25, 4),    // hdop
+    field(6, FieldType::F32, 29, 4),    // speed
+    field(7, FieldType::F32, 33, 4),    // course
+    field(8, FieldType::U16, 37, 2),    // year
+    field(9, FieldType::U8, 39, 1),     // month
+    field(10, FieldType::U8, 40, 1),    // day
+    field(11, FieldType::U8, 41, 1),    // hour
+    field(12, FieldType::U8, 42, 1),    // minute
+    field(13, FieldType::U8, 43, 1),    // second
+    field(14, FieldType::Bool, 44, 1),  // location_valid
+    field(15, FieldType::Bool, 45, 1),  // date_time_valid
+    field(16, FieldType::F32, 46, 4),   // battery_voltage
+    field(17, FieldType::U8, 50, 1),    // gps_state
+    field(18, FieldType::U16, 51, 2),   // keep_alive_remaining_s
+    field(19, FieldType::U8, 53, 1),    // battery_percent
+    field(20, FieldType::Bool, 54, 1),  // is_stationary
+    field(21, FieldType::F32, 55, 4),   // temperature_c
+    field(22, FieldType::F32, 59, 4),   // pressure_pa
+    field(23, FieldType::U8, 63, 1),    // fix_mode
+    field(24, FieldType::U8, 64, 1),    // fix_quality
+    field(25, FieldType::Text, 65, 24), // firmware_version
+    field(26, FieldType::U32, 89, 4),   // capabilities
+    field(27, FieldType::U16, 93, 2),   // softdevice_fwid
+    field(28, FieldType::U32, 95, 4),   // bootloader_version
+    field(29, FieldType::Bool, 99, 1),  // debug_protected
+    field(30, FieldType::U8, 100, 1),   // status_flags
+    field(31, FieldType::U8, 101, 1),   // charge_state
+    field(32, FieldType::U16, 102, 2),  // time_to_empty_min
+];
+
+/// Write the entries from `first` on that fit in `out`; returns how many.
+pub fn write_entries(first: usize, out: &mut [u8]) -> usize {
+    let mut count = 0;
+    for (entry, chunk) in SCHEMA
+        .iter()
+        .skip(first)
+        .zip(out.chunks_exact_mut(ENTRY_LEN))
+    {
+        chunk[0] = entry.id;
+        chunk[1] = entry.kind as u8;
+        chunk[2..4].copy_from_slice(&entry.offset.to_le_bytes());
+        chunk[4] = entry.size;
+        count += 1;
+    }
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fields_tile_the_snapshot() {
+        let mut next = 0usize;
+        for (idx, entry) in SCHEMA.iter().enumerate() {
+            assert_eq!(entry.id as usize, idx);
+            assert_eq!(entry.offset as usize, next, "field {}", entry.id);
+            let natural = match entry.kind {
+                FieldType::U8 | FieldType::Bool => Some(1),
+                FieldType::U16 => Some(2),
+                FieldType::U32 | FieldType::F32 => Some(4),
+                FieldType::F64 => Some(8),
+                FieldType::Text => None,
+            };
+            assert!(
+                natural.is_none_or(|size| size == entry.size),
+                "field {}",
+                entry.id
+            );
+            next += entry.size as usize;
+        }
+        assert_eq!(next, SNAPSHOT_LEN);
+    }
+
+    #[test]
+    fn pages_entries() {
+        let mut out = [0u8; 2 * ENTRY_LEN + 3];
+        assert_eq!(write_entries(25, &mut out), 2);
+        assert_eq!(out[..ENTRY_LEN], [25, FieldType::Text as u8, 65, 0, 24]);
+        assert_eq!(write_entries(32, &mut out), 1);
+        assert_eq!(write_entries(33, &mut out), 0);
+    }
+}
@@ -0,0 +1,216 @@
+//! Accelerometer step counting.
+//!
+//! While the GPS is idle (S2) the track has gaps; counting steps there gives
+//! at least activity context. Hourly totals of those steps are appended to
+//! `/STEPS.CSV` as `hour_start_unix,steps` lines. A sustained step cadence
+//! also overrides a `stationary` verdict from the motion filter, so slow
+//! walking with the tracker in a pocket still wakes the GPS.
+//!
+//! Independently of the GPS state, `DailySteps` keeps the day's total for
+//! the display and BLE; finished days go to `/STEPDAYS.CSV`.
+//!
+//! # Design
+//!
+//! - Runs on the motion filter's 20 Hz frames (`FRAME_MS`).
+//! - A step is a peak of the gravity-removed magnitude above `STEP_PEAK_G`
+//!   with hysteresis and a minimum spacing; steps only count once
+//!   `STREAK_MIN_STEPS` arrive with walking cadence, rejecting single bumps.
+//! - With the GPS off there is no clock, so hours are derived from the last
+//!   GPS time plus uptime.
+//! - Days split like the daily logs (UTC or local midnight). Steps before
+//!   the clock is known after boot count towards the day restored from the
+//!   checkpoint, or towards the first known day.
+
+const FRAME_MS: u32 = 50;
+const BASELINE_ALPHA: f32 = 0.05;
+const STEP_PEAK_G: f32 = 0.12;
+const STEP_RELEASE_G: f32 = 0.04;
+const STEP_MIN_INTERVAL_MS: u32 = 250;
+const STEP_MAX_INTERVAL_MS: u32 = 2000;
+const STREAK_MIN_STEPS: u8 = 4;
+const SECS_PER_HOUR: u32 = 3600;
+
+pub struct StepDetector {
+    initialized: bool,
+    baseline: f32,
+    armed: bool,
+    since_step_ms: u32,
+    pending: u8,
+    walking: bool,
+}
+
+impl StepDetector {
+    pub const fn new() -> Self {
+        Self {
+            initialized: false,
+            baseline: 1.0,
+            armed: true,
+            since_step_ms: u32::MAX,
+            pending: 0,
+            walking: false,
+        }
+    }
+
+    /// Feed one frame's acceleration magnitude (g). Returns newly confirmed steps.
+    pub fn update(&mut self, norm: f32) -> u32 {
+        if !self.initialized {
+            self.initialized = true;
+            self.baseline = norm;
+        }
+        self.baseline += BASELINE_ALPHA * (norm - self.baseline);
+        let dynamic = norm - self.baseline;
+        self.since_step_ms = self.since_step_ms.saturating_add(FRAME_MS);
+
+        if self.since_step_ms > STEP_MAX_INTERVAL_MS {
+            self.walking = false;
+            self.pending = 0;
+        }
+
+        if !self.armed {
+            if dynamic < STEP_RELEASE_G {
+                self.armed = true;
+            }
+            return 0;
+        }
+        if dynamic < STEP_PEAK_G || self.since_step_ms < STEP_MIN_INTERVAL_MS {
+            return 0;
+        }
+
+        self.armed = false;
+        self.since_step_ms = 0;
+        if self.walking {
+            return 1;
+        }
+        self.pending += 1;
+        if self.pending >= STREAK_MIN_STEPS {
+            self.walking = true;
+            let confirmed = self.pending as u32;
+            self.pending = 0;
+            return confirmed;
+        }
+        0
+    }
+
+    /// True while steps arrive with walking cadence.
+    pub fn is_walking(&self) -> bool {
+        self.walking
+    }
+}
+
+/// Hourly step totals keyed by UTC hour.
+pub struct HourlySteps {
+    /// Last GPS unix time and the uptime it was observed at.
+    anchor: Option<(u32, u64)>,
+    hour_start: Option<u32>,
+    steps: u32,
+}
+
+impl HourlySteps {
+    pub const fn new() -> Self {
+        Self {
+            anchor: None,
+            hour_start: None,
+            steps: 0,
+        }
+    }
+
+    pub fn note_time(&mut self, unix_ts: u32, now_ms: u64) {
+        self.anchor = Some((unix_ts, now_ms));
+    }
+
+    /// Current unix time extrapolated from the last GPS time.
+    pub fn unix_now(&self, now_ms: u64) -> Option<u32> {
+        let (unix_ts, at_ms) = self.anchor?;
+        let elapsed = (now_ms.saturating_sub(at_ms) / 1000) as u32;
+        Some(unix_ts.saturating_add(elapsed))
+    }
+
+    /// Add steps; returns a finished `(hour_start, steps)` total when the hour rolls over.
+    pub fn add(&mut self, steps: u32, now_ms: u64) -> Option<(u32, u32)> {
+        let now = self.unix_now(now_ms)?;
+        let hour = now - now % SECS_PER_HOUR;
+        let finished = match self.hour_start {
+            Some(prev) if prev != hour && self.steps > 0 => Some((prev, self.steps)),
+            _ => None,
+        };
+        if self.hour_start != Some(hour) {
+            self.hour_start = Some(hour);
+            self.steps = 0;
+        }
+        self.steps = self.steps.saturating_add(steps);
+        finished
+    }
+}
+
+/// Step total of the current day, whatever the GPS state.
+pub struct DailySteps {
+    /// YYYYMMDD, `None` until the clock or a checkpoint says.
+    date: Option<u32>,
+    steps: u32,
+}
+
+impl DailySteps {
+    pub const fn new() -> Self {
+        Self {
+            date: None,
+            steps: 0,
+        }
+    }
+
+    /// Resume a total saved before a reboot.
+    pub fn restore(&mut self, date: u32, steps: u32) {
+        self.date = Some(date);
+        self.steps = steps;
+    }
+
+    /// Add steps counted on `date`, `None` while the clock is unknown.
+    /// Returns the finished `(date, steps)` total when the day changes.
+    pub fn add(&mut self, date: Option<u32>, steps: u32) -> Option<(u32, u32)> {
+        let mut finished = None;
+        if let Some(date) = date {
+            if let Some(prev) = self.date.filter(|&prev| prev != date) {
+                if self.steps > 0 {
+                    finished = Some((prev, self.steps));
+                }
+                self.steps = 0;
+            }
+            self.date = Some(date);
+        }
+        self.steps = self.steps.saturating_add(steps);
+        finished
+    }
+
+    pub fn date(&self) -> Option<u32> {
+        self.date
+    }
+
+    pub fn steps(&self) -> u32 {
+        self.steps
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn day_total_rolls_over() {
+        let mut daily = DailySteps::new();
+        // Steps before the clock is known count towards the first day.
+        assert_eq!(daily.add(None, 5), None);
+        assert_eq!(daily.add(Some(20261014), 3), None);
+        assert_eq!(daily.steps(), 8);
+        assert_eq!(daily.add(Some(20261015), 2), Some((20261014, 8)));
+        assert_eq!(daily.date(), Some(20261015));
+        assert_eq!(daily.steps(), 2);
+    }
+
+    #[test]
+    fn stale_checkpoint_is_finished_on_the_next_day() {
+        let mut daily = DailySteps::new();
+        daily.restore(20261013, 4000);
+        assert_eq!(daily.add(None, 10), None);
+        assert_eq!(daily.add(Some(20261014), 0), Some((20261013, 4010)));
+        assert_eq!(daily.steps(), 0);
+    }
+}
@@ -1,28 +1,64 @@
 use core::cell::{Cell, RefCell};
 use core::cmp::Ordering;
-use core::sync::atomic::{AtomicU32, Ordering as AtomicOrdering};
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU8, Ordering as AtomicOrdering};
 
-use embassy_embedded_hal::SetConfig;
+use embassy_executor::task;
+use embassy_futures::select::{select3, Either3};
 use embassy_nrf::gpio::Output;
-use embassy_nrf::spim::{self, Spim};
+use embassy_nrf::spim;
 use embassy_sync::blocking_mutex::raw::{CriticalSectionRawMutex, ThreadModeRawMutex};
 use embassy_sync::blocking_mutex::Mutex as BlockingMutex;
+use embassy_sync::channel::Channel;
 use embassy_sync::mutex::Mutex;
-use embassy_time::{Delay, Instant};
-use embedded_hal::spi::{Operation, SpiBus, SpiDevice};
+use embassy_sync::signal::Signal;
+use embassy_time::{Delay, Duration, Instant, Timer};
 use embedded_sdmmc::{
-    DirEntry, Error, Mode, RawDirectory, RawFile, RawVolume, SdCard, ShortFileName, TimeSource,
-    Timestamp, VolumeIdx, VolumeManager,
+    Block, BlockDevice, BlockIdx, DirEntry, Error, LfnBuffer, Mode, RawDirectory, RawFile,
+    RawVolume, SdCard, ShortFileName, TimeSource, Timestamp, VolumeIdx, VolumeManager,
 };
 use libm::{round, roundf};
 
-// Max open: 6 dirs (root + listing + ensure_log_directory peak + margin), 4 files, 1 volume
+#[cfg(feature = "lora")]
+use crate::lorawan::LORA_CONFIG_LEN;
+use crate::activity::Activity;
+use crate::bmp280::Bmp280Data;
+use crate::bonds::{BONDS_FILE_MAX_LEN, BOND_RECORD_LEN};
+use crate::crash::{self, BootReport, CrashKind};
+use crate::diag::{self, TaskId};
+use crate::fat_lfn;
+use crate::fix_stats::{FixAttempt, FIX_STATS_LEN};
+use crate::fuel_gauge::{GaugeReading, GaugeSample};
+use crate::gpx_export;
+use crate::gpz::{GpzDecoder, ValidPrefix, HEADER_ACTIVITY, HEADER_FIX};
+use crate::guest::LOCKDOWN_CONFIG_LEN;
+use crate::log_block::LogBlock;
+use crate::pressure_trend::Tendency;
+use crate::recording::RECORDING_CONFIG_LEN;
+use crate::sd_arbiter::{self, SdGuard, SdPriority};
+use crate::sessions;
+use crate::settings::SETTINGS_FILE_MAX_LEN;
+use crate::spi_bus::{SharedSpiBus, SharedSpiDevice};
+use crate::system_info::SYSTEM_INFO;
+use crate::timezone::{self, TzCache, TzSettings, TZ_SETTINGS_LEN};
+use crate::track_stats::{self, TODAY_STATS_LEN};
+
+// Max open: 6 dirs (root + listing + ensure_log_directory peak + margin, or
+// archive copy: source month + ARCHIVE + year + month), 4 files, 1 volume
 type SdVolumeManager = VolumeManager<SdCard<SdSpiDevice, Delay>, GpsTimeSource, 6, 4, 1>;
 
 // Global GPS time storage (bit-packed FAT timestamp)
 // Format: bits 31-25: year-1980, bits 24-21: month, bits 20-16: day,
 //         bits 15-11: hour, bits 10-5: minute, bits 4-0: second/2
 static GPS_TIME: AtomicU32 = AtomicU32::new(0);
+/// `GPS_TIME` of 2025-01-01 00:00, what files are stamped before GPS time.
+const FALLBACK_FAT_TIME: u32 = (2025 - 1980) << 25 | 1 << 21 | 1 << 16;
+// Largest SD write-cache fill seen since boot (diagnostics).
+static CACHE_PEAK_LEN: AtomicU32 = AtomicU32::new(0);
+
+/// Largest SD write-cache occupancy observed since boot, in bytes.
+pub fn cache_peak_len() -> u32 {
+    CACHE_PEAK_LEN.load(AtomicOrdering::Relaxed)
+}
 
 /// Set the GPS time for file timestamps.
 /// Call this when GPS time becomes valid (after NMEA parsing).
@@ -38,12 +74,27 @@ pub fn set_gps_time(year: u16, month: u8, day: u8, hour: u8, minute: u8, second:
     GPS_TIME.store(packed, AtomicOrdering::Relaxed);
 }
 
-const CACHE_SIZE: usize = 4096;
+pub const CACHE_SIZE: usize = 4096;
 const ENCODER_BUFFER_SIZE: usize = 64;
-const FULL_BLOCK_INTERVAL: usize = 64;
+pub(crate) const FULL_BLOCK_INTERVAL: usize = 64;
 const MAX_FILE_SIZE_BYTES: u64 = 1024 * 1024 * 1024;
 const MAX_GPX_FILES: usize = 64;
 const LOG_EXTENSION: &[u8] = b"gpz";
+const GPX_EXTENSION: &[u8] = b"gpx";
+/// Day stats checkpoint next to each log, see `track_stats`.
+const DAY_STATS_EXTENSION: &[u8] = b"sts";
+/// Extension of the button waypoints kept next to a day log.
+const DAY_WAYPOINTS_EXTENSION: &[u8] = b"wpt";
+/// Year directories walked by the GPX export; later years are skipped.
+const MAX_EXPORT_YEARS: usize = 16;
+type DigitDirs = heapless::Vec<ShortFileName, MAX_EXPORT_YEARS>;
+const ARCHIVE_DIR: &str = "ARCHIVE";
+const COPY_CHUNK: usize = 512;
+/// `COPY_CHUNK`s read per SD lock by log scans and copies that span several
+/// locks; 4 KB keeps each hold well inside the Logger limit.
+const CHUNKS_PER_LOCK: usize = 8;
+/// The same for copies at Transfer priority, whose hold limit is 30 ms.
+const TRANSFER_CHUNKS_PER_LOCK: usize = 2;
 pub const MAX_PATH_LENGTH: usize = 64;
 
 pub enum ListDirOutcome {
@@ -57,12 +108,79 @@ pub enum ListDirOutcome {
     Error,
 }
 
+// The GPX log is split in two halves. `LOG_WRITER` holds the RAM side
+// (encoder, day rotation, write cache) and never touches the card, so
+// appends do not wait behind BLE transfers. `SD_LOGGER` owns the card and
+// file system for everyone else, and `log_writer_task` moves full caches
+// from one to the other through `LOG_BLOCK`, one block per SD lock. The
+// one exception is a day switch (including the first point after boot),
+// which reads that day's log once, a few KB per SD lock, so points already
+// in it are not logged again.
+//
+// Lock order: `LOG_WRITER`, then `LOG_TAIL_SCAN`, then `SD_LOGGER`, then
+// `LOG_BLOCK`.
 static SD_LOGGER: Mutex<CriticalSectionRawMutex, Option<SdLogger>> = Mutex::new(None);
+static LOG_WRITER: Mutex<CriticalSectionRawMutex, LogWriter> = Mutex::new(LogWriter::new());
+static LOG_BLOCK: Mutex<CriticalSectionRawMutex, LogBlock<CACHE_SIZE>> =
+    Mutex::new(LogBlock::new());
+static LOG_BLOCK_READY: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+static LOG_BLOCK_WRITTEN: Signal<CriticalSectionRawMutex, bool> = Signal::new();
+/// Held across a `log_tail_timestamp` scan, so two tasks never check or cut
+/// the same log at once.
+static LOG_TAIL_SCAN: Mutex<CriticalSectionRawMutex, ()> = Mutex::new(());
+/// Whether `SD_LOGGER` holds a mounted card (false in USB mode).
+static LOGGER_READY: AtomicBool = AtomicBool::new(false);
+/// The card stopped answering (or was never found) and sits in
+/// `REMOVED_CARD`. Points stay in RAM until it mounts again.
+static CARD_REMOVED: AtomicBool = AtomicBool::new(false);
+/// A card missing at boot, or removed since, was mounted by `check_card`.
+static CARD_MOUNTED: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+/// The card's configuration files have been read since boot. Until then
+/// they are not written, so values set without a card never replace the
+/// card's own.
+static CONFIG_READ: AtomicBool = AtomicBool::new(false);
+/// A file write failed; `log_writer_task` probes the card now instead of
+/// at the next poll.
+static PROBE_CARD: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+/// How often `log_writer_task` probes a mounted card, and the first retry
+/// for a removed one.
+const CARD_POLL_S: u64 = 5;
+/// Retries for a removed card double up to this, so a card left out does
+/// not cost an SPI init every few seconds.
+const CARD_RETRY_MAX_S: u64 = 30;
+/// Day (YYYYMMDD) being logged, 0 before the first point.
+static CURRENT_LOG_DATE: AtomicU32 = AtomicU32::new(0);
+static DISCARD_LOG_CACHE: AtomicBool = AtomicBool::new(false);
+/// Failed log writes in a row (refused points or dropped blocks).
+static LOG_FAILURE_STREAK: AtomicU8 = AtomicU8::new(0);
+static LOG_DEGRADED: AtomicBool = AtomicBool::new(false);
+/// `Instant` seconds of the last block written to the card, plus one; `0`
+/// before the first.
+static LAST_LOG_WRITE_S: AtomicU32 = AtomicU32::new(0);
+/// Consecutive failed log writes before logging counts as degraded.
+const LOG_DEGRADED_AFTER: u8 = 3;
+/// Writes of one log block before it is dropped, on a card that still
+/// answers; the waits between them double from `LOG_RETRY_BASE_MS`.
+const LOG_BLOCK_ATTEMPTS: u8 = 4;
+const LOG_RETRY_BASE_MS: u64 = 1_000;
+/// Log blocks dropped after `LOG_BLOCK_ATTEMPTS` failed writes.
+static BLOCKS_DROPPED: AtomicU32 = AtomicU32::new(0);
+
+async fn lock_logger(
+    priority: SdPriority,
+) -> SdGuard<'static, CriticalSectionRawMutex, Option<SdLogger>> {
+    sd_arbiter::lock(&SD_LOGGER, priority).await
+}
 // ThreadModeRawMutex: USB_CARD is only accessed from the single-threaded executor,
 // so a lightweight thread-mode mutex (no critical section) is sufficient.
 static USB_CARD: BlockingMutex<ThreadModeRawMutex, RefCell<Option<UsbSdCard>>> =
     BlockingMutex::new(RefCell::new(None));
+// The board has no card-detect line: a card that stops answering is parked
+// here, unmounted, and `log_writer_task` keeps trying to mount it again.
+static REMOVED_CARD: BlockingMutex<ThreadModeRawMutex, RefCell<Option<UsbSdCard>>> =
+    BlockingMutex::new(RefCell::new(None));
 
+/// A card without a mounted file system (USB mode or removed).
 struct UsbSdCard {
     card: SdCard<SdSpiDevice, Delay>,
     init_frequency: spim::Frequency,
@@ -84,19 +202,31 @@ impl UsbSdCard {
 }
 
 pub fn init_sd_logger(
-    spi: Spim<'static>,
-    mut cs: Output<'static>,
+    bus: &'static SharedSpiBus,
+    cs: Output<'static>,
     config: spim::Config,
     run_frequency: spim::Frequency,
 ) -> bool {
-    cs.set_high();
     let init_frequency = config.frequency;
-    let Some(logger) = create_logger(spi, cs, config, init_frequency, run_frequency) else {
+    let Some(card) = create_card(bus, cs, config) else {
         defmt::warn!("SD idle clock preamble failed");
         return false;
     };
+    let logger = match mount_card(UsbSdCard::new(card, init_frequency, run_frequency)) {
+        Ok(logger) => logger,
+        Err(card) => {
+            // Keep the card so an inserted one is picked up later.
+            park_removed_card(card);
+            if let Ok(mut info) = SYSTEM_INFO.try_lock() {
+                info.sd_card_missing = true;
+            }
+            defmt::warn!("SD: no card, waiting for insertion");
+            return false;
+        }
+    };
     if let Ok(mut guard) = SD_LOGGER.try_lock() {
         *guard = Some(logger);
+        LOGGER_READY.store(true, AtomicOrdering::Release);
         defmt::info!("SD logger initialized");
         return true;
     }
@@ -104,57 +234,211 @@ pub fn init_sd_logger(
     false
 }
 
-pub async fn enter_usb_mode() -> bool {
-    let logger = {
-        let mut guard = SD_LOGGER.lock().await;
-        guard.take()
+/// Who may touch the card. Every hand-over between the logger and the USB
+/// host goes through `Transitioning`, during which neither side gets it:
+/// SCSI commands see "becoming ready" and logger code sees no logger.
+#[repr(u8)]
+#[derive(Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum CardOwner {
+    /// `SD_LOGGER` (or `REMOVED_CARD` while the card is out).
+    Logger = 0,
+    /// `USB_CARD`, block access for the MSC host only.
+    UsbHost = 1,
+    Transitioning = 2,
+}
+
+static CARD_OWNER: AtomicU8 = AtomicU8::new(CardOwner::Logger as u8);
+
+pub fn card_owner() -> CardOwner {
+    match CARD_OWNER.load(AtomicOrdering::Acquire) {
+        1 => CardOwner::UsbHost,
+        2 => CardOwner::Transitioning,
+        _ => CardOwner::Logger,
+    }
+}
+
+/// Move ownership from `from` to `to`; refused if the card is not owned by
+/// `from`.
+fn transfer_card(from: CardOwner, to: CardOwner) -> bool {
+    let moved = CARD_OWNER
+        .compare_exchange(
+            from as u8,
+            to as u8,
+            AtomicOrdering::AcqRel,
+            AtomicOrdering::Acquire,
+        )
+        .is_ok();
+    if !moved {
+        defmt::warn!("SD: card owner is {}, refusing {} -> {}", card_owner(), from, to);
+    }
+    moved
+}
+
+/// Hand the card over for USB mode. With `export_gpx`, GPX copies of the
+/// logs are written first (see `gpx_export`).
+pub async fn enter_usb_mode(export_gpx: bool) -> bool {
+    match card_owner() {
+        CardOwner::UsbHost => {
+            defmt::info!("enter_usb_mode: already in usb mode");
+            return true;
+        }
+        CardOwner::Transitioning => {
+            defmt::warn!("enter_usb_mode: hand-over already in progress");
+            return false;
+        }
+        CardOwner::Logger => {}
+    }
+    if !transfer_card(CardOwner::Logger, CardOwner::Transitioning) {
+        return false;
+    }
+
+    // Park the writer first so nothing is encoded against a card the host
+    // is about to modify; the next point after USB starts a full block.
+    let mut writer = LOG_WRITER.lock().await;
+    hand_off_log_cache(&mut writer).await;
+    write_log_block().await;
+    writer.encoder.clear();
+    LOGGER_READY.store(false, AtomicOrdering::Release);
+    drop(writer);
+
+    if export_gpx {
+        let exported = export_gpx_logs().await;
+        defmt::info!("enter_usb_mode: exported {} GPX files", exported);
+    }
+    let logger = lock_logger(SdPriority::Config).await.take();
+
+    let Some(logger) = logger else {
+        defmt::warn!("enter_usb_mode: no logger");
+        transfer_card(CardOwner::Transitioning, CardOwner::Logger);
+        return false;
     };
+    let usb_card = logger.into_usb_card();
+    USB_CARD.lock(|card| {
+        let mut card = card.borrow_mut();
+        defmt::debug_assert!(card.is_none(), "USB card slot already taken");
+        *card = Some(usb_card);
+    });
+    transfer_card(CardOwner::Transitioning, CardOwner::UsbHost);
+    defmt::info!("enter_usb_mode: logger -> usb card");
+    true
+}
 
-    if let Some(logger) = logger {
-        let usb_card = logger.into_usb_card();
-        USB_CARD.lock(|card| {
-            *card.borrow_mut() = Some(usb_card);
-        });
-        defmt::info!("enter_usb_mode: logger -> usb card");
-        return true;
+/// Walk `/YYYY/MM/` and write `YYYYMMDD.gpx` beside every log whose GPX
+/// is missing or stale, one directory listing, GPX check or few KB of log
+/// per SD lock. Returns the number of files written.
+async fn export_gpx_logs() -> u32 {
+    let years = match lock_logger(SdPriority::Config).await.as_mut() {
+        Some(logger) => logger.digit_dirs(b"", 4),
+        None => return 0,
+    };
+    let mut exported = 0;
+    for year in &years {
+        let months = match lock_logger(SdPriority::Config).await.as_mut() {
+            Some(logger) => logger.digit_dirs(year.base_name(), 2),
+            None => return exported,
+        };
+        for month in &months {
+            let dir = month_dir_path(year, month);
+            let candidates = match lock_logger(SdPriority::Config).await.as_mut() {
+                Some(logger) => logger.gpx_candidates(&dir),
+                None => return exported,
+            };
+            for (log, check) in &candidates {
+                if *check {
+                    let current = match lock_logger(SdPriority::Config).await.as_mut() {
+                        Some(logger) => logger.gpx_up_to_date(&dir, log),
+                        None => return exported,
+                    };
+                    if current {
+                        continue;
+                    }
+                }
+                let Some(mut export) = GpxExport::new(dir, log) else {
+                    continue;
+                };
+                if export_gpx_file(&mut export).await {
+                    exported += 1;
+                } else {
+                    defmt::warn!("SD: GPX export of {} failed", export.src.as_str());
+                }
+            }
+        }
     }
+    exported
+}
 
-    let has_usb = USB_CARD.lock(|card| card.borrow().is_some());
-    if has_usb {
-        defmt::info!("enter_usb_mode: already in usb mode");
-    } else {
-        defmt::warn!("enter_usb_mode: no logger or usb card");
+async fn export_gpx_file(export: &mut GpxExport) -> bool {
+    loop {
+        let step = match lock_logger(SdPriority::Config).await.as_mut() {
+            Some(logger) => logger.export_gpx_step(export),
+            None => None,
+        };
+        match step {
+            Some(true) => embassy_futures::yield_now().await,
+            Some(false) => return true,
+            None => return false,
+        }
     }
-    has_usb
 }
 
 pub async fn exit_usb_mode() -> bool {
+    match card_owner() {
+        CardOwner::Logger => {
+            let guard = lock_logger(SdPriority::Config).await;
+            let has_logger = guard.is_some();
+            if has_logger {
+                defmt::info!("exit_usb_mode: logger already active");
+            } else {
+                defmt::warn!("exit_usb_mode: no usb card or logger");
+            }
+            return has_logger;
+        }
+        CardOwner::Transitioning => {
+            defmt::warn!("exit_usb_mode: hand-over already in progress");
+            return false;
+        }
+        CardOwner::UsbHost => {}
+    }
+    // From here on SCSI commands no longer reach the card, so the host
+    // cannot write behind the file system being mounted.
+    if !transfer_card(CardOwner::UsbHost, CardOwner::Transitioning) {
+        return false;
+    }
     let usb_card = USB_CARD.lock(|card| card.borrow_mut().take());
     let Some(usb_card) = usb_card else {
-        let guard = SD_LOGGER.lock().await;
-        let has_logger = guard.is_some();
-        if has_logger {
-            defmt::info!("exit_usb_mode: logger already active");
-        } else {
-            defmt::warn!("exit_usb_mode: no usb card or logger");
-        }
-        return has_logger;
+        defmt::warn!("exit_usb_mode: usb card slot empty");
+        transfer_card(CardOwner::Transitioning, CardOwner::Logger);
+        return false;
     };
 
-    let Some(logger) = rebuild_logger(usb_card) else {
-        defmt::warn!("exit_usb_mode: rebuild logger failed");
-        return false;
+    let logger = match mount_card(usb_card) {
+        Ok(logger) => logger,
+        Err(card) => {
+            // Pulled while in USB mode: treat it like any other removal.
+            park_removed_card(card);
+            transfer_card(CardOwner::Transitioning, CardOwner::Logger);
+            publish_card_missing(true).await;
+            defmt::warn!("exit_usb_mode: rebuild logger failed");
+            return false;
+        }
     };
-    let mut guard = SD_LOGGER.lock().await;
+    let mut guard = lock_logger(SdPriority::Config).await;
+    defmt::debug_assert!(guard.is_none(), "logger mounted during USB mode");
     *guard = Some(logger);
+    LOGGER_READY.store(true, AtomicOrdering::Release);
+    transfer_card(CardOwner::Transitioning, CardOwner::Logger);
     defmt::info!("exit_usb_mode: logger restored");
     true
 }
 
+/// Run `f` on the raw card. `None` unless the USB host owns the card.
 pub fn with_usb_card<F, R>(f: F) -> Option<R>
 where
     F: FnOnce(&mut SdCard<SdSpiDevice, Delay>) -> R,
 {
+    if card_owner() != CardOwner::UsbHost {
+        return None;
+    }
     USB_CARD.lock(|card| {
         let mut card = card.borrow_mut();
         match card.as_mut() {
@@ -164,29 +448,614 @@ where
     })
 }
 
-pub async fn append_gpx_point(
+/// A fix waiting for `point_writer_task`.
+#[derive(Clone, Copy)]
+pub struct QueuedPoint {
+    pub timestamp: u32,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub altitude_m: f32,
+    pub pressure_pa: Option<f32>,
+    pub speed_kmh: f32,
+    pub activity: Activity,
+    /// As `SystemInfo::fix_mode` and `fix_quality`, logged in fix blocks.
+    pub fix_mode: u8,
+    pub fix_quality: u8,
+}
+
+/// Points waiting for the log cache. A slow card then holds up only the
+/// writer task, not the GPS state machine; at 1 Hz this covers half a
+/// minute of stalled writes.
+pub const POINT_QUEUE_LEN: usize = 32;
+static POINT_QUEUE: Channel<CriticalSectionRawMutex, QueuedPoint, POINT_QUEUE_LEN> = Channel::new();
+/// Queued points not yet through `append_gpx_point`, the one in hand
+/// included.
+static POINTS_PENDING: AtomicU32 = AtomicU32::new(0);
+static POINT_QUEUE_PEAK: AtomicU32 = AtomicU32::new(0);
+static POINTS_DROPPED: AtomicU32 = AtomicU32::new(0);
+/// No more points are queued: power off has begun.
+static POINT_QUEUE_CLOSED: AtomicBool = AtomicBool::new(false);
+/// Longest `flush_sd_cache` waits for queued points to reach the cache.
+const POINT_QUEUE_DRAIN_MS: u64 = 2_000;
+
+/// Queue a point for the log without waiting. A full queue drops the point
+/// and counts it.
+pub fn queue_gpx_point(point: QueuedPoint) -> bool {
+    if POINT_QUEUE_CLOSED.load(AtomicOrdering::Acquire) {
+        return false;
+    }
+    if POINT_QUEUE.try_send(point).is_err() {
+        POINTS_DROPPED.fetch_add(1, AtomicOrdering::Relaxed);
+        defmt::warn!("GPS log: point queue full, point dropped");
+        return false;
+    }
+    POINTS_PENDING.fetch_add(1, AtomicOrdering::AcqRel);
+    POINT_QUEUE_PEAK.fetch_max(POINT_QUEUE.len() as u32, AtomicOrdering::Relaxed);
+    true
+}
+
+/// Refuse further points, for power off. Points already queued are still
+/// logged by the next `flush_sd_cache`.
+pub fn close_point_queue() {
+    POINT_QUEUE_CLOSED.store(true, AtomicOrdering::Release);
+}
+
+/// Deepest the point queue has been since boot.
+pub fn point_queue_peak() -> u32 {
+    POINT_QUEUE_PEAK.load(AtomicOrdering::Relaxed)
+}
+
+/// Points dropped on a full queue since boot.
+pub fn points_dropped() -> u32 {
+    POINTS_DROPPED.load(AtomicOrdering::Relaxed)
+}
+
+/// Log blocks dropped after repeated write failures since boot.
+pub fn blocks_dropped() -> u32 {
+    BLOCKS_DROPPED.load(AtomicOrdering::Relaxed)
+}
+
+/// Logs queued points, then counts the logged ones towards the session and
+/// today's stats. Spawn once at boot, whatever the features, or the queue
+/// fills and every point counts as dropped.
+#[task]
+pub async fn point_writer_task() {
+    loop {
+        let point = POINT_QUEUE.receive().await;
+        let logged = append_gpx_point(
+            point.timestamp,
+            point.latitude,
+            point.longitude,
+            point.altitude_m,
+            point.pressure_pa,
+            point.activity,
+            (point.fix_mode, point.fix_quality),
+        )
+        .await;
+        POINTS_PENDING.fetch_sub(1, AtomicOrdering::AcqRel);
+        if !logged {
+            continue;
+        }
+        sessions::note_point(point.timestamp, point.activity).await;
+        track_stats::note_point(
+            point.timestamp,
+            point.latitude,
+            point.longitude,
+            point.altitude_m,
+            point.speed_kmh,
+        )
+        .await;
+    }
+}
+
+/// Wait, for a bounded time, until queued points are in the log cache.
+async fn drain_point_queue() {
+    let deadline = Instant::now() + Duration::from_millis(POINT_QUEUE_DRAIN_MS);
+    while POINTS_PENDING.load(AtomicOrdering::Acquire) > 0 && Instant::now() < deadline {
+        Timer::after_millis(10).await;
+    }
+}
+
+/// Encode a point into the RAM log cache. Never waits for the card unless
+/// the previous full cache is still being written.
+async fn append_gpx_point(
     timestamp: u32,
     latitude: f64,
     longitude: f64,
     altitude_m: f32,
+    pressure_pa: Option<f32>,
+    activity: Activity,
+    fix: (u8, u8),
 ) -> bool {
-    let mut logger = SD_LOGGER.lock().await;
-    let Some(logger) = logger.as_mut() else {
+    let buffering = CARD_REMOVED.load(AtomicOrdering::Acquire);
+    if !LOGGER_READY.load(AtomicOrdering::Acquire) && !buffering {
+        // No card mounted. USB mode pauses logging on purpose.
+        if card_owner() == CardOwner::Logger {
+            note_log_write(false).await;
+        }
+        return false;
+    }
+    let mut writer = LOG_WRITER.lock().await;
+    let writer = &mut *writer;
+    if DISCARD_LOG_CACHE.swap(false, AtomicOrdering::AcqRel) {
+        writer.cache_len = 0;
+        writer.encoder.clear();
+    }
+    if !writer.accept_timestamp(timestamp) {
         return false;
+    }
+
+    let Some(date) = writer.log_date_for(timestamp, latitude, longitude) else {
+        return false;
+    };
+    if date != writer.current_date {
+        if !hand_off_log_cache(writer).await {
+            // The old day's points cannot leave RAM yet.
+            note_log_write(false).await;
+            return false;
+        }
+        writer.current_date = date;
+        writer.encoder.clear();
+        writer.resume_after = log_tail_timestamp(date).await.unwrap_or(0);
+        CURRENT_LOG_DATE.store(date, AtomicOrdering::Release);
+    }
+    if timestamp <= writer.resume_after {
+        // A reboot mid-trip: the point is already in the log, or would go
+        // behind its last point.
+        defmt::warn!("GPS log skipped: not after the last logged point");
+        return false;
+    }
+    writer.resume_after = 0;
+
+    let entry = GpxPointInternal::new(timestamp, latitude, longitude, altitude_m, pressure_pa);
+    let len = writer.encoder.encode_labelled(entry, activity as u8, fix);
+    let data = writer.encoder.buffer();
+    if data.len() != len {
+        return false;
+    }
+    if writer.cache_len + len > CACHE_SIZE && !hand_off_log_cache(writer).await {
+        // Card removed and both RAM buffers are full.
+        note_log_write(false).await;
+        return false;
+    }
+
+    let data = writer.encoder.buffer();
+    writer.cache[writer.cache_len..writer.cache_len + len].copy_from_slice(data);
+    writer.cache_len += len;
+    writer.cache_last_timestamp = timestamp;
+    CACHE_PEAK_LEN.fetch_max(writer.cache_len as u32, AtomicOrdering::Relaxed);
+
+    if writer.cache_len >= CACHE_SIZE {
+        hand_off_log_cache(writer).await;
+    }
+    true
+}
+
+/// Timestamp of the last valid point in the log of `date`, if it exists.
+/// The log last written or scanned is answered from memory; any other is
+/// decoded `CHUNKS_PER_LOCK` chunks per SD lock. The first log looked at
+/// since mount has its tail repaired on the way.
+async fn log_tail_timestamp(date: u32) -> Option<u32> {
+    let _scan = LOG_TAIL_SCAN.lock().await;
+    let repair = {
+        let mut logger = lock_logger(SdPriority::Logger).await;
+        let logger = logger.as_mut()?;
+        if let Some((tail_date, last)) = logger.log_tail {
+            if tail_date == date {
+                return last;
+            }
+        }
+        !core::mem::replace(&mut logger.log_tail_checked, true)
+    };
+    if repair {
+        restore_log_shadow(date).await;
+    }
+
+    let mut scan = LogScan::new();
+    loop {
+        let step = match lock_logger(SdPriority::Logger).await.as_mut() {
+            Some(logger) => logger.scan_log_step(date, &mut scan),
+            None => return None,
+        };
+        if step != Some(true) {
+            break;
+        }
+        embassy_futures::yield_now().await;
+    }
+    let last = scan.prefix.last_timestamp();
+    let valid_len = scan.prefix.valid_len();
+    if repair && valid_len < scan.size {
+        cut_log_tail(date, valid_len, scan.size).await;
+    }
+    if let Some(logger) = lock_logger(SdPriority::Logger).await.as_mut() {
+        logger.log_tail = Some((date, last));
+    }
+    last
+}
+
+// A power cut mid-write can leave a torn block or a zero-filled cluster at
+// the end of the log. Points appended behind it would decode as garbage,
+// so before the first append since mount the file is decoded up to the
+// last valid block and anything after it cut off.
+//
+// embedded-sdmmc cannot truncate to a length, so the valid part is copied
+// to the shadow name (`YYYYMMDD.gp~`) and back over the truncated original.
+// A shadow found before the scan means that copy-back was interrupted; it
+// is complete whenever it is longer than the original.
+async fn restore_log_shadow(date: u32) {
+    let Some((dir, name, shadow)) = log_paths(date) else {
+        return;
+    };
+    let (name, shadow) = (name.as_str(), shadow.as_str());
+    let lens = match lock_logger(SdPriority::Logger).await.as_mut() {
+        Some(logger) => logger.shadow_lens(&dir, name, shadow),
+        None => return,
+    };
+    let Some((original_len, shadow_len)) = lens else {
+        return;
+    };
+    if original_len < shadow_len {
+        defmt::warn!("SD: restoring {} from interrupted tail repair", name);
+        let mode = Mode::ReadWriteCreateOrTruncate;
+        let mut copy = FileCopy::new(&dir, shadow, &dir, name, shadow_len, mode);
+        if !copy_file_chunked(SdPriority::Logger, &mut copy).await {
+            return;
+        }
+    }
+    if let Some(logger) = lock_logger(SdPriority::Logger).await.as_mut() {
+        logger.delete_in_dir(&dir, shadow);
+    }
+}
+
+/// Cut the log of `date` back to its first `valid_len` bytes.
+async fn cut_log_tail(date: u32, valid_len: u32, size: u32) {
+    let Some((dir, name, shadow)) = log_paths(date) else {
+        return;
+    };
+    let (name, shadow) = (name.as_str(), shadow.as_str());
+    defmt::warn!(
+        "SD: {} has {} bytes after the last valid block, truncating",
+        name,
+        size - valid_len
+    );
+    let mode = Mode::ReadWriteCreateOrTruncate;
+    let mut copy = FileCopy::new(&dir, name, &dir, shadow, valid_len, mode);
+    if !copy_file_chunked(SdPriority::Logger, &mut copy).await {
+        return;
+    }
+    let mode = Mode::ReadWriteTruncate;
+    let mut copy = FileCopy::new(&dir, shadow, &dir, name, valid_len, mode);
+    if !copy_file_chunked(SdPriority::Logger, &mut copy).await {
+        return;
+    }
+    if let Some(logger) = lock_logger(SdPriority::Logger).await.as_mut() {
+        logger.delete_in_dir(&dir, shadow);
+    }
+}
+
+/// Month directory, file name and shadow name of the log of `date`.
+fn log_paths(date: u32) -> Option<([u8; 7], Filename, heapless::String<12>)> {
+    let (year, month, day) = date_parts(date)?;
+    let name = build_bare_filename(year, month, day);
+    let shadow = shadow_name(name.as_str())?;
+    Some((month_path(year, month), name, shadow))
+}
+
+/// `YYYY/MM`.
+fn month_path(year: u16, month: u8) -> [u8; 7] {
+    let mut path = [b'/'; 7];
+    path[..4].copy_from_slice(&year_to_digits(year));
+    path[5..].copy_from_slice(&two_digits(month));
+    path
+}
+
+/// `YYYY/MM` from the names `digit_dirs` returned.
+fn month_dir_path(year: &ShortFileName, month: &ShortFileName) -> [u8; 7] {
+    let mut path = [b'/'; 7];
+    path[..4].copy_from_slice(year.base_name());
+    path[5..].copy_from_slice(month.base_name());
+    path
+}
+
+/// Run `copy` to the end, a few chunks per SD lock. A failed copy leaves
+/// no destination file unless the card went away mid-copy.
+async fn copy_file_chunked(priority: SdPriority, copy: &mut FileCopy<'_>) -> bool {
+    let chunks = match priority {
+        SdPriority::Transfer => TRANSFER_CHUNKS_PER_LOCK,
+        _ => CHUNKS_PER_LOCK,
     };
-    logger.append_gpx_point(timestamp, latitude, longitude, altitude_m)
+    loop {
+        let step = match lock_logger(priority).await.as_mut() {
+            Some(logger) => logger.copy_file_step(copy, chunks),
+            None => None,
+        };
+        match step {
+            Some(true) => embassy_futures::yield_now().await,
+            Some(false) => return true,
+            None => return false,
+        }
+    }
 }
 
+/// Write everything logged so far to the card.
 pub async fn flush_sd_cache() -> bool {
-    let mut logger = SD_LOGGER.lock().await;
-    let Some(logger) = logger.as_mut() else {
+    drain_point_queue().await;
+    {
+        let mut writer = LOG_WRITER.lock().await;
+        hand_off_log_cache(&mut writer).await;
+    }
+    write_log_block().await
+}
+
+/// Writes log caches handed off by `append_gpx_point` and watches for card
+/// removal and reinsertion. Spawn once at boot.
+#[task]
+pub async fn log_writer_task() {
+    let mut poll_s = CARD_POLL_S;
+    let mut next_check = Instant::now() + Duration::from_secs(poll_s);
+    loop {
+        let retry_at = LOG_BLOCK
+            .lock()
+            .await
+            .retry_at_ms()
+            .map(Instant::from_millis);
+        let wake_at = retry_at.map_or(next_check, |at| at.min(next_check));
+        let wake = select3(
+            LOG_BLOCK_READY.wait(),
+            PROBE_CARD.wait(),
+            Timer::at(wake_at),
+        )
+        .await;
+        let _busy = diag::busy(TaskId::LogWriter);
+        let probe = match wake {
+            Either3::First(()) => false,
+            Either3::Second(()) => true,
+            Either3::Third(()) => Instant::now() >= next_check,
+        };
+        if !probe {
+            write_log_block().await;
+            continue;
+        }
+        check_card().await;
+        poll_s = if CARD_REMOVED.load(AtomicOrdering::Acquire) {
+            (poll_s * 2).min(CARD_RETRY_MAX_S)
+        } else {
+            CARD_POLL_S
+        };
+        next_check = Instant::now() + Duration::from_secs(poll_s);
+    }
+}
+
+/// Move the cache into the pending block for `log_writer_task`, waiting
+/// while the previous block is still being written. Returns false, leaving
+/// the cache in place, if the block is held for a removed card.
+async fn hand_off_log_cache(writer: &mut LogWriter) -> bool {
+    if writer.cache_len == 0 {
+        return true;
+    }
+    loop {
+        {
+            let mut block = LOG_BLOCK.lock().await;
+            let cache = &writer.cache[..writer.cache_len];
+            if block.fill(writer.current_date, writer.cache_last_timestamp, cache) {
+                writer.cache_len = 0;
+                break;
+            }
+        }
+        if CARD_REMOVED.load(AtomicOrdering::Acquire) {
+            return false;
+        }
+        LOG_BLOCK_READY.signal(());
+        LOG_BLOCK_WRITTEN.wait().await;
+    }
+    LOG_BLOCK_READY.signal(());
+    true
+}
+
+/// Write the pending block, if any. A block that fails on a card that still
+/// answers is retried with backoff and dropped after `LOG_BLOCK_ATTEMPTS`,
+/// so a broken card cannot stall the writer for long; if the card is gone
+/// the block is kept for reinsertion.
+async fn write_log_block() -> bool {
+    let pending = {
+        let block = LOG_BLOCK.lock().await;
+        (block.len > 0).then_some(block.date)
+    };
+    if let Some(date) = pending {
+        // A card mounted again may end in a torn block: cut it off before
+        // appending. Answered from memory once the log has been written.
+        log_tail_timestamp(date).await;
+    }
+    let result = {
+        let mut logger = lock_logger(SdPriority::Logger).await;
+        let mut block = LOG_BLOCK.lock().await;
+        if block.len == 0 {
+            return true;
+        }
+        let now_ms = Instant::now().as_millis();
+        if block.retry_at_ms().is_some_and(|at| now_ms < at) {
+            return false;
+        }
+        let (date, last_timestamp) = (block.date, block.last_timestamp);
+        let ok = logger.as_mut().is_some_and(|logger| {
+            logger.write_log_block(date, &block.data[..block.len], last_timestamp)
+        });
+        let removed = !ok && detach_if_removed(&mut logger);
+        let held = !ok && CARD_REMOVED.load(AtomicOrdering::Acquire);
+        let retry = !ok && !held && block.failures + 1 < LOG_BLOCK_ATTEMPTS;
+        if retry {
+            let backoff_ms = LOG_RETRY_BASE_MS << block.failures;
+            defmt::warn!("SD: log block write failed, retrying in {} ms", backoff_ms);
+            block.failures += 1;
+            block.retry_at_ms = Instant::now().as_millis() + backoff_ms;
+        } else if !held {
+            finish_pending_block(&mut block, ok);
+        }
+        (ok, removed, held || retry)
+    };
+    let (ok, removed, kept) = result;
+    if removed {
+        publish_card_missing(true).await;
+    }
+    if kept {
+        // Nothing lost yet; only points refused for lack of RAM count.
+        return false;
+    }
+    note_log_write(ok).await;
+    ok
+}
+
+fn finish_pending_block(block: &mut LogBlock<CACHE_SIZE>, ok: bool) {
+    let len = block.len;
+    if !ok {
+        // Later deltas would decode against the lost points: restart the
+        // encoder with a full block.
+        defmt::warn!("SD: log block write failed, {} bytes dropped", len);
+        DISCARD_LOG_CACHE.store(true, AtomicOrdering::Release);
+        BLOCKS_DROPPED.fetch_add(1, AtomicOrdering::Relaxed);
+    }
+    block.finish();
+    LOG_BLOCK_WRITTEN.signal(ok);
+}
+
+/// Whether the logger holds a mounted card. False without a card and in
+/// USB mode.
+pub fn card_mounted() -> bool {
+    LOGGER_READY.load(AtomicOrdering::Acquire)
+}
+
+/// Wait until `log_writer_task` mounts a card that was missing.
+pub async fn wait_card_mounted() {
+    CARD_MOUNTED.wait().await;
+}
+
+/// Allow configuration writes. Call once the card's configuration is
+/// loaded: at boot, or on the first mount of a card inserted later.
+pub fn note_config_read() {
+    CONFIG_READ.store(true, AtomicOrdering::Release);
+}
+
+pub fn config_read() -> bool {
+    CONFIG_READ.load(AtomicOrdering::Acquire)
+}
+
+/// Probe a mounted card, or try to mount a removed one.
+async fn check_card() {
+    if LOGGER_READY.load(AtomicOrdering::Acquire) {
+        let removed = {
+            let mut logger = lock_logger(SdPriority::Config).await;
+            detach_if_removed(&mut logger)
+        };
+        if removed {
+            publish_card_missing(true).await;
+        }
+        return;
+    }
+    if card_owner() != CardOwner::Logger {
+        return;
+    }
+    let Some(card) = REMOVED_CARD.lock(|card| card.borrow_mut().take()) else {
+        return;
+    };
+    match mount_card(card) {
+        Ok(logger) => {
+            *lock_logger(SdPriority::Config).await = Some(logger);
+            CARD_REMOVED.store(false, AtomicOrdering::Release);
+            LOGGER_READY.store(true, AtomicOrdering::Release);
+            publish_card_missing(false).await;
+            defmt::info!("SD: card mounted again");
+            // Flush the block held while the card was out.
+            LOG_BLOCK_READY.signal(());
+            CARD_MOUNTED.signal(());
+        }
+        Err(card) => park_removed_card(card),
+    }
+}
+
+/// Unmount the card if it no longer answers. Returns true if it was
+/// detached.
+fn detach_if_removed(logger: &mut Option<SdLogger>) -> bool {
+    if logger.as_ref().is_none_or(SdLogger::card_present) {
+        return false;
+    }
+    let Some(logger) = logger.take() else {
         return false;
     };
-    logger.flush_cache()
+    LOGGER_READY.store(false, AtomicOrdering::Release);
+    park_removed_card(logger.into_usb_card());
+    defmt::warn!("SD: card removed, buffering log in RAM");
+    true
+}
+
+fn park_removed_card(card: UsbSdCard) {
+    REMOVED_CARD.lock(|slot| {
+        *slot.borrow_mut() = Some(card);
+    });
+    CARD_REMOVED.store(true, AtomicOrdering::Release);
+}
+
+async fn publish_card_missing(missing: bool) {
+    SYSTEM_INFO.lock().await.sd_card_missing = missing;
+}
+
+/// Whether the last `LOG_DEGRADED_AFTER` log writes all failed.
+pub fn logging_degraded() -> bool {
+    LOG_DEGRADED.load(AtomicOrdering::Acquire)
+}
+
+/// Seconds since a log block last reached the card, `None` before the
+/// first since boot.
+pub fn last_log_write_age_s() -> Option<u32> {
+    let stored = LAST_LOG_WRITE_S.load(AtomicOrdering::Acquire);
+    let written = stored.checked_sub(1)?;
+    Some((Instant::now().as_secs() as u32).saturating_sub(written))
+}
+
+async fn note_log_write(ok: bool) {
+    let degraded = if ok {
+        let now_s = Instant::now().as_secs() as u32;
+        LAST_LOG_WRITE_S.store(now_s.saturating_add(1), AtomicOrdering::Release);
+        LOG_FAILURE_STREAK.store(0, AtomicOrdering::Release);
+        false
+    } else {
+        let streak = LOG_FAILURE_STREAK
+            .fetch_add(1, AtomicOrdering::AcqRel)
+            .saturating_add(1);
+        streak >= LOG_DEGRADED_AFTER || LOG_DEGRADED.load(AtomicOrdering::Acquire)
+    };
+    if LOG_DEGRADED.swap(degraded, AtomicOrdering::AcqRel) == degraded {
+        return;
+    }
+    if degraded {
+        defmt::warn!(
+            "SD: logging degraded after {} failed writes",
+            LOG_DEGRADED_AFTER
+        );
+    } else {
+        defmt::info!("SD: logging recovered");
+    }
+    SYSTEM_INFO.lock().await.logging_degraded = degraded;
+}
+
+/// Today's log was deleted: the next point starts a fresh file.
+fn discard_current_log() {
+    DISCARD_LOG_CACHE.store(true, AtomicOrdering::Release);
+    let date = CURRENT_LOG_DATE.load(AtomicOrdering::Acquire);
+    if let Ok(mut block) = LOG_BLOCK.try_lock() {
+        // A hand-off may be waiting for the block while holding
+        // `LOG_WRITER`; without the signal it never wakes.
+        if block.discard(date) {
+            LOG_BLOCK_WRITTEN.signal(true);
+        }
+    }
+}
+
+fn current_log_date_parts() -> Option<(u16, u8, u8)> {
+    date_parts(CURRENT_LOG_DATE.load(AtomicOrdering::Acquire))
 }
 
 pub async fn list_dir_next(path: &[u8]) -> ListDirOutcome {
-    let mut logger = SD_LOGGER.lock().await;
+    let mut logger = lock_logger(SdPriority::Transfer).await;
     let Some(logger) = logger.as_mut() else {
         return ListDirOutcome::Error;
     };
@@ -194,7 +1063,7 @@ pub async fn list_dir_next(path: &[u8]) -> ListDirOutcome {
 }
 
 pub async fn open_file(path: &[u8]) -> Option<u32> {
-    let mut logger = SD_LOGGER.lock().await;
+    let mut logger = lock_logger(SdPriority::Transfer).await;
     let Some(logger) = logger.as_mut() else {
         return None;
     };
@@ -202,7 +1071,7 @@ pub async fn open_file(path: &[u8]) -> Option<u32> {
 }
 
 pub async fn read_file(offset: u32, out: &mut [u8]) -> Result<usize, ()> {
-    let mut logger = SD_LOGGER.lock().await;
+    let mut logger = lock_logger(SdPriority::Transfer).await;
     let Some(logger) = logger.as_mut() else {
         return Err(());
     };
@@ -210,7 +1079,7 @@ pub async fn read_file(offset: u32, out: &mut [u8]) -> Result<usize, ()> {
 }
 
 pub async fn close_file() -> bool {
-    let mut logger = SD_LOGGER.lock().await;
+    let mut logger = lock_logger(SdPriority::Transfer).await;
     let Some(logger) = logger.as_mut() else {
         return false;
     };
@@ -218,147 +1087,894 @@ pub async fn close_file() -> bool {
 }
 
 pub async fn delete_file(path: &[u8]) -> bool {
-    let mut logger = SD_LOGGER.lock().await;
+    let mut logger = lock_logger(SdPriority::Transfer).await;
     let Some(logger) = logger.as_mut() else {
         return false;
     };
     logger.delete_transfer_file(path)
 }
 
-/// FindMy key material size: private_key(28) + symmetric_key(32) + epoch(8) = 68 bytes.
-pub const FINDMY_KEY_SIZE: usize = 68;
-
-/// FindMy SK cache size: sk(32) + counter(4) = 36 bytes.
-pub const FINDMY_SK_CACHE_SIZE: usize = 36;
-
-/// FMDN EIK size: 32 bytes.
-pub const FMDN_EIK_SIZE: usize = 32;
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum LogFileAction {
+    Delete,
+    /// Move into `/ARCHIVE/YYYY/MM/`.
+    Archive,
+}
 
-/// Read FindMy key material from SD card (`/FINDMY.KEY`).
-pub async fn read_findmy_keys() -> Option<[u8; FINDMY_KEY_SIZE]> {
-    let mut logger = SD_LOGGER.lock().await;
-    let Some(logger) = logger.as_mut() else {
-        return None;
-    };
-    logger.read_findmy_keys()
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum LogFileOutcome {
+    /// No log file for that day.
+    Missing,
+    Done,
+    /// Today's open log, or a transfer is in progress.
+    Skipped,
+    Failed,
 }
 
-/// Write FindMy key material to SD card (`/FINDMY.KEY`).
-pub async fn write_findmy_keys(data: &[u8; FINDMY_KEY_SIZE]) -> bool {
-    let mut logger = SD_LOGGER.lock().await;
-    let Some(logger) = logger.as_mut() else {
+/// Delete or archive the daily log `YYYY/MM/YYYYMMDD.gpz`.
+///
+/// embedded-sdmmc has no rename, so archiving copies the file into
+/// `/ARCHIVE/YYYY/MM/`, `TRANSFER_CHUNKS_PER_LOCK` chunks per SD lock, and
+/// then deletes the original. An existing archive copy is never
+/// overwritten; the original then stays in place.
+pub async fn apply_log_file_action(
+    action: LogFileAction,
+    year: u16,
+    month: u8,
+    day: u8,
+) -> LogFileOutcome {
+    if action == LogFileAction::Archive {
+        let ready = match lock_logger(SdPriority::Transfer).await.as_mut() {
+            Some(logger) => logger
+                .check_day_log(year, month, day)
+                .and_then(|()| {
+                    let made = logger.make_archive_dir(year, month);
+                    made.then_some(()).ok_or(LogFileOutcome::Failed)
+                }),
+            None => Err(LogFileOutcome::Failed),
+        };
+        if let Err(outcome) = ready {
+            return outcome;
+        }
+        let src_dir = month_path(year, month);
+        let mut dst_dir = [0u8; ARCHIVE_DIR.len() + 1 + 7];
+        dst_dir[..ARCHIVE_DIR.len()].copy_from_slice(ARCHIVE_DIR.as_bytes());
+        dst_dir[ARCHIVE_DIR.len()] = b'/';
+        dst_dir[ARCHIVE_DIR.len() + 1..].copy_from_slice(&src_dir);
+        let filename = build_bare_filename(year, month, day);
+        let name = filename.as_str();
+        let mode = Mode::ReadWriteCreate;
+        let mut copy = FileCopy::new(&src_dir, name, &dst_dir, name, u32::MAX, mode);
+        if !copy_file_chunked(SdPriority::Transfer, &mut copy).await {
+            return LogFileOutcome::Failed;
+        }
+    }
+    match lock_logger(SdPriority::Transfer).await.as_mut() {
+        Some(logger) => logger.delete_day_log(year, month, day),
+        None => LogFileOutcome::Failed,
+    }
+}
+
+/// Logs listed by `list_day_logs`; older ones are left out.
+pub const MAX_LISTED_LOGS: usize = 16;
+
+/// A daily log found on the card.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct DayLog {
+    /// `YYYYMMDD`, as in the file name.
+    pub date: u32,
+    pub size: u32,
+}
+
+impl DayLog {
+    pub fn year(&self) -> u16 {
+        (self.date / 10_000) as u16
+    }
+
+    pub fn month(&self) -> u8 {
+        (self.date / 100 % 100) as u8
+    }
+
+    pub fn day(&self) -> u8 {
+        (self.date % 100) as u8
+    }
+}
+
+/// The newest `MAX_LISTED_LOGS` daily logs, newest first. Empty without a
+/// mounted card. Each directory is read under its own SD lock.
+pub async fn list_day_logs() -> heapless::Vec<DayLog, MAX_LISTED_LOGS> {
+    let mut logs = heapless::Vec::new();
+    let mut years = match lock_logger(SdPriority::Transfer).await.as_mut() {
+        Some(logger) => logger.digit_dirs(b"", 4),
+        None => return logs,
+    };
+    years.sort_unstable_by(|a, b| b.base_name().cmp(a.base_name()));
+    for year in &years {
+        let mut months = match lock_logger(SdPriority::Transfer).await.as_mut() {
+            Some(logger) => logger.digit_dirs(year.base_name(), 2),
+            None => return logs,
+        };
+        months.sort_unstable_by(|a, b| b.base_name().cmp(a.base_name()));
+        for month in &months {
+            let dir = month_dir_path(year, month);
+            let days = match lock_logger(SdPriority::Transfer).await.as_mut() {
+                Some(logger) => logger.month_day_logs(&dir),
+                None => return logs,
+            };
+            for day in days {
+                if logs.push(day).is_err() {
+                    return logs;
+                }
+            }
+        }
+    }
+    logs
+}
+
+/// Delete one log returned by `list_day_logs`, with its day stats.
+pub async fn delete_day_log(log: DayLog) -> LogFileOutcome {
+    apply_log_file_action(LogFileAction::Delete, log.year(), log.month(), log.day()).await
+}
+
+/// FindMy key material size: private_key(28) + symmetric_key(32) + epoch(8) = 68 bytes.
+pub const FINDMY_KEY_SIZE: usize = 68;
+
+/// FindMy SK cache size: sk(32) + counter(4) = 36 bytes.
+pub const FINDMY_SK_CACHE_SIZE: usize = 36;
+
+/// FMDN EIK size: 32 bytes.
+pub const FMDN_EIK_SIZE: usize = 32;
+
+/// Read FindMy key material from SD card (`/FINDMY.KEY`).
+pub async fn read_findmy_keys() -> Option<[u8; FINDMY_KEY_SIZE]> {
+    let mut logger = lock_logger(SdPriority::Config).await;
+    let Some(logger) = logger.as_mut() else {
+        return None;
+    };
+    let mut buf = [0u8; FINDMY_KEY_SIZE];
+    logger.read_config_file("FINDMY.KEY", &mut buf, |d| d.len() == FINDMY_KEY_SIZE)?;
+    Some(buf)
+}
+
+/// Write FindMy key material to SD card (`/FINDMY.KEY`).
+pub async fn write_findmy_keys(data: &[u8; FINDMY_KEY_SIZE]) -> bool {
+    let mut logger = lock_logger(SdPriority::Config).await;
+    let Some(logger) = logger.as_mut() else {
         return false;
     };
-    logger.write_findmy_keys(data)
+    logger.write_config_file("FINDMY.KEY", data)
 }
 
 /// Delete FindMy SK cache from SD card (`/FINDMY.SKC`).
 pub async fn delete_findmy_sk_cache() -> bool {
-    let mut logger = SD_LOGGER.lock().await;
+    let mut logger = lock_logger(SdPriority::Config).await;
     let Some(logger) = logger.as_mut() else {
         return false;
     };
-    let _ = logger
-        .volume_mgr
-        .delete_file_in_dir(logger.root_dir, "FINDMY.SKC");
+    logger.delete_config_file("FINDMY.SKC");
     true
 }
 
 /// Read FindMy SK cache from SD card (`/FINDMY.SKC`).
 pub async fn read_findmy_sk_cache() -> Option<[u8; FINDMY_SK_CACHE_SIZE]> {
-    let mut logger = SD_LOGGER.lock().await;
+    let mut logger = lock_logger(SdPriority::Config).await;
     let Some(logger) = logger.as_mut() else {
         return None;
     };
-    logger.read_findmy_sk_cache()
+    let mut buf = [0u8; FINDMY_SK_CACHE_SIZE];
+    logger.read_config_file("FINDMY.SKC", &mut buf, |d| d.len() == FINDMY_SK_CACHE_SIZE)?;
+    Some(buf)
 }
 
 /// Write FindMy SK cache to SD card (`/FINDMY.SKC`).
 pub async fn write_findmy_sk_cache(data: &[u8; FINDMY_SK_CACHE_SIZE]) -> bool {
-    let mut logger = SD_LOGGER.lock().await;
+    let mut logger = lock_logger(SdPriority::Config).await;
     let Some(logger) = logger.as_mut() else {
         return false;
     };
-    logger.write_findmy_sk_cache(data)
+    logger.write_config_file("FINDMY.SKC", data)
 }
 
 /// Read FMDN EIK from SD card (`/FMDN.EIK`).
 pub async fn read_fmdn_eik() -> Option<[u8; FMDN_EIK_SIZE]> {
-    let mut logger = SD_LOGGER.lock().await;
+    let mut logger = lock_logger(SdPriority::Config).await;
     let Some(logger) = logger.as_mut() else {
         return None;
     };
-    logger.read_fmdn_eik()
+    let mut buf = [0u8; FMDN_EIK_SIZE];
+    logger.read_config_file("FMDN.EIK", &mut buf, |d| d.len() == FMDN_EIK_SIZE)?;
+    Some(buf)
 }
 
 /// Write FMDN EIK to SD card (`/FMDN.EIK`).
 pub async fn write_fmdn_eik(data: &[u8; FMDN_EIK_SIZE]) -> bool {
-    let mut logger = SD_LOGGER.lock().await;
+    let mut logger = lock_logger(SdPriority::Config).await;
     let Some(logger) = logger.as_mut() else {
         return false;
     };
-    logger.write_fmdn_eik(data)
+    logger.write_config_file("FMDN.EIK", data)
 }
 
-fn create_logger(
-    mut spi: Spim<'static>,
-    mut cs: Output<'static>,
-    config: spim::Config,
-    init_frequency: spim::Frequency,
-    run_frequency: spim::Frequency,
-) -> Option<SdLogger> {
-    cs.set_high();
-    let idle = [0xFFu8; 10];
-    SpiBus::write(&mut spi, &idle).ok()?;
-    let _ = SpiBus::flush(&mut spi);
-
-    let sd_spi = SdSpiDevice::new(spi, cs, config);
-    let delay = Delay;
-    let sd_card = SdCard::new(sd_spi, delay);
-    let volume_mgr = SdVolumeManager::new_with_limits(sd_card, GpsTimeSource, 0);
-    let volume = volume_mgr.open_raw_volume(VolumeIdx(0)).ok()?;
-    let root_dir = volume_mgr.open_root_dir(volume).ok()?;
+/// Read the waypoint database from SD card (`/WAYPTS.DB`).
+/// Returns the number of bytes read.
+#[cfg(feature = "nav")]
+pub async fn read_waypoint_db(out: &mut [u8]) -> Option<usize> {
+    let mut logger = lock_logger(SdPriority::Config).await;
+    let Some(logger) = logger.as_mut() else {
+        return None;
+    };
+    let full = out.len();
+    logger.read_config_file("WAYPTS.DB", out, |d| d.len() == full)
+}
 
-    let _ = volume_mgr.device(|sd| {
-        sd.spi(|spi| {
-            spi.set_frequency(run_frequency);
-        });
-        GpsTimeSource
-    });
+/// Write the waypoint database to SD card (`/WAYPTS.DB`).
+#[cfg(feature = "nav")]
+pub async fn write_waypoint_db(data: &[u8]) -> bool {
+    if !config_read() {
+        return false;
+    }
+    let mut logger = lock_logger(SdPriority::Config).await;
+    let Some(logger) = logger.as_mut() else {
+        return false;
+    };
+    logger.write_config_file("WAYPTS.DB", data)
+}
 
-    Some(SdLogger::new(
-        volume_mgr,
-        volume,
-        root_dir,
-        init_frequency,
-        run_frequency,
-    ))
+/// Read the geofence database from SD card (`/FENCES.DB`).
+/// Returns the number of bytes read.
+pub async fn read_fence_db(out: &mut [u8]) -> Option<usize> {
+    let mut logger = lock_logger(SdPriority::Config).await;
+    let Some(logger) = logger.as_mut() else {
+        return None;
+    };
+    let full = out.len();
+    logger.read_config_file("FENCES.DB", out, |d| d.len() == full)
+}
+
+/// Write the geofence database to SD card (`/FENCES.DB`).
+pub async fn write_fence_db(data: &[u8]) -> bool {
+    if !config_read() {
+        return false;
+    }
+    let mut logger = lock_logger(SdPriority::Config).await;
+    let Some(logger) = logger.as_mut() else {
+        return false;
+    };
+    logger.write_config_file("FENCES.DB", data)
+}
+
+/// Read the session table from SD card (`/SESSIONS.DB`).
+/// Returns the number of bytes read.
+pub async fn read_session_db(out: &mut [u8]) -> Option<usize> {
+    let mut logger = lock_logger(SdPriority::Config).await;
+    let Some(logger) = logger.as_mut() else {
+        return None;
+    };
+    let full = out.len();
+    logger.read_config_file("SESSIONS.DB", out, |d| d.len() == full)
+}
+
+/// Write the session table to SD card (`/SESSIONS.DB`).
+pub async fn write_session_db(data: &[u8]) -> bool {
+    if !config_read() {
+        return false;
+    }
+    let mut logger = lock_logger(SdPriority::Config).await;
+    let Some(logger) = logger.as_mut() else {
+        return false;
+    };
+    logger.write_config_file("SESSIONS.DB", data)
+}
+
+/// Read the session metadata sidecar from SD card (`/SESSMETA.DB`).
+/// Returns the number of bytes read.
+pub async fn read_session_meta_db(out: &mut [u8]) -> Option<usize> {
+    let mut logger = lock_logger(SdPriority::Config).await;
+    let Some(logger) = logger.as_mut() else {
+        return None;
+    };
+    let full = out.len();
+    logger.read_config_file("SESSMETA.DB", out, |d| d.len() == full)
+}
+
+/// Write the session metadata sidecar to SD card (`/SESSMETA.DB`).
+pub async fn write_session_meta_db(data: &[u8]) -> bool {
+    let mut logger = lock_logger(SdPriority::Config).await;
+    let Some(logger) = logger.as_mut() else {
+        return false;
+    };
+    logger.write_config_file("SESSMETA.DB", data)
+}
+
+/// Append one marker record to `/MARKERS.BIN`.
+pub async fn append_marker(record: &[u8]) -> bool {
+    let mut logger = lock_logger(SdPriority::Config).await;
+    let Some(logger) = logger.as_mut() else {
+        return false;
+    };
+    logger.append_root_file("MARKERS.BIN", record)
+}
+
+/// Append one `unix,latitude,longitude,altitude_m` line for a waypoint
+/// dropped with the button to `YYYY/MM/YYYYMMDD.wpt`, next to the log of
+/// `date` (YYYYMMDD).
+pub async fn append_waypoint_log(
+    date: u32,
+    unix: u32,
+    latitude: f64,
+    longitude: f64,
+    altitude: f32,
+) -> bool {
+    let mut line = heapless::String::<64>::new();
+    if core::fmt::write(
+        &mut line,
+        format_args!(
+            "{},{:.7},{:.7},{:.1}\n",
+            unix, latitude, longitude, altitude
+        ),
+    )
+    .is_err()
+    {
+        return false;
+    }
+    let mut logger = lock_logger(SdPriority::Config).await;
+    let Some(logger) = logger.as_mut() else {
+        return false;
+    };
+    logger.append_day_file(date, DAY_WAYPOINTS_EXTENSION, line.as_bytes())
+}
+
+/// Append raw vibration capture records to `/VIBRATE.BIN`.
+pub async fn append_vibration_log(data: &[u8]) -> bool {
+    let mut logger = lock_logger(SdPriority::Logger).await;
+    let Some(logger) = logger.as_mut() else {
+        return false;
+    };
+    logger.append_root_file("VIBRATE.BIN", data)
+}
+
+/// Append one `hour_start_unix,steps` line to `/STEPS.CSV`.
+pub async fn append_step_log(hour_start: u32, steps: u32) -> bool {
+    let mut line = heapless::String::<24>::new();
+    if core::fmt::write(&mut line, format_args!("{},{}\n", hour_start, steps)).is_err() {
+        return false;
+    }
+    let mut logger = lock_logger(SdPriority::Logger).await;
+    let Some(logger) = logger.as_mut() else {
+        return false;
+    };
+    logger.append_root_file("STEPS.CSV", line.as_bytes())
+}
+
+/// Append one `YYYYMMDD,steps` day total to `/STEPDAYS.CSV`.
+pub async fn append_step_day(date: u32, steps: u32) -> bool {
+    let mut line = heapless::String::<24>::new();
+    if core::fmt::write(&mut line, format_args!("{},{}\n", date, steps)).is_err() {
+        return false;
+    }
+    let mut logger = lock_logger(SdPriority::Logger).await;
+    let Some(logger) = logger.as_mut() else {
+        return false;
+    };
+    logger.append_root_file("STEPDAYS.CSV", line.as_bytes())
+}
+
+/// Append one `nmea_unix,casic_unix,chosen` line to `/TIMECHK.CSV` when the
+/// GPS time sources start to disagree; `chosen` is `nmea`, `casic` or `none`.
+pub async fn append_time_event(nmea_unix: u32, casic_unix: u32, chosen: &str) -> bool {
+    let mut line = heapless::String::<32>::new();
+    if core::fmt::write(&mut line, format_args!("{},{},{}\n", nmea_unix, casic_unix, chosen))
+        .is_err()
+    {
+        return false;
+    }
+    let mut logger = lock_logger(SdPriority::Logger).await;
+    let Some(logger) = logger.as_mut() else {
+        return false;
+    };
+    logger.append_root_file("TIMECHK.CSV", line.as_bytes())
+}
+
+/// Append one `unix,step,backoff_s` line to `/GPSESC.CSV` when repeated fix
+/// failures escalate; `step` is `warm`, `cold`, `reset` or `backoff`.
+pub async fn append_escalation_log(unix: u32, step: &str, backoff_s: u32) -> bool {
+    let mut line = heapless::String::<32>::new();
+    if core::fmt::write(&mut line, format_args!("{},{},{}\n", unix, step, backoff_s)).is_err() {
+        return false;
+    }
+    let mut logger = lock_logger(SdPriority::Logger).await;
+    let Some(logger) = logger.as_mut() else {
+        return false;
+    };
+    logger.append_root_file("GPSESC.CSV", line.as_bytes())
+}
+
+/// Append one `uptime_s,delta_s,event` line to `/FMCLOCK.CSV` when the Find
+/// My key clock sees a time jump; `event` is `jump` or `reanchor`.
+#[cfg(feature = "findmy")]
+pub async fn append_key_clock_event(uptime_s: u32, delta_s: i64, event: &str) -> bool {
+    let mut line = heapless::String::<40>::new();
+    if core::fmt::write(
+        &mut line,
+        format_args!("{},{},{}\n", uptime_s, delta_s, event),
+    )
+    .is_err()
+    {
+        return false;
+    }
+    let mut logger = lock_logger(SdPriority::Logger).await;
+    let Some(logger) = logger.as_mut() else {
+        return false;
+    };
+    logger.append_root_file("FMCLOCK.CSV", line.as_bytes())
+}
+
+/// Append one `unix,outcome,duration_s,agnss` line to `/FIXLOG.CSV` for a
+/// GPS power cycle; `outcome` is `fix` or `nofix`, `unix` 0 when unknown.
+pub async fn append_fix_log(unix: u32, attempt: &FixAttempt) -> bool {
+    let mut line = heapless::String::<40>::new();
+    let outcome = if attempt.fixed { "fix" } else { "nofix" };
+    if core::fmt::write(
+        &mut line,
+        format_args!(
+            "{},{},{},{}\n",
+            unix, outcome, attempt.duration_s, attempt.agnss as u8
+        ),
+    )
+    .is_err()
+    {
+        return false;
+    }
+    let mut logger = lock_logger(SdPriority::Logger).await;
+    let Some(logger) = logger.as_mut() else {
+        return false;
+    };
+    logger.append_root_file("FIXLOG.CSV", line.as_bytes())
+}
+
+/// Read the per-day fix statistics saved by `write_fix_stats`
+/// (`/FIXSTATS.CFG`).
+pub async fn read_fix_stats() -> Option<[u8; FIX_STATS_LEN]> {
+    let mut buf = [0u8; FIX_STATS_LEN];
+    let mut logger = lock_logger(SdPriority::Config).await;
+    let logger = logger.as_mut()?;
+    logger.read_config_file("FIXSTATS.CFG", &mut buf, |d| d.len() == FIX_STATS_LEN)?;
+    Some(buf)
+}
+
+/// Save the per-day fix statistics (`fix_stats::FixStats::to_bytes`).
+pub async fn write_fix_stats(data: &[u8; FIX_STATS_LEN]) -> bool {
+    let mut logger = lock_logger(SdPriority::Config).await;
+    let Some(logger) = logger.as_mut() else {
+        return false;
+    };
+    logger.write_config_file("FIXSTATS.CFG", data)
+}
+
+/// Read the running day total saved by `write_step_checkpoint`
+/// (`/STEPDAY.CFG`) as `(date, steps)`.
+pub async fn read_step_checkpoint() -> Option<(u32, u32)> {
+    let mut buf = [0u8; 8];
+    let mut logger = lock_logger(SdPriority::Config).await;
+    let logger = logger.as_mut()?;
+    logger.read_config_file("STEPDAY.CFG", &mut buf, |d| d.len() == 8)?;
+    let date = u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]);
+    let steps = u32::from_le_bytes([buf[4], buf[5], buf[6], buf[7]]);
+    Some((date, steps))
+}
+
+/// Save the running day total `[date: u32 LE][steps: u32 LE]`, so a reboot
+/// does not lose it.
+pub async fn write_step_checkpoint(date: u32, steps: u32) -> bool {
+    let mut buf = [0u8; 8];
+    buf[..4].copy_from_slice(&date.to_le_bytes());
+    buf[4..].copy_from_slice(&steps.to_le_bytes());
+    let mut logger = lock_logger(SdPriority::Config).await;
+    let Some(logger) = logger.as_mut() else {
+        return false;
+    };
+    logger.write_config_file("STEPDAY.CFG", &buf)
+}
+
+/// Read the `track_stats` checkpoint kept next to the log of `date`
+/// (YYYYMMDD), `YYYY/MM/YYYYMMDD.sts`.
+pub async fn read_day_stats(date: u32) -> Option<[u8; TODAY_STATS_LEN]> {
+    let mut buf = [0u8; TODAY_STATS_LEN];
+    let mut logger = lock_logger(SdPriority::Config).await;
+    let logger = logger.as_mut()?;
+    logger.read_day_stats(date, &mut buf)?;
+    Some(buf)
+}
+
+/// Replace the `track_stats` checkpoint of `date`, power-fail safe like the
+/// config files.
+pub async fn write_day_stats(date: u32, data: &[u8; TODAY_STATS_LEN]) -> bool {
+    let mut logger = lock_logger(SdPriority::Config).await;
+    let Some(logger) = logger.as_mut() else {
+        return false;
+    };
+    logger.write_day_stats(date, data)
+}
+
+/// Append one `uptime_s,gps_state,duration_s,gps_ua,sys_ua,sys_mv` line to
+/// `/POWER.CSV`.
+#[cfg(feature = "power-monitor")]
+pub async fn append_power_log(
+    uptime_s: u32,
+    gps_state: u8,
+    duration_s: u32,
+    gps_ua: i32,
+    sys_ua: i32,
+    sys_mv: i32,
+) -> bool {
+    let mut line = heapless::String::<64>::new();
+    if core::fmt::write(
+        &mut line,
+        format_args!(
+            "{},{},{},{},{},{}\n",
+            uptime_s, gps_state, duration_s, gps_ua, sys_ua, sys_mv
+        ),
+    )
+    .is_err()
+    {
+        return false;
+    }
+    let mut logger = lock_logger(SdPriority::Logger).await;
+    let Some(logger) = logger.as_mut() else {
+        return false;
+    };
+    logger.append_root_file("POWER.CSV", line.as_bytes())
+}
+
+/// Append one `uptime_s,unix,battery_mv,percent,charge_state,load_ma,tte_min,
+/// raw_adc` line to `/BATT.CSV`; `unix` 0 when unknown, `tte_min` -1 while
+/// charging, `raw_adc` the SAADC count of the last unfiltered sample.
+pub async fn append_battery_log(
+    uptime_s: u32,
+    unix: u32,
+    raw_adc: u16,
+    sample: &GaugeSample,
+    reading: &GaugeReading,
+) -> bool {
+    let tte_min = reading.time_to_empty_min.map_or(-1, i32::from);
+    let mut line = heapless::String::<64>::new();
+    if core::fmt::write(
+        &mut line,
+        format_args!(
+            "{},{},{},{},{},{:.1},{},{}\n",
+            uptime_s,
+            unix,
+            sample.voltage_mv as u32,
+            reading.percent,
+            reading.state as u8,
+            sample.load_ma,
+            tte_min,
+            raw_adc
+        ),
+    )
+    .is_err()
+    {
+        return false;
+    }
+    let mut logger = lock_logger(SdPriority::Logger).await;
+    let Some(logger) = logger.as_mut() else {
+        return false;
+    };
+    logger.append_root_file("BATT.CSV", line.as_bytes())
 }
 
-fn rebuild_logger(usb_card: UsbSdCard) -> Option<SdLogger> {
-    usb_card.card.spi(|spi| {
-        spi.set_frequency(usb_card.init_frequency);
+/// Append one `uptime_s,unix,pressure_pa,temperature_c,change_3h_hpa,tendency`
+/// line to `/PRESSURE.CSV`; `unix` 0 when unknown, the change empty and the
+/// tendency `unknown` with less than an hour of history.
+pub async fn append_pressure_log(
+    uptime_s: u32,
+    unix: u32,
+    data: &Bmp280Data,
+    change_hpa: Option<f32>,
+) -> bool {
+    let (pressure_pa, temperature_c) = (data.pressure_pa, data.temperature_c);
+    let mut line = heapless::String::<64>::new();
+    let written = match change_hpa {
+        Some(change_hpa) => {
+            let tendency = Tendency::from_change(change_hpa).name();
+            core::fmt::write(
+                &mut line,
+                format_args!(
+                    "{},{},{:.0},{:.1},{:.1},{}\n",
+                    uptime_s, unix, pressure_pa, temperature_c, change_hpa, tendency
+                ),
+            )
+        }
+        None => core::fmt::write(
+            &mut line,
+            format_args!(
+                "{},{},{:.0},{:.1},,unknown\n",
+                uptime_s, unix, pressure_pa, temperature_c
+            ),
+        ),
+    };
+    if written.is_err() {
+        return false;
+    }
+    let mut logger = lock_logger(SdPriority::Logger).await;
+    let Some(logger) = logger.as_mut() else {
+        return false;
+    };
+    logger.append_root_file("PRESSURE.CSV", line.as_bytes())
+}
+
+/// Append one `reset_reason,kind,pc,lr,message` line to `/CRASH.LOG`:
+/// `RESETREAS` and addresses in hex, `kind` `none`, `panic` or `hardfault`.
+pub async fn append_crash_log(report: &BootReport) -> bool {
+    let kind = match report.kind {
+        CrashKind::None => "none",
+        CrashKind::Panic => "panic",
+        CrashKind::HardFault => "hardfault",
+    };
+    let mut line = heapless::Vec::<u8, { 48 + 4 * crash::MESSAGE_LEN + 1 }>::new();
+    let mut head = heapless::String::<48>::new();
+    let (reason, pc, lr) = (report.reset_reason, report.pc, report.lr);
+    let formatted = core::fmt::write(
+        &mut head,
+        format_args!("0x{:08x},{},0x{:08x},0x{:08x},", reason, kind, pc, lr),
+    );
+    if formatted.is_err() || line.extend_from_slice(head.as_bytes()).is_err() {
+        return false;
+    }
+    // The message ends the line, so a comma or line break in it would
+    // break the record; those, other control bytes and `\` go out as `\xNN`.
+    for &byte in report.message() {
+        let pushed = match byte {
+            0x00..=0x1F | 0x7F | b',' | b'\\' => {
+                let mut escape = heapless::String::<4>::new();
+                core::fmt::write(&mut escape, format_args!("\\x{:02x}", byte)).is_ok()
+                    && line.extend_from_slice(escape.as_bytes()).is_ok()
+            }
+            _ => line.push(byte).is_ok(),
+        };
+        if !pushed {
+            return false;
+        }
+    }
+    if line.push(b'\n').is_err() {
+        return false;
+    }
+    let mut logger = lock_logger(SdPriority::Config).await;
+    let Some(logger) = logger.as_mut() else {
+        return false;
+    };
+    logger.append_root_file("CRASH.LOG", &line)
+}
+
+/// Append one `uptime_s,source` line to `/CHARGE.CSV`, `source` being a
+/// `usb_power::UsbSource` value.
+pub async fn append_charge_log(uptime_s: u32, source: u8) -> bool {
+    let mut line = heapless::String::<24>::new();
+    if core::fmt::write(&mut line, format_args!("{},{}\n", uptime_s, source)).is_err() {
+        return false;
+    }
+    let mut logger = lock_logger(SdPriority::Logger).await;
+    let Some(logger) = logger.as_mut() else {
+        return false;
+    };
+    logger.append_root_file("CHARGE.CSV", line.as_bytes())
+}
+
+/// Read timezone settings from SD card (`/TZ.CFG`).
+pub async fn read_tz_settings() -> Option<TzSettings> {
+    let mut logger = lock_logger(SdPriority::Config).await;
+    let Some(logger) = logger.as_mut() else {
+        return None;
+    };
+    let mut buf = [0u8; TZ_SETTINGS_LEN];
+    logger.read_config_file("TZ.CFG", &mut buf, |d| {
+        d.try_into().ok().and_then(TzSettings::from_bytes).is_some()
+    })?;
+    TzSettings::from_bytes(&buf)
+}
+
+/// Write timezone settings to SD card (`/TZ.CFG`).
+pub async fn write_tz_settings(settings: &TzSettings) -> bool {
+    if !config_read() {
+        return false;
+    }
+    let mut logger = lock_logger(SdPriority::Config).await;
+    let Some(logger) = logger.as_mut() else {
+        return false;
+    };
+    logger.write_config_file("TZ.CFG", &settings.to_bytes())
+}
+
+/// Read the recording flags byte from SD card (`/REC.CFG`).
+pub async fn read_recording_config() -> Option<u8> {
+    let mut logger = lock_logger(SdPriority::Config).await;
+    let Some(logger) = logger.as_mut() else {
+        return None;
+    };
+    let mut buf = [0u8; RECORDING_CONFIG_LEN];
+    logger.read_config_file("REC.CFG", &mut buf, |d| d.len() == RECORDING_CONFIG_LEN)?;
+    Some(buf[0])
+}
+
+/// Write the recording flags to SD card (`/REC.CFG`).
+pub async fn write_recording_config(data: &[u8; RECORDING_CONFIG_LEN]) -> bool {
+    let mut logger = lock_logger(SdPriority::Config).await;
+    let Some(logger) = logger.as_mut() else {
+        return false;
+    };
+    logger.write_config_file("REC.CFG", data)
+}
+
+/// Read the user boot logo from SD card (`/SPLASH.PBM`), as much as fits.
+pub async fn read_splash(out: &mut [u8]) -> Option<usize> {
+    let mut logger = lock_logger(SdPriority::Config).await;
+    let Some(logger) = logger.as_mut() else {
+        return None;
+    };
+    logger.read_root_file("SPLASH.PBM", out)
+}
+
+/// Read the settings registry records from SD card (`/SETTINGS.CFG`).
+pub async fn read_settings_file(out: &mut [u8; SETTINGS_FILE_MAX_LEN]) -> Option<usize> {
+    let mut logger = lock_logger(SdPriority::Config).await;
+    let Some(logger) = logger.as_mut() else {
+        return None;
+    };
+    logger.read_config_file("SETTINGS.CFG", out, |d| d.len() % 6 == 0)
+}
+
+/// Write the settings registry records to SD card (`/SETTINGS.CFG`).
+pub async fn write_settings_file(data: &[u8]) -> bool {
+    if !config_read() {
+        return false;
+    }
+    let mut logger = lock_logger(SdPriority::Config).await;
+    let Some(logger) = logger.as_mut() else {
+        return false;
+    };
+    logger.write_config_file("SETTINGS.CFG", data)
+}
+
+/// Read the connection lockdown flags byte from SD card (`/LOCK.CFG`).
+pub async fn read_lockdown_config() -> Option<u8> {
+    let mut logger = lock_logger(SdPriority::Config).await;
+    let Some(logger) = logger.as_mut() else {
+        return None;
+    };
+    let mut buf = [0u8; LOCKDOWN_CONFIG_LEN];
+    logger.read_config_file("LOCK.CFG", &mut buf, |d| d.len() == LOCKDOWN_CONFIG_LEN)?;
+    Some(buf[0])
+}
+
+/// Write the connection lockdown flags to SD card (`/LOCK.CFG`).
+pub async fn write_lockdown_config(data: &[u8; LOCKDOWN_CONFIG_LEN]) -> bool {
+    if !config_read() {
+        return false;
+    }
+    let mut logger = lock_logger(SdPriority::Config).await;
+    let Some(logger) = logger.as_mut() else {
+        return false;
+    };
+    logger.write_config_file("LOCK.CFG", data)
+}
+
+/// Read the bonded BLE peers from SD card (`/BONDS.DB`). Returns the number
+/// of bytes read.
+pub async fn read_bond_db(out: &mut [u8; BONDS_FILE_MAX_LEN]) -> Option<usize> {
+    let mut logger = lock_logger(SdPriority::Config).await;
+    let Some(logger) = logger.as_mut() else {
+        return None;
+    };
+    logger.read_config_file("BONDS.DB", out, |d| d.len().is_multiple_of(BOND_RECORD_LEN))
+}
+
+/// Write the bonded BLE peers to SD card (`/BONDS.DB`).
+pub async fn write_bond_db(data: &[u8]) -> bool {
+    if !config_read() {
+        return false;
+    }
+    let mut logger = lock_logger(SdPriority::Config).await;
+    let Some(logger) = logger.as_mut() else {
+        return false;
+    };
+    logger.write_config_file("BONDS.DB", data)
+}
+
+/// Read the LoRaWAN uplink config from SD card (`/LORA.CFG`).
+#[cfg(feature = "lora")]
+pub async fn read_lora_config() -> Option<[u8; LORA_CONFIG_LEN]> {
+    let mut logger = lock_logger(SdPriority::Config).await;
+    let Some(logger) = logger.as_mut() else {
+        return None;
+    };
+    let mut buf = [0u8; LORA_CONFIG_LEN];
+    logger.read_config_file("LORA.CFG", &mut buf, |d| d.len() == LORA_CONFIG_LEN)?;
+    Some(buf)
+}
+
+/// Write the LoRaWAN uplink config to SD card (`/LORA.CFG`).
+#[cfg(feature = "lora")]
+pub async fn write_lora_config(data: &[u8; LORA_CONFIG_LEN]) -> bool {
+    let mut logger = lock_logger(SdPriority::Config).await;
+    let Some(logger) = logger.as_mut() else {
+        return false;
+    };
+    logger.write_config_file("LORA.CFG", data)
+}
+
+/// Read the next LoRaWAN uplink frame counter (`/LORA.CNT`).
+#[cfg(feature = "lora")]
+pub async fn read_lora_fcnt() -> Option<u32> {
+    let mut logger = lock_logger(SdPriority::Config).await;
+    let Some(logger) = logger.as_mut() else {
+        return None;
+    };
+    let mut buf = [0u8; 4];
+    logger.read_config_file("LORA.CNT", &mut buf, |d| d.len() == 4)?;
+    Some(u32::from_le_bytes(buf))
+}
+
+/// Persist the next LoRaWAN uplink frame counter (`/LORA.CNT`).
+#[cfg(feature = "lora")]
+pub async fn write_lora_fcnt(fcnt: u32) -> bool {
+    let mut logger = lock_logger(SdPriority::Config).await;
+    let Some(logger) = logger.as_mut() else {
+        return false;
+    };
+    logger.write_config_file("LORA.CNT", &fcnt.to_le_bytes())
+}
+
+fn create_card(
+    bus: &'static SharedSpiBus,
+    cs: Output<'static>,
+    config: spim::Config,
+) -> Option<SdCard<SdSpiDevice, Delay>> {
+    let mut sd_spi = SdSpiDevice::new(bus, cs, config);
+    sd_spi.send_idle_clocks().ok()?;
+    Some(SdCard::new(sd_spi, Delay))
+}
+
+/// Initialize the card and open its file system. Hands the card back if
+/// that fails, so the caller can retry later.
+fn mount_card(usb_card: UsbSdCard) -> Result<SdLogger, UsbSdCard> {
+    let UsbSdCard {
+        card,
+        init_frequency,
+        run_frequency,
+    } = usb_card;
+    card.spi(|spi| {
+        spi.set_frequency(init_frequency);
         let _ = spi.send_idle_clocks();
     });
-    usb_card.card.mark_card_uninit();
-
-    let volume_mgr = SdVolumeManager::new_with_limits(usb_card.card, GpsTimeSource, 0);
-    let volume = volume_mgr.open_raw_volume(VolumeIdx(0)).ok()?;
-    let root_dir = volume_mgr.open_root_dir(volume).ok()?;
+    card.mark_card_uninit();
+
+    let volume_mgr = SdVolumeManager::new_with_limits(card, GpsTimeSource, 0);
+    let opened = volume_mgr.open_raw_volume(VolumeIdx(0)).and_then(|volume| {
+        volume_mgr
+            .open_root_dir(volume)
+            .map(|root_dir| (volume, root_dir))
+            .inspect_err(|_| {
+                let _ = volume_mgr.close_volume(volume);
+            })
+    });
+    let Ok((volume, root_dir)) = opened else {
+        let (card, _time) = volume_mgr.free();
+        return Err(UsbSdCard::new(card, init_frequency, run_frequency));
+    };
 
     let _ = volume_mgr.device(|sd| {
         sd.spi(|spi| {
-            spi.set_frequency(usb_card.run_frequency);
+            spi.set_frequency(run_frequency);
         });
         GpsTimeSource
     });
 
-    Some(SdLogger::new(
+    Ok(SdLogger::new(
         volume_mgr,
         volume,
         root_dir,
-        usb_card.init_frequency,
-        usb_card.run_frequency,
+        init_frequency,
+        run_frequency,
     ))
 }
 
@@ -382,20 +1998,177 @@ impl TransferState {
     }
 }
 
-struct SdLogger {
-    // Max open: 4 files, 6 dirs (root_dir + listing_dir + ensure_log_directory peak of 2 temp dirs + margin), 1 volume
-    volume_mgr: SdVolumeManager,
-    volume: RawVolume,
-    root_dir: RawDirectory,
-    current_file: Option<RawFile>,
+struct LogWriter {
     current_date: u32,
     encoder: GpsDataEncoder,
     cache: [u8; CACHE_SIZE],
     cache_len: usize,
-    cache_dirty: bool,
+    /// Timestamp of the last point in `cache`.
+    cache_last_timestamp: u32,
     last_timestamp: u32,
     last_nrf_timestamp: u32,
+    /// Last point already in the current day's log when logging resumed on
+    /// it; points up to it are dropped. 0 once a later point is logged.
+    resume_after: u32,
+    tz_cache: TzCache,
+}
+
+impl LogWriter {
+    const fn new() -> Self {
+        Self {
+            current_date: 0,
+            encoder: GpsDataEncoder::new(FULL_BLOCK_INTERVAL),
+            cache: [0; CACHE_SIZE],
+            cache_len: 0,
+            cache_last_timestamp: 0,
+            last_timestamp: 0,
+            last_nrf_timestamp: 0,
+            resume_after: 0,
+            tz_cache: TzCache::new(),
+        }
+    }
+
+    fn accept_timestamp(&mut self, timestamp: u32) -> bool {
+        if timestamp == 0 {
+            defmt::warn!("GPS log skipped: timestamp is zero");
+            return false;
+        }
+
+        let now_sec = (Instant::now().as_millis() / 1000) as u32;
+        if self.last_timestamp != 0 && self.last_nrf_timestamp != 0 {
+            let gps_diff = timestamp as i64 - self.last_timestamp as i64;
+            let nrf_diff = now_sec as i64 - self.last_nrf_timestamp as i64;
+            if nrf_diff >= 0 && (gps_diff - nrf_diff).abs() > 3600 {
+                defmt::warn!("GPS log skipped: timestamp jump detected");
+                return false;
+            }
+        }
+        self.last_timestamp = timestamp;
+        self.last_nrf_timestamp = now_sec;
+        true
+    }
+
+    /// Day (YYYYMMDD) whose log the point belongs to.
+    fn log_date_for(&mut self, timestamp: u32, latitude: f64, longitude: f64) -> Option<u32> {
+        log_day(timestamp, latitude, longitude, &mut self.tz_cache)
+    }
+}
+
+/// Day (YYYYMMDD) of `timestamp` under the log rotation rule: UTC, or local
+/// midnight at the given position with `local_midnight_rotation`.
+pub fn log_day(
+    timestamp: u32,
+    latitude: f64,
+    longitude: f64,
+    tz_cache: &mut TzCache,
+) -> Option<u32> {
+    // Point timestamps stay UTC; only the day boundary moves.
+    let day_timestamp = if timezone::settings().local_midnight_rotation {
+        let offset = tz_cache.get_offset_at(latitude as f32, longitude as f32, timestamp);
+        timestamp.saturating_add_signed(offset.total_minutes as i32 * 60)
+    } else {
+        timestamp
+    };
+    let (year, month, day) = unix_to_date(day_timestamp)?;
+    Some((year as u32) * 10000 + (month as u32) * 100 + (day as u32))
+}
+
+/// A decode of a day's log spread over several SD locks.
+struct LogScan {
+    prefix: ValidPrefix,
+    offset: u32,
+    size: u32,
+}
+
+impl LogScan {
+    const fn new() -> Self {
+        Self {
+            prefix: ValidPrefix::new(),
+            offset: 0,
+            size: 0,
+        }
+    }
+}
+
+/// A GPX export of one day's log spread over several SD locks.
+struct GpxExport {
+    /// `YYYY/MM`.
+    dir: [u8; 7],
+    src: heapless::String<12>,
+    dst: heapless::String<12>,
+    decoder: GpzDecoder,
+    /// Label of the open `<trk>`, `None` before the first point.
+    activity: Option<u8>,
+    /// Whether the GPX has been created and its header written.
+    started: bool,
+    /// Bytes of the log decoded so far.
+    read: u32,
+}
+
+impl GpxExport {
+    fn new(dir: [u8; 7], log: &ShortFileName) -> Option<Self> {
+        Some(Self {
+            dir,
+            src: with_extension(log.base_name(), LOG_EXTENSION)?,
+            dst: with_extension(log.base_name(), GPX_EXTENSION)?,
+            decoder: GpzDecoder::new(),
+            activity: None,
+            started: false,
+            read: 0,
+        })
+    }
+}
+
+/// A file copy spread over several SD locks. Directories are paths from
+/// the root, so no handle is held between locks.
+struct FileCopy<'a> {
+    src_dir: &'a [u8],
+    src_name: &'a str,
+    dst_dir: &'a [u8],
+    dst_name: &'a str,
+    /// Bytes to copy at most; the copy also ends with the source.
+    len: u32,
+    /// Mode the destination is opened with for the first chunk; later
+    /// chunks append.
+    dst_mode: Mode,
+    copied: u32,
+}
+
+impl<'a> FileCopy<'a> {
+    fn new(
+        src_dir: &'a [u8],
+        src_name: &'a str,
+        dst_dir: &'a [u8],
+        dst_name: &'a str,
+        len: u32,
+        dst_mode: Mode,
+    ) -> Self {
+        Self {
+            src_dir,
+            src_name,
+            dst_dir,
+            dst_name,
+            len,
+            dst_mode,
+            copied: 0,
+        }
+    }
+}
+
+struct SdLogger {
+    // Max open: 4 files, 6 dirs (root_dir + listing_dir + ensure_log_directory peak of 2 temp dirs + margin), 1 volume
+    volume_mgr: SdVolumeManager,
+    volume: RawVolume,
+    root_dir: RawDirectory,
+    current_file: Option<RawFile>,
+    /// Day (YYYYMMDD) of the last log block written, for rotation.
+    log_date: u32,
     transfer: TransferState,
+    /// Whether the first log opened since mount has had its tail checked.
+    log_tail_checked: bool,
+    /// Day and last point timestamp of the log last written or scanned,
+    /// so `log_tail_timestamp` need not read it again.
+    log_tail: Option<(u32, Option<u32>)>,
     init_frequency: spim::Frequency,
     run_frequency: spim::Frequency,
 }
@@ -413,19 +2186,25 @@ impl SdLogger {
             volume,
             root_dir,
             current_file: None,
-            current_date: 0,
-            encoder: GpsDataEncoder::new(FULL_BLOCK_INTERVAL),
-            cache: [0; CACHE_SIZE],
-            cache_len: 0,
-            cache_dirty: false,
-            last_timestamp: 0,
-            last_nrf_timestamp: 0,
+            log_date: 0,
             transfer: TransferState::new(),
+            log_tail_checked: false,
+            log_tail: None,
             init_frequency,
             run_frequency,
         }
     }
 
+    /// Whether the card still answers (reads its CSD register).
+    fn card_present(&self) -> bool {
+        let present = Cell::new(false);
+        let _ = self.volume_mgr.device(|sd| {
+            present.set(sd.num_bytes().is_ok());
+            GpsTimeSource
+        });
+        present.get()
+    }
+
     fn into_usb_card(mut self) -> UsbSdCard {
         self.prepare_for_usb();
         let (card, _time) = self.volume_mgr.free();
@@ -433,7 +2212,6 @@ impl SdLogger {
     }
 
     fn prepare_for_usb(&mut self) {
-        let _ = self.flush_cache();
         self.close_current_file();
         if let Some(file) = self.transfer.open_file.take() {
             let _ = self.volume_mgr.close_file(file);
@@ -449,65 +2227,48 @@ impl SdLogger {
         }
     }
 
-    fn current_date_parts(&self) -> Option<(u16, u8, u8)> {
-        if self.current_date == 0 {
-            return None;
-        }
-        let year = (self.current_date / 10000) as u16;
-        let month = ((self.current_date / 100) % 100) as u8;
-        let day = (self.current_date % 100) as u8;
-        Some((year, month, day))
+    fn ensure_log_directory(&mut self, year: u16, month: u8) -> Result<RawDirectory, ()> {
+        self.ensure_month_directory(self.root_dir, year, month)
     }
 
-    fn ensure_log_directory(&mut self, year: u16, month: u8) -> Result<RawDirectory, ()> {
+    /// Open (creating if needed) `YYYY/MM` below `parent`.
+    fn ensure_month_directory(
+        &mut self,
+        parent: RawDirectory,
+        year: u16,
+        month: u8,
+    ) -> Result<RawDirectory, ()> {
         let year_digits = year_to_digits(year);
         let month_digits = two_digits(month);
         let year_str = bytes_to_str(&year_digits);
         let month_str = bytes_to_str(&month_digits);
 
-        let year_dir = match self.volume_mgr.open_dir(self.root_dir, year_str) {
-            Ok(dir) => dir,
-            Err(_) => {
-                if self.volume_mgr.make_dir_in_dir(self.root_dir, year_str).is_err() {
-                    return Err(());
-                }
-                match self.volume_mgr.open_dir(self.root_dir, year_str) {
-                    Ok(dir) => dir,
-                    Err(_) => return Err(()),
-                }
-            }
-        };
-
-        let month_dir = match self.volume_mgr.open_dir(year_dir, month_str) {
-            Ok(month_dir) => month_dir,
-            Err(_) => {
-                if self.volume_mgr.make_dir_in_dir(year_dir, month_str).is_err() {
-                    let _ = self.volume_mgr.close_dir(year_dir);
-                    return Err(());
-                }
-                match self.volume_mgr.open_dir(year_dir, month_str) {
-                    Ok(month_dir) => month_dir,
-                    Err(_) => {
-                        let _ = self.volume_mgr.close_dir(year_dir);
-                        return Err(());
-                    }
-                }
-            }
-        };
-
+        let year_dir = self.open_or_make_dir(parent, year_str)?;
+        let month_dir = self.open_or_make_dir(year_dir, month_str);
         let _ = self.volume_mgr.close_dir(year_dir);
-        Ok(month_dir)
+        month_dir
+    }
+
+    fn open_or_make_dir(&mut self, parent: RawDirectory, name: &str) -> Result<RawDirectory, ()> {
+        if let Ok(dir) = self.volume_mgr.open_dir(parent, name) {
+            return Ok(dir);
+        }
+        if self.volume_mgr.make_dir_in_dir(parent, name).is_err() {
+            return Err(());
+        }
+        self.volume_mgr.open_dir(parent, name).map_err(|_| ())
     }
 
-    fn open_log_file_for_current_date(&mut self) -> Option<RawFile> {
-        let (year, month, day) = self.current_date_parts()?;
+    fn open_log_file(&mut self, date: u32) -> Option<RawFile> {
+        let (year, month, day) = date_parts(date)?;
         
         // 确保日志目录存在
         let log_dir = self.ensure_log_directory(year, month).ok()?;
         
         // 构建文件名（不包含路径）
         let filename = build_bare_filename(year, month, day);
-        
+        self.create_long_named(log_dir, &month_path(year, month), filename.as_str());
+
         // 在日志目录中打开文件
         let file = self.volume_mgr
             .open_file_in_dir(log_dir, filename.as_str(), Mode::ReadWriteCreateOrAppend)
@@ -519,83 +2280,80 @@ impl SdLogger {
         Some(file)
     }
 
-    fn is_current_log_file(&self, file_name: &str) -> bool {
-        let Some((year, month, day)) = self.current_date_parts() else {
-            return false;
-        };
-        let current_path = build_log_filename(year, month, day);
-        current_path.as_str().eq_ignore_ascii_case(file_name)
-    }
-
-    fn append_gpx_point(
-        &mut self,
-        timestamp: u32,
-        latitude: f64,
-        longitude: f64,
-        altitude_m: f32,
-    ) -> bool {
-        if timestamp == 0 {
-            defmt::warn!("GPS log skipped: timestamp is zero");
-            return false;
-        }
-
-        let now_sec = (Instant::now().as_millis() / 1000) as u32;
-        if self.last_timestamp != 0 && self.last_nrf_timestamp != 0 {
-            let gps_diff = timestamp as i64 - self.last_timestamp as i64;
-            let nrf_diff = now_sec as i64 - self.last_nrf_timestamp as i64;
-            if nrf_diff >= 0 && (gps_diff - nrf_diff).abs() > 3600 {
-                defmt::warn!("GPS log skipped: timestamp jump detected");
-                return false;
+    /// Decode up to `CHUNKS_PER_LOCK` more chunks of the log of `date`.
+    /// Returns whether there is more to read; `None` without a log.
+    fn scan_log_step(&mut self, date: u32, scan: &mut LogScan) -> Option<bool> {
+        let (year, month, day) = date_parts(date)?;
+        let (dir, is_root) = self.open_dir_from_path(&month_path(year, month)).ok()?;
+        let filename = build_bare_filename(year, month, day);
+        let file = self
+            .volume_mgr
+            .open_file_in_dir(dir, filename.as_str(), Mode::ReadOnly);
+        self.close_dir_if_needed(dir, is_root);
+        let file = file.ok()?;
+        scan.size = self.volume_mgr.file_length(file).unwrap_or(0);
+        let seek = self.volume_mgr.file_seek_from_start(file, scan.offset);
+        let mut more = seek.is_ok();
+        let mut buf = [0u8; COPY_CHUNK];
+        for _ in 0..CHUNKS_PER_LOCK {
+            if !more {
+                break;
             }
+            more = match self.volume_mgr.read(file, &mut buf) {
+                Ok(n) if n > 0 => {
+                    scan.offset += n as u32;
+                    scan.prefix.push(&buf[..n])
+                }
+                _ => false,
+            };
         }
-        self.last_timestamp = timestamp;
-        self.last_nrf_timestamp = now_sec;
-
-        let entry = GpxPointInternal {
-            timestamp,
-            latitude_scaled_1e5: round_f64(latitude * 1e5) as i32,
-            longitude_scaled_1e5: round_f64(longitude * 1e5) as i32,
-            altitude_m_scaled_1e1: round_f32(altitude_m * 10.0) as i32,
-        };
-
-        // Release any open listing directory before log rotation, which may open
-        // temporary directories (year/month) via ensure_log_directory.
-        self.finish_listing();
+        let _ = self.volume_mgr.close_file(file);
+        Some(more)
+    }
 
-        if !self.rotate_log_file_if_needed(timestamp) {
-            return false;
-        }
+    /// Lengths of `name` (0 if missing) and of `shadow` in `dir`, if the
+    /// shadow exists.
+    fn shadow_lens(&mut self, dir: &[u8], name: &str, shadow: &str) -> Option<(u32, u32)> {
+        let (dir, is_root) = self.open_dir_from_path(dir).ok()?;
+        let shadow = self.volume_mgr.find_directory_entry(dir, shadow);
+        let original = self.volume_mgr.find_directory_entry(dir, name);
+        let lens = shadow
+            .ok()
+            .map(|shadow| (original.map_or(0, |original| original.size), shadow.size));
+        self.close_dir_if_needed(dir, is_root);
+        lens
+    }
 
-        let len = self.encoder.encode(entry);
-        if self.cache_len + len > self.cache.len() && !self.flush_cache() {
-            return false;
+    fn delete_in_dir(&mut self, dir: &[u8], name: &str) {
+        if let Ok((dir, is_root)) = self.open_dir_from_path(dir) {
+            let _ = self.volume_mgr.delete_file_in_dir(dir, name);
+            self.close_dir_if_needed(dir, is_root);
         }
+    }
 
-        let data = self.encoder.buffer();
-        if data.len() != len {
+    fn is_current_log_file(&self, file_name: &str) -> bool {
+        let Some((year, month, day)) = current_log_date_parts() else {
             return false;
-        }
-
-        self.cache[self.cache_len..self.cache_len + len].copy_from_slice(data);
-        self.cache_len += len;
-        self.cache_dirty = true;
-
-        if self.cache_len >= self.cache.len() {
-            return self.flush_cache();
-        }
-
-        true
+        };
+        let current_path = build_log_filename(year, month, day);
+        current_path.as_str().eq_ignore_ascii_case(file_name)
     }
 
-    fn flush_cache(&mut self) -> bool {
-        if !self.cache_dirty || self.cache_len == 0 {
-            return true;
+    fn write_log_block(&mut self, date: u32, data: &[u8], last_timestamp: u32) -> bool {
+        // Release any open listing directory first: rotation and opening the
+        // log may open temporary directories (year/month).
+        self.finish_listing();
+        if date != self.log_date {
+            // Day rotation.
+            self.close_current_file();
+            self.manage_old_files();
+            self.log_date = date;
         }
 
         let file = match self.current_file {
             Some(file) => file,
             None => {
-                let Some(file) = self.open_log_file_for_current_date() else {
+                let Some(file) = self.open_log_file(date) else {
                     return false;
                 };
                 self.current_file = Some(file);
@@ -603,42 +2361,12 @@ impl SdLogger {
             }
         };
 
-        let write_ok = self
-            .volume_mgr
-            .write(file, &self.cache[..self.cache_len])
-            .is_ok();
+        let write_ok = self.volume_mgr.write(file, data).is_ok();
         let flush_ok = write_ok && self.volume_mgr.flush_file(file).is_ok();
         self.close_current_file();
-
-        if !flush_ok {
-            return false;
-        }
-
-        self.cache_len = 0;
-        self.cache_dirty = false;
-        true
-    }
-
-    fn rotate_log_file_if_needed(&mut self, timestamp: u32) -> bool {
-        let Some((year, month, day)) = unix_to_date(timestamp) else {
-            return false;
-        };
-        let new_date = (year as u32) * 10000 + (month as u32) * 100 + (day as u32);
-
-        if new_date == self.current_date && self.current_date != 0 {
-            return true;
-        }
-
-        if self.current_date != 0 && !self.flush_cache() {
-            return false;
-        }
-
-        self.close_current_file();
-
-        self.manage_old_files();
-        self.current_date = new_date;
-        self.encoder.clear();
-        true
+        // A failed write may have left part of the block behind.
+        self.log_tail = flush_ok.then_some((date, Some(last_timestamp)));
+        flush_ok
     }
 
     fn manage_old_files(&mut self) {
@@ -690,12 +2418,14 @@ impl SdLogger {
         };
 
         let target_index = self.transfer.list_index;
-        let mut found: Option<DirEntry> = None;
+        let mut found: Option<(DirEntry, [u8; MAX_PATH_LENGTH], usize)> = None;
         let mut idx = 0usize;
+        let mut lfn_storage = [0u8; MAX_PATH_LENGTH];
+        let mut lfn_buffer = LfnBuffer::new(&mut lfn_storage);
 
         if self
             .volume_mgr
-            .iterate_dir(dir, |entry| {
+            .iterate_dir_lfn(dir, &mut lfn_buffer, |entry, long_name| {
                 if found.is_some() {
                     return;
                 }
@@ -703,7 +2433,16 @@ impl SdLogger {
                     return;
                 }
                 if idx == target_index {
-                    found = Some(entry.clone());
+                    // The long name when it fits, as a PC shows it.
+                    let mut name = [0u8; MAX_PATH_LENGTH];
+                    let name_len = match long_name.filter(|long| long.len() < MAX_PATH_LENGTH) {
+                        Some(long) => {
+                            name[..long.len()].copy_from_slice(long.as_bytes());
+                            long.len()
+                        }
+                        None => short_name_to_buf(&entry.name, &mut name),
+                    };
+                    found = Some((entry.clone(), name, name_len));
                 }
                 idx = idx.saturating_add(1);
             })
@@ -713,10 +2452,8 @@ impl SdLogger {
             return ListDirOutcome::Error;
         }
 
-        if let Some(entry) = found {
+        if let Some((entry, name, name_len)) = found {
             self.transfer.list_index = self.transfer.list_index.saturating_add(1);
-            let mut name = [0u8; MAX_PATH_LENGTH];
-            let name_len = short_name_to_buf(&entry.name, &mut name);
             let is_dir = entry.attributes.is_directory();
             return ListDirOutcome::Entry {
                 is_dir,
@@ -753,9 +2490,13 @@ impl SdLogger {
         }
 
         let (dir, is_root) = self.open_dir_from_path(dir_path.as_bytes()).ok()?;
+        let Some(short_name) = self.resolve_name(dir, file_name) else {
+            self.close_dir_if_needed(dir, is_root);
+            return None;
+        };
         let file = match self
             .volume_mgr
-            .open_file_in_dir(dir, file_name, Mode::ReadOnly)
+            .open_file_in_dir(dir, short_name.clone(), Mode::ReadOnly)
         {
             Ok(file) => file,
             Err(err) => {
@@ -766,7 +2507,7 @@ impl SdLogger {
                     self.close_current_file();
                     match self
                         .volume_mgr
-                        .open_file_in_dir(dir, file_name, Mode::ReadOnly)
+                        .open_file_in_dir(dir, short_name, Mode::ReadOnly)
                     {
                         Ok(file) => file,
                         Err(_) => {
@@ -797,174 +2538,646 @@ impl SdLogger {
         self.volume_mgr.read(file, out).map_err(|_| ())
     }
 
-    fn close_transfer_file(&mut self) -> bool {
-        if let Some(file) = self.transfer.open_file.take() {
-            let _ = self.volume_mgr.close_file(file);
+    fn close_transfer_file(&mut self) -> bool {
+        if let Some(file) = self.transfer.open_file.take() {
+            let _ = self.volume_mgr.close_file(file);
+        }
+        true
+    }
+
+    fn delete_transfer_file(&mut self, path: &[u8]) -> bool {
+        if self.transfer.open_file.is_some() {
+            return false;
+        }
+        if path.is_empty() || path.len() >= MAX_PATH_LENGTH {
+            return false;
+        }
+        let path_str = match core::str::from_utf8(path) {
+            Ok(path) => path,
+            Err(_) => return false,
+        };
+        let trimmed = path_str.trim_matches('/');
+        if trimmed.is_empty() {
+            return false;
+        }
+
+        let (dir_path, file_name) = match trimmed.rfind('/') {
+            Some(idx) => (&trimmed[..idx], &trimmed[idx + 1..]),
+            None => ("", trimmed),
+        };
+        if file_name.is_empty() {
+            return false;
+        }
+
+        let deleting_current = dir_path.is_empty() && self.is_current_log_file(file_name);
+        self.close_current_file();
+        self.log_tail = None;
+
+        let (dir, is_root) = match self.open_dir_from_path(dir_path.as_bytes()) {
+            Ok(result) => result,
+            Err(_) => return false,
+        };
+
+        let found = self.resolve_name(dir, file_name);
+        let entry = found.and_then(|name| self.volume_mgr.find_directory_entry(dir, name).ok());
+        let entry = match entry {
+            Some(entry) => entry,
+            None => {
+                if deleting_current {
+                    discard_current_log();
+                }
+                self.close_dir_if_needed(dir, is_root);
+                return false;
+            }
+        };
+
+        if entry.attributes.is_directory() {
+            self.close_dir_if_needed(dir, is_root);
+            return false;
+        }
+
+        let short = fat_lfn::short_name_parts(entry.name.base_name(), entry.name.extension());
+        if let Some(short) = short {
+            self.forget_long_name(dir_path.as_bytes(), &short);
+        }
+        let ok = self
+            .volume_mgr
+            .delete_file_in_dir(dir, entry.name)
+            .is_ok();
+        if ok && deleting_current {
+            discard_current_log();
+        }
+        self.close_dir_if_needed(dir, is_root);
+        ok
+    }
+
+    /// Check that the log of a day exists and may be touched; `Err` with
+    /// the outcome otherwise.
+    fn check_day_log(&mut self, year: u16, month: u8, day: u8) -> Result<(), LogFileOutcome> {
+        // The open log keeps being appended to; a transfer may be reading
+        // any file.
+        if self.transfer.open_file.is_some()
+            || current_log_date_parts() == Some((year, month, day))
+        {
+            return Err(LogFileOutcome::Skipped);
+        }
+        let Ok((dir, is_root)) = self.open_dir_from_path(&month_path(year, month)) else {
+            return Err(LogFileOutcome::Missing);
+        };
+        let filename = build_bare_filename(year, month, day);
+        let is_file = self
+            .volume_mgr
+            .find_directory_entry(dir, filename.as_str())
+            .is_ok_and(|entry| !entry.attributes.is_directory());
+        self.close_dir_if_needed(dir, is_root);
+        if is_file {
+            Ok(())
+        } else {
+            Err(LogFileOutcome::Missing)
+        }
+    }
+
+    /// Create `/ARCHIVE/YYYY/MM/` for an archive copy.
+    fn make_archive_dir(&mut self, year: u16, month: u8) -> bool {
+        let Ok(archive_dir) = self.open_or_make_dir(self.root_dir, ARCHIVE_DIR) else {
+            return false;
+        };
+        let dst_dir = self.ensure_month_directory(archive_dir, year, month);
+        let _ = self.volume_mgr.close_dir(archive_dir);
+        let Ok(dst_dir) = dst_dir else {
+            return false;
+        };
+        let _ = self.volume_mgr.close_dir(dst_dir);
+        true
+    }
+
+    /// Delete the log of a day, and its stats checkpoint.
+    fn delete_day_log(&mut self, year: u16, month: u8, day: u8) -> LogFileOutcome {
+        if let Err(outcome) = self.check_day_log(year, month, day) {
+            return outcome;
+        }
+        let Ok((dir, is_root)) = self.open_dir_from_path(&month_path(year, month)) else {
+            return LogFileOutcome::Failed;
+        };
+        let filename = build_bare_filename(year, month, day);
+        let ok = self.delete_long_named(dir, &month_path(year, month), filename.as_str());
+        if ok {
+            self.log_tail = None;
+            // The checkpoint only served the running stats of that day.
+            if let Some(stats) = day_stats_name(year, month, day) {
+                self.delete_safe_file(dir, stats.as_str());
+            }
+        }
+        self.close_dir_if_needed(dir, is_root);
+        if ok {
+            LogFileOutcome::Done
+        } else {
+            LogFileOutcome::Failed
+        }
+    }
+
+    /// Copy up to `chunks` more chunks of `copy`. Returns whether there is
+    /// more to copy; `None` once it failed.
+    fn copy_file_step(&mut self, copy: &mut FileCopy, chunks: usize) -> Option<bool> {
+        let (src_dir, src_is_root) = self.open_dir_from_path(copy.src_dir).ok()?;
+        let same_dir = copy.src_dir == copy.dst_dir;
+        let dst = if same_dir {
+            Ok((src_dir, src_is_root))
+        } else {
+            self.open_dir_from_path(copy.dst_dir)
+        };
+        let Ok((dst_dir, dst_is_root)) = dst else {
+            self.close_dir_if_needed(src_dir, src_is_root);
+            return None;
+        };
+        let step = self.copy_chunks(src_dir, dst_dir, copy, chunks);
+        if !same_dir {
+            self.close_dir_if_needed(dst_dir, dst_is_root);
+        }
+        self.close_dir_if_needed(src_dir, src_is_root);
+        step
+    }
+
+    fn copy_chunks(
+        &mut self,
+        src_dir: RawDirectory,
+        dst_dir: RawDirectory,
+        copy: &mut FileCopy,
+        chunks: usize,
+    ) -> Option<bool> {
+        let src = self
+            .volume_mgr
+            .open_file_in_dir(src_dir, copy.src_name, Mode::ReadOnly)
+            .ok()?;
+        let mode = if copy.copied == 0 {
+            self.create_long_named(dst_dir, copy.dst_dir, copy.dst_name);
+            copy.dst_mode
+        } else {
+            Mode::ReadWriteAppend
+        };
+        let dst = self.volume_mgr.open_file_in_dir(dst_dir, copy.dst_name, mode);
+        let Ok(dst) = dst else {
+            let _ = self.volume_mgr.close_file(src);
+            return None;
+        };
+
+        let mut buf = [0u8; COPY_CHUNK];
+        let seek = self.volume_mgr.file_seek_from_start(src, copy.copied);
+        let mut step = seek.ok().map(|_| copy.copied < copy.len);
+        for _ in 0..chunks {
+            if step != Some(true) {
+                break;
+            }
+            let want = (copy.len - copy.copied).min(COPY_CHUNK as u32) as usize;
+            step = match self.volume_mgr.read(src, &mut buf[..want]) {
+                Ok(0) => Some(false),
+                Ok(n) if self.volume_mgr.write(dst, &buf[..n]).is_ok() => {
+                    copy.copied += n as u32;
+                    Some(copy.copied < copy.len)
+                }
+                _ => None,
+            };
+        }
+        if step.is_some() && self.volume_mgr.flush_file(dst).is_err() {
+            step = None;
+        }
+        let _ = self.volume_mgr.close_file(dst);
+        let _ = self.volume_mgr.close_file(src);
+        if step.is_none() {
+            self.delete_long_named(dst_dir, copy.dst_dir, copy.dst_name);
+        }
+        step
+    }
+
+    /// Names of the subdirectories of `dir` named with `digits` digits.
+    fn digit_dirs(&mut self, dir: &[u8], digits: usize) -> DigitDirs {
+        // The path may be the directory a transfer is listing.
+        self.finish_listing();
+        let mut names = DigitDirs::new();
+        let Ok((dir, is_root)) = self.open_dir_from_path(dir) else {
+            return names;
+        };
+        let _ = self.volume_mgr.iterate_dir(dir, |entry| {
+            if entry.attributes.is_directory() && is_digit_name(&entry.name, digits) {
+                let _ = names.push(entry.name.clone());
+            }
+        });
+        self.close_dir_if_needed(dir, is_root);
+        names
+    }
+
+    /// Daily logs in the month directory `dir`, newest first.
+    fn month_day_logs(&mut self, dir: &[u8]) -> heapless::Vec<DayLog, 31> {
+        let mut days: heapless::Vec<DayLog, 31> = heapless::Vec::new();
+        let Ok((dir, is_root)) = self.open_dir_from_path(dir) else {
+            return days;
+        };
+        let _ = self.volume_mgr.iterate_dir(dir, |entry| {
+            if entry.attributes.is_directory() || !is_gpx_entry(entry) {
+                return;
+            }
+            if let Some(date) = log_date(entry.name.base_name()) {
+                let size = entry.size;
+                let _ = days.push(DayLog { date, size });
+            }
+        });
+        self.close_dir_if_needed(dir, is_root);
+        days.sort_unstable_by(|a, b| b.date.cmp(&a.date));
+        days
+    }
+
+    /// Logs in `dir` to export: those without a GPX, today's, and those
+    /// changed after theirs. Any other log with a GPX comes back flagged,
+    /// to be checked with `gpx_up_to_date`.
+    fn gpx_candidates(&mut self, dir: &[u8]) -> heapless::Vec<(ShortFileName, bool), 31> {
+        let mut candidates = heapless::Vec::new();
+        let Ok((dir, is_root)) = self.open_dir_from_path(dir) else {
+            return candidates;
+        };
+        let mut logs: heapless::Vec<(ShortFileName, u32), 31> = heapless::Vec::new();
+        let mut gpx: heapless::Vec<(ShortFileName, u32), 31> = heapless::Vec::new();
+        let _ = self.volume_mgr.iterate_dir(dir, |entry| {
+            if entry.attributes.is_directory() {
+                return;
+            }
+            let modified = timestamp_key(&entry.mtime);
+            if is_gpx_entry(entry) {
+                let _ = logs.push((entry.name.clone(), modified));
+            } else if entry.name.extension().eq_ignore_ascii_case(GPX_EXTENSION) {
+                let _ = gpx.push((entry.name.clone(), modified));
+            }
+        });
+        self.close_dir_if_needed(dir, is_root);
+
+        let current = current_log_date_parts().map(|(y, m, d)| build_bare_filename(y, m, d));
+        for (log, log_modified) in logs {
+            let src = with_extension(log.base_name(), LOG_EXTENSION);
+            let is_current = current
+                .as_ref()
+                .zip(src.as_ref())
+                .is_some_and(|(name, src)| name.as_str().eq_ignore_ascii_case(src));
+            let base = log.base_name();
+            let export = gpx.iter().find(|(name, _)| name.base_name() == base);
+            let check = export.is_some_and(|&(_, modified)| modified >= log_modified);
+            let _ = candidates.push((log, check && !is_current));
         }
-        true
+        candidates
     }
 
-    fn delete_transfer_file(&mut self, path: &[u8]) -> bool {
-        if self.transfer.open_file.is_some() {
-            return false;
-        }
-        if path.is_empty() || path.len() >= MAX_PATH_LENGTH {
+    /// Whether the GPX of `log` in `dir` was rendered from the log as it is.
+    fn gpx_up_to_date(&mut self, dir: &[u8], log: &ShortFileName) -> bool {
+        let (Some(src), Some(dst)) = (
+            with_extension(log.base_name(), LOG_EXTENSION),
+            with_extension(log.base_name(), GPX_EXTENSION),
+        ) else {
             return false;
-        }
-        let path_str = match core::str::from_utf8(path) {
-            Ok(path) => path,
-            Err(_) => return false,
         };
-        let trimmed = path_str.trim_matches('/');
-        if trimmed.is_empty() {
+        let Ok((dir, is_root)) = self.open_dir_from_path(dir) else {
             return false;
-        }
-
-        let (dir_path, file_name) = match trimmed.rfind('/') {
-            Some(idx) => (&trimmed[..idx], &trimmed[idx + 1..]),
-            None => ("", trimmed),
         };
-        if file_name.is_empty() {
+        let log_len = self.volume_mgr.find_directory_entry(dir, src.as_str());
+        let (dst, mode) = (dst.as_str(), Mode::ReadOnly);
+        let file = self.volume_mgr.open_file_in_dir(dir, dst, mode);
+        self.close_dir_if_needed(dir, is_root);
+        let Ok(file) = file else {
             return false;
-        }
-
-        let deleting_current = dir_path.is_empty() && self.is_current_log_file(file_name);
-        self.close_current_file();
-
-        let (dir, is_root) = match self.open_dir_from_path(dir_path.as_bytes()) {
-            Ok(result) => result,
-            Err(_) => return false,
         };
-
-        let entry = match self.volume_mgr.find_directory_entry(dir, file_name) {
-            Ok(entry) => entry,
-            Err(_) => {
-                if deleting_current {
-                    self.cache_len = 0;
-                    self.cache_dirty = false;
-                    self.encoder.clear();
-                }
-                self.close_dir_if_needed(dir, is_root);
-                return false;
-            }
+        let mut mark = [0u8; gpx_export::SOURCE_MARK_LEN];
+        let offset = gpx_export::HEADER.len() as u32;
+        let read = match self.volume_mgr.file_seek_from_start(file, offset) {
+            Ok(()) => self.volume_mgr.read(file, &mut mark).unwrap_or(0),
+            Err(_) => 0,
         };
+        let _ = self.volume_mgr.close_file(file);
+        let rendered_from = gpx_export::parse_source_mark(&mark[..read]);
+        log_len.is_ok_and(|entry| rendered_from == Some(entry.size))
+    }
 
-        if entry.attributes.is_directory() {
-            self.close_dir_if_needed(dir, is_root);
-            return false;
-        }
-
-        let ok = self
-            .volume_mgr
-            .delete_file_in_dir(dir, file_name)
-            .is_ok();
-        if ok && deleting_current {
-            self.cache_len = 0;
-            self.cache_dirty = false;
-            self.encoder.clear();
+    /// Export up to `CHUNKS_PER_LOCK` more chunks of `export`. Returns
+    /// whether there is more to export; `None` once it failed, leaving no
+    /// GPX behind.
+    fn export_gpx_step(&mut self, export: &mut GpxExport) -> Option<bool> {
+        let (dir, is_root) = self.open_dir_from_path(&export.dir).ok()?;
+        let step = self.export_gpx_chunks(dir, export);
+        if step.is_none() {
+            self.delete_long_named(dir, &export.dir, export.dst.as_str());
         }
         self.close_dir_if_needed(dir, is_root);
-        ok
+        step
     }
 
-    fn read_findmy_keys(&mut self) -> Option<[u8; FINDMY_KEY_SIZE]> {
-        let file = self
+    fn export_gpx_chunks(&mut self, dir: RawDirectory, export: &mut GpxExport) -> Option<bool> {
+        let src_name = export.src.as_str();
+        let src = self
             .volume_mgr
-            .open_file_in_dir(self.root_dir, "FINDMY.KEY", Mode::ReadOnly)
+            .open_file_in_dir(dir, src_name, Mode::ReadOnly)
             .ok()?;
-        let mut buf = [0u8; FINDMY_KEY_SIZE];
-        let n = self.volume_mgr.read(file, &mut buf).ok()?;
-        let _ = self.volume_mgr.close_file(file);
-        if n == FINDMY_KEY_SIZE {
-            Some(buf)
+        let dst_mode = if export.started {
+            Mode::ReadWriteAppend
         } else {
-            None
+            self.create_long_named(dir, &export.dir, export.dst.as_str());
+            Mode::ReadWriteCreateOrTruncate
+        };
+        let dst_name = export.dst.as_str();
+        let dst = self.volume_mgr.open_file_in_dir(dir, dst_name, dst_mode);
+        let Ok(dst) = dst else {
+            let _ = self.volume_mgr.close_file(src);
+            return None;
+        };
+
+        let mut ok = true;
+        if !export.started {
+            export.started = true;
+            let log_len = self.volume_mgr.file_length(src).unwrap_or(0);
+            ok = self.volume_mgr.write(dst, gpx_export::HEADER).is_ok()
+                && self
+                    .volume_mgr
+                    .write(dst, &gpx_export::source_mark(log_len))
+                    .is_ok();
+        }
+        let seek = self.volume_mgr.file_seek_from_start(src, export.read);
+        ok = ok && seek.is_ok();
+
+        let mut in_buf = [0u8; COPY_CHUNK];
+        let mut out = [0u8; COPY_CHUNK];
+        let mut out_len = 0;
+        let mut line = [0u8; gpx_export::MAX_POINT_LEN];
+        let mut more = ok;
+        'read: for _ in 0..CHUNKS_PER_LOCK {
+            if !more {
+                break;
+            }
+            let n = match self.volume_mgr.read(src, &mut in_buf) {
+                Ok(0) => {
+                    more = false;
+                    break;
+                }
+                Ok(n) => n,
+                Err(_) => {
+                    ok = false;
+                    break;
+                }
+            };
+            export.read += n as u32;
+            for point in export.decoder.points(&in_buf[..n]) {
+                if export.activity != Some(point.activity) {
+                    if export.activity.is_some() {
+                        ok = self.buffer_gpx(dst, &mut out, &mut out_len, gpx_export::TRACK_END);
+                    }
+                    let start = gpx_export::track_start(point.activity);
+                    ok = ok && self.buffer_gpx(dst, &mut out, &mut out_len, start);
+                    export.activity = Some(point.activity);
+                }
+                let len = gpx_export::format_point(&point, &mut line);
+                ok = ok && self.buffer_gpx(dst, &mut out, &mut out_len, &line[..len]);
+                if !ok {
+                    break 'read;
+                }
+            }
+        }
+        let done = ok && !more;
+        if done && export.activity.is_some() {
+            ok = self.buffer_gpx(dst, &mut out, &mut out_len, gpx_export::TRACK_END);
+        }
+        if done {
+            ok = ok && self.buffer_gpx(dst, &mut out, &mut out_len, gpx_export::FOOTER);
+        }
+        ok = ok
+            && self.volume_mgr.write(dst, &out[..out_len]).is_ok()
+            && self.volume_mgr.flush_file(dst).is_ok();
+        let _ = self.volume_mgr.close_file(dst);
+        let _ = self.volume_mgr.close_file(src);
+        let errors = export.decoder.errors();
+        if done && errors > 0 {
+            defmt::warn!("SD: {} had {} invalid blocks", src_name, errors);
         }
+        ok.then_some(!done)
+    }
+
+    /// Append `bytes` to the export buffer, writing it out first when full.
+    fn buffer_gpx(
+        &mut self,
+        dst: RawFile,
+        out: &mut [u8; COPY_CHUNK],
+        out_len: &mut usize,
+        bytes: &[u8],
+    ) -> bool {
+        if *out_len + bytes.len() > out.len() {
+            if self.volume_mgr.write(dst, &out[..*out_len]).is_err() {
+                return false;
+            }
+            *out_len = 0;
+        }
+        out[*out_len..*out_len + bytes.len()].copy_from_slice(bytes);
+        *out_len += bytes.len();
+        true
     }
 
-    fn read_fmdn_eik(&mut self) -> Option<[u8; FMDN_EIK_SIZE]> {
+    fn read_root_file(&mut self, name: &str, out: &mut [u8]) -> Option<usize> {
+        self.read_dir_file(self.root_dir, name, out)
+    }
+
+    fn read_dir_file(&mut self, dir: RawDirectory, name: &str, out: &mut [u8]) -> Option<usize> {
         let file = self
             .volume_mgr
-            .open_file_in_dir(self.root_dir, "FMDN.EIK", Mode::ReadOnly)
+            .open_file_in_dir(dir, name, Mode::ReadOnly)
             .ok()?;
-        let mut buf = [0u8; FMDN_EIK_SIZE];
-        let n = self.volume_mgr.read(file, &mut buf).ok()?;
+        let n = self.volume_mgr.read(file, out);
         let _ = self.volume_mgr.close_file(file);
-        if n == FMDN_EIK_SIZE {
-            Some(buf)
-        } else {
-            None
-        }
+        n.ok()
     }
 
-    fn write_fmdn_eik(&mut self, data: &[u8; FMDN_EIK_SIZE]) -> bool {
-        let _ = self
-            .volume_mgr
-            .delete_file_in_dir(self.root_dir, "FMDN.EIK");
-        let file = match self.volume_mgr.open_file_in_dir(
-            self.root_dir,
-            "FMDN.EIK",
-            Mode::ReadWriteCreateOrTruncate,
-        ) {
-            Ok(f) => f,
-            Err(_) => return false,
+    fn write_root_file(&mut self, name: &str, data: &[u8]) -> bool {
+        self.write_dir_file(self.root_dir, name, data)
+    }
+
+    fn write_dir_file(&mut self, dir: RawDirectory, name: &str, data: &[u8]) -> bool {
+        let _ = self.volume_mgr.delete_file_in_dir(dir, name);
+        let mode = Mode::ReadWriteCreateOrTruncate;
+        let Ok(file) = self.volume_mgr.open_file_in_dir(dir, name, mode) else {
+            PROBE_CARD.signal(());
+            return false;
         };
         let ok = self.volume_mgr.write(file, data).is_ok();
         let flush_ok = ok && self.volume_mgr.flush_file(file).is_ok();
         let _ = self.volume_mgr.close_file(file);
+        if !flush_ok {
+            PROBE_CARD.signal(());
+        }
         flush_ok
     }
 
-    fn write_findmy_keys(&mut self, data: &[u8; FINDMY_KEY_SIZE]) -> bool {
-        // Delete existing file first (ignore error if not found).
-        let _ = self
+    fn append_root_file(&mut self, name: &str, data: &[u8]) -> bool {
+        self.append_dir_file(self.root_dir, name, data)
+    }
+
+    /// Append to the file with extension `ext` next to the log of `date`.
+    fn append_day_file(&mut self, date: u32, ext: &[u8], data: &[u8]) -> bool {
+        let Some((year, month, day)) = date_parts(date) else {
+            return false;
+        };
+        let Some(name) = day_file_name(year, month, day, ext) else {
+            return false;
+        };
+        let Ok(dir) = self.ensure_log_directory(year, month) else {
+            PROBE_CARD.signal(());
+            return false;
+        };
+        self.create_long_named(dir, &month_path(year, month), name.as_str());
+        let ok = self.append_dir_file(dir, name.as_str(), data);
+        let _ = self.volume_mgr.close_dir(dir);
+        ok
+    }
+
+    fn append_dir_file(&mut self, dir: RawDirectory, name: &str, data: &[u8]) -> bool {
+        let file = match self
             .volume_mgr
-            .delete_file_in_dir(self.root_dir, "FINDMY.KEY");
-        let file = match self.volume_mgr.open_file_in_dir(
-            self.root_dir,
-            "FINDMY.KEY",
-            Mode::ReadWriteCreateOrTruncate,
-        ) {
+            .open_file_in_dir(dir, name, Mode::ReadWriteCreateOrAppend)
+        {
             Ok(f) => f,
-            Err(_) => return false,
+            Err(_) => {
+                PROBE_CARD.signal(());
+                return false;
+            }
         };
         let ok = self.volume_mgr.write(file, data).is_ok();
         let flush_ok = ok && self.volume_mgr.flush_file(file).is_ok();
         let _ = self.volume_mgr.close_file(file);
+        if !flush_ok {
+            PROBE_CARD.signal(());
+        }
         flush_ok
     }
 
-    fn read_findmy_sk_cache(&mut self) -> Option<[u8; FINDMY_SK_CACHE_SIZE]> {
-        let file = self
-            .volume_mgr
-            .open_file_in_dir(self.root_dir, "FINDMY.SKC", Mode::ReadOnly)
-            .ok()?;
-        let mut buf = [0u8; FINDMY_SK_CACHE_SIZE];
-        let n = self.volume_mgr.read(file, &mut buf).ok()?;
-        let _ = self.volume_mgr.close_file(file);
-        if n == FINDMY_SK_CACHE_SIZE {
-            Some(buf)
-        } else {
-            None
+    // Config writes are power-fail safe. embedded-sdmmc has no rename, so the
+    // new content goes to a shadow copy (`NAME.EX~`) first, then replaces the
+    // primary, then the shadow is removed. A cut during either step leaves at
+    // least one complete copy; readers try the primary, then the shadow.
+
+    fn write_config_file(&mut self, name: &str, data: &[u8]) -> bool {
+        self.write_safe_file(self.root_dir, name, data)
+    }
+
+    fn write_safe_file(&mut self, dir: RawDirectory, name: &str, data: &[u8]) -> bool {
+        let Some(shadow) = shadow_name(name) else {
+            return false;
+        };
+        if !self.write_dir_file(dir, shadow.as_str(), data) {
+            return false;
+        }
+        if !self.write_dir_file(dir, name, data) {
+            return false;
         }
+        let _ = self.volume_mgr.delete_file_in_dir(dir, shadow.as_str());
+        true
     }
 
-    fn write_findmy_sk_cache(&mut self, data: &[u8; FINDMY_SK_CACHE_SIZE]) -> bool {
-        let _ = self
-            .volume_mgr
-            .delete_file_in_dir(self.root_dir, "FINDMY.SKC");
-        let file = match self.volume_mgr.open_file_in_dir(
-            self.root_dir,
-            "FINDMY.SKC",
-            Mode::ReadWriteCreateOrTruncate,
-        ) {
-            Ok(f) => f,
-            Err(_) => return false,
+    fn read_config_file(
+        &mut self,
+        name: &str,
+        out: &mut [u8],
+        valid: impl Fn(&[u8]) -> bool,
+    ) -> Option<usize> {
+        self.read_safe_file(self.root_dir, name, out, valid)
+    }
+
+    fn read_safe_file(
+        &mut self,
+        dir: RawDirectory,
+        name: &str,
+        out: &mut [u8],
+        valid: impl Fn(&[u8]) -> bool,
+    ) -> Option<usize> {
+        if let Some(n) = self.read_dir_file(dir, name, out) {
+            if valid(&out[..n]) {
+                return Some(n);
+            }
+        }
+        let shadow = shadow_name(name)?;
+        let n = self.read_dir_file(dir, shadow.as_str(), out)?;
+        if !valid(&out[..n]) {
+            return None;
+        }
+        defmt::warn!("SD: {} invalid, recovered from shadow copy", name);
+        Some(n)
+    }
+
+    fn delete_config_file(&mut self, name: &str) {
+        self.delete_safe_file(self.root_dir, name);
+    }
+
+    fn delete_safe_file(&mut self, dir: RawDirectory, name: &str) {
+        let _ = self.volume_mgr.delete_file_in_dir(dir, name);
+        if let Some(shadow) = shadow_name(name) {
+            let _ = self.volume_mgr.delete_file_in_dir(dir, shadow.as_str());
+        }
+    }
+
+    /// Before `name` in `dir` (at `dir_path`) is created, write it with its
+    /// long name if it is a day file that gets one (see `fat_lfn`).
+    fn create_long_named(&mut self, dir: RawDirectory, dir_path: &[u8], name: &str) {
+        let bytes = name.as_bytes();
+        let (Some(long), Some(short)) = (fat_lfn::day_long_name(bytes), fat_lfn::short_name(bytes))
+        else {
+            return;
         };
-        let ok = self.volume_mgr.write(file, data).is_ok();
-        let flush_ok = ok && self.volume_mgr.flush_file(file).is_ok();
-        let _ = self.volume_mgr.close_file(file);
-        flush_ok
+        if self.volume_mgr.find_directory_entry(dir, name).is_ok() {
+            return;
+        }
+        let stamp = match GPS_TIME.load(AtomicOrdering::Relaxed) {
+            0 => FALLBACK_FAT_TIME,
+            packed => packed,
+        };
+        // `device` drops the block embedded-sdmmc had cached, so it reads
+        // the directory back from the card.
+        let _ = self.volume_mgr.device(|sd| {
+            let long = long.as_bytes();
+            fat_lfn::create(&mut CardBlocks(sd), dir_path, &short, long, stamp);
+            GpsTimeSource
+        });
+    }
+
+    /// Mark the long-name entries of `short` in `dir_path` free before it
+    /// is deleted.
+    fn forget_long_name(&mut self, dir_path: &[u8], short: &[u8; 11]) {
+        let _ = self.volume_mgr.device(|sd| {
+            if !fat_lfn::forget(&mut CardBlocks(sd), dir_path, short) {
+                defmt::warn!("SD: long name left behind");
+            }
+            GpsTimeSource
+        });
+    }
+
+    /// Delete `name` in `dir` (at `dir_path`) with its long name.
+    fn delete_long_named(&mut self, dir: RawDirectory, dir_path: &[u8], name: &str) -> bool {
+        if let Some(short) = fat_lfn::short_name(name.as_bytes()) {
+            self.forget_long_name(dir_path, &short);
+        }
+        self.volume_mgr.delete_file_in_dir(dir, name).is_ok()
+    }
+
+    fn read_day_stats(&mut self, date: u32, out: &mut [u8; TODAY_STATS_LEN]) -> Option<()> {
+        let (year, month, day) = date_parts(date)?;
+        let name = day_stats_name(year, month, day)?;
+        let dir = self.ensure_log_directory(year, month).ok()?;
+        let n = self.read_safe_file(dir, name.as_str(), out, |d| d.len() == TODAY_STATS_LEN);
+        let _ = self.volume_mgr.close_dir(dir);
+        n.map(|_| ())
+    }
+
+    fn write_day_stats(&mut self, date: u32, data: &[u8; TODAY_STATS_LEN]) -> bool {
+        let Some((year, month, day)) = date_parts(date) else {
+            return false;
+        };
+        let Some(name) = day_stats_name(year, month, day) else {
+            return false;
+        };
+        let Ok(dir) = self.ensure_log_directory(year, month) else {
+            return false;
+        };
+        let ok = self.write_safe_file(dir, name.as_str(), data);
+        let _ = self.volume_mgr.close_dir(dir);
+        ok
     }
 
     fn open_dir_from_path(&mut self, path: &[u8]) -> Result<(RawDirectory, bool), ()> {
@@ -984,9 +3197,10 @@ impl SdLogger {
             if component.is_empty() {
                 continue;
             }
-            let next = match self.volume_mgr.open_dir(current, component) {
-                Ok(dir) => dir,
-                Err(_) => {
+            let name = self.resolve_name(current, component);
+            let next = match name.and_then(|name| self.volume_mgr.open_dir(current, name).ok()) {
+                Some(dir) => dir,
+                None => {
                     self.close_dir_if_needed(current, current_is_root);
                     return Err(());
                 }
@@ -999,6 +3213,29 @@ impl SdLogger {
         Ok((current, current_is_root))
     }
 
+    /// Short name of the entry `name` in `dir`, which may be its 8.3 name
+    /// or, ignoring ASCII case, its long name.
+    fn resolve_name(&mut self, dir: RawDirectory, name: &str) -> Option<ShortFileName> {
+        if let Ok(short_name) = ShortFileName::create_from_str(name) {
+            let entry = self.volume_mgr.find_directory_entry(dir, name);
+            if entry.is_ok() {
+                return Some(short_name);
+            }
+        }
+        let mut found = None;
+        let mut lfn_storage = [0u8; MAX_PATH_LENGTH];
+        let mut lfn_buffer = LfnBuffer::new(&mut lfn_storage);
+        self.volume_mgr
+            .iterate_dir_lfn(dir, &mut lfn_buffer, |entry, long_name| {
+                let matches = long_name.is_some_and(|long| long.eq_ignore_ascii_case(name));
+                if matches && found.is_none() {
+                    found = Some(entry.name.clone());
+                }
+            })
+            .ok()?;
+        found
+    }
+
     fn close_dir_if_needed(&mut self, dir: RawDirectory, is_root: bool) {
         if !is_root {
             let _ = self.volume_mgr.close_dir(dir);
@@ -1030,6 +3267,57 @@ impl GpxFileInfo {
     }
 }
 
+/// 8.3 shadow name for config writes: last extension char becomes `~`.
+fn shadow_name(name: &str) -> Option<heapless::String<12>> {
+    let mut out = heapless::String::<12>::new();
+    let (stem, ext) = name.rsplit_once('.').unwrap_or((name, ""));
+    out.push_str(stem).ok()?;
+    out.push('.').ok()?;
+    match ext.len() {
+        0..=2 => out.push_str(ext).ok()?,
+        _ => out.push_str(&ext[..2]).ok()?,
+    }
+    out.push('~').ok()?;
+    Some(out)
+}
+
+/// `YYYYMMDD.sts`, the `track_stats` checkpoint next to the day log.
+fn day_stats_name(year: u16, month: u8, day: u8) -> Option<heapless::String<12>> {
+    day_file_name(year, month, day, DAY_STATS_EXTENSION)
+}
+
+/// `YYYYMMDD.ext` next to the day log.
+fn day_file_name(year: u16, month: u8, day: u8, ext: &[u8]) -> Option<heapless::String<12>> {
+    let log = build_bare_filename(year, month, day);
+    let (base, _) = log.as_str().rsplit_once('.')?;
+    with_extension(base.as_bytes(), ext)
+}
+
+/// `base.ext` as an 8.3 name.
+fn with_extension(base: &[u8], ext: &[u8]) -> Option<heapless::String<12>> {
+    let mut out = heapless::String::<12>::new();
+    out.push_str(core::str::from_utf8(base).ok()?).ok()?;
+    out.push('.').ok()?;
+    out.push_str(core::str::from_utf8(ext).ok()?).ok()?;
+    Some(out)
+}
+
+/// A `YYYY` or `MM` log directory name.
+/// `timestamp` as a number that orders like it.
+fn timestamp_key(timestamp: &Timestamp) -> u32 {
+    u32::from(timestamp.year_since_1970) << 26
+        | u32::from(timestamp.zero_indexed_month) << 22
+        | u32::from(timestamp.zero_indexed_day) << 17
+        | u32::from(timestamp.hours) << 12
+        | u32::from(timestamp.minutes) << 6
+        | u32::from(timestamp.seconds)
+}
+
+fn is_digit_name(name: &ShortFileName, len: usize) -> bool {
+    let base = name.base_name();
+    base.len() == len && name.extension().is_empty() && base.iter().all(u8::is_ascii_digit)
+}
+
 fn find_oldest_index(files: &[GpxFileInfo]) -> usize {
     let mut oldest = 0;
     for (idx, file) in files.iter().enumerate().skip(1) {
@@ -1076,6 +3364,14 @@ fn should_skip_entry(entry: &DirEntry) -> bool {
     entry.name == ShortFileName::this_dir() || entry.name == ShortFileName::parent_dir()
 }
 
+/// `YYYYMMDD` from a log's base name.
+fn log_date(base: &[u8]) -> Option<u32> {
+    if base.len() != 8 || !base.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    Some(base.iter().fold(0, |acc, &b| acc * 10 + (b - b'0') as u32))
+}
+
 fn is_gpx_entry(entry: &DirEntry) -> bool {
     entry.name.extension().eq_ignore_ascii_case(LOG_EXTENSION)
 }
@@ -1100,120 +3396,174 @@ impl TimeSource for GpsTimeSource {
     }
 }
 
-pub(crate) struct SdSpiDevice {
-    spi: Spim<'static>,
-    cs: Output<'static>,
-    config: spim::Config,
-}
-
-impl SdSpiDevice {
-    fn new(spi: Spim<'static>, mut cs: Output<'static>, config: spim::Config) -> Self {
-        cs.set_high();
-        Self { spi, cs, config }
-    }
+pub(crate) type SdSpiDevice = SharedSpiDevice;
 
-    fn set_frequency(&mut self, frequency: spim::Frequency) {
-        self.config.frequency = frequency;
-        let _ = self.spi.set_config(&self.config);
-    }
+/// The card under the volume manager, as `fat_lfn` reads and writes it.
+struct CardBlocks<'a>(&'a mut SdCard<SdSpiDevice, Delay>);
 
-    fn send_idle_clocks(&mut self) -> Result<(), embassy_nrf::spim::Error> {
-        self.cs.set_high();
-        let idle = [0xFFu8; 10];
-        SpiBus::write(&mut self.spi, &idle)?;
-        let _ = SpiBus::flush(&mut self.spi);
-        Ok(())
+impl fat_lfn::Blocks for CardBlocks<'_> {
+    fn read(&mut self, idx: u32, block: &mut fat_lfn::Block) -> bool {
+        let mut blocks = [Block::new()];
+        let ok = self.0.read(&mut blocks, BlockIdx(idx)).is_ok();
+        block.copy_from_slice(&blocks[0].contents);
+        ok
     }
-}
-
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
-pub(crate) enum SdSpiError {
-    Spi(embassy_nrf::spim::Error),
-}
 
-impl embedded_hal::spi::Error for SdSpiError {
-    fn kind(&self) -> embedded_hal::spi::ErrorKind {
-        embedded_hal::spi::ErrorKind::Other
+    fn write(&mut self, idx: u32, block: &fat_lfn::Block) -> bool {
+        let mut blocks = [Block::new()];
+        blocks[0].contents.copy_from_slice(block);
+        self.0.write(&blocks, BlockIdx(idx)).is_ok()
     }
 }
 
-impl embedded_hal::spi::ErrorType for SdSpiDevice {
-    type Error = SdSpiError;
+#[derive(Clone, Copy, Default)]
+pub(crate) struct GpxPointInternal {
+    timestamp: u32,
+    latitude_scaled_1e7: i32,
+    longitude_scaled_1e7: i32,
+    altitude_m_scaled_1e1: i32,
+    /// Pascals, 0 without a barometer reading.
+    pressure_pa: i32,
 }
 
-impl SpiDevice<u8> for SdSpiDevice {
-    fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Self::Error> {
-        self.cs.set_low();
+impl GpxPointInternal {
+    pub(crate) const ZERO: Self = Self {
+        timestamp: 0,
+        latitude_scaled_1e7: 0,
+        longitude_scaled_1e7: 0,
+        altitude_m_scaled_1e1: 0,
+        pressure_pa: 0,
+    };
 
-        for op in operations {
-            let result = match op {
-                Operation::Read(buf) => SpiBus::read(&mut self.spi, buf),
-                Operation::Write(buf) => SpiBus::write(&mut self.spi, buf),
-                Operation::Transfer(read, write) => SpiBus::transfer(&mut self.spi, read, write),
-                Operation::TransferInPlace(buf) => SpiBus::transfer_in_place(&mut self.spi, buf),
-                Operation::DelayNs(ns) => {
-                    embassy_time::block_for(embassy_time::Duration::from_nanos(*ns as u64));
-                    Ok(())
-                }
-            };
-            if let Err(err) = result {
-                self.cs.set_high();
-                return Err(SdSpiError::Spi(err));
-            }
+    pub(crate) fn new(
+        timestamp: u32,
+        latitude: f64,
+        longitude: f64,
+        altitude_m: f32,
+        pressure_pa: Option<f32>,
+    ) -> Self {
+        Self {
+            timestamp,
+            latitude_scaled_1e7: round_f64(latitude * 1e7) as i32,
+            longitude_scaled_1e7: round_f64(longitude * 1e7) as i32,
+            altitude_m_scaled_1e1: round_f32(altitude_m * 10.0) as i32,
+            pressure_pa: pressure_pa.map_or(0, |pa| round_f32(pa) as i32),
         }
+    }
 
-        if let Err(err) = SpiBus::flush(&mut self.spi) {
-            self.cs.set_high();
-            return Err(SdSpiError::Spi(err));
+    /// Block fields in encoding order: V3 keeps 1e7 coordinates and adds the
+    /// pressure, V1 rounds the coordinates to 1e5.
+    fn fields(&self, v3: bool) -> [i32; 5] {
+        if v3 {
+            [
+                self.timestamp as i32,
+                self.latitude_scaled_1e7,
+                self.longitude_scaled_1e7,
+                self.altitude_m_scaled_1e1,
+                self.pressure_pa,
+            ]
+        } else {
+            [
+                self.timestamp as i32,
+                div_round(self.latitude_scaled_1e7, 100),
+                div_round(self.longitude_scaled_1e7, 100),
+                self.altitude_m_scaled_1e1,
+                0,
+            ]
         }
-
-        self.cs.set_high();
-        Ok(())
     }
 }
 
-#[derive(Clone, Copy, Default)]
-struct GpxPointInternal {
-    timestamp: u32,
-    latitude_scaled_1e5: i32,
-    longitude_scaled_1e5: i32,
-    altitude_m_scaled_1e1: i32,
-}
-
-struct GpsDataEncoder {
+/// Delta encoder for `.gpz` logs, also used for the live BLE stream.
+///
+/// Points with a pressure are written as V3 blocks, others as V1; a change
+/// between the two starts a new full block.
+pub(crate) struct GpsDataEncoder {
     buffer: [u8; ENCODER_BUFFER_SIZE],
     buffer_len: usize,
     previous_point: GpxPointInternal,
+    previous_v3: bool,
     full_block_interval: usize,
     points_since_last_full_block: usize,
     is_first_point: bool,
+    /// Label of the last activity block written, `NO_ACTIVITY` before any.
+    activity: u8,
+    /// `(mode, quality)` of the last fix block written, `NO_FIX` before any.
+    fix: (u8, u8),
 }
 
+/// No activity block written yet; not a valid label.
+const NO_ACTIVITY: u8 = 0xFF;
+/// No fix block written yet; not a valid GSA mode.
+const NO_FIX: (u8, u8) = (0xFF, 0xFF);
+
 impl GpsDataEncoder {
-    fn new(full_block_interval: usize) -> Self {
+    pub(crate) const fn new(full_block_interval: usize) -> Self {
         Self {
             buffer: [0; ENCODER_BUFFER_SIZE],
             buffer_len: 0,
-            previous_point: GpxPointInternal::default(),
-            full_block_interval: full_block_interval.max(1),
+            previous_point: GpxPointInternal::ZERO,
+            previous_v3: false,
+            full_block_interval: if full_block_interval == 0 {
+                1
+            } else {
+                full_block_interval
+            },
             points_since_last_full_block: 0,
             is_first_point: true,
+            activity: NO_ACTIVITY,
+            fix: NO_FIX,
         }
     }
 
-    fn clear(&mut self) {
+    pub(crate) fn clear(&mut self) {
         *self = Self::new(self.full_block_interval);
     }
 
-    fn buffer(&self) -> &[u8] {
+    pub(crate) fn buffer(&self) -> &[u8] {
         &self.buffer[..self.buffer_len]
     }
 
-    fn encode(&mut self, point: GpxPointInternal) -> usize {
+    pub(crate) fn encode(&mut self, point: GpxPointInternal) -> usize {
+        self.buffer_len = 0;
+        self.write_point(point);
+        self.buffer_len
+    }
+
+    /// As `encode`, preceded by an activity block when `activity` differs
+    /// from the last label written, and a fix block when `fix` (GSA mode,
+    /// GGA quality) differs from the last one. A cleared encoder has
+    /// written neither, so everything written after a clear starts with
+    /// both, unknown included.
+    pub(crate) fn encode_labelled(
+        &mut self,
+        point: GpxPointInternal,
+        activity: u8,
+        fix: (u8, u8),
+    ) -> usize {
         self.buffer_len = 0;
+        if activity != self.activity {
+            self.write_u8(HEADER_ACTIVITY);
+            self.write_u8(activity);
+            self.activity = activity;
+        }
+        if fix != self.fix {
+            self.write_u8(HEADER_FIX);
+            self.write_u8(fix.0);
+            self.write_u8(fix.1);
+            self.fix = fix;
+        }
+        self.write_point(point);
+        self.buffer_len
+    }
+
+    fn write_point(&mut self, point: GpxPointInternal) {
+        let v3 = point.pressure_pa != 0;
+        let field_count = if v3 { 5 } else { 4 };
+        let fields = point.fields(v3);
         let mut use_full = false;
 
-        if self.is_first_point {
+        if self.is_first_point || v3 != self.previous_v3 {
             use_full = true;
         } else if self.full_block_interval == 1 {
             use_full = true;
@@ -1222,54 +3572,35 @@ impl GpsDataEncoder {
         }
 
         if use_full {
-            self.write_u8(0xFF);
+            self.write_u8(if v3 { 0xFD } else { 0xFF });
             self.write_u32_le(point.timestamp);
-            self.write_i32_le(point.latitude_scaled_1e5);
-            self.write_i32_le(point.longitude_scaled_1e5);
-            self.write_i32_le(point.altitude_m_scaled_1e1);
+            for &field in &fields[1..field_count] {
+                self.write_i32_le(field);
+            }
             self.points_since_last_full_block = 0;
             self.is_first_point = false;
         } else {
-            let delta_timestamp = point.timestamp as i32 - self.previous_point.timestamp as i32;
-            let delta_latitude =
-                point.latitude_scaled_1e5 - self.previous_point.latitude_scaled_1e5;
-            let delta_longitude =
-                point.longitude_scaled_1e5 - self.previous_point.longitude_scaled_1e5;
-            let delta_altitude =
-                point.altitude_m_scaled_1e1 - self.previous_point.altitude_m_scaled_1e1;
-
-            let mut header = 0u8;
-            if delta_timestamp != 0 {
-                header |= 1 << 3;
-            }
-            if delta_latitude != 0 {
-                header |= 1 << 2;
-            }
-            if delta_longitude != 0 {
-                header |= 1 << 1;
-            }
-            if delta_altitude != 0 {
-                header |= 1 << 0;
+            let previous = self.previous_point.fields(v3);
+            let mut deltas = [0i32; 5];
+            let mut header = if v3 { 0x20 } else { 0x00 };
+            for (i, delta) in deltas.iter_mut().enumerate().take(field_count) {
+                *delta = fields[i].wrapping_sub(previous[i]);
+                if *delta != 0 {
+                    header |= 1 << (field_count - 1 - i);
+                }
             }
 
             self.write_u8(header);
-            if delta_timestamp != 0 {
-                self.write_varint_s32(delta_timestamp);
-            }
-            if delta_latitude != 0 {
-                self.write_varint_s32(delta_latitude);
-            }
-            if delta_longitude != 0 {
-                self.write_varint_s32(delta_longitude);
-            }
-            if delta_altitude != 0 {
-                self.write_varint_s32(delta_altitude);
+            for &delta in &deltas[..field_count] {
+                if delta != 0 {
+                    self.write_varint_s32(delta);
+                }
             }
             self.points_since_last_full_block += 1;
         }
 
         self.previous_point = point;
-        self.buffer_len
+        self.previous_v3 = v3;
     }
 
     fn write_u8(&mut self, value: u8) {
@@ -1399,6 +3730,16 @@ fn bytes_to_str(bytes: &[u8]) -> &str {
     core::str::from_utf8(bytes).unwrap_or("")
 }
 
+fn date_parts(date: u32) -> Option<(u16, u8, u8)> {
+    if date == 0 {
+        return None;
+    }
+    let year = (date / 10000) as u16;
+    let month = ((date / 100) % 100) as u8;
+    let day = (date % 100) as u8;
+    Some((year, month, day))
+}
+
 fn unix_to_date(timestamp: u32) -> Option<(u16, u8, u8)> {
     let mut days = timestamp / 86_400;
     let mut year: u16 = 1970;
@@ -1442,3 +3783,9 @@ fn round_f64(value: f64) -> f64 {
 fn round_f32(value: f32) -> f32 {
     roundf(value)
 }
+
+/// `value / divisor` rounded half away from zero, like `round`.
+fn div_round(value: i32, divisor: i32) -> i32 {
+    let half = if value < 0 { -divisor / 2 } else { divisor / 2 };
+    (value + half) / divisor
+}
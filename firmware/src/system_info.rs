@@ -1,6 +1,12 @@
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_sync::mutex::Mutex;
 
+use crate::activity::Activity;
+use crate::build_info::{BuildInfo, FIRMWARE_VERSION_FIELD_LEN};
+use crate::fuel_gauge::ChargeState;
+use crate::sky_view::SkyView;
+use crate::status_schema;
+
 #[repr(u8)]
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum GpsState {
@@ -27,6 +33,9 @@ pub struct SystemInfo {
     pub hdop: f32,
     pub speed: f32,
     pub course: f32,
+    /// Smoothed course for the compass page, -1 before the first one; see
+    /// `heading`.
+    pub heading: f32,
     pub year: u16,
     pub month: u8,
     pub day: u8,
@@ -40,8 +49,31 @@ pub struct SystemInfo {
     pub is_stationary: bool,
     pub keep_alive_remaining_s: u16,
     pub battery_percent: u8,
+    /// From VBUS and the battery voltage, see `fuel_gauge`.
+    pub charge_state: ChargeState,
+    /// Minutes at the average load, `u16::MAX` when unknown or charging.
+    pub time_to_empty_min: u16,
     pub temperature_c: f32,
     pub pressure_pa: f32,
+    /// GSA fix mode: 0 = unknown, 1 = no fix, 2 = 2D, 3 = 3D.
+    pub fix_mode: u8,
+    /// GGA fix quality: 0 = invalid, 1 = GPS, 2 = DGPS, 4/5 = RTK, ...
+    pub fix_quality: u8,
+    /// Several GPX log writes in a row failed; points are being lost.
+    pub logging_degraded: bool,
+    /// The SD card stopped answering; points are held in RAM until it is back.
+    pub sd_card_missing: bool,
+    /// NMEA and CASIC time disagree, see `time_check`.
+    pub time_inconsistent: bool,
+    /// Steps counted on `steps_date` (YYYYMMDD, 0 before the clock is known).
+    pub steps_today: u32,
+    pub steps_date: u32,
+    /// Label of the track being logged, see `activity`.
+    pub activity: Activity,
+    /// Satellites in view from GSV, for the Satellites page.
+    pub sky: SkyView,
+    /// Fixed at boot.
+    pub build: BuildInfo,
 }
 
 impl SystemInfo {
@@ -54,6 +86,7 @@ impl SystemInfo {
             hdop: 99.9,
             speed: 0.0,
             course: 0.0,
+            heading: -1.0,
             year: 0,
             month: 0,
             day: 0,
@@ -67,8 +100,20 @@ impl SystemInfo {
             is_stationary: false,
             keep_alive_remaining_s: 0,
             battery_percent: 0,
+            charge_state: ChargeState::Discharging,
+            time_to_empty_min: u16::MAX,
             temperature_c: 0.0,
             pressure_pa: 0.0,
+            fix_mode: 0,
+            fix_quality: 0,
+            logging_degraded: false,
+            sd_card_missing: false,
+            time_inconsistent: false,
+            steps_today: 0,
+            steps_date: 0,
+            activity: Activity::Unknown,
+            sky: SkyView::new(),
+            build: BuildInfo::unknown(),
         }
     }
 }
@@ -76,8 +121,15 @@ impl SystemInfo {
 pub static SYSTEM_INFO: Mutex<CriticalSectionRawMutex, SystemInfo> =
     Mutex::new(SystemInfo::new());
 
-pub const SYSTEM_INFO_VERSION: u8 = 2;
-pub const SYSTEM_INFO_SERIALIZED_LEN: usize = 63;
+pub const SYSTEM_INFO_VERSION: u8 = 6;
+pub const SYSTEM_INFO_SERIALIZED_LEN: usize = 104;
+// A new field needs a `status_schema::SCHEMA` entry as well.
+const _: () = assert!(status_schema::SNAPSHOT_LEN == SYSTEM_INFO_SERIALIZED_LEN);
+
+/// V5 `statusFlags` bits.
+const STATUS_LOGGING_DEGRADED: u8 = 0x01;
+const STATUS_SD_CARD_MISSING: u8 = 0x02;
+const STATUS_TIME_INCONSISTENT: u8 = 0x04;
 
 pub fn serialize_system_info(
     info: &SystemInfo,
@@ -85,7 +137,7 @@ pub fn serialize_system_info(
 ) -> usize {
     let mut offset = 0;
 
-    // V2 format: version byte + 50 legacy bytes + keep_alive + new fields
+    // V6 format: version byte + 50 legacy bytes + V2-V6 fields
     out[offset] = SYSTEM_INFO_VERSION;
     offset += 1;
 
@@ -137,5 +189,47 @@ pub fn serialize_system_info(
     out[offset..offset + 4].copy_from_slice(&info.pressure_pa.to_le_bytes());
     offset += 4;
 
+    // V3 new fields
+    out[offset] = info.fix_mode;
+    offset += 1;
+    out[offset] = info.fix_quality;
+    offset += 1;
+
+    // V4 new fields
+    let build = &info.build;
+    let version = build.firmware_version.as_bytes();
+    let version_len = version.len().min(FIRMWARE_VERSION_FIELD_LEN);
+    out[offset..offset + FIRMWARE_VERSION_FIELD_LEN].fill(0);
+    out[offset..offset + version_len].copy_from_slice(&version[..version_len]);
+    offset += FIRMWARE_VERSION_FIELD_LEN;
+    out[offset..offset + 4].copy_from_slice(&build.capabilities.to_le_bytes());
+    offset += 4;
+    out[offset..offset + 2].copy_from_slice(&build.softdevice_fwid.to_le_bytes());
+    offset += 2;
+    out[offset..offset + 4].copy_from_slice(&build.bootloader_version.to_le_bytes());
+    offset += 4;
+    out[offset] = u8::from(build.debug_protected);
+    offset += 1;
+
+    // V5 new fields
+    let mut status_flags = 0;
+    if info.logging_degraded {
+        status_flags |= STATUS_LOGGING_DEGRADED;
+    }
+    if info.sd_card_missing {
+        status_flags |= STATUS_SD_CARD_MISSING;
+    }
+    if info.time_inconsistent {
+        status_flags |= STATUS_TIME_INCONSISTENT;
+    }
+    out[offset] = status_flags;
+    offset += 1;
+
+    // V6 new fields
+    out[offset] = info.charge_state as u8;
+    offset += 1;
+    out[offset..offset + 2].copy_from_slice(&info.time_to_empty_min.to_le_bytes());
+    offset += 2;
+
     offset
 }
@@ -0,0 +1,164 @@
+//! Cross-check of NMEA time against the receiver's CASIC `NAV-TIMEUTC`.
+//!
+//! A single glitched RMC sentence can carry a wrong date, and everything
+//! downstream trusts the GPS clock: log file rotation, session boundaries,
+//! the Find My key schedule. The receiver also reports UTC in binary, so
+//! the two are compared and the one that agrees with itself wins.
+//!
+//! # Design
+//!
+//! - Each source is projected forward on the uptime clock from its last
+//!   sample. A source is steady when its last two samples advanced like
+//!   uptime did, within `STEADY_TOLERANCE_MS`.
+//! - NMEA more than `MAX_DISAGREE_S` from the projected CASIC time is an
+//!   inconsistency. The steady source is preferred; when both are steady
+//!   NMEA is kept (it is the normal source, and a constant offset is more
+//!   likely a leap-second quirk than a glitch), when neither is the time is
+//!   unreliable.
+//! - CASIC samples older than `MAX_CASIC_AGE_MS` are not compared, so a
+//!   receiver without `NAV-TIMEUTC` output changes nothing.
+
+const STEADY_TOLERANCE_MS: i64 = 1_500;
+const MAX_DISAGREE_S: i64 = 2;
+const MAX_CASIC_AGE_MS: u64 = 5_000;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TimeSource {
+    Nmea,
+    Casic,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Verdict {
+    /// The sources agree, or there is nothing to compare against.
+    Consistent,
+    /// The sources disagree; use this one.
+    Prefer(TimeSource),
+    /// They disagree and neither is steady: use no time at all.
+    Unreliable,
+}
+
+#[derive(Clone, Copy)]
+struct Sample {
+    unix: u32,
+    uptime_ms: u64,
+}
+
+#[derive(Clone, Copy)]
+struct Track {
+    last: Option<Sample>,
+    steady: bool,
+}
+
+impl Track {
+    const fn new() -> Self {
+        Self {
+            last: None,
+            steady: false,
+        }
+    }
+
+    fn push(&mut self, unix: u32, uptime_ms: u64) {
+        self.steady = self.last.is_some_and(|last| {
+            let clock_ms = (unix as i64 - last.unix as i64) * 1000;
+            let uptime_ms = uptime_ms.saturating_sub(last.uptime_ms) as i64;
+            (clock_ms - uptime_ms).abs() <= STEADY_TOLERANCE_MS
+        });
+        self.last = Some(Sample { unix, uptime_ms });
+    }
+
+    fn project(&self, uptime_ms: u64) -> Option<u32> {
+        let last = self.last?;
+        let elapsed_s = uptime_ms.saturating_sub(last.uptime_ms) / 1000;
+        Some(last.unix.saturating_add(elapsed_s as u32))
+    }
+}
+
+pub struct TimeCheck {
+    nmea: Track,
+    casic: Track,
+}
+
+impl TimeCheck {
+    pub const fn new() -> Self {
+        Self {
+            nmea: Track::new(),
+            casic: Track::new(),
+        }
+    }
+
+    /// Forget both sources, e.g. when the receiver is powered off.
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    pub fn note_casic(&mut self, unix: u32, uptime_ms: u64) {
+        self.casic.push(unix, uptime_ms);
+    }
+
+    /// CASIC time projected to `uptime_ms`, if recent enough to trust.
+    pub fn casic_time(&self, uptime_ms: u64) -> Option<u32> {
+        let last = self.casic.last?;
+        if uptime_ms.saturating_sub(last.uptime_ms) > MAX_CASIC_AGE_MS {
+            return None;
+        }
+        self.casic.project(uptime_ms)
+    }
+
+    /// Judge an NMEA time against the CASIC one.
+    pub fn check_nmea(&mut self, unix: u32, uptime_ms: u64) -> Verdict {
+        self.nmea.push(unix, uptime_ms);
+        let Some(casic) = self.casic_time(uptime_ms) else {
+            return Verdict::Consistent;
+        };
+        if (unix as i64 - casic as i64).abs() <= MAX_DISAGREE_S {
+            return Verdict::Consistent;
+        }
+        match (self.nmea.steady, self.casic.steady) {
+            (true, _) => Verdict::Prefer(TimeSource::Nmea),
+            (false, true) => Verdict::Prefer(TimeSource::Casic),
+            (false, false) => Verdict::Unreliable,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const T0: u32 = 1_718_000_000;
+
+    #[test]
+    fn a_glitched_sentence_loses_to_casic() {
+        let mut check = TimeCheck::new();
+        for i in 0..3u32 {
+            check.note_casic(T0 + i, i as u64 * 1000);
+            assert_eq!(
+                check.check_nmea(T0 + i, i as u64 * 1000 + 100),
+                Verdict::Consistent
+            );
+        }
+        check.note_casic(T0 + 3, 3000);
+        // One RMC a day off.
+        assert_eq!(
+            check.check_nmea(T0 + 3 + 86_400, 3100),
+            Verdict::Prefer(TimeSource::Casic)
+        );
+        assert_eq!(check.casic_time(3100), Some(T0 + 3));
+        // Back to normal.
+        check.note_casic(T0 + 4, 4000);
+        assert_eq!(check.check_nmea(T0 + 4, 4100), Verdict::Consistent);
+    }
+
+    #[test]
+    fn steady_nmea_wins_and_stale_casic_is_ignored() {
+        let mut check = TimeCheck::new();
+        check.note_casic(T0 + 100, 0);
+        check.check_nmea(T0, 0);
+        assert_eq!(
+            check.check_nmea(T0 + 1, 1000),
+            Verdict::Prefer(TimeSource::Nmea)
+        );
+        assert_eq!(check.check_nmea(T0 + 10, 10_000), Verdict::Consistent);
+    }
+}
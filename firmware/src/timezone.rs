@@ -6,6 +6,16 @@
 //!   - tz_rle.bin: (count, tz_id)[] RLE encoded grid data
 //!   - tz_transition_index.bin: per-tz_id base offset + transition index
 //!   - tz_transitions.bin: UTC transition timestamps and offsets
+//!
+//! The location-derived zone can be overridden at runtime (see `TzSettings`),
+//! either with a fixed UTC offset or a pinned zone from the same database.
+//!
+//! `TzCache` keeps the last few grid cells it resolved, least recently used
+//! out first, each with its offset and the span of time that offset holds:
+//! stepping into another cell looks the zone up again, and a DST change
+//! takes effect on time without moving.
+
+use core::sync::atomic::{AtomicU16, AtomicU32, Ordering};
 
 #[cfg(feature = "host-test")]
 const TZ_ROW_INDEX: &[u8] = include_bytes!(concat!(env!("TZ_DATA_DIR"), "/tz_row_index.bin"));
@@ -88,15 +98,26 @@ fn transition_at(index: usize) -> Option<(u32, i16)> {
     Some((ts, offset))
 }
 
-fn lookup_tz_id(lat: f32, lon: f32) -> u16 {
-    if !(lat >= -90.0 && lat < 90.0 && lon >= -180.0 && lon < 180.0) {
-        return 0;
+/// Grid cell of a coordinate, `row * COLS + col`; `None` off the grid.
+fn grid_cell(lat: f32, lon: f32) -> Option<u16> {
+    if !((-90.0..90.0).contains(&lat) && (-180.0..180.0).contains(&lon)) {
+        return None;
     }
     let lat_idx = (lat + 90.0) as usize;
     let lon_idx = (lon + 180.0) as usize;
     if lat_idx >= ROWS || lon_idx >= COLS {
-        return 0;
+        return None;
     }
+    Some((lat_idx * COLS + lon_idx) as u16)
+}
+
+fn lookup_tz_id(lat: f32, lon: f32) -> u16 {
+    grid_cell(lat, lon).map_or(0, tz_id_for_cell)
+}
+
+fn tz_id_for_cell(cell: u16) -> u16 {
+    let lat_idx = cell as usize / COLS;
+    let lon_idx = cell as usize % COLS;
     let row_offset_pos = lat_idx * ROW_INDEX_ENTRY_LEN;
     if row_offset_pos + ROW_INDEX_ENTRY_LEN > TZ_ROW_INDEX.len() {
         return 0;
@@ -128,20 +149,50 @@ fn lookup_tz_id(lat: f32, lon: f32) -> u16 {
 }
 
 fn lookup_offset_minutes_for_tz(tz_id: u16, utc_timestamp: u32) -> i16 {
+    lookup_offset_span(tz_id, utc_timestamp).minutes
+}
+
+/// An offset and the UTC timestamps it holds for, `from..until`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct OffsetSpan {
+    minutes: i16,
+    from: u32,
+    until: u32,
+}
+
+impl OffsetSpan {
+    const fn always(minutes: i16) -> Self {
+        Self {
+            minutes,
+            from: 0,
+            until: u32::MAX,
+        }
+    }
+
+    fn contains(&self, timestamp: u32) -> bool {
+        (self.from..self.until).contains(&timestamp)
+    }
+}
+
+fn lookup_offset_span(tz_id: u16, utc_timestamp: u32) -> OffsetSpan {
     let Some(entry) = tz_index_entry(tz_id) else {
-        return 0;
+        return OffsetSpan::always(0);
     };
+    let base = OffsetSpan::always(entry.base_offset);
     let count = entry.transition_count as usize;
     if count == 0 {
-        return entry.base_offset;
+        return base;
     }
 
     let first_index = entry.first_transition as usize;
     let Some((first_ts, _)) = transition_at(first_index) else {
-        return entry.base_offset;
+        return base;
     };
     if utc_timestamp < first_ts {
-        return entry.base_offset;
+        return OffsetSpan {
+            until: first_ts,
+            ..base
+        };
     }
 
     let mut lo = 0usize;
@@ -150,7 +201,7 @@ fn lookup_offset_minutes_for_tz(tz_id: u16, utc_timestamp: u32) -> i16 {
         let mid = lo + (hi - lo) / 2;
         let idx = first_index + mid;
         let Some((ts, _)) = transition_at(idx) else {
-            return entry.base_offset;
+            return base;
         };
         if ts <= utc_timestamp {
             lo = mid;
@@ -160,9 +211,15 @@ fn lookup_offset_minutes_for_tz(tz_id: u16, utc_timestamp: u32) -> i16 {
     }
 
     let idx = first_index + lo;
-    transition_at(idx)
-        .map(|(_, offset)| offset)
-        .unwrap_or(entry.base_offset)
+    let Some((from, minutes)) = transition_at(idx) else {
+        return base;
+    };
+    let next = (lo + 1 < count).then(|| transition_at(idx + 1)).flatten();
+    OffsetSpan {
+        minutes,
+        from,
+        until: next.map_or(u32::MAX, |(ts, _)| ts),
+    }
 }
 
 /// UTC offset with hours and minutes parts.
@@ -196,28 +253,151 @@ impl UtcOffset {
     }
 }
 
-/// Cached timezone lookup to avoid recalculating when position hasn't changed much.
+/// Manual override of the location-derived timezone.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TzOverride {
+    /// Derive the zone from the current GPS position.
+    Auto,
+    /// Fixed offset from UTC in minutes, no DST.
+    FixedOffset(i16),
+    /// Pinned zone id from the embedded grid, DST rules still apply.
+    Zone(u16),
+}
+
+/// Persisted timezone settings (`TZ_SETTINGS_LEN` bytes on SD).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TzSettings {
+    pub tz_override: TzOverride,
+    /// Rotate daily log files at local midnight instead of UTC midnight.
+    pub local_midnight_rotation: bool,
+}
+
+/// Serialized size: [mode: 1B] [flags: 1B] [fixed_offset: i16 LE] [tz_id: u16 LE].
+pub const TZ_SETTINGS_LEN: usize = 6;
+
+const TZ_MODE_AUTO: u8 = 0;
+const TZ_MODE_FIXED_OFFSET: u8 = 1;
+const TZ_MODE_ZONE: u8 = 2;
+const TZ_FLAG_LOCAL_MIDNIGHT: u8 = 0x01;
+/// Offsets in the wild range from UTC-12 to UTC+14.
+const MAX_FIXED_OFFSET_MINUTES: i16 = 14 * 60;
+
+impl TzSettings {
+    pub const fn new() -> Self {
+        Self {
+            tz_override: TzOverride::Auto,
+            local_midnight_rotation: false,
+        }
+    }
+
+    pub fn to_bytes(self) -> [u8; TZ_SETTINGS_LEN] {
+        let (mode, fixed_offset, tz_id) = match self.tz_override {
+            TzOverride::Auto => (TZ_MODE_AUTO, 0i16, 0u16),
+            TzOverride::FixedOffset(minutes) => (TZ_MODE_FIXED_OFFSET, minutes, 0),
+            TzOverride::Zone(tz_id) => (TZ_MODE_ZONE, 0, tz_id),
+        };
+        let flags = if self.local_midnight_rotation {
+            TZ_FLAG_LOCAL_MIDNIGHT
+        } else {
+            0
+        };
+        let offset_bytes = fixed_offset.to_le_bytes();
+        let tz_bytes = tz_id.to_le_bytes();
+        [mode, flags, offset_bytes[0], offset_bytes[1], tz_bytes[0], tz_bytes[1]]
+    }
+
+    pub fn from_bytes(data: &[u8; TZ_SETTINGS_LEN]) -> Option<Self> {
+        let fixed_offset = i16::from_le_bytes([data[2], data[3]]);
+        let tz_id = u16::from_le_bytes([data[4], data[5]]);
+        let tz_override = match data[0] {
+            TZ_MODE_AUTO => TzOverride::Auto,
+            TZ_MODE_FIXED_OFFSET => {
+                if fixed_offset.abs() > MAX_FIXED_OFFSET_MINUTES {
+                    return None;
+                }
+                TzOverride::FixedOffset(fixed_offset)
+            }
+            TZ_MODE_ZONE => {
+                tz_index_entry(tz_id)?;
+                TzOverride::Zone(tz_id)
+            }
+            _ => return None,
+        };
+        Some(Self {
+            tz_override,
+            local_midnight_rotation: (data[1] & TZ_FLAG_LOCAL_MIDNIGHT) != 0,
+        })
+    }
+}
+
+// Packed settings word: [mode: 8][flags: 8][fixed_offset: 16]; zone id kept separately.
+static TZ_SETTINGS_WORD: AtomicU32 = AtomicU32::new(0);
+static TZ_SETTINGS_ZONE: AtomicU16 = AtomicU16::new(0);
+
+/// Install timezone settings for all `TzCache` users.
+pub fn set_settings(settings: TzSettings) {
+    let bytes = settings.to_bytes();
+    TZ_SETTINGS_ZONE.store(u16::from_le_bytes([bytes[4], bytes[5]]), Ordering::Relaxed);
+    let word = ((bytes[0] as u32) << 24)
+        | ((bytes[1] as u32) << 16)
+        | u16::from_le_bytes([bytes[2], bytes[3]]) as u32;
+    TZ_SETTINGS_WORD.store(word, Ordering::Release);
+}
+
+/// Current timezone settings.
+pub fn settings() -> TzSettings {
+    let word = TZ_SETTINGS_WORD.load(Ordering::Acquire);
+    let zone = TZ_SETTINGS_ZONE.load(Ordering::Relaxed).to_le_bytes();
+    let offset = (word as u16).to_le_bytes();
+    let bytes = [
+        (word >> 24) as u8,
+        (word >> 16) as u8,
+        offset[0],
+        offset[1],
+        zone[0],
+        zone[1],
+    ];
+    TzSettings::from_bytes(&bytes).unwrap_or(TzSettings::new())
+}
+
+/// True when local time does not depend on having a GPS position.
+pub fn has_override() -> bool {
+    settings().tz_override != TzOverride::Auto
+}
+
+/// Resolve the grid zone id for a coordinate, e.g. to pin the zone of a home location.
+pub fn zone_id_for_location(lat: f32, lon: f32) -> u16 {
+    lookup_tz_id(lat, lon)
+}
+
+/// Grid cells remembered by a `TzCache`.
+const TZ_CACHE_ENTRIES: usize = 4;
+
+#[derive(Clone, Copy)]
+struct TzCacheEntry {
+    /// `None` for coordinates off the grid.
+    cell: Option<u16>,
+    tz_id: u16,
+    span: OffsetSpan,
+    last_used: u32,
+}
+
+/// Cached timezone lookup, so an unchanged grid cell and offset cost nothing.
 pub struct TzCache {
-    last_lat: f32,
-    last_lon: f32,
-    cached_tz_id: u16,
-    valid: bool,
+    entries: [Option<TzCacheEntry>; TZ_CACHE_ENTRIES],
+    uses: u32,
 }
 
 impl TzCache {
-    /// Distance threshold in degrees (~55km) before recalculating.
-    const THRESHOLD: f32 = 0.5;
-
     pub const fn new() -> Self {
         Self {
-            last_lat: 0.0,
-            last_lon: 0.0,
-            cached_tz_id: 0,
-            valid: false,
+            entries: [None; TZ_CACHE_ENTRIES],
+            uses: 0,
         }
     }
 
     /// Get UTC offset for coordinates and UTC date/time.
+    #[allow(clippy::too_many_arguments)]
     pub fn get_offset(
         &mut self,
         lat: f32,
@@ -233,24 +413,73 @@ impl TzCache {
         else {
             return UtcOffset::from_minutes(0);
         };
+        self.get_offset_at(lat, lon, timestamp)
+    }
 
-        if !self.valid
-            || (lat - self.last_lat).abs() > Self::THRESHOLD
-            || (lon - self.last_lon).abs() > Self::THRESHOLD
-        {
-            self.cached_tz_id = lookup_tz_id(lat, lon);
-            self.last_lat = lat;
-            self.last_lon = lon;
-            self.valid = true;
+    /// Get UTC offset for coordinates and a UTC unix timestamp.
+    pub fn get_offset_at(&mut self, lat: f32, lon: f32, timestamp: u32) -> UtcOffset {
+        self.offset_with_settings(&settings(), lat, lon, timestamp)
+    }
+
+    fn offset_with_settings(
+        &mut self,
+        settings: &TzSettings,
+        lat: f32,
+        lon: f32,
+        timestamp: u32,
+    ) -> UtcOffset {
+        match settings.tz_override {
+            TzOverride::Auto => {}
+            TzOverride::FixedOffset(minutes) => return UtcOffset::from_minutes(minutes),
+            TzOverride::Zone(tz_id) => {
+                return UtcOffset::from_minutes(lookup_offset_minutes_for_tz(tz_id, timestamp));
+            }
         }
 
-        UtcOffset::from_minutes(lookup_offset_minutes_for_tz(self.cached_tz_id, timestamp))
+        self.uses = self.uses.wrapping_add(1);
+        let uses = self.uses;
+        let cell = grid_cell(lat, lon);
+        let cached = self.entries.iter_mut().flatten().find(|e| e.cell == cell);
+        let minutes = match cached {
+            Some(entry) => {
+                if !entry.span.contains(timestamp) {
+                    entry.span = lookup_offset_span(entry.tz_id, timestamp);
+                }
+                entry.last_used = uses;
+                entry.span.minutes
+            }
+            None => self.insert(cell, timestamp).span.minutes,
+        };
+        UtcOffset::from_minutes(minutes)
+    }
+
+    /// Look `cell` up into an empty entry, or over the least recently used.
+    fn insert(&mut self, cell: Option<u16>, timestamp: u32) -> TzCacheEntry {
+        let uses = self.uses;
+        let age = |entry: &Option<TzCacheEntry>| {
+            entry.map_or(u32::MAX, |e| uses.wrapping_sub(e.last_used))
+        };
+        let mut slot = 0;
+        for (index, entry) in self.entries.iter().enumerate() {
+            if age(entry) > age(&self.entries[slot]) {
+                slot = index;
+            }
+        }
+        let tz_id = cell.map_or(0, tz_id_for_cell);
+        let entry = TzCacheEntry {
+            cell,
+            tz_id,
+            span: lookup_offset_span(tz_id, timestamp),
+            last_used: uses,
+        };
+        self.entries[slot] = Some(entry);
+        entry
     }
 
     /// Invalidate cache (force recalculation on next lookup).
     #[allow(dead_code)]
     pub fn invalidate(&mut self) {
-        self.valid = false;
+        self.entries = [None; TZ_CACHE_ENTRIES];
     }
 }
 
@@ -262,7 +491,7 @@ pub fn date_time_to_unix_timestamp(
     minute: u8,
     second: u8,
 ) -> Option<u32> {
-    if year < 1970 || year > 2100 {
+    if !(1970..=2100).contains(&year) {
         return None;
     }
     if month == 0 || month > 12 {
@@ -279,7 +508,8 @@ pub fn date_time_to_unix_timestamp(
         base_year_minus_one / 4 - base_year_minus_one / 100 + base_year_minus_one / 400;
     let mut days = (year as u32 - 1970) * 365 + (leap_years - base_leaps);
 
-    let is_leap = (year % 4 == 0 && year % 100 != 0) || (year % 400 == 0);
+    let is_leap =
+        (year.is_multiple_of(4) && !year.is_multiple_of(100)) || year.is_multiple_of(400);
     let mut days_in_month = [0u8, 31, 28, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31];
     if is_leap {
         days_in_month[2] = 29;
@@ -302,7 +532,10 @@ pub fn date_time_to_unix_timestamp(
 
 #[cfg(test)]
 mod tests {
-    use super::{date_time_to_unix_timestamp, lookup_offset_minutes_for_tz, lookup_tz_id};
+    use super::{
+        date_time_to_unix_timestamp, grid_cell, lookup_offset_minutes_for_tz, lookup_tz_id,
+        TzCache, TzOverride, TzSettings, TZ_CACHE_ENTRIES,
+    };
 
     const SECS_PER_DAY: u32 = 86_400;
 
@@ -391,4 +624,118 @@ mod tests {
         assert_eq!(winter, 330);
         assert_eq!(summer, 330);
     }
+
+    #[test]
+    fn settings_round_trip() {
+        let cases = [
+            TzSettings::new(),
+            TzSettings {
+                tz_override: TzOverride::FixedOffset(-570),
+                local_midnight_rotation: true,
+            },
+            TzSettings {
+                tz_override: TzOverride::Zone(lookup_tz_id(39.9, 116.4)),
+                local_midnight_rotation: false,
+            },
+        ];
+        for settings in cases {
+            assert_eq!(TzSettings::from_bytes(&settings.to_bytes()), Some(settings));
+        }
+    }
+
+    #[test]
+    fn settings_reject_invalid_bytes() {
+        assert!(TzSettings::from_bytes(&[9, 0, 0, 0, 0, 0]).is_none());
+        let too_far = (15i16 * 60).to_le_bytes();
+        assert!(TzSettings::from_bytes(&[1, 0, too_far[0], too_far[1], 0, 0]).is_none());
+        assert!(TzSettings::from_bytes(&[2, 0, 0, 0, 0xFF, 0xFF]).is_none());
+    }
+
+    #[test]
+    fn fixed_offset_ignores_location() {
+        let settings = TzSettings {
+            tz_override: TzOverride::FixedOffset(-180),
+            local_midnight_rotation: false,
+        };
+        let mut cache = TzCache::new();
+        let offset = cache.offset_with_settings(&settings, 39.9, 116.4, ts(2025, 7, 1, 12, 0, 0));
+        assert_eq!(offset.total_minutes, -180);
+    }
+
+    #[test]
+    fn pinned_zone_keeps_dst_rules() {
+        let settings = TzSettings {
+            tz_override: TzOverride::Zone(lookup_tz_id(40.7, -74.0)),
+            local_midnight_rotation: false,
+        };
+        let mut cache = TzCache::new();
+        let winter = cache.offset_with_settings(&settings, 39.9, 116.4, ts(2025, 1, 15, 12, 0, 0));
+        let summer = cache.offset_with_settings(&settings, 39.9, 116.4, ts(2025, 7, 1, 12, 0, 0));
+        assert_eq!(winter.total_minutes, -300);
+        assert_eq!(summer.total_minutes, -240);
+    }
+
+    impl TzCache {
+        fn auto_offset(&mut self, lat: f32, lon: f32, timestamp: u32) -> i16 {
+            let offset = self.offset_with_settings(&TzSettings::new(), lat, lon, timestamp);
+            offset.total_minutes
+        }
+
+        fn holds(&self, lat: f32, lon: f32) -> bool {
+            let cell = grid_cell(lat, lon);
+            self.entries.iter().flatten().any(|e| e.cell == cell)
+        }
+    }
+
+    #[test]
+    fn cache_follows_border_crossings() {
+        let winter = ts(2025, 1, 15, 12, 0, 0);
+        let mut cache = TzCache::new();
+        // Portugal to Spain and back, in steps well under a cell.
+        for step in (0..=8).chain((0..8).rev()) {
+            let lon = -6.4 + step as f32 * 0.1;
+            let expected = if lon < -6.0 { 0 } else { 60 };
+            assert_eq!(cache.auto_offset(41.5, lon, winter), expected, "{}", lon);
+        }
+        // Illinois to Indiana, a step of less than a tenth of a degree.
+        assert_eq!(cache.auto_offset(39.5, -88.05, winter), -360);
+        assert_eq!(cache.auto_offset(39.5, -87.95, winter), -300);
+        assert!(cache.holds(41.5, -6.5) && cache.holds(41.5, -5.5));
+
+        // One more cell than fits drops the least recently used.
+        cache.auto_offset(41.5, -6.5, winter);
+        cache.auto_offset(39.9, 116.4, winter);
+        assert!(!cache.holds(41.5, -5.5));
+        assert!(cache.holds(41.5, -6.5));
+        assert_eq!(cache.entries.iter().flatten().count(), TZ_CACHE_ENTRIES);
+        assert_eq!(cache.auto_offset(41.5, -5.5, winter), 60);
+        assert_eq!(cache.auto_offset(-91.0, 0.0, winter), 0);
+        cache.invalidate();
+        assert!(!cache.holds(41.5, -5.5));
+    }
+
+    #[test]
+    fn cache_follows_dst_changes_in_place() {
+        let mut cache = TzCache::new();
+        let mut new_york = |timestamp| cache.auto_offset(40.7, -74.0, timestamp);
+        // New York springs forward at 07:00 UTC on 9 March 2025.
+        assert_eq!(new_york(ts(2025, 3, 9, 6, 59, 59)), -300);
+        assert_eq!(new_york(ts(2025, 3, 9, 7, 0, 0)), -240);
+        // And falls back at 06:00 UTC on 2 November.
+        assert_eq!(new_york(ts(2025, 11, 2, 5, 59, 59)), -240);
+        assert_eq!(new_york(ts(2025, 11, 2, 6, 0, 0)), -300);
+        // A clock set back to before the change.
+        assert_eq!(new_york(ts(2025, 3, 9, 6, 0, 0)), -300);
+
+        // Sydney falls back at 03:00 local on 6 April 2025, 16:00 UTC the day
+        // before.
+        let mut sydney = |timestamp| cache.auto_offset(-33.9, 151.2, timestamp);
+        assert_eq!(sydney(ts(2025, 4, 5, 15, 59, 59)), 660);
+        assert_eq!(sydney(ts(2025, 4, 5, 16, 0, 0)), 600);
+        // Switching between the cached zones keeps each one's rules.
+        let july = ts(2025, 7, 1, 12, 0, 0);
+        assert_eq!(cache.auto_offset(40.7, -74.0, july), -240);
+        assert_eq!(cache.auto_offset(-33.9, 151.2, july), 600);
+        assert_eq!(cache.entries.iter().flatten().count(), 2);
+    }
 }
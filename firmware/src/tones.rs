@@ -0,0 +1,132 @@
+//! Tone patterns for the piezo buzzer.
+//!
+//! `sound` plays these on PWM0; this module holds the parts that do not
+//! touch the peripheral: the patterns and the duty cycle for a volume.
+//!
+//! # Design
+//!
+//! - A pattern is a list of `Tone`s, a frequency and how long to hold it;
+//!   a frequency of 0 is a rest.
+//! - Frequencies sit around 2.7-4 kHz, where small piezo discs resonate and
+//!   are loudest.
+//! - A ring plays one of `RING_PATTERNS` patterns, chosen with
+//!   `sound.ring_pattern`: some are easier to place by ear, some carry
+//!   further.
+//! - Alerts beep once for info, twice for a warning and three times for a
+//!   critical alert, like the LED.
+//! - Volume scales the duty cycle: a piezo is loudest at 50 %, so 100 %
+//!   volume is half the PWM period.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Tone {
+    /// Hz, `0` for a rest.
+    pub freq_hz: u16,
+    pub ms: u16,
+}
+
+const fn tone(freq_hz: u16, ms: u16) -> Tone {
+    Tone { freq_hz, ms }
+}
+
+/// Number of ring patterns, `sound.ring_pattern` 0 to this minus one.
+pub const RING_PATTERNS: u8 = 3;
+
+/// "Ring my device": a two-tone warble, repeated until stopped.
+const RING_WARBLE: &[Tone] = &[
+    tone(2700, 120),
+    tone(3400, 120),
+    tone(2700, 120),
+    tone(3400, 120),
+    tone(0, 400),
+];
+/// Three short beeps at the loudest frequency.
+const RING_BEEPS: &[Tone] = &[
+    tone(3400, 80),
+    tone(0, 120),
+    tone(3400, 80),
+    tone(0, 120),
+    tone(3400, 80),
+    tone(0, 520),
+];
+/// A rising sweep across the piezo's band.
+const RING_SWEEP: &[Tone] = &[
+    tone(2700, 80),
+    tone(3000, 80),
+    tone(3300, 80),
+    tone(3600, 80),
+    tone(4000, 160),
+    tone(0, 400),
+];
+
+const ALERT_INFO: &[Tone] = &[tone(3000, 100), tone(0, 200)];
+const ALERT_WARNING: &[Tone] = &[tone(3000, 150), tone(0, 150), tone(3000, 150), tone(0, 200)];
+const ALERT_CRITICAL: &[Tone] = &[
+    tone(3800, 300),
+    tone(0, 150),
+    tone(3800, 300),
+    tone(0, 150),
+    tone(3800, 300),
+    tone(0, 200),
+];
+
+/// Ring pattern `pattern`; out-of-range values play the warble.
+pub fn ring(pattern: u8) -> &'static [Tone] {
+    match pattern {
+        1 => RING_BEEPS,
+        2 => RING_SWEEP,
+        _ => RING_WARBLE,
+    }
+}
+
+/// Pattern for an alert severity, `0` = info to `2` = critical.
+pub fn alert(severity: u8) -> &'static [Tone] {
+    match severity {
+        0 => ALERT_INFO,
+        1 => ALERT_WARNING,
+        _ => ALERT_CRITICAL,
+    }
+}
+
+/// Compare value for `volume_pct` (0-100) with the counter running to
+/// `max_duty`.
+pub fn duty(max_duty: u16, volume_pct: u8) -> u16 {
+    let volume = volume_pct.min(100) as u32;
+    (max_duty as u32 * volume / 200) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn alerts_beep_once_per_severity_step() {
+        for severity in 0..3u8 {
+            let beeps = alert(severity).iter().filter(|tone| tone.freq_hz != 0);
+            assert_eq!(beeps.count(), severity as usize + 1);
+        }
+        assert_eq!(alert(7), alert(2));
+        assert_eq!(ring(0).iter().map(|tone| tone.ms as u32).sum::<u32>(), 880);
+        // Every pattern ends on a rest, so repeats stay apart.
+        assert_eq!(ring(0).last().map(|tone| tone.freq_hz), Some(0));
+    }
+
+    #[test]
+    fn ring_patterns_differ_and_end_on_a_rest() {
+        for pattern in 0..RING_PATTERNS {
+            assert_eq!(ring(pattern).last().map(|tone| tone.freq_hz), Some(0));
+            for other in 0..pattern {
+                assert_ne!(ring(pattern), ring(other));
+            }
+        }
+        assert_eq!(ring(RING_PATTERNS), ring(0));
+    }
+
+    #[test]
+    fn volume_scales_up_to_half_the_period() {
+        assert_eq!(duty(1000, 0), 0);
+        assert_eq!(duty(1000, 50), 250);
+        assert_eq!(duty(1000, 100), 500);
+        assert_eq!(duty(1000, 255), 500);
+        assert_eq!(duty(u16::MAX, 100), u16::MAX / 2);
+    }
+}
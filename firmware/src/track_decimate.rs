@@ -0,0 +1,226 @@
+//! Track decimation for quick previews.
+//!
+//! Works on the decoder's point stream, one point in and at most one point
+//! out, so a preview can be produced while the file is being read.
+//!
+//! - `EveryNth` keeps points `0, N, 2N, ...`.
+//! - `Tolerance` is an online Douglas-Peucker variant: points are buffered
+//!   after the last kept point until one of them strays more than the
+//!   tolerance from the segment to the newest point, then the point before
+//!   the newest is kept and becomes the new start. The buffer is bounded, so
+//!   long straight stretches still keep a point every `WINDOW - 1` points.
+//!
+//! Both modes always keep the first and last point.
+
+use crate::gpz::TrackPoint;
+use crate::stationary::cos_approx;
+
+const WINDOW: usize = 32;
+const METERS_PER_DEG: f64 = 111_320.0;
+const METERS_PER_E7: f64 = METERS_PER_DEG / 1e7;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecimateMode {
+    EveryNth(u16),
+    /// Maximum deviation in metres.
+    Tolerance(u16),
+}
+
+pub struct Decimator {
+    mode: DecimateMode,
+    /// Position within the current group of `N` points.
+    index: u32,
+    /// `window[0]` is the last kept point.
+    window: [TrackPoint; WINDOW],
+    len: usize,
+    /// Metres per 1e-7 degree of longitude at the start point.
+    lon_scale: f32,
+    last: Option<TrackPoint>,
+    last_kept: bool,
+}
+
+impl Decimator {
+    pub const fn new(mode: DecimateMode) -> Self {
+        Self {
+            mode,
+            index: 0,
+            window: [TrackPoint {
+                timestamp: 0,
+                latitude_e7: 0,
+                longitude_e7: 0,
+                altitude_dm: 0,
+                pressure_pa: 0,
+                activity: 0,
+                fix_mode: 0,
+                fix_quality: 0,
+            }; WINDOW],
+            len: 0,
+            lon_scale: 0.0,
+            last: None,
+            last_kept: false,
+        }
+    }
+
+    /// Feed the next point; returns a point to keep, if any.
+    pub fn push(&mut self, point: TrackPoint) -> Option<TrackPoint> {
+        let kept = match self.mode {
+            DecimateMode::EveryNth(n) => {
+                let keep = self.index == 0;
+                self.index = (self.index + 1) % u32::from(n.max(1));
+                keep.then_some(point)
+            }
+            DecimateMode::Tolerance(tolerance_m) => self.push_tolerance(point, tolerance_m),
+        };
+        self.last = Some(point);
+        self.last_kept = kept == Some(point);
+        kept
+    }
+
+    /// End of track: returns the last point if it was not kept yet.
+    pub fn finish(&mut self) -> Option<TrackPoint> {
+        if self.last_kept {
+            return None;
+        }
+        self.last_kept = true;
+        self.last
+    }
+
+    fn push_tolerance(&mut self, point: TrackPoint, tolerance_m: u16) -> Option<TrackPoint> {
+        if self.len == 0 {
+            self.rebase(point);
+            return Some(point);
+        }
+        if self.len < WINDOW && self.within(point, f32::from(tolerance_m)) {
+            self.window[self.len] = point;
+            self.len += 1;
+            return None;
+        }
+        let kept = self.window[self.len - 1];
+        self.rebase(kept);
+        self.window[1] = point;
+        self.len = 2;
+        Some(kept)
+    }
+
+    fn rebase(&mut self, start: TrackPoint) {
+        self.window[0] = start;
+        self.len = 1;
+        let lat_rad = (start.latitude_e7 as f64 / 1e7).to_radians();
+        self.lon_scale = (METERS_PER_E7 * cos_approx(lat_rad)) as f32;
+    }
+
+    /// Offset of `point` from the start point in metres (east, north).
+    fn offset_m(&self, point: &TrackPoint) -> (f32, f32) {
+        let start = &self.window[0];
+        let dx = point.longitude_e7.wrapping_sub(start.longitude_e7) as f32 * self.lon_scale;
+        let dy = point.latitude_e7.wrapping_sub(start.latitude_e7) as f32 * METERS_PER_E7 as f32;
+        (dx, dy)
+    }
+
+    /// Whether every buffered point lies within `tolerance_m` of the segment
+    /// from the start point to `end`.
+    fn within(&self, end: TrackPoint, tolerance_m: f32) -> bool {
+        let (ex, ey) = self.offset_m(&end);
+        let seg_len2 = ex * ex + ey * ey;
+        let limit2 = tolerance_m * tolerance_m;
+        self.window[1..self.len].iter().all(|p| {
+            let (px, py) = self.offset_m(p);
+            let t = if seg_len2 > 0.0 {
+                ((px * ex + py * ey) / seg_len2).clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+            let (dx, dy) = (px - t * ex, py - t * ey);
+            dx * dx + dy * dy <= limit2
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Points along a meridian near the equator, `north_m` metres apart.
+    fn point(ts: u32, north_m: f64, east_m: f64) -> TrackPoint {
+        TrackPoint {
+            timestamp: ts,
+            latitude_e7: (north_m / METERS_PER_E7) as i32,
+            longitude_e7: (east_m / METERS_PER_E7) as i32,
+            altitude_dm: 0,
+            pressure_pa: 0,
+            activity: 0,
+            fix_mode: 0,
+            fix_quality: 0,
+        }
+    }
+
+    fn run(mode: DecimateMode, points: &[TrackPoint]) -> Vec<TrackPoint> {
+        let mut decimator = Decimator::new(mode);
+        let mut out: Vec<_> = points.iter().filter_map(|&p| decimator.push(p)).collect();
+        out.extend(decimator.finish());
+        out
+    }
+
+    #[test]
+    fn every_nth_keeps_first_and_last() {
+        let points: Vec<_> = (0..10).map(|i| point(i, i as f64, 0.0)).collect();
+        let kept: Vec<u32> = run(DecimateMode::EveryNth(4), &points)
+            .iter()
+            .map(|p| p.timestamp)
+            .collect();
+        assert_eq!(kept, [0, 4, 8, 9]);
+    }
+
+    #[test]
+    fn every_nth_does_not_repeat_a_kept_last_point() {
+        let points: Vec<_> = (0..9).map(|i| point(i, i as f64, 0.0)).collect();
+        let kept: Vec<u32> = run(DecimateMode::EveryNth(4), &points)
+            .iter()
+            .map(|p| p.timestamp)
+            .collect();
+        assert_eq!(kept, [0, 4, 8]);
+    }
+
+    #[test]
+    fn tolerance_collapses_straight_line() {
+        let points: Vec<_> = (0..20).map(|i| point(i, i as f64 * 10.0, 0.0)).collect();
+        let kept: Vec<u32> = run(DecimateMode::Tolerance(5), &points)
+            .iter()
+            .map(|p| p.timestamp)
+            .collect();
+        assert_eq!(kept, [0, 19]);
+    }
+
+    #[test]
+    fn tolerance_keeps_corner() {
+        // North 100 m, then east 100 m.
+        let mut points: Vec<_> = (0..=10).map(|i| point(i, i as f64 * 10.0, 0.0)).collect();
+        points.extend((1..=10).map(|i| point(10 + i, 100.0, i as f64 * 10.0)));
+        let kept: Vec<u32> = run(DecimateMode::Tolerance(5), &points)
+            .iter()
+            .map(|p| p.timestamp)
+            .collect();
+        assert_eq!(kept, [0, 10, 20]);
+    }
+
+    #[test]
+    fn tolerance_keeps_turnaround_of_out_and_back() {
+        let mut points: Vec<_> = (0..=5).map(|i| point(i, i as f64 * 20.0, 0.0)).collect();
+        points.extend((1..=5).map(|i| point(5 + i, 100.0 - i as f64 * 20.0, 0.0)));
+        let kept: Vec<u32> = run(DecimateMode::Tolerance(5), &points)
+            .iter()
+            .map(|p| p.timestamp)
+            .collect();
+        assert_eq!(kept, [0, 5, 10]);
+    }
+
+    #[test]
+    fn tolerance_window_bounds_gap_between_points() {
+        let points: Vec<_> = (0..100).map(|i| point(i, i as f64, 0.0)).collect();
+        let kept = run(DecimateMode::Tolerance(50), &points);
+        assert!(
+            kept.windows(2)
+                .all(|w| w[1].timestamp - w[0].timestamp < WINDOW as u32)
+        );
+    }
+}
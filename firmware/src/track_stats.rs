@@ -0,0 +1,305 @@
+//! Live track statistics for the current day.
+//!
+//! Every logged track point is folded into running aggregates (distance,
+//! moving time, maximum speed, ascent and descent, point count, first point
+//! time), so the app can show today's summary right after connecting instead
+//! of downloading and decoding the day file. The aggregates are checkpointed
+//! next to the day log and picked up again after a reboot.
+//!
+//! # Design
+//!
+//! - The day boundary follows log file rotation (`storage::log_day`): local
+//!   midnight when the timezone settings ask for it, UTC midnight otherwise.
+//! - Distance advances only once the position moved `MIN_STEP_M` from the
+//!   last counted point, which keeps GPS jitter while standing still out of
+//!   both distance and moving time.
+//! - A gap longer than `MAX_SEGMENT_GAP_S` (GPS idle, recording paused)
+//!   starts a new segment; the jump across the gap is not counted.
+//! - Ascent and descent only count altitude changes of at least
+//!   `ALTITUDE_HYSTERESIS_M` from the last turning point, so altitude noise
+//!   on flat ground does not add up over a day.
+//! - The checkpoint (`YYYY/MM/YYYYMMDD.sts`, the `to_bytes` record) is
+//!   written every `CHECKPOINT_INTERVAL_S` of track time and when the day
+//!   ends. Points logged after the last checkpoint are lost on a power cut;
+//!   the checkpoint is read back at the first point of its day.
+
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::mutex::Mutex;
+use embassy_sync::signal::Signal;
+
+use crate::geo;
+use crate::storage;
+use crate::system_info::SYSTEM_INFO;
+use crate::timezone::{self, TzCache};
+
+pub const TODAY_STATS_LEN: usize = 26;
+
+const MIN_STEP_M: f64 = 5.0;
+const MAX_SEGMENT_GAP_S: u32 = 60;
+/// Faster than this between two points is treated as a position glitch.
+const MAX_PLAUSIBLE_SPEED_MPS: f64 = 100.0;
+const MAX_PLAUSIBLE_SPEED_KMH: f32 = (MAX_PLAUSIBLE_SPEED_MPS * 3.6) as f32;
+const ALTITUDE_HYSTERESIS_M: f32 = 3.0;
+/// Minimum track time between change notifications.
+const NOTIFY_INTERVAL_S: u32 = 10;
+const CHECKPOINT_INTERVAL_S: u32 = 300;
+
+static STATS_CHANGED: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+#[derive(Clone, Copy)]
+pub struct DayStats {
+    /// UTC time of the first point, 0 if none yet.
+    pub start_ts: u32,
+    /// UTC time of the latest point.
+    pub last_ts: u32,
+    pub distance_m: f64,
+    pub moving_s: u32,
+    pub points: u32,
+    pub max_speed_kmh: f32,
+    pub ascent_m: f32,
+    pub descent_m: f32,
+}
+
+impl DayStats {
+    const EMPTY: Self = Self {
+        start_ts: 0,
+        last_ts: 0,
+        distance_m: 0.0,
+        moving_s: 0,
+        points: 0,
+        max_speed_kmh: 0.0,
+        ascent_m: 0.0,
+        descent_m: 0.0,
+    };
+
+    pub fn to_bytes(&self) -> [u8; TODAY_STATS_LEN] {
+        let mut out = [0u8; TODAY_STATS_LEN];
+        out[0..4].copy_from_slice(&self.start_ts.to_le_bytes());
+        out[4..8].copy_from_slice(&self.last_ts.to_le_bytes());
+        out[8..12].copy_from_slice(&(self.distance_m as u32).to_le_bytes());
+        out[12..16].copy_from_slice(&self.moving_s.to_le_bytes());
+        out[16..20].copy_from_slice(&self.points.to_le_bytes());
+        out[20..22].copy_from_slice(&((self.max_speed_kmh * 10.0) as u16).to_le_bytes());
+        out[22..24].copy_from_slice(&(self.ascent_m as u16).to_le_bytes());
+        out[24..26].copy_from_slice(&(self.descent_m as u16).to_le_bytes());
+        out
+    }
+
+    /// Inverse of `to_bytes`; fractions dropped there stay dropped.
+    pub fn from_bytes(raw: &[u8; TODAY_STATS_LEN]) -> Self {
+        let u32_at =
+            |at: usize| u32::from_le_bytes([raw[at], raw[at + 1], raw[at + 2], raw[at + 3]]);
+        let u16_at = |at: usize| u16::from_le_bytes([raw[at], raw[at + 1]]);
+        Self {
+            start_ts: u32_at(0),
+            last_ts: u32_at(4),
+            distance_m: u32_at(8) as f64,
+            moving_s: u32_at(12),
+            points: u32_at(16),
+            max_speed_kmh: u16_at(20) as f32 / 10.0,
+            ascent_m: u16_at(22) as f32,
+            descent_m: u16_at(24) as f32,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Anchor {
+    timestamp: u32,
+    latitude: f64,
+    longitude: f64,
+}
+
+struct StatsEngine {
+    /// Log day (YYYYMMDD) of `stats`, 0 before the first point.
+    day: u32,
+    stats: DayStats,
+    /// Last point counted towards distance.
+    anchor: Option<Anchor>,
+    /// Altitude of the last ascent/descent turning point.
+    altitude_ref: Option<f32>,
+    notified_ts: u32,
+    saved_ts: u32,
+    tz_cache: TzCache,
+}
+
+impl StatsEngine {
+    const fn new() -> Self {
+        Self {
+            day: 0,
+            stats: DayStats::EMPTY,
+            anchor: None,
+            altitude_ref: None,
+            notified_ts: 0,
+            saved_ts: 0,
+            tz_cache: TzCache::new(),
+        }
+    }
+
+    fn start_day(&mut self, day: u32, resumed: Option<DayStats>) {
+        self.day = day;
+        self.stats = resumed.unwrap_or(DayStats::EMPTY);
+        self.anchor = None;
+        self.altitude_ref = None;
+        self.saved_ts = self.stats.last_ts;
+    }
+
+    fn note_point(
+        &mut self,
+        timestamp: u32,
+        latitude: f64,
+        longitude: f64,
+        altitude_m: f32,
+        speed_kmh: f32,
+    ) {
+        let stats = &mut self.stats;
+        if stats.start_ts == 0 {
+            stats.start_ts = timestamp;
+        }
+        stats.last_ts = timestamp;
+        stats.points = stats.points.saturating_add(1);
+        if (0.0..=MAX_PLAUSIBLE_SPEED_KMH).contains(&speed_kmh) {
+            stats.max_speed_kmh = stats.max_speed_kmh.max(speed_kmh);
+        }
+        match self.altitude_ref {
+            Some(reference) if altitude_m - reference >= ALTITUDE_HYSTERESIS_M => {
+                stats.ascent_m += altitude_m - reference;
+                self.altitude_ref = Some(altitude_m);
+            }
+            Some(reference) if reference - altitude_m >= ALTITUDE_HYSTERESIS_M => {
+                stats.descent_m += reference - altitude_m;
+                self.altitude_ref = Some(altitude_m);
+            }
+            Some(_) => {}
+            None => self.altitude_ref = Some(altitude_m),
+        }
+
+        let here = Anchor {
+            timestamp,
+            latitude,
+            longitude,
+        };
+        let Some(anchor) = self.anchor else {
+            self.anchor = Some(here);
+            return;
+        };
+        let dt = timestamp.saturating_sub(anchor.timestamp);
+        let step = geo::distance_m(anchor.latitude, anchor.longitude, latitude, longitude);
+        if dt > MAX_SEGMENT_GAP_S {
+            self.anchor = Some(here);
+            return;
+        }
+        if step < MIN_STEP_M {
+            return;
+        }
+        if step <= dt as f64 * MAX_PLAUSIBLE_SPEED_MPS {
+            stats.distance_m += step;
+            stats.moving_s = stats.moving_s.saturating_add(dt);
+        }
+        self.anchor = Some(here);
+    }
+
+    /// The checkpoint record if one is due.
+    fn take_checkpoint(&mut self) -> Option<(u32, [u8; TODAY_STATS_LEN])> {
+        let unsaved_s = self.stats.last_ts.saturating_sub(self.saved_ts);
+        if self.day == 0 || unsaved_s < CHECKPOINT_INTERVAL_S {
+            return None;
+        }
+        // A failed write is retried at the next interval.
+        self.saved_ts = self.stats.last_ts;
+        Some((self.day, self.stats.to_bytes()))
+    }
+}
+
+static STATS: Mutex<CriticalSectionRawMutex, StatsEngine> = Mutex::new(StatsEngine::new());
+
+/// Fold a logged track point into today's aggregates. `speed_kmh` is
+/// negative when the receiver reported none.
+pub async fn note_point(
+    timestamp: u32,
+    latitude: f64,
+    longitude: f64,
+    altitude_m: f32,
+    speed_kmh: f32,
+) {
+    if timestamp == 0 {
+        return;
+    }
+    let mut engine = STATS.lock().await;
+    let Some(day) = storage::log_day(timestamp, latitude, longitude, &mut engine.tz_cache) else {
+        return;
+    };
+    let mut finished = None;
+    if day != engine.day {
+        if engine.day != 0 && engine.stats.last_ts != engine.saved_ts {
+            finished = Some((engine.day, engine.stats.to_bytes()));
+        }
+        let resumed = storage::read_day_stats(day)
+            .await
+            .map(|raw| DayStats::from_bytes(&raw));
+        if resumed.is_some() {
+            defmt::info!("Track stats: resumed {} from checkpoint", day);
+        }
+        engine.start_day(day, resumed);
+        engine.notified_ts = 0;
+    }
+    engine.note_point(timestamp, latitude, longitude, altitude_m, speed_kmh);
+    if timestamp.saturating_sub(engine.notified_ts) >= NOTIFY_INTERVAL_S {
+        engine.notified_ts = timestamp;
+        STATS_CHANGED.signal(());
+    }
+    let checkpoint = engine.take_checkpoint();
+    drop(engine);
+
+    for (day, record) in finished.into_iter().chain(checkpoint) {
+        if !storage::write_day_stats(day, &record).await {
+            defmt::warn!("Track stats: checkpoint of {} failed", day);
+        }
+    }
+}
+
+/// Aggregates for the day containing `now_ts` (UTC), or for the day of the
+/// latest point when the current time is unknown.
+async fn today(now_ts: Option<u32>) -> DayStats {
+    let mut engine = STATS.lock().await;
+    let (Some(now_ts), Some(anchor)) = (now_ts, engine.anchor) else {
+        return engine.stats;
+    };
+    let engine = &mut *engine;
+    let day = storage::log_day(
+        now_ts,
+        anchor.latitude,
+        anchor.longitude,
+        &mut engine.tz_cache,
+    );
+    if day != Some(engine.day) {
+        return DayStats::EMPTY;
+    }
+    engine.stats
+}
+
+/// Today's aggregates by the GPS clock, all zero once the day has ended.
+pub async fn current() -> DayStats {
+    let now_ts = {
+        let info = SYSTEM_INFO.lock().await;
+        if info.date_time_valid {
+            timezone::date_time_to_unix_timestamp(
+                info.year,
+                info.month,
+                info.day,
+                info.hour,
+                info.minute,
+                info.second,
+            )
+        } else {
+            None
+        }
+    };
+    today(now_ts).await
+}
+
+/// Wait until the aggregates moved on, at most every `NOTIFY_INTERVAL_S` of
+/// track time and on day rollover.
+pub async fn wait_change() {
+    STATS_CHANGED.wait().await;
+}
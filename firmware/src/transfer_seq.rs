@@ -0,0 +1,152 @@
+//! Sequence numbers and the whole-file CRC of a `READ_WINDOW` transfer.
+//!
+//! Window frames carried their offset and a CRC of their own data, but a
+//! notification dropped at the end of a window or file left nothing to
+//! notice, so the app could save a truncated file. Sequenced frames number
+//! every frame sent, and the end-of-file frame carries the CRC of the whole
+//! file.
+//!
+//! # Design
+//!
+//! - One counter per open file, wrapping at `u16`. Every frame takes the
+//!   next number, retransmissions included, so any gap is a lost frame.
+//! - The last `HISTORY_LEN` frames are remembered by number for
+//!   `RETRANSMIT`; older ones must be asked for again by offset.
+//! - The file CRC runs along as frames go out in file order. Frames out of
+//!   order (retransmissions, a resumed transfer) are skipped, and the end
+//!   of the file reads whatever the running CRC has not seen yet.
+
+use crate::crc32::Crc32;
+
+/// Two full windows.
+pub const HISTORY_LEN: usize = 32;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SentChunk {
+    pub offset: u32,
+    /// `0` for the end-of-file frame.
+    pub len: u16,
+}
+
+pub struct TransferSequence {
+    next_seq: u16,
+    sent: [Option<(u16, SentChunk)>; HISTORY_LEN],
+    crc: Crc32,
+    crc_through: u32,
+}
+
+impl TransferSequence {
+    pub const fn new() -> Self {
+        Self {
+            next_seq: 0,
+            sent: [None; HISTORY_LEN],
+            crc: Crc32::new(),
+            crc_through: 0,
+        }
+    }
+
+    /// Number a frame about to be sent and feed its data to the file CRC.
+    pub fn record(&mut self, offset: u32, data: &[u8]) -> u16 {
+        let seq = self.record_unread(offset, data.len() as u16);
+        self.absorb(offset, data);
+        seq
+    }
+
+    /// Number a frame sent in place of the `len` bytes at `offset`, which
+    /// could not be read.
+    pub fn record_unread(&mut self, offset: u32, len: u16) -> u16 {
+        let seq = self.next_seq;
+        self.next_seq = seq.wrapping_add(1);
+        self.sent[seq as usize % HISTORY_LEN] = Some((seq, SentChunk { offset, len }));
+        seq
+    }
+
+    /// The frame sent as `seq`, if it is still remembered.
+    pub fn lookup(&self, seq: u16) -> Option<SentChunk> {
+        match self.sent[seq as usize % HISTORY_LEN] {
+            Some((sent_seq, chunk)) if sent_seq == seq => Some(chunk),
+            _ => None,
+        }
+    }
+
+    /// Feed file data at `offset` to the CRC; ignored unless it starts
+    /// where the CRC stopped.
+    pub fn absorb(&mut self, offset: u32, data: &[u8]) {
+        if offset == self.crc_through {
+            self.crc.update(data);
+            self.crc_through += data.len() as u32;
+        }
+    }
+
+    /// Bytes from the start of the file the CRC covers.
+    pub fn crc_through(&self) -> u32 {
+        self.crc_through
+    }
+
+    /// CRC-32 of the first `crc_through` bytes.
+    pub fn file_crc(&self) -> u32 {
+        self.crc.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crc32::crc32;
+
+    #[test]
+    fn numbers_frames_and_forgets_old_ones() {
+        let mut sequence = TransferSequence::new();
+        for i in 0..40u32 {
+            assert_eq!(sequence.record(i * 10, &[0; 10]), i as u16);
+        }
+        assert_eq!(sequence.lookup(0), None);
+        assert_eq!(sequence.lookup(7), None);
+        let chunk = SentChunk {
+            offset: 390,
+            len: 10,
+        };
+        assert_eq!(sequence.lookup(39), Some(chunk));
+        assert_eq!(sequence.lookup(8).map(|chunk| chunk.offset), Some(80));
+        // Not sent yet.
+        assert_eq!(sequence.lookup(40), None);
+
+        let mut wrapped = TransferSequence::new();
+        wrapped.next_seq = u16::MAX;
+        assert_eq!(wrapped.record(0, &[]), u16::MAX);
+        assert_eq!(wrapped.record(0, &[]), 0);
+        assert_eq!(wrapped.lookup(u16::MAX).map(|chunk| chunk.len), Some(0));
+    }
+
+    #[test]
+    fn file_crc_skips_out_of_order_frames() {
+        let file: [u8; 100] = core::array::from_fn(|i| (i * 7) as u8);
+        let mut sequence = TransferSequence::new();
+        sequence.record(0, &file[0..30]);
+        // Lost frame skipped by the sender, then retransmitted.
+        sequence.record(60, &file[60..90]);
+        assert_eq!(sequence.crc_through(), 30);
+        sequence.record(30, &file[30..60]);
+        sequence.record(30, &file[30..60]);
+        assert_eq!(sequence.crc_through(), 60);
+        // End of file: the rest is read back.
+        sequence.absorb(60, &file[60..100]);
+        assert_eq!(sequence.crc_through(), 100);
+        assert_eq!(sequence.file_crc(), crc32(&file));
+    }
+
+    #[test]
+    fn unread_frames_are_numbered_but_not_checksummed() {
+        let mut sequence = TransferSequence::new();
+        sequence.record(0, &[1; 30]);
+        assert_eq!(sequence.record_unread(30, 30), 1);
+        assert_eq!(sequence.crc_through(), 30);
+        let chunk = SentChunk {
+            offset: 30,
+            len: 30,
+        };
+        assert_eq!(sequence.lookup(1), Some(chunk));
+        assert_eq!(sequence.record(30, &[2; 30]), 2);
+        assert_eq!(sequence.crc_through(), 60);
+    }
+}
@@ -0,0 +1,133 @@
+//! Last-hour speed and altitude trend for the display.
+//!
+//! Logged points are averaged into one-minute buckets, so the trend page can
+//! draw a sparkline of the recent climb or pace without reading the day
+//! file back from SD.
+//!
+//! # Design
+//!
+//! - `TREND_MINUTES` buckets indexed by UTC minute; a bucket whose minute is
+//!   older than the window is empty. The window ends at the newest point, not
+//!   at the current time, so a trip stays visible while the GPS sleeps.
+//! - Minutes without points (GPS idle, recording paused) are gaps in the
+//!   line, not interpolated.
+//! - Fed from the same points as the GPX log, plus the RMC speed at that
+//!   moment. RAM only.
+
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::mutex::Mutex;
+
+pub const TREND_MINUTES: usize = 60;
+const SECS_PER_MINUTE: u32 = 60;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum TrendKind {
+    /// km/h.
+    Speed,
+    /// Metres.
+    Altitude,
+}
+
+#[derive(Clone, Copy)]
+struct Bucket {
+    minute: u32,
+    speed_sum: f32,
+    speed_count: u16,
+    alt_sum: f32,
+    alt_count: u16,
+}
+
+impl Bucket {
+    const EMPTY: Self = Self {
+        minute: 0,
+        speed_sum: 0.0,
+        speed_count: 0,
+        alt_sum: 0.0,
+        alt_count: 0,
+    };
+}
+
+/// One value per minute, oldest first, ending at the newest point.
+pub type Series = [Option<f32>; TREND_MINUTES];
+
+struct Trend {
+    buckets: [Bucket; TREND_MINUTES],
+    /// Minute of the newest point, 0 before the first one.
+    last_minute: u32,
+}
+
+impl Trend {
+    const fn new() -> Self {
+        Self {
+            buckets: [Bucket::EMPTY; TREND_MINUTES],
+            last_minute: 0,
+        }
+    }
+
+    fn note(&mut self, timestamp: u32, speed_kmh: Option<f32>, altitude_m: f32) {
+        let minute = timestamp / SECS_PER_MINUTE;
+        if minute + TREND_MINUTES as u32 <= self.last_minute {
+            return;
+        }
+        self.last_minute = self.last_minute.max(minute);
+        let bucket = &mut self.buckets[minute as usize % TREND_MINUTES];
+        if bucket.minute != minute {
+            *bucket = Bucket {
+                minute,
+                ..Bucket::EMPTY
+            };
+        }
+        if let Some(speed) = speed_kmh {
+            bucket.speed_sum += speed;
+            bucket.speed_count = bucket.speed_count.saturating_add(1);
+        }
+        bucket.alt_sum += altitude_m;
+        bucket.alt_count = bucket.alt_count.saturating_add(1);
+    }
+
+    fn series(&self, kind: TrendKind) -> Series {
+        let mut out = [None; TREND_MINUTES];
+        if self.last_minute == 0 {
+            return out;
+        }
+        let first = self.last_minute + 1 - TREND_MINUTES as u32;
+        for (offset, value) in out.iter_mut().enumerate() {
+            let minute = first + offset as u32;
+            let bucket = &self.buckets[minute as usize % TREND_MINUTES];
+            if bucket.minute != minute {
+                continue;
+            }
+            let (sum, count) = match kind {
+                TrendKind::Speed => (bucket.speed_sum, bucket.speed_count),
+                TrendKind::Altitude => (bucket.alt_sum, bucket.alt_count),
+            };
+            if count > 0 {
+                *value = Some(sum / count as f32);
+            }
+        }
+        out
+    }
+}
+
+static TREND: Mutex<CriticalSectionRawMutex, Trend> = Mutex::new(Trend::new());
+
+/// Fold a logged point into the trend. A negative speed means unknown.
+pub async fn note_point(timestamp: u32, speed_kmh: f32, altitude_m: f32) {
+    if timestamp == 0 {
+        return;
+    }
+    let speed = (speed_kmh >= 0.0).then_some(speed_kmh);
+    TREND.lock().await.note(timestamp, speed, altitude_m);
+}
+
+pub async fn series(kind: TrendKind) -> Series {
+    TREND.lock().await.series(kind)
+}
+
+/// Smallest and largest value in the series, if it has any.
+pub fn min_max(series: &[Option<f32>]) -> Option<(f32, f32)> {
+    series.iter().flatten().fold(None, |range, &value| match range {
+        None => Some((value, value)),
+        Some((min, max)) => Some((min.min(value), max.max(value))),
+    })
+}
@@ -7,7 +7,7 @@ use embassy_futures::select::{select, Either};
 use embassy_nrf::peripherals;
 use embassy_nrf::usb::vbus_detect::{SoftwareVbusDetect, VbusDetect};
 use embassy_nrf::Peri;
-use embassy_time::Timer;
+use embassy_time::{Instant, Timer};
 use embedded_sdmmc::{Block, BlockDevice, BlockIdx};
 use nrf_softdevice::{raw, RawError};
 use nrf_pac as pac;
@@ -21,7 +21,8 @@ use usbd_storage::subclass::Command;
 use usbd_storage::transport::bbb::{BulkOnly, BulkOnlyError};
 use usbd_storage::transport::TransportError;
 
-use crate::storage;
+use crate::storage::{self, CardOwner};
+use crate::usb_power::{self, UsbSource};
 
 const USB_VID: u16 = 0xCAFE;
 const USB_PID: u16 = 0x4001;
@@ -37,10 +38,12 @@ const SENSE_KEY_MEDIUM_ERROR: u8 = 0x03;
 const SENSE_KEY_ILLEGAL_REQUEST: u8 = 0x05;
 
 const ASC_NO_ADDITIONAL_SENSE: u8 = 0x00;
+const ASC_LOGICAL_UNIT_NOT_READY: u8 = 0x04;
 const ASC_INVALID_COMMAND: u8 = 0x20;
 const ASC_LBA_OUT_OF_RANGE: u8 = 0x21;
 const ASC_MEDIUM_NOT_PRESENT: u8 = 0x3A;
 const ASC_WRITE_FAULT: u8 = 0x03;
+const ASCQ_BECOMING_READY: u8 = 0x01;
 
 const SCSI_CMD_START_STOP_UNIT: u8 = 0x1B;
 const SCSI_CMD_PREVENT_ALLOW_MEDIUM_REMOVAL: u8 = 0x1E;
@@ -83,8 +86,17 @@ async fn wait_hfclk_running() {
 }
 
 #[task]
-pub async fn usb_msc_task(usbd: Peri<'static, peripherals::USBD>, vbus: &'static SoftwareVbusDetect) {
+pub async fn usb_msc_task(
+    usbd: Peri<'static, peripherals::USBD>,
+    vbus: &'static SoftwareVbusDetect,
+    host_timeout_s: u8,
+) {
     defmt::info!("USB MSC task start");
+    // 0 = booted without a timeout from normal mode.
+    let enumeration_timeout_ms = match host_timeout_s {
+        0 => usb_power::ENUMERATION_TIMEOUT_MS,
+        seconds => seconds as u64 * 1000,
+    };
     let usb_bus = USB_BUS.init(UsbBusAllocator::new(Usbd::new(UsbdPeripheral::new(usbd))));
     let mut usb_buf = Some(USB_BUF.init([0; MSC_BUFFER_SIZE]) as &'static mut [u8]);
     let mut scsi = None;
@@ -134,7 +146,9 @@ pub async fn usb_msc_task(usbd: Peri<'static, peripherals::USBD>, vbus: &'static
         let scsi = scsi.as_mut().unwrap();
         let usb_dev = usb_dev.as_mut().unwrap();
 
-        let ok = storage::enter_usb_mode().await;
+        // Exporting is done before the reset into USB mode, where the settings
+        // are still loaded.
+        let ok = storage::enter_usb_mode(false).await;
         if ok {
             defmt::info!("USB mode storage ready");
         } else {
@@ -142,7 +156,22 @@ pub async fn usb_msc_task(usbd: Peri<'static, peripherals::USBD>, vbus: &'static
         }
         state.reset();
         let _ = usb_dev.force_reset();
+        let attached_at = Instant::now();
+        let mut enumerated = false;
         while vbus.is_usb_detected() {
+            if !enumerated {
+                if matches!(
+                    usb_dev.state(),
+                    UsbDeviceState::Addressed | UsbDeviceState::Configured
+                ) {
+                    enumerated = true;
+                    usb_power::set_source(UsbSource::Host);
+                    defmt::info!("USB host enumerated");
+                } else if attached_at.elapsed().as_millis() > enumeration_timeout_ms {
+                    defmt::warn!("USB: no host enumeration, treating port as a charger");
+                    crate::leave_usb_mode_for_charger();
+                }
+            }
             if usb_dev.poll(&mut [scsi]) {
                 if let Err(err) = scsi.poll(|cmd| handle_scsi_command(cmd, &mut state)) {
                     log_usb_error("scsi.poll", err);
@@ -152,6 +181,8 @@ pub async fn usb_msc_task(usbd: Peri<'static, peripherals::USBD>, vbus: &'static
             }
         }
 
+        // Drop any half-received write before the card changes hands.
+        state.reset();
         let _ = storage::exit_usb_mode().await;
     }
 }
@@ -299,6 +330,16 @@ where
             }
         }
         ScsiCommand::TestUnitReady => {
+            if !host_owns_card() {
+                fail_with_sense(
+                    state,
+                    cmd,
+                    SENSE_KEY_NOT_READY,
+                    ASC_LOGICAL_UNIT_NOT_READY,
+                    ASCQ_BECOMING_READY,
+                );
+                return;
+            }
             if num_blocks().is_some() {
                 cmd.pass();
             } else {
@@ -402,6 +443,16 @@ where
     Bus: UsbBus,
     Buf: BorrowMut<[u8]>,
 {
+    if !host_owns_card() {
+        fail_with_sense(
+            state,
+            cmd,
+            SENSE_KEY_NOT_READY,
+            ASC_LOGICAL_UNIT_NOT_READY,
+            ASCQ_BECOMING_READY,
+        );
+        return;
+    }
     let Some((lba, blocks)) = normalize_transfer(lba, len) else {
         warn!("Read normalize_transfer failed");
         fail_with_sense(state, cmd, SENSE_KEY_ILLEGAL_REQUEST, ASC_LBA_OUT_OF_RANGE, 0);
@@ -498,6 +549,16 @@ where
     Bus: UsbBus,
     Buf: BorrowMut<[u8]>,
 {
+    if !host_owns_card() {
+        fail_with_sense(
+            state,
+            cmd,
+            SENSE_KEY_NOT_READY,
+            ASC_LOGICAL_UNIT_NOT_READY,
+            ASCQ_BECOMING_READY,
+        );
+        return;
+    }
     let Some((lba, blocks)) = normalize_transfer(lba, len) else {
         warn!("Write normalize_transfer failed");
         fail_with_sense(state, cmd, SENSE_KEY_ILLEGAL_REQUEST, ASC_LBA_OUT_OF_RANGE, 0);
@@ -655,6 +716,17 @@ fn log_usb_error(context: &'static str, err: UsbError) {
     }
 }
 
+/// Whether SCSI may reach the card. Not while the logger hands it over or
+/// mounts it back.
+fn host_owns_card() -> bool {
+    let owner = storage::card_owner();
+    if owner != CardOwner::UsbHost {
+        warn!("SCSI: card owned by {}", owner);
+        return false;
+    }
+    true
+}
+
 fn num_blocks() -> Option<u32> {
     match storage::with_usb_card(|card| card.num_blocks()) {
         Some(Ok(count)) => Some(count.0),
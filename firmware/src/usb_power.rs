@@ -0,0 +1,115 @@
+//! USB power source classification.
+//!
+//! Tells a dumb charger apart from a data host so the rest of the firmware
+//! can adapt: no USB mode on a charger, a longer display timeout while power
+//! is not scarce, and a `/CHARGE.CSV` line for every change of source.
+//!
+//! # Design
+//!
+//! - The nRF52840 has no BC1.2 detector: USBREGSTATUS only reports VBUS
+//!   and regulator readiness, and D+/D- cannot be sensed without running
+//!   USBD. VBUS alone is `Unknown`.
+//! - Enumeration is the only tell. In USB mode, if no host addresses the
+//!   device within `usb.host_timeout_s` (`ENUMERATION_TIMEOUT_MS` by
+//!   default), the port is a charger: a GPREGRET flag records that and the
+//!   device resets back to normal mode, which marks the source `Charger`
+//!   until VBUS goes away. Recording resumes there, so a USB mode entered
+//!   by accident ends by itself.
+//! - A host that enumerates is `Host`; that is only seen in USB mode, where
+//!   the SD card belongs to the PC and nothing is logged.
+
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use embassy_executor::task;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+use embassy_time::Instant;
+
+use crate::storage;
+
+/// How long a host gets to address the device before the port is taken
+/// for a charger, unless `usb.host_timeout_s` says otherwise.
+pub const ENUMERATION_TIMEOUT_MS: u64 = 5_000;
+/// Display timeout floor while on USB power.
+pub const USB_DISPLAY_TIMEOUT_S: i32 = 300;
+
+#[repr(u8)]
+#[derive(Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum UsbSource {
+    None = 0,
+    /// VBUS present, not classified yet.
+    Unknown = 1,
+    /// VBUS without a host.
+    Charger = 2,
+    Host = 3,
+}
+
+impl UsbSource {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            1 => UsbSource::Unknown,
+            2 => UsbSource::Charger,
+            3 => UsbSource::Host,
+            _ => UsbSource::None,
+        }
+    }
+}
+
+static SOURCE: AtomicU8 = AtomicU8::new(UsbSource::None as u8);
+static CHANGED: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+pub fn source() -> UsbSource {
+    UsbSource::from_u8(SOURCE.load(Ordering::Acquire))
+}
+
+pub fn set_source(source: UsbSource) {
+    let previous = SOURCE.swap(source as u8, Ordering::AcqRel);
+    if previous != source as u8 {
+        CHANGED.signal(());
+    }
+}
+
+/// VBUS edge from the SoftDevice event callback. Keeps a classification
+/// made earlier in this plug-in.
+pub fn note_vbus(present: bool) {
+    if !present {
+        set_source(UsbSource::None);
+    } else if SOURCE
+        .compare_exchange(
+            UsbSource::None as u8,
+            UsbSource::Unknown as u8,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        )
+        .is_ok()
+    {
+        CHANGED.signal(());
+    }
+}
+
+/// Display timeout with the USB floor applied.
+pub fn display_timeout_s(setting_s: i32) -> i32 {
+    if source() == UsbSource::None {
+        setting_s
+    } else {
+        setting_s.max(USB_DISPLAY_TIMEOUT_S)
+    }
+}
+
+/// Log every change of source to `/CHARGE.CSV`. Normal mode only.
+#[task]
+pub async fn charge_log_task() {
+    let mut logged = None;
+    loop {
+        let current = source();
+        if logged != Some(current) {
+            let uptime_s = Instant::now().as_secs() as u32;
+            defmt::info!("USB power: source={}", current);
+            if !storage::append_charge_log(uptime_s, current as u8).await {
+                defmt::warn!("USB power: CHARGE.CSV append failed");
+            }
+            logged = Some(current);
+        }
+        CHANGED.wait().await;
+    }
+}
@@ -0,0 +1,260 @@
+//! High-rate accelerometer capture for vibration analysis.
+//!
+//! A BLE-triggered capture switches the accelerometer to 50/100 Hz sampling for a
+//! bounded duration and appends compressed bursts to `/VIBRATE.BIN`. The
+//! motion filter keeps running on a decimated stream, and blocks go through
+//! a small channel to a writer task, so GPS logging is never blocked by the
+//! capture (a full channel drops the block and counts it).
+//!
+//! # File format (little-endian, records appended back to back)
+//!
+//! - Capture start, 12 bytes:
+//!   `[0xC0][version=1][rate_hz][range_g][start_unix: u32][start_uptime_ms: u32]`
+//! - Data block:
+//!   `[0xD0][count][t_offset_ms: u32][x, y, z: i16]` followed by
+//!   `count - 1` samples of zigzag-varint deltas `(dx, dy, dz)`.
+//!   Values are milli-g; `t_offset_ms` is the first sample's offset from the
+//!   capture start.
+//! - Capture end, 7 bytes: `[0xE0][samples: u32][dropped_blocks: u16]`
+
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU8, Ordering};
+
+use embassy_executor::task;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::channel::Channel;
+use embassy_sync::signal::Signal;
+use embassy_time::Instant;
+
+use crate::storage;
+use crate::system_info::SYSTEM_INFO;
+use crate::timezone;
+
+pub const MAX_CAPTURE_SECS: u16 = 600;
+
+const RECORD_CAPTURE_START: u8 = 0xC0;
+const RECORD_DATA_BLOCK: u8 = 0xD0;
+const RECORD_CAPTURE_END: u8 = 0xE0;
+const FORMAT_VERSION: u8 = 1;
+const BLOCK_SAMPLES: usize = 32;
+const BLOCK_HEADER_LEN: usize = 12;
+/// A delta between two i16 values zigzag-encodes to at most 3 varint bytes.
+const BLOCK_MAX_LEN: usize = BLOCK_HEADER_LEN + (BLOCK_SAMPLES - 1) * 3 * 3;
+
+#[derive(Clone, Copy)]
+pub struct CaptureRequest {
+    pub rate_hz: u8,
+    pub duration_s: u16,
+}
+
+struct VibBlock {
+    len: usize,
+    data: [u8; BLOCK_MAX_LEN],
+}
+
+impl VibBlock {
+    const fn new() -> Self {
+        Self {
+            len: 0,
+            data: [0; BLOCK_MAX_LEN],
+        }
+    }
+
+    fn push(&mut self, byte: u8) {
+        if self.len < self.data.len() {
+            self.data[self.len] = byte;
+            self.len += 1;
+        }
+    }
+
+    fn push_slice(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.push(b);
+        }
+    }
+
+    fn push_varint_s32(&mut self, value: i32) {
+        let mut zz = ((value as u32) << 1) ^ ((value >> 31) as u32);
+        while zz >= 0x80 {
+            self.push((zz as u8) | 0x80);
+            zz >>= 7;
+        }
+        self.push(zz as u8);
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        &self.data[..self.len]
+    }
+}
+
+static CAPTURE_REQUEST: Signal<CriticalSectionRawMutex, CaptureRequest> = Signal::new();
+static BLOCK_CHANNEL: Channel<CriticalSectionRawMutex, VibBlock, 2> = Channel::new();
+static CAPTURE_ACTIVE: AtomicBool = AtomicBool::new(false);
+static CAPTURE_RATE_HZ: AtomicU8 = AtomicU8::new(0);
+static CAPTURE_END_UPTIME_S: AtomicU32 = AtomicU32::new(0);
+
+/// Request a capture (`duration_s = 0` stops a running one).
+/// Returns `false` for unsupported parameters.
+pub fn request_capture(rate_hz: u8, duration_s: u16) -> bool {
+    if duration_s > 0 && (!matches!(rate_hz, 50 | 100) || duration_s > MAX_CAPTURE_SECS) {
+        return false;
+    }
+    CAPTURE_REQUEST.signal(CaptureRequest {
+        rate_hz,
+        duration_s,
+    });
+    true
+}
+
+/// Pending request for the accelerometer task, if any.
+pub fn take_request() -> Option<CaptureRequest> {
+    CAPTURE_REQUEST.try_take()
+}
+
+/// `(active, rate_hz, remaining_s)` of the current capture.
+pub fn status() -> (bool, u8, u16) {
+    if !CAPTURE_ACTIVE.load(Ordering::Acquire) {
+        return (false, 0, 0);
+    }
+    let now_s = (Instant::now().as_millis() / 1000) as u32;
+    let remaining = CAPTURE_END_UPTIME_S
+        .load(Ordering::Acquire)
+        .saturating_sub(now_s);
+    (
+        true,
+        CAPTURE_RATE_HZ.load(Ordering::Acquire),
+        remaining.min(u16::MAX as u32) as u16,
+    )
+}
+
+async fn gps_unix_ts() -> u32 {
+    let info = *SYSTEM_INFO.lock().await;
+    if !info.date_time_valid {
+        // Indoors before the first fix the phone time is better than none.
+        return crate::phone_location::unix_now().map_or(0, |unix_ts| unix_ts as u32);
+    }
+    timezone::date_time_to_unix_timestamp(
+        info.year,
+        info.month,
+        info.day,
+        info.hour,
+        info.minute,
+        info.second,
+    )
+    .unwrap_or(0)
+}
+
+/// Delta encoder for one capture, driven by the accelerometer task.
+pub struct BurstEncoder {
+    start_ms: u64,
+    block: VibBlock,
+    count: u8,
+    last: [i16; 3],
+    samples: u32,
+    dropped_blocks: u16,
+}
+
+impl BurstEncoder {
+    /// Emit the capture start record and mark the capture active.
+    pub async fn begin(req: CaptureRequest, range_g: u8) -> Self {
+        let start_ms = Instant::now().as_millis();
+        let mut header = VibBlock::new();
+        header.push(RECORD_CAPTURE_START);
+        header.push(FORMAT_VERSION);
+        header.push(req.rate_hz);
+        header.push(range_g);
+        header.push_slice(&gps_unix_ts().await.to_le_bytes());
+        header.push_slice(&(start_ms as u32).to_le_bytes());
+        let mut encoder = Self {
+            start_ms,
+            block: VibBlock::new(),
+            count: 0,
+            last: [0; 3],
+            samples: 0,
+            dropped_blocks: 0,
+        };
+        encoder.submit(header);
+
+        CAPTURE_RATE_HZ.store(req.rate_hz, Ordering::Release);
+        CAPTURE_END_UPTIME_S.store(
+            (start_ms / 1000) as u32 + req.duration_s as u32,
+            Ordering::Release,
+        );
+        CAPTURE_ACTIVE.store(true, Ordering::Release);
+        defmt::info!(
+            "Vibration: capture started {}Hz for {}s",
+            req.rate_hz,
+            req.duration_s
+        );
+        encoder
+    }
+
+    pub fn elapsed_ms(&self) -> u64 {
+        Instant::now().as_millis().saturating_sub(self.start_ms)
+    }
+
+    /// Add one sample in milli-g.
+    pub fn push(&mut self, sample: [i16; 3]) {
+        if self.count == 0 {
+            self.block.push(RECORD_DATA_BLOCK);
+            self.block.push(0); // count, patched on flush
+            self.block.push_slice(&(self.elapsed_ms() as u32).to_le_bytes());
+            for axis in sample {
+                self.block.push_slice(&axis.to_le_bytes());
+            }
+        } else {
+            for (axis, last) in sample.iter().zip(self.last.iter()) {
+                self.block.push_varint_s32(*axis as i32 - *last as i32);
+            }
+        }
+        self.last = sample;
+        self.count += 1;
+        self.samples = self.samples.saturating_add(1);
+        if self.count as usize >= BLOCK_SAMPLES {
+            self.flush_block();
+        }
+    }
+
+    fn flush_block(&mut self) {
+        if self.count == 0 {
+            return;
+        }
+        self.block.data[1] = self.count;
+        let block = core::mem::replace(&mut self.block, VibBlock::new());
+        self.count = 0;
+        self.submit(block);
+    }
+
+    fn submit(&mut self, block: VibBlock) {
+        if BLOCK_CHANNEL.try_send(block).is_err() {
+            self.dropped_blocks = self.dropped_blocks.saturating_add(1);
+        }
+    }
+
+    /// Flush the partial block and emit the capture end record.
+    pub async fn finish(mut self) {
+        self.flush_block();
+        let mut trailer = VibBlock::new();
+        trailer.push(RECORD_CAPTURE_END);
+        trailer.push_slice(&self.samples.to_le_bytes());
+        trailer.push_slice(&self.dropped_blocks.to_le_bytes());
+        // The end record must not be lost; wait for channel space.
+        BLOCK_CHANNEL.send(trailer).await;
+        CAPTURE_ACTIVE.store(false, Ordering::Release);
+        defmt::info!(
+            "Vibration: capture done, {} samples, {} blocks dropped",
+            self.samples,
+            self.dropped_blocks
+        );
+    }
+}
+
+/// Writer task: appends capture records to SD outside the sampling loop.
+#[task]
+pub async fn vibration_writer_task() {
+    loop {
+        let block = BLOCK_CHANNEL.receive().await;
+        if !storage::append_vibration_log(block.as_slice()).await {
+            defmt::warn!("Vibration: SD append failed");
+        }
+    }
+}
@@ -0,0 +1,252 @@
+//! Persistent user waypoint database.
+//!
+//! Waypoints are kept in RAM and mirrored to `/WAYPTS.DB` on the SD card as
+//! an array of fixed-size records, so slot numbers stay stable across
+//! reboots and host tools can parse the file without a schema.
+//!
+//! # Record layout (`WAYPOINT_RECORD_SIZE` bytes, little-endian)
+//!
+//! | Offset | Size | Field                              |
+//! | :----- | :--- | :--------------------------------- |
+//! | 0      | 1    | flags (bit0 = in use, bit1 = fav)  |
+//! | 1      | 1    | name length                        |
+//! | 2      | 2    | reserved (0)                       |
+//! | 4      | 4    | latitude, i32, degrees * 1e7       |
+//! | 8      | 4    | longitude, i32, degrees * 1e7      |
+//! | 12     | 20   | name, UTF-8, zero padded           |
+//!
+//! At most one waypoint is the favorite; the main page shows the distance to
+//! it, or to the nearest waypoint when none is marked.
+
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::mutex::Mutex;
+
+use crate::geo;
+use crate::storage;
+
+pub const MAX_WAYPOINTS: usize = 64;
+pub const WAYPOINT_RECORD_SIZE: usize = 32;
+pub const WAYPOINT_NAME_MAX: usize = 20;
+pub const WAYPOINT_DB_SIZE: usize = MAX_WAYPOINTS * WAYPOINT_RECORD_SIZE;
+
+const FLAG_IN_USE: u8 = 0x01;
+const FLAG_FAVORITE: u8 = 0x02;
+
+#[derive(Clone, Copy)]
+pub struct Waypoint {
+    pub latitude_e7: i32,
+    pub longitude_e7: i32,
+    pub name: [u8; WAYPOINT_NAME_MAX],
+    pub name_len: u8,
+    pub favorite: bool,
+}
+
+impl Waypoint {
+    const fn empty() -> Self {
+        Self {
+            latitude_e7: 0,
+            longitude_e7: 0,
+            name: [0; WAYPOINT_NAME_MAX],
+            name_len: 0,
+            favorite: false,
+        }
+    }
+
+    /// Build a waypoint from wire fields; the name is truncated to fit.
+    pub fn new(latitude_e7: i32, longitude_e7: i32, name: &[u8]) -> Option<Self> {
+        if !(-900_000_000..=900_000_000).contains(&latitude_e7)
+            || !(-1_800_000_000..=1_800_000_000).contains(&longitude_e7)
+        {
+            return None;
+        }
+        let mut wp = Self::empty();
+        let len = core::cmp::min(name.len(), WAYPOINT_NAME_MAX);
+        wp.name[..len].copy_from_slice(&name[..len]);
+        wp.name_len = len as u8;
+        wp.latitude_e7 = latitude_e7;
+        wp.longitude_e7 = longitude_e7;
+        Some(wp)
+    }
+
+    pub fn name(&self) -> &[u8] {
+        &self.name[..self.name_len as usize]
+    }
+
+    pub fn latitude(&self) -> f64 {
+        self.latitude_e7 as f64 / 1e7
+    }
+
+    pub fn longitude(&self) -> f64 {
+        self.longitude_e7 as f64 / 1e7
+    }
+
+    pub fn write_record(&self, out: &mut [u8]) {
+        out[0] = FLAG_IN_USE | if self.favorite { FLAG_FAVORITE } else { 0 };
+        out[1] = self.name_len;
+        out[2] = 0;
+        out[3] = 0;
+        out[4..8].copy_from_slice(&self.latitude_e7.to_le_bytes());
+        out[8..12].copy_from_slice(&self.longitude_e7.to_le_bytes());
+        out[12..12 + WAYPOINT_NAME_MAX].copy_from_slice(&self.name);
+    }
+
+    fn read_record(data: &[u8]) -> Option<Self> {
+        if (data[0] & FLAG_IN_USE) == 0 {
+            return None;
+        }
+        let latitude_e7 = i32::from_le_bytes([data[4], data[5], data[6], data[7]]);
+        let longitude_e7 = i32::from_le_bytes([data[8], data[9], data[10], data[11]]);
+        let name_len = core::cmp::min(data[1] as usize, WAYPOINT_NAME_MAX);
+        let mut wp = Self::new(latitude_e7, longitude_e7, &data[12..12 + name_len])?;
+        wp.favorite = (data[0] & FLAG_FAVORITE) != 0;
+        Some(wp)
+    }
+}
+
+struct WaypointDb {
+    slots: [Option<Waypoint>; MAX_WAYPOINTS],
+}
+
+impl WaypointDb {
+    const fn new() -> Self {
+        Self {
+            slots: [None; MAX_WAYPOINTS],
+        }
+    }
+
+    fn serialize(&self, out: &mut [u8; WAYPOINT_DB_SIZE]) {
+        out.fill(0);
+        for (slot, chunk) in self.slots.iter().zip(out.chunks_exact_mut(WAYPOINT_RECORD_SIZE)) {
+            if let Some(wp) = slot {
+                wp.write_record(chunk);
+            }
+        }
+    }
+}
+
+static WAYPOINTS: Mutex<CriticalSectionRawMutex, WaypointDb> = Mutex::new(WaypointDb::new());
+
+/// Load waypoints from SD card. Call once after the SD logger is initialized.
+pub async fn load() {
+    let mut buf = [0u8; WAYPOINT_DB_SIZE];
+    let Some(len) = storage::read_waypoint_db(&mut buf).await else {
+        defmt::info!("Waypoints: no database on SD");
+        return;
+    };
+    let mut db = WAYPOINTS.lock().await;
+    let mut count = 0usize;
+    for (slot, chunk) in db.slots.iter_mut().zip(buf[..len].chunks_exact(WAYPOINT_RECORD_SIZE)) {
+        *slot = Waypoint::read_record(chunk);
+        if slot.is_some() {
+            count += 1;
+        }
+    }
+    defmt::info!("Waypoints: loaded {} from SD", count);
+}
+
+async fn persist(db: &WaypointDb) -> bool {
+    let mut buf = [0u8; WAYPOINT_DB_SIZE];
+    db.serialize(&mut buf);
+    storage::write_waypoint_db(&buf).await
+}
+
+/// Store a waypoint in the first free slot. Returns the slot index.
+pub async fn add(wp: Waypoint) -> Option<u8> {
+    let mut db = WAYPOINTS.lock().await;
+    let idx = db.slots.iter().position(|slot| slot.is_none())?;
+    db.slots[idx] = Some(wp);
+    if !persist(&db).await {
+        db.slots[idx] = None;
+        return None;
+    }
+    Some(idx as u8)
+}
+
+/// Replace the waypoint in an occupied slot. The favorite mark is kept.
+pub async fn update(slot: u8, mut wp: Waypoint) -> bool {
+    let mut db = WAYPOINTS.lock().await;
+    let Some(entry) = db.slots.get_mut(slot as usize) else {
+        return false;
+    };
+    wp.favorite = entry.is_some_and(|previous| previous.favorite);
+    let Some(previous) = entry.replace(wp) else {
+        *entry = None;
+        return false;
+    };
+    if !persist(&db).await {
+        db.slots[slot as usize] = Some(previous);
+        return false;
+    }
+    true
+}
+
+/// Free a slot. Deleting an empty slot is not an error.
+pub async fn delete(slot: u8) -> bool {
+    let mut db = WAYPOINTS.lock().await;
+    let Some(entry) = db.slots.get_mut(slot as usize) else {
+        return false;
+    };
+    let Some(previous) = entry.take() else {
+        return true;
+    };
+    if !persist(&db).await {
+        db.slots[slot as usize] = Some(previous);
+        return false;
+    }
+    true
+}
+
+/// Return the next occupied slot at or after `start`.
+pub async fn next_from(start: usize) -> Option<(u8, Waypoint)> {
+    let db = WAYPOINTS.lock().await;
+    db.slots
+        .iter()
+        .enumerate()
+        .skip(start)
+        .find_map(|(idx, slot)| slot.map(|wp| (idx as u8, wp)))
+}
+
+/// Mark `slot` as the favorite, or clear the mark with `None`.
+pub async fn set_favorite(slot: Option<u8>) -> bool {
+    let mut db = WAYPOINTS.lock().await;
+    if let Some(slot) = slot {
+        if db.slots.get(slot as usize).is_none_or(|entry| entry.is_none()) {
+            return false;
+        }
+    }
+    let previous = db.slots;
+    for (idx, entry) in db.slots.iter_mut().enumerate() {
+        if let Some(wp) = entry {
+            wp.favorite = slot == Some(idx as u8);
+        }
+    }
+    if !persist(&db).await {
+        db.slots = previous;
+        return false;
+    }
+    true
+}
+
+pub struct NavTarget {
+    pub waypoint: Waypoint,
+    pub distance_m: f64,
+    pub bearing_deg: f64,
+}
+
+/// Distance and bearing from the given position to the favorite waypoint,
+/// or to the nearest one when no favorite is set.
+pub async fn nav_target(latitude: f64, longitude: f64) -> Option<NavTarget> {
+    let db = WAYPOINTS.lock().await;
+    let target = |wp: Waypoint| NavTarget {
+        waypoint: wp,
+        distance_m: geo::distance_m(latitude, longitude, wp.latitude(), wp.longitude()),
+        bearing_deg: geo::bearing_deg(latitude, longitude, wp.latitude(), wp.longitude()),
+    };
+    let waypoints = db.slots.iter().flatten().copied();
+    if let Some(favorite) = waypoints.clone().find(|wp| wp.favorite) {
+        return Some(target(favorite));
+    }
+    waypoints
+        .map(target)
+        .min_by(|a, b| a.distance_m.total_cmp(&b.distance_m))
+}
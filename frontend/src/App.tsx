@@ -88,7 +88,10 @@ const formatSysInfo = (info: SysInfo | null) => {
       gpsState: "-",
       temperature: "-",
       pressure: "-",
-      motion: "-"
+      motion: "-",
+      fixType: "-",
+      firmware: "-",
+      bootloader: "-"
     };
   }
 
@@ -110,6 +113,11 @@ const formatSysInfo = (info: SysInfo | null) => {
   const motion = info.isStationary !== undefined
     ? (info.isStationary ? "Stationary" : "Moving")
     : "-";
+  const fixDimension = info.fixMode === 3 ? "3D" : info.fixMode === 2 ? "2D" : info.fixMode === 1 ? "No fix" : "-";
+  const fixType = info.fixQuality === 2 && (info.fixMode ?? 0) >= 2 ? `${fixDimension} DGPS` : fixDimension;
+  const firmware = info.firmwareVersion ?? "-";
+  const bl = info.bootloaderVersion;
+  const bootloader = bl === undefined ? "-" : bl === 0 ? "Unknown" : `${bl >> 16}.${(bl >> 8) & 0xff}.${bl & 0xff}`;
 
   return {
     latitude: `${info.latitude.toFixed(7)} deg`,
@@ -127,7 +135,10 @@ const formatSysInfo = (info: SysInfo | null) => {
     gpsState: gpsStateLabels[info.gpsState] ?? `${info.gpsState}`,
     temperature,
     pressure,
-    motion
+    motion,
+    fixType,
+    firmware,
+    bootloader
   };
 };
 
@@ -914,7 +925,10 @@ export default function App() {
                       ["GPS State", info.gpsState],
                       ["Temperature", info.temperature],
                       ["Pressure", info.pressure],
-                      ["Motion", info.motion]
+                      ["Motion", info.motion],
+                      ["Fix Type", info.fixType],
+                      ["Firmware", info.firmware],
+                      ["Bootloader", info.bootloader]
                     ].map(([label, value]) => (
                       <div key={label} className="rounded-md border border-border/70 bg-white/60 p-3">
                         <div className="text-xs font-semibold uppercase tracking-wide text-muted-foreground">
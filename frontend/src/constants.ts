@@ -29,7 +29,10 @@
   },
   SYSINFO_V1_LEN: 50,
   SYSINFO_V2_LEN: 63,
-  SYSINFO_PAYLOAD_LEN: 63,  // Current version
+  SYSINFO_V3_LEN: 65,
+  SYSINFO_V4_LEN: 100,
+  SYSINFO_PAYLOAD_LEN: 100,  // Current version
+  SYSINFO_FIRMWARE_VERSION_LEN: 24,
   DEFAULT_MTU_SIZE: 23,
   FINDMY_KEY_SIZE: 68,
   FMDN_EIK_SIZE: 32
@@ -242,7 +242,7 @@ export function createBleService(logger: Logger) {
     const payload = new DataView(value.buffer, 2, payloadLen);
     logger.log(`Parsed RX payload length: ${payloadLen}`);
 
-    if (currentPromises.getSysInfo && (payloadLen === CONSTANTS.SYSINFO_V1_LEN || payloadLen === CONSTANTS.SYSINFO_V2_LEN)) {
+    if (currentPromises.getSysInfo && (payloadLen === CONSTANTS.SYSINFO_V1_LEN || payloadLen >= CONSTANTS.SYSINFO_V2_LEN)) {
       try {
         const info = parseSysInfoPayload(payload, payloadLen);
         currentPromises.getSysInfo.resolve(info);
@@ -646,12 +646,13 @@ export function createBleService(logger: Logger) {
       return value;
     };
 
-    // Check version: 50 = V1 (master), 63 = V2 (with version byte)
-    const isV2 = payloadLen === CONSTANTS.SYSINFO_V2_LEN;
+    // Check version: 50 = V1 (master), >= 63 = V2+ (with version byte).
+    // Later versions only append fields, so unknown trailing bytes are ignored.
+    const isV2 = payloadLen >= CONSTANTS.SYSINFO_V2_LEN;
     let version: number | undefined;
 
     if (isV2) {
-      version = getUint8();  // Read version byte (should be 2)
+      version = getUint8();  // Read version byte
     }
 
     // Parse 50 legacy bytes (same for V1 and V2)
@@ -677,7 +678,7 @@ export function createBleService(logger: Logger) {
 
     // V2 additional fields
     if (isV2) {
-      return {
+      const v2Info: SysInfo = {
         ...baseInfo,
         version,
         keepAliveRemainingS: getUint16(),
@@ -686,6 +687,27 @@ export function createBleService(logger: Logger) {
         temperatureC: getFloat32(),
         pressurePa: getFloat32()
       };
+      // V3 additional fields
+      if ((version ?? 0) >= 3 && payloadLen >= CONSTANTS.SYSINFO_V3_LEN) {
+        v2Info.fixMode = getUint8();
+        v2Info.fixQuality = getUint8();
+      }
+      // V4 additional fields
+      if ((version ?? 0) >= 4 && payloadLen >= CONSTANTS.SYSINFO_V4_LEN) {
+        const versionBytes = new Uint8Array(
+          payload.buffer,
+          payload.byteOffset + offset,
+          CONSTANTS.SYSINFO_FIRMWARE_VERSION_LEN
+        );
+        const end = versionBytes.indexOf(0);
+        v2Info.firmwareVersion = new TextDecoder().decode(end >= 0 ? versionBytes.subarray(0, end) : versionBytes);
+        offset += CONSTANTS.SYSINFO_FIRMWARE_VERSION_LEN;
+        v2Info.capabilities = getUint32();
+        v2Info.softdeviceFwid = getUint16();
+        v2Info.bootloaderVersion = getUint32();
+        v2Info.debugProtected = getUint8();
+      }
+      return v2Info;
     }
 
     // V1 (no additional fields)
@@ -5,9 +5,16 @@ export type GpsPoint = {
   latitude_scaled_1e7: number;
   longitude_scaled_1e7: number;
   altitude_m_scaled_1e1: number;
+  // 气压（帕），仅 V3 数据块携带，0 表示无读数
+  pressure_pa?: number;
+  // 活动类型（最近的活动块）：0 = 未知，1 = 步行，2 = 骑行，3 = 驾车
+  activity?: number;
+  // 定位类型（最近的定位块）：GSA 模式 2 = 2D、3 = 3D，GGA 质量 1 = GPS、2 = DGPS，0 = 未知
+  fix_mode?: number;
+  fix_quality?: number;
 };
 
-type FormatVersion = "V1" | "V2" | null;
+type FormatVersion = "V1" | "V2" | "V3" | null;
 
 export function createGpsDecoder() {
   const readVarintS32 = (view: DataView, offsetObj: { offset: number }) => {
@@ -52,7 +59,11 @@ export function createGpsDecoder() {
       // V1 和 V2 各自维护前一个点的状态
       let previousPointV1: GpsPoint | null = null;
       let previousPointV2: GpsPoint | null = null;
+      let previousPointV3: GpsPoint | null = null;
       let currentVersion: FormatVersion = null;
+      let activity = 0;
+      let fixMode = 0;
+      let fixQuality = 0;
 
       let pointIndex = 0;
 
@@ -72,6 +83,25 @@ export function createGpsDecoder() {
           const header = view.getUint8(offsetObj.offset++);
           let currentPoint: GpsPoint;
 
+          // 活动块 (0xFC)：1 字节标签，作用于其后的数据点
+          if (header === 0xfc) {
+            if (offsetObj.offset + 1 > view.byteLength) {
+              throw new Error(`Buffer underflow for activity block at offset ${offsetObj.offset}.`);
+            }
+            activity = view.getUint8(offsetObj.offset++);
+            continue;
+          }
+
+          // 定位块 (0xFB)：GSA 模式与 GGA 质量各 1 字节，作用于其后的数据点
+          if (header === 0xfb) {
+            if (offsetObj.offset + 2 > view.byteLength) {
+              throw new Error(`Buffer underflow for fix block at offset ${offsetObj.offset}.`);
+            }
+            fixMode = view.getUint8(offsetObj.offset++);
+            fixQuality = view.getUint8(offsetObj.offset++);
+            continue;
+          }
+
           // V1 Full Block (0xFF)
           if (header === 0xff) {
             if (offsetObj.offset + 16 > view.byteLength) {
@@ -114,6 +144,61 @@ export function createGpsDecoder() {
             currentVersion = "V2";
             previousPointV2 = currentPoint;
           }
+          // V3 Full Block (0xFD)
+          else if (header === 0xfd) {
+            if (offsetObj.offset + 20 > view.byteLength) {
+              throw new Error(
+                `Buffer underflow for V3 full block payload at offset ${offsetObj.offset}. Needed 20, got ${
+                  view.byteLength - offsetObj.offset
+                }.`
+              );
+            }
+
+            // V3 在 V2 之后追加气压字段
+            currentPoint = {
+              timestamp: view.getUint32(offsetObj.offset, true),
+              latitude_scaled_1e7: view.getInt32(offsetObj.offset + 4, true),
+              longitude_scaled_1e7: view.getInt32(offsetObj.offset + 8, true),
+              altitude_m_scaled_1e1: view.getInt32(offsetObj.offset + 12, true),
+              pressure_pa: view.getInt32(offsetObj.offset + 16, true)
+            };
+            offsetObj.offset += 20;
+            currentVersion = "V3";
+            previousPointV3 = currentPoint;
+          }
+          // V3 Delta Block (0x20-0x3F, bit 5 = 1)
+          else if ((header & 0xe0) === 0x20) {
+            if (currentVersion !== "V3" || !previousPointV3) {
+              throw new Error(
+                `V3 Delta block at offset ${pointStartOffset} without preceding V3 Full block.`
+              );
+            }
+
+            currentPoint = { ...previousPointV3 };
+            const flags = header & 0x1f;
+
+            if ((flags >> 4) & 1) {
+              currentPoint.timestamp = (currentPoint.timestamp + readVarintS32(view, offsetObj)) >>> 0;
+            }
+
+            if ((flags >> 3) & 1) {
+              currentPoint.latitude_scaled_1e7 += readVarintS32(view, offsetObj);
+            }
+
+            if ((flags >> 2) & 1) {
+              currentPoint.longitude_scaled_1e7 += readVarintS32(view, offsetObj);
+            }
+
+            if ((flags >> 1) & 1) {
+              currentPoint.altitude_m_scaled_1e1 += readVarintS32(view, offsetObj);
+            }
+
+            if (flags & 1) {
+              currentPoint.pressure_pa = (currentPoint.pressure_pa ?? 0) + readVarintS32(view, offsetObj);
+            }
+
+            previousPointV3 = currentPoint;
+          }
           // V1 Delta Block (0x00-0x0F, bit 4 = 0)
           else if ((header & 0xf0) === 0x00) {
             if (currentVersion !== "V1" || !previousPointV1) {
@@ -182,7 +267,7 @@ export function createGpsDecoder() {
             );
           }
 
-          points.push(currentPoint);
+          points.push({ ...currentPoint, activity, fix_mode: fixMode, fix_quality: fixQuality });
         } catch (error) {
           const message = error instanceof Error ? error.message : String(error);
           console.error(
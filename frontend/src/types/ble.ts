@@ -31,5 +31,12 @@ export type SysInfo = {
   isStationary?: number;
   temperatureC?: number;
   pressurePa?: number;
+  fixMode?: number;
+  fixQuality?: number;
+  firmwareVersion?: string;
+  capabilities?: number;
+  softdeviceFwid?: number;
+  bootloaderVersion?: number;
+  debugProtected?: number;
 };
 
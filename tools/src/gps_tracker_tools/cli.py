@@ -138,6 +138,7 @@ Examples:
   gt casic parse data.bin -v
   gt gps decode input.bin output.json
   gt uf2 build
+  gt uf2 size --no-default-features --features i2c-spi
 """,
     )
     sub = parser.add_subparsers(dest="command")
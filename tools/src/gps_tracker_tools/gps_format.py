@@ -7,18 +7,46 @@ GPS trajectory data.
 Block types:
 - Full Block (0xFF): Complete GPS data (timestamp, lat, lon, alt)
 - Delta Block (0x0X): Compressed delta values for changed fields
+- V3 Full Block (0xFD) / Delta Block (0x20-0x3F): 1e7 coordinates plus
+  barometric pressure, written when the device has a BMP280 reading
+- Activity Block (0xFC): one label byte (walk / ride / drive) for the
+  points after it; decoded points carry it as `activity`
+- Fix Block (0xFB): GSA fix mode and GGA fix quality bytes for the points
+  after it; decoded points carry them as `fix_mode` and `fix_quality`
+
+Also decodes the device waypoint database (WAYPTS.DB), an array of
+32-byte fixed-size records, and the session table (SESSIONS.DB), an array
+of 48-byte records, with its metadata sidecar (SESSMETA.DB).
+
+Vibration captures (VIBRATE.BIN) are decoded to per-sample rows.
+
+Track files are decoded block by block from a stream (`iter_blocks`), so
+`decode` and `validate` handle files far larger than memory.
 """
 
 import argparse
+import io
 import json
 import struct
 from datetime import datetime
 from pathlib import Path
-from typing import Optional
+from typing import BinaryIO, Iterator, Optional
+
+# Longest block: an activity block and a fix block, then a V3 delta header
+# with five 5-byte varints.
+MAX_BLOCK_LEN = 2 + 3 + 1 + 5 * 5
+READ_CHUNK = 1 << 16
+HEADER_ACTIVITY = 0xFC
+HEADER_FIX = 0xFB
+ACTIVITY_NAMES = {1: "walking", 2: "cycling", 3: "driving"}
 
 
 class GpsPoint:
-    """GPS point with scaled values."""
+    """GPS point with scaled values.
+
+    Coordinates are degrees * 1e5 as written by V1 blocks; V3 points carry
+    degrees * 1e7 with `coord_scale` set accordingly, and a pressure.
+    """
 
     def __init__(
         self,
@@ -26,29 +54,39 @@ class GpsPoint:
         latitude_scaled_1e5: int,
         longitude_scaled_1e5: int,
         altitude_m_scaled_1e1: int,
+        pressure_pa: int = 0,
+        coord_scale: float = 1e5,
     ):
         self.timestamp = timestamp
         self.latitude_scaled_1e5 = latitude_scaled_1e5
         self.longitude_scaled_1e5 = longitude_scaled_1e5
         self.altitude_m_scaled_1e1 = altitude_m_scaled_1e1
+        self.pressure_pa = pressure_pa
+        self.coord_scale = coord_scale
 
     def to_dict(self) -> dict:
-        return {
+        data = {
             "timestamp": self.timestamp,
             "timestamp_iso": datetime.fromtimestamp(
                 self.timestamp
             ).isoformat(),
-            "latitude": self.latitude_scaled_1e5 / 1e5,
-            "longitude": self.longitude_scaled_1e5 / 1e5,
+            "latitude": self.latitude_scaled_1e5 / self.coord_scale,
+            "longitude": self.longitude_scaled_1e5 / self.coord_scale,
             "altitude": self.altitude_m_scaled_1e1 / 10.0,
             "latitude_scaled": self.latitude_scaled_1e5,
             "longitude_scaled": self.longitude_scaled_1e5,
             "altitude_scaled": self.altitude_m_scaled_1e1,
         }
+        if self.coord_scale != 1e5:
+            data["coord_scale"] = self.coord_scale
+        if self.pressure_pa:
+            data["pressure_pa"] = self.pressure_pa
+        return data
 
     @classmethod
     def from_dict(cls, data: dict) -> "GpsPoint":
-        if "latitude_scaled" in data:
+        # The encoder writes V1 blocks, so 1e7 points take the float path.
+        if "latitude_scaled" in data and "coord_scale" not in data:
             return cls(
                 data["timestamp"],
                 data["latitude_scaled"],
@@ -69,6 +107,11 @@ class GpsFormatDecoder:
     def __init__(self):
         self.previous_point: Optional[GpsPoint] = None
         self.is_first_point = True
+        # Label of the last activity block, 0 = unknown.
+        self.activity = 0
+        # Mode and quality of the last fix block, 0 = unknown.
+        self.fix_mode = 0
+        self.fix_quality = 0
 
     def _read_varint_s32(
         self, data: bytes, offset: int
@@ -107,6 +150,28 @@ class GpsFormatDecoder:
         header = data[offset]
         offset += 1
 
+        if header == HEADER_ACTIVITY:
+            # Applies to the points after it; decode the next block with it.
+            if offset >= len(data):
+                raise ValueError(
+                    "Buffer underflow for Activity Block payload"
+                )
+            self.activity = data[offset]
+            point, consumed, block_type = self.decode_block(
+                data, offset + 1
+            )
+            return point, consumed + 2, block_type
+
+        if header == HEADER_FIX:
+            if offset + 2 > len(data):
+                raise ValueError("Buffer underflow for Fix Block payload")
+            self.fix_mode = data[offset]
+            self.fix_quality = data[offset + 1]
+            point, consumed, block_type = self.decode_block(
+                data, offset + 2
+            )
+            return point, consumed + 3, block_type
+
         if header == 0xFF:
             if offset + 16 > len(data):
                 raise ValueError(
@@ -122,11 +187,35 @@ class GpsFormatDecoder:
             self.is_first_point = False
             return point, 17, "full"
 
+        elif header == 0xFD:
+            if offset + 20 > len(data):
+                raise ValueError(
+                    "Buffer underflow for V3 Full Block payload"
+                )
+            point = GpsPoint(
+                self._read_uint32_le(data, offset),
+                self._read_int32_le(data, offset + 4),
+                self._read_int32_le(data, offset + 8),
+                self._read_int32_le(data, offset + 12),
+                self._read_int32_le(data, offset + 16),
+                coord_scale=1e7,
+            )
+            self.previous_point = point
+            self.is_first_point = False
+            return point, 21, "full"
+
+        elif (header & 0xE0) == 0x20:
+            return self._decode_v3_delta(data, offset, header)
+
         elif (header & 0x80) == 0:
             if self.is_first_point:
                 raise ValueError(
                     "Invalid data: Delta Block found as first block"
                 )
+            if self.previous_point.coord_scale != 1e5:
+                raise ValueError(
+                    "Invalid data: V1 Delta Block after a V3 block"
+                )
             if (header & 0x70) != 0:
                 raise ValueError(
                     f"Invalid Delta Block header: 0x{header:02X}"
@@ -185,32 +274,87 @@ class GpsFormatDecoder:
                 f"Invalid block header: 0x{header:02X}"
             )
 
-    def decode_file(self, data: bytes) -> list[dict]:
-        points = []
+    def _decode_v3_delta(
+        self, data: bytes, offset: int, header: int
+    ) -> tuple[GpsPoint, int, str]:
+        prev = self.previous_point
+        if prev is None or prev.coord_scale != 1e7:
+            raise ValueError(
+                "Invalid data: V3 Delta Block without a V3 Full Block"
+            )
+        fields = [
+            prev.timestamp,
+            prev.latitude_scaled_1e5,
+            prev.longitude_scaled_1e5,
+            prev.altitude_m_scaled_1e1,
+            prev.pressure_pa,
+        ]
+        bytes_consumed = 1
+        # Mask bits 4..0 are TS, LAT, LON, ALT, PRES.
+        for i in range(5):
+            if (header >> (4 - i)) & 1:
+                delta, consumed = self._read_varint_s32(data, offset)
+                fields[i] += delta
+                offset += consumed
+                bytes_consumed += consumed
+        point = GpsPoint(
+            fields[0] & 0xFFFFFFFF, *fields[1:], coord_scale=1e7
+        )
+        self.previous_point = point
+        return point, bytes_consumed, "delta"
+
+    def iter_blocks(
+        self, stream: BinaryIO, chunk_size: int = READ_CHUNK
+    ) -> Iterator[dict]:
+        """Decode blocks from `stream`, reading `chunk_size` bytes at a time.
+
+        Only the unread tail of the current chunk is held, so memory stays
+        flat whatever the file size. Stops at the first invalid block.
+        """
+        data = b""
         offset = 0
+        # File offset of data[0].
+        base = 0
         block_index = 0
-
-        while offset < len(data):
+        eof = False
+
+        while True:
+            # Keep a whole block ahead, so none is cut at a chunk boundary.
+            while not eof and len(data) - offset < MAX_BLOCK_LEN:
+                chunk = stream.read(chunk_size)
+                eof = not chunk
+                base += offset
+                data = data[offset:] + chunk
+                offset = 0
+            if offset >= len(data):
+                return
             try:
                 point, consumed, block_type = self.decode_block(
                     data, offset
                 )
-                points.append(
-                    {
-                        "index": block_index,
-                        "type": block_type,
-                        "data": point.to_dict(),
-                    }
-                )
-                offset += consumed
-                block_index += 1
             except Exception as e:
                 print(
-                    f"Error decoding block {block_index} at offset {offset}: {e}"
+                    f"Error decoding block {block_index} at offset {base + offset}: {e}"
                 )
-                break
+                return
+            point_data = point.to_dict()
+            if self.activity:
+                point_data["activity"] = ACTIVITY_NAMES.get(
+                    self.activity, self.activity
+                )
+            if self.fix_mode:
+                point_data["fix_mode"] = self.fix_mode
+                point_data["fix_quality"] = self.fix_quality
+            yield {
+                "index": block_index,
+                "type": block_type,
+                "data": point_data,
+            }
+            offset += consumed
+            block_index += 1
 
-        return points
+    def decode_file(self, data: bytes) -> list[dict]:
+        return list(self.iter_blocks(io.BytesIO(data)))
 
 
 class GpsFormatEncoder:
@@ -327,17 +471,261 @@ class GpsFormatEncoder:
         return bytes(self.output_buffer)
 
 
+WAYPOINT_RECORD_SIZE = 32
+WAYPOINT_NAME_MAX = 20
+
+
+def decode_waypoint_db(data: bytes) -> list[dict]:
+    """Decode WAYPTS.DB records. Empty slots are skipped."""
+    waypoints = []
+    for slot in range(len(data) // WAYPOINT_RECORD_SIZE):
+        record = data[
+            slot * WAYPOINT_RECORD_SIZE : (slot + 1) * WAYPOINT_RECORD_SIZE
+        ]
+        flags, name_len = record[0], record[1]
+        if not flags & 0x01:
+            continue
+        lat_e7, lon_e7 = struct.unpack("<ii", record[4:12])
+        name_len = min(name_len, WAYPOINT_NAME_MAX)
+        name = record[12 : 12 + name_len].decode("utf-8", errors="replace")
+        waypoints.append(
+            {
+                "slot": slot,
+                "name": name,
+                "latitude": lat_e7 / 1e7,
+                "longitude": lon_e7 / 1e7,
+                "favorite": bool(flags & 0x02),
+            }
+        )
+    return waypoints
+
+
+SESSION_RECORD_SIZE = 48
+SESSION_NAME_MAX = 24
+SESSION_ACTIVITIES = {
+    0: "other",
+    1: "walk",
+    2: "run",
+    3: "cycle",
+    4: "drive",
+    5: "hike",
+}
+
+
+def decode_session_db(data: bytes) -> list[dict]:
+    """Decode SESSIONS.DB records, oldest first. Empty slots are skipped."""
+    sessions = []
+    for slot in range(len(data) // SESSION_RECORD_SIZE):
+        record = data[
+            slot * SESSION_RECORD_SIZE : (slot + 1) * SESSION_RECORD_SIZE
+        ]
+        flags, activity, seq = struct.unpack("<BBH", record[0:4])
+        if not flags & 0x01:
+            continue
+        start, end, points, paused = struct.unpack("<IIII", record[4:20])
+        name_len = min(record[20], SESSION_NAME_MAX)
+        name = record[24 : 24 + name_len].decode("utf-8", errors="replace")
+        # Labelled on the device from the motion classifier; 0 = none yet.
+        detected = record[21]
+        sessions.append(
+            {
+                "slot": slot,
+                "seq": seq,
+                "name": name,
+                "activity": SESSION_ACTIVITIES.get(activity, str(activity)),
+                "start": start,
+                "end": end,
+                "points": points,
+                "paused_seconds": paused,
+                "open": bool(flags & 0x02),
+                "paused": bool(flags & 0x04),
+                "detected_activity": (
+                    SESSION_ACTIVITIES.get(detected, str(detected))
+                    if detected
+                    else None
+                ),
+                "detected_share_pct": record[22],
+            }
+        )
+    sessions.sort(key=lambda s: s["seq"])
+    return sessions
+
+
+META_RECORD_SIZE = 48
+META_NOTES_MAX = 40
+META_ACTIVITY_UNSET = 0xFF
+
+
+def decode_session_meta_db(data: bytes) -> dict[tuple[int, int], dict]:
+    """Decode SESSMETA.DB records, keyed by (slot, session seq)."""
+    meta = {}
+    for slot in range(len(data) // META_RECORD_SIZE):
+        record = data[slot * META_RECORD_SIZE : (slot + 1) * META_RECORD_SIZE]
+        flags, activity, seq = struct.unpack("<BBH", record[0:4])
+        if not flags & 0x01:
+            continue
+        r, g, b = record[4:7]
+        notes_len = min(record[7], META_NOTES_MAX)
+        meta[(slot, seq)] = {
+            "activity": None
+            if activity == META_ACTIVITY_UNSET
+            else SESSION_ACTIVITIES.get(activity, str(activity)),
+            "color": f"#{r:02x}{g:02x}{b:02x}",
+            "notes": record[8 : 8 + notes_len].decode("utf-8", errors="replace"),
+        }
+    return meta
+
+
+def apply_session_meta(
+    sessions: list[dict], meta: dict[tuple[int, int], dict]
+) -> None:
+    """Merge sidecar metadata into decoded sessions. Records left behind by
+    an overwritten session do not match its successor's seq and are skipped."""
+    for session in sessions:
+        m = meta.get((session["slot"], session["seq"]))
+        if m is None:
+            continue
+        if m["activity"] is not None:
+            session["activity"] = m["activity"]
+        session["color"] = m["color"]
+        session["notes"] = m["notes"]
+
+
+def _read_varint_s32(data: bytes, offset: int) -> tuple[int, int]:
+    result = 0
+    shift = 0
+    while True:
+        byte = data[offset]
+        offset += 1
+        result |= (byte & 0x7F) << shift
+        shift += 7
+        if not byte & 0x80:
+            break
+    return (result >> 1) ^ -(result & 1), offset
+
+
+def decode_vibration_log(data: bytes) -> list[dict]:
+    """Decode VIBRATE.BIN into captures with samples in milli-g."""
+    captures: list[dict] = []
+    current: Optional[dict] = None
+    offset = 0
+    while offset < len(data):
+        tag = data[offset]
+        if tag == 0xC0:
+            _, rate_hz, range_g, start, uptime = struct.unpack(
+                "<BBBII", data[offset + 1 : offset + 12]
+            )
+            current = {
+                "rate_hz": rate_hz,
+                "range_g": range_g,
+                "start": start,
+                "start_uptime_ms": uptime,
+                "samples": [],
+                "complete": False,
+            }
+            captures.append(current)
+            offset += 12
+        elif tag == 0xD0:
+            count = data[offset + 1]
+            (t_ms,) = struct.unpack("<I", data[offset + 2 : offset + 6])
+            xyz = list(struct.unpack("<hhh", data[offset + 6 : offset + 12]))
+            offset += 12
+            rows = [xyz]
+            for _ in range(count - 1):
+                delta = []
+                for _axis in range(3):
+                    value, offset = _read_varint_s32(data, offset)
+                    delta.append(value)
+                xyz = [a + d for a, d in zip(xyz, delta)]
+                rows.append(xyz)
+            if current is None:
+                continue
+            # Sample times are reconstructed from the nominal rate.
+            period_ms = 1000 / current["rate_hz"] if current["rate_hz"] else 0
+            for i, (x, y, z) in enumerate(rows):
+                current["samples"].append(
+                    {"t_ms": round(t_ms + i * period_ms), "x": x, "y": y, "z": z}
+                )
+        elif tag == 0xE0:
+            total, dropped = struct.unpack("<IH", data[offset + 1 : offset + 7])
+            if current is not None:
+                current["complete"] = True
+                current["reported_samples"] = total
+                current["dropped_blocks"] = dropped
+            offset += 7
+        else:
+            raise ValueError(f"Unknown vibration record 0x{tag:02x} at {offset}")
+    return captures
+
+
+def _xml_escape(text: str) -> str:
+    return (
+        text.replace("&", "&amp;")
+        .replace("<", "&lt;")
+        .replace(">", "&gt;")
+    )
+
+
+GPX_EXTENSION_NS = "https://github.com/Enter-tainer/gps_tracker/gpx/1"
+
+
+def _session_tracks(
+    points: list[dict], sessions: list[dict]
+) -> list[tuple[dict, list[dict]]]:
+    """Split points into the sessions whose time range covers them."""
+    tracks = []
+    for session in sessions:
+        if session["start"] == 0:
+            continue
+        end = session["end"] or float("inf")
+        inside = [
+            p for p in points if session["start"] <= p["timestamp"] <= end
+        ]
+        if inside:
+            tracks.append((session, inside))
+    return tracks
+
+
+def _track_header(filename: str, session: Optional[dict]) -> str:
+    if session is None:
+        return f"    <name>{filename}</name>\n"
+    header = f"    <name>{_xml_escape(session['name'] or filename)}</name>\n"
+    if session.get("notes"):
+        header += f"    <desc>{_xml_escape(session['notes'])}</desc>\n"
+    header += f"    <type>{_xml_escape(session['activity'])}</type>\n"
+    if session.get("color"):
+        header += "    <extensions>\n"
+        header += f"      <gt:color>{session['color']}</gt:color>\n"
+        header += "    </extensions>\n"
+    return header
+
+
 def convert_to_gpx(
-    points_data: list[dict], filename: str = "track"
+    points_data: list[dict],
+    filename: str = "track",
+    waypoints: Optional[list[dict]] = None,
+    sessions: Optional[list[dict]] = None,
 ) -> str:
-    """Convert decoded points to GPX format."""
+    """Convert decoded points (and optional waypoints) to GPX format.
+
+    With `sessions`, each session that covers some of the points becomes its
+    own <trk> carrying the session name, activity and metadata; points
+    outside every session are left out. Without a matching session the whole
+    file is one track.
+    """
     if not points_data:
         return ""
 
     points = [item["data"] for item in points_data]
+    tracks: list[tuple[Optional[dict], list[dict]]] = list(
+        _session_tracks(points, sessions or [])
+    )
+    if not tracks:
+        tracks = [(None, points)]
 
     gpx = f"""<?xml version="1.0" encoding="UTF-8" standalone="no" ?>
 <gpx xmlns="http://www.topografix.com/GPX/1/1"
+    xmlns:gt="{GPX_EXTENSION_NS}"
     xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance"
     xsi:schemaLocation="http://www.topografix.com/GPX/1/1 http://www.topografix.com/GPX/1/1/gpx.xsd"
     version="1.1" creator="gps-tracker-tools">
@@ -345,28 +733,33 @@ def convert_to_gpx(
     <name>{filename}</name>
     <time>{datetime.fromtimestamp(points[0]['timestamp']).isoformat()}</time>
   </metadata>
-  <trk>
-    <name>{filename}</name>
-    <trkseg>
 """
-    for point in points:
-        lat = point["latitude"]
-        lon = point["longitude"]
-        ele = point["altitude"]
-        ts = datetime.fromtimestamp(point["timestamp"]).isoformat()
-
-        if not (-90 <= lat <= 90) or not (-180 <= lon <= 180):
-            print(f"Skipping invalid point: Lat {lat}, Lon {lon}")
-            continue
-
-        gpx += f'      <trkpt lat="{lat:.5f}" lon="{lon:.5f}">\n'
-        gpx += f"        <ele>{ele:.1f}</ele>\n"
-        gpx += f"        <time>{ts}</time>\n"
-        gpx += "      </trkpt>\n"
-
-    gpx += """    </trkseg>
-  </trk>
-</gpx>"""
+    for wp in waypoints or []:
+        gpx += f'  <wpt lat="{wp["latitude"]:.7f}" lon="{wp["longitude"]:.7f}">\n'
+        gpx += f"    <name>{_xml_escape(wp['name'])}</name>\n"
+        gpx += "  </wpt>\n"
+    for session, track_points in tracks:
+        gpx += "  <trk>\n"
+        gpx += _track_header(filename, session)
+        gpx += "    <trkseg>\n"
+        for point in track_points:
+            lat = point["latitude"]
+            lon = point["longitude"]
+            ele = point["altitude"]
+            ts = datetime.fromtimestamp(point["timestamp"]).isoformat()
+
+            if not (-90 <= lat <= 90) or not (-180 <= lon <= 180):
+                print(f"Skipping invalid point: Lat {lat}, Lon {lon}")
+                continue
+
+            gpx += f'      <trkpt lat="{lat:.5f}" lon="{lon:.5f}">\n'
+            gpx += f"        <ele>{ele:.1f}</ele>\n"
+            gpx += f"        <time>{ts}</time>\n"
+            gpx += "      </trkpt>\n"
+        gpx += "    </trkseg>\n"
+        gpx += "  </trk>\n"
+
+    gpx += "</gpx>"
     return gpx
 
 
@@ -376,27 +769,26 @@ def convert_to_gpx(
 
 
 def cmd_decode(args):
-    with open(args.input, "rb") as f:
-        binary_data = f.read()
-
+    # Points are written as they decode; "file_info" follows them, once the
+    # total is known.
     decoder = GpsFormatDecoder()
-    points = decoder.decode_file(binary_data)
-
-    with open(args.output, "w", encoding="utf-8") as f:
-        json.dump(
-            {
-                "file_info": {
-                    "input_file": args.input,
-                    "total_points": len(points),
-                    "format_version": "1.0",
-                },
-                "points": points,
-            },
-            f,
-            indent=2,
-            ensure_ascii=False,
-        )
-    print(f"Decoded {len(points)} points to {args.output}")
+    total = 0
+    with open(args.input, "rb") as src, open(
+        args.output, "w", encoding="utf-8"
+    ) as f:
+        f.write('{\n  "points": [')
+        for item in decoder.iter_blocks(src):
+            f.write(",\n    " if total else "\n    ")
+            f.write(json.dumps(item, ensure_ascii=False))
+            total += 1
+        file_info = {
+            "input_file": args.input,
+            "total_points": total,
+            "format_version": "1.0",
+        }
+        f.write("\n  ],\n" if total else "],\n")
+        f.write(f'  "file_info": {json.dumps(file_info, ensure_ascii=False)}\n}}\n')
+    print(f"Decoded {total} points to {args.output}")
 
 
 def cmd_encode(args):
@@ -422,12 +814,23 @@ def cmd_encode(args):
 
 
 def cmd_to_gpx(args):
-    with open(args.input, "rb") as f:
-        binary_data = f.read()
-
     decoder = GpsFormatDecoder()
-    points = decoder.decode_file(binary_data)
-    gpx_content = convert_to_gpx(points, Path(args.input).stem)
+    with open(args.input, "rb") as f:
+        points = list(decoder.iter_blocks(f))
+    waypoints = None
+    if args.waypoints:
+        with open(args.waypoints, "rb") as f:
+            waypoints = decode_waypoint_db(f.read())
+    sessions = None
+    if args.sessions:
+        with open(args.sessions, "rb") as f:
+            sessions = decode_session_db(f.read())
+        if args.session_meta:
+            with open(args.session_meta, "rb") as f:
+                apply_session_meta(sessions, decode_session_meta_db(f.read()))
+    gpx_content = convert_to_gpx(
+        points, Path(args.input).stem, waypoints, sessions
+    )
 
     with open(args.output, "w", encoding="utf-8") as f:
         f.write(gpx_content)
@@ -436,34 +839,106 @@ def cmd_to_gpx(args):
     )
 
 
-def cmd_validate(args):
+def cmd_waypoints(args):
+    with open(args.input, "rb") as f:
+        waypoints = decode_waypoint_db(f.read())
+
+    if args.output:
+        with open(args.output, "w", encoding="utf-8") as f:
+            json.dump(waypoints, f, indent=2, ensure_ascii=False)
+        print(f"Decoded {len(waypoints)} waypoints to {args.output}")
+        return
+
+    for wp in waypoints:
+        print(
+            f"  [{wp['slot']:2d}] {wp['name']:20s} "
+            f"({wp['latitude']:.7f}, {wp['longitude']:.7f})"
+            f"{' *' if wp['favorite'] else ''}"
+        )
+    print(f"Total: {len(waypoints)} waypoints")
+
+
+def _format_ts(ts: int) -> str:
+    if ts == 0:
+        return "-"
+    return datetime.fromtimestamp(ts).strftime("%Y-%m-%d %H:%M:%S")
+
+
+def cmd_sessions(args):
+    with open(args.input, "rb") as f:
+        sessions = decode_session_db(f.read())
+    if args.meta:
+        with open(args.meta, "rb") as f:
+            apply_session_meta(sessions, decode_session_meta_db(f.read()))
+
+    if args.output:
+        with open(args.output, "w", encoding="utf-8") as f:
+            json.dump(sessions, f, indent=2, ensure_ascii=False)
+        print(f"Decoded {len(sessions)} sessions to {args.output}")
+        return
+
+    for s in sessions:
+        state = "paused" if s["paused"] else "open" if s["open"] else ""
+        print(
+            f"  [{s['slot']:2d}] #{s['seq']:<5d} {s['name']:24s} "
+            f"{s['activity']:6s} {_format_ts(s['start'])} -> "
+            f"{_format_ts(s['end'])} {s['points']} pts {state}"
+        )
+    print(f"Total: {len(sessions)} sessions")
+
+
+def cmd_vibration(args):
     with open(args.input, "rb") as f:
-        binary_data = f.read()
+        captures = decode_vibration_log(f.read())
+
+    if args.output:
+        with open(args.output, "w", encoding="utf-8") as f:
+            f.write("capture,t_ms,x_mg,y_mg,z_mg\n")
+            for idx, cap in enumerate(captures):
+                for s in cap["samples"]:
+                    f.write(f"{idx},{s['t_ms']},{s['x']},{s['y']},{s['z']}\n")
+        print(f"Wrote {len(captures)} captures to {args.output}")
+        return
+
+    for idx, cap in enumerate(captures):
+        status = "" if cap["complete"] else " (incomplete)"
+        dropped = cap.get("dropped_blocks", 0)
+        print(
+            f"  [{idx}] {_format_ts(cap['start'])} {cap['rate_hz']}Hz "
+            f"{len(cap['samples'])} samples, {dropped} blocks dropped{status}"
+        )
+    print(f"Total: {len(captures)} captures")
 
+
+def cmd_validate(args):
     decoder = GpsFormatDecoder()
-    points = decoder.decode_file(binary_data)
+    counts = {"full": 0, "delta": 0}
+    first_ts = last_ts = None
+    with open(args.input, "rb") as f:
+        for item in decoder.iter_blocks(f):
+            counts[item["type"]] += 1
+            last_ts = item["data"]["timestamp"]
+            if first_ts is None:
+                first_ts = last_ts
+    file_size = Path(args.input).stat().st_size
+    total = counts["full"] + counts["delta"]
 
     print("File validation successful!")
-    print(f"  Total points: {len(points)}")
-    print(f"  File size: {len(binary_data)} bytes")
-
-    full_blocks = sum(1 for p in points if p["type"] == "full")
-    delta_blocks = sum(1 for p in points if p["type"] == "delta")
+    print(f"  Total points: {total}")
+    print(f"  File size: {file_size} bytes")
 
-    print(f"  Full blocks: {full_blocks}")
-    print(f"  Delta blocks: {delta_blocks}")
+    print(f"  Full blocks: {counts['full']}")
+    print(f"  Delta blocks: {counts['delta']}")
 
-    if len(points) >= 2:
-        first_ts = points[0]["data"]["timestamp"]
-        last_ts = points[-1]["data"]["timestamp"]
+    if total >= 2:
         duration = last_ts - first_ts
         print(
             f"  Duration: {duration} seconds ({duration/3600:.1f} hours)"
         )
 
-    if points:
+    if total:
         print(
-            f"  Compression ratio: {len(binary_data) / (len(points) * 16):.2f}x"
+            f"  Compression ratio: {file_size / (total * 16):.2f}x"
         )
 
 
@@ -505,8 +980,42 @@ def add_subcommands(subparsers) -> None:
     )
     gpx_p.add_argument("input", help="Input binary file")
     gpx_p.add_argument("output", help="Output GPX file")
+    gpx_p.add_argument(
+        "--waypoints",
+        help="Device waypoint database (WAYPTS.DB) to embed as <wpt>",
+    )
+    gpx_p.add_argument(
+        "--sessions",
+        help="Device session table (SESSIONS.DB): one <trk> per session",
+    )
+    gpx_p.add_argument(
+        "--session-meta",
+        help="Session metadata sidecar (SESSMETA.DB) for --sessions",
+    )
     gpx_p.set_defaults(func=cmd_to_gpx)
 
+    wpt_p = subparsers.add_parser(
+        "waypoints", help="Decode device waypoint database (WAYPTS.DB)"
+    )
+    wpt_p.add_argument("input", help="Input WAYPTS.DB file")
+    wpt_p.add_argument("-o", "--output", help="Save waypoints to JSON file")
+    wpt_p.set_defaults(func=cmd_waypoints)
+
+    sess_p = subparsers.add_parser(
+        "sessions", help="Decode device session table (SESSIONS.DB)"
+    )
+    sess_p.add_argument("input", help="Input SESSIONS.DB file")
+    sess_p.add_argument("-o", "--output", help="Save sessions to JSON file")
+    sess_p.add_argument("--meta", help="Session metadata sidecar (SESSMETA.DB)")
+    sess_p.set_defaults(func=cmd_sessions)
+
+    vib_p = subparsers.add_parser(
+        "vibration", help="Decode accelerometer captures (VIBRATE.BIN)"
+    )
+    vib_p.add_argument("input", help="Input VIBRATE.BIN file")
+    vib_p.add_argument("-o", "--output", help="Save samples to CSV file")
+    vib_p.set_defaults(func=cmd_vibration)
+
     validate_p = subparsers.add_parser(
         "validate", help="Validate binary file format"
     )
@@ -1,52 +1,87 @@
 """
 UF2 firmware build tool.
 
-Builds firmware, converts to Intel HEX, merges with SoftDevice, and generates UF2.
+Builds firmware and the slot bootloader, converts to Intel HEX, merges with
+SoftDevice, and generates UF2. Also writes the raw bank A image that the
+`FIRMWARE` BLE command stages, and reports flash/RAM usage of the app ELF
+against the `memory.x` budget.
 """
 
 from __future__ import annotations
 
 import argparse
+import struct
 import subprocess
 import sys
 from pathlib import Path
 
 ROOT = Path(__file__).resolve().parents[3]  # uf2.py -> gps_tracker_tools -> src -> tools -> repo root
 FIRMWARE_DIR = ROOT / "firmware"
+BOOTLOADER_DIR = ROOT / "bootloader"
 TARGET = "thumbv7em-none-eabihf"
 PROFILE = "release"
 TARGET_DIR = FIRMWARE_DIR / "target" / TARGET / PROFILE
 
 DEFAULT_ELF = TARGET_DIR / "gps-tracker-firmware"
 DEFAULT_APP_HEX = TARGET_DIR / "gps-tracker-firmware.hex"
+DEFAULT_APP_BIN = TARGET_DIR / "gps-tracker-firmware.bin"
+BOOTLOADER_TARGET_DIR = BOOTLOADER_DIR / "target" / TARGET / PROFILE
+DEFAULT_BOOTLOADER_ELF = BOOTLOADER_TARGET_DIR / "gps-tracker-bootloader"
+DEFAULT_BOOTLOADER_HEX = BOOTLOADER_TARGET_DIR / "gps-tracker-bootloader.hex"
 DEFAULT_SD_HEX = ROOT / "s140_nrf52_7.3.0_softdevice.hex"
 DEFAULT_COMBINED_HEX = TARGET_DIR / "gps-tracker-combined.hex"
 DEFAULT_UF2 = TARGET_DIR / "gps-tracker-combined.uf2"
 UF2CONV = ROOT / "uf2conv.py"
 UF2_FAMILY_ID = "0xADA52840"
 
+# Application regions from firmware/memory.x: FLASH is bank A of the slot
+# bootloader (bootloader/memory.x), bank B is one page larger.
+FLASH_BUDGET = 0x5E000
+RAM_BUDGET = 256 * 1024 - 0x3000
+
 
 def run(cmd: list[str], cwd: Path | None = None) -> None:
     print("+", " ".join(str(c) for c in cmd))
     subprocess.run(cmd, cwd=cwd, check=True)
 
 
-def build_firmware() -> None:
+def feature_args(features: str | None, no_default_features: bool) -> list[str]:
+    args = []
+    if no_default_features:
+        args.append("--no-default-features")
+    if features:
+        args += ["--features", features]
+    return args
+
+
+def build_firmware(
+    features: str | None = None,
+    no_default_features: bool = False,
+    crate_dir: Path = FIRMWARE_DIR,
+) -> None:
     run(
         [
             "cargo",
             "build",
             "--manifest-path",
-            str(FIRMWARE_DIR / "Cargo.toml"),
+            str(crate_dir / "Cargo.toml"),
             "--release",
             "--target",
             TARGET,
+            *feature_args(features, no_default_features),
         ],
-        cwd=FIRMWARE_DIR,
+        cwd=crate_dir,
     )
 
 
-def objcopy_to_hex(elf: Path, out_hex: Path) -> None:
+def objcopy_to_hex(
+    elf: Path,
+    out_hex: Path,
+    features: str | None = None,
+    no_default_features: bool = False,
+    crate_dir: Path = FIRMWARE_DIR,
+    out_format: str = "ihex",
+) -> None:
     out_hex.parent.mkdir(parents=True, exist_ok=True)
     try:
         run(
@@ -54,16 +89,17 @@ def objcopy_to_hex(elf: Path, out_hex: Path) -> None:
                 "cargo",
                 "objcopy",
                 "--manifest-path",
-                str(FIRMWARE_DIR / "Cargo.toml"),
+                str(crate_dir / "Cargo.toml"),
                 "--release",
                 "--target",
                 TARGET,
+                *feature_args(features, no_default_features),
                 "--",
                 "-O",
-                "ihex",
+                out_format,
                 str(out_hex),
             ],
-            cwd=FIRMWARE_DIR,
+            cwd=crate_dir,
         )
         return
     except (subprocess.CalledProcessError, FileNotFoundError):
@@ -74,11 +110,11 @@ def objcopy_to_hex(elf: Path, out_hex: Path) -> None:
             [
                 "llvm-objcopy",
                 "-O",
-                "ihex",
+                out_format,
                 str(elf),
                 str(out_hex),
             ],
-            cwd=FIRMWARE_DIR,
+            cwd=crate_dir,
         )
         return
     except (subprocess.CalledProcessError, FileNotFoundError):
@@ -89,11 +125,11 @@ def objcopy_to_hex(elf: Path, out_hex: Path) -> None:
             [
                 "arm-none-eabi-objcopy",
                 "-O",
-                "ihex",
+                out_format,
                 str(elf),
                 str(out_hex),
             ],
-            cwd=FIRMWARE_DIR,
+            cwd=crate_dir,
         )
     except (subprocess.CalledProcessError, FileNotFoundError) as err:
         raise RuntimeError(
@@ -212,12 +248,11 @@ def write_ihex(mem: dict[int, int], path: Path) -> None:
 
 
 def merge_hex(
-    app_hex: Path, sd_hex: Path | None, out_hex: Path
+    app_hex: Path, extra_hex: list[Path], out_hex: Path
 ) -> None:
     mem = parse_ihex(app_hex)
-    if sd_hex is not None:
-        sd_mem = parse_ihex(sd_hex)
-        for addr, byte in sd_mem.items():
+    for hex_path in extra_hex:
+        for addr, byte in parse_ihex(hex_path).items():
             prev = mem.get(addr)
             if prev is not None and prev != byte:
                 raise ValueError(
@@ -248,6 +283,78 @@ def convert_to_uf2(in_hex: Path, out_uf2: Path) -> None:
     )
 
 
+# ---------------------------------------------------------------------------
+# Size report
+# ---------------------------------------------------------------------------
+
+SHT_NOBITS = 8
+SHF_WRITE = 0x1
+SHF_ALLOC = 0x2
+
+
+def elf_sections(elf: Path) -> list[tuple[str, int, int, int]]:
+    """Return `(name, type, flags, size)` for every section of an ELF32 LE file."""
+    data = elf.read_bytes()
+    if data[:4] != b"\x7fELF" or data[4] != 1 or data[5] != 1:
+        raise ValueError(f"not a 32-bit little-endian ELF: {elf}")
+    shoff, = struct.unpack_from("<I", data, 0x20)
+    shentsize, shnum, shstrndx = struct.unpack_from("<HHH", data, 0x2E)
+
+    headers = [
+        struct.unpack_from("<IIIIIIIIII", data, shoff + i * shentsize)
+        for i in range(shnum)
+    ]
+    strtab_off = headers[shstrndx][4]
+
+    sections = []
+    for name_off, sh_type, flags, _addr, _off, size, *_ in headers:
+        end = data.index(b"\0", strtab_off + name_off)
+        name = data[strtab_off + name_off : end].decode()
+        sections.append((name, sh_type, flags, size))
+    return sections
+
+
+def size_report(elf: Path) -> tuple[int, int, list[tuple[str, int, str]]]:
+    """Compute `(flash, ram, rows)` for allocated sections.
+
+    Flash holds every loaded section (including the `.data` init image);
+    RAM holds writable sections (`.data`, `.bss`, `.uninit`). The stack is
+    not a section: with flip-link it takes whatever RAM is left.
+    """
+    flash = 0
+    ram = 0
+    rows = []
+    for name, sh_type, flags, size in elf_sections(elf):
+        if not flags & SHF_ALLOC or size == 0:
+            continue
+        regions = []
+        if sh_type != SHT_NOBITS:
+            flash += size
+            regions.append("flash")
+        if flags & SHF_WRITE:
+            ram += size
+            regions.append("ram")
+        rows.append((name, size, "+".join(regions)))
+    return flash, ram, rows
+
+
+def check_size(elf: Path, flash_budget: int, ram_budget: int) -> bool:
+    """Print the size report; return False when a budget is exceeded."""
+    if not elf.is_file():
+        raise FileNotFoundError(f"ELF not found: {elf}")
+    flash, ram, rows = size_report(elf)
+    for name, size, regions in rows:
+        print(f"  {name:<20} {size:>8}  {regions}")
+
+    ok = True
+    for label, used, budget in (("flash", flash, flash_budget), ("ram", ram, ram_budget)):
+        pct = 100.0 * used / budget if budget else 0.0
+        status = "OK" if used <= budget else "OVER BUDGET"
+        print(f"{label:<6} {used:>8} / {budget:>8} bytes ({pct:5.1f}%)  {status}")
+        ok = ok and used <= budget
+    return ok
+
+
 # ---------------------------------------------------------------------------
 # Subcommands
 # ---------------------------------------------------------------------------
@@ -258,36 +365,91 @@ def cmd_build(args) -> int:
     combined_hex = DEFAULT_COMBINED_HEX
 
     if not args.no_build and args.app_hex is None:
-        build_firmware()
-        objcopy_to_hex(args.elf, app_hex)
+        build_firmware(args.features, args.no_default_features)
+        objcopy_to_hex(args.elf, app_hex, args.features, args.no_default_features)
+        objcopy_to_hex(
+            args.elf,
+            DEFAULT_APP_BIN,
+            args.features,
+            args.no_default_features,
+            out_format="binary",
+        )
+        if not check_size(args.elf, args.flash_budget, args.ram_budget):
+            print("error: firmware exceeds size budget", file=sys.stderr)
+            return 1
+        if not args.no_bootloader:
+            build_firmware(crate_dir=BOOTLOADER_DIR)
+            objcopy_to_hex(
+                DEFAULT_BOOTLOADER_ELF,
+                DEFAULT_BOOTLOADER_HEX,
+                crate_dir=BOOTLOADER_DIR,
+            )
 
     if not app_hex.is_file():
         raise FileNotFoundError(
             f"app hex not found: {app_hex}"
         )
 
-    sd_hex = None if args.no_softdevice else args.softdevice
-    if sd_hex is not None and not sd_hex.is_file():
-        raise FileNotFoundError(
-            f"SoftDevice hex not found: {sd_hex}"
-        )
+    extra_hex = []
+    if not args.no_softdevice:
+        extra_hex.append(args.softdevice)
+    if not args.no_bootloader:
+        extra_hex.append(args.bootloader)
+    for hex_path in extra_hex:
+        if not hex_path.is_file():
+            raise FileNotFoundError(
+                f"hex not found: {hex_path}"
+            )
 
-    merge_hex(app_hex, sd_hex, combined_hex)
+    merge_hex(app_hex, extra_hex, combined_hex)
     convert_to_uf2(combined_hex, args.out)
     print(f"UF2 ready: {args.out}")
+    if DEFAULT_APP_BIN.is_file():
+        print(f"BLE update image (FIRMWARE command): {DEFAULT_APP_BIN}")
     return 0
 
 
+def cmd_size(args) -> int:
+    if not args.no_build:
+        build_firmware(args.features, args.no_default_features)
+    return 0 if check_size(args.elf, args.flash_budget, args.ram_budget) else 1
+
+
 # ---------------------------------------------------------------------------
 # CLI setup
 # ---------------------------------------------------------------------------
 
 
+def add_build_args(p) -> None:
+    p.add_argument(
+        "--features",
+        default=None,
+        help="Comma-separated cargo features (e.g. 'i2c-spi,nav').",
+    )
+    p.add_argument(
+        "--no-default-features",
+        action="store_true",
+        help="Build without the default feature set.",
+    )
+    p.add_argument(
+        "--flash-budget",
+        type=lambda v: int(v, 0),
+        default=FLASH_BUDGET,
+        help=f"Max app flash bytes (default: {FLASH_BUDGET}).",
+    )
+    p.add_argument(
+        "--ram-budget",
+        type=lambda v: int(v, 0),
+        default=RAM_BUDGET,
+        help=f"Max static RAM bytes (default: {RAM_BUDGET}).",
+    )
+
+
 def add_subcommands(subparsers) -> None:
     """Register UF2 subcommands."""
     p = subparsers.add_parser(
         "build",
-        help="Build firmware + SoftDevice and generate a combined UF2",
+        help="Build firmware + slot bootloader + SoftDevice and generate a combined UF2",
     )
     p.add_argument(
         "--no-build",
@@ -317,14 +479,44 @@ def add_subcommands(subparsers) -> None:
         action="store_true",
         help="Skip SoftDevice merge (app-only UF2).",
     )
+    p.add_argument(
+        "--bootloader",
+        type=Path,
+        default=DEFAULT_BOOTLOADER_HEX,
+        help="Slot bootloader hex to merge.",
+    )
+    p.add_argument(
+        "--no-bootloader",
+        action="store_true",
+        help="Skip the slot bootloader (only for a device that already has it).",
+    )
     p.add_argument(
         "--out",
         type=Path,
         default=DEFAULT_UF2,
         help="Output UF2 file path.",
     )
+    add_build_args(p)
     p.set_defaults(func=cmd_build)
 
+    p = subparsers.add_parser(
+        "size",
+        help="Report app flash/RAM usage and check it against the budget",
+    )
+    p.add_argument(
+        "--no-build",
+        action="store_true",
+        help="Skip cargo build and inspect the existing ELF.",
+    )
+    p.add_argument(
+        "--elf",
+        type=Path,
+        default=DEFAULT_ELF,
+        help="App ELF path.",
+    )
+    add_build_args(p)
+    p.set_defaults(func=cmd_size)
+
 
 def main() -> int:
     parser = argparse.ArgumentParser(
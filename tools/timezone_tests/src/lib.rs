@@ -1,2 +1,136 @@
+// Only the tests exercise the firmware modules; the host lib itself has no callers.
+#[allow(dead_code)]
 #[path = "../../../firmware/src/timezone.rs"]
 mod timezone;
+
+#[allow(dead_code)]
+#[path = "../../../firmware/src/stationary.rs"]
+mod stationary;
+
+#[allow(dead_code)]
+#[path = "../../../firmware/src/gpz.rs"]
+mod gpz;
+
+#[allow(dead_code)]
+#[path = "../../../firmware/src/track_decimate.rs"]
+mod track_decimate;
+
+#[allow(dead_code)]
+#[path = "../../../firmware/src/gpx_export.rs"]
+mod gpx_export;
+
+#[allow(dead_code)]
+#[path = "../../../firmware/src/altitude_fusion.rs"]
+mod altitude_fusion;
+
+#[allow(dead_code)]
+#[path = "../../../firmware/src/steps.rs"]
+mod steps;
+
+#[allow(dead_code)]
+#[path = "../../../firmware/src/crc32.rs"]
+mod crc32;
+
+#[allow(dead_code)]
+#[path = "../../../firmware/src/status_schema.rs"]
+mod status_schema;
+
+#[allow(dead_code)]
+#[path = "../../../firmware/src/time_check.rs"]
+mod time_check;
+
+#[allow(dead_code)]
+#[path = "../../../firmware/src/agnss_window.rs"]
+mod agnss_window;
+
+#[allow(dead_code)]
+#[path = "../../../firmware/src/key_clock.rs"]
+mod key_clock;
+
+#[allow(dead_code)]
+#[path = "../../../firmware/src/casic.rs"]
+mod casic;
+
+#[allow(dead_code)]
+#[path = "../../../firmware/src/agnss_import.rs"]
+mod agnss_import;
+
+#[allow(dead_code)]
+#[path = "../../../firmware/src/heading.rs"]
+mod heading;
+
+#[allow(dead_code)]
+#[path = "../../../firmware/src/fix_stats.rs"]
+mod fix_stats;
+
+#[allow(dead_code)]
+#[path = "../../../firmware/src/nmea_command.rs"]
+mod nmea_command;
+
+#[allow(dead_code)]
+#[path = "../../../firmware/src/fuel_gauge.rs"]
+mod fuel_gauge;
+
+#[allow(dead_code)]
+#[path = "../../../firmware/src/transfer_seq.rs"]
+mod transfer_seq;
+
+#[allow(dead_code)]
+#[path = "../../../firmware/src/flash_ring.rs"]
+mod flash_ring;
+
+#[allow(dead_code)]
+#[path = "../../../firmware/src/tones.rs"]
+mod tones;
+
+#[allow(dead_code)]
+#[path = "../../../firmware/src/splash.rs"]
+mod splash;
+
+#[allow(dead_code)]
+#[path = "../../../firmware/src/gps/adaptive.rs"]
+mod adaptive;
+
+#[allow(dead_code)]
+#[path = "../../../firmware/src/gps/agnss_policy.rs"]
+mod agnss_policy;
+
+#[allow(dead_code)]
+#[path = "../../../firmware/src/gps/escalation.rs"]
+mod escalation;
+
+#[allow(dead_code)]
+#[path = "../../../firmware/src/sky_view.rs"]
+mod sky_view;
+
+#[allow(dead_code)]
+#[path = "../../../firmware/src/bonds.rs"]
+mod bonds;
+
+#[allow(dead_code)]
+#[path = "../../../firmware/src/activity.rs"]
+mod activity;
+
+#[allow(dead_code)]
+#[path = "../../../firmware/src/battery_history.rs"]
+mod battery_history;
+
+#[allow(dead_code)]
+#[path = "../../../firmware/src/pressure_trend.rs"]
+mod pressure_trend;
+
+#[allow(dead_code)]
+#[path = "../../../firmware/src/datum.rs"]
+mod datum;
+
+#[allow(dead_code)]
+#[path = "../../../firmware/src/fat_lfn.rs"]
+mod fat_lfn;
+
+#[allow(dead_code)]
+#[path = "../../../firmware/src/slot_meta.rs"]
+mod slot_meta;
+
+#[allow(dead_code)]
+#[path = "../../../firmware/src/log_block.rs"]
+mod log_block;